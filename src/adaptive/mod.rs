@@ -0,0 +1,532 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+use crate::shadow::ShadowCache;
+use crate::sync_cache::lock;
+use crate::{Cache, CacheStats, Policy};
+
+const DEFAULT_WINDOW: u64 = 1000;
+const DEFAULT_SWITCH_RATE: f64 = 0.25;
+
+// Keys a shadow evicted, oldest first; the real cache evicts from these for it. A key
+// written again is forgotten, since the shadow holds it again, and its queued entry is
+// skipped by its stamp, so forgetting does not walk the queue.
+struct Evicted<K> {
+    order: VecDeque<(K, u64)>,
+    // The stamp of each remembered key's latest entry in `order`
+    latest: HashMap<K, u64>,
+    next: u64,
+}
+
+impl<K: Clone + Hash + Eq> Evicted<K> {
+    fn new() -> Self {
+        Evicted {
+            order: VecDeque::new(),
+            latest: HashMap::new(),
+            next: 0,
+        }
+    }
+
+    fn push(&mut self, key: K, bound: usize) {
+        self.next += 1;
+        self.latest.insert(key.clone(), self.next);
+        self.order.push_back((key, self.next));
+        // Older keys have long left the real cache too
+        while self.latest.len() > bound {
+            self.pop_front();
+        }
+        if self.order.len() > 2 * bound {
+            let latest = &self.latest;
+            self.order
+                .retain(|(key, stamp)| latest.get(key) == Some(stamp));
+        }
+    }
+
+    fn forget(&mut self, key: &K) {
+        self.latest.remove(key);
+    }
+
+    // The oldest remembered key passing `evictable`, dropping those before it that fail.
+    fn oldest<F: Fn(&K) -> bool>(&mut self, evictable: F) -> Option<K> {
+        while let Some((key, stamp)) = self.order.front() {
+            if self.latest.get(key) == Some(stamp) && evictable(key) {
+                return Some(key.clone());
+            }
+            self.pop_front();
+        }
+        None
+    }
+
+    fn pop_front(&mut self) {
+        if let Some((key, stamp)) = self.order.pop_front() {
+            if self.latest.get(&key) == Some(&stamp) {
+                self.latest.remove(&key);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.order.clear();
+        self.latest.clear();
+    }
+}
+
+struct Candidate<K> {
+    shadow: ShadowCache<K>,
+    evicted: Arc<Mutex<Evicted<K>>>,
+    // Hits in past windows, halved at the end of each one
+    score: f64,
+    hits_at_window: u64,
+    // The part of the real cache's evictions routed to this policy
+    share: f64,
+    // Smooth weighted round robin: evictions are owed in proportion to `share`
+    credit: f64,
+    evictions: u64,
+}
+
+// Holds the values once and lets several policies compete to decide what to evict. Each
+// policy runs as a shadow at the same capacity on the same traffic, and every `window`
+// requests the one with the best recent hit rate gains share of the real evictions, which
+// evict a key that policy's shadow has already evicted. Shares move by the switch rate
+// each window rather than at once, so one noisy window does not flip the cache, while a
+// lasting change of traffic, e.g. from recency-driven to frequency-driven, moves it over
+// within a few windows.
+pub struct AdaptiveCache<K, V> {
+    // Each value with its weight and when it was last written
+    map: HashMap<K, (V, u32, u64)>,
+    writes: u64,
+    capacity: usize,
+    max_weight: Option<u64>,
+    candidates: Vec<Candidate<K>>,
+    window: u64,
+    switch_rate: f64,
+    requests: u64,
+    stats: CacheStats,
+}
+
+impl<K: Clone + Hash + Eq + Send + 'static, V> AdaptiveCache<K, V> {
+    // Starts out evicting for each of `policies` equally.
+    pub fn new(capacity: usize, policies: &[Policy]) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        assert!(policies.len() >= 2, "At least two policies must compete");
+        let candidates = policies
+            .iter()
+            .map(|&policy| {
+                let mut shadow = ShadowCache::new(policy, capacity);
+                let evicted = Arc::new(Mutex::new(Evicted::new()));
+                let sink = evicted.clone();
+                shadow.set_eviction_listener(move |key| lock(&sink).push(key, capacity));
+                Candidate {
+                    shadow,
+                    evicted,
+                    score: 0.0,
+                    hits_at_window: 0,
+                    share: 1.0 / policies.len() as f64,
+                    credit: 0.0,
+                    evictions: 0,
+                }
+            })
+            .collect();
+        AdaptiveCache {
+            map: HashMap::new(),
+            writes: 0,
+            capacity,
+            max_weight: None,
+            candidates,
+            window: DEFAULT_WINDOW,
+            switch_rate: DEFAULT_SWITCH_RATE,
+            requests: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    // Bounds the real cache and every shadow by total weight.
+    pub fn set_max_weight(&mut self, max_weight: u64) {
+        self.max_weight = Some(max_weight);
+        for candidate in &mut self.candidates {
+            candidate.shadow.set_max_weight(max_weight);
+        }
+        self.evict_to_fit(None);
+    }
+
+    // How many lookups make a window, after which shares move.
+    pub fn set_window(&mut self, requests: u64) {
+        assert!(requests > 0, "Window must be greater than 0");
+        self.window = requests;
+    }
+
+    // The part of the way to the leader shares move each window, where 1 switches at once.
+    pub fn set_switch_rate(&mut self, rate: f64) {
+        assert!(rate > 0.0 && rate <= 1.0, "Switch rate must be in (0, 1]");
+        self.switch_rate = rate;
+    }
+
+    // The policy with the best recent hit rate.
+    pub fn leader(&self) -> Policy {
+        self.candidates[self.leading()].shadow.policy()
+    }
+
+    // Each policy's part of the evictions, summing to 1.
+    pub fn shares(&self) -> Vec<(Policy, f64)> {
+        self.candidates
+            .iter()
+            .map(|candidate| (candidate.shadow.policy(), candidate.share))
+            .collect()
+    }
+
+    // Real evictions each policy decided, in the order the policies were given.
+    pub fn evictions_by_policy(&self) -> Vec<(Policy, u64)> {
+        self.candidates
+            .iter()
+            .map(|candidate| (candidate.shadow.policy(), candidate.evictions))
+            .collect()
+    }
+
+    pub fn shadows(&self) -> impl Iterator<Item = &ShadowCache<K>> {
+        self.candidates.iter().map(|candidate| &candidate.shadow)
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.lookup(key);
+        self.map.get(key).map(|(value, _, _)| value)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.lookup(key);
+        self.map.get_mut(key).map(|(value, _, _)| value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.map.get(key).map(|(value, _, _)| value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        for candidate in &mut self.candidates {
+            candidate.shadow.fill(key.clone(), weight);
+            lock(&candidate.evicted).forget(&key);
+        }
+        self.writes += 1;
+        match self.map.insert(key.clone(), (value, weight, self.writes)) {
+            Some((_, old, _)) => self.stats.current_weight -= u64::from(old),
+            None => self.stats.insertions += 1,
+        }
+        self.stats.current_weight += u64::from(weight);
+        self.evict_to_fit(Some(&key));
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        for candidate in &mut self.candidates {
+            candidate.shadow.remove(key);
+        }
+        let (value, weight, _) = self.map.remove(key)?;
+        self.stats.current_weight -= u64::from(weight);
+        Some(value)
+    }
+
+    // Removes the entry the policy due the next eviction would evict.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.evict(None)
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        let index = self.deciding();
+        let victim = self.victim(index, None)?;
+        self.map.get_key_value(&victim).map(|(key, _)| key)
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.stats.current_weight = 0;
+        for candidate in &mut self.candidates {
+            candidate.shadow.clear();
+            lock(&candidate.evicted).clear();
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    // A shadow that misses where the real cache hit is filled now; after a real miss, the
+    // real put fills it.
+    fn lookup(&mut self, key: &K) {
+        let cached = self.map.get(key).map(|&(_, weight, _)| weight);
+        match cached {
+            Some(_) => self.stats.hits += 1,
+            None => self.stats.misses += 1,
+        }
+        for candidate in &mut self.candidates {
+            let weight = cached.unwrap_or(1);
+            if !candidate.shadow.lookup(key, weight) && cached.is_some() {
+                candidate.shadow.fill(key.clone(), weight);
+                lock(&candidate.evicted).forget(key);
+            }
+        }
+        self.requests += 1;
+        if self.requests.is_multiple_of(self.window) {
+            self.end_window();
+        }
+    }
+
+    // Halves the old scores, adds this window's hits and moves shares toward the leader.
+    fn end_window(&mut self) {
+        for candidate in &mut self.candidates {
+            let hits = candidate.shadow.report().hits;
+            candidate.score = candidate.score / 2.0 + (hits - candidate.hits_at_window) as f64;
+            candidate.hits_at_window = hits;
+        }
+        let leader = self.leading();
+        let rate = self.switch_rate;
+        for (index, candidate) in self.candidates.iter_mut().enumerate() {
+            let target = if index == leader { 1.0 } else { 0.0 };
+            candidate.share += (target - candidate.share) * rate;
+        }
+    }
+
+    // The best score; the first policy given wins a tie.
+    fn leading(&self) -> usize {
+        let mut leader = 0;
+        for (index, candidate) in self.candidates.iter().enumerate() {
+            if candidate.score > self.candidates[leader].score {
+                leader = index;
+            }
+        }
+        leader
+    }
+
+    // The policy owed the next eviction, without settling the debt.
+    fn deciding(&self) -> usize {
+        let mut best = 0;
+        let mut best_credit = f64::MIN;
+        for (index, candidate) in self.candidates.iter().enumerate() {
+            let credit = candidate.credit + candidate.share;
+            if credit > best_credit {
+                best = index;
+                best_credit = credit;
+            }
+        }
+        best
+    }
+
+    fn next_deciding(&mut self) -> usize {
+        let index = self.deciding();
+        for candidate in &mut self.candidates {
+            candidate.credit += candidate.share;
+        }
+        self.candidates[index].credit -= 1.0;
+        index
+    }
+
+    // The oldest key the policy's shadow evicted that is still cached, else the shadow's
+    // own next victim, else a cached key the shadow no longer holds, evicted longer ago
+    // than the queue remembers. A shadow bounded by weight alone can hold every cached
+    // key while the real cache is over its entry count, and then the key written longest
+    // ago goes. `keep`, the key being written, is never chosen.
+    fn victim(&self, index: usize, keep: Option<&K>) -> Option<K> {
+        let candidate = &self.candidates[index];
+        let evictable = |key: &K| Some(key) != keep && self.map.contains_key(key);
+        if let Some(key) = lock(&candidate.evicted).oldest(evictable) {
+            return Some(key);
+        }
+        let shadow = &candidate.shadow;
+        shadow
+            .peek_victim()
+            .filter(|key| evictable(key))
+            .or_else(|| {
+                self.map
+                    .keys()
+                    .find(|key| Some(*key) != keep && !shadow.contains_key(key))
+            })
+            .or_else(|| {
+                self.map
+                    .iter()
+                    .filter(|&(key, _)| Some(key) != keep)
+                    .min_by_key(|&(_, &(_, _, written))| written)
+                    .map(|(key, _)| key)
+            })
+            .cloned()
+    }
+
+    fn over_capacity(&self) -> bool {
+        self.map.len() > self.capacity
+            || self
+                .max_weight
+                .is_some_and(|max_weight| self.stats.current_weight > max_weight)
+    }
+
+    // Like `pop_victim`, but never evicts `keep`.
+    fn evict(&mut self, keep: Option<&K>) -> Option<(K, V)> {
+        let index = self.next_deciding();
+        let victim = self.victim(index, keep)?;
+        self.candidates[index].evictions += 1;
+        let (value, weight, _) = self.map.remove(&victim)?;
+        self.stats.current_weight -= u64::from(weight);
+        Some((victim, value))
+    }
+
+    fn evict_to_fit(&mut self, keep: Option<&K>) {
+        while self.over_capacity() && self.evict(keep).is_some() {
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq + Send + 'static, V> Cache<K, V> for AdaptiveCache<K, V> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::{replay, Access};
+    use crate::workload::Workload;
+
+    fn share_of(cache: &AdaptiveCache<String, ()>, policy: Policy) -> f64 {
+        cache
+            .shares()
+            .into_iter()
+            .find(|&(candidate, _)| candidate == policy)
+            .map_or(0.0, |(_, share)| share)
+    }
+
+    #[test]
+    fn test_moves_toward_the_better_policy_gradually() {
+        // A loop one key longer than the cache: LRU never hits, MRU mostly does
+        let keys: Vec<String> = (0..2000).map(|i| (i % 11).to_string()).collect();
+        let mut cache = AdaptiveCache::new(10, &[Policy::LRU, Policy::MRU]);
+        cache.set_window(100);
+        cache.set_switch_rate(0.5);
+        let mut shares = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            if cache.get(key).is_none() {
+                cache.put(key.clone(), (), 1);
+            }
+            if i == 99 || i == 199 || i == 1999 {
+                shares.push(share_of(&cache, Policy::MRU));
+            }
+        }
+        assert_eq!(cache.leader(), Policy::MRU);
+        assert_eq!(shares[0], 0.75);
+        assert_eq!(shares[1], 0.875);
+        assert!(shares[2] > 0.999);
+        assert!(cache.stats().hits > 1500);
+        assert_eq!(cache.len(), 10);
+    }
+
+    #[test]
+    fn test_follows_a_shift_in_traffic() {
+        let mut cache = AdaptiveCache::new(50, &[Policy::LRU, Policy::MRU]);
+        cache.set_window(500);
+        let looping: Vec<Access> = (0..5000u64)
+            .map(|i| Access {
+                timestamp: i,
+                key: (i % 51).to_string(),
+                size: None,
+                cost: None,
+            })
+            .collect();
+        replay(&mut cache, &looping);
+        assert_eq!(cache.leader(), Policy::MRU);
+
+        let skewed = Workload::zipf(1000, 1.0).seed(3).trace(20_000);
+        let report = replay(&mut cache, &skewed);
+        assert_eq!(cache.leader(), Policy::LRU);
+        assert!(share_of(&cache, Policy::LRU) > 0.99);
+        assert!(report.hit_ratio() > 0.3);
+    }
+
+    #[test]
+    fn test_respects_weight_and_removals() {
+        let mut cache = AdaptiveCache::new(10, &[Policy::LRU, Policy::LFU]);
+        cache.set_max_weight(10);
+        cache.put("a", 1, 6);
+        cache.put("b", 2, 6);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.stats().current_weight <= 10);
+        assert_eq!(cache.remove(&"b"), Some(2));
+        assert!(cache.is_empty());
+        assert!(cache.shadows().all(|shadow| !shadow.contains_key(&"b")));
+    }
+
+    #[test]
+    fn test_weight_bound_keeps_the_entry_count() {
+        let policies = [Policy::ARC, Policy::FIFO, Policy::Random];
+        let mut cache = AdaptiveCache::new(8, &policies);
+        cache.set_max_weight(20);
+        for key in Workload::zipf(50, 0.8).seed(5).generate(2000) {
+            if cache.get(&key).is_none() {
+                cache.put(key, (), 1 + (key % 3) as u32);
+            }
+            assert!(cache.len() <= 8);
+            assert!(cache.stats().current_weight <= 20);
+        }
+    }
+
+    #[test]
+    fn test_keeps_a_key_written_again() {
+        let mut cache = AdaptiveCache::new(2, &[Policy::LRU, Policy::FIFO]);
+        for key in 1..=3 {
+            cache.put(key, key, 1);
+        }
+        // Both shadows evicted 1 before it came back
+        cache.put(1, 10, 1);
+        assert_eq!(cache.peek(&1), Some(&10));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains_key(&3) && !cache.contains_key(&2));
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+use crate::frequency::FrequencySketch;
+use crate::random::Rng;
+use crate::tinylfu::TinyLFU;
+use crate::Cache;
+
+// Decides whether a new key may push out the cache's next victim. Only consulted when the
+// cache is full: until then every key is admitted.
+pub trait AdmissionPolicy<K> {
+    // Sees every lookup and every new key, for policies that learn from the traffic.
+    fn record(&mut self, _key: &K) {}
+
+    fn admit(&mut self, candidate: &K, weight: u32, victim: &K) -> bool;
+}
+
+// Admits every key, leaving the decision to the eviction policy alone.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlwaysAdmit;
+
+impl<K> AdmissionPolicy<K> for AlwaysAdmit {
+    fn admit(&mut self, _candidate: &K, _weight: u32, _victim: &K) -> bool {
+        true
+    }
+}
+
+// Admits a key once it has been seen `threshold` times recently, by a `FrequencySketch`,
+// so that queries asked once never push out anything.
+pub struct FrequencyThreshold<S = RandomState> {
+    sketch: FrequencySketch<S>,
+    threshold: u32,
+}
+
+impl FrequencyThreshold {
+    // `capacity` sizes the sketch, and should be the capacity of the cache.
+    pub fn new(capacity: usize, threshold: u32) -> Self {
+        FrequencyThreshold::with_hasher(capacity, threshold, RandomState::new())
+    }
+}
+
+impl<S: BuildHasher> FrequencyThreshold<S> {
+    pub fn with_hasher(capacity: usize, threshold: u32, hash_builder: S) -> Self {
+        FrequencyThreshold {
+            sketch: FrequencySketch::with_hasher(capacity, hash_builder),
+            threshold,
+        }
+    }
+
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    pub fn sketch(&self) -> &FrequencySketch<S> {
+        &self.sketch
+    }
+}
+
+impl<K: Hash, S: BuildHasher> AdmissionPolicy<K> for FrequencyThreshold<S> {
+    fn record(&mut self, key: &K) {
+        self.sketch.record(key);
+    }
+
+    fn admit(&mut self, candidate: &K, _weight: u32, _victim: &K) -> bool {
+        self.sketch.estimate(candidate) >= self.threshold
+    }
+}
+
+// AdaptSize's coin flip: a key of weight `w` is admitted with probability `e^(-w / scale)`,
+// so small results nearly always get in and large ones only occasionally. A large result
+// that keeps being asked for is admitted after a few tries; one asked for once rarely is.
+pub struct SizeProbability {
+    scale: f64,
+    rng: Rng,
+}
+
+impl SizeProbability {
+    pub fn new(scale: f64) -> Self {
+        assert!(scale > 0.0, "Scale must be positive");
+        SizeProbability {
+            scale,
+            rng: Rng::new(RandomState::new().hash_one(scale.to_bits())),
+        }
+    }
+
+    // Makes the coin flips reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn probability(&self, weight: u32) -> f64 {
+        (-f64::from(weight) / self.scale).exp()
+    }
+}
+
+impl<K> AdmissionPolicy<K> for SizeProbability {
+    fn admit(&mut self, _candidate: &K, weight: u32, _victim: &K) -> bool {
+        self.rng.next_unit() < self.probability(weight)
+    }
+}
+
+impl<K: Hash, S: BuildHasher> AdmissionPolicy<K> for TinyLFU<S> {
+    fn record(&mut self, key: &K) {
+        self.record(key);
+    }
+
+    fn admit(&mut self, candidate: &K, _weight: u32, victim: &K) -> bool {
+        TinyLFU::admit(self, candidate, victim)
+    }
+}
+
+// Puts any admission policy in front of any cache. Every lookup and every new key is shown
+// to the policy; once the cache is full, a new key is only stored if the policy admits it
+// in place of the next victim. Fullness is judged by entry count, like `TinyLFUCache`.
+pub struct AdmissionCache<K, V, C, A> {
+    cache: C,
+    capacity: usize,
+    policy: A,
+    rejections: u64,
+    marker: PhantomData<fn(K, V)>,
+}
+
+impl<K, V, C: Cache<K, V>, A: AdmissionPolicy<K>> AdmissionCache<K, V, C, A> {
+    // `capacity` should be the entry capacity the cache was built with.
+    pub fn new(cache: C, capacity: usize, policy: A) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        AdmissionCache {
+            cache,
+            capacity,
+            policy,
+            rejections: 0,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.policy.record(key);
+        self.cache.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.policy.record(key);
+        self.cache.get_mut(key)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.cache.peek(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    // Returns whether the entry was stored. Updates to cached keys always are.
+    pub fn put(&mut self, key: K, value: V, weight: u32) -> bool {
+        if self.cache.contains_key(&key) {
+            self.cache.put(key, value, weight);
+            return true;
+        }
+
+        self.policy.record(&key);
+        if self.cache.len() >= self.capacity {
+            if let Some(victim) = self.cache.peek_victim() {
+                if !self.policy.admit(&key, weight, victim) {
+                    self.rejections += 1;
+                    return false;
+                }
+            }
+        }
+        self.cache.put(key, value, weight);
+        true
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.cache.remove(key)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.cache.pop_victim()
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.cache.peek_victim()
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear()
+    }
+
+    // New keys turned away since the cache was built.
+    pub fn rejections(&self) -> u64 {
+        self.rejections
+    }
+
+    pub fn policy(&self) -> &A {
+        &self.policy
+    }
+
+    pub fn policy_mut(&mut self) -> &mut A {
+        &mut self.policy
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.cache
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.cache
+    }
+
+    pub fn into_inner(self) -> C {
+        self.cache
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+impl<K, V, C: Cache<K, V>, A: AdmissionPolicy<K>> Cache<K, V> for AdmissionCache<K, V, C, A> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lru::LRUCache;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+
+    // Keeps the sketch's collisions the same from run to run.
+    type FixedState = BuildHasherDefault<DefaultHasher>;
+
+    #[test]
+    fn test_singletons_do_not_evict_under_a_threshold() {
+        let policy = FrequencyThreshold::with_hasher(2, 2, FixedState::default());
+        let mut cache = AdmissionCache::new(LRUCache::new(2), 2, policy);
+        assert!(cache.put("rust", 1, 1));
+        assert!(cache.put("cache", 2, 1));
+        assert!(!cache.put("once", 3, 1));
+        assert_eq!(cache.rejections(), 1);
+
+        cache.get(&"zig");
+        assert!(cache.put("zig", 4, 1));
+        assert!(!cache.contains_key(&"rust"));
+        assert!(cache.put("cache", 5, 1));
+    }
+
+    #[test]
+    fn test_always_admit_leaves_eviction_alone() {
+        let mut cache = AdmissionCache::new(LRUCache::new(1), 1, AlwaysAdmit);
+        assert!(cache.put(1, "one", 1));
+        assert!(cache.put(2, "two", 1));
+        assert_eq!(cache.peek(&2), Some(&"two"));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_large_entries_are_rarely_admitted() {
+        let mut policy = SizeProbability::new(10.0);
+        policy.set_seed(7);
+        assert_eq!(policy.probability(0), 1.0);
+        let admitted = |policy: &mut SizeProbability, weight| {
+            (0..1000)
+                .filter(|_| policy.admit(&"candidate", weight, &"victim"))
+                .count()
+        };
+        assert_eq!(admitted(&mut policy, 0), 1000);
+        let large = admitted(&mut policy, 50);
+        let small = admitted(&mut policy, 1);
+        assert!(large < 30, "{}", large);
+        assert!(small > 850, "{}", small);
+    }
+}
@@ -0,0 +1,1144 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::expiry::{Expiration, Freshness, Timestamps, TimingWheel, WHEEL_RESOLUTION};
+use crate::observer::{BoxedObserver, CacheObserver};
+use crate::stats::{CacheStats, EntryInfo};
+use crate::trace;
+use crate::util::{KeyIndex, Keyed, Link, Slab, SlabKey};
+use crate::{Cache, EvictionListener, NewCache, Weigher};
+
+mod entry;
+mod persist;
+
+const POLICY: &str = "arc";
+
+pub use self::entry::{Entry, OccupiedEntry, VacantEntry};
+
+// T1 holds keys seen once recently and T2 keys seen at least twice; B1 and B2 remember the
+// keys recently evicted from each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    T1,
+    T2,
+    B1,
+    B2,
+}
+
+impl Segment {
+    fn ghost(self) -> Segment {
+        match self {
+            Segment::T1 | Segment::B1 => Segment::B1,
+            Segment::T2 | Segment::B2 => Segment::B2,
+        }
+    }
+
+    fn is_ghost(self) -> bool {
+        self == Segment::B1 || self == Segment::B2
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct List {
+    head: Link,
+    tail: Link,
+    len: usize,
+}
+
+// Ghosts keep their key and list links but no value.
+struct Node<K, V> {
+    key: K,
+    value: Option<V>,
+    weight: u32,
+    timestamps: Timestamps,
+    segment: Segment,
+    prev: Link,
+    next: Link,
+}
+
+impl<K, V> Keyed for Node<K, V> {
+    type Key = K;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    nodes: &'a Slab<Node<K, V>>,
+    next: Option<usize>,
+    then: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_none() {
+            self.next = self.then.take();
+        }
+        let node = self.nodes.get_at(self.next?)?;
+        self.next = node.next.get();
+        self.remaining -= 1;
+        Some((&node.key, node.value.as_ref()?))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+pub struct IntoIter<K, V> {
+    nodes: Slab<Node<K, V>>,
+    next: Option<usize>,
+    then: Option<usize>,
+    remaining: usize,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_none() {
+            self.next = self.then.take();
+        }
+        let node = self.nodes.remove(self.next?);
+        self.next = node.next.get();
+        self.remaining -= 1;
+        Some((node.key, node.value?))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+// Adaptive Replacement Cache (Megiddo and Modha). Entries start in a recency list and move
+// to a frequency list on their second use. A hit on a ghost of either list shifts the
+// target size of the recency list towards it, so the cache leans to LRU during scans and
+// to LFU while a hot set is being reused.
+pub struct ARCCache<K, V, S = RandomState> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    // The target length of T1
+    target: usize,
+    map: KeyIndex<S>,
+    nodes: Slab<Node<K, V>>,
+    lists: [List; 4],
+    eviction_listener: Option<EvictionListener<K, V>>,
+    weigher: Option<Weigher<K, V>>,
+    observer: Option<BoxedObserver<K>>,
+    stats: CacheStats,
+    expiration: Expiration,
+    expiry_wheel: Option<TimingWheel<SlabKey>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K: Clone + Hash + Eq, V> ARCCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        ARCCache::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher> ARCCache<K, V, S> {
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        ARCCache {
+            capacity,
+            max_weight: None,
+            target: 0,
+            map: KeyIndex::with_hasher(hash_builder),
+            nodes: Slab::with_capacity(capacity),
+            lists: [List::default(); 4],
+            eviction_listener: None,
+            weigher: None,
+            observer: None,
+            stats: CacheStats::default(),
+            expiration: Expiration::default(),
+            expiry_wheel: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let _span = trace::get_span(POLICY, key);
+        let idx = self.live_index(key)?;
+        self.promote(idx);
+        Some(self.value(idx))
+    }
+
+    pub fn get_stale(&mut self, key: &K) -> Option<Freshness<&V>> {
+        let now = self.clock.now();
+        match self.resident_index(key) {
+            Some(idx) if self.is_expired(idx, now) => {
+                self.stats.misses += 1;
+                trace::miss(POLICY, key);
+                self.notify(|observer| observer.on_miss(key));
+                Some(Freshness::Stale(self.value(idx)))
+            }
+            _ => self.get(key).map(Freshness::Fresh),
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let idx = self.live_index(key)?;
+        self.promote(idx);
+        Some(self.value_mut(idx))
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        let idx = self.resident_index(key)?;
+        if self.is_expired(idx, self.clock.now()) {
+            return None;
+        }
+        Some(self.value(idx))
+    }
+
+    // Like `peek`, this does not count as a use of the key.
+    pub fn entry_info(&self, key: &K) -> Option<EntryInfo> {
+        let idx = self.resident_index(key)?;
+        if self.is_expired(idx, self.clock.now()) {
+            return None;
+        }
+        let node = self.node(idx);
+        Some(EntryInfo::new(
+            &node.timestamps,
+            node.weight,
+            &self.expiration,
+        ))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+
+    // Writing a cached key counts as its second use and moves it to the frequency list.
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        let _span = trace::put_span(POLICY, &key, weight);
+        if let Some(idx) = self.resident_index(&key) {
+            let now = self.clock.now();
+            let node = self.node_mut(idx);
+            let old_weight = node.weight;
+            node.value = Some(value);
+            node.weight = weight;
+            node.timestamps.rewrite(now);
+            self.promote(idx);
+            self.stats.current_weight -= u64::from(old_weight);
+            self.stats.current_weight += u64::from(weight);
+            // A heavier value can push the cache over its budget; the key itself goes last
+            while self.len() > 1 && self.is_over_budget() {
+                self.evict(false, Some(idx));
+            }
+            self.notify(|observer| observer.on_update(&key));
+        } else {
+            self.insert_new(key, value, weight);
+        }
+    }
+
+    // Stores the value with the weight the weigher gives it, or a weight of 1 without one.
+    pub fn insert(&mut self, key: K, value: V) {
+        let weight = self
+            .weigher
+            .as_ref()
+            .map_or(1, |weigher| weigher(&key, &value));
+        self.put(key, value, weight);
+    }
+
+    // An occupied entry counts as a use of the key, just like `get`.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        match self.live_index(&key) {
+            Some(idx) => {
+                self.promote(idx);
+                Entry::Occupied(OccupiedEntry { cache: self, idx })
+            }
+            None => Entry::Vacant(VacantEntry { cache: self, key }),
+        }
+    }
+
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, weight: u32, f: F) -> &V {
+        self.entry(key).or_insert_with(weight, f)
+    }
+
+    // A failed computation leaves the cache untouched and returns the error.
+    pub fn try_get_or_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        weight: u32,
+        f: F,
+    ) -> Result<&V, E> {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(f()?, weight)),
+        }
+    }
+
+    // Removed keys are forgotten rather than remembered as ghosts.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let idx = self.resident_index(key)?;
+        self.remove_at(idx).value
+    }
+
+    // The entry `pop_victim` would remove, left in place.
+    pub fn peek_victim(&self) -> Option<&K> {
+        Some(&self.node(self.victim(false, None)?).key)
+    }
+
+    // The victim is remembered as a ghost, as on eviction.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.take_victim(false, None)
+    }
+
+    // Evicts entries as a full cache would, listener and stats included, until at most
+    // `len` are left. Returns how many were evicted.
+    pub fn evict_to(&mut self, len: usize) -> usize {
+        let mut evicted = 0;
+        while self.len() > len {
+            self.evict(false, None);
+            evicted += 1;
+        }
+        evicted
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        let doomed: Vec<usize> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| match node.value {
+                Some(ref value) => !f(&node.key, value),
+                None => false,
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+
+        for idx in doomed {
+            self.remove_at(idx);
+        }
+    }
+
+    pub fn set_expire_after_write(&mut self, time_to_live: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_live = Some(time_to_live);
+        self.set_expiration(expiration);
+    }
+
+    pub fn set_expire_after_access(&mut self, time_to_idle: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_idle = Some(time_to_idle);
+        self.set_expiration(expiration);
+    }
+
+    // Existing entries are scheduled against the new limits.
+    pub(crate) fn set_expiration(&mut self, expiration: Expiration) {
+        self.expiration = expiration;
+        self.expiry_wheel = if expiration.is_enabled() {
+            Some(TimingWheel::new(self.clock.now(), WHEEL_RESOLUTION))
+        } else {
+            None
+        };
+        let live: Vec<usize> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| !node.segment.is_ghost())
+            .map(|(idx, _)| idx)
+            .collect();
+        for idx in live {
+            self.schedule_expiry(idx);
+        }
+    }
+
+    // Replaces the time source; entries keep the timestamps they were given by the old one.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+        let expiration = self.expiration;
+        self.set_expiration(expiration);
+    }
+
+    pub fn expire_due(&mut self, now: Instant) -> usize {
+        let due = match self.expiry_wheel.as_mut() {
+            Some(wheel) => wheel.advance(now),
+            None => return 0,
+        };
+
+        let mut expired = 0;
+        for slot in due {
+            let idx = match self.nodes.get(slot) {
+                Some(node) if !node.segment.is_ghost() => slot.index(),
+                _ => continue,
+            };
+            if self.is_expired(idx, now) {
+                self.remove_at(idx);
+                expired += 1;
+            } else {
+                // Touched or rewritten since it was scheduled
+                self.schedule_expiry(idx);
+            }
+        }
+        expired
+    }
+
+    // The ghost lists still remember up to `capacity` keys.
+    pub fn set_max_weight(&mut self, max_weight: u64) {
+        self.max_weight = Some(max_weight);
+        while !self.is_empty() && self.is_over_budget() {
+            self.evict(false, None);
+        }
+    }
+
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.set_boxed_eviction_listener(Box::new(listener));
+    }
+
+    pub(crate) fn set_boxed_eviction_listener(&mut self, listener: EvictionListener<K, V>) {
+        self.eviction_listener = Some(listener);
+    }
+
+    // Computes the weight of entries stored with `insert`.
+    pub fn set_weigher<F: Fn(&K, &V) -> u32 + Send + 'static>(&mut self, weigher: F) {
+        self.set_boxed_weigher(Box::new(weigher));
+    }
+
+    pub(crate) fn set_boxed_weigher(&mut self, weigher: Weigher<K, V>) {
+        self.weigher = Some(weigher);
+    }
+
+    pub fn set_observer<O: CacheObserver<K> + Send + 'static>(&mut self, observer: O) {
+        self.set_boxed_observer(Box::new(observer));
+    }
+
+    pub(crate) fn set_boxed_observer(&mut self, observer: BoxedObserver<K>) {
+        self.observer = Some(observer);
+    }
+
+    // Forgets the ghosts and the learned target as well.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.nodes.clear();
+        self.lists = [List::default(); 4];
+        self.target = 0;
+        self.stats.current_weight = 0;
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.clear();
+        }
+    }
+
+    // Moves entries and ghosts out of the slots freed by removals, so the node storage and
+    // the map can release the memory they no longer need.
+    pub fn shrink_to_fit(&mut self) {
+        for (from, to) in self.nodes.compaction_moves() {
+            self.relocate(from, to);
+        }
+        self.nodes.shrink_to_fit();
+        self.map.shrink_to_fit(&self.nodes);
+    }
+
+    // Reserves room for a full cache. The ghost lists share the node storage, so it can
+    // hold up to twice the capacity.
+    pub fn preallocate(&mut self) {
+        self.nodes.reserve(2 * self.capacity);
+        self.map.reserve(2 * self.capacity, &self.nodes);
+    }
+
+    // Entries seen once come first, then those seen again, each from most recently used.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            nodes: &self.nodes,
+            next: self.list(Segment::T1).head.get(),
+            then: self.list(Segment::T2).head.get(),
+            remaining: self.len(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    // How many entries ARC currently wants in its recency list; it grows after hits on
+    // keys recently evicted from there and shrinks after hits on the frequency ghosts.
+    pub fn recency_target(&self) -> usize {
+        self.target
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.list(Segment::T1).len + self.list(Segment::T2).len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn insert_new(&mut self, key: K, value: V, weight: u32) -> usize {
+        let segment = match self.index_of(&key) {
+            Some(ghost_idx) => {
+                let ghost = self.node(ghost_idx).segment;
+                self.adapt(ghost);
+                self.forget(ghost_idx);
+                self.make_room(weight, ghost == Segment::B2);
+                Segment::T2
+            }
+            None => {
+                self.trim_history();
+                self.make_room(weight, false);
+                Segment::T1
+            }
+        };
+
+        self.stats.insertions += 1;
+        self.stats.current_weight += u64::from(weight);
+        trace::insert(POLICY, &key, weight);
+        self.notify(|observer| observer.on_insert(&key));
+        let node = Node {
+            key,
+            value: Some(value),
+            weight,
+            timestamps: Timestamps::new(self.clock.now()),
+            segment,
+            prev: Link::NONE,
+            next: Link::NONE,
+        };
+        let slot = self.nodes.insert(node);
+        let idx = slot.index();
+        self.map.insert(slot, &self.nodes);
+        self.push_front(idx, segment);
+        self.schedule_expiry(idx);
+        idx
+    }
+
+    // A ghost hit means the list it was evicted from deserved more room.
+    fn adapt(&mut self, ghost: Segment) {
+        let b1 = self.list(Segment::B1).len;
+        let b2 = self.list(Segment::B2).len;
+        if ghost == Segment::B1 {
+            let delta = (b2 / b1).max(1);
+            self.target = (self.target + delta).min(self.capacity);
+        } else {
+            let delta = (b1 / b2).max(1);
+            self.target = self.target.saturating_sub(delta);
+        }
+    }
+
+    // Makes room in the history for a key never seen before. When T1 and B1 together
+    // cover the whole capacity and B1 is empty, T1's LRU entry is evicted without a ghost.
+    fn trim_history(&mut self) {
+        let t1 = self.list(Segment::T1).len;
+        let b1 = self.list(Segment::B1).len;
+        let total = t1 + b1 + self.list(Segment::T2).len + self.list(Segment::B2).len;
+        if t1 + b1 >= self.capacity {
+            if let Some(ghost_idx) = self.list(Segment::B1).tail.get() {
+                self.forget(ghost_idx);
+            } else if self.max_weight.is_none() {
+                self.evict(false, None);
+                if let Some(ghost_idx) = self.list(Segment::B1).head.get() {
+                    self.forget(ghost_idx);
+                }
+            }
+        } else if total >= 2 * self.capacity {
+            if let Some(ghost_idx) = self.list(Segment::B2).tail.get() {
+                self.forget(ghost_idx);
+            }
+        }
+
+        // Only reached when a weight budget lets more than `capacity` entries in
+        while self.list(Segment::B1).len + self.list(Segment::B2).len > self.capacity {
+            let oldest = self
+                .list(Segment::B2)
+                .tail
+                .get()
+                .or(self.list(Segment::B1).tail.get());
+            match oldest {
+                Some(ghost_idx) => self.forget(ghost_idx),
+                None => break,
+            }
+        }
+    }
+
+    // Whether an entry of this weight only fits after an eviction.
+    fn is_full(&self, weight: u32) -> bool {
+        match self.max_weight {
+            Some(max_weight) => self.stats.current_weight + u64::from(weight) > max_weight,
+            None => self.len() >= self.capacity,
+        }
+    }
+
+    fn is_over_budget(&self) -> bool {
+        self.max_weight
+            .is_some_and(|max_weight| self.stats.current_weight > max_weight)
+    }
+
+    fn make_room(&mut self, weight: u32, for_b2_ghost: bool) {
+        while !self.is_empty() && self.is_full(weight) {
+            self.evict(for_b2_ghost, None);
+        }
+    }
+
+    // ARC's REPLACE: T1 gives up its LRU entry while it is longer than the target (or as
+    // long as it, when making room for a B2 ghost), otherwise T2 does. `spare` is never
+    // chosen while the other list has an entry.
+    fn victim(&self, for_b2_ghost: bool, spare: Option<usize>) -> Option<usize> {
+        let t1 = self.list(Segment::T1).len;
+        let t2 = self.list(Segment::T2).len;
+        let t1_first =
+            t1 > 0 && (t1 > self.target || (for_b2_ghost && t1 == self.target) || t2 == 0);
+        let order = if t1_first {
+            [Segment::T1, Segment::T2]
+        } else {
+            [Segment::T2, Segment::T1]
+        };
+        order
+            .iter()
+            .filter_map(|&segment| self.list(segment).tail.get())
+            .find(|&idx| Some(idx) != spare)
+    }
+
+    fn take_victim(&mut self, for_b2_ghost: bool, spare: Option<usize>) -> Option<(K, V)> {
+        let idx = self.victim(for_b2_ghost, spare)?;
+        Some(self.demote(idx))
+    }
+
+    // Looks up a key, dropping it instead if it has expired, and records the access.
+    // This is the single place hits and misses are observed; ghosts count as misses.
+    fn live_index(&mut self, key: &K) -> Option<usize> {
+        let now = self.clock.now();
+        let idx = match self.resident_index(key) {
+            Some(idx) if self.is_expired(idx, now) => {
+                self.remove_at(idx);
+                None
+            }
+            found => found,
+        };
+        match idx {
+            Some(idx) => {
+                self.node_mut(idx).timestamps.touch(now);
+                self.stats.hits += 1;
+                self.notify(|observer| observer.on_hit(key));
+            }
+            None => {
+                self.stats.misses += 1;
+                trace::miss(POLICY, key);
+                self.notify(|observer| observer.on_miss(key));
+            }
+        }
+        idx
+    }
+
+    fn resident_index(&self, key: &K) -> Option<usize> {
+        let idx = self.index_of(key)?;
+        if self.node(idx).segment.is_ghost() {
+            return None;
+        }
+        Some(idx)
+    }
+
+    fn is_expired(&self, idx: usize, now: Instant) -> bool {
+        self.expiration.is_expired(&self.node(idx).timestamps, now)
+    }
+
+    fn schedule_expiry(&mut self, idx: usize) {
+        let node = &self.nodes[idx];
+        if let (Some(wheel), Some(deadline)) = (
+            self.expiry_wheel.as_mut(),
+            self.expiration.deadline(&node.timestamps),
+        ) {
+            wheel.schedule(self.nodes.key_at(idx), deadline);
+        }
+    }
+
+    // Any use of a cached key moves it to the front of T2.
+    fn promote(&mut self, idx: usize) {
+        if self.list(Segment::T2).head == Link::some(idx) {
+            return;
+        }
+        self.detach(idx);
+        self.push_front(idx, Segment::T2);
+    }
+
+    // Evicts a resident entry, keeping its key in the matching ghost list.
+    fn demote(&mut self, idx: usize) -> (K, V) {
+        self.detach(idx);
+        let slot = self.nodes.key_at(idx);
+        let ghost = self.node(idx).segment.ghost();
+        let node = self.node_mut(idx);
+        let value = node.value.take().expect("index refers to a resident node");
+        let weight = node.weight;
+        node.weight = 0;
+        let key = node.key.clone();
+        self.stats.current_weight -= u64::from(weight);
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.cancel(&slot);
+        }
+        self.push_front(idx, ghost);
+        (key, value)
+    }
+
+    // Drops a ghost from the history.
+    fn forget(&mut self, idx: usize) {
+        self.detach(idx);
+        let node = self.nodes.remove(idx);
+        self.map.remove(&node.key, idx);
+    }
+
+    fn remove_at(&mut self, idx: usize) -> Node<K, V> {
+        self.detach(idx);
+        let slot = self.nodes.key_at(idx);
+        let node = self.nodes.remove(idx);
+        self.map.remove(&node.key, idx);
+        self.stats.current_weight -= u64::from(node.weight);
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.cancel(&slot);
+        }
+        node
+    }
+
+    fn notify<F: FnOnce(&mut dyn CacheObserver<K>)>(&mut self, f: F) {
+        if let Some(observer) = self.observer.as_mut() {
+            f(&mut **observer);
+        }
+    }
+
+    // Capacity-driven eviction; the listener sees every entry dropped this way.
+    fn evict(&mut self, for_b2_ghost: bool, spare: Option<usize>) {
+        if let Some((key, value)) = self.take_victim(for_b2_ghost, spare) {
+            self.stats.evictions += 1;
+            trace::eviction(POLICY, &key);
+            if let Some(listener) = self.eviction_listener.as_mut() {
+                listener(key, value);
+            }
+        }
+    }
+
+    fn list(&self, segment: Segment) -> &List {
+        &self.lists[segment as usize]
+    }
+
+    fn list_mut(&mut self, segment: Segment) -> &mut List {
+        &mut self.lists[segment as usize]
+    }
+
+    fn node(&self, idx: usize) -> &Node<K, V> {
+        &self.nodes[idx]
+    }
+
+    fn node_mut(&mut self, idx: usize) -> &mut Node<K, V> {
+        &mut self.nodes[idx]
+    }
+
+    fn index_of(&self, key: &K) -> Option<usize> {
+        self.map.get(key, &self.nodes).map(SlabKey::index)
+    }
+
+    fn value(&self, idx: usize) -> &V {
+        self.node(idx)
+            .value
+            .as_ref()
+            .expect("index refers to a resident node")
+    }
+
+    fn value_mut(&mut self, idx: usize) -> &mut V {
+        self.node_mut(idx)
+            .value
+            .as_mut()
+            .expect("index refers to a resident node")
+    }
+
+    // Moves a node into a free slot, repointing its neighbours and its map entry.
+    fn relocate(&mut self, from: usize, to: usize) {
+        let old = self.nodes.key_at(from);
+        let slot = self.nodes.relocate(from, to);
+        let node = &self.nodes[to];
+        let (prev, next, segment) = (node.prev, node.next, node.segment);
+        match prev.get() {
+            Some(p) => self.node_mut(p).next = Link::some(to),
+            None => self.list_mut(segment).head = Link::some(to),
+        }
+        match next.get() {
+            Some(n) => self.node_mut(n).prev = Link::some(to),
+            None => self.list_mut(segment).tail = Link::some(to),
+        }
+        self.map.repoint(&self.nodes[to].key, from, slot);
+        if self
+            .expiry_wheel
+            .as_mut()
+            .is_some_and(|wheel| wheel.cancel(&old))
+        {
+            self.schedule_expiry(to);
+        }
+    }
+
+    fn detach(&mut self, idx: usize) {
+        let node = self.node(idx);
+        let (prev, next, segment) = (node.prev, node.next, node.segment);
+
+        match prev.get() {
+            Some(p) => self.node_mut(p).next = next,
+            None => self.list_mut(segment).head = next,
+        }
+
+        match next.get() {
+            Some(n) => self.node_mut(n).prev = prev,
+            None => self.list_mut(segment).tail = prev,
+        }
+
+        self.list_mut(segment).len -= 1;
+    }
+
+    fn push_front(&mut self, idx: usize, segment: Segment) {
+        let head = self.list(segment).head;
+        let node = self.node_mut(idx);
+        node.prev = Link::NONE;
+        node.next = head;
+        node.segment = segment;
+
+        if let Some(old_head) = head.get() {
+            self.node_mut(old_head).prev = Link::some(idx);
+        }
+
+        let list = self.list_mut(segment);
+        list.head = Link::some(idx);
+        if list.tail.is_none() {
+            list.tail = Link::some(idx);
+        }
+        list.len += 1;
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher> IntoIterator for ARCCache<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter {
+            remaining: self.len(),
+            next: self.list(Segment::T1).head.get(),
+            then: self.list(Segment::T2).head.get(),
+            nodes: self.nodes,
+        }
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher> IntoIterator for &'a ARCCache<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+// The capacity is sized to hold every collected entry.
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Default> FromIterator<(K, V, u32)>
+    for ARCCache<K, V, S>
+{
+    fn from_iter<I: IntoIterator<Item = (K, V, u32)>>(iter: I) -> Self {
+        let entries: Vec<_> = iter.into_iter().collect();
+        let mut cache = ARCCache::with_hasher(entries.len().max(1), S::default());
+        cache.extend(entries);
+        cache
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher> Extend<(K, V, u32)> for ARCCache<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V, u32)>>(&mut self, iter: I) {
+        for (key, value, weight) in iter {
+            self.put(key, value, weight);
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher> Cache<K, V> for ARCCache<K, V, S> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Default> NewCache<K, V> for ARCCache<K, V, S> {
+    fn new(capacity: usize) -> Self {
+        ARCCache::with_hasher(capacity, S::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    impl<K: Clone + Hash + Eq, V, S: BuildHasher> ARCCache<K, V, S> {
+        fn keys_in(&self, segment: Segment) -> Vec<K> {
+            let mut keys = Vec::new();
+            let mut next = self.list(segment).head;
+            while let Some(idx) = next.get() {
+                let node = self.node(idx);
+                keys.push(node.key.clone());
+                next = node.next;
+            }
+            keys
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Capacity must be greater than 0")]
+    fn test_new_cache_zero_capacity() {
+        let _cache: ARCCache<String, i32> = ARCCache::new(0);
+    }
+
+    #[test]
+    fn test_basic_operations() {
+        let mut cache = ARCCache::new(2);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn test_second_use_moves_to_frequency_list() {
+        let mut cache = ARCCache::new(3);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.get(&1);
+
+        assert_eq!(cache.keys_in(Segment::T1), vec![2]);
+        assert_eq!(cache.keys_in(Segment::T2), vec![1]);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&2, &1]);
+    }
+
+    #[test]
+    fn test_scan_does_not_flush_hot_set() {
+        let mut cache = ARCCache::new(4);
+        for key in 0..2 {
+            cache.put(key, "hot", 1);
+            cache.get(&key);
+        }
+
+        // A long scan of keys used once only cycles through T1
+        for key in 100..200 {
+            cache.put(key, "scan", 1);
+        }
+        assert!(cache.contains_key(&0));
+        assert!(cache.contains_key(&1));
+        assert_eq!(cache.len(), 4);
+    }
+
+    #[test]
+    fn test_ghost_hits_adapt_the_target() {
+        let mut cache = ARCCache::new(2);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.get(&2);
+        cache.put(3, "three", 1); // evicts 1 from T1 into B1
+        assert_eq!(cache.keys_in(Segment::B1), vec![1]);
+
+        // Key 1 came back soon after leaving T1, so T1 should have been larger
+        cache.put(1, "one", 1);
+        assert_eq!(cache.recency_target(), 1);
+        assert_eq!(cache.keys_in(Segment::T1), vec![3]);
+        assert_eq!(cache.keys_in(Segment::T2), vec![1]);
+        assert_eq!(cache.keys_in(Segment::B2), vec![2]);
+
+        // A hit on a B2 ghost shrinks the target again
+        cache.put(2, "two", 1);
+        assert_eq!(cache.recency_target(), 0);
+        assert_eq!(cache.keys_in(Segment::T2), vec![2, 1]);
+        assert_eq!(cache.keys_in(Segment::B1), vec![3]);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut cache = ARCCache::new(3);
+        for round in 0..5 {
+            for key in 0..10 {
+                cache.put(key, round, 1);
+                if key % 3 == 0 {
+                    cache.get(&key);
+                }
+            }
+        }
+
+        let t1 = cache.list(Segment::T1).len;
+        let b1 = cache.list(Segment::B1).len;
+        let ghosts = b1 + cache.list(Segment::B2).len;
+        assert_eq!(cache.len(), 3);
+        assert!(t1 + b1 <= 3);
+        assert!(cache.len() + ghosts <= 6);
+        assert_eq!(cache.map.len(), cache.len() + ghosts);
+    }
+
+    #[test]
+    fn test_get_of_ghost_is_a_miss() {
+        let mut cache = ARCCache::new(2);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.get(&2);
+        cache.put(3, "three", 1);
+
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.remove(&1), None);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.keys_in(Segment::B1), vec![1]);
+    }
+
+    #[test]
+    fn test_remove_and_pop_victim() {
+        let mut cache = ARCCache::new(3);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.put(3, "three", 1);
+        cache.get(&1);
+
+        assert_eq!(cache.remove(&3), Some("three"));
+        assert_eq!(cache.pop_victim(), Some((2, "two")));
+        assert_eq!(cache.pop_victim(), Some((1, "one")));
+        assert_eq!(cache.pop_victim(), None);
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats().current_weight, 0);
+    }
+
+    #[test]
+    fn test_heavier_update_evicts_others() {
+        let mut cache = ARCCache::new(4);
+        cache.set_max_weight(30);
+        cache.put(1, "one", 10);
+        cache.put(2, "two", 10);
+        cache.put(3, "three", 10);
+
+        cache.put(3, "THREE", 20);
+        assert_eq!(cache.peek(&3), Some(&"THREE"));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats().current_weight, 30);
+    }
+
+    #[test]
+    fn test_eviction_listener() {
+        use std::sync::Mutex;
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = ARCCache::new(1);
+        cache.set_eviction_listener(move |key, value| sink.lock().unwrap().push((key, value)));
+
+        cache.put(1, "one", 1);
+        cache.remove(&1);
+        cache.put(2, "two", 1);
+        cache.put(3, "three", 1);
+        assert_eq!(*evicted.lock().unwrap(), vec![(2, "two")]);
+    }
+
+    #[test]
+    fn test_expire_after_access() {
+        let clock = ManualClock::new();
+        let mut cache = ARCCache::new(2);
+        cache.set_clock(Arc::new(clock.clone()));
+        cache.set_expire_after_access(Duration::from_secs(10));
+
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        clock.advance(Duration::from_secs(5));
+        cache.get(&2);
+        clock.advance(Duration::from_secs(5));
+
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.expire_due(clock.now()), 1);
+        assert_eq!(cache.get(&2), Some(&"two"));
+        assert!(cache.keys_in(Segment::B1).is_empty());
+    }
+
+    #[test]
+    fn test_iter_and_into_iter() {
+        let mut cache: ARCCache<_, _> = (1..=3).map(|i| (i, i * 10, 1)).collect();
+        cache.get(&2);
+
+        assert_eq!(cache.iter().len(), 3);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&3, &1, &2]);
+        let entries: Vec<_> = cache.into_iter().collect();
+        assert_eq!(entries, vec![(3, 30), (1, 10), (2, 20)]);
+    }
+
+    #[test]
+    fn test_shrink_to_fit_keeps_ghosts() {
+        let mut cache = ARCCache::new(4);
+        for key in 0..4 {
+            cache.put(key, key, 1);
+        }
+        cache.get(&0);
+        cache.get(&1);
+        for key in 4..10 {
+            cache.put(key, key, 1);
+        }
+        cache.remove(&0);
+        cache.remove(&9);
+        let segments = [Segment::T1, Segment::T2, Segment::B1, Segment::B2];
+        let before: Vec<Vec<i32>> = segments.iter().map(|&s| cache.keys_in(s)).collect();
+        assert!(!before[2].is_empty());
+
+        cache.shrink_to_fit();
+        assert_eq!(cache.nodes.slots(), cache.map.len());
+        let after: Vec<Vec<i32>> = segments.iter().map(|&s| cache.keys_in(s)).collect();
+        assert_eq!(after, before);
+
+        // A ghost hit still finds its relocated node
+        let ghost = before[2][0];
+        cache.put(ghost, 0, 1);
+        assert_eq!(cache.keys_in(Segment::T2)[0], ghost);
+        assert_eq!(cache.get(&ghost), Some(&0));
+    }
+}
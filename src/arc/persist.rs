@@ -0,0 +1,268 @@
+use std::hash::{BuildHasher, Hash};
+use std::io::{Read, Write};
+
+use super::{ARCCache, Node, Segment};
+use crate::expiry::{SavedTimestamps, Timestamps};
+use crate::snapshot::{self, impl_codec, Decode, Encode, SnapshotError, SnapshotReader};
+use crate::stats::CacheStats;
+use crate::util::{self, Link};
+use crate::Policy;
+
+// What is saved of a cache, by serde and by snapshots alike. All four lists are saved,
+// ghosts included, so a restored cache adapts exactly as the original would. Each list
+// runs from its least to its most recently used key.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Saved<K, V> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    stats: CacheStats,
+    target: usize,
+    t1: Vec<SavedEntry<K, V>>,
+    t2: Vec<SavedEntry<K, V>>,
+    b1: Vec<K>,
+    b2: Vec<K>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct SavedEntry<K, V> {
+    key: K,
+    value: V,
+    weight: u32,
+    timestamps: SavedTimestamps,
+}
+
+impl_codec!(Saved<K, V> {
+    capacity,
+    max_weight,
+    stats,
+    target,
+    t1,
+    t2,
+    b1,
+    b2,
+});
+impl_codec!(SavedEntry<K, V> {
+    key,
+    value,
+    weight,
+    timestamps,
+});
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher> ARCCache<K, V, S> {
+    fn save(&self) -> Saved<&K, &V> {
+        let now = self.clock.now();
+        let resident = |segment| {
+            self.oldest_first(segment)
+                .map(|node| SavedEntry {
+                    key: &node.key,
+                    value: node.value.as_ref().expect("T1 and T2 hold resident nodes"),
+                    weight: node.weight,
+                    timestamps: SavedTimestamps::save(&node.timestamps, now),
+                })
+                .collect()
+        };
+        let ghosts = |segment| self.oldest_first(segment).map(|node| &node.key).collect();
+        Saved {
+            capacity: self.capacity,
+            max_weight: self.max_weight,
+            stats: self.stats,
+            target: self.target,
+            t1: resident(Segment::T1),
+            t2: resident(Segment::T2),
+            b1: ghosts(Segment::B1),
+            b2: ghosts(Segment::B2),
+        }
+    }
+
+    fn oldest_first(&self, segment: Segment) -> impl Iterator<Item = &Node<K, V>> {
+        let mut next = self.list(segment).tail.get();
+        std::iter::from_fn(move || {
+            let node = self.node(next?);
+            next = node.prev.get();
+            Some(node)
+        })
+    }
+
+    // Appends a node at the head of its list, unless the key is already there.
+    fn push_restored(
+        &mut self,
+        segment: Segment,
+        key: K,
+        value: Option<V>,
+        weight: u32,
+        timestamps: Timestamps,
+    ) -> Result<(), &'static str> {
+        if self.index_of(&key).is_some() {
+            return Err("duplicate key");
+        }
+        let slot = self.nodes.insert(Node {
+            key,
+            value,
+            weight,
+            timestamps,
+            segment,
+            prev: Link::NONE,
+            next: Link::NONE,
+        });
+        self.map.insert(slot, &self.nodes);
+        self.push_front(slot.index(), segment);
+        self.stats.current_weight += u64::from(weight);
+        Ok(())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Default> ARCCache<K, V, S> {
+    fn restore(saved: Saved<K, V>) -> Result<Self, &'static str> {
+        let resident = saved.t1.len() + saved.t2.len();
+        snapshot::check_len(saved.capacity, saved.max_weight, resident)?;
+        if saved.target > saved.capacity {
+            return Err("recency target exceeds the capacity");
+        }
+        let mut cache = ARCCache::with_hasher(saved.capacity, S::default());
+        cache.max_weight = saved.max_weight;
+        cache.target = saved.target;
+        cache.stats = saved.stats;
+        cache.stats.current_weight = 0;
+        let now = cache.clock.now();
+        for (segment, entries) in [(Segment::T1, saved.t1), (Segment::T2, saved.t2)] {
+            for entry in entries {
+                let timestamps = entry.timestamps.restore(now);
+                cache.push_restored(
+                    segment,
+                    entry.key,
+                    Some(entry.value),
+                    entry.weight,
+                    timestamps,
+                )?;
+            }
+        }
+        for (segment, keys) in [(Segment::B1, saved.b1), (Segment::B2, saved.b2)] {
+            for key in keys {
+                cache.push_restored(segment, key, None, 0, Timestamps::new(now))?;
+            }
+        }
+        Ok(cache)
+    }
+}
+
+impl<K: Clone + Hash + Eq + Encode, V: Encode, S: BuildHasher> ARCCache<K, V, S> {
+    // Writes the entries and all four lists as a checksummed binary snapshot.
+    pub fn save_to<W: Write>(&self, writer: W) -> Result<(), SnapshotError> {
+        snapshot::write(writer, Policy::ARC, &self.save())
+    }
+}
+
+impl<K: Clone + Hash + Eq + Decode, V: Decode, S: BuildHasher + Default> ARCCache<K, V, S> {
+    pub fn load_from<R: Read>(reader: R) -> Result<Self, SnapshotError> {
+        ARCCache::load_snapshot(SnapshotReader::open(reader)?)
+    }
+
+    pub(crate) fn load_snapshot<R: Read>(
+        snapshot: SnapshotReader<R>,
+    ) -> Result<Self, SnapshotError> {
+        let saved = snapshot.body(Policy::ARC)?;
+        ARCCache::restore(saved).map_err(SnapshotError::Invalid)
+    }
+}
+
+impl<K: Clone + Hash + Eq, V: Clone, S: BuildHasher> ARCCache<K, V, S> {
+    // The `n` entries ARC values most, with their weights: those seen again, then those
+    // seen once, each from most recently used.
+    pub fn export_top(&self, n: usize) -> Vec<(K, V, u32)> {
+        let (recent, frequent): (Vec<_>, Vec<_>) = self.iter().partition(|&(key, _)| {
+            self.index_of(key)
+                .is_some_and(|idx| self.node(idx).segment == Segment::T1)
+        });
+        let entries = frequent.into_iter().chain(recent);
+        util::top_entries(entries, n, |key| self.entry_info(key))
+    }
+
+    // Stores exported entries, hottest first, in the frequency list, as if each had been
+    // seen twice.
+    pub fn import<I: IntoIterator<Item = (K, V, u32)>>(&mut self, entries: I) {
+        let entries: Vec<_> = entries.into_iter().collect();
+        for (key, value, weight) in entries.into_iter().rev() {
+            self.put(key.clone(), value, weight);
+            if let Some(idx) = self.resident_index(&key) {
+                self.promote(idx);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V, S> serde::Serialize for ARCCache<K, V, S>
+where
+    K: Clone + Hash + Eq + serde::Serialize,
+    V: serde::Serialize,
+    S: BuildHasher,
+{
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        serde::Serialize::serialize(&self.save(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, S> serde::Deserialize<'de> for ARCCache<K, V, S>
+where
+    K: Clone + Hash + Eq + serde::Deserialize<'de>,
+    V: serde::Deserialize<'de>,
+    S: BuildHasher + Default,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = serde::Deserialize::deserialize(deserializer)?;
+        ARCCache::restore(saved).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::RandomState;
+
+    fn used() -> ARCCache<i32, i32> {
+        let mut cache = ARCCache::new(3);
+        for key in 0..3 {
+            cache.put(key, key * 10, 1);
+        }
+        cache.get(&0);
+        cache.put(3, 30, 1);
+        cache.put(4, 40, 1);
+        cache.put(1, 10, 1);
+        cache
+    }
+
+    fn assert_same(mut restored: ARCCache<i32, i32, RandomState>, mut cache: ARCCache<i32, i32>) {
+        assert_eq!(restored.stats(), cache.stats());
+        assert_eq!(restored.recency_target(), cache.recency_target());
+        let order: Vec<i32> = restored.iter().map(|(&key, _)| key).collect();
+        let original: Vec<i32> = cache.iter().map(|(&key, _)| key).collect();
+        assert_eq!(order, original);
+
+        // The ghosts came along, so a hit on one adapts the target the same way
+        for key in [2, 3, 5] {
+            restored.put(key, 0, 1);
+            cache.put(key, 0, 1);
+            assert_eq!(restored.recency_target(), cache.recency_target());
+        }
+        for _ in 0..3 {
+            assert_eq!(restored.pop_victim(), cache.pop_victim());
+        }
+    }
+
+    #[test]
+    fn test_snapshot_keeps_lists_and_ghosts() {
+        let cache = used();
+        let mut bytes = Vec::new();
+        cache.save_to(&mut bytes).unwrap();
+        assert_same(ARCCache::load_from(bytes.as_slice()).unwrap(), cache);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_keeps_lists_and_ghosts() {
+        let cache = used();
+        let json = serde_json::to_string(&cache).unwrap();
+        assert_same(serde_json::from_str(&json).unwrap(), cache);
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OnceCell;
+
+use crate::sync_cache::lock;
+use crate::{Cache, NewCache};
+
+// Shared by the callers waiting for one key to load.
+type Loading<V> = Arc<OnceCell<V>>;
+
+// Puts any policy behind a lock for async callers and loads missing values with async
+// loaders. The lock is a plain `Mutex` held only for single cache operations, never
+// across an await, so a task waiting on it is never suspended holding it and the wait
+// stays short. While a key is loading, later callers for it wait for the same load
+// instead of starting their own.
+pub struct AsyncCache<K, V, C> {
+    cache: Mutex<C>,
+    loading: Mutex<HashMap<K, Loading<V>>>,
+    marker: PhantomData<fn(K, V)>,
+}
+
+impl<K: Clone + Hash + Eq, V: Clone, C: NewCache<K, V>> AsyncCache<K, V, C> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        AsyncCache::new(C::new(capacity))
+    }
+}
+
+impl<K: Clone + Hash + Eq, V: Clone, C: Cache<K, V>> AsyncCache<K, V, C> {
+    pub fn new(cache: C) -> Self {
+        AsyncCache {
+            cache: Mutex::new(cache),
+            loading: Mutex::new(HashMap::new()),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        lock(&self.cache).get(key).cloned()
+    }
+
+    // Returns the cached value, or awaits `loader` and caches what it returns. If the
+    // task driving a load is cancelled, one of the callers still waiting runs its own
+    // loader instead.
+    pub async fn get_with<F, Fut>(&self, key: K, weight: u32, loader: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let landing = match self.join_load(key) {
+            Ok(value) => return value,
+            Err(landing) => landing,
+        };
+        let value = landing.cell.get_or_init(loader).await.clone();
+        self.finish_load(landing, &value, weight);
+        value
+    }
+
+    // Like `get_with`, but errors are returned to the caller whose loader failed and
+    // nothing is cached. Callers waiting on the same key run their own loaders.
+    pub async fn try_get_with<E, F, Fut>(&self, key: K, weight: u32, loader: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        let landing = match self.join_load(key) {
+            Ok(value) => return Ok(value),
+            Err(landing) => landing,
+        };
+        let value = landing.cell.get_or_try_init(loader).await?.clone();
+        self.finish_load(landing, &value, weight);
+        Ok(value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        lock(&self.cache).contains_key(key)
+    }
+
+    pub fn put(&self, key: K, value: V, weight: u32) {
+        lock(&self.cache).put(key, value, weight);
+    }
+
+    // Loads already in flight still cache their result when they finish.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        lock(&self.cache).remove(key)
+    }
+
+    pub fn clear(&self) {
+        lock(&self.cache).clear();
+    }
+
+    pub fn len(&self) -> usize {
+        lock(&self.cache).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        lock(&self.cache).is_empty()
+    }
+
+    // Returns the cached value, or the load to wait on. The cache is peeked again with
+    // the loading map locked, since a load that just finished stores its value and
+    // leaves the map under that lock; only the first look counts as a hit or miss.
+    fn join_load(&self, key: K) -> Result<V, Landing<'_, K, V>> {
+        if let Some(value) = self.get(&key) {
+            return Ok(value);
+        }
+        let mut loading = lock(&self.loading);
+        if let Some(value) = lock(&self.cache).peek(&key).cloned() {
+            return Ok(value);
+        }
+        let cell = loading.entry(key.clone()).or_default().clone();
+        Err(Landing {
+            loading: &self.loading,
+            key: Some(key),
+            cell,
+        })
+    }
+
+    // The first caller to get here for a load caches its value; the others find the
+    // load already gone.
+    fn finish_load(&self, mut landing: Landing<'_, K, V>, value: &V, weight: u32) {
+        let key = landing.key.take().expect("the load has not landed yet");
+        let mut loading = lock(&self.loading);
+        if loading
+            .get(&key)
+            .is_some_and(|current| Arc::ptr_eq(current, &landing.cell))
+        {
+            loading.remove(&key);
+            lock(&self.cache).put(key, value.clone(), weight);
+        }
+    }
+}
+
+// Deregisters a load once no caller is left to finish it, because every loader failed or
+// every task awaiting it was cancelled, so failing keys do not pile up in the map.
+struct Landing<'a, K: Hash + Eq, V> {
+    loading: &'a Mutex<HashMap<K, Loading<V>>>,
+    // Taken once the load has finished
+    key: Option<K>,
+    cell: Loading<V>,
+}
+
+impl<'a, K: Hash + Eq, V> Drop for Landing<'a, K, V> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            // Not `lock`: this may run while unwinding, where a second panic aborts
+            if let Ok(mut loading) = self.loading.lock() {
+                // Held by the map and this caller alone; anyone else waiting finishes it
+                let abandoned = loading.get(&key).is_some_and(|current| {
+                    Arc::ptr_eq(current, &self.cell) && Arc::strong_count(&self.cell) == 2
+                });
+                if abandoned {
+                    loading.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V: Clone, C: Cache<K, V>> From<C> for AsyncCache<K, V, C> {
+    fn from(cache: C) -> Self {
+        AsyncCache::new(cache)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lru::LRUCache;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_miss_loads_and_caches() {
+        let cache: AsyncCache<&str, String, LRUCache<_, _>> = AsyncCache::with_capacity(2);
+        let value = cache
+            .get_with("rust", 1, || async { "results for rust".to_string() })
+            .await;
+        assert_eq!(value, "results for rust");
+        assert_eq!(lock(&cache.cache).stats().misses, 1);
+        assert_eq!(cache.get(&"rust"), Some(value));
+
+        let cached = cache
+            .get_with("rust", 1, || async { unreachable!("the value is cached") })
+            .await;
+        assert_eq!(cached, "results for rust");
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_misses_share_one_load() {
+        let cache: AsyncCache<u32, u32, LRUCache<_, _>> = AsyncCache::with_capacity(4);
+        let loads = AtomicUsize::new(0);
+        let load = || async {
+            loads.fetch_add(1, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            7
+        };
+        let (a, b, c) = tokio::join!(
+            cache.get_with(1, 1, load),
+            cache.get_with(1, 1, load),
+            cache.get_with(1, 1, load),
+        );
+        assert_eq!((a, b, c), (7, 7, 7));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.get(&1), Some(7));
+        assert!(lock(&cache.loading).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_loads_are_not_cached() {
+        let cache: AsyncCache<u32, u32, LRUCache<_, _>> = AsyncCache::with_capacity(4);
+        let failed = cache
+            .try_get_with(1, 1, || async { Err::<u32, _>("backend unavailable") })
+            .await;
+        assert_eq!(failed, Err("backend unavailable"));
+        assert!(!cache.contains_key(&1));
+        assert!(lock(&cache.loading).is_empty());
+
+        let loaded = cache
+            .try_get_with(1, 1, || async { Ok::<_, &str>(3) })
+            .await;
+        assert_eq!(loaded, Ok(3));
+        assert_eq!(cache.remove(&1), Some(3));
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_loads_are_forgotten() {
+        let cache: AsyncCache<u32, u32, LRUCache<_, _>> = AsyncCache::with_capacity(4);
+        let stalled = cache.get_with(1, 1, std::future::pending::<u32>);
+        tokio::select! {
+            biased;
+            _ = stalled => unreachable!("the loader never finishes"),
+            _ = tokio::task::yield_now() => {}
+        }
+        assert!(lock(&cache.loading).is_empty());
+        assert_eq!(cache.get_with(1, 1, || async { 5 }).await, 5);
+        assert_eq!(lock(&cache.cache).stats().misses, 2);
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::sync_cache::lock;
+use crate::{CacheStats, CacheType, Policy};
+
+// The top completions of one prefix, best first.
+struct Completions<T> {
+    items: Vec<T>,
+    // Whether these are all the completions there are, rather than only the best `k`
+    complete: bool,
+}
+
+impl<T: AsRef<str> + Clone> Completions<T> {
+    // The completions of a longer prefix, when these are enough to know its best `k`: all
+    // of them if this list was complete, or `k` that match if it was not.
+    fn narrow(&self, prefix: &str, k: usize) -> Option<Completions<T>> {
+        let mut items: Vec<T> = self
+            .items
+            .iter()
+            .filter(|item| item.as_ref().starts_with(prefix))
+            .cloned()
+            .collect();
+        if !self.complete && items.len() < k {
+            return None;
+        }
+        items.truncate(k);
+        Some(Completions {
+            items,
+            complete: self.complete,
+        })
+    }
+}
+
+// The cached prefixes, character by character.
+#[derive(Default)]
+struct Trie {
+    children: HashMap<char, Trie>,
+    cached: bool,
+}
+
+impl Trie {
+    fn insert(&mut self, prefix: &str) {
+        let mut node = self;
+        for c in prefix.chars() {
+            node = node.children.entry(c).or_default();
+        }
+        node.cached = true;
+    }
+
+    // Branches left without a cached prefix are pruned.
+    fn remove(&mut self, prefix: &str) {
+        let mut chars = prefix.chars();
+        match chars.next() {
+            None => self.cached = false,
+            Some(c) => {
+                if let Some(child) = self.children.get_mut(&c) {
+                    child.remove(chars.as_str());
+                    if !child.cached && child.children.is_empty() {
+                        self.children.remove(&c);
+                    }
+                }
+            }
+        }
+    }
+
+    // The byte lengths of the cached prefixes of `query` shorter than it, longest first.
+    fn ancestors(&self, query: &str) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut node = self;
+        for (i, c) in query.char_indices() {
+            if node.cached {
+                found.push(i);
+            }
+            match node.children.get(&c) {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+        found.reverse();
+        found
+    }
+
+    fn clear(&mut self) {
+        self.children.clear();
+        self.cached = false;
+    }
+}
+
+// Caches the top `k` completions of each prefix typed into a search box. A prefix that
+// misses can often be answered from a shorter one that is cached: the completions of
+// "rus" are those of "ru" that start with "rus", as long as "ru" kept all of its
+// completions, or at least `k` of them start with "rus". A trie of the cached prefixes
+// finds the longest one usable without looking up every shorter prefix, and the answer is
+// cached under the longer prefix in turn. Only prefixes no cached one can answer go to the
+// backend.
+//
+// Backends should return up to `k` completions, best first; fewer than `k` means there
+// are no more.
+pub struct AutocompleteCache<T> {
+    cache: CacheType<String, Completions<T>>,
+    trie: Trie,
+    k: usize,
+    narrowed: u64,
+    // What the policy evicted during the current call, removed from the trie before it
+    // returns
+    evicted: Arc<Mutex<Vec<String>>>,
+}
+
+impl<T: AsRef<str> + Clone> AutocompleteCache<T> {
+    // Caches the completions of up to `capacity` prefixes.
+    pub fn new(policy: Policy, capacity: usize, k: usize) -> Self {
+        assert!(k > 0, "Completions per prefix must be greater than 0");
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = CacheType::new(policy, capacity);
+        cache.set_eviction_listener(move |prefix, _| lock(&sink).push(prefix));
+        AutocompleteCache {
+            cache,
+            trie: Trie::default(),
+            k,
+            narrowed: 0,
+            evicted,
+        }
+    }
+
+    // The completions of the prefix, from its own entry or narrowed from a shorter one.
+    pub fn get(&mut self, prefix: &str) -> Option<&[T]> {
+        if !self.lookup(prefix) {
+            return None;
+        }
+        self.peek(prefix)
+    }
+
+    // Like `get`, but a prefix nothing cached can answer is passed to `fetch` with `k`,
+    // and what it returns is cached. Nothing is cached if `fetch` fails.
+    pub fn get_or_fetch<E, F>(&mut self, prefix: &str, fetch: F) -> Result<&[T], E>
+    where
+        F: FnOnce(&str, usize) -> Result<Vec<T>, E>,
+    {
+        if !self.lookup(prefix) {
+            let completions = fetch(prefix, self.k)?;
+            self.put(prefix, completions);
+        }
+        Ok(self.peek(prefix).unwrap_or_default())
+    }
+
+    // Only the prefix's own entry, without narrowing.
+    pub fn peek(&self, prefix: &str) -> Option<&[T]> {
+        self.cache
+            .peek(&prefix.to_string())
+            .map(|completions| completions.items.as_slice())
+    }
+
+    pub fn contains_key(&self, prefix: &str) -> bool {
+        self.cache.contains_key(&prefix.to_string())
+    }
+
+    // Keeps the first `k` completions. Fewer than `k` are taken to be all there are.
+    pub fn put(&mut self, prefix: &str, mut completions: Vec<T>) {
+        let complete = completions.len() < self.k;
+        completions.truncate(self.k);
+        self.store(
+            prefix,
+            Completions {
+                items: completions,
+                complete,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, prefix: &str) -> Option<Vec<T>> {
+        self.trie.remove(prefix);
+        self.cache
+            .remove(&prefix.to_string())
+            .map(|completions| completions.items)
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+        self.trie.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    // Misses answered by narrowing a shorter prefix's completions.
+    pub fn narrowed(&self) -> u64 {
+        self.narrowed
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    // Returns whether the prefix's completions are now cached under it.
+    fn lookup(&mut self, prefix: &str) -> bool {
+        if self.cache.get(&prefix.to_string()).is_some() {
+            return true;
+        }
+        match self.narrow(prefix) {
+            Some(completions) => {
+                self.narrowed += 1;
+                self.store(prefix, completions);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn narrow(&mut self, prefix: &str) -> Option<Completions<T>> {
+        for end in self.trie.ancestors(prefix) {
+            let ancestor = &prefix[..end];
+            match self.cache.peek(&ancestor.to_string()) {
+                Some(completions) => {
+                    if let Some(narrowed) = completions.narrow(prefix, self.k) {
+                        return Some(narrowed);
+                    }
+                }
+                // Expired since it was cached
+                None => self.trie.remove(ancestor),
+            }
+        }
+        None
+    }
+
+    fn store(&mut self, prefix: &str, completions: Completions<T>) {
+        self.trie.insert(prefix);
+        self.cache.put(prefix.to_string(), completions, 1);
+        for evicted in lock(&self.evicted).drain(..) {
+            self.trie.remove(&evicted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    fn completions(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn test_longer_prefixes_narrow_complete_lists() {
+        let mut cache = AutocompleteCache::new(Policy::LRU, 10, 3);
+        cache.put("ru", completions(&["rust", "ruby"]));
+        assert_eq!(cache.get("rus"), Some(&completions(&["rust"])[..]));
+        assert_eq!(cache.get("rusty"), Some(&[][..]));
+        assert_eq!(cache.narrowed(), 2);
+        assert!(cache.contains_key("rus"));
+        assert_eq!(cache.get("zig"), None);
+    }
+
+    #[test]
+    fn test_truncated_lists_only_narrow_with_enough_matches() {
+        let mut cache = AutocompleteCache::new(Policy::LRU, 10, 2);
+        cache.put("r", completions(&["rust", "rust book", "ruby"]));
+        assert_eq!(cache.peek("r").map(<[String]>::len), Some(2));
+        assert_eq!(
+            cache.get("rus"),
+            Some(&completions(&["rust", "rust book"])[..])
+        );
+        assert_eq!(cache.get("rub"), None);
+
+        let mut fetched = Vec::new();
+        let ruby = cache
+            .get_or_fetch("rub", |prefix, k| {
+                fetched.push((prefix.to_string(), k));
+                Ok::<_, Infallible>(completions(&["ruby", "rubocop"]))
+            })
+            .unwrap();
+        assert_eq!(ruby, &completions(&["ruby", "rubocop"])[..]);
+        assert_eq!(fetched, vec![("rub".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_evicted_prefixes_leave_the_trie() {
+        let mut cache = AutocompleteCache::new(Policy::LRU, 1, 3);
+        cache.put("ru", completions(&["rust"]));
+        cache.put("zi", completions(&["zig"]));
+        assert_eq!(cache.get("rus"), None);
+        assert!(cache.trie.ancestors("rus").is_empty());
+
+        cache.remove("zi");
+        assert!(cache.trie.children.is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+// Replays an access log through cache policies at a range of sizes and prints how each
+// did, for running policy experiments without writing Rust.
+use std::collections::HashSet;
+use std::env;
+use std::process;
+
+use search_engine_cache::{
+    compare_policies, compare_policies_by_weight, read_trace_file, ComparisonReport, Policy,
+};
+
+const USAGE: &str = "usage: cachesim TRACE [options]
+
+TRACE is a .csv file of `timestamp,key[,size[,cost]]` lines or a .jsonl file of
+objects with the same fields.
+
+options:
+  --policies LIST   policies to compare, comma separated (default: all)
+  --sizes RANGE     cache sizes, as MIN..MAX or a comma separated list
+                    (default: 1% to 100% of the distinct keys)
+  --steps N         sizes to try within MIN..MAX, spaced evenly on a log scale (default: 8)
+  --by-weight       size caches by total request size instead of entries
+  --csv             print CSV instead of a table
+  --plot            also plot hit ratio against size";
+
+const ALL_POLICIES: [Policy; 8] = [
+    Policy::LRU,
+    Policy::LFU,
+    Policy::Landlord,
+    Policy::LFUDA,
+    Policy::ARC,
+    Policy::FIFO,
+    Policy::Random,
+    Policy::MRU,
+];
+
+const PLOT_HEIGHT: usize = 20;
+const PLOT_COLUMN: usize = 6;
+
+#[derive(Debug, PartialEq)]
+enum Sizes {
+    Range { min: u64, max: u64 },
+    List(Vec<u64>),
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    trace: String,
+    policies: Vec<Policy>,
+    sizes: Option<Sizes>,
+    steps: usize,
+    by_weight: bool,
+    csv: bool,
+    plot: bool,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut trace = None;
+    let mut options = Options {
+        trace: String::new(),
+        policies: ALL_POLICIES.to_vec(),
+        sizes: None,
+        steps: 8,
+        by_weight: false,
+        csv: false,
+        plot: false,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "--policies" => {
+                options.policies = value("--policies")?
+                    .split(',')
+                    .map(|name| name.trim().parse().map_err(|error| format!("{}", error)))
+                    .collect::<Result<_, _>>()?;
+            }
+            "--sizes" => options.sizes = Some(parse_sizes(&value("--sizes")?)?),
+            "--steps" => {
+                options.steps = match value("--steps")?.parse() {
+                    Ok(steps) if steps > 0 => steps,
+                    _ => return Err("--steps must be a positive number".to_string()),
+                }
+            }
+            "--by-weight" => options.by_weight = true,
+            "--csv" => options.csv = true,
+            "--plot" => options.plot = true,
+            "-h" | "--help" => return Err(String::new()),
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            path if trace.is_none() => trace = Some(path.to_string()),
+            extra => return Err(format!("unexpected argument {}", extra)),
+        }
+    }
+    options.trace = trace.ok_or("no trace file given")?;
+    Ok(options)
+}
+
+fn parse_sizes(sizes: &str) -> Result<Sizes, String> {
+    let size = |size: &str| {
+        size.trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|&size| size > 0)
+            .ok_or(format!("invalid size {:?}", size))
+    };
+    if let Some((min, max)) = sizes.split_once("..") {
+        let (min, max) = (size(min)?, size(max)?);
+        if min > max {
+            return Err(format!("empty size range {}", sizes));
+        }
+        return Ok(Sizes::Range { min, max });
+    }
+    Ok(Sizes::List(
+        sizes.split(',').map(size).collect::<Result<_, _>>()?,
+    ))
+}
+
+// `steps` sizes from `min` to `max`, spaced evenly on a log scale, without repeats.
+fn log_spaced(min: u64, max: u64, steps: usize) -> Vec<u64> {
+    if steps == 1 || min == max {
+        return vec![max];
+    }
+    let ratio = (max as f64 / min as f64).powf(1.0 / (steps - 1) as f64);
+    let mut sizes: Vec<u64> = (0..steps)
+        .map(|step| (min as f64 * ratio.powi(step as i32)).round() as u64)
+        .collect();
+    sizes[steps - 1] = max;
+    sizes.dedup();
+    sizes
+}
+
+// A column per size and a row per 5% of hit ratio, with a letter for each policy.
+fn plot(report: &ComparisonReport, sizes: &[u64], policies: &[Policy]) -> String {
+    let mark = |index: usize| (b'A' + index as u8) as char;
+    let mut grid = vec![vec![' '; sizes.len() * PLOT_COLUMN]; PLOT_HEIGHT + 1];
+    for (column, &size) in sizes.iter().enumerate() {
+        for (index, &policy) in policies.iter().enumerate() {
+            if let Some(row) = report.get(policy, size) {
+                let height = (row.report.hit_ratio() * PLOT_HEIGHT as f64).round() as usize;
+                let cell = &mut grid[PLOT_HEIGHT - height][column * PLOT_COLUMN + PLOT_COLUMN / 2];
+                // Policies on the same point share a `*`
+                *cell = if *cell == ' ' { mark(index) } else { '*' };
+            }
+        }
+    }
+
+    let mut plot = String::new();
+    for (line, cells) in grid.iter().enumerate() {
+        let percent = (PLOT_HEIGHT - line) * 100 / PLOT_HEIGHT;
+        let label = if line % 4 == 0 {
+            format!("{:>4}%", percent)
+        } else {
+            String::new()
+        };
+        let cells: String = cells.iter().collect();
+        plot.push_str(&format!("{:>5} |{}\n", label, cells.trim_end()));
+    }
+    plot.push_str(&format!(
+        "{:>5} +{}\n",
+        "",
+        "-".repeat(sizes.len() * PLOT_COLUMN)
+    ));
+    let axis: String = sizes
+        .iter()
+        .map(|size| format!("{:^width$}", size, width = PLOT_COLUMN))
+        .collect();
+    plot.push_str(&format!("{:>5}  {}\n\n", "size", axis.trim_end()));
+    for (index, policy) in policies.iter().enumerate() {
+        plot.push_str(&format!("  {} {:?}\n", mark(index), policy));
+    }
+    plot
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let trace = read_trace_file(&options.trace).map_err(|error| format!("{}", error))?;
+    if trace.is_empty() {
+        return Err(format!("{} holds no requests", options.trace));
+    }
+    let sizes = match &options.sizes {
+        Some(Sizes::List(sizes)) => sizes.clone(),
+        Some(Sizes::Range { min, max }) => log_spaced(*min, *max, options.steps),
+        None => {
+            // Entries, or bytes, to hold every distinct key once
+            let mut seen = HashSet::new();
+            let total: u64 = trace
+                .iter()
+                .filter(|access| seen.insert(access.key.as_str()))
+                .map(|access| {
+                    if options.by_weight {
+                        u64::from(access.weight())
+                    } else {
+                        1
+                    }
+                })
+                .sum();
+            log_spaced((total / 100).max(1), total, options.steps)
+        }
+    };
+
+    let report = if options.by_weight {
+        compare_policies_by_weight(&trace, &sizes, &options.policies)
+    } else {
+        let capacities: Vec<usize> = sizes.iter().map(|&size| size as usize).collect();
+        compare_policies(&trace, &capacities, &options.policies)
+    };
+    if options.csv {
+        print!("{}", report.to_csv());
+    } else {
+        println!("{} requests\n", trace.len());
+        print!("{}", report);
+    }
+    if options.plot {
+        println!();
+        print!("{}", plot(&report, &sizes, &options.policies));
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            if !error.is_empty() {
+                eprintln!("cachesim: {}\n", error);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(error) = run(&options) {
+        eprintln!("cachesim: {}", error);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(str::to_string)
+    }
+
+    #[test]
+    fn test_parses_options() {
+        let options = parse_args(args(
+            "log.csv --policies lru,ARC --sizes 10..1000 --steps 3 --plot",
+        ))
+        .unwrap();
+        assert_eq!(options.trace, "log.csv");
+        assert_eq!(options.policies, vec![Policy::LRU, Policy::ARC]);
+        assert_eq!(options.sizes, Some(Sizes::Range { min: 10, max: 1000 }));
+        assert!(options.plot && !options.csv);
+        assert_eq!(log_spaced(10, 1000, 3), vec![10, 100, 1000]);
+        assert_eq!(log_spaced(1, 2, 5), vec![1, 2]);
+
+        assert_eq!(parse_sizes("5, 50"), Ok(Sizes::List(vec![5, 50])));
+        assert!(parse_args(args("--csv")).is_err());
+        assert!(parse_args(args("log.csv --policies lru,clock")).is_err());
+        assert!(parse_args(args("log.csv --sizes 100..10")).is_err());
+    }
+
+    #[test]
+    fn test_plots_every_policy() {
+        let trace = search_engine_cache::Workload::zipf(50, 1.0).trace(2000);
+        let policies = [Policy::LRU, Policy::MRU];
+        let report = compare_policies(&trace, &[5, 50], &policies);
+        let plot = plot(&report, &[5, 50], &policies);
+        assert!(plot.contains("  A LRU\n  B MRU\n"));
+        assert!(plot.lines().next().unwrap().starts_with(" 100% |"));
+        // Both fit every key at 50, so they share a point there
+        let points: String = plot.lines().take(PLOT_HEIGHT + 1).collect();
+        assert_eq!(points.matches('*').count(), 1);
+        assert_eq!(points.matches(['A', 'B']).count(), 2);
+    }
+}
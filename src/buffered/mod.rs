@@ -0,0 +1,272 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+
+use crossbeam_queue::ArrayQueue;
+
+use crate::concurrent::{read, write};
+use crate::sync_cache::lock;
+use crate::util::shard_of;
+use crate::{CacheStats, CacheType, Policy};
+
+const DEFAULT_STRIPES: usize = 16;
+
+// Hits recorded before the oldest are applied. Past that, new hits are dropped until a
+// writer catches up, which costs the policy some accuracy but never blocks a reader.
+pub(crate) const HIT_BUFFER_SIZE: usize = 1024;
+
+struct Policed<K, S> {
+    policy: CacheType<K, (), S>,
+    // Keys the policy evicted during the current write
+    evicted: Arc<Mutex<Vec<K>>>,
+}
+
+// Any policy, with hits that never wait for the policy's lock. Values live in striped maps
+// that readers only lock shared, and the policy itself only tracks keys. A hit pushes its
+// key onto a lock-free buffer; the next writer, or `maintain`, replays the buffered hits
+// into the policy before doing its own work. Eviction order therefore lags the reads by
+// at most one batch.
+pub struct BufferedCache<K, V, S = RandomState> {
+    stripes: Box<[RwLock<HashMap<K, V, S>>]>,
+    hash_builder: S,
+    policed: Mutex<Policed<K, S>>,
+    hits: ArrayQueue<K>,
+    hit_count: AtomicU64,
+    miss_count: AtomicU64,
+}
+
+impl<K: Clone + Hash + Eq + Send + 'static, V> BufferedCache<K, V> {
+    pub fn new(policy: Policy, capacity: usize) -> Self {
+        BufferedCache::with_hasher(policy, capacity, DEFAULT_STRIPES, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq + Send + 'static, V, S: BuildHasher + Clone> BufferedCache<K, V, S> {
+    pub fn with_hasher(policy: Policy, capacity: usize, stripes: usize, hash_builder: S) -> Self {
+        assert!(stripes > 0, "Stripe count must be greater than 0");
+        let mut policy = CacheType::with_hasher(policy, capacity, hash_builder.clone());
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        policy.set_eviction_listener(move |key, ()| lock(&sink).push(key));
+        BufferedCache {
+            stripes: (0..stripes)
+                .map(|_| RwLock::new(HashMap::with_hasher(hash_builder.clone())))
+                .collect(),
+            hash_builder,
+            policed: Mutex::new(Policed { policy, evicted }),
+            hits: ArrayQueue::new(HIT_BUFFER_SIZE),
+            hit_count: AtomicU64::new(0),
+            miss_count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.with_value(key, V::clone)
+    }
+
+    // Runs `f` on the cached value while its stripe is read-locked, which avoids the clone.
+    pub fn with_value<R, F: FnOnce(&V) -> R>(&self, key: &K, f: F) -> Option<R> {
+        let result = read(self.stripe(key)).get(key).map(f);
+        if result.is_none() {
+            self.miss_count.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        self.hit_count.fetch_add(1, Ordering::Relaxed);
+        if self.hits.push(key.clone()).is_err() {
+            // Apply the backlog if no writer is busy; otherwise this hit is dropped
+            if let Ok(mut policed) = self.policed.try_lock() {
+                self.apply_hits(&mut policed);
+            }
+        }
+        result
+    }
+
+    // Does not count as a use of the key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        read(self.stripe(key)).contains_key(key)
+    }
+
+    pub fn put(&self, key: K, value: V, weight: u32) {
+        let mut policed = self.lock_policy();
+        write(self.stripe(&key)).insert(key.clone(), value);
+        policed.policy.put(key, (), weight);
+        self.drop_evicted(&mut policed);
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut policed = self.lock_policy();
+        policed.policy.remove(key)?;
+        write(self.stripe(key)).remove(key)
+    }
+
+    pub fn clear(&self) {
+        let mut policed = self.lock_policy();
+        policed.policy.clear();
+        for stripe in self.stripes.iter() {
+            write(stripe).clear();
+        }
+    }
+
+    // Applies the buffered hits now, e.g. from a timer on a read-only cache.
+    pub fn maintain(&self) {
+        drop(self.lock_policy());
+    }
+
+    // The hit and miss counts are exact even where the policy has not seen the hits yet.
+    pub fn stats(&self) -> CacheStats {
+        let policed = lock(&self.policed);
+        CacheStats {
+            hits: self.hit_count.load(Ordering::Relaxed),
+            misses: self.miss_count.load(Ordering::Relaxed),
+            ..policed.policy.stats()
+        }
+    }
+
+    pub fn policy(&self) -> Policy {
+        lock(&self.policed).policy.policy()
+    }
+
+    pub fn len(&self) -> usize {
+        lock(&self.policed).policy.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Locks the policy and brings it up to date with the reads so far.
+    fn lock_policy(&self) -> MutexGuard<'_, Policed<K, S>> {
+        let mut policed = lock(&self.policed);
+        self.apply_hits(&mut policed);
+        policed
+    }
+
+    // Stops after one buffer's worth, so readers cannot keep a writer here forever.
+    fn apply_hits(&self, policed: &mut Policed<K, S>) {
+        for _ in 0..self.hits.capacity() {
+            match self.hits.pop() {
+                // The key may have left since the hit, which the policy treats as a miss
+                Some(key) => {
+                    policed.policy.get(&key);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn drop_evicted(&self, policed: &mut Policed<K, S>) {
+        for key in lock(&policed.evicted).drain(..) {
+            write(self.stripe(&key)).remove(&key);
+        }
+    }
+
+    fn stripe(&self, key: &K) -> &RwLock<HashMap<K, V, S>> {
+        let hash = self.hash_builder.hash_one(key);
+        &self.stripes[shard_of(hash, self.stripes.len())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_buffered_hits_reach_the_policy() {
+        let cache = BufferedCache::new(Policy::LRU, 2);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        assert_eq!(cache.get(&1), Some("one"));
+
+        // The hit on 1 is applied before the write, so 2 is the victim
+        cache.put(3, "three", 1);
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_stats_count_reads_before_they_are_applied() {
+        let cache = BufferedCache::new(Policy::LFU, 4);
+        cache.put("a", 1, 3);
+        assert_eq!(cache.with_value(&"a", |value| value * 2), Some(2));
+        assert_eq!(cache.get(&"b"), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!(stats.insertions, 1);
+        assert_eq!(stats.current_weight, 3);
+
+        cache.maintain();
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.remove(&"a"), Some(1));
+        assert_eq!(cache.remove(&"a"), None);
+        assert!(cache.is_empty());
+        assert_eq!(cache.policy(), Policy::LFU);
+    }
+
+    #[test]
+    fn test_full_buffer_does_not_block_reads() {
+        let cache = BufferedCache::new(Policy::ARC, 8);
+        cache.put(1, 1, 1);
+        {
+            // A writer holds the policy for longer than the buffer lasts
+            let _policed = lock(&cache.policed);
+            for _ in 0..2 * HIT_BUFFER_SIZE {
+                assert_eq!(cache.get(&1), Some(1));
+            }
+            assert_eq!(cache.hits.len(), HIT_BUFFER_SIZE);
+        }
+        cache.maintain();
+        assert!(cache.hits.is_empty());
+        assert_eq!(cache.stats().hits, 2 * HIT_BUFFER_SIZE as u64);
+    }
+
+    #[test]
+    fn test_evicted_values_leave_the_stripes() {
+        let cache = BufferedCache::new(Policy::FIFO, 16);
+        for key in 0..100 {
+            cache.put(key, key * 10, 1);
+        }
+        assert_eq!(cache.len(), 16);
+        let cached = (0..100).filter(|key| cache.contains_key(key)).count();
+        assert_eq!(cached, 16);
+        assert_eq!(cache.get(&99), Some(990));
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.get(&99), None);
+    }
+
+    #[test]
+    fn test_reads_and_writes_from_many_threads() {
+        let cache = BufferedCache::new(Policy::Landlord, 64);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for key in 0..10_000u64 {
+                    cache.put(key % 128, key % 128 * 2, 1);
+                }
+            });
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for key in 0..10_000u64 {
+                        if let Some(value) = cache.get(&(key % 128)) {
+                            assert_eq!(value, key % 128 * 2);
+                        }
+                    }
+                });
+            }
+        });
+        cache.maintain();
+        assert_eq!(cache.len(), 64);
+        let cached = (0..128).filter(|key| cache.contains_key(key)).count();
+        assert_eq!(cached, 64);
+        let stats = cache.stats();
+        assert_eq!(stats.hits + stats.misses, 40_000);
+    }
+}
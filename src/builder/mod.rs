@@ -0,0 +1,522 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::arc::ARCCache;
+use crate::clock::Clock;
+use crate::expiry::Expiration;
+use crate::fifo::FIFOCache;
+use crate::landlord::Landlord;
+use crate::lfu_w::LFUCache;
+use crate::lfuda::LFUDACache;
+use crate::lru::LRUCache;
+use crate::mru::MRUCache;
+use crate::observer::{BoxedObserver, CacheObserver};
+use crate::random::RandomCache;
+use crate::{CacheType, EvictionListener, Policy, Weigher};
+
+// Collects cache configuration in one place instead of one constructor per combination.
+pub struct CacheBuilder<K, V> {
+    policy: Policy,
+    capacity: usize,
+    max_weight: Option<u64>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    weigher: Option<Weigher<K, V>>,
+    expiration: Expiration,
+    clock: Option<Arc<dyn Clock>>,
+    observer: Option<BoxedObserver<K>>,
+    preallocate: bool,
+}
+
+impl<K: Clone + Hash + Eq, V> CacheBuilder<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        CacheBuilder {
+            policy: Policy::LRU,
+            capacity,
+            max_weight: None,
+            eviction_listener: None,
+            weigher: None,
+            expiration: Expiration::default(),
+            clock: None,
+            observer: None,
+            preallocate: false,
+        }
+    }
+
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    // Bounds the cache by total entry weight instead of by `capacity` entries.
+    pub fn max_weight(mut self, max_weight: u64) -> Self {
+        self.max_weight = Some(max_weight);
+        self
+    }
+
+    pub fn eviction_listener<F: FnMut(K, V) + Send + 'static>(mut self, listener: F) -> Self {
+        self.eviction_listener = Some(Box::new(listener));
+        self
+    }
+
+    // Weighs the entries stored with `insert`, so callers need not pass a weight.
+    pub fn weigher<F: Fn(&K, &V) -> u32 + Send + 'static>(mut self, weigher: F) -> Self {
+        self.weigher = Some(Box::new(weigher));
+        self
+    }
+
+    // Entries expire this long after they were last written.
+    pub fn expire_after_write(mut self, time_to_live: Duration) -> Self {
+        self.expiration.time_to_live = Some(time_to_live);
+        self
+    }
+
+    // Entries expire once they have gone this long without being read or written.
+    pub fn expire_after_access(mut self, time_to_idle: Duration) -> Self {
+        self.expiration.time_to_idle = Some(time_to_idle);
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub fn observer<O: CacheObserver<K> + Send + 'static>(mut self, observer: O) -> Self {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    // Reserves every internal structure for `capacity` entries at construction, so a
+    // full cache cycling through inserts and evictions never allocates. This covers
+    // entry-count bounds only: a cache bounded by `max_weight` alone may hold more
+    // entries, expiration keeps its own deadline storage, and keys or values that own
+    // heap memory still allocate when they are created.
+    pub fn preallocate(mut self) -> Self {
+        self.preallocate = true;
+        self
+    }
+
+    pub fn build(self) -> CacheType<K, V> {
+        self.build_with_hasher(RandomState::new())
+    }
+
+    pub fn build_with_hasher<S: BuildHasher + Clone>(self, hash_builder: S) -> CacheType<K, V, S> {
+        let mut cache = CacheType::with_hasher(self.policy, self.capacity, hash_builder);
+        if let Some(listener) = self.eviction_listener {
+            cache.set_boxed_eviction_listener(listener);
+        }
+        if let Some(observer) = self.observer {
+            cache.set_boxed_observer(observer);
+        }
+        if let Some(weigher) = self.weigher {
+            cache.set_boxed_weigher(weigher);
+        }
+        if let Some(clock) = self.clock {
+            cache.set_clock(clock);
+        }
+        cache.set_expiration(self.expiration);
+        if let Some(max_weight) = self.max_weight {
+            cache.set_max_weight(max_weight);
+        }
+        if self.preallocate {
+            cache.preallocate();
+        }
+        cache
+    }
+
+    // The concrete builders ignore the configured policy.
+    pub fn build_lru(self) -> LRUCache<K, V> {
+        let mut cache = LRUCache::new(self.capacity);
+        if let Some(listener) = self.eviction_listener {
+            cache.set_boxed_eviction_listener(listener);
+        }
+        if let Some(observer) = self.observer {
+            cache.set_boxed_observer(observer);
+        }
+        if let Some(weigher) = self.weigher {
+            cache.set_boxed_weigher(weigher);
+        }
+        if let Some(clock) = self.clock {
+            cache.set_clock(clock);
+        }
+        cache.set_expiration(self.expiration);
+        if let Some(max_weight) = self.max_weight {
+            cache.set_max_weight(max_weight);
+        }
+        if self.preallocate {
+            cache.preallocate();
+        }
+        cache
+    }
+
+    pub fn build_lfu(self) -> LFUCache<K, V> {
+        let mut cache = LFUCache::new(self.capacity);
+        if let Some(listener) = self.eviction_listener {
+            cache.set_boxed_eviction_listener(listener);
+        }
+        if let Some(observer) = self.observer {
+            cache.set_boxed_observer(observer);
+        }
+        if let Some(weigher) = self.weigher {
+            cache.set_boxed_weigher(weigher);
+        }
+        if let Some(clock) = self.clock {
+            cache.set_clock(clock);
+        }
+        cache.set_expiration(self.expiration);
+        if let Some(max_weight) = self.max_weight {
+            cache.set_max_weight(max_weight);
+        }
+        if self.preallocate {
+            cache.preallocate();
+        }
+        cache
+    }
+
+    pub fn build_landlord(self) -> Landlord<K, V> {
+        let mut cache = Landlord::new(self.capacity);
+        if let Some(listener) = self.eviction_listener {
+            cache.set_boxed_eviction_listener(listener);
+        }
+        if let Some(observer) = self.observer {
+            cache.set_boxed_observer(observer);
+        }
+        if let Some(weigher) = self.weigher {
+            cache.set_boxed_weigher(weigher);
+        }
+        if let Some(clock) = self.clock {
+            cache.set_clock(clock);
+        }
+        cache.set_expiration(self.expiration);
+        if let Some(max_weight) = self.max_weight {
+            cache.set_max_weight(max_weight);
+        }
+        if self.preallocate {
+            cache.preallocate();
+        }
+        cache
+    }
+
+    pub fn build_lfuda(self) -> LFUDACache<K, V> {
+        let mut cache = LFUDACache::new(self.capacity);
+        if let Some(listener) = self.eviction_listener {
+            cache.set_boxed_eviction_listener(listener);
+        }
+        if let Some(observer) = self.observer {
+            cache.set_boxed_observer(observer);
+        }
+        if let Some(weigher) = self.weigher {
+            cache.set_boxed_weigher(weigher);
+        }
+        if let Some(clock) = self.clock {
+            cache.set_clock(clock);
+        }
+        cache.set_expiration(self.expiration);
+        if let Some(max_weight) = self.max_weight {
+            cache.set_max_weight(max_weight);
+        }
+        if self.preallocate {
+            cache.preallocate();
+        }
+        cache
+    }
+
+    pub fn build_arc(self) -> ARCCache<K, V> {
+        let mut cache = ARCCache::new(self.capacity);
+        if let Some(listener) = self.eviction_listener {
+            cache.set_boxed_eviction_listener(listener);
+        }
+        if let Some(observer) = self.observer {
+            cache.set_boxed_observer(observer);
+        }
+        if let Some(weigher) = self.weigher {
+            cache.set_boxed_weigher(weigher);
+        }
+        if let Some(clock) = self.clock {
+            cache.set_clock(clock);
+        }
+        cache.set_expiration(self.expiration);
+        if let Some(max_weight) = self.max_weight {
+            cache.set_max_weight(max_weight);
+        }
+        if self.preallocate {
+            cache.preallocate();
+        }
+        cache
+    }
+
+    pub fn build_fifo(self) -> FIFOCache<K, V> {
+        let mut cache = FIFOCache::new(self.capacity);
+        if let Some(listener) = self.eviction_listener {
+            cache.set_boxed_eviction_listener(listener);
+        }
+        if let Some(observer) = self.observer {
+            cache.set_boxed_observer(observer);
+        }
+        if let Some(weigher) = self.weigher {
+            cache.set_boxed_weigher(weigher);
+        }
+        if let Some(clock) = self.clock {
+            cache.set_clock(clock);
+        }
+        cache.set_expiration(self.expiration);
+        if let Some(max_weight) = self.max_weight {
+            cache.set_max_weight(max_weight);
+        }
+        if self.preallocate {
+            cache.preallocate();
+        }
+        cache
+    }
+
+    pub fn build_random(self) -> RandomCache<K, V> {
+        let mut cache = RandomCache::new(self.capacity);
+        if let Some(listener) = self.eviction_listener {
+            cache.set_boxed_eviction_listener(listener);
+        }
+        if let Some(observer) = self.observer {
+            cache.set_boxed_observer(observer);
+        }
+        if let Some(weigher) = self.weigher {
+            cache.set_boxed_weigher(weigher);
+        }
+        if let Some(clock) = self.clock {
+            cache.set_clock(clock);
+        }
+        cache.set_expiration(self.expiration);
+        if let Some(max_weight) = self.max_weight {
+            cache.set_max_weight(max_weight);
+        }
+        if self.preallocate {
+            cache.preallocate();
+        }
+        cache
+    }
+
+    pub fn build_mru(self) -> MRUCache<K, V> {
+        let mut cache = MRUCache::new(self.capacity);
+        if let Some(listener) = self.eviction_listener {
+            cache.set_boxed_eviction_listener(listener);
+        }
+        if let Some(observer) = self.observer {
+            cache.set_boxed_observer(observer);
+        }
+        if let Some(weigher) = self.weigher {
+            cache.set_boxed_weigher(weigher);
+        }
+        if let Some(clock) = self.clock {
+            cache.set_clock(clock);
+        }
+        cache.set_expiration(self.expiration);
+        if let Some(max_weight) = self.max_weight {
+            cache.set_max_weight(max_weight);
+        }
+        if self.preallocate {
+            cache.preallocate();
+        }
+        cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::util::counting_alloc::allocations_during;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_build_selects_policy() {
+        let cache: CacheType<i32, &str> = CacheBuilder::new(2).policy(Policy::LFU).build();
+        assert_eq!(cache.policy(), Policy::LFU);
+
+        let cache: CacheType<i32, &str> = CacheBuilder::new(2).build();
+        assert_eq!(cache.policy(), Policy::LRU);
+    }
+
+    #[test]
+    fn test_capacity_is_applied() {
+        let mut cache = CacheBuilder::new(1)
+            .capacity(3)
+            .policy(Policy::Landlord)
+            .build();
+        cache.put(1, "one", 10);
+        cache.put(2, "two", 20);
+        cache.put(3, "three", 30);
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn test_max_weight_is_applied() {
+        let mut cache = CacheBuilder::new(1).max_weight(50).build_landlord();
+        cache.put(1, "one", 20);
+        cache.put(2, "two", 20);
+        assert_eq!(cache.len(), 2);
+
+        cache.put(3, "three", 20);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_weigher_is_installed() {
+        let mut cache = CacheBuilder::new(4)
+            .policy(Policy::LFU)
+            .weigher(|key: &i32, _: &&str| *key as u32 * 10)
+            .max_weight(50)
+            .build();
+        cache.insert(2, "two");
+        cache.insert(3, "three");
+        assert_eq!(cache.len(), 2);
+
+        cache.insert(4, "four");
+        assert_eq!(cache.stats().current_weight, 40);
+    }
+
+    #[test]
+    fn test_eviction_listener_is_installed() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+
+        let mut cache = CacheBuilder::new(1)
+            .policy(Policy::LRU)
+            .eviction_listener(move |key, _| sink.lock().unwrap().push(key))
+            .build();
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+
+        assert_eq!(*evicted.lock().unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn test_concrete_builders() {
+        let evicted = Arc::new(Mutex::new(0));
+        let counter = evicted.clone();
+
+        let mut lfu = CacheBuilder::new(1)
+            .eviction_listener(move |_, _| *counter.lock().unwrap() += 1)
+            .build_lfu();
+        lfu.put(1, "one", 1);
+        lfu.put(2, "two", 1);
+        assert_eq!(*evicted.lock().unwrap(), 1);
+
+        let mut lru: LRUCache<i32, &str> = CacheBuilder::new(2).build_lru();
+        lru.put(1, "one", 0);
+        assert_eq!(lru.len(), 1);
+
+        let mut landlord: Landlord<i32, &str> = CacheBuilder::new(2).build_landlord();
+        landlord.put(1, "one", 10);
+        assert_eq!(landlord.len(), 1);
+
+        let mut lfuda: LFUDACache<i32, &str> = CacheBuilder::new(2).build_lfuda();
+        lfuda.put(1, "one", 1);
+        assert_eq!(lfuda.len(), 1);
+
+        let mut arc: ARCCache<i32, &str> = CacheBuilder::new(2).build_arc();
+        arc.put(1, "one", 1);
+        assert_eq!(arc.len(), 1);
+
+        let mut fifo: FIFOCache<i32, &str> = CacheBuilder::new(2).build_fifo();
+        fifo.put(1, "one", 1);
+        assert_eq!(fifo.len(), 1);
+
+        let mut random: RandomCache<i32, &str> = CacheBuilder::new(2).build_random();
+        random.put(1, "one", 1);
+        assert_eq!(random.len(), 1);
+
+        let mut mru: MRUCache<i32, &str> = CacheBuilder::new(2).build_mru();
+        mru.put(1, "one", 1);
+        assert_eq!(mru.len(), 1);
+    }
+
+    #[test]
+    fn test_expiration_is_applied() {
+        let clock = ManualClock::new();
+
+        let mut cache = CacheBuilder::new(2)
+            .policy(Policy::LFU)
+            .expire_after_write(Duration::from_secs(30))
+            .clock(Arc::new(clock.clone()))
+            .build();
+        cache.put(1, "one", 1);
+        assert_eq!(cache.get(&1), Some(&"one"));
+
+        let mut landlord = CacheBuilder::new(2)
+            .expire_after_access(Duration::from_secs(30))
+            .clock(Arc::new(clock.clone()))
+            .build_landlord();
+        landlord.put(1, "one", 10);
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(landlord.get(&1), None);
+    }
+
+    #[test]
+    fn test_observer_is_installed() {
+        struct CountInserts(Arc<Mutex<usize>>);
+
+        impl CacheObserver<i32> for CountInserts {
+            fn on_insert(&mut self, _key: &i32) {
+                *self.0.lock().unwrap() += 1;
+            }
+        }
+
+        let inserts = Arc::new(Mutex::new(0));
+        let mut cache = CacheBuilder::new(2)
+            .observer(CountInserts(inserts.clone()))
+            .build_lfu();
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.put(1, "ONE", 1);
+
+        assert_eq!(*inserts.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_preallocated_caches_do_not_allocate_in_steady_state() {
+        let policies = [
+            Policy::LRU,
+            Policy::LFU,
+            Policy::Landlord,
+            Policy::LFUDA,
+            Policy::ARC,
+            Policy::FIFO,
+            Policy::Random,
+            Policy::MRU,
+        ];
+        for &policy in &policies {
+            let mut cache: CacheType<u64, u64> =
+                CacheBuilder::new(64).policy(policy).preallocate().build();
+            let mut state = 0x2545_F491_4F6C_DD1Du64;
+            let mut step = |cache: &mut CacheType<u64, u64>| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let key = state % 256;
+                if cache.get(&key).is_none() {
+                    cache.put(key, state, 1);
+                }
+            };
+            let allocations = allocations_during(|| {
+                for _ in 0..20_000 {
+                    step(&mut cache);
+                }
+            });
+            assert_eq!(cache.len(), 64);
+            assert_eq!(allocations, 0, "{:?} allocated", policy);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Capacity must be greater than 0")]
+    fn test_zero_capacity_panics_on_build() {
+        let _cache: CacheType<i32, &str> = CacheBuilder::new(0).build();
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// The time source behind every time-based feature, so expiry can be tested without sleeping.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// A clock that only moves when told to. Clones share the same time, so a test can keep a
+// handle while the cache holds another.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock::starting_at(Instant::now())
+    }
+
+    pub fn starting_at(start: Instant) -> Self {
+        ManualClock {
+            now: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += by;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+// Runs `f` and returns what it took by `clock` in milliseconds, at least one, as the cost
+// of whatever it computed.
+pub(crate) fn timed_cost<T, F: FnOnce() -> T>(clock: &dyn Clock, f: F) -> (T, f64) {
+    let started = clock.now();
+    let value = f();
+    let latency = clock.now().saturating_duration_since(started);
+    (value, (latency.as_secs_f64() * 1000.0).max(1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_only_moves_when_advanced() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+
+        let handle = clock.clone();
+        handle.advance(Duration::from_secs(3));
+        assert_eq!(clock.now(), start + Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_system_clock_is_monotonic() {
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let first = clock.now();
+        assert!(clock.now() >= first);
+    }
+}
@@ -0,0 +1,244 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::replay::{replay_with, Access, ReplayReport};
+use crate::{CacheType, Policy};
+
+// How one policy did at one capacity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    pub policy: Policy,
+    // Entries, or total weight for `compare_policies_by_weight`.
+    pub capacity: u64,
+    pub report: ReplayReport,
+}
+
+impl Comparison {
+    // Evictions per request, how hard the policy works to keep its hit ratio.
+    pub fn churn(&self) -> f64 {
+        if self.report.requests == 0 {
+            0.0
+        } else {
+            self.report.evictions as f64 / self.report.requests as f64
+        }
+    }
+}
+
+// The results of a comparison, ordered by capacity and then by policy as they were given.
+// Displays as a table; `to_csv` gives the same columns for a spreadsheet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComparisonReport {
+    rows: Vec<Comparison>,
+}
+
+impl ComparisonReport {
+    pub fn rows(&self) -> &[Comparison] {
+        &self.rows
+    }
+
+    pub fn get(&self, policy: Policy, capacity: u64) -> Option<&Comparison> {
+        self.rows
+            .iter()
+            .find(|row| row.policy == policy && row.capacity == capacity)
+    }
+
+    // The policy with the highest hit ratio at `capacity`; the first given wins a tie.
+    pub fn best(&self, capacity: u64) -> Option<&Comparison> {
+        self.rows
+            .iter()
+            .filter(|row| row.capacity == capacity)
+            .fold(None, |best: Option<&Comparison>, row| match best {
+                Some(best) if best.report.hit_ratio() >= row.report.hit_ratio() => Some(best),
+                _ => Some(row),
+            })
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "policy,capacity,requests,hits,hit_ratio,byte_hit_ratio,evictions,churn\n",
+        );
+        for row in &self.rows {
+            csv.push_str(&format!(
+                "{:?},{},{},{},{:.6},{:.6},{},{:.6}\n",
+                row.policy,
+                row.capacity,
+                row.report.requests,
+                row.report.hits,
+                row.report.hit_ratio(),
+                row.report.byte_hit_ratio(),
+                row.report.evictions,
+                row.churn()
+            ));
+        }
+        csv
+    }
+}
+
+impl fmt::Display for ComparisonReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<10} {:>12} {:>10} {:>10} {:>12} {:>8}",
+            "policy", "capacity", "hit ratio", "byte hits", "evictions", "churn"
+        )?;
+        for row in &self.rows {
+            writeln!(
+                f,
+                "{:<10} {:>12} {:>9.2}% {:>9.2}% {:>12} {:>8.3}",
+                format!("{:?}", row.policy),
+                row.capacity,
+                row.report.hit_ratio() * 100.0,
+                row.report.byte_hit_ratio() * 100.0,
+                row.report.evictions,
+                row.churn()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Replays the trace through every policy at every capacity, counted in entries. Runs are
+// spread across the available cores, each on a cache of its own. Misses are stored with
+// the request's size as their weight and its cost, so Landlord sees what a miss cost.
+pub fn compare_policies(
+    trace: &[Access],
+    capacities: &[usize],
+    policies: &[Policy],
+) -> ComparisonReport {
+    let capacities: Vec<u64> = capacities.iter().map(|&capacity| capacity as u64).collect();
+    compare(trace, &capacities, policies, |policy, capacity| {
+        CacheType::new(policy, capacity as usize)
+    })
+}
+
+// Like `compare_policies`, with caches bounded by the total size of what they hold, e.g.
+// bytes when the trace's sizes are bytes.
+pub fn compare_policies_by_weight(
+    trace: &[Access],
+    max_weights: &[u64],
+    policies: &[Policy],
+) -> ComparisonReport {
+    compare(trace, max_weights, policies, |policy, max_weight| {
+        // The weight bound is the one reached; every request could be a distinct key.
+        let mut cache = CacheType::new(policy, trace.len().max(1));
+        cache.set_max_weight(max_weight);
+        cache
+    })
+}
+
+fn compare<F>(
+    trace: &[Access],
+    capacities: &[u64],
+    policies: &[Policy],
+    build: F,
+) -> ComparisonReport
+where
+    F: Fn(Policy, u64) -> CacheType<String, ()> + Sync,
+{
+    let runs: Vec<(Policy, u64)> = capacities
+        .iter()
+        .flat_map(|&capacity| policies.iter().map(move |&policy| (policy, capacity)))
+        .collect();
+    let workers = thread::available_parallelism()
+        .map_or(1, |cores| cores.get())
+        .min(runs.len());
+    let next = AtomicUsize::new(0);
+    let mut finished: Vec<(usize, Comparison)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut finished = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let (policy, capacity) = match runs.get(index) {
+                            Some(&run) => run,
+                            None => return finished,
+                        };
+                        let mut cache = build(policy, capacity);
+                        let report = replay_with(&mut cache, trace, |cache, access| {
+                            let cost = access.miss_cost().round().max(1.0) as u32;
+                            cache.put_sized(access.key.clone(), (), cost, access.weight());
+                        });
+                        finished.push((
+                            index,
+                            Comparison {
+                                policy,
+                                capacity,
+                                report,
+                            },
+                        ));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("comparison worker panicked"))
+            .collect()
+    });
+    finished.sort_by_key(|&(index, _)| index);
+    ComparisonReport {
+        rows: finished.into_iter().map(|(_, row)| row).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(keys: &[&str]) -> Vec<Access> {
+        keys.iter()
+            .enumerate()
+            .map(|(timestamp, &key)| Access {
+                timestamp: timestamp as u64,
+                key: key.to_string(),
+                size: Some(10),
+                cost: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compares_every_combination() {
+        // A loop one key longer than the cache: LRU always misses, MRU keeps most of it
+        let trace = trace(&["a", "b", "c", "a", "b", "c", "a", "b", "c"]);
+        let report = compare_policies(&trace, &[2, 3], &[Policy::LRU, Policy::MRU]);
+        let order: Vec<(Policy, u64)> = report
+            .rows()
+            .iter()
+            .map(|row| (row.policy, row.capacity))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (Policy::LRU, 2),
+                (Policy::MRU, 2),
+                (Policy::LRU, 3),
+                (Policy::MRU, 3)
+            ]
+        );
+
+        let lru = report.get(Policy::LRU, 2).unwrap();
+        assert_eq!(lru.report.hits, 0);
+        assert_eq!(lru.report.evictions, 7);
+        assert_eq!(report.best(2).unwrap().policy, Policy::MRU);
+        assert_eq!(report.get(Policy::LRU, 3).unwrap().report.hits, 6);
+        assert_eq!(report.best(3).unwrap().policy, Policy::LRU);
+    }
+
+    #[test]
+    fn test_weight_bounds_and_output() {
+        let trace = trace(&["a", "b", "a", "b"]);
+        let report = compare_policies_by_weight(&trace, &[10, 20], &[Policy::FIFO]);
+        assert_eq!(report.get(Policy::FIFO, 10).unwrap().report.hits, 0);
+        assert_eq!(report.get(Policy::FIFO, 20).unwrap().report.hits, 2);
+
+        let csv = report.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].starts_with("FIFO,20,4,2,0.500000,0.500000,0,"));
+        let table = report.to_string();
+        assert!(table.lines().nth(2).unwrap().contains("50.00%"));
+    }
+}
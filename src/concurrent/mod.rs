@@ -0,0 +1,344 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::sync_cache::lock;
+use crate::util::shard_of;
+use crate::CacheStats;
+
+const DEFAULT_STRIPES: usize = 16;
+
+struct Slot<K, V> {
+    // Set by hits, cleared when the hand passes
+    referenced: AtomicBool,
+    entry: RwLock<Option<(K, V)>>,
+}
+
+// State only writers touch, guarded by the maintenance lock.
+struct Hand {
+    position: usize,
+    len: usize,
+    // Empty slots, taken before the hand is moved at all
+    free: Vec<usize>,
+    insertions: u64,
+    evictions: u64,
+}
+
+// CLOCK replacement with a read path that never takes an exclusive lock. Entries live in
+// a fixed ring of slots and the key index is split into stripes. A hit takes shared
+// locks on one stripe and one slot and sets the slot's reference bit; CLOCK never moves
+// entries on a hit, so that is all a hit has to record. Inserts and removals are
+// serialized by a maintenance lock and only lock a stripe or slot exclusively for the
+// moment they change it. The hand sweeps the ring, clearing reference bits, and evicts
+// the first entry not used since its last pass.
+pub struct ConcurrentCache<K, V, S = RandomState> {
+    slots: Box<[Slot<K, V>]>,
+    stripes: Box<[RwLock<HashMap<K, usize, S>>]>,
+    hash_builder: S,
+    maintenance: Mutex<Hand>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K: Clone + Hash + Eq, V> ConcurrentCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        ConcurrentCache::with_hasher(capacity, DEFAULT_STRIPES, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> ConcurrentCache<K, V, S> {
+    // More stripes let more writers update the index while readers hold other stripes.
+    pub fn with_hasher(capacity: usize, stripes: usize, hash_builder: S) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        assert!(stripes > 0, "Stripe count must be greater than 0");
+        ConcurrentCache {
+            slots: (0..capacity)
+                .map(|_| Slot {
+                    referenced: AtomicBool::new(false),
+                    entry: RwLock::new(None),
+                })
+                .collect(),
+            stripes: (0..stripes)
+                .map(|_| RwLock::new(HashMap::with_hasher(hash_builder.clone())))
+                .collect(),
+            hash_builder,
+            maintenance: Mutex::new(Hand {
+                position: 0,
+                len: 0,
+                free: (0..capacity).rev().collect(),
+                insertions: 0,
+                evictions: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.with_value(key, V::clone)
+    }
+
+    // Runs `f` on the cached value while its slot is read-locked, which avoids the clone.
+    pub fn with_value<R, F: FnOnce(&V) -> R>(&self, key: &K, f: F) -> Option<R> {
+        let result = self.find(key).and_then(|idx| {
+            let slot = &self.slots[idx];
+            match *read(&slot.entry) {
+                // A writer may have reused the slot since the index was read
+                Some((ref cached, ref value)) if cached == key => {
+                    slot.referenced.store(true, Ordering::Relaxed);
+                    Some(f(value))
+                }
+                _ => None,
+            }
+        });
+        let counter = if result.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    // Does not count as a use of the key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    // A cache that is not full fills a free slot; the hand only passes over entries when
+    // it is full, so an insert evicts at most one entry.
+    pub fn insert(&self, key: K, value: V) {
+        let mut hand = lock(&self.maintenance);
+        if let Some(idx) = self.find(&key) {
+            let slot = &self.slots[idx];
+            *write(&slot.entry) = Some((key, value));
+            slot.referenced.store(true, Ordering::Relaxed);
+            return;
+        }
+
+        let idx = match hand.free.pop() {
+            Some(idx) => {
+                hand.len += 1;
+                idx
+            }
+            None => {
+                let idx = self.advance(&mut hand);
+                // Taking the victim out of its slot first makes readers that already found
+                // it miss; they would otherwise keep seeing it until the index is updated.
+                let victim = write(&self.slots[idx].entry).take();
+                if let Some((victim, _)) = victim {
+                    write(self.stripe(&victim)).remove(&victim);
+                    hand.evictions += 1;
+                }
+                idx
+            }
+        };
+        let slot = &self.slots[idx];
+        slot.referenced.store(false, Ordering::Relaxed);
+        *write(&slot.entry) = Some((key.clone(), value));
+        write(self.stripe(&key)).insert(key, idx);
+        hand.insertions += 1;
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut hand = lock(&self.maintenance);
+        let idx = write(self.stripe(key)).remove(key)?;
+        let (_, value) = write(&self.slots[idx].entry)
+            .take()
+            .expect("indexed slots hold an entry");
+        hand.len -= 1;
+        hand.free.push(idx);
+        Some(value)
+    }
+
+    pub fn clear(&self) {
+        let mut hand = lock(&self.maintenance);
+        for stripe in self.stripes.iter() {
+            write(stripe).clear();
+        }
+        for slot in self.slots.iter() {
+            *write(&slot.entry) = None;
+            slot.referenced.store(false, Ordering::Relaxed);
+        }
+        hand.position = 0;
+        hand.len = 0;
+        hand.free = (0..self.slots.len()).rev().collect();
+    }
+
+    // Entries have no weights, so each one counts as weighing 1.
+    pub fn stats(&self) -> CacheStats {
+        let hand = lock(&self.maintenance);
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            insertions: hand.insertions,
+            evictions: hand.evictions,
+            current_weight: hand.len as u64,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn len(&self) -> usize {
+        lock(&self.maintenance).len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn find(&self, key: &K) -> Option<usize> {
+        read(self.stripe(key)).get(key).copied()
+    }
+
+    // Moves the hand to the next victim of a full cache and returns it. Every referenced
+    // entry it passes loses its bit, so it stops within two sweeps of the ring.
+    fn advance(&self, hand: &mut Hand) -> usize {
+        loop {
+            let idx = hand.position;
+            hand.position = (idx + 1) % self.slots.len();
+            if !self.slots[idx].referenced.swap(false, Ordering::Relaxed) {
+                return idx;
+            }
+        }
+    }
+
+    fn stripe(&self, key: &K) -> &RwLock<HashMap<K, usize, S>> {
+        let hash = self.hash_builder.hash_one(key);
+        &self.stripes[shard_of(hash, self.stripes.len())]
+    }
+}
+
+// Readers never hold a lock while panicking in cache code, so only a writer can poison
+// one, and then the entry behind it may be half updated.
+pub(crate) fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().expect("cache lock poisoned")
+}
+
+pub(crate) fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().expect("cache lock poisoned")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_hand_spares_referenced_entries() {
+        let cache = ConcurrentCache::new(3);
+        for key in 1..=3 {
+            cache.insert(key, key * 10);
+        }
+        assert_eq!(cache.get(&1), Some(10));
+
+        // The hand clears 1's bit and evicts 2
+        cache.insert(4, 40);
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.get(&4), Some(40));
+
+        // The hand moves on to 3, then comes back round to 1, whose bit is now clear
+        cache.insert(5, 50);
+        assert!(!cache.contains_key(&3));
+        cache.insert(6, 60);
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.len(), 3);
+
+        let stats = cache.stats();
+        assert_eq!(stats.insertions, 6);
+        assert_eq!(stats.evictions, 3);
+        assert_eq!(stats.hits, 2);
+    }
+
+    #[test]
+    fn test_update_remove_and_clear() {
+        let cache = ConcurrentCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("a", 2);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.with_value(&"a", |value| value * 10), Some(20));
+
+        assert_eq!(cache.remove(&"a"), Some(2));
+        assert_eq!(cache.remove(&"a"), None);
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.stats().misses, 1);
+
+        cache.insert("b", 3);
+        cache.insert("c", 4);
+        cache.clear();
+        assert!(cache.is_empty());
+        cache.insert("d", 5);
+        assert_eq!(cache.get(&"d"), Some(5));
+    }
+
+    #[test]
+    fn test_insert_after_remove_evicts_nothing() {
+        let cache = ConcurrentCache::new(3);
+        for key in 1..=3 {
+            cache.insert(key, key * 10);
+        }
+        cache.remove(&3);
+        cache.insert(4, 40);
+        assert_eq!(cache.len(), 3);
+        assert!(cache.contains_key(&1) && cache.contains_key(&2));
+        assert_eq!(cache.stats().evictions, 0);
+
+        // Full again, so the next insert does sweep
+        cache.insert(5, 50);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_hits_do_not_wait_for_writers() {
+        let cache = ConcurrentCache::new(4);
+        cache.insert(1, "one");
+        let _maintenance = lock(&cache.maintenance);
+        thread::scope(|scope| {
+            let reader = scope.spawn(|| cache.get(&1));
+            assert_eq!(reader.join().unwrap(), Some("one"));
+        });
+    }
+
+    #[test]
+    fn test_readers_never_see_another_keys_value() {
+        let cache = Arc::new(ConcurrentCache::with_hasher(64, 4, RandomState::new()));
+        let writer = {
+            let cache = cache.clone();
+            thread::spawn(move || {
+                for key in 0..20_000u64 {
+                    cache.insert(key % 256, key % 256 * 2);
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                thread::spawn(move || {
+                    for key in 0..20_000u64 {
+                        let key = key % 256;
+                        if let Some(value) = cache.get(&key) {
+                            assert_eq!(value, key * 2);
+                        }
+                    }
+                })
+            })
+            .collect();
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(cache.len(), 64);
+        let stats = cache.stats();
+        assert_eq!(stats.hits + stats.misses, 80_000);
+    }
+}
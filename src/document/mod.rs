@@ -0,0 +1,221 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{Cache, CacheStats, CacheType};
+
+// Caches stored documents, e.g. the fields a snippet is built from, by doc id. It sits
+// under the result cache: a cached result page is a list of ids, and rendering it looks
+// all of them up here at once, fetching the missing ones from the document store in one
+// batch. Any policy can run it; set a weigher to bound it by document size.
+pub struct DocumentCache<K, V, S = RandomState> {
+    cache: CacheType<K, V, S>,
+}
+
+impl<K, V, S> DocumentCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    pub fn new(cache: CacheType<K, V, S>) -> Self {
+        DocumentCache { cache }
+    }
+
+    pub fn get(&mut self, id: &K) -> Option<&V> {
+        self.cache.get(id)
+    }
+
+    pub fn peek(&self, id: &K) -> Option<&V> {
+        self.cache.peek(id)
+    }
+
+    pub fn contains_key(&self, id: &K) -> bool {
+        self.cache.contains_key(id)
+    }
+
+    pub fn put(&mut self, id: K, document: V, weight: u32) {
+        self.cache.put(id, document, weight);
+    }
+
+    pub fn remove(&mut self, id: &K) -> Option<V> {
+        self.cache.remove(id)
+    }
+
+    // Looks up every id, in order. Each counts as a lookup of its own, hit or miss.
+    pub fn get_many(&mut self, ids: &[K]) -> Vec<Option<&V>> {
+        for id in ids {
+            self.cache.get(id);
+        }
+        self.peek_many(ids)
+    }
+
+    pub fn peek_many(&self, ids: &[K]) -> Vec<Option<&V>> {
+        ids.iter().map(|id| self.cache.peek(id)).collect()
+    }
+
+    // The ids that are not cached, in order, e.g. to fetch them from the document store.
+    pub fn missing(&self, ids: &[K]) -> Vec<K> {
+        ids.iter()
+            .filter(|id| !self.cache.contains_key(id))
+            .cloned()
+            .collect()
+    }
+
+    // Like `get_many`, but the ids missing are passed to `fetch` in one call, and the
+    // documents it returns are cached with the weight the weigher gives them. Ids the
+    // store has no document for come back as `None`, as do any the fetched documents
+    // pushed out of a cache too small for the batch. Nothing is cached if `fetch` fails.
+    pub fn get_many_with<E, F>(&mut self, ids: &[K], fetch: F) -> Result<Vec<Option<&V>>, E>
+    where
+        F: FnOnce(&[K]) -> Result<Vec<(K, V)>, E>,
+    {
+        let mut missing = Vec::new();
+        for id in ids {
+            if self.cache.get(id).is_none() && !missing.contains(id) {
+                missing.push(id.clone());
+            }
+        }
+        if !missing.is_empty() {
+            for (id, document) in fetch(&missing)? {
+                self.cache.insert(id, document);
+            }
+        }
+        Ok(self.peek_many(ids))
+    }
+
+    pub fn put_many<I: IntoIterator<Item = (K, V, u32)>>(&mut self, documents: I) {
+        for (id, document, weight) in documents {
+            self.cache.put(id, document, weight);
+        }
+    }
+
+    // Returns how many of the ids were cached.
+    pub fn remove_many(&mut self, ids: &[K]) -> usize {
+        ids.iter()
+            .filter(|id| self.cache.remove(id).is_some())
+            .count()
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn inner(&self) -> &CacheType<K, V, S> {
+        &self.cache
+    }
+
+    pub fn inner_mut(&mut self) -> &mut CacheType<K, V, S> {
+        &mut self.cache
+    }
+
+    pub fn into_inner(self) -> CacheType<K, V, S> {
+        self.cache
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+impl<K, V, S> Cache<K, V> for DocumentCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.cache.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.cache.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.cache.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Policy;
+    use std::convert::Infallible;
+
+    fn documents() -> DocumentCache<u64, String> {
+        let mut cache = DocumentCache::new(CacheType::new(Policy::LRU, 4));
+        cache.put_many(vec![
+            (1, "Rust caches".to_string(), 1),
+            (2, "LRU explained".to_string(), 1),
+        ]);
+        cache
+    }
+
+    #[test]
+    fn test_bulk_lookups_keep_order() {
+        let mut cache = documents();
+        let page = cache.get_many(&[2, 7, 1]);
+        assert_eq!(page[0].map(String::as_str), Some("LRU explained"));
+        assert_eq!(page[1], None);
+        assert_eq!(page[2].map(String::as_str), Some("Rust caches"));
+        assert_eq!(cache.stats().hits, 2);
+        assert_eq!(cache.stats().misses, 1);
+        assert_eq!(cache.missing(&[1, 7, 8]), vec![7, 8]);
+        assert_eq!(cache.remove_many(&[1, 7]), 1);
+    }
+
+    #[test]
+    fn test_missing_documents_are_fetched_in_one_batch() {
+        let mut cache = documents();
+        let mut batches = Vec::new();
+        let page = cache
+            .get_many_with(&[3, 1, 4, 3], |ids| {
+                batches.push(ids.to_vec());
+                Ok::<_, Infallible>(vec![(3, "ARC in practice".to_string())])
+            })
+            .unwrap();
+        assert_eq!(page[0].map(String::as_str), Some("ARC in practice"));
+        assert_eq!(page[2], None);
+        assert_eq!(page[3], page[0]);
+        assert_eq!(batches, vec![vec![3, 4]]);
+        assert!(cache.contains_key(&3));
+
+        let failed = cache.get_many_with(&[5], |_| Err("store unavailable"));
+        assert_eq!(failed, Err("store unavailable"));
+        assert!(!cache.contains_key(&5));
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{Cache, CacheStats, CacheType};
+
+pub struct Versioned<V> {
+    pub epoch: u64,
+    pub value: V,
+}
+
+// A cache whose entries carry the epoch they were computed in, e.g. the index generation
+// that produced a result. Raising the minimum accepted epoch invalidates every older entry
+// at once, in constant time: stale entries are treated as misses and reclaimed when they
+// are next touched, or all together by `purge_stale`.
+//
+// An index that is updated continuously can instead report each new generation with
+// `set_current_generation`: with a staleness horizon set, results computed more than that
+// many generations ago are stale without anyone invalidating them.
+//
+// Until reclaimed, stale entries still count towards the cache's size and can be chosen
+// as victims like any other.
+pub struct EpochCache<K, V, S = RandomState> {
+    cache: CacheType<K, Versioned<V>, S>,
+    min_epoch: u64,
+    current_generation: u64,
+    horizon: Option<u64>,
+}
+
+impl<K, V, S> EpochCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    pub fn new(cache: CacheType<K, Versioned<V>, S>) -> Self {
+        EpochCache {
+            cache,
+            min_epoch: 0,
+            current_generation: 0,
+            horizon: None,
+        }
+    }
+
+    // The oldest epoch whose entries are still served, whether set directly or implied by
+    // the staleness horizon.
+    pub fn min_epoch(&self) -> u64 {
+        let horizon = self.horizon.map_or(0, |generations| {
+            self.current_generation.saturating_sub(generations)
+        });
+        self.min_epoch.max(horizon)
+    }
+
+    // Invalidates every entry cached so far and returns the new minimum epoch, which is
+    // past the current generation `put` has been storing entries in.
+    pub fn bump_epoch(&mut self) -> u64 {
+        self.min_epoch = self.min_epoch().max(self.current_generation) + 1;
+        self.min_epoch
+    }
+
+    // The generation the index has reached. `put` stores entries in it, and with a
+    // staleness horizon, older generations fall out of date as it rises. It never goes
+    // back down.
+    pub fn set_current_generation(&mut self, generation: u64) {
+        self.current_generation = self.current_generation.max(generation);
+    }
+
+    pub fn current_generation(&self) -> u64 {
+        self.current_generation
+    }
+
+    // Serves entries computed up to `generations` generations before the current one; 0
+    // serves only the current generation. Without a horizon, entries stay current until
+    // the minimum epoch passes them.
+    pub fn set_staleness_horizon(&mut self, generations: u64) {
+        self.horizon = Some(generations);
+    }
+
+    pub fn staleness_horizon(&self) -> Option<u64> {
+        self.horizon
+    }
+
+    // Invalidates the entries older than `epoch`. The minimum never goes back down.
+    pub fn set_min_epoch(&mut self, epoch: u64) {
+        self.min_epoch = self.min_epoch.max(epoch);
+    }
+
+    // A stale entry is dropped and the lookup counted as a miss.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.reclaim_stale(key);
+        self.cache.get(key).map(|versioned| &versioned.value)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.reclaim_stale(key);
+        self.cache
+            .get_mut(key)
+            .map(|versioned| &mut versioned.value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.cache
+            .peek(key)
+            .filter(|versioned| versioned.epoch >= self.min_epoch())
+            .map(|versioned| &versioned.value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+
+    // The epoch a current entry was stored in.
+    pub fn epoch_of(&self, key: &K) -> Option<u64> {
+        self.cache
+            .peek(key)
+            .map(|versioned| versioned.epoch)
+            .filter(|&epoch| epoch >= self.min_epoch())
+    }
+
+    // Stores the entry in the current generation, or the minimum epoch if that is newer, so
+    // it stays current until the next bump or until it falls past the horizon.
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        let epoch = self.current_generation.max(self.min_epoch());
+        self.cache.put(key, Versioned { epoch, value }, weight);
+    }
+
+    // Stores a value computed in `epoch`. Returns false without storing it when the epoch
+    // is already invalidated, as a result computed against an old index would be.
+    pub fn put_versioned(&mut self, key: K, value: V, weight: u32, epoch: u64) -> bool {
+        if epoch < self.min_epoch() {
+            return false;
+        }
+        self.cache.put(key, Versioned { epoch, value }, weight);
+        true
+    }
+
+    // Returns the value only if it was current.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let min_epoch = self.min_epoch();
+        self.cache
+            .remove(key)
+            .filter(|versioned| versioned.epoch >= min_epoch)
+            .map(|versioned| versioned.value)
+    }
+
+    // Stale entries may be returned too, since the policy does not know about epochs.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.cache
+            .pop_victim()
+            .map(|(key, versioned)| (key, versioned.value))
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.cache.peek_victim()
+    }
+
+    // Drops every stale entry now and returns how many there were.
+    pub fn purge_stale(&mut self) -> usize {
+        let before = self.cache.len();
+        let min_epoch = self.min_epoch();
+        self.cache
+            .retain(|_, versioned| versioned.epoch >= min_epoch);
+        before - self.cache.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn inner(&self) -> &CacheType<K, Versioned<V>, S> {
+        &self.cache
+    }
+
+    pub fn inner_mut(&mut self) -> &mut CacheType<K, Versioned<V>, S> {
+        &mut self.cache
+    }
+
+    pub fn into_inner(self) -> CacheType<K, Versioned<V>, S> {
+        self.cache
+    }
+
+    // Stale entries not reclaimed yet are counted.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    fn reclaim_stale(&mut self, key: &K) {
+        let stale = self
+            .cache
+            .peek(key)
+            .is_some_and(|versioned| versioned.epoch < self.min_epoch());
+        if stale {
+            self.cache.remove(key);
+        }
+    }
+}
+
+impl<K, V, S> Cache<K, V> for EpochCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Policy;
+
+    fn versioned() -> EpochCache<&'static str, u32> {
+        EpochCache::new(CacheType::new(Policy::LRU, 4))
+    }
+
+    #[test]
+    fn test_bumping_invalidates_older_entries() {
+        let mut cache = versioned();
+        cache.put("rust", 1, 1);
+        cache.put("go", 2, 1);
+        assert_eq!(cache.bump_epoch(), 1);
+
+        assert_eq!(cache.peek(&"rust"), None);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"rust"), None);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.stats().misses, 1);
+        assert_eq!(cache.stats().hits, 0);
+
+        cache.put("rust", 10, 1);
+        assert_eq!(cache.get(&"rust"), Some(&10));
+        assert_eq!(cache.epoch_of(&"rust"), Some(1));
+        assert_eq!(cache.purge_stale(), 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_bumping_passes_the_current_generation() {
+        let mut cache = versioned();
+        cache.set_current_generation(5);
+        cache.put("rust", 1, 1);
+        assert_eq!(cache.epoch_of(&"rust"), Some(5));
+        assert_eq!(cache.bump_epoch(), 6);
+        assert_eq!(cache.get(&"rust"), None);
+
+        cache.put("rust", 2, 1);
+        assert_eq!(cache.epoch_of(&"rust"), Some(6));
+    }
+
+    #[test]
+    fn test_late_results_from_old_epochs_are_turned_away() {
+        let mut cache = versioned();
+        cache.set_min_epoch(5);
+        assert!(!cache.put_versioned("rust", 1, 1, 4));
+        assert!(cache.put_versioned("rust", 1, 1, 6));
+
+        // Entries from a newer epoch outlive a bump to an older one
+        cache.set_min_epoch(6);
+        cache.set_min_epoch(3);
+        assert_eq!(cache.min_epoch(), 6);
+        assert_eq!(cache.remove(&"rust"), Some(1));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_results_fall_past_the_staleness_horizon() {
+        let mut cache = versioned();
+        cache.set_staleness_horizon(2);
+        cache.set_current_generation(10);
+        cache.put("rust", 1, 1);
+        assert!(cache.put_versioned("go", 2, 1, 9));
+        assert!(!cache.put_versioned("zig", 3, 1, 7));
+
+        cache.set_current_generation(11);
+        assert_eq!(cache.min_epoch(), 9);
+        assert_eq!(cache.get(&"go"), Some(&2));
+        cache.set_current_generation(12);
+        assert_eq!(cache.get(&"go"), None);
+        assert_eq!(cache.epoch_of(&"rust"), Some(10));
+
+        // A bump still invalidates everything served so far
+        assert_eq!(cache.bump_epoch(), 13);
+        assert_eq!(cache.peek(&"rust"), None);
+        cache.put("rust", 4, 1);
+        assert_eq!(cache.epoch_of(&"rust"), Some(13));
+    }
+}
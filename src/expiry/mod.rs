@@ -0,0 +1,203 @@
+use std::time::{Duration, Instant};
+
+mod wheel;
+
+pub(crate) use self::wheel::TimingWheel;
+
+pub(crate) const WHEEL_RESOLUTION: Duration = Duration::from_millis(1);
+
+// Cache-wide time-to-live (since the last write) and time-to-idle (since the last read or
+// write). Expired entries are treated as misses and reclaimed when they are next touched,
+// or proactively by a cache's `expire_due`. Each cache schedules an entry's deadline on
+// its timing wheel only on insert; entries touched since are rescheduled lazily, when
+// their original deadline comes due.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Expiration {
+    pub time_to_live: Option<Duration>,
+    pub time_to_idle: Option<Duration>,
+}
+
+impl Expiration {
+    pub fn is_enabled(&self) -> bool {
+        self.time_to_live.is_some() || self.time_to_idle.is_some()
+    }
+
+    pub(crate) fn is_expired(&self, timestamps: &Timestamps, now: Instant) -> bool {
+        let outlived = |since: Instant, limit: Option<Duration>| match limit {
+            Some(limit) => now.saturating_duration_since(since) >= limit,
+            None => false,
+        };
+        outlived(timestamps.written, self.time_to_live)
+            || outlived(timestamps.accessed, self.time_to_idle)
+    }
+
+    // The earliest instant at which the entry expires, if any limit applies.
+    pub(crate) fn deadline(&self, timestamps: &Timestamps) -> Option<Instant> {
+        let after = |since: Instant, limit: Option<Duration>| since.checked_add(limit?);
+        match (
+            after(timestamps.written, self.time_to_live),
+            after(timestamps.accessed, self.time_to_idle),
+        ) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+// What a stale-tolerant lookup found. A stale value has expired and should be refreshed,
+// but serving it beats a round-trip to the backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness<T> {
+    Fresh(T),
+    Stale(T),
+}
+
+impl<T> Freshness<T> {
+    pub fn is_stale(&self) -> bool {
+        matches!(self, Freshness::Stale(_))
+    }
+
+    pub fn into_inner(self) -> T {
+        match self {
+            Freshness::Fresh(value) | Freshness::Stale(value) => value,
+        }
+    }
+
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Freshness<U> {
+        match self {
+            Freshness::Fresh(value) => Freshness::Fresh(f(value)),
+            Freshness::Stale(value) => Freshness::Stale(f(value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timestamps {
+    pub(crate) inserted: Instant,
+    pub(crate) written: Instant,
+    pub(crate) accessed: Instant,
+    pub(crate) access_count: u64,
+}
+
+impl Timestamps {
+    pub(crate) fn new(now: Instant) -> Self {
+        Timestamps {
+            inserted: now,
+            written: now,
+            accessed: now,
+            access_count: 0,
+        }
+    }
+
+    // Records a hit.
+    pub(crate) fn touch(&mut self, now: Instant) {
+        self.accessed = now;
+        self.access_count += 1;
+    }
+
+    pub(crate) fn rewrite(&mut self, now: Instant) {
+        self.written = now;
+        self.accessed = now;
+    }
+}
+
+// Timestamps as ages at the moment they were saved, since an `Instant` only means
+// something within the process that took it.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct SavedTimestamps {
+    inserted_ago: Duration,
+    written_ago: Duration,
+    accessed_ago: Duration,
+    access_count: u64,
+}
+
+crate::snapshot::impl_codec!(SavedTimestamps {
+    inserted_ago,
+    written_ago,
+    accessed_ago,
+    access_count,
+});
+
+impl SavedTimestamps {
+    pub(crate) fn save(timestamps: &Timestamps, now: Instant) -> Self {
+        SavedTimestamps {
+            inserted_ago: now.saturating_duration_since(timestamps.inserted),
+            written_ago: now.saturating_duration_since(timestamps.written),
+            accessed_ago: now.saturating_duration_since(timestamps.accessed),
+            access_count: timestamps.access_count,
+        }
+    }
+
+    // Ages reaching back before the clock's earliest instant are cut short.
+    pub(crate) fn restore(&self, now: Instant) -> Timestamps {
+        let ago = |age: Duration| now.checked_sub(age).unwrap_or(now);
+        Timestamps {
+            inserted: ago(self.inserted_ago),
+            written: ago(self.written_ago),
+            accessed: ago(self.accessed_ago),
+            access_count: self.access_count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_never_expires() {
+        let start = Instant::now();
+        let expiration = Expiration::default();
+
+        assert!(!expiration.is_enabled());
+        assert!(!expiration.is_expired(&Timestamps::new(start), start + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn test_time_to_live_counts_from_write() {
+        let start = Instant::now();
+        let expiration = Expiration {
+            time_to_live: Some(Duration::from_secs(10)),
+            time_to_idle: None,
+        };
+        let mut timestamps = Timestamps::new(start);
+        timestamps.touch(start + Duration::from_secs(9));
+
+        assert!(!expiration.is_expired(&timestamps, start + Duration::from_secs(9)));
+        assert!(expiration.is_expired(&timestamps, start + Duration::from_secs(10)));
+
+        timestamps.rewrite(start + Duration::from_secs(10));
+        assert!(!expiration.is_expired(&timestamps, start + Duration::from_secs(15)));
+    }
+
+    #[test]
+    fn test_time_to_idle_counts_from_access() {
+        let start = Instant::now();
+        let expiration = Expiration {
+            time_to_live: None,
+            time_to_idle: Some(Duration::from_secs(5)),
+        };
+        let mut timestamps = Timestamps::new(start);
+
+        timestamps.touch(start + Duration::from_secs(4));
+        assert!(!expiration.is_expired(&timestamps, start + Duration::from_secs(8)));
+        assert!(expiration.is_expired(&timestamps, start + Duration::from_secs(9)));
+    }
+
+    #[test]
+    fn test_deadline_is_the_earliest_limit() {
+        let start = Instant::now();
+        let mut timestamps = Timestamps::new(start);
+        timestamps.touch(start + Duration::from_secs(8));
+
+        let expiration = Expiration {
+            time_to_live: Some(Duration::from_secs(10)),
+            time_to_idle: Some(Duration::from_secs(5)),
+        };
+        assert_eq!(
+            expiration.deadline(&timestamps),
+            Some(start + Duration::from_secs(10))
+        );
+        assert_eq!(Expiration::default().deadline(&timestamps), None);
+    }
+}
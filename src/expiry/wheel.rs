@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem;
+use std::time::{Duration, Instant};
+
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const SLOT_MASK: u64 = SLOTS as u64 - 1;
+const LEVELS: usize = 6;
+
+// A hierarchical timing wheel of key deadlines. Level `n` has 64 slots, each covering
+// 64^n ticks, so six levels span about two years at millisecond resolution. Entries are
+// cascaded into finer levels as time reaches them, which makes advancing cost
+// proportional to the number of due keys rather than to the number of scheduled ones.
+pub(crate) struct TimingWheel<K> {
+    origin: Instant,
+    resolution: Duration,
+    // The next tick that has not been processed yet.
+    elapsed: u64,
+    levels: Vec<Vec<Vec<(K, u64)>>>,
+    level_lens: [usize; LEVELS],
+    // The live deadline of every scheduled key; slot entries that disagree are stale.
+    deadlines: HashMap<K, u64>,
+}
+
+impl<K: Clone + Hash + Eq> TimingWheel<K> {
+    pub(crate) fn new(origin: Instant, resolution: Duration) -> Self {
+        assert!(
+            resolution > Duration::from_secs(0),
+            "Resolution must be non-zero"
+        );
+        TimingWheel {
+            origin,
+            resolution,
+            elapsed: 0,
+            levels: (0..LEVELS)
+                .map(|_| (0..SLOTS).map(|_| Vec::new()).collect())
+                .collect(),
+            level_lens: [0; LEVELS],
+            deadlines: HashMap::new(),
+        }
+    }
+
+    // Schedules the key, replacing any deadline it already had. Deadlines are truncated to
+    // whole ticks, so a key may come due up to one tick early; callers recheck the entry.
+    pub(crate) fn schedule(&mut self, key: K, deadline: Instant) {
+        // Overdue keys fire on the next tick that gets processed
+        let tick = self.tick_of(deadline).max(self.elapsed);
+        self.deadlines.insert(key.clone(), tick);
+        self.place(key, tick);
+    }
+
+    // Returns whether the key was scheduled.
+    pub(crate) fn cancel(&mut self, key: &K) -> bool {
+        self.deadlines.remove(key).is_some()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        for level in &mut self.levels {
+            for slot in level.iter_mut() {
+                slot.clear();
+            }
+        }
+        self.level_lens = [0; LEVELS];
+        self.deadlines.clear();
+    }
+
+    // Removes and returns every key whose deadline is at or before `now`.
+    pub(crate) fn advance(&mut self, now: Instant) -> Vec<K> {
+        let target = self.tick_of(now);
+        let mut due = Vec::new();
+
+        while self.elapsed <= target {
+            let next = if self.level_lens[0] > 0 {
+                self.fire(target, &mut due);
+                self.elapsed + 1
+            } else {
+                // Nothing can fire before the next boundary of the finest non-empty level
+                match (1..LEVELS).find(|&level| self.level_lens[level] > 0) {
+                    Some(level) => {
+                        let shift = SLOT_BITS * level as u32;
+                        (((self.elapsed >> shift) + 1) << shift).min(target + 1)
+                    }
+                    None => target + 1,
+                }
+            };
+            self.elapsed = next;
+            self.cascade();
+        }
+        due
+    }
+
+    fn fire(&mut self, target: u64, due: &mut Vec<K>) {
+        let slot = (self.elapsed & SLOT_MASK) as usize;
+        let entries = mem::take(&mut self.levels[0][slot]);
+        self.level_lens[0] -= entries.len();
+
+        for (key, tick) in entries {
+            if self.deadlines.get(&key) != Some(&tick) {
+                continue;
+            }
+            if tick <= target {
+                self.deadlines.remove(&key);
+                due.push(key);
+            } else {
+                // Deadlines beyond the wheel's span wrap around and come back early
+                self.place(key, tick);
+            }
+        }
+    }
+
+    // Moves the entries of every level whose slot boundary was just reached into finer levels.
+    fn cascade(&mut self) {
+        for level in (1..LEVELS).rev() {
+            let shift = SLOT_BITS * level as u32;
+            if self.elapsed & ((1 << shift) - 1) != 0 {
+                continue;
+            }
+            let slot = ((self.elapsed >> shift) & SLOT_MASK) as usize;
+            let entries = mem::take(&mut self.levels[level][slot]);
+            self.level_lens[level] -= entries.len();
+
+            for (key, tick) in entries {
+                if self.deadlines.get(&key) == Some(&tick) {
+                    self.place(key, tick);
+                }
+            }
+        }
+    }
+
+    fn place(&mut self, key: K, tick: u64) {
+        let differing = tick ^ self.elapsed;
+        let level = if differing == 0 {
+            0
+        } else {
+            let highest_bit = 63 - differing.leading_zeros();
+            ((highest_bit / SLOT_BITS) as usize).min(LEVELS - 1)
+        };
+        let slot = ((tick >> (SLOT_BITS * level as u32)) & SLOT_MASK) as usize;
+        self.levels[level][slot].push((key, tick));
+        self.level_lens[level] += 1;
+    }
+
+    fn tick_of(&self, now: Instant) -> u64 {
+        let elapsed = now.saturating_duration_since(self.origin);
+        (elapsed.as_nanos() / self.resolution.as_nanos()) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wheel() -> (TimingWheel<u32>, Instant) {
+        let origin = Instant::now();
+        (TimingWheel::new(origin, Duration::from_millis(1)), origin)
+    }
+
+    fn sorted(mut keys: Vec<u32>) -> Vec<u32> {
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn test_keys_fire_at_their_deadline() {
+        let (mut wheel, origin) = wheel();
+        wheel.schedule(1, origin + Duration::from_millis(5));
+        wheel.schedule(2, origin + Duration::from_millis(10));
+
+        assert!(wheel.advance(origin + Duration::from_millis(4)).is_empty());
+        assert_eq!(wheel.advance(origin + Duration::from_millis(5)), vec![1]);
+        assert!(wheel.advance(origin + Duration::from_millis(9)).is_empty());
+        assert_eq!(wheel.advance(origin + Duration::from_millis(10)), vec![2]);
+        assert_eq!(wheel.deadlines.len(), 0);
+    }
+
+    #[test]
+    fn test_far_deadlines_cascade_down() {
+        let (mut wheel, origin) = wheel();
+        let deadlines = [70, 4_100, 300_000, 20_000_000];
+        for (key, &ms) in deadlines.iter().enumerate() {
+            wheel.schedule(key as u32, origin + Duration::from_millis(ms));
+        }
+
+        for (key, &ms) in deadlines.iter().enumerate() {
+            assert!(wheel
+                .advance(origin + Duration::from_millis(ms - 1))
+                .is_empty());
+            assert_eq!(
+                wheel.advance(origin + Duration::from_millis(ms)),
+                vec![key as u32]
+            );
+        }
+    }
+
+    #[test]
+    fn test_one_large_jump_fires_everything_due() {
+        let (mut wheel, origin) = wheel();
+        for key in 0..100 {
+            wheel.schedule(key, origin + Duration::from_millis(u64::from(key) * 997));
+        }
+
+        let due = wheel.advance(origin + Duration::from_millis(50 * 997));
+        assert_eq!(sorted(due), (0..=50).collect::<Vec<_>>());
+        assert_eq!(wheel.deadlines.len(), 49);
+    }
+
+    #[test]
+    fn test_reschedule_and_cancel() {
+        let (mut wheel, origin) = wheel();
+        wheel.schedule(1, origin + Duration::from_millis(5));
+        wheel.schedule(1, origin + Duration::from_millis(100));
+        wheel.schedule(2, origin + Duration::from_millis(5));
+        wheel.cancel(&2);
+
+        assert!(wheel.advance(origin + Duration::from_millis(99)).is_empty());
+        assert_eq!(wheel.advance(origin + Duration::from_millis(100)), vec![1]);
+    }
+
+    #[test]
+    fn test_overdue_keys_fire_on_next_advance() {
+        let (mut wheel, origin) = wheel();
+        wheel.advance(origin + Duration::from_millis(50));
+        wheel.schedule(1, origin + Duration::from_millis(10));
+
+        assert_eq!(wheel.advance(origin + Duration::from_millis(51)), vec![1]);
+    }
+}
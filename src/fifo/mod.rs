@@ -0,0 +1,278 @@
+use std::collections::hash_map::RandomState;
+
+use crate::queue::{self, End, Order, QueueCache};
+use crate::Policy;
+
+pub use crate::queue::{IntoIter, Iter};
+
+// First in, first out: entries are evicted in the order they were inserted, however often
+// they are read. A baseline for comparing the other policies against.
+pub struct Fifo;
+
+impl Order for Fifo {
+    const POLICY: Policy = Policy::FIFO;
+    const NAME: &'static str = "fifo";
+    const VICTIM: End = End::Oldest;
+    const RENEWS_ON_USE: bool = false;
+}
+
+pub type FIFOCache<K, V, S = RandomState> = QueueCache<K, V, S, Fifo>;
+pub type Entry<'a, K, V, S = RandomState> = queue::Entry<'a, K, V, S, Fifo>;
+pub type OccupiedEntry<'a, K, V, S = RandomState> = queue::OccupiedEntry<'a, K, V, S, Fifo>;
+pub type VacantEntry<'a, K, V, S = RandomState> = queue::VacantEntry<'a, K, V, S, Fifo>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, ManualClock};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    #[should_panic(expected = "Capacity must be greater than 0")]
+    fn test_new_cache_zero_capacity() {
+        let _cache: FIFOCache<String, i32> = FIFOCache::new(0);
+    }
+
+    #[test]
+    fn test_evicts_in_insertion_order() {
+        let mut cache = FIFOCache::new(2);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        assert_eq!(cache.get(&1), Some(&"one"));
+        cache.put(3, "three", 0);
+
+        // Reading key 1 did not save it
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), Some(&"two"));
+        assert_eq!(cache.get(&3), Some(&"three"));
+        assert_eq!(cache.stats().hits, 3);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn test_update_keeps_place() {
+        let mut cache = FIFOCache::new(2);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.put(1, "ONE", 0);
+        cache.put(3, "three", 0);
+
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&3, &2]);
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut cache = FIFOCache::new(2);
+
+        cache.put(1, vec![1], 0);
+        cache.get_mut(&1).unwrap().push(10);
+        assert_eq!(cache.get_mut(&3), None);
+        assert_eq!(cache.peek(&1), Some(&vec![1, 10]));
+    }
+
+    #[test]
+    fn test_remove_and_pop_victim() {
+        let mut cache = FIFOCache::new(3);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.put(3, "three", 0);
+        cache.get(&1);
+
+        assert_eq!(cache.remove(&2), Some("two"));
+        assert_eq!(cache.remove(&2), None);
+        assert_eq!(cache.peek_victim(), Some(&1));
+        assert_eq!(cache.pop_victim(), Some((1, "one")));
+        assert_eq!(cache.pop_victim(), Some((3, "three")));
+        assert_eq!(cache.pop_victim(), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_iter_from_iter_and_retain() {
+        let mut cache: FIFOCache<_, _> = vec![(1, "one", 0), (2, "two", 0), (3, "three", 0)]
+            .into_iter()
+            .collect();
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.iter().len(), 3);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&3, &2, &1]);
+        assert_eq!(
+            cache.values().collect::<Vec<_>>(),
+            vec![&"three", &"two", &"one"]
+        );
+
+        cache.retain(|&key, _| key != 2);
+        let entries: Vec<_> = cache.into_iter().collect();
+        assert_eq!(entries, vec![(3, "three"), (1, "one")]);
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut cache = FIFOCache::new(2);
+        let mut loads = 0;
+
+        for _ in 0..3 {
+            let value = cache.get_or_insert_with(1, 0, || {
+                loads += 1;
+                "one"
+            });
+            assert_eq!(value, &"one");
+        }
+
+        assert_eq!(loads, 1);
+        let failed: Result<&&str, &str> =
+            cache.try_get_or_insert_with(2, 0, || Err("backend down"));
+        assert_eq!(failed, Err("backend down"));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_eviction_listener() {
+        use std::sync::Mutex;
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = FIFOCache::new(2);
+        cache.set_eviction_listener(move |key, value| sink.lock().unwrap().push((key, value)));
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.remove(&2);
+        cache.put(3, "three", 0);
+        assert!(evicted.lock().unwrap().is_empty());
+
+        cache.put(4, "four", 0);
+        assert_eq!(*evicted.lock().unwrap(), vec![(1, "one")]);
+    }
+
+    #[test]
+    fn test_heavier_update_evicts_others() {
+        let mut cache = FIFOCache::new(4);
+        cache.set_max_weight(100);
+
+        cache.put(1, "one", 30);
+        cache.put(2, "two", 30);
+        cache.put(3, "three", 30);
+        cache.put(1, "ONE", 60);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&3, &1]);
+
+        // The updated key is never evicted to make room for itself
+        cache.put(1, "one", 200);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(cache.stats().current_weight, 200);
+    }
+
+    #[test]
+    fn test_expire_after_write() {
+        let clock = ManualClock::new();
+        let mut cache = FIFOCache::new(2);
+        cache.set_clock(Arc::new(clock.clone()));
+        cache.set_expire_after_write(Duration::from_secs(30));
+
+        cache.put(1, "one", 0);
+        clock.advance(Duration::from_secs(29));
+        assert_eq!(cache.get(&1), Some(&"one"));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.expire_due(clock.now()), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_or_insert_and_modify() {
+        let mut cache = FIFOCache::new(2);
+
+        *cache.entry(1).or_insert(0, 0) += 1;
+        cache.entry(1).and_modify(|v| *v += 10).or_insert(0, 0);
+
+        assert_eq!(cache.get(&1), Some(&11));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_occupied_entry_keeps_order() {
+        let mut cache = FIFOCache::new(2);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.entry(1);
+        cache.put(3, "three", 0);
+
+        assert!(!cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+    }
+
+    #[test]
+    fn test_occupied_insert_and_remove() {
+        let mut cache = FIFOCache::new(2);
+        cache.put(1, "one", 0);
+
+        match cache.entry(1) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.insert("ONE"), "one");
+                assert_eq!(entry.remove(), "ONE");
+            }
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_vacant_insert_evicts() {
+        let mut cache = FIFOCache::new(1);
+        cache.put(1, "one", 0);
+
+        match cache.entry(2) {
+            Entry::Vacant(entry) => {
+                assert_eq!(entry.key(), &2);
+                entry.insert("two", 0);
+            }
+            Entry::Occupied(_) => panic!("expected a vacant entry"),
+        }
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.peek(&2), Some(&"two"));
+    }
+
+    #[test]
+    fn test_snapshot_keeps_insertion_order() {
+        let mut cache = FIFOCache::new(3);
+        cache.put(1, "one".to_string(), 2);
+        cache.put(2, "two".to_string(), 1);
+        cache.put(3, "three".to_string(), 1);
+        cache.get(&1);
+        cache.get(&9);
+
+        let mut bytes = Vec::new();
+        cache.save_to(&mut bytes).unwrap();
+        let mut restored: FIFOCache<i32, String, RandomState> =
+            FIFOCache::load_from(bytes.as_slice()).unwrap();
+        assert_eq!(restored.stats(), cache.stats());
+        let order: Vec<i32> = restored.iter().map(|(&key, _)| key).collect();
+        assert_eq!(order, vec![3, 2, 1]);
+
+        restored.put(4, "four".to_string(), 1);
+        assert!(!restored.contains_key(&1));
+        assert_eq!(restored.get(&2), Some(&"two".to_string()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_keeps_insertion_order() {
+        let mut cache = FIFOCache::new(3);
+        cache.put(1, "one".to_string(), 2);
+        cache.put(2, "two".to_string(), 1);
+        cache.put(3, "three".to_string(), 1);
+        cache.get(&1);
+
+        let json = serde_json::to_string(&cache).unwrap();
+        let restored: FIFOCache<i32, String, RandomState> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.stats(), cache.stats());
+        let order: Vec<i32> = restored.iter().map(|(&key, _)| key).collect();
+        assert_eq!(order, vec![3, 2, 1]);
+    }
+}
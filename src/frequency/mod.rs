@@ -0,0 +1,188 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+// Counter rows, each probed at a different position for the same key.
+pub(crate) const DEPTH: usize = 4;
+
+// Small sketches still get enough counters to keep collisions between keys rare.
+const MIN_WIDTH: usize = 64;
+
+// Counters saturate here, as 4-bit counters would: the sketch only needs to tell rare keys
+// from popular ones.
+pub const MAX_COUNT: u8 = 15;
+
+// Counts are halved after this many recorded accesses per key the sketch is sized for.
+const SAMPLE_FACTOR: usize = 10;
+
+// Derives the `i`th probe position from a key's 64-bit hash by double hashing, so a key
+// is hashed only once.
+pub(crate) fn probe(hash: u64, i: usize, mask: usize) -> usize {
+    let h1 = hash as usize;
+    let h2 = ((hash >> 32) as usize) | 1;
+    h1.wrapping_add(i.wrapping_mul(h2)) & mask
+}
+
+// Estimates how often each key has been seen recently in a fixed amount of memory: a
+// count-min sketch with saturating counters, whose estimates can only be too high, never
+// too low. Every counter is halved once a sample period's worth of accesses has been
+// recorded, so queries that were popular last week fade and today's take their place.
+//
+// Sized for `capacity` distinct hot keys, e.g. a cache's capacity. Estimates suit
+// admission decisions, as in `TinyLFU`, or serve as weights and costs for frequency-aware
+// policies.
+pub struct FrequencySketch<S = RandomState> {
+    hash_builder: S,
+    rows: Vec<Vec<u8>>,
+    mask: usize,
+    sample_size: usize,
+    recorded: usize,
+}
+
+impl FrequencySketch {
+    pub fn new(capacity: usize) -> Self {
+        FrequencySketch::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<S: BuildHasher> FrequencySketch<S> {
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        let width = capacity.max(MIN_WIDTH).next_power_of_two();
+        FrequencySketch {
+            hash_builder,
+            rows: vec![vec![0; width]; DEPTH],
+            mask: width - 1,
+            sample_size: capacity.max(1) * SAMPLE_FACTOR,
+            recorded: 0,
+        }
+    }
+
+    // Ages the counts after every `accesses` recorded accesses instead.
+    pub fn set_sample_size(&mut self, accesses: usize) {
+        assert!(accesses > 0, "Sample size must be greater than 0");
+        self.sample_size = accesses;
+    }
+
+    pub fn sample_size(&self) -> usize {
+        self.sample_size
+    }
+
+    pub fn record<K: Hash + ?Sized>(&mut self, key: &K) {
+        let hash = self.hash(key);
+        self.increment(hash);
+        self.tick();
+    }
+
+    // At most `MAX_COUNT`.
+    pub fn estimate<K: Hash + ?Sized>(&self, key: &K) -> u32 {
+        u32::from(self.estimate_hash(self.hash(key)))
+    }
+
+    // The estimate as a weight or cost for `put`: keys never seen still count as 1.
+    pub fn weight<K: Hash + ?Sized>(&self, key: &K) -> u32 {
+        self.estimate(key).max(1)
+    }
+
+    // Halves every counter now, without waiting for the sample period to end.
+    pub fn age(&mut self) {
+        for counter in self.rows.iter_mut().flatten() {
+            *counter /= 2;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for counter in self.rows.iter_mut().flatten() {
+            *counter = 0;
+        }
+        self.recorded = 0;
+    }
+
+    pub(crate) fn hash<K: Hash + ?Sized>(&self, key: &K) -> u64 {
+        self.hash_builder.hash_one(key)
+    }
+
+    pub(crate) fn increment(&mut self, hash: u64) {
+        for (i, row) in self.rows.iter_mut().enumerate() {
+            let counter = &mut row[probe(hash, i, self.mask)];
+            if *counter < MAX_COUNT {
+                *counter += 1;
+            }
+        }
+    }
+
+    pub(crate) fn estimate_hash(&self, hash: u64) -> u8 {
+        self.rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| row[probe(hash, i, self.mask)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    // Counts one access towards the sample period, ageing the counters when it ends.
+    // Returns whether they were aged.
+    pub(crate) fn tick(&mut self) -> bool {
+        self.recorded += 1;
+        if self.recorded < self.sample_size {
+            return false;
+        }
+        self.age();
+        self.recorded /= 2;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+
+    // Keeps the sketch's collisions the same from run to run.
+    type FixedState = BuildHasherDefault<DefaultHasher>;
+
+    #[test]
+    fn test_sketch_counts_and_saturates() {
+        let mut sketch = FrequencySketch::with_hasher(64, FixedState::default());
+        sketch.set_sample_size(usize::MAX);
+        for _ in 0..3 {
+            sketch.record("rust");
+        }
+        assert_eq!(sketch.estimate("rust"), 3);
+        assert_eq!(sketch.estimate("zig"), 0);
+        assert_eq!(sketch.weight("zig"), 1);
+
+        for _ in 0..100 {
+            sketch.record("rust");
+        }
+        assert_eq!(sketch.estimate("rust"), u32::from(MAX_COUNT));
+
+        sketch.age();
+        assert_eq!(sketch.estimate("rust"), u32::from(MAX_COUNT / 2));
+        sketch.clear();
+        assert_eq!(sketch.estimate("rust"), 0);
+    }
+
+    #[test]
+    fn test_sketch_never_underestimates() {
+        let mut sketch = FrequencySketch::with_hasher(16, FixedState::default());
+        sketch.set_sample_size(usize::MAX);
+        for key in 0..200u64 {
+            sketch.record(&key);
+        }
+        assert!((0..200u64).all(|key| sketch.estimate(&key) >= 1));
+    }
+
+    #[test]
+    fn test_counts_age_each_sample_period() {
+        let mut sketch = FrequencySketch::with_hasher(64, FixedState::default());
+        sketch.set_sample_size(10);
+        for _ in 0..8 {
+            sketch.record("old favourite");
+        }
+        for _ in 0..2 {
+            sketch.record("new");
+        }
+        assert_eq!(sketch.estimate("old favourite"), 4);
+        assert_eq!(sketch.estimate("new"), 1);
+    }
+}
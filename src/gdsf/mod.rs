@@ -0,0 +1,556 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+
+use crate::stats::CacheStats;
+use crate::trace;
+use crate::util::PriorityHeap;
+use crate::weight::Ordered;
+use crate::{Cache, EvictionListener, NewCache};
+
+const POLICY: &str = "gdsf";
+
+struct GDSFNode<V> {
+    value: V,
+    // The size counts against `max_weight`; the cost is what a miss on the entry would
+    // take to recompute, e.g. a query latency.
+    size: u32,
+    cost: f64,
+    freq: u64,
+}
+
+impl<V> GDSFNode<V> {
+    // Empty entries are priced as one unit so that their priority stays finite.
+    fn priority(&self, l: f64) -> f64 {
+        l + self.freq as f64 * self.cost / f64::from(self.size.max(1))
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    entries: std::vec::IntoIter<(&'a K, &'a V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+pub struct IntoIter<K, V> {
+    entries: std::vec::IntoIter<(K, V)>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+// GreedyDual-Size-Frequency: an entry's priority is `L + freq * cost / size`, and `L` is
+// raised to the priority of every victim. Small, popular, expensive entries stay longest,
+// which suits caches whose entries range from a few bytes to whole posting lists.
+pub struct GDSFCache<K, V, S = RandomState> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    l: f64,
+    pq: PriorityHeap<K, Reverse<Ordered<f64>>, S>,
+    cache: HashMap<K, GDSFNode<V>, S>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    stats: CacheStats,
+}
+
+impl<K: Clone + Hash + Eq, V> GDSFCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        GDSFCache::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> GDSFCache<K, V, S> {
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        GDSFCache {
+            capacity,
+            max_weight: None,
+            l: 0.0,
+            pq: PriorityHeap::with_hasher(hash_builder.clone()),
+            cache: HashMap::with_hasher(hash_builder),
+            eviction_listener: None,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let _span = trace::get_span(POLICY, key);
+        if !self.bump(key) {
+            return None;
+        }
+        self.cache.get(key).map(|gdsf_node| &gdsf_node.value)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if !self.bump(key) {
+            return None;
+        }
+        self.cache
+            .get_mut(key)
+            .map(|gdsf_node| &mut gdsf_node.value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.cache.get(key).map(|gdsf_node| &gdsf_node.value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    // Stores an entry whose cost equals its size, so only frequency and recency tell
+    // entries apart.
+    pub fn put(&mut self, key: K, value: V, size: u32) {
+        self.put_with_cost(key, value, size, f64::from(size));
+    }
+
+    // Replacing a value counts as a use: the entry keeps its frequency and gains one.
+    pub fn put_with_cost(&mut self, key: K, value: V, size: u32, cost: f64) {
+        let _span = trace::put_span(POLICY, &key, size);
+        let freq = match self.cache.get(&key).map(|gdsf_node| gdsf_node.freq) {
+            Some(freq) => {
+                self.remove(&key);
+                freq.saturating_add(1)
+            }
+            None => {
+                self.stats.insertions += 1;
+                trace::insert(POLICY, &key, size);
+                1
+            }
+        };
+
+        self.make_room(size);
+        let gdsf_node = GDSFNode {
+            value,
+            size,
+            cost,
+            freq,
+        };
+        let priority = gdsf_node.priority(self.l);
+        self.cache.insert(key.clone(), gdsf_node);
+        self.pq.push(key, Reverse(Ordered(priority)));
+        self.stats.current_weight += u64::from(size);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let gdsf_node = self.cache.remove(key)?;
+        self.pq.remove(key);
+        self.stats.current_weight -= u64::from(gdsf_node.size);
+        Some(gdsf_node.value)
+    }
+
+    // The entry `pop_victim` would remove, left in place.
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.pq.peek().map(|(key, _)| key)
+    }
+
+    // The victim's priority becomes the new `L`.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        let (key, Reverse(Ordered(priority))) = self.pq.pop()?;
+        self.l = priority;
+        let gdsf_node = self.cache.remove(&key)?;
+        self.stats.current_weight -= u64::from(gdsf_node.size);
+        Some((key, gdsf_node.value))
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        let doomed: Vec<K> = self
+            .cache
+            .iter()
+            .filter(|&(key, gdsf_node)| !f(key, &gdsf_node.value))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in doomed {
+            self.remove(&key);
+        }
+    }
+
+    // Bounds the cache by the summed size of its entries instead of by entry count.
+    // Entries already over the budget are evicted right away.
+    pub fn set_max_weight(&mut self, max_weight: u64) {
+        self.max_weight = Some(max_weight);
+        while !self.is_empty() && self.is_over_budget() {
+            self.evict();
+        }
+    }
+
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.eviction_listener = Some(Box::new(listener));
+    }
+
+    pub fn clear(&mut self) {
+        self.pq.clear();
+        self.cache.clear();
+        self.l = 0.0;
+        self.stats.current_weight = 0;
+    }
+
+    // Entries from highest priority to the next victim.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut by_priority: Vec<(&K, Ordered<f64>)> = self
+            .pq
+            .iter()
+            .map(|(key, &Reverse(priority))| (key, priority))
+            .collect();
+        by_priority.sort_by_key(|&(_, priority)| Reverse(priority));
+
+        let entries: Vec<(&K, &V)> = by_priority
+            .into_iter()
+            .map(|(key, _)| (key, &self.cache[key].value))
+            .collect();
+
+        Iter {
+            entries: entries.into_iter(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    pub fn get_freq(&self, key: &K) -> Option<u64> {
+        self.cache.get(key).map(|gdsf_node| gdsf_node.freq)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.pq.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pq.is_empty()
+    }
+
+    // Records the lookup and, on a hit, raises the entry's priority against the current `L`.
+    fn bump(&mut self, key: &K) -> bool {
+        let l = self.l;
+        match self.cache.get_mut(key) {
+            Some(gdsf_node) => {
+                gdsf_node.freq = gdsf_node.freq.saturating_add(1);
+                self.pq
+                    .change_priority(key, Reverse(Ordered(gdsf_node.priority(l))));
+                self.stats.hits += 1;
+                true
+            }
+            None => {
+                self.stats.misses += 1;
+                trace::miss(POLICY, key);
+                false
+            }
+        }
+    }
+
+    // Whether an entry of this size only fits after an eviction.
+    fn is_full(&self, size: u32) -> bool {
+        match self.max_weight {
+            Some(max_weight) => self.stats.current_weight + u64::from(size) > max_weight,
+            None => self.len() >= self.capacity,
+        }
+    }
+
+    fn is_over_budget(&self) -> bool {
+        self.max_weight
+            .is_some_and(|max_weight| self.stats.current_weight > max_weight)
+    }
+
+    fn make_room(&mut self, size: u32) {
+        while !self.is_empty() && self.is_full(size) {
+            self.evict();
+        }
+    }
+
+    // Capacity-driven eviction; the listener sees every entry dropped this way.
+    fn evict(&mut self) {
+        if let Some((key, value)) = self.pop_victim() {
+            self.stats.evictions += 1;
+            trace::eviction(POLICY, &key);
+            if let Some(listener) = self.eviction_listener.as_mut() {
+                listener(key, value);
+            }
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> IntoIterator for GDSFCache<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        let mut cache = self.cache;
+        let mut by_priority: Vec<(K, Ordered<f64>)> = self
+            .pq
+            .into_iter()
+            .map(|(key, Reverse(priority))| (key, priority))
+            .collect();
+        by_priority.sort_by_key(|&(_, priority)| Reverse(priority));
+
+        let entries: Vec<(K, V)> = by_priority
+            .into_iter()
+            .filter_map(|(key, _)| {
+                let gdsf_node = cache.remove(&key)?;
+                Some((key, gdsf_node.value))
+            })
+            .collect();
+
+        IntoIter {
+            entries: entries.into_iter(),
+        }
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher + Clone> IntoIterator for &'a GDSFCache<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+// The capacity is sized to hold every collected entry.
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone + Default> FromIterator<(K, V, u32)>
+    for GDSFCache<K, V, S>
+{
+    fn from_iter<I: IntoIterator<Item = (K, V, u32)>>(iter: I) -> Self {
+        let entries: Vec<_> = iter.into_iter().collect();
+        let mut cache = GDSFCache::with_hasher(entries.len().max(1), S::default());
+        cache.extend(entries);
+        cache
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Extend<(K, V, u32)> for GDSFCache<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V, u32)>>(&mut self, iter: I) {
+        for (key, value, size) in iter {
+            self.put(key, value, size);
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Cache<K, V> for GDSFCache<K, V, S> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone + Default> NewCache<K, V>
+    for GDSFCache<K, V, S>
+{
+    fn new(capacity: usize) -> Self {
+        GDSFCache::with_hasher(capacity, S::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "Capacity must be greater than 0")]
+    fn test_new_cache_zero_capacity() {
+        let _cache: GDSFCache<String, i32> = GDSFCache::new(0);
+    }
+
+    #[test]
+    fn test_basic_operations() {
+        let mut cache = GDSFCache::new(2);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn test_large_entries_go_first() {
+        let mut cache = GDSFCache::new(2);
+        // Equally expensive to recompute, but the posting list takes 100 times the space
+        cache.put_with_cost("posting list", 0, 1_000, 50.0);
+        cache.put_with_cost("result page", 1, 10, 50.0);
+
+        cache.put_with_cost("another page", 2, 10, 50.0);
+        assert!(!cache.contains_key(&"posting list"));
+        assert!(cache.contains_key(&"result page"));
+        assert_eq!(cache.l, 0.05);
+    }
+
+    #[test]
+    fn test_frequency_outweighs_size() {
+        let mut cache = GDSFCache::new(2);
+        cache.put_with_cost("big", 0, 4, 4.0); // priority 1
+        cache.put_with_cost("small", 1, 1, 2.0); // priority 2
+        for _ in 0..2 {
+            cache.get(&"big"); // priority 3
+        }
+
+        cache.put_with_cost("new", 2, 1, 2.0);
+        assert!(cache.contains_key(&"big"));
+        assert!(!cache.contains_key(&"small"));
+        assert_eq!(cache.l, 2.0);
+    }
+
+    #[test]
+    fn test_inflation_retires_stale_entries() {
+        let mut cache = GDSFCache::new(2);
+        cache.put(-1, "favourite", 1);
+        for _ in 0..4 {
+            cache.get(&-1);
+        }
+
+        // Each eviction raises `L`, so newcomers eventually outrank the old favourite
+        for key in 0..10 {
+            cache.put(key, "newcomer", 1);
+        }
+        assert!(!cache.contains_key(&-1));
+    }
+
+    #[test]
+    fn test_update_keeps_frequency() {
+        let mut cache = GDSFCache::new(2);
+        cache.put(1, "one", 10);
+        cache.get(&1);
+        cache.put(1, "ONE", 20);
+
+        assert_eq!(cache.get_freq(&1), Some(3));
+        assert_eq!(cache.peek(&1), Some(&"ONE"));
+        assert_eq!(cache.stats().current_weight, 20);
+        assert_eq!(cache.stats().insertions, 1);
+    }
+
+    #[test]
+    fn test_max_weight_bounds_total_size() {
+        let mut cache = GDSFCache::new(1);
+        cache.set_max_weight(100);
+        cache.put(1, "one", 40);
+        cache.put(2, "two", 40);
+        assert_eq!(cache.len(), 2);
+
+        cache.put(3, "three", 40);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats().current_weight, 80);
+
+        cache.set_max_weight(50);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_zero_size_entries() {
+        let mut cache = GDSFCache::new(2);
+        cache.put_with_cost(1, "empty", 0, 1.0);
+        cache.put_with_cost(2, "two", 2, 1.0);
+
+        cache.put_with_cost(3, "three", 1, 1.0);
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+    }
+
+    #[test]
+    fn test_iter_and_retain() {
+        let mut cache: GDSFCache<_, _> = vec![(1, "one", 4), (2, "two", 1), (3, "three", 2)]
+            .into_iter()
+            .collect();
+        for _ in 0..3 {
+            cache.get(&1);
+        }
+        cache.get(&2);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&1, &2, &3]);
+
+        cache.retain(|&key, _| key != 2);
+        let entries: Vec<_> = cache.into_iter().collect();
+        assert_eq!(entries, vec![(1, "one"), (3, "three")]);
+    }
+
+    #[test]
+    fn test_eviction_listener() {
+        use std::sync::{Arc, Mutex};
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = GDSFCache::new(1);
+        cache.set_eviction_listener(move |key, value| sink.lock().unwrap().push((key, value)));
+
+        cache.put(1, "one", 1);
+        cache.remove(&1);
+        cache.put(2, "two", 1);
+        cache.put(3, "three", 1);
+        assert_eq!(*evicted.lock().unwrap(), vec![(2, "two")]);
+        assert_eq!(cache.pop_victim(), Some((3, "three")));
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,452 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+use crate::clock::{timed_cost, Clock, SystemClock};
+use crate::stats::CacheStats;
+use crate::trace;
+use crate::util::PriorityHeap;
+use crate::weight::Ordered;
+use crate::{Cache, EvictionListener, NewCache};
+
+const POLICY: &str = "greedy_dual";
+
+// Prices an entry when it is inserted, e.g. from the latency of the query that produced it.
+// Closures taking the key and value implement it.
+pub trait CostFn<K, V> {
+    fn cost(&self, key: &K, value: &V) -> f64;
+}
+
+impl<K, V, F: Fn(&K, &V) -> f64> CostFn<K, V> for F {
+    fn cost(&self, key: &K, value: &V) -> f64 {
+        self(key, value)
+    }
+}
+
+// Prices every entry the same, leaving `L` alone to rank them by how recently they were used.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnitCost;
+
+impl<K, V> CostFn<K, V> for UnitCost {
+    fn cost(&self, _key: &K, _value: &V) -> f64 {
+        1.0
+    }
+}
+
+struct GreedyDualNode<V> {
+    value: V,
+    cost: f64,
+}
+
+pub struct Iter<'a, K, V> {
+    entries: std::vec::IntoIter<(&'a K, &'a V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+// Classic GreedyDual: an entry's credit starts at `L + cost` and is restored to it on every
+// hit, and `L` is raised to the credit of every victim. Cheap entries leave first, and
+// expensive ones leave once they have gone unused long enough for `L` to catch up.
+pub struct GreedyDual<K, V, C = UnitCost, S = RandomState> {
+    capacity: usize,
+    l: f64,
+    cost_fn: C,
+    pq: PriorityHeap<K, Reverse<Ordered<f64>>, S>,
+    cache: HashMap<K, GreedyDualNode<V>, S>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    clock: Arc<dyn Clock>,
+    stats: CacheStats,
+}
+
+impl<K: Clone + Hash + Eq, V> GreedyDual<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        GreedyDual::with_cost_fn(capacity, UnitCost)
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, C: CostFn<K, V>> GreedyDual<K, V, C> {
+    pub fn with_cost_fn(capacity: usize, cost_fn: C) -> Self {
+        GreedyDual::with_hasher(capacity, cost_fn, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, C: CostFn<K, V>, S: BuildHasher + Clone> GreedyDual<K, V, C, S> {
+    pub fn with_hasher(capacity: usize, cost_fn: C, hash_builder: S) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        GreedyDual {
+            capacity,
+            l: 0.0,
+            cost_fn,
+            pq: PriorityHeap::with_hasher(hash_builder.clone()),
+            cache: HashMap::with_hasher(hash_builder),
+            eviction_listener: None,
+            clock: Arc::new(SystemClock),
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let _span = trace::get_span(POLICY, key);
+        if !self.restore(key) {
+            return None;
+        }
+        self.cache
+            .get(key)
+            .map(|greedy_dual_node| &greedy_dual_node.value)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if !self.restore(key) {
+            return None;
+        }
+        self.cache
+            .get_mut(key)
+            .map(|greedy_dual_node| &mut greedy_dual_node.value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.cache
+            .get(key)
+            .map(|greedy_dual_node| &greedy_dual_node.value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    // Prices the entry with the cost function.
+    pub fn insert(&mut self, key: K, value: V) {
+        let cost = self.cost_fn.cost(&key, &value);
+        self.put_with_cost(key, value, cost);
+    }
+
+    // Bypasses the cost function with a cost already known to the caller.
+    pub fn put_with_cost(&mut self, key: K, value: V, cost: f64) {
+        let _span = trace::put_span(POLICY, &key, cost);
+        if self.remove(&key).is_none() {
+            self.stats.insertions += 1;
+            trace::insert(POLICY, &key, cost);
+            while !self.is_empty() && self.len() >= self.capacity {
+                self.evict();
+            }
+        }
+
+        let credit = self.l + cost;
+        self.cache
+            .insert(key.clone(), GreedyDualNode { value, cost });
+        self.pq.push(key, Reverse(Ordered(credit)));
+    }
+
+    // Computes the value on a miss and prices it with the milliseconds `f` took, bypassing
+    // the cost function, so queries that were slow to answer are the last to go.
+    pub fn get_or_insert_timed<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &V {
+        if !self.restore(&key) {
+            let (value, millis) = timed_cost(self.clock.as_ref(), f);
+            self.put_with_cost(key.clone(), value, millis);
+        }
+        &self.cache[&key].value
+    }
+
+    // The clock `get_or_insert_timed` measures with, so tests can control the latency.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.pq.remove(key);
+        self.cache
+            .remove(key)
+            .map(|greedy_dual_node| greedy_dual_node.value)
+    }
+
+    // The entry `pop_victim` would remove, left in place.
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.pq.peek().map(|(key, _)| key)
+    }
+
+    // The victim's credit becomes the new `L`.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        let (key, Reverse(Ordered(credit))) = self.pq.pop()?;
+        self.l = credit;
+        let greedy_dual_node = self.cache.remove(&key)?;
+        Some((key, greedy_dual_node.value))
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        let doomed: Vec<K> = self
+            .cache
+            .iter()
+            .filter(|&(key, greedy_dual_node)| !f(key, &greedy_dual_node.value))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in doomed {
+            self.remove(&key);
+        }
+    }
+
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.eviction_listener = Some(Box::new(listener));
+    }
+
+    pub fn clear(&mut self) {
+        self.pq.clear();
+        self.cache.clear();
+        self.l = 0.0;
+    }
+
+    // Entries from highest credit to the next victim.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut by_credit: Vec<(&K, Ordered<f64>)> = self
+            .pq
+            .iter()
+            .map(|(key, &Reverse(credit))| (key, credit))
+            .collect();
+        by_credit.sort_by_key(|&(_, credit)| Reverse(credit));
+
+        let entries: Vec<(&K, &V)> = by_credit
+            .into_iter()
+            .map(|(key, _)| (key, &self.cache[key].value))
+            .collect();
+
+        Iter {
+            entries: entries.into_iter(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn get_cost(&self, key: &K) -> Option<f64> {
+        self.cache
+            .get(key)
+            .map(|greedy_dual_node| greedy_dual_node.cost)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.pq.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pq.is_empty()
+    }
+
+    // Records the lookup and, on a hit, restores the entry's credit against the current `L`.
+    fn restore(&mut self, key: &K) -> bool {
+        match self.cache.get(key) {
+            Some(greedy_dual_node) => {
+                let credit = self.l + greedy_dual_node.cost;
+                self.pq.change_priority(key, Reverse(Ordered(credit)));
+                self.stats.hits += 1;
+                true
+            }
+            None => {
+                self.stats.misses += 1;
+                trace::miss(POLICY, key);
+                false
+            }
+        }
+    }
+
+    // Capacity-driven eviction; the listener sees every entry dropped this way.
+    fn evict(&mut self) {
+        if let Some((key, value)) = self.pop_victim() {
+            self.stats.evictions += 1;
+            trace::eviction(POLICY, &key);
+            if let Some(listener) = self.eviction_listener.as_mut() {
+                listener(key, value);
+            }
+        }
+    }
+}
+
+// Through the trait the weight is taken as the entry's cost.
+impl<K: Clone + Hash + Eq, V, C: CostFn<K, V>, S: BuildHasher + Clone> Cache<K, V>
+    for GreedyDual<K, V, C, S>
+{
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put_with_cost(key, value, f64::from(weight))
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K, V, C, S> NewCache<K, V> for GreedyDual<K, V, C, S>
+where
+    K: Clone + Hash + Eq,
+    C: CostFn<K, V> + Default,
+    S: BuildHasher + Clone + Default,
+{
+    fn new(capacity: usize) -> Self {
+        GreedyDual::with_hasher(capacity, C::default(), S::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::time::Duration;
+
+    #[test]
+    #[should_panic(expected = "Capacity must be greater than 0")]
+    fn test_new_cache_zero_capacity() {
+        let _cache: GreedyDual<String, i32> = GreedyDual::new(0);
+    }
+
+    #[test]
+    fn test_insert_defaults_to_unit_cost() {
+        let mut cache = GreedyDual::new(2);
+        cache.put_with_cost(1, "one", 0.5);
+        cache.put_with_cost(2, "two", 2.0);
+
+        cache.insert(3, "three");
+        assert_eq!(cache.get_cost(&3), Some(1.0));
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_cost_fn_prices_inserts() {
+        // Long result lists took longer to compute
+        let mut cache =
+            GreedyDual::with_cost_fn(2, |_: &&str, results: &Vec<u32>| results.len() as f64);
+        cache.insert("rare query", vec![1]);
+        cache.insert("broad query", vec![1, 2, 3, 4]);
+        assert_eq!(cache.get_cost(&"broad query"), Some(4.0));
+
+        cache.insert("another query", vec![1, 2]);
+        assert!(!cache.contains_key(&"rare query"));
+        assert_eq!(cache.l, 1.0);
+    }
+
+    #[test]
+    fn test_hit_restores_credit() {
+        let mut cache = GreedyDual::new(2);
+        cache.put_with_cost("slow", 0, 5.0);
+        cache.put_with_cost("fast", 1, 1.0);
+        cache.put_with_cost("a", 2, 1.0); // evicts fast, l = 1
+        cache.put_with_cost("b", 3, 1.0); // evicts a, l = 2
+        cache.put_with_cost("c", 4, 1.0); // evicts b, l = 3
+        cache.put_with_cost("d", 5, 1.0); // evicts c, l = 4
+
+        // Restored to 4 + 5 instead of evicted at 5
+        cache.get(&"slow");
+        cache.put_with_cost("e", 6, 1.0);
+        cache.put_with_cost("f", 7, 1.0);
+        assert!(cache.contains_key(&"slow"));
+    }
+
+    #[test]
+    fn test_unused_expensive_entries_eventually_leave() {
+        let mut cache = GreedyDual::new(2);
+        cache.put_with_cost(-1, "expensive", 5.0);
+        for key in 0..10 {
+            cache.put_with_cost(key, "cheap", 1.0);
+        }
+        assert!(!cache.contains_key(&-1));
+    }
+
+    #[test]
+    fn test_update_reprices_entry() {
+        let mut cache = GreedyDual::new(2);
+        cache.put_with_cost(1, "one", 1.0);
+        cache.put_with_cost(1, "ONE", 3.0);
+
+        assert_eq!(cache.peek(&1), Some(&"ONE"));
+        assert_eq!(cache.get_cost(&1), Some(3.0));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.stats().insertions, 1);
+    }
+
+    #[test]
+    fn test_iter_retain_and_pop_victim() {
+        let mut cache = GreedyDual::new(3);
+        cache.put_with_cost(1, "one", 2.0);
+        cache.put_with_cost(2, "two", 3.0);
+        cache.put_with_cost(3, "three", 1.0);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&2, &1, &3]);
+
+        cache.retain(|&key, _| key != 2);
+        assert_eq!(cache.pop_victim(), Some((3, "three")));
+        assert_eq!(cache.pop_victim(), Some((1, "one")));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_timed_inserts_are_priced_by_latency() {
+        let clock = ManualClock::new();
+        let mut cache = GreedyDual::new(2);
+        cache.set_clock(Arc::new(clock.clone()));
+        cache.get_or_insert_timed("slow", || {
+            clock.advance(Duration::from_millis(25));
+            1
+        });
+        cache.get_or_insert_timed("fast", || 2);
+        assert_eq!(cache.get_cost(&"slow"), Some(25.0));
+        assert_eq!(cache.get_cost(&"fast"), Some(1.0));
+
+        assert_eq!(cache.get_or_insert_timed("slow", || 3), &1);
+        cache.insert("next", 4);
+        assert!(!cache.contains_key(&"fast"));
+        assert_eq!(cache.stats().hits, 1);
+    }
+}
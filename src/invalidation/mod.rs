@@ -0,0 +1,255 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::{Cache, CacheType, EpochCache, PrefixCache, SyncCache};
+
+// Commands applied per hold of the cache lock, so a flood of invalidations does not keep
+// foreground calls waiting.
+const COMMAND_BATCH: usize = 64;
+
+// A command to drop cached entries, e.g. sent by the indexer after a document changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Invalidation<K> {
+    Key(K),
+    // Every key starting with the prefix. Keys that lead with a tag, like "q:rust page:1",
+    // are invalidated a tag at a time this way.
+    Prefix(String),
+    // Every entry older than the epoch.
+    Epoch(u64),
+    All,
+}
+
+// A cache that can apply invalidation commands. Commands the cache has no notion of, such
+// as epochs on a plain policy, are ignored.
+pub trait Invalidate<K> {
+    // Returns how many entries the command removed. Entries invalidated by an epoch are
+    // only reclaimed later, so they are not counted.
+    fn invalidate(&mut self, command: &Invalidation<K>) -> usize;
+}
+
+impl<K, V, S> Invalidate<K> for CacheType<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    fn invalidate(&mut self, command: &Invalidation<K>) -> usize {
+        match command {
+            Invalidation::Key(key) => self.remove(key).map_or(0, |_| 1),
+            Invalidation::Prefix(_) | Invalidation::Epoch(_) => 0,
+            Invalidation::All => {
+                let removed = self.len();
+                self.clear();
+                removed
+            }
+        }
+    }
+}
+
+impl<K, V, S> Invalidate<K> for PrefixCache<K, V, S>
+where
+    K: Clone + Hash + Ord + Borrow<str> + Send + 'static,
+    V: Send + 'static,
+    S: BuildHasher + Clone,
+{
+    fn invalidate(&mut self, command: &Invalidation<K>) -> usize {
+        match command {
+            Invalidation::Key(key) => self.remove(key).map_or(0, |_| 1),
+            Invalidation::Prefix(prefix) => self.invalidate_prefix(prefix),
+            Invalidation::Epoch(_) => 0,
+            Invalidation::All => {
+                let removed = self.len();
+                self.clear();
+                removed
+            }
+        }
+    }
+}
+
+impl<K, V, S> Invalidate<K> for EpochCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    fn invalidate(&mut self, command: &Invalidation<K>) -> usize {
+        match command {
+            Invalidation::Key(key) => self.remove(key).map_or(0, |_| 1),
+            Invalidation::Prefix(_) => 0,
+            Invalidation::Epoch(epoch) => {
+                self.set_min_epoch(*epoch);
+                0
+            }
+            Invalidation::All => {
+                let removed = self.len();
+                self.clear();
+                removed
+            }
+        }
+    }
+}
+
+enum Message<K> {
+    Apply(Invalidation<K>),
+    Stop,
+}
+
+// The sending end of a listener's channel. Clone it for every thread, or every connection
+// relaying commands from other nodes; none of them touch the cache lock.
+pub struct Invalidator<K> {
+    sender: Sender<Message<K>>,
+}
+
+impl<K> Clone for Invalidator<K> {
+    fn clone(&self) -> Self {
+        Invalidator {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<K> Invalidator<K> {
+    // Queues the command. Returns false once the listener has stopped.
+    pub fn send(&self, command: Invalidation<K>) -> bool {
+        self.sender.send(Message::Apply(command)).is_ok()
+    }
+}
+
+// Applies the commands sent through the returned `Invalidator` to a shared cache, on a new
+// thread, in the order they were sent. The thread runs until the worker is stopped or
+// dropped.
+pub fn listen<K, V, C>(cache: Arc<SyncCache<K, V, C>>) -> (Invalidator<K>, InvalidationWorker<K>)
+where
+    K: Send + Sync + 'static,
+    V: Send + Sync + 'static,
+    C: Cache<K, V> + Invalidate<K> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || apply_commands(&cache, &receiver));
+    let worker = InvalidationWorker {
+        sender: sender.clone(),
+        handle: Some(handle),
+    };
+    (Invalidator { sender }, worker)
+}
+
+// Returns how many commands were applied.
+fn apply_commands<K, V, C>(cache: &SyncCache<K, V, C>, receiver: &Receiver<Message<K>>) -> u64
+where
+    C: Cache<K, V> + Invalidate<K>,
+{
+    let mut applied = 0;
+    let mut stopping = false;
+    // The worker holds a sender too, so the channel only closes after it asked to stop
+    while !stopping {
+        let first = match receiver.recv() {
+            Ok(Message::Apply(command)) => command,
+            Ok(Message::Stop) | Err(_) => break,
+        };
+        let mut guard = cache.lock();
+        let mut removed = guard.invalidate(&first);
+        applied += 1;
+        for _ in 1..COMMAND_BATCH {
+            match receiver.try_recv() {
+                Ok(Message::Apply(command)) => {
+                    removed += guard.invalidate(&command);
+                    applied += 1;
+                }
+                Ok(Message::Stop) => {
+                    stopping = true;
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+        drop(guard);
+        // A batch that removed entries counts as one mutation, so `Persistence` saves it
+        if removed > 0 {
+            cache.mutated();
+        }
+    }
+    applied
+}
+
+// The thread started by `listen`. Dropping it stops the thread too.
+pub struct InvalidationWorker<K> {
+    sender: Sender<Message<K>>,
+    handle: Option<JoinHandle<u64>>,
+}
+
+impl<K> InvalidationWorker<K> {
+    // Waits for the commands sent before this call to be applied, and returns how many
+    // commands the thread applied in all.
+    pub fn stop(mut self) -> u64 {
+        self.shut_down().unwrap_or(0)
+    }
+
+    fn shut_down(&mut self) -> Option<u64> {
+        let handle = self.handle.take()?;
+        let _ = self.sender.send(Message::Stop);
+        // A panic in the worker was already reported on its thread
+        handle.join().ok()
+    }
+}
+
+impl<K> Drop for InvalidationWorker<K> {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Policy;
+
+    #[test]
+    fn test_listener_applies_commands_in_order() {
+        let cache = Arc::new(SyncCache::new(CacheType::new(Policy::LRU, 10)));
+        for key in 0..5 {
+            cache.put(key, key * 10, 1);
+        }
+        let (invalidator, worker) = listen(cache.clone());
+        let remote = invalidator.clone();
+        let relay = thread::spawn(move || {
+            assert!(remote.send(Invalidation::Key(1)));
+            assert!(remote.send(Invalidation::Key(3)));
+        });
+        relay.join().unwrap();
+        // Commands a plain policy cannot apply are skipped
+        assert!(invalidator.send(Invalidation::Epoch(7)));
+
+        assert_eq!(worker.stop(), 3);
+        assert_eq!(cache.len(), 3);
+        assert!(!cache.contains_key(&1));
+        assert!(!cache.contains_key(&3));
+        assert!(cache.mutations() > 5);
+        assert!(!invalidator.send(Invalidation::All));
+        assert_eq!(Arc::strong_count(&cache), 1);
+    }
+
+    #[test]
+    fn test_prefixes_and_epochs_reach_the_caches_that_know_them() {
+        let mut pages = PrefixCache::new(CacheType::new(Policy::LRU, 10));
+        for key in &["q:rust page:1", "q:rust page:2", "q:go page:1"] {
+            pages.put(key.to_string(), 1, 1);
+        }
+        assert_eq!(
+            pages.invalidate(&Invalidation::Prefix("q:rust ".to_string())),
+            2
+        );
+        assert_eq!(pages.invalidate(&Invalidation::All), 1);
+
+        let cache = Arc::new(SyncCache::new(EpochCache::new(CacheType::new(
+            Policy::LRU,
+            10,
+        ))));
+        cache.put("rust", 1, 1);
+        let (invalidator, worker) = listen(cache.clone());
+        invalidator.send(Invalidation::Epoch(1));
+        drop(worker);
+        assert_eq!(cache.lock().min_epoch(), 1);
+        assert_eq!(cache.get(&"rust"), None);
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::hash::Hash;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::observer::CacheObserver;
+use crate::snapshot::crc::Crc32;
+use crate::snapshot::{Decode, Encode, SnapshotError};
+use crate::sync_cache::lock;
+use crate::Cache;
+
+const MAGIC: [u8; 4] = *b"SECJ";
+const VERSION: u16 = 1;
+const HEADER_LEN: u64 = 6;
+
+// An append-only log of inserted keys, a lighter alternative to full snapshots: values
+// are not written, but loaded again on startup by `warm_from_journal`. Each record is a
+// key and an optional weight with a CRC-32 of its own, so a record torn by a crash ends
+// the journal without hiding the ones before it.
+//
+// Clones share the file. Set one as the cache's observer to journal every insert, and
+// keep another to `flush` it. The journal only grows; start a new one with `create` once
+// the cache has been warmed from it.
+pub struct Journal<K> {
+    writer: Arc<Mutex<Writer>>,
+    key: PhantomData<fn(&K)>,
+}
+
+struct Writer {
+    file: BufWriter<File>,
+    // The first write that failed. Later records are dropped rather than written after a gap.
+    error: Option<io::Error>,
+}
+
+impl<K> Clone for Journal<K> {
+    fn clone(&self) -> Self {
+        Journal {
+            writer: self.writer.clone(),
+            key: PhantomData,
+        }
+    }
+}
+
+impl<K> Journal<K> {
+    // Starts an empty journal, replacing any file at `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        file.write_all(&MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        Ok(Journal::from_file(file))
+    }
+
+    // Appends to the journal at `path`, or starts one if there is none. A torn record at
+    // the end is cut off first, so new records are not hidden behind it.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Journal::create(path)?);
+        }
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let end = {
+            let mut reader = BufReader::new(&mut file);
+            check_header(&mut reader)?;
+            let mut end = HEADER_LEN;
+            while let Some(record) = read_record(&mut reader)? {
+                end += record_len(&record);
+            }
+            end
+        };
+        file.set_len(end)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(Journal::from_file(file))
+    }
+
+    fn from_file(file: File) -> Self {
+        Journal {
+            writer: Arc::new(Mutex::new(Writer {
+                file: BufWriter::new(file),
+                error: None,
+            })),
+            key: PhantomData,
+        }
+    }
+
+    // Writes out buffered records, or returns the error that stopped the journal.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut writer = lock(&self.writer);
+        if let Some(error) = writer.error.take() {
+            return Err(error);
+        }
+        writer.file.flush()
+    }
+}
+
+impl<K: Encode> Journal<K> {
+    // Appends a record directly, for inserts the observer does not see the weight of.
+    pub fn record(&self, key: &K, weight: Option<u32>) {
+        let mut body = Vec::new();
+        let encoded = (key, weight).encode(&mut body);
+        let mut writer = lock(&self.writer);
+        if writer.error.is_some() {
+            return;
+        }
+        let mut crc = Crc32::new();
+        crc.update(&body);
+        let written = encoded
+            .and_then(|()| body.encode(&mut writer.file))
+            .and_then(|()| crc.finish().encode(&mut writer.file));
+        if let Err(error) = written {
+            writer.error = Some(error);
+        }
+    }
+}
+
+impl<K: Encode> CacheObserver<K> for Journal<K> {
+    fn on_insert(&mut self, key: &K) {
+        self.record(key, None);
+    }
+}
+
+// Replays a journal into `cache`, oldest insert first, loading each value with `loader`.
+// A key journaled more than once is loaded once, in the place of its last insert. Keys
+// already cached, and keys the loader returns `None` for, are skipped. Entries journaled
+// without a weight are put with a weight of 1. Returns how many entries were loaded.
+pub fn warm_from_journal<K, V, C, P, F>(
+    cache: &mut C,
+    path: P,
+    mut loader: F,
+) -> Result<usize, SnapshotError>
+where
+    K: Hash + Eq + Clone + Decode,
+    C: Cache<K, V> + ?Sized,
+    P: AsRef<Path>,
+    F: FnMut(&K) -> Option<V>,
+{
+    let mut reader = BufReader::new(File::open(path)?);
+    check_header(&mut reader)?;
+    let mut records = Vec::new();
+    while let Some(record) = read_record(&mut reader)? {
+        let (key, weight) = <(K, Option<u32>)>::decode(&mut record.as_slice())?;
+        records.push((key, weight));
+    }
+
+    let last_insert: HashMap<K, usize> = records
+        .iter()
+        .enumerate()
+        .map(|(position, (key, _))| (key.clone(), position))
+        .collect();
+    let mut loaded = 0;
+    for (position, (key, weight)) in records.into_iter().enumerate() {
+        if last_insert[&key] != position || cache.contains_key(&key) {
+            continue;
+        }
+        if let Some(value) = loader(&key) {
+            cache.put(key, value, weight.unwrap_or(1));
+            loaded += 1;
+        }
+    }
+    Ok(loaded)
+}
+
+fn check_header<R: Read>(reader: &mut R) -> Result<(), SnapshotError> {
+    let mut magic = [0; 4];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|error| match error.kind() {
+            io::ErrorKind::UnexpectedEof => SnapshotError::NotASnapshot,
+            _ => SnapshotError::Io(error),
+        })?;
+    if magic != MAGIC {
+        return Err(SnapshotError::NotASnapshot);
+    }
+    match u16::decode(reader)? {
+        VERSION => Ok(()),
+        version => Err(SnapshotError::UnsupportedVersion(version)),
+    }
+}
+
+// The next intact record, or `None` at the end of the journal or at a torn record.
+fn read_record<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, SnapshotError> {
+    let torn = |error: SnapshotError| match error {
+        SnapshotError::Io(error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        error => Err(error),
+    };
+    let body = match Vec::<u8>::decode(reader) {
+        Ok(body) => body,
+        Err(error) => return torn(error),
+    };
+    let checksum = match u32::decode(reader) {
+        Ok(checksum) => checksum,
+        Err(error) => return torn(error),
+    };
+    let mut crc = Crc32::new();
+    crc.update(&body);
+    Ok(Some(body).filter(|_| crc.finish() == checksum))
+}
+
+// A record's length prefix, body and checksum.
+fn record_len(body: &[u8]) -> u64 {
+    8 + body.len() as u64 + 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CacheType, Policy};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("journal-{}-{}", process::id(), name))
+    }
+
+    #[test]
+    fn test_warms_a_cache_from_journaled_inserts() {
+        let path = scratch("warm");
+        let journal = Journal::create(&path).unwrap();
+        let mut cache = CacheType::new(Policy::LRU, 2);
+        cache.set_observer(journal.clone());
+        for key in [1, 2, 3, 1, 3] {
+            cache.put(key, key * 10, 1);
+        }
+        journal.record(&5, Some(2));
+        journal.flush().unwrap();
+
+        let mut loads = Vec::new();
+        let mut warmed = CacheType::new(Policy::LRU, 3);
+        warmed.put(5, 0, 1);
+        let loaded = warm_from_journal(&mut warmed, &path, |&key| {
+            loads.push(key);
+            Some(key * 100).filter(|_| key != 3)
+        })
+        .unwrap();
+        // 1 was evicted and inserted again, 3 was only updated, and 5 was already cached
+        assert_eq!(loads, vec![2, 3, 1]);
+        assert_eq!(loaded, 2);
+        assert_eq!(warmed.peek(&1), Some(&100));
+        assert_eq!(warmed.peek(&5), Some(&0));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reopening_cuts_off_a_torn_record() {
+        let path = scratch("torn");
+        let journal = Journal::create(&path).unwrap();
+        journal.record(&"kept".to_string(), Some(3));
+        journal.record(&"torn".to_string(), None);
+        journal.flush().unwrap();
+        drop(journal);
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 2)
+            .unwrap();
+
+        let journal = Journal::open(&path).unwrap();
+        journal.record(&"appended".to_string(), None);
+        journal.flush().unwrap();
+
+        let mut cache: CacheType<String, usize> = CacheType::new(Policy::FIFO, 4);
+        let loaded = warm_from_journal(&mut cache, &path, |key| Some(key.len())).unwrap();
+        assert_eq!(loaded, 2);
+        assert_eq!(cache.peek(&"kept".to_string()), Some(&4));
+        assert!(cache.contains_key(&"appended".to_string()));
+        assert_eq!(cache.stats().current_weight, 4);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        let path = scratch("other");
+        fs::write(&path, b"not a journal").unwrap();
+        let mut cache: CacheType<u32, u32> = CacheType::new(Policy::LRU, 1);
+        let result = warm_from_journal(&mut cache, &path, |_| Some(0));
+        assert!(matches!(result, Err(SnapshotError::NotASnapshot)));
+        assert!(matches!(
+            Journal::<u32>::open(&path),
+            Err(SnapshotError::NotASnapshot)
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+}
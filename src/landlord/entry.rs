@@ -0,0 +1,237 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+use super::Landlord;
+use crate::weight::Weight;
+
+pub enum Entry<'a, K, V, S = RandomState, W = u32> {
+    Occupied(OccupiedEntry<'a, K, V, S, W>),
+    Vacant(VacantEntry<'a, K, V, S, W>),
+}
+
+pub struct OccupiedEntry<'a, K, V, S = RandomState, W = u32> {
+    pub(super) cache: &'a mut Landlord<K, V, S, W>,
+    pub(super) key: K,
+}
+
+pub struct VacantEntry<'a, K, V, S = RandomState, W = u32> {
+    pub(super) cache: &'a mut Landlord<K, V, S, W>,
+    pub(super) key: K,
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher + Clone, W: Weight> Entry<'a, K, V, S, W> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, value: V, weight: W) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(value, weight),
+        }
+    }
+
+    pub fn or_insert_sized(self, value: V, cost: W, size: W) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert_sized(value, cost, size),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, weight: W, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(f(), weight),
+        }
+    }
+
+    // Computes both the value and its weight from the key on a miss.
+    pub fn or_insert_with_key<F: FnOnce(&K) -> (V, W)>(self, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let (value, weight) = f(entry.key());
+                entry.insert(value, weight)
+            }
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher + Clone, W: Weight> OccupiedEntry<'a, K, V, S, W> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.cache.cache[&self.key].value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self
+            .cache
+            .cache
+            .get_mut(&self.key)
+            .expect("occupied entry is present")
+            .value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self
+            .cache
+            .cache
+            .get_mut(&self.key)
+            .expect("occupied entry is present")
+            .value
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        let now = self.cache.clock.now();
+        let landlord_node = self
+            .cache
+            .cache
+            .get_mut(&self.key)
+            .expect("occupied entry is present");
+        landlord_node.timestamps.rewrite(now);
+        let previous = mem::replace(&mut landlord_node.value, value);
+        let key = &self.key;
+        self.cache.notify(|observer| observer.on_update(key));
+        previous
+    }
+
+    pub fn remove(self) -> V {
+        self.cache
+            .remove(&self.key)
+            .expect("occupied entry is present")
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher + Clone, W: Weight> VacantEntry<'a, K, V, S, W> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V, weight: W) -> &'a mut V {
+        self.insert_sized(value, weight, weight)
+    }
+
+    // Like `Landlord::put_sized`, credits the entry with its cost and charges its size.
+    pub fn insert_sized(self, value: V, cost: W, size: W) -> &'a mut V {
+        self.cache.insert_new(self.key.clone(), value, cost, size);
+        &mut self
+            .cache
+            .cache
+            .get_mut(&self.key)
+            .expect("entry was just inserted")
+            .value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Landlord;
+    use super::Entry;
+
+    #[test]
+    fn test_or_insert_on_vacant_and_occupied() {
+        let mut cache = Landlord::new(2);
+
+        *cache.entry("a").or_insert(0, 10) += 1;
+        *cache.entry("a").or_insert(0, 10) += 1;
+
+        assert_eq!(cache.peek(&"a"), Some(&2));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_or_insert_with_key_computes_weight() {
+        let mut cache = Landlord::new(2);
+        cache.put(1, 1, 5);
+
+        cache
+            .entry(20)
+            .or_insert_with_key(|&key| (key * 2, key as u32));
+        assert_eq!(cache.peek(&20), Some(&40));
+
+        // Key 20 has credit 20, key 1 only 5
+        cache.put(3, 3, 10);
+        assert!(!cache.contains_key(&1));
+        assert!(cache.contains_key(&20));
+    }
+
+    #[test]
+    fn test_occupied_entry_refreshes_credit() {
+        let mut cache = Landlord::new(2);
+        cache.put("key1", 100, 10);
+        cache.put("key2", 200, 5);
+        cache.put("key3", 300, 5); // evicts key2, l = 5, credit 10
+
+        // Refresh key1 to l + 10 = 15, above key3
+        cache.entry("key1").and_modify(|v| *v += 1);
+        cache.put("key4", 400, 20);
+
+        assert_eq!(cache.peek(&"key1"), Some(&101));
+        assert!(!cache.contains_key(&"key3"));
+    }
+
+    #[test]
+    fn test_occupied_insert_and_remove() {
+        let mut cache = Landlord::new(2);
+        cache.put(1, "one", 10);
+
+        match cache.entry(1) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.get(), &"one");
+                assert_eq!(entry.insert("ONE"), "one");
+                assert_eq!(entry.remove(), "ONE");
+            }
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_or_insert_sized_charges_size_and_credits_cost() {
+        let mut cache = Landlord::new(10);
+        cache.set_max_weight(4);
+        cache.entry("cheap").or_insert_sized(1, 1, 1);
+        cache.entry("costly").or_insert_sized(2, 50, 3);
+        assert_eq!(cache.stats().current_weight, 4);
+        assert_eq!(cache.entry_info(&"costly").unwrap().weight, 3);
+
+        // Only the size counts against the budget, and the low cost goes first
+        cache.entry("next").or_insert_sized(3, 20, 1);
+        assert!(!cache.contains_key(&"cheap"));
+        assert!(cache.contains_key(&"costly"));
+    }
+
+    #[test]
+    fn test_vacant_insert_evicts() {
+        let mut cache = Landlord::new(1);
+        cache.put(1, "one", 10);
+
+        match cache.entry(2) {
+            Entry::Vacant(entry) => entry.insert("two", 10),
+            Entry::Occupied(_) => panic!("expected a vacant entry"),
+        };
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.peek(&2), Some(&"two"));
+    }
+}
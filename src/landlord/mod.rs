@@ -1,52 +1,489 @@
-use priority_queue::PriorityQueue;
 use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
 
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-pub struct LandlordNode<V> {
+use crate::clock::{timed_cost, Clock, SystemClock};
+use crate::expiry::{Expiration, Freshness, Timestamps, TimingWheel, WHEEL_RESOLUTION};
+use crate::observer::{BoxedObserver, CacheObserver};
+use crate::stats::{CacheStats, EntryInfo};
+use crate::trace;
+use crate::util::PriorityHeap;
+use crate::weight::{Ordered, Weight};
+use crate::{Cache, EvictionListener, NewCache, Weigher};
+
+mod entry;
+mod persist;
+
+const POLICY: &str = "landlord";
+
+pub use self::entry::{Entry, OccupiedEntry, VacantEntry};
+
+// How eviction charges rent to the entries that stay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rent {
+    // Every resident pays the same rent, so credit is spent per entry whatever its size.
+    #[default]
+    Uniform,
+    // Residents pay rent in proportion to their size, as in Young's Landlord: eviction
+    // ranks entries by credit per unit of size.
+    Proportional,
+}
+
+pub struct LandlordNode<V, W = u32> {
     value: V,
-    weight: u32,
+    weight: W,
+    cost: W,
+    timestamps: Timestamps,
+}
+
+pub struct Iter<'a, K, V> {
+    entries: std::vec::IntoIter<(&'a K, &'a V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+pub struct IntoIter<K, V> {
+    entries: std::vec::IntoIter<(K, V)>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
 }
 
-pub struct Landlord<K, V> {
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+// Generic over the weight type `W`, so costs can be fractional; construct a non-default
+// weight type with `with_hasher`. Credit is kept as `f64` whatever `W` is, so that
+// proportional rent does not round an integer cost per size away.
+pub struct Landlord<K, V, S = RandomState, W = u32> {
     capacity: usize,
-    l: u32,
-    pq: PriorityQueue<K, Reverse<u32>>,
-    cache: HashMap<K, LandlordNode<V>>,
+    max_weight: Option<u64>,
+    l: f64,
+    rent: Rent,
+    refresh_factor: f64,
+    pq: PriorityHeap<K, Reverse<Ordered<f64>>, S>,
+    cache: HashMap<K, LandlordNode<V, W>, S>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    weigher: Option<Weigher<K, V, W>>,
+    observer: Option<BoxedObserver<K>>,
+    stats: CacheStats,
+    expiration: Expiration,
+    expiry_wheel: Option<TimingWheel<K>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Rent {
+    // The credit an entry holds after a write or a hit, relative to `l`. A zero size pays
+    // no rent.
+    fn credit<V, W: Weight>(self, landlord_node: &LandlordNode<V, W>) -> f64 {
+        let cost = landlord_node.cost.to_f64();
+        match self {
+            Rent::Uniform => cost,
+            Rent::Proportional if landlord_node.weight == W::default() => cost,
+            Rent::Proportional => cost / landlord_node.weight.to_f64(),
+        }
+    }
 }
 
 impl<K: Clone + Hash + Eq, V> Landlord<K, V> {
     pub fn new(capacity: usize) -> Self {
+        Landlord::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone, W: Weight> Landlord<K, V, S, W> {
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
         assert!(capacity > 0, "Capacity must be greater than 0");
         Landlord {
             capacity,
-            l: 0,
-            pq: PriorityQueue::new(),
-            cache: HashMap::new(),
+            max_weight: None,
+            l: 0.0,
+            rent: Rent::default(),
+            refresh_factor: 1.0,
+            pq: PriorityHeap::with_hasher(hash_builder.clone()),
+            cache: HashMap::with_hasher(hash_builder),
+            eviction_listener: None,
+            weigher: None,
+            observer: None,
+            stats: CacheStats::default(),
+            expiration: Expiration::default(),
+            expiry_wheel: None,
+            clock: Arc::new(SystemClock),
         }
     }
 
     pub fn get(&mut self, key: &K) -> Option<&V> {
-        if let Some(landlord_node) = self.cache.get(&key) {
-            let new_priority = self.l + landlord_node.weight;
-            self.pq.change_priority(key, Reverse(new_priority));
-            Some(&landlord_node.value)
-        } else {
-            None
+        let _span = trace::get_span(POLICY, key);
+        if !self.is_live(key) {
+            return None;
         }
+        self.refresh(key);
+        self.cache
+            .get(key)
+            .map(|landlord_node| &landlord_node.value)
+    }
+
+    pub fn get_stale(&mut self, key: &K) -> Option<Freshness<&V>> {
+        let now = self.clock.now();
+        let expired = self.cache.get(key).is_some_and(|landlord_node| {
+            self.expiration.is_expired(&landlord_node.timestamps, now)
+        });
+        if !expired {
+            return self.get(key).map(Freshness::Fresh);
+        }
+        self.stats.misses += 1;
+        trace::miss(POLICY, key);
+        self.notify(|observer| observer.on_miss(key));
+        self.cache
+            .get(key)
+            .map(|landlord_node| Freshness::Stale(&landlord_node.value))
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if !self.is_live(key) {
+            return None;
+        }
+        self.refresh(key);
+        self.cache
+            .get_mut(key)
+            .map(|landlord_node| &mut landlord_node.value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        let now = self.clock.now();
+        self.cache
+            .get(key)
+            .filter(|landlord_node| !self.expiration.is_expired(&landlord_node.timestamps, now))
+            .map(|landlord_node| &landlord_node.value)
+    }
+
+    // Like `peek`, this does not count as a use of the key.
+    pub fn entry_info(&self, key: &K) -> Option<EntryInfo<W>> {
+        let now = self.clock.now();
+        self.cache
+            .get(key)
+            .filter(|landlord_node| !self.expiration.is_expired(&landlord_node.timestamps, now))
+            .map(|landlord_node| {
+                EntryInfo::new(
+                    &landlord_node.timestamps,
+                    landlord_node.weight,
+                    &self.expiration,
+                )
+            })
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
     }
 
-    pub fn put(&mut self, key: K, value: V, weight: u32) {
+    pub fn put(&mut self, key: K, value: V, weight: W) {
+        self.put_sized(key, value, weight, weight);
+    }
+
+    // Stores an entry whose cost, the credit it is given, differs from its size, the weight
+    // it takes up. `put` uses the weight for both.
+    pub fn put_sized(&mut self, key: K, value: V, cost: W, size: W) {
+        let _span = trace::put_span(POLICY, &key, size);
         if self.cache.contains_key(&key) {
+            self.rewrite(&key, value, cost, size);
+            self.notify(|observer| observer.on_update(&key));
+        } else {
+            self.insert_new(key, value, cost, size);
+        }
+    }
+
+    // Stores the value with the weight the weigher gives it, or a weight of 1 without one.
+    pub fn insert(&mut self, key: K, value: V) {
+        let weight = self
+            .weigher
+            .as_ref()
+            .map_or(W::UNIT, |weigher| weigher(&key, &value));
+        self.put(key, value, weight);
+    }
+
+    // An occupied entry counts as a hit and refreshes the credit, just like `get`.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S, W> {
+        if !self.is_live(&key) {
+            return Entry::Vacant(VacantEntry { cache: self, key });
+        }
+        if self.refresh(&key) {
+            Entry::Occupied(OccupiedEntry { cache: self, key })
+        } else {
+            Entry::Vacant(VacantEntry { cache: self, key })
+        }
+    }
+
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, weight: W, f: F) -> &V {
+        self.entry(key).or_insert_with(weight, f)
+    }
+
+    // Like `get_or_insert_with`, but `f` is timed with the cache's clock and the
+    // milliseconds it took become the entry's cost, while `size` is what it weighs. Results
+    // that were slow to compute are given more credit, so they outlast cheap ones.
+    pub fn get_or_insert_timed<F: FnOnce() -> V>(&mut self, key: K, size: W, f: F) -> &V {
+        let clock = Arc::clone(&self.clock);
+        match self.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let (value, millis) = timed_cost(clock.as_ref(), f);
+                entry.insert_sized(value, W::UNIT.scale(millis), size)
+            }
+        }
+    }
+
+    // A failed computation leaves the cache untouched and returns the error.
+    pub fn try_get_or_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        weight: W,
+        f: F,
+    ) -> Result<&V, E> {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(f()?, weight)),
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let landlord_node = self.cache.remove(key)?;
+        self.pq.remove(key);
+        self.stats.current_weight -= landlord_node.weight.to_u64();
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.cancel(key);
+        }
+        Some(landlord_node.value)
+    }
+
+    // The entry `pop_victim` would remove, left in place.
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.pq.peek().map(|(key, _)| key)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        let (key, Reverse(Ordered(priority))) = self.pq.pop()?;
+        self.l = priority;
+        let landlord_node = self.cache.remove(&key)?;
+        self.stats.current_weight -= landlord_node.weight.to_u64();
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.cancel(&key);
+        }
+        Some((key, landlord_node.value))
+    }
+
+    // Evicts entries as a full cache would, listener and stats included, until at most
+    // `len` are left. Returns how many were evicted.
+    pub fn evict_to(&mut self, len: usize) -> usize {
+        let mut evicted = 0;
+        while self.len() > len {
+            self.evict();
+            evicted += 1;
+        }
+        evicted
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        let doomed: Vec<K> = self
+            .cache
+            .iter()
+            .filter(|&(key, landlord_node)| !f(key, &landlord_node.value))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in doomed {
             self.remove(&key);
         }
-        if self.cache.len() >= self.capacity {
+    }
+
+    pub fn set_max_weight(&mut self, max_weight: u64) {
+        self.max_weight = Some(max_weight);
+        while !self.is_empty() && self.is_over_budget() {
             self.evict();
         }
+    }
+
+    // Existing entries are given their full credit under the new rent.
+    pub fn set_rent(&mut self, rent: Rent) {
+        self.rent = rent;
+        for (key, landlord_node) in &self.cache {
+            let priority = self.l + rent.credit(landlord_node);
+            self.pq.change_priority(key, Reverse(Ordered(priority)));
+        }
+    }
+
+    pub fn rent(&self) -> Rent {
+        self.rent
+    }
+
+    // How far a hit restores an entry's credit towards its full cost: 1 restores all of it,
+    // behaving like LRU among equal costs, while 0 leaves credit as it is, like FIFO.
+    pub fn set_refresh_factor(&mut self, factor: f64) {
+        assert!(
+            (0.0..=1.0).contains(&factor),
+            "Refresh factor must be between 0 and 1"
+        );
+        self.refresh_factor = factor;
+    }
+
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.set_boxed_eviction_listener(Box::new(listener));
+    }
+
+    pub(crate) fn set_boxed_eviction_listener(&mut self, listener: EvictionListener<K, V>) {
+        self.eviction_listener = Some(listener);
+    }
+
+    // Computes the weight of entries stored with `insert`.
+    pub fn set_weigher<F: Fn(&K, &V) -> W + Send + 'static>(&mut self, weigher: F) {
+        self.set_boxed_weigher(Box::new(weigher));
+    }
+
+    pub(crate) fn set_boxed_weigher(&mut self, weigher: Weigher<K, V, W>) {
+        self.weigher = Some(weigher);
+    }
+
+    pub fn set_observer<O: CacheObserver<K> + Send + 'static>(&mut self, observer: O) {
+        self.set_boxed_observer(Box::new(observer));
+    }
+
+    pub(crate) fn set_boxed_observer(&mut self, observer: BoxedObserver<K>) {
+        self.observer = Some(observer);
+    }
+
+    pub fn set_expire_after_write(&mut self, time_to_live: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_live = Some(time_to_live);
+        self.set_expiration(expiration);
+    }
+
+    pub fn set_expire_after_access(&mut self, time_to_idle: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_idle = Some(time_to_idle);
+        self.set_expiration(expiration);
+    }
+
+    // Existing entries are scheduled against the new limits.
+    pub(crate) fn set_expiration(&mut self, expiration: Expiration) {
+        self.expiration = expiration;
+        self.expiry_wheel = if expiration.is_enabled() {
+            Some(TimingWheel::new(self.clock.now(), WHEEL_RESOLUTION))
+        } else {
+            None
+        };
+        let live: Vec<K> = self.cache.keys().cloned().collect();
+        for key in live {
+            self.schedule_expiry(key);
+        }
+    }
+
+    // Replaces the time source; entries keep the timestamps they were given by the old one.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+        let expiration = self.expiration;
+        self.set_expiration(expiration);
+    }
+
+    pub fn expire_due(&mut self, now: Instant) -> usize {
+        let due = match self.expiry_wheel.as_mut() {
+            Some(wheel) => wheel.advance(now),
+            None => return 0,
+        };
+
+        let mut expired = 0;
+        for key in due {
+            let expired_now = match self.cache.get(&key) {
+                Some(landlord_node) => self.expiration.is_expired(&landlord_node.timestamps, now),
+                None => continue,
+            };
+            if expired_now {
+                self.remove(&key);
+                expired += 1;
+            } else {
+                // Touched or rewritten since it was scheduled
+                self.schedule_expiry(key);
+            }
+        }
+        expired
+    }
+
+    pub fn clear(&mut self) {
+        self.pq.clear();
+        self.cache.clear();
+        self.l = 0.0;
+        self.stats.current_weight = 0;
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.clear();
+        }
+    }
+
+    // Releases the memory the queue and the map no longer need.
+    pub fn shrink_to_fit(&mut self) {
+        self.pq.shrink_to_fit();
+        self.cache.shrink_to_fit();
+    }
+
+    // Reserves room for a full cache. The map gets twice that, so the churn of evictions
+    // rehashes it in place instead of growing it.
+    pub fn preallocate(&mut self) {
+        self.pq.reserve(self.capacity);
         self.cache
-            .insert(key.clone(), LandlordNode { value, weight });
-        self.pq.push(key.clone(), Reverse(self.l + weight));
+            .reserve((2 * self.capacity).saturating_sub(self.cache.len()));
+    }
+
+    // Entries from highest credit to the next victim.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut by_credit: Vec<(&K, Ordered<f64>)> = self
+            .pq
+            .iter()
+            .map(|(key, &Reverse(priority))| (key, priority))
+            .collect();
+        by_credit.sort_by_key(|&(_, priority)| Reverse(priority));
+
+        let entries: Vec<(&K, &V)> = by_credit
+            .into_iter()
+            .map(|(key, _)| (key, &self.cache[key].value))
+            .collect();
+
+        Iter {
+            entries: entries.into_iter(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
     }
 
     pub fn len(&self) -> usize {
@@ -57,28 +494,273 @@ impl<K: Clone + Hash + Eq, V> Landlord<K, V> {
         self.pq.is_empty()
     }
 
-    fn remove(&mut self, key: &K) {
-        self.cache.remove(&key);
+    fn insert_new(&mut self, key: K, value: V, cost: W, size: W) {
+        self.stats.insertions += 1;
+        trace::insert(POLICY, &key, size);
+        self.notify(|observer| observer.on_insert(&key));
+        self.store(key, value, cost, size);
+    }
+
+    fn store(&mut self, key: K, value: V, cost: W, size: W) {
+        self.make_room(size);
+        let landlord_node = LandlordNode {
+            value,
+            weight: size,
+            cost,
+            timestamps: Timestamps::new(self.clock.now()),
+        };
+        let priority = self.l + self.rent.credit(&landlord_node);
+        self.cache.insert(key.clone(), landlord_node);
+        self.pq.push(key.clone(), Reverse(Ordered(priority)));
+        self.stats.current_weight += size.to_u64();
+        self.schedule_expiry(key);
+    }
+
+    // Updates a cached entry in place, keeping its timestamps, and gives it full credit
+    // for its new cost and size. A heavier value can push the cache over its budget; the
+    // key sits out of the queue while others are evicted to make room, so it is spared.
+    fn rewrite(&mut self, key: &K, value: V, cost: W, size: W) {
+        let now = self.clock.now();
+        let landlord_node = match self.cache.get_mut(key) {
+            Some(landlord_node) => landlord_node,
+            None => return,
+        };
+        let old_size = landlord_node.weight;
+        landlord_node.value = value;
+        landlord_node.weight = size;
+        landlord_node.cost = cost;
+        landlord_node.timestamps.rewrite(now);
+        self.stats.current_weight -= old_size.to_u64();
+        self.stats.current_weight += size.to_u64();
+
         self.pq.remove(key);
+        while !self.pq.is_empty() && self.is_over_budget() {
+            self.evict();
+        }
+        let credit = match self.cache.get(key) {
+            Some(landlord_node) => self.rent.credit(landlord_node),
+            None => return,
+        };
+        self.pq.push(key.clone(), Reverse(Ordered(self.l + credit)));
+    }
+
+    // Whether an entry of this weight only fits after an eviction.
+    fn is_full(&self, weight: W) -> bool {
+        match self.max_weight {
+            Some(max_weight) => self.stats.current_weight + weight.to_u64() > max_weight,
+            None => self.len() >= self.capacity,
+        }
+    }
+
+    fn is_over_budget(&self) -> bool {
+        self.max_weight
+            .is_some_and(|max_weight| self.stats.current_weight > max_weight)
+    }
+
+    fn make_room(&mut self, weight: W) {
+        while !self.is_empty() && self.is_full(weight) {
+            self.evict();
+        }
+    }
+
+    fn schedule_expiry(&mut self, key: K) {
+        let deadline = self
+            .cache
+            .get(&key)
+            .and_then(|landlord_node| self.expiration.deadline(&landlord_node.timestamps));
+        if let (Some(wheel), Some(deadline)) = (self.expiry_wheel.as_mut(), deadline) {
+            wheel.schedule(key, deadline);
+        }
+    }
+
+    // Drops the key if it has expired, otherwise records the access.
+    // This is the single place hits and misses are observed.
+    fn is_live(&mut self, key: &K) -> bool {
+        let now = self.clock.now();
+        let live = match self.cache.get_mut(key) {
+            Some(landlord_node) => {
+                let expired = self.expiration.is_expired(&landlord_node.timestamps, now);
+                if !expired {
+                    landlord_node.timestamps.touch(now);
+                }
+                !expired
+            }
+            None => false,
+        };
+        if live {
+            self.stats.hits += 1;
+            self.notify(|observer| observer.on_hit(key));
+        } else {
+            self.remove(key);
+            self.stats.misses += 1;
+            trace::miss(POLICY, key);
+            self.notify(|observer| observer.on_miss(key));
+        }
+        live
+    }
+
+    // Restores the credit of a hit entry by the refresh factor. Returns whether it is cached.
+    fn refresh(&mut self, key: &K) -> bool {
+        let full = match self.cache.get(key) {
+            Some(landlord_node) => self.rent.credit(landlord_node),
+            None => return false,
+        };
+        let credit = match self.pq.get_priority(key) {
+            Some(&Reverse(Ordered(priority))) if priority < self.l + full => {
+                let remaining = priority - self.l;
+                remaining + (full - remaining) * self.refresh_factor
+            }
+            _ => full,
+        };
+        self.pq
+            .change_priority(key, Reverse(Ordered(self.l + credit)));
+        true
     }
 
+    fn notify<F: FnOnce(&mut dyn CacheObserver<K>)>(&mut self, f: F) {
+        if let Some(observer) = self.observer.as_mut() {
+            f(&mut **observer);
+        }
+    }
+
+    // Capacity-driven eviction; the listener sees every entry dropped this way.
     fn evict(&mut self) {
-        if let Some(evicted_key) = self.pq.pop() {
-            self.l = evicted_key.1 .0;
-            self.cache.remove(&evicted_key.0);
+        if let Some((key, value)) = self.pop_victim() {
+            self.stats.evictions += 1;
+            trace::eviction(POLICY, &key);
+            if let Some(listener) = self.eviction_listener.as_mut() {
+                listener(key, value);
+            }
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone, W: Weight> IntoIterator
+    for Landlord<K, V, S, W>
+{
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        let mut cache = self.cache;
+        let mut by_credit: Vec<(K, Ordered<f64>)> = self
+            .pq
+            .into_iter()
+            .map(|(key, Reverse(priority))| (key, priority))
+            .collect();
+        by_credit.sort_by_key(|&(_, priority)| Reverse(priority));
+
+        let entries: Vec<(K, V)> = by_credit
+            .into_iter()
+            .filter_map(|(key, _)| {
+                let landlord_node = cache.remove(&key)?;
+                Some((key, landlord_node.value))
+            })
+            .collect();
+
+        IntoIter {
+            entries: entries.into_iter(),
         }
     }
 }
 
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher + Clone, W: Weight> IntoIterator
+    for &'a Landlord<K, V, S, W>
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+// The capacity is sized to hold every collected entry.
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone + Default, W: Weight> FromIterator<(K, V, W)>
+    for Landlord<K, V, S, W>
+{
+    fn from_iter<I: IntoIterator<Item = (K, V, W)>>(iter: I) -> Self {
+        let entries: Vec<_> = iter.into_iter().collect();
+        let mut cache = Landlord::with_hasher(entries.len().max(1), S::default());
+        cache.extend(entries);
+        cache
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone, W: Weight> Extend<(K, V, W)>
+    for Landlord<K, V, S, W>
+{
+    fn extend<I: IntoIterator<Item = (K, V, W)>>(&mut self, iter: I) {
+        for (key, value, weight) in iter {
+            self.put(key, value, weight);
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Cache<K, V> for Landlord<K, V, S> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone + Default> NewCache<K, V>
+    for Landlord<K, V, S>
+{
+    fn new(capacity: usize) -> Self {
+        Landlord::with_hasher(capacity, S::default())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::ManualClock;
 
     #[test]
     fn test_new_cache() {
         let cache: Landlord<String, i32> = Landlord::new(5);
         assert_eq!(cache.capacity, 5);
-        assert_eq!(cache.l, 0);
+        assert_eq!(cache.l, 0.0);
     }
 
     #[test]
@@ -150,13 +832,13 @@ mod tests {
     fn test_l_value_updates_on_eviction() {
         let mut cache = Landlord::new(2);
         cache.put("key1".to_string(), 100, 10);
-        assert_eq!(cache.l, 0);
+        assert_eq!(cache.l, 0.0);
 
         cache.put("key2".to_string(), 200, 20);
         cache.put("key3".to_string(), 300, 30); // Triggers eviction
 
         // After eviction, l should be updated to the priority of the evicted item
-        assert_eq!(cache.l, 10);
+        assert_eq!(cache.l, 10.0);
     }
 
     #[test]
@@ -175,7 +857,221 @@ mod tests {
     fn test_evict_on_empty_cache() {
         let mut cache: Landlord<String, i32> = Landlord::new(3);
         cache.evict(); // Should not panic
-        assert_eq!(cache.l, 0);
+        assert_eq!(cache.l, 0.0);
+    }
+
+    #[test]
+    fn test_fractional_costs() {
+        let mut cache: Landlord<&str, i32, RandomState, f64> =
+            Landlord::with_hasher(2, RandomState::new());
+        cache.put("fast", 1, 0.4);
+        cache.put("slow", 2, 0.6);
+        cache.put("slowest", 3, 0.9);
+
+        // The cheaper query goes first and its exact credit is charged to the rest
+        assert!(!cache.contains_key(&"fast"));
+        assert_eq!(cache.l, 0.4);
+        assert_eq!(cache.entry_info(&"slow").unwrap().weight, 0.6);
+
+        cache.insert("unit", 4);
+        assert_eq!(cache.entry_info(&"unit").unwrap().weight, 1.0);
+        assert!(!cache.contains_key(&"slow"));
+    }
+
+    #[test]
+    fn test_wide_integer_costs() {
+        let mut cache: Landlord<i32, &str, RandomState, u64> =
+            Landlord::with_hasher(2, RandomState::new());
+        let heavy = u64::from(u32::MAX) * 4;
+        cache.put(1, "one", heavy);
+        cache.put(2, "two", heavy + 1);
+        cache.put(3, "three", heavy + 2);
+
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.l, heavy as f64);
+        assert_eq!(cache.stats().current_weight, 2 * heavy + 3);
+    }
+
+    #[test]
+    fn test_proportional_rent_ranks_by_credit_per_size() {
+        let mut uniform: Landlord<&str, i32> = Landlord::new(10);
+        let mut proportional: Landlord<&str, i32> = Landlord::new(10);
+        proportional.set_rent(Rent::Proportional);
+        for cache in [&mut uniform, &mut proportional] {
+            cache.set_max_weight(4);
+            cache.put_sized("big", 1, 4, 2);
+            cache.put_sized("small", 2, 3, 1);
+            cache.put_sized("new", 3, 1, 2);
+        }
+
+        // Uniform rent drops the lower credit; proportional rent the lower credit per size
+        assert!(!uniform.contains_key(&"small"));
+        assert!(uniform.contains_key(&"big"));
+        assert!(!proportional.contains_key(&"big"));
+        assert!(proportional.contains_key(&"small"));
+        assert_eq!(proportional.l, 2.0);
+        assert_eq!(proportional.stats().current_weight, 3);
+    }
+
+    #[test]
+    fn test_fractional_proportional_credit() {
+        let mut whole: Landlord<&str, i32> = Landlord::new(2);
+        let mut fractional: Landlord<&str, i32, RandomState, f64> =
+            Landlord::with_hasher(2, RandomState::new());
+        whole.set_rent(Rent::Proportional);
+        fractional.set_rent(Rent::Proportional);
+
+        // 7 / 4 and 5 / 4 would both round down to 1 in integer arithmetic
+        whole.put_sized("slow", 1, 7, 4);
+        whole.put_sized("fast", 2, 5, 4);
+        whole.put_sized("new", 3, 1, 1);
+        fractional.put_sized("slow", 1, 7.0, 4.0);
+        fractional.put_sized("fast", 2, 5.0, 4.0);
+        fractional.put_sized("new", 3, 1.0, 1.0);
+
+        for (fast, slow, l) in [
+            (
+                whole.contains_key(&"fast"),
+                whole.contains_key(&"slow"),
+                whole.l,
+            ),
+            (
+                fractional.contains_key(&"fast"),
+                fractional.contains_key(&"slow"),
+                fractional.l,
+            ),
+        ] {
+            assert!(!fast);
+            assert!(slow);
+            assert_eq!(l, 1.25);
+        }
+        assert_eq!(
+            whole.pq.get_priority(&"slow"),
+            Some(&Reverse(Ordered(1.75)))
+        );
+    }
+
+    #[test]
+    fn test_refresh_factor() {
+        for (factor, survivor) in [(1.0, "b"), (0.0, "c")] {
+            let mut cache = Landlord::new(2);
+            cache.set_refresh_factor(factor);
+            cache.put("a", 1, 5);
+            cache.put("b", 2, 10);
+            cache.put("c", 3, 8); // evicts a, l = 5, b has 5 of its 10 left
+
+            cache.get(&"b");
+            cache.put("d", 4, 10);
+            assert!(cache.contains_key(&survivor), "{}", factor);
+        }
+    }
+
+    #[test]
+    fn test_partial_refresh_is_fractional() {
+        let mut cache: Landlord<&str, i32, RandomState, f64> =
+            Landlord::with_hasher(2, RandomState::new());
+        cache.set_refresh_factor(0.5);
+        cache.put("a", 1, 5.0);
+        cache.put("b", 2, 10.0);
+        cache.put("c", 3, 8.0);
+
+        // Half of the 5 spent comes back
+        cache.get(&"b");
+        assert_eq!(cache.pq.get_priority(&"b"), Some(&Reverse(Ordered(12.5))));
+    }
+
+    #[test]
+    #[should_panic(expected = "Refresh factor must be between 0 and 1")]
+    fn test_refresh_factor_out_of_range() {
+        let mut cache: Landlord<&str, i32> = Landlord::new(2);
+        cache.set_refresh_factor(1.5);
+    }
+
+    #[test]
+    fn test_set_rent_recredits_residents() {
+        let mut cache: Landlord<&str, i32> = Landlord::new(2);
+        cache.put_sized("big", 1, 4, 2);
+        cache.put_sized("small", 2, 3, 1);
+        assert_eq!(cache.peek_victim(), Some(&"small"));
+
+        cache.set_rent(Rent::Proportional);
+        assert_eq!(cache.rent(), Rent::Proportional);
+        assert_eq!(cache.peek_victim(), Some(&"big"));
+    }
+
+    // Young's Landlord as published: on each eviction every resident pays rent of delta
+    // times its size, where delta is the least credit per size, and a hit restores the
+    // full cost.
+    struct ReferenceLandlord {
+        max_weight: f64,
+        // (key, credit, cost, size)
+        residents: Vec<(u32, f64, f64, f64)>,
+    }
+
+    impl ReferenceLandlord {
+        fn access(&mut self, key: u32, cost: f64, size: f64, evicted: &mut Vec<u32>) {
+            if let Some(resident) = self.residents.iter_mut().find(|r| r.0 == key) {
+                resident.1 = resident.2;
+                return;
+            }
+            while !self.residents.is_empty()
+                && self.residents.iter().map(|r| r.3).sum::<f64>() + size > self.max_weight
+            {
+                let delta = self
+                    .residents
+                    .iter()
+                    .map(|r| r.1 / r.3)
+                    .fold(f64::INFINITY, f64::min);
+                for resident in &mut self.residents {
+                    resident.1 -= delta * resident.3;
+                }
+                let victim = self
+                    .residents
+                    .iter()
+                    .position(|r| r.1 / r.3 <= 1e-9)
+                    .unwrap();
+                evicted.push(self.residents.remove(victim).0);
+            }
+            self.residents.push((key, cost, cost, size));
+        }
+    }
+
+    #[test]
+    fn test_proportional_rent_matches_reference() {
+        use std::sync::Mutex;
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache: Landlord<u32, (), RandomState, f64> =
+            Landlord::with_hasher(100, RandomState::new());
+        cache.set_rent(Rent::Proportional);
+        cache.set_max_weight(20);
+        cache.set_eviction_listener(move |key, _| sink.lock().unwrap().push(key));
+
+        let mut reference = ReferenceLandlord {
+            max_weight: 20.0,
+            residents: Vec::new(),
+        };
+        let mut expected = Vec::new();
+
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        for _ in 0..1000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let key = (state % 30) as u32;
+            // Costs whose credit per size never ties, so the eviction order is unambiguous
+            let cost = 1.0 + f64::from(key).ln_1p();
+            let size = f64::from(1 + key % 5);
+
+            if cache.get(&key).is_none() {
+                cache.put_sized(key, (), cost, size);
+            }
+            reference.access(key, cost, size, &mut expected);
+        }
+
+        assert!(expected.len() > 100);
+        assert_eq!(*evicted.lock().unwrap(), expected);
     }
 
     #[test]
@@ -203,6 +1099,286 @@ mod tests {
         assert_eq!(cache.get(&"key1".to_string()), Some(&200));
     }
 
+    #[test]
+    fn test_rewrite_keeps_timestamps() {
+        let (mut cache, clock) = manual_clock_cache(3);
+        cache.set_max_weight(10);
+        cache.put(1, "one", 4);
+        cache.put(2, "two", 4);
+        cache.get(&1);
+        let inserted_at = cache.entry_info(&1).unwrap().inserted_at;
+
+        clock.advance(Duration::from_secs(5));
+        cache.put_sized(1, "uno", 1, 8);
+        let info = cache.entry_info(&1).unwrap();
+        assert_eq!(info.inserted_at, inserted_at);
+        assert_eq!(info.access_count, 1);
+        assert_eq!(info.weight, 8);
+
+        // Growing to 8 left no room for 2, even with less credit than it
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.stats().current_weight, 8);
+        assert_eq!(cache.peek_victim(), Some(&1));
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut cache = Landlord::new(2);
+        cache.put("key1".to_string(), vec![1], 10);
+        cache.put("key2".to_string(), vec![2], 5);
+
+        cache.get_mut(&"key1".to_string()).unwrap().push(10);
+        assert_eq!(cache.get_mut(&"missing".to_string()), None);
+
+        cache.put("key3".to_string(), vec![3], 15);
+        assert_eq!(cache.get(&"key1".to_string()), Some(&vec![1, 10]));
+        assert_eq!(cache.get(&"key2".to_string()), None);
+    }
+
+    #[test]
+    fn test_peek_does_not_refresh_credit() {
+        let mut cache = Landlord::new(2);
+        cache.put("key1".to_string(), 100, 10);
+        cache.put("key2".to_string(), 200, 20);
+        cache.put("key3".to_string(), 300, 30); // l = 10
+
+        // A get would raise key2 to 10 + 20, above key3
+        assert_eq!(cache.peek(&"key2".to_string()), Some(&200));
+        assert!(cache.contains_key(&"key2".to_string()));
+        assert!(!cache.contains_key(&"key1".to_string()));
+
+        cache.put("key4".to_string(), 400, 40);
+        assert!(!cache.contains_key(&"key2".to_string()));
+        assert_eq!(cache.peek(&"key3".to_string()), Some(&300));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut cache = Landlord::new(2);
+        cache.put("key1".to_string(), 100, 10);
+        cache.put("key2".to_string(), 200, 20);
+
+        assert_eq!(cache.remove(&"key1".to_string()), Some(100));
+        assert_eq!(cache.remove(&"key1".to_string()), None);
+        assert_eq!(cache.len(), 1);
+
+        // No eviction needed after the removal, so l stays untouched
+        cache.put("key3".to_string(), 300, 5);
+        assert_eq!(cache.l, 0.0);
+        assert_eq!(cache.get(&"key2".to_string()), Some(&200));
+        assert_eq!(cache.get(&"key3".to_string()), Some(&300));
+    }
+
+    #[test]
+    fn test_pop_victim_drains_by_credit() {
+        let mut cache = Landlord::new(3);
+        cache.put("key1".to_string(), 100, 30);
+        cache.put("key2".to_string(), 200, 10);
+        cache.put("key3".to_string(), 300, 20);
+
+        assert_eq!(cache.pop_victim(), Some(("key2".to_string(), 200)));
+        assert_eq!(cache.l, 10.0);
+        assert_eq!(cache.pop_victim(), Some(("key3".to_string(), 300)));
+        assert_eq!(cache.pop_victim(), Some(("key1".to_string(), 100)));
+        assert_eq!(cache.pop_victim(), None);
+        assert_eq!(cache.l, 30.0);
+    }
+
+    #[test]
+    fn test_iter_in_credit_order() {
+        let mut cache = Landlord::new(3);
+        cache.put(1, "one", 30);
+        cache.put(2, "two", 10);
+        cache.put(3, "three", 20);
+
+        let entries: Vec<_> = cache.iter().collect();
+        assert_eq!(entries, vec![(&1, &"one"), (&3, &"three"), (&2, &"two")]);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&1, &3, &2]);
+        assert_eq!(
+            cache.values().collect::<Vec<_>>(),
+            vec![&"one", &"three", &"two"]
+        );
+    }
+
+    #[test]
+    fn test_from_iter_and_into_iter() {
+        let mut cache: Landlord<_, _> = vec![(1, "one", 30), (2, "two", 10), (3, "three", 20)]
+            .into_iter()
+            .collect();
+        assert_eq!(cache.len(), 3);
+
+        cache.extend(vec![(4, "four", 5)]);
+        assert!(!cache.contains_key(&2));
+
+        let keys: Vec<i32> = (&cache).into_iter().map(|(&key, _)| key).collect();
+        assert_eq!(keys, vec![1, 3, 4]);
+
+        let entries: Vec<_> = cache.into_iter().collect();
+        assert_eq!(entries, vec![(1, "one"), (3, "three"), (4, "four")]);
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut cache = Landlord::new(2);
+        let mut loads = 0;
+
+        for _ in 0..3 {
+            let value = cache.get_or_insert_with(1, 10, || {
+                loads += 1;
+                "one"
+            });
+            assert_eq!(value, &"one");
+        }
+
+        assert_eq!(loads, 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_try_get_or_insert_with() {
+        let mut cache = Landlord::new(2);
+
+        let failed: Result<&&str, &str> =
+            cache.try_get_or_insert_with(1, 10, || Err("backend down"));
+        assert_eq!(failed, Err("backend down"));
+        assert!(!cache.contains_key(&1));
+        assert!(cache.is_empty());
+
+        let loaded: Result<&&str, &str> = cache.try_get_or_insert_with(1, 10, || Ok("one"));
+        assert_eq!(loaded, Ok(&"one"));
+
+        // A cached value is returned without calling the loader
+        let cached: Result<&&str, &str> =
+            cache.try_get_or_insert_with(1, 10, || panic!("loader must not run"));
+        assert_eq!(cached, Ok(&"one"));
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut cache = Landlord::new(4);
+        cache.put("shard1:a".to_string(), 1, 10);
+        cache.put("shard2:b".to_string(), 2, 20);
+        cache.put("shard1:c".to_string(), 3, 30);
+
+        cache.retain(|key, _| !key.starts_with("shard1:"));
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.iter().count(), 1);
+        assert_eq!(cache.peek(&"shard2:b".to_string()), Some(&2));
+    }
+
+    #[test]
+    fn test_with_custom_hasher() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+
+        let mut cache: Landlord<i32, &str, BuildHasherDefault<DefaultHasher>> =
+            Landlord::with_hasher(2, BuildHasherDefault::default());
+        cache.put(1, "one", 20);
+        cache.put(2, "two", 10);
+        cache.get(&1);
+        cache.put(3, "three", 10);
+
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&3), Some(&"three"));
+    }
+
+    #[test]
+    fn test_eviction_listener() {
+        use std::sync::{Arc, Mutex};
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = Landlord::new(2);
+        cache.set_eviction_listener(move |key, value| sink.lock().unwrap().push((key, value)));
+
+        cache.put(1, "one", 10);
+        cache.put(2, "two", 20);
+        cache.remove(&2);
+        cache.put(3, "three", 30);
+        assert!(evicted.lock().unwrap().is_empty());
+
+        cache.put(4, "four", 40);
+        assert_eq!(*evicted.lock().unwrap(), vec![(1, "one")]);
+    }
+
+    fn manual_clock_cache(capacity: usize) -> (Landlord<i32, &'static str>, ManualClock) {
+        let clock = ManualClock::new();
+        let mut cache = Landlord::new(capacity);
+        cache.set_clock(Arc::new(clock.clone()));
+        (cache, clock)
+    }
+
+    #[test]
+    fn test_expire_after_write() {
+        let (mut cache, clock) = manual_clock_cache(2);
+        cache.set_expire_after_write(Duration::from_secs(30));
+
+        cache.put(1, "one", 10);
+        assert_eq!(cache.get(&1), Some(&"one"));
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_expire_after_access() {
+        let (mut cache, clock) = manual_clock_cache(2);
+        cache.set_expire_after_access(Duration::from_secs(60));
+
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 10);
+        for _ in 0..3 {
+            clock.advance(Duration::from_secs(25));
+            assert_eq!(cache.entry(1).or_insert("uno", 1), &mut "one");
+        }
+
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_expire_due_drops_expired_credit() {
+        let (mut cache, clock) = manual_clock_cache(3);
+        cache.put(1, "one", 30);
+        cache.set_expire_after_access(Duration::from_secs(10));
+        cache.put(2, "two", 10);
+        cache.put(3, "three", 20);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.expire_due(clock.now()), 0);
+        cache.get(&3);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.expire_due(clock.now()), 2);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&3]);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.expire_due(clock.now()), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut cache = Landlord::new(2);
+        cache.put("key1".to_string(), 100, 10);
+        cache.put("key2".to_string(), 200, 20);
+        cache.put("key3".to_string(), 300, 30);
+        assert_eq!(cache.l, 10.0);
+
+        cache.clear();
+
+        assert!(cache.is_empty());
+        assert_eq!(cache.l, 0.0);
+        assert_eq!(cache.get(&"key2".to_string()), None);
+
+        cache.put("key4".to_string(), 400, 40);
+        assert_eq!(cache.get(&"key4".to_string()), Some(&400));
+    }
+
     #[test]
     fn test_with_integer_keys() {
         let mut cache = Landlord::new(3);
@@ -229,4 +1405,22 @@ mod tests {
         cache.put("key4".to_string(), 400, 40);
         assert!(cache.l > second_l);
     }
+
+    #[test]
+    fn test_timed_inserts_are_priced_by_latency() {
+        let (mut cache, clock) = manual_clock_cache(2);
+        let slow = clock.clone();
+        cache.get_or_insert_timed(1, 1, || {
+            slow.advance(Duration::from_millis(40));
+            "slow"
+        });
+        cache.get_or_insert_timed(2, 1, || "fast");
+        assert_eq!(cache.get_or_insert_timed(1, 1, || "recomputed"), &"slow");
+
+        // The cheap query goes first, charging its one millisecond to the slow one
+        cache.put(3, "three", 1);
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.l, 1.0);
+        assert!(cache.contains_key(&1));
+    }
 }
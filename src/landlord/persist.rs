@@ -0,0 +1,260 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Write};
+
+use super::{Landlord, LandlordNode, Rent};
+use crate::expiry::SavedTimestamps;
+use crate::snapshot::{self, impl_codec, Decode, Encode, SnapshotError, SnapshotReader};
+use crate::stats::CacheStats;
+use crate::util;
+use crate::weight::{Ordered, Weight};
+use crate::Policy;
+
+// What is saved of a cache, by serde and by snapshots alike. Each entry keeps its credit
+// as it stands, offset by `l` like in the heap. Entries are saved in heap order, which
+// pushing them back in the same order leaves untouched, so even ties between equal
+// credits are broken as before.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Saved<K, V, W> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    stats: CacheStats,
+    l: f64,
+    rent: Rent,
+    refresh_factor: f64,
+    entries: Vec<SavedEntry<K, V, W>>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct SavedEntry<K, V, W> {
+    key: K,
+    value: V,
+    weight: W,
+    cost: W,
+    credit: f64,
+    timestamps: SavedTimestamps,
+}
+
+impl_codec!(Saved<K, V, W> {
+    capacity,
+    max_weight,
+    stats,
+    l,
+    rent,
+    refresh_factor,
+    entries,
+});
+impl_codec!(SavedEntry<K, V, W> {
+    key,
+    value,
+    weight,
+    cost,
+    credit,
+    timestamps,
+});
+
+impl Encode for Rent {
+    fn encode<Wr: Write>(&self, writer: &mut Wr) -> io::Result<()> {
+        let tag: u8 = match self {
+            Rent::Uniform => 0,
+            Rent::Proportional => 1,
+        };
+        tag.encode(writer)
+    }
+}
+
+impl Decode for Rent {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        match u8::decode(reader)? {
+            0 => Ok(Rent::Uniform),
+            1 => Ok(Rent::Proportional),
+            _ => Err(SnapshotError::Invalid("unknown rent")),
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone, W: Weight> Landlord<K, V, S, W> {
+    fn save(&self) -> Saved<&K, &V, W> {
+        let now = self.clock.now();
+        let entries = self
+            .pq
+            .iter()
+            .map(|(key, &Reverse(Ordered(credit)))| {
+                let landlord_node = &self.cache[key];
+                SavedEntry {
+                    key,
+                    value: &landlord_node.value,
+                    weight: landlord_node.weight,
+                    cost: landlord_node.cost,
+                    credit,
+                    timestamps: SavedTimestamps::save(&landlord_node.timestamps, now),
+                }
+            })
+            .collect();
+        Saved {
+            capacity: self.capacity,
+            max_weight: self.max_weight,
+            stats: self.stats,
+            l: self.l,
+            rent: self.rent,
+            refresh_factor: self.refresh_factor,
+            entries,
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone + Default, W: Weight> Landlord<K, V, S, W> {
+    fn restore(saved: Saved<K, V, W>) -> Result<Self, &'static str> {
+        snapshot::check_len(saved.capacity, saved.max_weight, saved.entries.len())?;
+        if !(0.0..=1.0).contains(&saved.refresh_factor) {
+            return Err("refresh factor must be between 0 and 1");
+        }
+        let mut cache = Landlord::with_hasher(saved.capacity, S::default());
+        cache.max_weight = saved.max_weight;
+        cache.l = saved.l;
+        cache.rent = saved.rent;
+        cache.refresh_factor = saved.refresh_factor;
+        cache.stats = saved.stats;
+        cache.stats.current_weight = 0;
+        let now = cache.clock.now();
+        for entry in saved.entries {
+            match cache.cache.entry(entry.key.clone()) {
+                Entry::Occupied(_) => return Err("duplicate key"),
+                Entry::Vacant(slot) => slot.insert(LandlordNode {
+                    value: entry.value,
+                    weight: entry.weight,
+                    cost: entry.cost,
+                    timestamps: entry.timestamps.restore(now),
+                }),
+            };
+            cache.stats.current_weight += entry.weight.to_u64();
+            cache.pq.push(entry.key, Reverse(Ordered(entry.credit)));
+        }
+        Ok(cache)
+    }
+}
+
+impl<K, V, S, W> Landlord<K, V, S, W>
+where
+    K: Clone + Hash + Eq + Encode,
+    V: Encode,
+    S: BuildHasher + Clone,
+    W: Weight + Encode,
+{
+    // Writes the entries and their credits as a checksummed binary snapshot.
+    pub fn save_to<Wr: Write>(&self, writer: Wr) -> Result<(), SnapshotError> {
+        snapshot::write(writer, Policy::Landlord, &self.save())
+    }
+}
+
+impl<K, V, S, W> Landlord<K, V, S, W>
+where
+    K: Clone + Hash + Eq + Decode,
+    V: Decode,
+    S: BuildHasher + Clone + Default,
+    W: Weight + Decode,
+{
+    pub fn load_from<R: Read>(reader: R) -> Result<Self, SnapshotError> {
+        Landlord::load_snapshot(SnapshotReader::open(reader)?)
+    }
+
+    pub(crate) fn load_snapshot<R: Read>(
+        snapshot: SnapshotReader<R>,
+    ) -> Result<Self, SnapshotError> {
+        let saved = snapshot.body(Policy::Landlord)?;
+        Landlord::restore(saved).map_err(SnapshotError::Invalid)
+    }
+}
+
+impl<K: Clone + Hash + Eq, V: Clone, S: BuildHasher + Clone, W: Weight> Landlord<K, V, S, W> {
+    // The `n` entries with the most credit, highest first, with their weights.
+    pub fn export_top(&self, n: usize) -> Vec<(K, V, W)> {
+        util::top_entries(self.iter(), n, |key| self.entry_info(key))
+    }
+
+    // Stores exported entries, hottest first. Each is given its full credit on top of the
+    // current inflation, which is as much as any entry holds.
+    pub fn import<I: IntoIterator<Item = (K, V, W)>>(&mut self, entries: I) {
+        let entries: Vec<_> = entries.into_iter().collect();
+        for (key, value, weight) in entries.into_iter().rev() {
+            self.put(key, value, weight);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V, S, W> serde::Serialize for Landlord<K, V, S, W>
+where
+    K: Clone + Hash + Eq + serde::Serialize,
+    V: serde::Serialize,
+    S: BuildHasher + Clone,
+    W: Weight + serde::Serialize,
+{
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        serde::Serialize::serialize(&self.save(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, S, W> serde::Deserialize<'de> for Landlord<K, V, S, W>
+where
+    K: Clone + Hash + Eq + serde::Deserialize<'de>,
+    V: serde::Deserialize<'de>,
+    S: BuildHasher + Clone + Default,
+    W: Weight + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = serde::Deserialize::deserialize(deserializer)?;
+        Landlord::restore(saved).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::RandomState;
+
+    type Restored = Landlord<i32, String, RandomState, f64>;
+
+    fn used() -> Landlord<i32, &'static str, RandomState, f64> {
+        let mut cache = Landlord::with_hasher(3, RandomState::new());
+        cache.set_rent(Rent::Proportional);
+        cache.set_refresh_factor(0.5);
+        cache.put_sized(1, "one", 8.0, 2.0);
+        cache.put_sized(2, "two", 3.0, 1.0);
+        cache.put_sized(3, "three", 5.0, 1.0);
+        cache.put_sized(4, "four", 6.0, 3.0);
+        cache.get(&2);
+        cache
+    }
+
+    fn assert_same(mut restored: Restored, mut cache: Landlord<i32, &str, RandomState, f64>) {
+        assert_eq!(restored.stats(), cache.stats());
+        assert_eq!(restored.rent(), Rent::Proportional);
+        assert_eq!(restored.l, cache.l);
+        for key in [2, 3, 4] {
+            assert_eq!(restored.pq.get_priority(&key), cache.pq.get_priority(&key));
+        }
+        for _ in 0..3 {
+            let (key, value) = restored.pop_victim().unwrap();
+            assert_eq!(Some((key, value.as_str())), cache.pop_victim());
+        }
+    }
+
+    #[test]
+    fn test_snapshot_keeps_credits() {
+        let cache = used();
+        let mut bytes = Vec::new();
+        cache.save_to(&mut bytes).unwrap();
+        assert_same(Restored::load_from(bytes.as_slice()).unwrap(), cache);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_keeps_credits() {
+        let cache = used();
+        let json = serde_json::to_string(&cache).unwrap();
+        assert_same(serde_json::from_str(&json).unwrap(), cache);
+    }
+}
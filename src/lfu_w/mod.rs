@@ -1,79 +1,463 @@
-use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use priority_queue::PriorityQueue;
+use crate::clock::{Clock, SystemClock};
+use crate::expiry::{Expiration, Freshness, Timestamps, TimingWheel, WHEEL_RESOLUTION};
+use crate::observer::{BoxedObserver, CacheObserver};
+use crate::stats::{CacheStats, EntryInfo};
+use crate::trace;
+use crate::util::{KeyIndex, Keyed, Link, Slab, SlabKey};
+use crate::{Cache, EvictionListener, NewCache, Weigher};
+
+mod entry;
+mod persist;
+
+const POLICY: &str = "lfu";
+
+pub use self::entry::{Entry, OccupiedEntry, VacantEntry};
 
 struct Node<K, V> {
     key: K,
     value: V,
     freq: usize,
     weight: u32,
-    prev: Option<usize>,
-    next: Option<usize>,
+    timestamps: Timestamps,
+    bucket: usize,
+    prev: Link,
+    next: Link,
 }
 
-struct PriorityList {
-    head: Option<usize>,
-    tail: Option<usize>,
-    size: usize,
+impl<K, V> Keyed for Node<K, V> {
+    type Key = K;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
 }
 
-impl PriorityList {
-    fn new() -> Self {
-        PriorityList {
-            head: None,
-            tail: None,
-            size: 0,
-        }
+impl<K, V> Node<K, V> {
+    // Frequency times weight. Saturates rather than wrapping, so hot, heavy entries stay
+    // at the top of the order instead of overflowing to the bottom.
+    fn priority(&self) -> u64 {
+        (self.freq as u64).saturating_mul(u64::from(self.weight))
+    }
+}
+
+// The entries sharing one priority, most recently used at the head. Buckets form a list
+// of their own in ascending priority, and are dropped as soon as they empty.
+struct Bucket {
+    priority: u64,
+    head: Link,
+    tail: Link,
+    lower: Link,
+    higher: Link,
+}
+
+pub struct Iter<'a, K, V> {
+    nodes: &'a Slab<Node<K, V>>,
+    order: std::vec::IntoIter<usize>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.nodes.get_at(self.order.next()?)?;
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.order.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+pub struct IntoIter<K, V> {
+    nodes: Slab<Node<K, V>>,
+    order: std::vec::IntoIter<usize>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.nodes.remove(self.order.next()?);
+        Some((node.key, node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.order.size_hint()
     }
 }
 
-pub struct LFUCache<K, V> {
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+pub struct LFUCache<K, V, S = RandomState> {
     capacity: usize,
-    nodes: Vec<Node<K, V>>,
-    min_priority_queue: priority_queue::PriorityQueue<K, Reverse<u32>>,
-    key_to_idx: HashMap<K, usize>,
-    priority_to_list: HashMap<u32, PriorityList>,
-    free_list: Vec<usize>,
+    max_weight: Option<u64>,
+    nodes: Slab<Node<K, V>>,
+    key_to_idx: KeyIndex<S>,
+    buckets: Slab<Bucket>,
+    priority_to_bucket: HashMap<u64, SlabKey>,
+    lowest: Link,
+    highest: Link,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    weigher: Option<Weigher<K, V>>,
+    observer: Option<BoxedObserver<K>>,
+    stats: CacheStats,
+    expiration: Expiration,
+    expiry_wheel: Option<TimingWheel<SlabKey>>,
+    clock: Arc<dyn Clock>,
 }
 
-impl<K: Clone + Hash + Eq, V> LFUCache<K, V> {
+impl<K: Hash + Eq, V> LFUCache<K, V> {
     pub fn new(capacity: usize) -> Self {
+        LFUCache::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> LFUCache<K, V, S> {
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
         assert!(capacity > 0, "Capacity must be greater than 0");
         LFUCache {
             capacity,
-            nodes: Vec::with_capacity(capacity),
-            min_priority_queue: PriorityQueue::new(),
-            key_to_idx: HashMap::new(),
-            priority_to_list: HashMap::new(),
-            free_list: Vec::new(),
+            max_weight: None,
+            nodes: Slab::with_capacity(capacity),
+            key_to_idx: KeyIndex::with_hasher(hash_builder),
+            buckets: Slab::with_capacity(0),
+            priority_to_bucket: HashMap::new(),
+            lowest: Link::NONE,
+            highest: Link::NONE,
+            eviction_listener: None,
+            weigher: None,
+            observer: None,
+            stats: CacheStats::default(),
+            expiration: Expiration::default(),
+            expiry_wheel: None,
+            clock: Arc::new(SystemClock),
         }
     }
 
     pub fn get(&mut self, key: &K) -> Option<&V> {
-        let idx = *self.key_to_idx.get(key)?;
+        let _span = trace::get_span(POLICY, key);
+        let idx = self.live_index(key)?;
+        self.increment_priority(idx);
+        Some(&self.node(idx).value)
+    }
+
+    pub fn get_stale(&mut self, key: &K) -> Option<Freshness<&V>> {
+        let now = self.clock.now();
+        match self.index_of(key) {
+            Some(idx) if self.is_expired(idx, now) => {
+                self.stats.misses += 1;
+                trace::miss(POLICY, key);
+                self.notify(|observer| observer.on_miss(key));
+                Some(Freshness::Stale(&self.node(idx).value))
+            }
+            _ => self.get(key).map(Freshness::Fresh),
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let idx = self.live_index(key)?;
         self.increment_priority(idx);
-        Some(&self.nodes[idx].value)
+        Some(&mut self.node_mut(idx).value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        let idx = self.index_of(key)?;
+        if self.is_expired(idx, self.clock.now()) {
+            return None;
+        }
+        Some(&self.node(idx).value)
+    }
+
+    // Like `peek`, this does not count as a use of the key.
+    pub fn entry_info(&self, key: &K) -> Option<EntryInfo> {
+        let idx = self.index_of(key)?;
+        if self.is_expired(idx, self.clock.now()) {
+            return None;
+        }
+        let node = self.node(idx);
+        Some(EntryInfo::new(
+            &node.timestamps,
+            node.weight,
+            &self.expiration,
+        ))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
     }
 
     pub fn put(&mut self, key: K, value: V, weight: u32) {
-        if let Some(&idx) = self.key_to_idx.get(&key) {
+        let _span = trace::put_span(POLICY, &key, weight);
+        if let Some(idx) = self.index_of(&key) {
             // Update existing key
-            self.nodes[idx].value = value;
-            self.increment_priority(idx);
+            let now = self.clock.now();
+            let node = self.node_mut(idx);
+            let old_weight = node.weight;
+            node.value = value;
+            node.weight = weight;
+            node.freq = node.freq.saturating_add(1);
+            node.timestamps.rewrite(now);
+            self.reprioritize(idx);
+            self.stats.current_weight -= u64::from(old_weight);
+            self.stats.current_weight += u64::from(weight);
+            // A heavier value can push the cache over its budget; the key itself may rank
+            // lowest, but is spared like a new entry would be
+            while self.len() > 1 && self.is_over_budget() {
+                self.evict_sparing(Link::some(idx));
+            }
+            self.notify(|observer| observer.on_update(&key));
         } else {
-            // Need to evict if at capacity
-            if self.key_to_idx.len() >= self.capacity {
-                self.evict_lfu();
+            self.insert_new(key, value, weight);
+        }
+    }
+
+    // Stores the value with the weight the weigher gives it, or a weight of 1 without one.
+    pub fn insert(&mut self, key: K, value: V) {
+        let weight = self
+            .weigher
+            .as_ref()
+            .map_or(1, |weigher| weigher(&key, &value));
+        self.put(key, value, weight);
+    }
+
+    // An occupied entry counts as a use of the key, just like `get`.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        match self.live_index(&key) {
+            Some(idx) => {
+                self.increment_priority(idx);
+                Entry::Occupied(OccupiedEntry { cache: self, idx })
+            }
+            None => Entry::Vacant(VacantEntry { cache: self, key }),
+        }
+    }
+
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, weight: u32, f: F) -> &V {
+        self.entry(key).or_insert_with(weight, f)
+    }
+
+    // A failed computation leaves the cache untouched and returns the error.
+    pub fn try_get_or_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        weight: u32,
+        f: F,
+    ) -> Result<&V, E> {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(f()?, weight)),
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let idx = self.index_of(key)?;
+        Some(self.remove_at(idx).value)
+    }
+
+    // The entry `pop_victim` would remove, left in place.
+    pub fn peek_victim(&self) -> Option<&K> {
+        let tail_idx = self.buckets[self.lowest.get()?].tail.get()?;
+        Some(&self.node(tail_idx).key)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        // The victim is the least recently used entry of the lowest priority bucket
+        let tail_idx = self.buckets[self.lowest.get()?].tail.get()?;
+        let node = self.remove_at(tail_idx);
+        Some((node.key, node.value))
+    }
+
+    // Evicts entries as a full cache would, listener and stats included, until at most
+    // `len` are left. Returns how many were evicted.
+    pub fn evict_to(&mut self, len: usize) -> usize {
+        let mut evicted = 0;
+        while self.len() > len {
+            self.evict();
+            evicted += 1;
+        }
+        evicted
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        let doomed: Vec<usize> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| !f(&node.key, &node.value))
+            .map(|(idx, _)| idx)
+            .collect();
+
+        for idx in doomed {
+            self.remove_at(idx);
+        }
+    }
+
+    pub fn set_max_weight(&mut self, max_weight: u64) {
+        self.max_weight = Some(max_weight);
+        while !self.is_empty() && self.is_over_budget() {
+            self.evict();
+        }
+    }
+
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.set_boxed_eviction_listener(Box::new(listener));
+    }
+
+    pub(crate) fn set_boxed_eviction_listener(&mut self, listener: EvictionListener<K, V>) {
+        self.eviction_listener = Some(listener);
+    }
+
+    // Computes the weight of entries stored with `insert`.
+    pub fn set_weigher<F: Fn(&K, &V) -> u32 + Send + 'static>(&mut self, weigher: F) {
+        self.set_boxed_weigher(Box::new(weigher));
+    }
+
+    pub(crate) fn set_boxed_weigher(&mut self, weigher: Weigher<K, V>) {
+        self.weigher = Some(weigher);
+    }
+
+    pub fn set_observer<O: CacheObserver<K> + Send + 'static>(&mut self, observer: O) {
+        self.set_boxed_observer(Box::new(observer));
+    }
+
+    pub(crate) fn set_boxed_observer(&mut self, observer: BoxedObserver<K>) {
+        self.observer = Some(observer);
+    }
+
+    pub fn set_expire_after_write(&mut self, time_to_live: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_live = Some(time_to_live);
+        self.set_expiration(expiration);
+    }
+
+    pub fn set_expire_after_access(&mut self, time_to_idle: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_idle = Some(time_to_idle);
+        self.set_expiration(expiration);
+    }
+
+    // Existing entries are scheduled against the new limits.
+    pub(crate) fn set_expiration(&mut self, expiration: Expiration) {
+        self.expiration = expiration;
+        self.expiry_wheel = if expiration.is_enabled() {
+            Some(TimingWheel::new(self.clock.now(), WHEEL_RESOLUTION))
+        } else {
+            None
+        };
+        let live: Vec<usize> = self.nodes.iter().map(|(idx, _)| idx).collect();
+        for idx in live {
+            self.schedule_expiry(idx);
+        }
+    }
+
+    // Replaces the time source; entries keep the timestamps they were given by the old one.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+        let expiration = self.expiration;
+        self.set_expiration(expiration);
+    }
+
+    pub fn expire_due(&mut self, now: Instant) -> usize {
+        let due = match self.expiry_wheel.as_mut() {
+            Some(wheel) => wheel.advance(now),
+            None => return 0,
+        };
+
+        let mut expired = 0;
+        for slot in due {
+            let idx = match self.nodes.get(slot) {
+                Some(_) => slot.index(),
+                None => continue,
+            };
+            if self.is_expired(idx, now) {
+                self.remove_at(idx);
+                expired += 1;
+            } else {
+                // Touched or rewritten since it was scheduled
+                self.schedule_expiry(idx);
+            }
+        }
+        expired
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.key_to_idx.clear();
+        self.buckets.clear();
+        self.priority_to_bucket.clear();
+        self.lowest = Link::NONE;
+        self.highest = Link::NONE;
+        self.stats.current_weight = 0;
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.clear();
+        }
+    }
+
+    // Moves entries and buckets out of the slots freed by removals, so their storage and
+    // the maps can release the memory they no longer need.
+    pub fn shrink_to_fit(&mut self) {
+        for (from, to) in self.nodes.compaction_moves() {
+            self.relocate(from, to);
+        }
+        for (from, to) in self.buckets.compaction_moves() {
+            self.relocate_bucket(from, to);
+        }
+        self.nodes.shrink_to_fit();
+        self.buckets.shrink_to_fit();
+        self.key_to_idx.shrink_to_fit(&self.nodes);
+        self.priority_to_bucket.shrink_to_fit();
+    }
+
+    // Reserves room for a full cache. Every bucket holds at least one entry, except the
+    // one a promoted entry moves into before its old bucket is released.
+    pub fn preallocate(&mut self) {
+        self.nodes.reserve(self.capacity);
+        self.key_to_idx.reserve(self.capacity, &self.nodes);
+        self.buckets.reserve(self.capacity + 1);
+        self.priority_to_bucket
+            .reserve((2 * (self.capacity + 1)).saturating_sub(self.priority_to_bucket.len()));
+    }
+
+    // Entries from highest priority to the next victim, most recent first
+    // within a priority.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            nodes: &self.nodes,
+            order: self.eviction_order().into_iter(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    fn eviction_order(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.key_to_idx.len());
+        let mut bucket = self.highest;
+        while let Some(bucket_idx) = bucket.get() {
+            let mut current = self.buckets[bucket_idx].head;
+            while let Some(idx) = current.get() {
+                order.push(idx);
+                current = self.node(idx).next;
             }
-            // Create new node with frequency 1
-            let idx = self.allocate_node(key.clone(), value, 1, weight);
-            self.key_to_idx.insert(key.clone(), idx);
-            self.add_to_priority_list(idx, 1 * weight);
-            self.min_priority_queue
-                .push(key.clone(), std::cmp::Reverse(1 * weight));
+            bucket = self.buckets[bucket_idx].lower;
         }
+        order
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
     }
 
     pub fn len(&self) -> usize {
@@ -84,109 +468,427 @@ impl<K: Clone + Hash + Eq, V> LFUCache<K, V> {
         self.key_to_idx.is_empty()
     }
 
-    fn increment_priority(&mut self, idx: usize) {
-        let weight = self.nodes[idx].weight;
-        let old_freq = self.nodes[idx].freq;
-        let new_freq = old_freq + 1;
-
-        self.remove_from_priority_list(idx, old_freq as u32 * weight);
-        self.nodes[idx].freq = new_freq;
-
-        // Add to new priority list
-        self.add_to_priority_list(idx, new_freq as u32 * weight);
-        self.min_priority_queue.change_priority(
-            &self.nodes[idx].key,
-            std::cmp::Reverse(new_freq as u32 * weight),
-        );
+    fn insert_new(&mut self, key: K, value: V, weight: u32) -> usize {
+        self.make_room(weight);
+        // Create new node with frequency 1
+        self.stats.insertions += 1;
+        self.stats.current_weight += u64::from(weight);
+        trace::insert(POLICY, &key, weight);
+        self.notify(|observer| observer.on_insert(&key));
+        let slot = self.allocate_node(key, value, 1, weight);
+        let idx = slot.index();
+        self.key_to_idx.insert(slot, &self.nodes);
+        let priority = self.node(idx).priority();
+        let bucket_idx = self.bucket_for(priority, Link::NONE);
+        self.push_front(idx, bucket_idx);
+        self.schedule_expiry(idx);
+        idx
+    }
+
+    // Whether an entry of this weight only fits after an eviction.
+    fn is_full(&self, weight: u32) -> bool {
+        match self.max_weight {
+            Some(max_weight) => self.stats.current_weight + u64::from(weight) > max_weight,
+            None => self.len() >= self.capacity,
+        }
     }
 
-    fn add_to_priority_list(&mut self, idx: usize, priority: u32) {
-        let list = self
-            .priority_to_list
-            .entry(priority)
-            .or_insert_with(PriorityList::new);
+    fn is_over_budget(&self) -> bool {
+        self.max_weight
+            .is_some_and(|max_weight| self.stats.current_weight > max_weight)
+    }
 
-        self.nodes[idx].next = list.head;
-        self.nodes[idx].prev = None;
+    fn make_room(&mut self, weight: u32) {
+        while !self.is_empty() && self.is_full(weight) {
+            self.evict();
+        }
+    }
 
-        if let Some(old_head) = list.head {
-            self.nodes[old_head].prev = Some(idx);
+    // Looks up a key, dropping it instead if it has expired, and records the access.
+    // This is the single place hits and misses are observed.
+    fn live_index(&mut self, key: &K) -> Option<usize> {
+        let now = self.clock.now();
+        let idx = match self.index_of(key) {
+            Some(idx) if self.is_expired(idx, now) => {
+                self.remove_at(idx);
+                None
+            }
+            found => found,
+        };
+        match idx {
+            Some(idx) => {
+                self.node_mut(idx).timestamps.touch(now);
+                self.stats.hits += 1;
+                self.notify(|observer| observer.on_hit(key));
+            }
+            None => {
+                self.stats.misses += 1;
+                trace::miss(POLICY, key);
+                self.notify(|observer| observer.on_miss(key));
+            }
         }
+        idx
+    }
 
-        list.head = Some(idx);
+    fn is_expired(&self, idx: usize, now: Instant) -> bool {
+        self.expiration.is_expired(&self.node(idx).timestamps, now)
+    }
 
-        if list.tail.is_none() {
-            list.tail = Some(idx);
+    // Unlinks the node from every internal structure and frees its slot.
+    fn remove_at(&mut self, idx: usize) -> Node<K, V> {
+        self.unlink(idx);
+        let slot = self.nodes.key_at(idx);
+        let node = self.nodes.remove(idx);
+        self.key_to_idx.remove(&node.key, idx);
+        self.stats.current_weight -= u64::from(node.weight);
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.cancel(&slot);
         }
-
-        list.size += 1;
+        node
     }
 
-    fn remove_from_priority_list(&mut self, idx: usize, priority: u32) {
+    fn schedule_expiry(&mut self, idx: usize) {
         let node = &self.nodes[idx];
-        let prev = node.prev;
-        let next = node.next;
+        if let (Some(wheel), Some(deadline)) = (
+            self.expiry_wheel.as_mut(),
+            self.expiration.deadline(&node.timestamps),
+        ) {
+            wheel.schedule(self.nodes.key_at(idx), deadline);
+        }
+    }
+
+    fn notify<F: FnOnce(&mut dyn CacheObserver<K>)>(&mut self, f: F) {
+        if let Some(observer) = self.observer.as_mut() {
+            f(&mut **observer);
+        }
+    }
 
-        if let Some(list) = self.priority_to_list.get_mut(&priority) {
-            match prev {
-                Some(p) => self.nodes[p].next = next,
-                None => list.head = next,
+    // Capacity-driven eviction; the listener sees every entry dropped this way.
+    fn evict(&mut self) {
+        self.evict_sparing(Link::NONE);
+    }
+
+    // Evicts the victim, or the entry that would be next after it when the victim is `keep`.
+    fn evict_sparing(&mut self, keep: Link) {
+        let lowest = match self.lowest.get() {
+            Some(lowest) => lowest,
+            None => return,
+        };
+        let mut victim = self.buckets[lowest].tail;
+        if let Some(kept) = keep.get().filter(|&kept| victim == Link::some(kept)) {
+            victim = match self.node(kept).prev.get() {
+                Some(prev) => Link::some(prev),
+                None => match self.buckets[lowest].higher.get() {
+                    Some(higher) => self.buckets[higher].tail,
+                    None => Link::NONE,
+                },
+            };
+        }
+        if let Some(idx) = victim.get() {
+            let node = self.remove_at(idx);
+            self.stats.evictions += 1;
+            trace::eviction(POLICY, &node.key);
+            if let Some(listener) = self.eviction_listener.as_mut() {
+                listener(node.key, node.value);
+            }
+        }
+    }
+
+    fn node(&self, idx: usize) -> &Node<K, V> {
+        &self.nodes[idx]
+    }
+
+    fn node_mut(&mut self, idx: usize) -> &mut Node<K, V> {
+        &mut self.nodes[idx]
+    }
+
+    fn index_of(&self, key: &K) -> Option<usize> {
+        self.key_to_idx.get(key, &self.nodes).map(SlabKey::index)
+    }
+
+    // Moves the node to the bucket of its next frequency. With unit weights that bucket is
+    // the next one up, so a use costs O(1); heavier entries step past the priorities they
+    // skip over.
+    fn increment_priority(&mut self, idx: usize) {
+        let node = self.node_mut(idx);
+        node.freq = node.freq.saturating_add(1);
+        self.reprioritize(idx);
+    }
+
+    // Moves the node to the bucket of its priority after its frequency or weight changed.
+    fn reprioritize(&mut self, idx: usize) {
+        let old_bucket = self.node(idx).bucket;
+        let old_priority = self.buckets[old_bucket].priority;
+        let new_priority = self.node(idx).priority();
+
+        if new_priority == old_priority {
+            // Saturated, or reweighed to the same priority: only the recency changes
+            if self.buckets[old_bucket].head != Link::some(idx) {
+                self.unlink(idx);
+                self.push_front(idx, old_bucket);
             }
+            return;
+        }
+        // A lighter value can lower the priority, and then the search starts at the bottom
+        let from = if new_priority > old_priority {
+            Link::some(old_bucket)
+        } else {
+            Link::NONE
+        };
+        let new_bucket = self.bucket_for(new_priority, from);
+        self.unlink(idx);
+        self.push_front(idx, new_bucket);
+    }
+
+    // The bucket for a priority, created if needed. The search starts above `from`, a
+    // bucket of lower priority, or at the lowest bucket.
+    fn bucket_for(&mut self, priority: u64, from: Link) -> usize {
+        if let Some(&slot) = self.priority_to_bucket.get(&priority) {
+            return self.buckets.index_of(slot);
+        }
 
-            match next {
-                Some(n) => self.nodes[n].prev = prev,
-                None => list.tail = prev,
+        let mut lower = from;
+        let mut higher = match from.get() {
+            Some(bucket_idx) => self.buckets[bucket_idx].higher,
+            None => self.lowest,
+        };
+        while let Some(bucket_idx) = higher.get() {
+            if self.buckets[bucket_idx].priority > priority {
+                break;
             }
+            lower = higher;
+            higher = self.buckets[bucket_idx].higher;
+        }
+
+        let bucket = Bucket {
+            priority,
+            head: Link::NONE,
+            tail: Link::NONE,
+            lower,
+            higher,
+        };
+        let slot = self.buckets.insert(bucket);
+        let bucket_idx = slot.index();
+        match lower.get() {
+            Some(lower) => self.buckets[lower].higher = Link::some(bucket_idx),
+            None => self.lowest = Link::some(bucket_idx),
+        }
+        match higher.get() {
+            Some(higher) => self.buckets[higher].lower = Link::some(bucket_idx),
+            None => self.highest = Link::some(bucket_idx),
+        }
+        self.priority_to_bucket.insert(priority, slot);
+        bucket_idx
+    }
+
+    fn push_front(&mut self, idx: usize, bucket_idx: usize) {
+        let old_head = self.buckets[bucket_idx].head;
+        let node = self.node_mut(idx);
+        node.bucket = bucket_idx;
+        node.prev = Link::NONE;
+        node.next = old_head;
 
-            list.size -= 1;
+        match old_head.get() {
+            Some(old_head) => self.node_mut(old_head).prev = Link::some(idx),
+            None => self.buckets[bucket_idx].tail = Link::some(idx),
         }
+        self.buckets[bucket_idx].head = Link::some(idx);
     }
 
-    fn evict_lfu(&mut self) {
-        // Remove the tail (least recently used) from min frequency list
-        let min_priority = self.min_priority_queue.pop().unwrap();
-        if let Some(list) = self.priority_to_list.get(&min_priority.1 .0) {
-            if let Some(tail_idx) = list.tail {
-                let key = self.nodes[tail_idx].key.clone();
-                self.key_to_idx.remove(&key);
-                self.remove_from_priority_list(tail_idx, min_priority.1 .0);
-                self.free_list.push(tail_idx);
+    // Takes the node out of its bucket, dropping the bucket if that empties it.
+    fn unlink(&mut self, idx: usize) {
+        let node = self.node(idx);
+        let (bucket_idx, prev, next) = (node.bucket, node.prev, node.next);
+
+        match prev.get() {
+            Some(prev) => self.node_mut(prev).next = next,
+            None => self.buckets[bucket_idx].head = next,
+        }
+        match next.get() {
+            Some(next) => self.node_mut(next).prev = prev,
+            None => self.buckets[bucket_idx].tail = prev,
+        }
+
+        if self.buckets[bucket_idx].head.is_none() {
+            let bucket = &self.buckets[bucket_idx];
+            let (priority, lower, higher) = (bucket.priority, bucket.lower, bucket.higher);
+            match lower.get() {
+                Some(lower) => self.buckets[lower].higher = higher,
+                None => self.lowest = higher,
             }
+            match higher.get() {
+                Some(higher) => self.buckets[higher].lower = lower,
+                None => self.highest = lower,
+            }
+            self.priority_to_bucket.remove(&priority);
+            self.buckets.remove(bucket_idx);
         }
     }
 
-    fn allocate_node(&mut self, key: K, value: V, freq: usize, weight: u32) -> usize {
-        if let Some(free_idx) = self.free_list.pop() {
-            self.nodes[free_idx] = Node {
-                key,
-                value,
-                freq,
-                weight,
-                prev: None,
-                next: None,
-            };
-            free_idx
-        } else {
-            self.nodes.push(Node {
-                key,
-                value,
-                freq,
-                weight,
-                prev: None,
-                next: None,
-            });
-            self.nodes.len() - 1
+    // Moves a node into a free slot, repointing its neighbours and its map entry.
+    fn relocate(&mut self, from: usize, to: usize) {
+        let old = self.nodes.key_at(from);
+        let slot = self.nodes.relocate(from, to);
+        let node = &self.nodes[to];
+        let (bucket_idx, prev, next) = (node.bucket, node.prev, node.next);
+        match prev.get() {
+            Some(prev) => self.node_mut(prev).next = Link::some(to),
+            None => self.buckets[bucket_idx].head = Link::some(to),
+        }
+        match next.get() {
+            Some(next) => self.node_mut(next).prev = Link::some(to),
+            None => self.buckets[bucket_idx].tail = Link::some(to),
+        }
+        self.key_to_idx.repoint(&self.nodes[to].key, from, slot);
+        if self
+            .expiry_wheel
+            .as_mut()
+            .is_some_and(|wheel| wheel.cancel(&old))
+        {
+            self.schedule_expiry(to);
+        }
+    }
+
+    // Moves a bucket into a free slot, repointing its neighbours and its entries.
+    fn relocate_bucket(&mut self, from: usize, to: usize) {
+        let slot = self.buckets.relocate(from, to);
+        let bucket = &self.buckets[to];
+        let (priority, head, lower, higher) =
+            (bucket.priority, bucket.head, bucket.lower, bucket.higher);
+        match lower.get() {
+            Some(lower) => self.buckets[lower].higher = Link::some(to),
+            None => self.lowest = Link::some(to),
+        }
+        match higher.get() {
+            Some(higher) => self.buckets[higher].lower = Link::some(to),
+            None => self.highest = Link::some(to),
         }
+        self.priority_to_bucket.insert(priority, slot);
+        let mut current = head;
+        while let Some(idx) = current.get() {
+            let node = self.node_mut(idx);
+            node.bucket = to;
+            current = node.next;
+        }
+    }
+
+    fn allocate_node(&mut self, key: K, value: V, freq: usize, weight: u32) -> SlabKey {
+        let node = Node {
+            key,
+            value,
+            freq,
+            weight,
+            timestamps: Timestamps::new(self.clock.now()),
+            bucket: 0,
+            prev: Link::NONE,
+            next: Link::NONE,
+        };
+        self.nodes.insert(node)
     }
 
     pub fn get_freq(&self, key: &K) -> Option<usize> {
-        self.key_to_idx.get(key).map(|&idx| self.nodes[idx].freq)
+        self.index_of(key).map(|idx| self.node(idx).freq)
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> IntoIterator for LFUCache<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        let order = self.eviction_order();
+        IntoIter {
+            nodes: self.nodes,
+            order: order.into_iter(),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher + Clone> IntoIterator for &'a LFUCache<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+// The capacity is sized to hold every collected entry.
+impl<K: Hash + Eq, V, S: BuildHasher + Clone + Default> FromIterator<(K, V, u32)>
+    for LFUCache<K, V, S>
+{
+    fn from_iter<I: IntoIterator<Item = (K, V, u32)>>(iter: I) -> Self {
+        let entries: Vec<_> = iter.into_iter().collect();
+        let mut cache = LFUCache::with_hasher(entries.len().max(1), S::default());
+        cache.extend(entries);
+        cache
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> Extend<(K, V, u32)> for LFUCache<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V, u32)>>(&mut self, iter: I) {
+        for (key, value, weight) in iter {
+            self.put(key, value, weight);
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> Cache<K, V> for LFUCache<K, V, S> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone + Default> NewCache<K, V> for LFUCache<K, V, S> {
+    fn new(capacity: usize) -> Self {
+        LFUCache::with_hasher(capacity, S::default())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::ManualClock;
 
     // ========== Basic Unweighted Tests ==========
 
@@ -322,6 +1024,320 @@ mod tests {
         assert_eq!(cache.get(&2), Some(&"two"));
     }
 
+    #[test]
+    fn test_get_mut() {
+        let mut cache = LFUCache::new(2);
+        cache.put(1, vec![1], 1);
+        cache.put(2, vec![2], 1);
+
+        cache.get_mut(&1).unwrap().push(10);
+        assert_eq!(cache.get_freq(&1), Some(2));
+        assert_eq!(cache.get_mut(&3), None);
+
+        cache.put(3, vec![3], 1);
+        assert_eq!(cache.get(&1), Some(&vec![1, 10]));
+        assert_eq!(cache.get(&2), None);
+    }
+
+    #[test]
+    fn test_peek_does_not_update_frequency() {
+        let mut cache = LFUCache::new(2);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.get(&2);
+
+        assert_eq!(cache.peek(&1), Some(&"one"));
+        assert_eq!(cache.peek(&1), Some(&"one"));
+        assert_eq!(cache.get_freq(&1), Some(1));
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&3));
+
+        // Key 1 still has the lowest frequency
+        cache.put(3, "three", 1);
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.peek(&2), Some(&"two"));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut cache = LFUCache::new(2);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.get(&2);
+
+        assert_eq!(cache.remove(&2), Some("two"));
+        assert_eq!(cache.remove(&2), None);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get_freq(&2), None);
+
+        // Freed slot is reused without evicting key 1
+        cache.put(3, "three", 1);
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.get(&3), Some(&"three"));
+    }
+
+    #[test]
+    fn test_remove_then_evict() {
+        let mut cache = LFUCache::new(2);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.get(&1);
+
+        cache.remove(&2);
+        cache.put(3, "three", 1);
+        cache.put(4, "four", 1);
+
+        // Key 3 is the only entry with the minimum priority
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.get(&4), Some(&"four"));
+    }
+
+    #[test]
+    fn test_pop_victim_drains_in_priority_order() {
+        let mut cache = LFUCache::new(3);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.put(3, "three", 1);
+        cache.get(&1);
+        cache.get(&1);
+        cache.get(&3);
+
+        assert_eq!(cache.pop_victim(), Some((2, "two")));
+        assert_eq!(cache.pop_victim(), Some((3, "three")));
+        assert_eq!(cache.pop_victim(), Some((1, "one")));
+        assert_eq!(cache.pop_victim(), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_eviction_keeps_queue_and_lists_in_sync() {
+        let mut cache = LFUCache::new(2);
+        for i in 0..20 {
+            cache.put(i, i, 1);
+            cache.get(&i);
+        }
+
+        // Every remaining key must still be reachable as a victim
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.pop_victim(), Some((18, 18)));
+        assert_eq!(cache.pop_victim(), Some((19, 19)));
+        assert_eq!(cache.pop_victim(), None);
+    }
+
+    #[test]
+    fn test_iter_in_priority_order() {
+        let mut cache = LFUCache::new(4);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.put(3, "three", 1);
+        cache.put(4, "four", 5);
+        cache.get(&1);
+        cache.get(&1);
+
+        let entries: Vec<_> = cache.iter().collect();
+        assert_eq!(
+            entries,
+            vec![(&4, &"four"), (&1, &"one"), (&3, &"three"), (&2, &"two")]
+        );
+        assert_eq!(cache.iter().len(), 4);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&4, &1, &3, &2]);
+
+        // The last entry yielded is the next victim
+        let victim = cache.keys().last().cloned();
+        assert_eq!(cache.pop_victim().map(|(key, _)| key), victim);
+    }
+
+    #[test]
+    fn test_from_iter_and_into_iter() {
+        let mut cache: LFUCache<_, _> = vec![(1, "one", 1), (2, "two", 3), (3, "three", 2)]
+            .into_iter()
+            .collect();
+        assert_eq!(cache.len(), 3);
+
+        cache.extend(vec![(4, "four", 5)]);
+        assert!(!cache.contains_key(&1));
+
+        let keys: Vec<i32> = (&cache).into_iter().map(|(&key, _)| key).collect();
+        assert_eq!(keys, vec![4, 2, 3]);
+
+        let entries: Vec<_> = cache.into_iter().collect();
+        assert_eq!(entries, vec![(4, "four"), (2, "two"), (3, "three")]);
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut cache = LFUCache::new(2);
+        let mut loads = 0;
+
+        for _ in 0..3 {
+            let value = cache.get_or_insert_with(1, 1, || {
+                loads += 1;
+                "one"
+            });
+            assert_eq!(value, &"one");
+        }
+
+        assert_eq!(loads, 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_try_get_or_insert_with() {
+        let mut cache = LFUCache::new(2);
+
+        let failed: Result<&&str, &str> =
+            cache.try_get_or_insert_with(1, 1, || Err("backend down"));
+        assert_eq!(failed, Err("backend down"));
+        assert!(!cache.contains_key(&1));
+        assert!(cache.is_empty());
+
+        let loaded: Result<&&str, &str> = cache.try_get_or_insert_with(1, 1, || Ok("one"));
+        assert_eq!(loaded, Ok(&"one"));
+
+        // A cached value is returned without calling the loader
+        let cached: Result<&&str, &str> =
+            cache.try_get_or_insert_with(1, 1, || panic!("loader must not run"));
+        assert_eq!(cached, Ok(&"one"));
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut cache = LFUCache::new(4);
+        for i in 1..=4 {
+            cache.put(i, i * 10, 1);
+        }
+        cache.get(&1);
+        cache.retain(|_, &value| value != 20 && value != 30);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get_freq(&1), Some(2));
+        assert_eq!(cache.pop_victim(), Some((4, 40)));
+        assert_eq!(cache.pop_victim(), Some((1, 10)));
+        assert_eq!(cache.pop_victim(), None);
+    }
+
+    #[test]
+    fn test_with_custom_hasher() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+
+        let mut cache: LFUCache<i32, &str, BuildHasherDefault<DefaultHasher>> =
+            LFUCache::with_hasher(2, BuildHasherDefault::default());
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.get(&1);
+        cache.put(3, "three", 1);
+
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&3), Some(&"three"));
+    }
+
+    #[test]
+    fn test_eviction_listener() {
+        use std::sync::{Arc, Mutex};
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = LFUCache::new(2);
+        cache.set_eviction_listener(move |key, value| sink.lock().unwrap().push((key, value)));
+
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.remove(&2);
+        cache.put(3, "three", 1);
+        assert!(evicted.lock().unwrap().is_empty());
+
+        cache.put(4, "four", 1);
+        assert_eq!(*evicted.lock().unwrap(), vec![(1, "one")]);
+    }
+
+    fn manual_clock_cache(capacity: usize) -> (LFUCache<i32, &'static str>, ManualClock) {
+        let clock = ManualClock::new();
+        let mut cache = LFUCache::new(capacity);
+        cache.set_clock(Arc::new(clock.clone()));
+        (cache, clock)
+    }
+
+    #[test]
+    fn test_expire_after_write() {
+        let (mut cache, clock) = manual_clock_cache(2);
+        cache.set_expire_after_write(Duration::from_secs(30));
+
+        cache.put(1, "one", 5);
+        cache.get(&1);
+        assert_eq!(cache.get_freq(&1), Some(2));
+
+        clock.advance(Duration::from_secs(30));
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.is_empty());
+
+        // A rewrite starts the entry's lifetime over
+        cache.put(2, "two", 1);
+        clock.advance(Duration::from_secs(20));
+        cache.put(2, "TWO", 1);
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(cache.get(&2), Some(&"TWO"));
+    }
+
+    #[test]
+    fn test_expire_after_access() {
+        let (mut cache, clock) = manual_clock_cache(2);
+        cache.set_expire_after_access(Duration::from_secs(60));
+
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        for _ in 0..3 {
+            clock.advance(Duration::from_secs(25));
+            assert_eq!(cache.get(&1), Some(&"one"));
+        }
+
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_expire_due_ignores_frequency() {
+        let (mut cache, clock) = manual_clock_cache(3);
+        cache.set_expire_after_write(Duration::from_secs(10));
+        let start = clock.now();
+
+        cache.put(1, "one", 10);
+        cache.put(2, "two", 1);
+        for _ in 0..5 {
+            cache.get(&1);
+        }
+
+        assert_eq!(cache.expire_due(start + Duration::from_secs(9)), 0);
+        assert_eq!(cache.expire_due(start + Duration::from_secs(10)), 2);
+        assert!(cache.is_empty());
+        assert_eq!(cache.pop_victim(), None);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut cache = LFUCache::new(2);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.get(&1);
+        cache.clear();
+
+        assert!(cache.is_empty());
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get_freq(&1), None);
+
+        // Frequencies start over after a clear
+        cache.put(1, "one", 1);
+        assert_eq!(cache.get_freq(&1), Some(1));
+        cache.put(2, "two", 1);
+        cache.get(&2);
+        cache.put(3, "three", 1);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), Some(&"two"));
+    }
+
     // ========== Weighted Tests ==========
 
     #[test]
@@ -368,6 +1384,35 @@ mod tests {
         assert_eq!(cache.get(&3), Some(&"three"));
     }
 
+    #[test]
+    fn test_hot_heavy_entries_do_not_overflow() {
+        let mut cache = LFUCache::new(2);
+        // 5 * u32::MAX wraps around in u32 arithmetic
+        cache.put(1, "hot", u32::MAX);
+        for _ in 0..4 {
+            cache.get(&1);
+        }
+        cache.put(2, "cold", 1);
+        cache.put(3, "new", 1);
+
+        assert_eq!(cache.get_freq(&1), Some(5));
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+    }
+
+    #[test]
+    fn test_frequency_still_ranks_heavy_entries() {
+        let mut cache = LFUCache::new(2);
+        cache.put(1, "one", u32::MAX);
+        cache.put(2, "two", u32::MAX);
+        cache.get(&2);
+
+        // Key 2 is worth twice key 1, which a wrapped u32 product would invert
+        cache.put(3, "three", 1);
+        assert!(!cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+    }
+
     #[test]
     fn test_weighted_high_weight_survives() {
         let mut cache = LFUCache::new(2);
@@ -413,6 +1458,27 @@ mod tests {
         assert_eq!(cache.get(&3), Some(&"three"));
     }
 
+    #[test]
+    fn test_rewrite_takes_the_new_weight() {
+        let mut cache = LFUCache::new(10);
+        cache.set_max_weight(13);
+        cache.put(1, "one", 5);
+        cache.put(2, "two", 7);
+        cache.put(1, "ONE", 6);
+        assert_eq!(cache.stats().current_weight, 13);
+        assert_eq!(cache.entry_info(&1).unwrap().weight, 6);
+
+        // Growing past the budget evicts the other entry, even though 2 ranks higher
+        for _ in 0..5 {
+            cache.get(&2);
+        }
+        cache.put(1, "one again", 12);
+        assert_eq!(cache.get(&1), Some(&"one again"));
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.stats().current_weight, 12);
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
     #[test]
     fn test_weighted_complex_scenario() {
         let mut cache = LFUCache::new(3);
@@ -443,6 +1509,75 @@ mod tests {
         let _cache: LFUCache<i32, &str> = LFUCache::new(0);
     }
 
+    // ========== Frequency Bucket Tests ==========
+
+    #[test]
+    fn test_victim_matches_brute_force() {
+        let mut cache = LFUCache::new(8);
+        // key -> (freq, weight, last use)
+        let mut model: HashMap<u32, (u64, u64, u64)> = HashMap::new();
+
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for tick in 0..2000u64 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let key = (state % 20) as u32;
+            let weight = 1 + (state >> 8) % 3;
+
+            match (state >> 16) % 3 {
+                0 => {
+                    if cache.get(&key).is_some() {
+                        let entry = model.get_mut(&key).unwrap();
+                        entry.0 += 1;
+                        entry.2 = tick;
+                    }
+                }
+                1 => {
+                    if !model.contains_key(&key) && model.len() == 8 {
+                        let victim = *cache.peek_victim().unwrap();
+                        model.remove(&victim);
+                    }
+                    cache.put(key, (), weight as u32);
+                    let entry = model.entry(key).or_insert((0, weight, 0));
+                    entry.0 += 1;
+                    entry.1 = weight;
+                    entry.2 = tick;
+                }
+                _ => {
+                    cache.remove(&key);
+                    model.remove(&key);
+                }
+            }
+
+            let expected = model
+                .iter()
+                .min_by_key(|&(_, &(freq, weight, last_use))| (freq * weight, last_use))
+                .map(|(key, _)| key);
+            assert_eq!(cache.peek_victim(), expected);
+
+            let mut priorities: Vec<u64> = model
+                .values()
+                .map(|&(freq, weight, _)| freq * weight)
+                .collect();
+            priorities.sort_unstable();
+            priorities.dedup();
+            assert_eq!(cache.priority_to_bucket.len(), priorities.len());
+        }
+    }
+
+    #[test]
+    fn test_buckets_are_reused() {
+        let mut cache = LFUCache::new(2);
+        for round in 0..100 {
+            cache.put(round, round, 1);
+            cache.get(&round);
+            cache.get(&round);
+        }
+        assert!(cache.buckets.slots() <= 4);
+        assert_eq!(cache.iter().count(), 2);
+    }
+
     // ========== Large Capacity Tests ==========
 
     #[test]
@@ -474,4 +1609,47 @@ mod tests {
             assert_eq!(cache.get(&i), None);
         }
     }
+
+    #[test]
+    fn test_shrink_to_fit_keeps_buckets_valid() {
+        let mut cache = LFUCache::new(50);
+        for key in 0..50 {
+            cache.put(key, key, 1);
+            for _ in 0..key % 4 {
+                cache.get(&key);
+            }
+        }
+        for key in (0..50).filter(|key| key % 3 != 0) {
+            cache.remove(&key);
+        }
+
+        cache.shrink_to_fit();
+        assert_eq!(cache.nodes.slots(), cache.len());
+        assert_eq!(cache.len(), 17);
+        // Keys used once, oldest first
+        assert_eq!(cache.peek_victim(), Some(&0));
+        cache.get(&0);
+        cache.get(&0);
+        assert_eq!(cache.peek_victim(), Some(&12));
+        for key in (0..50).step_by(3) {
+            assert_eq!(cache.get(&key), Some(&key));
+        }
+    }
+
+    #[test]
+    fn test_keys_need_not_be_clone() {
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct Term(String);
+        let term = |s: &str| Term(s.to_string());
+
+        let mut cache = LFUCache::new(2);
+        cache.put(term("rust"), 1, 1);
+        cache.put(term("cache"), 2, 1);
+        cache.get(&term("rust"));
+        cache.put(term("slab"), 3, 1);
+
+        assert_eq!(cache.get_freq(&term("rust")), Some(2));
+        assert!(!cache.contains_key(&term("cache")));
+        assert_eq!(cache.pop_victim(), Some((term("slab"), 3)));
+    }
 }
@@ -0,0 +1,218 @@
+use std::hash::{BuildHasher, Hash};
+use std::io::{Read, Write};
+
+use super::LFUCache;
+use crate::expiry::SavedTimestamps;
+use crate::snapshot::{self, impl_codec, Decode, Encode, SnapshotError, SnapshotReader};
+use crate::stats::CacheStats;
+use crate::util::{self, Link};
+use crate::Policy;
+
+// What is saved of a cache, by serde and by snapshots alike. Entries run from the next
+// victim onwards: ascending priority, and least recently used first within a priority.
+// Restoring them in order rebuilds every bucket.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Saved<K, V> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    stats: CacheStats,
+    entries: Vec<SavedEntry<K, V>>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct SavedEntry<K, V> {
+    key: K,
+    value: V,
+    freq: usize,
+    weight: u32,
+    timestamps: SavedTimestamps,
+}
+
+impl_codec!(Saved<K, V> {
+    capacity,
+    max_weight,
+    stats,
+    entries,
+});
+impl_codec!(SavedEntry<K, V> {
+    key,
+    value,
+    freq,
+    weight,
+    timestamps,
+});
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone> LFUCache<K, V, S> {
+    fn save(&self) -> Saved<&K, &V> {
+        let now = self.clock.now();
+        let entries = self
+            .eviction_order()
+            .into_iter()
+            .rev()
+            .map(|idx| {
+                let node = self.node(idx);
+                SavedEntry {
+                    key: &node.key,
+                    value: &node.value,
+                    freq: node.freq,
+                    weight: node.weight,
+                    timestamps: SavedTimestamps::save(&node.timestamps, now),
+                }
+            })
+            .collect();
+        Saved {
+            capacity: self.capacity,
+            max_weight: self.max_weight,
+            stats: self.stats,
+            entries,
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Clone + Default> LFUCache<K, V, S> {
+    fn restore(saved: Saved<K, V>) -> Result<Self, &'static str> {
+        snapshot::check_len(saved.capacity, saved.max_weight, saved.entries.len())?;
+        let mut cache = LFUCache::with_hasher(saved.capacity, S::default());
+        cache.max_weight = saved.max_weight;
+        cache.stats = saved.stats;
+        cache.stats.current_weight = 0;
+        let now = cache.clock.now();
+        let mut last = Link::NONE;
+        for entry in saved.entries {
+            if cache.index_of(&entry.key).is_some() {
+                return Err("duplicate key");
+            }
+            cache.stats.current_weight += u64::from(entry.weight);
+            let slot = cache.allocate_node(entry.key, entry.value, entry.freq, entry.weight);
+            let idx = slot.index();
+            cache.key_to_idx.insert(slot, &cache.nodes);
+            cache.node_mut(idx).timestamps = entry.timestamps.restore(now);
+
+            // Saved entries arrive in ascending priority, so each bucket is found by
+            // walking up from the previous one; anything else searches from the bottom
+            let priority = cache.node(idx).priority();
+            let from = match last.get() {
+                Some(bucket_idx) if cache.buckets[bucket_idx].priority <= priority => last,
+                _ => Link::NONE,
+            };
+            let bucket_idx = cache.bucket_for(priority, from);
+            cache.push_front(idx, bucket_idx);
+            last = Link::some(bucket_idx);
+        }
+        Ok(cache)
+    }
+}
+
+impl<K: Hash + Eq + Encode, V: Encode, S: BuildHasher + Clone> LFUCache<K, V, S> {
+    // Writes the entries and their frequencies as a checksummed binary snapshot.
+    pub fn save_to<W: Write>(&self, writer: W) -> Result<(), SnapshotError> {
+        snapshot::write(writer, Policy::LFU, &self.save())
+    }
+}
+
+impl<K: Hash + Eq + Decode, V: Decode, S: BuildHasher + Clone + Default> LFUCache<K, V, S> {
+    pub fn load_from<R: Read>(reader: R) -> Result<Self, SnapshotError> {
+        LFUCache::load_snapshot(SnapshotReader::open(reader)?)
+    }
+
+    pub(crate) fn load_snapshot<R: Read>(
+        snapshot: SnapshotReader<R>,
+    ) -> Result<Self, SnapshotError> {
+        let saved = snapshot.body(Policy::LFU)?;
+        LFUCache::restore(saved).map_err(SnapshotError::Invalid)
+    }
+}
+
+impl<K: Clone + Hash + Eq, V: Clone, S: BuildHasher + Clone> LFUCache<K, V, S> {
+    // The `n` entries that would be evicted last, highest priority first, with their
+    // weights.
+    pub fn export_top(&self, n: usize) -> Vec<(K, V, u32)> {
+        util::top_entries(self.iter(), n, |key| self.entry_info(key))
+    }
+
+    // Stores exported entries, hottest first. Each starts as if used once since its
+    // insert, so the next new key is evicted before any of them; among themselves they
+    // keep their order.
+    pub fn import<I: IntoIterator<Item = (K, V, u32)>>(&mut self, entries: I) {
+        let entries: Vec<_> = entries.into_iter().collect();
+        for (key, value, weight) in entries.into_iter().rev() {
+            self.put(key.clone(), value, weight);
+            if let Some(idx) = self.index_of(&key) {
+                self.increment_priority(idx);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V, S> serde::Serialize for LFUCache<K, V, S>
+where
+    K: Hash + Eq + serde::Serialize,
+    V: serde::Serialize,
+    S: BuildHasher + Clone,
+{
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        serde::Serialize::serialize(&self.save(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, S> serde::Deserialize<'de> for LFUCache<K, V, S>
+where
+    K: Hash + Eq + serde::Deserialize<'de>,
+    V: serde::Deserialize<'de>,
+    S: BuildHasher + Clone + Default,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = serde::Deserialize::deserialize(deserializer)?;
+        LFUCache::restore(saved).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::RandomState;
+
+    fn used() -> LFUCache<String, i32> {
+        let mut cache = LFUCache::new(4);
+        cache.put("a".to_string(), 1, 1);
+        cache.put("b".to_string(), 2, 3);
+        cache.put("c".to_string(), 3, 1);
+        cache.put("d".to_string(), 4, 1);
+        cache.get(&"a".to_string());
+        cache.get(&"a".to_string());
+        cache.get(&"d".to_string());
+        cache
+    }
+
+    fn assert_same(
+        mut restored: LFUCache<String, i32, RandomState>,
+        mut cache: LFUCache<String, i32>,
+    ) {
+        assert_eq!(restored.stats(), cache.stats());
+        assert_eq!(restored.get_freq(&"a".to_string()), Some(3));
+        let order: Vec<&String> = restored.iter().map(|(key, _)| key).collect();
+        let original: Vec<&String> = cache.iter().map(|(key, _)| key).collect();
+        assert_eq!(order, original);
+        for _ in 0..4 {
+            assert_eq!(restored.pop_victim(), cache.pop_victim());
+        }
+    }
+
+    #[test]
+    fn test_snapshot_keeps_frequencies_and_order() {
+        let cache = used();
+        let mut bytes = Vec::new();
+        cache.save_to(&mut bytes).unwrap();
+        assert_same(LFUCache::load_from(bytes.as_slice()).unwrap(), cache);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_keeps_frequencies_and_order() {
+        let cache = used();
+        let json = serde_json::to_string(&cache).unwrap();
+        assert_same(serde_json::from_str(&json).unwrap(), cache);
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+use super::LFUDACache;
+
+pub enum Entry<'a, K, V, S = RandomState> {
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+pub struct OccupiedEntry<'a, K, V, S = RandomState> {
+    pub(super) cache: &'a mut LFUDACache<K, V, S>,
+    pub(super) key: K,
+}
+
+pub struct VacantEntry<'a, K, V, S = RandomState> {
+    pub(super) cache: &'a mut LFUDACache<K, V, S>,
+    pub(super) key: K,
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Entry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, value: V, weight: u32) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(value, weight),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, weight: u32, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(f(), weight),
+        }
+    }
+
+    // Computes both the value and its weight from the key on a miss.
+    pub fn or_insert_with_key<F: FnOnce(&K) -> (V, u32)>(self, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let (value, weight) = f(entry.key());
+                entry.insert(value, weight)
+            }
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher + Clone> OccupiedEntry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.cache.cache[&self.key].value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self
+            .cache
+            .cache
+            .get_mut(&self.key)
+            .expect("occupied entry is present")
+            .value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self
+            .cache
+            .cache
+            .get_mut(&self.key)
+            .expect("occupied entry is present")
+            .value
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        let now = self.cache.clock.now();
+        let lfuda_node = self
+            .cache
+            .cache
+            .get_mut(&self.key)
+            .expect("occupied entry is present");
+        lfuda_node.timestamps.rewrite(now);
+        let previous = mem::replace(&mut lfuda_node.value, value);
+        let key = &self.key;
+        self.cache.notify(|observer| observer.on_update(key));
+        previous
+    }
+
+    pub fn remove(self) -> V {
+        self.cache
+            .remove(&self.key)
+            .expect("occupied entry is present")
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher + Clone> VacantEntry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V, weight: u32) -> &'a mut V {
+        self.cache.insert_new(self.key.clone(), value, weight);
+        &mut self
+            .cache
+            .cache
+            .get_mut(&self.key)
+            .expect("entry was just inserted")
+            .value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::LFUDACache;
+    use super::Entry;
+
+    #[test]
+    fn test_or_insert_counts_as_a_hit() {
+        let mut cache = LFUDACache::new(2);
+
+        *cache.entry("a").or_insert(0, 1) += 1;
+        *cache.entry("a").or_insert(0, 1) += 1;
+
+        assert_eq!(cache.peek(&"a"), Some(&2));
+        assert_eq!(cache.get_freq(&"a"), Some(2));
+    }
+
+    #[test]
+    fn test_occupied_insert_and_remove() {
+        let mut cache = LFUDACache::new(2);
+        cache.put(1, "one", 1);
+
+        match cache.entry(1) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.get(), &"one");
+                assert_eq!(entry.insert("ONE"), "one");
+                assert_eq!(entry.remove(), "ONE");
+            }
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_vacant_insert_evicts() {
+        let mut cache = LFUDACache::new(1);
+        cache.put(1, "one", 1);
+
+        match cache.entry(2) {
+            Entry::Vacant(entry) => entry.insert("two", 1),
+            Entry::Occupied(_) => panic!("expected a vacant entry"),
+        };
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.peek(&2), Some(&"two"));
+    }
+}
@@ -0,0 +1,807 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::expiry::{Expiration, Freshness, Timestamps, TimingWheel, WHEEL_RESOLUTION};
+use crate::observer::{BoxedObserver, CacheObserver};
+use crate::stats::{CacheStats, EntryInfo};
+use crate::trace;
+use crate::util::PriorityHeap;
+use crate::{Cache, EvictionListener, NewCache, Weigher};
+
+mod entry;
+mod persist;
+
+const POLICY: &str = "lfuda";
+
+pub use self::entry::{Entry, OccupiedEntry, VacantEntry};
+
+struct LFUDANode<V> {
+    value: V,
+    // The cost of the entry; its priority grows by this much with every hit.
+    weight: u32,
+    freq: u64,
+    timestamps: Timestamps,
+}
+
+pub struct Iter<'a, K, V> {
+    entries: std::vec::IntoIter<(&'a K, &'a V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+pub struct IntoIter<K, V> {
+    entries: std::vec::IntoIter<(K, V)>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+// LFU with dynamic aging: an entry's priority is `freq * weight + age`, where `age` is
+// raised to the priority of every victim. Entries that were hot long ago keep their old,
+// low age, so newer entries overtake them instead of the cache filling with stale
+// favourites.
+pub struct LFUDACache<K, V, S = RandomState> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    age: u64,
+    pq: PriorityHeap<K, Reverse<u64>, S>,
+    cache: HashMap<K, LFUDANode<V>, S>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    weigher: Option<Weigher<K, V>>,
+    observer: Option<BoxedObserver<K>>,
+    stats: CacheStats,
+    expiration: Expiration,
+    expiry_wheel: Option<TimingWheel<K>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K: Clone + Hash + Eq, V> LFUDACache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        LFUDACache::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> LFUDACache<K, V, S> {
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        LFUDACache {
+            capacity,
+            max_weight: None,
+            age: 0,
+            pq: PriorityHeap::with_hasher(hash_builder.clone()),
+            cache: HashMap::with_hasher(hash_builder),
+            eviction_listener: None,
+            weigher: None,
+            observer: None,
+            stats: CacheStats::default(),
+            expiration: Expiration::default(),
+            expiry_wheel: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let _span = trace::get_span(POLICY, key);
+        if !self.is_live(key) {
+            return None;
+        }
+        self.bump(key);
+        self.cache.get(key).map(|lfuda_node| &lfuda_node.value)
+    }
+
+    pub fn get_stale(&mut self, key: &K) -> Option<Freshness<&V>> {
+        let now = self.clock.now();
+        let expired = self
+            .cache
+            .get(key)
+            .is_some_and(|lfuda_node| self.expiration.is_expired(&lfuda_node.timestamps, now));
+        if !expired {
+            return self.get(key).map(Freshness::Fresh);
+        }
+        self.stats.misses += 1;
+        trace::miss(POLICY, key);
+        self.notify(|observer| observer.on_miss(key));
+        self.cache
+            .get(key)
+            .map(|lfuda_node| Freshness::Stale(&lfuda_node.value))
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if !self.is_live(key) {
+            return None;
+        }
+        self.bump(key);
+        self.cache
+            .get_mut(key)
+            .map(|lfuda_node| &mut lfuda_node.value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        let now = self.clock.now();
+        self.cache
+            .get(key)
+            .filter(|lfuda_node| !self.expiration.is_expired(&lfuda_node.timestamps, now))
+            .map(|lfuda_node| &lfuda_node.value)
+    }
+
+    // Like `peek`, this does not count as a use of the key.
+    pub fn entry_info(&self, key: &K) -> Option<EntryInfo> {
+        let now = self.clock.now();
+        self.cache
+            .get(key)
+            .filter(|lfuda_node| !self.expiration.is_expired(&lfuda_node.timestamps, now))
+            .map(|lfuda_node| {
+                EntryInfo::new(&lfuda_node.timestamps, lfuda_node.weight, &self.expiration)
+            })
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+
+    // Replacing a value counts as a use: the entry keeps its frequency and gains one.
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        let _span = trace::put_span(POLICY, &key, weight);
+        match self.cache.get(&key).map(|lfuda_node| lfuda_node.freq) {
+            Some(freq) => {
+                self.remove(&key);
+                self.notify(|observer| observer.on_update(&key));
+                self.store(key, value, weight, freq.saturating_add(1));
+            }
+            None => self.insert_new(key, value, weight),
+        }
+    }
+
+    // Stores the value with the weight the weigher gives it, or a weight of 1 without one.
+    pub fn insert(&mut self, key: K, value: V) {
+        let weight = self
+            .weigher
+            .as_ref()
+            .map_or(1, |weigher| weigher(&key, &value));
+        self.put(key, value, weight);
+    }
+
+    // An occupied entry counts as a hit, just like `get`.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        if self.is_live(&key) {
+            self.bump(&key);
+            Entry::Occupied(OccupiedEntry { cache: self, key })
+        } else {
+            Entry::Vacant(VacantEntry { cache: self, key })
+        }
+    }
+
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, weight: u32, f: F) -> &V {
+        self.entry(key).or_insert_with(weight, f)
+    }
+
+    // A failed computation leaves the cache untouched and returns the error.
+    pub fn try_get_or_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        weight: u32,
+        f: F,
+    ) -> Result<&V, E> {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(f()?, weight)),
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let lfuda_node = self.cache.remove(key)?;
+        self.pq.remove(key);
+        self.stats.current_weight -= u64::from(lfuda_node.weight);
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.cancel(key);
+        }
+        Some(lfuda_node.value)
+    }
+
+    // The entry `pop_victim` would remove, left in place.
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.pq.peek().map(|(key, _)| key)
+    }
+
+    // The victim's priority becomes the new age.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        let (key, Reverse(priority)) = self.pq.pop()?;
+        self.age = priority;
+        let lfuda_node = self.cache.remove(&key)?;
+        self.stats.current_weight -= u64::from(lfuda_node.weight);
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.cancel(&key);
+        }
+        Some((key, lfuda_node.value))
+    }
+
+    // Evicts entries as a full cache would, listener and stats included, until at most
+    // `len` are left. Returns how many were evicted.
+    pub fn evict_to(&mut self, len: usize) -> usize {
+        let mut evicted = 0;
+        while self.len() > len {
+            self.evict();
+            evicted += 1;
+        }
+        evicted
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        let doomed: Vec<K> = self
+            .cache
+            .iter()
+            .filter(|&(key, lfuda_node)| !f(key, &lfuda_node.value))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in doomed {
+            self.remove(&key);
+        }
+    }
+
+    pub fn set_max_weight(&mut self, max_weight: u64) {
+        self.max_weight = Some(max_weight);
+        while !self.is_empty() && self.is_over_budget() {
+            self.evict();
+        }
+    }
+
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.set_boxed_eviction_listener(Box::new(listener));
+    }
+
+    pub(crate) fn set_boxed_eviction_listener(&mut self, listener: EvictionListener<K, V>) {
+        self.eviction_listener = Some(listener);
+    }
+
+    // Computes the weight of entries stored with `insert`.
+    pub fn set_weigher<F: Fn(&K, &V) -> u32 + Send + 'static>(&mut self, weigher: F) {
+        self.set_boxed_weigher(Box::new(weigher));
+    }
+
+    pub(crate) fn set_boxed_weigher(&mut self, weigher: Weigher<K, V>) {
+        self.weigher = Some(weigher);
+    }
+
+    pub fn set_observer<O: CacheObserver<K> + Send + 'static>(&mut self, observer: O) {
+        self.set_boxed_observer(Box::new(observer));
+    }
+
+    pub(crate) fn set_boxed_observer(&mut self, observer: BoxedObserver<K>) {
+        self.observer = Some(observer);
+    }
+
+    pub fn set_expire_after_write(&mut self, time_to_live: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_live = Some(time_to_live);
+        self.set_expiration(expiration);
+    }
+
+    pub fn set_expire_after_access(&mut self, time_to_idle: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_idle = Some(time_to_idle);
+        self.set_expiration(expiration);
+    }
+
+    // Existing entries are scheduled against the new limits.
+    pub(crate) fn set_expiration(&mut self, expiration: Expiration) {
+        self.expiration = expiration;
+        self.expiry_wheel = if expiration.is_enabled() {
+            Some(TimingWheel::new(self.clock.now(), WHEEL_RESOLUTION))
+        } else {
+            None
+        };
+        let live: Vec<K> = self.cache.keys().cloned().collect();
+        for key in live {
+            self.schedule_expiry(key);
+        }
+    }
+
+    // Replaces the time source; entries keep the timestamps they were given by the old one.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+        let expiration = self.expiration;
+        self.set_expiration(expiration);
+    }
+
+    pub fn expire_due(&mut self, now: Instant) -> usize {
+        let due = match self.expiry_wheel.as_mut() {
+            Some(wheel) => wheel.advance(now),
+            None => return 0,
+        };
+
+        let mut expired = 0;
+        for key in due {
+            let expired_now = match self.cache.get(&key) {
+                Some(lfuda_node) => self.expiration.is_expired(&lfuda_node.timestamps, now),
+                None => continue,
+            };
+            if expired_now {
+                self.remove(&key);
+                expired += 1;
+            } else {
+                // Touched or rewritten since it was scheduled
+                self.schedule_expiry(key);
+            }
+        }
+        expired
+    }
+
+    pub fn clear(&mut self) {
+        self.pq.clear();
+        self.cache.clear();
+        self.age = 0;
+        self.stats.current_weight = 0;
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.clear();
+        }
+    }
+
+    // Releases the memory the queue and the map no longer need.
+    pub fn shrink_to_fit(&mut self) {
+        self.pq.shrink_to_fit();
+        self.cache.shrink_to_fit();
+    }
+
+    // Reserves room for a full cache. The map gets twice that, so the churn of evictions
+    // rehashes it in place instead of growing it.
+    pub fn preallocate(&mut self) {
+        self.pq.reserve(self.capacity);
+        self.cache
+            .reserve((2 * self.capacity).saturating_sub(self.cache.len()));
+    }
+
+    // Entries from highest priority to the next victim.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut by_priority: Vec<(&K, u64)> = self
+            .pq
+            .iter()
+            .map(|(key, &Reverse(priority))| (key, priority))
+            .collect();
+        by_priority.sort_by_key(|&(_, priority)| Reverse(priority));
+
+        let entries: Vec<(&K, &V)> = by_priority
+            .into_iter()
+            .map(|(key, _)| (key, &self.cache[key].value))
+            .collect();
+
+        Iter {
+            entries: entries.into_iter(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    pub fn get_freq(&self, key: &K) -> Option<u64> {
+        self.cache.get(key).map(|lfuda_node| lfuda_node.freq)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.pq.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pq.is_empty()
+    }
+
+    fn insert_new(&mut self, key: K, value: V, weight: u32) {
+        self.stats.insertions += 1;
+        trace::insert(POLICY, &key, weight);
+        self.notify(|observer| observer.on_insert(&key));
+        self.store(key, value, weight, 1);
+    }
+
+    fn store(&mut self, key: K, value: V, weight: u32, freq: u64) {
+        self.make_room(weight);
+        let lfuda_node = LFUDANode {
+            value,
+            weight,
+            freq,
+            timestamps: Timestamps::new(self.clock.now()),
+        };
+        let priority = self.priority(&lfuda_node);
+        self.cache.insert(key.clone(), lfuda_node);
+        self.pq.push(key.clone(), Reverse(priority));
+        self.stats.current_weight += u64::from(weight);
+        self.schedule_expiry(key);
+    }
+
+    // Records a hit on a live key and raises its priority to match.
+    fn bump(&mut self, key: &K) {
+        let age = self.age;
+        if let Some(lfuda_node) = self.cache.get_mut(key) {
+            lfuda_node.freq = lfuda_node.freq.saturating_add(1);
+            let priority = priority(lfuda_node, age);
+            self.pq.change_priority(key, Reverse(priority));
+        }
+    }
+
+    fn priority(&self, lfuda_node: &LFUDANode<V>) -> u64 {
+        priority(lfuda_node, self.age)
+    }
+
+    // Whether an entry of this weight only fits after an eviction.
+    fn is_full(&self, weight: u32) -> bool {
+        match self.max_weight {
+            Some(max_weight) => self.stats.current_weight + u64::from(weight) > max_weight,
+            None => self.len() >= self.capacity,
+        }
+    }
+
+    fn is_over_budget(&self) -> bool {
+        self.max_weight
+            .is_some_and(|max_weight| self.stats.current_weight > max_weight)
+    }
+
+    fn make_room(&mut self, weight: u32) {
+        while !self.is_empty() && self.is_full(weight) {
+            self.evict();
+        }
+    }
+
+    fn schedule_expiry(&mut self, key: K) {
+        let deadline = self
+            .cache
+            .get(&key)
+            .and_then(|lfuda_node| self.expiration.deadline(&lfuda_node.timestamps));
+        if let (Some(wheel), Some(deadline)) = (self.expiry_wheel.as_mut(), deadline) {
+            wheel.schedule(key, deadline);
+        }
+    }
+
+    // Drops the key if it has expired, otherwise records the access.
+    // This is the single place hits and misses are observed.
+    fn is_live(&mut self, key: &K) -> bool {
+        let now = self.clock.now();
+        let live = match self.cache.get_mut(key) {
+            Some(lfuda_node) => {
+                let expired = self.expiration.is_expired(&lfuda_node.timestamps, now);
+                if !expired {
+                    lfuda_node.timestamps.touch(now);
+                }
+                !expired
+            }
+            None => false,
+        };
+        if live {
+            self.stats.hits += 1;
+            self.notify(|observer| observer.on_hit(key));
+        } else {
+            self.remove(key);
+            self.stats.misses += 1;
+            trace::miss(POLICY, key);
+            self.notify(|observer| observer.on_miss(key));
+        }
+        live
+    }
+
+    fn notify<F: FnOnce(&mut dyn CacheObserver<K>)>(&mut self, f: F) {
+        if let Some(observer) = self.observer.as_mut() {
+            f(&mut **observer);
+        }
+    }
+
+    // Capacity-driven eviction; the listener sees every entry dropped this way.
+    fn evict(&mut self) {
+        if let Some((key, value)) = self.pop_victim() {
+            self.stats.evictions += 1;
+            trace::eviction(POLICY, &key);
+            if let Some(listener) = self.eviction_listener.as_mut() {
+                listener(key, value);
+            }
+        }
+    }
+}
+
+// Saturates rather than wrapping, like the LFU priorities.
+fn priority<V>(lfuda_node: &LFUDANode<V>, age: u64) -> u64 {
+    lfuda_node
+        .freq
+        .saturating_mul(u64::from(lfuda_node.weight))
+        .saturating_add(age)
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> IntoIterator for LFUDACache<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        let mut cache = self.cache;
+        let mut by_priority: Vec<(K, u64)> = self
+            .pq
+            .into_iter()
+            .map(|(key, Reverse(priority))| (key, priority))
+            .collect();
+        by_priority.sort_by_key(|&(_, priority)| Reverse(priority));
+
+        let entries: Vec<(K, V)> = by_priority
+            .into_iter()
+            .filter_map(|(key, _)| {
+                let lfuda_node = cache.remove(&key)?;
+                Some((key, lfuda_node.value))
+            })
+            .collect();
+
+        IntoIter {
+            entries: entries.into_iter(),
+        }
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher + Clone> IntoIterator for &'a LFUDACache<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+// The capacity is sized to hold every collected entry.
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone + Default> FromIterator<(K, V, u32)>
+    for LFUDACache<K, V, S>
+{
+    fn from_iter<I: IntoIterator<Item = (K, V, u32)>>(iter: I) -> Self {
+        let entries: Vec<_> = iter.into_iter().collect();
+        let mut cache = LFUDACache::with_hasher(entries.len().max(1), S::default());
+        cache.extend(entries);
+        cache
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Extend<(K, V, u32)> for LFUDACache<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V, u32)>>(&mut self, iter: I) {
+        for (key, value, weight) in iter {
+            self.put(key, value, weight);
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Cache<K, V> for LFUDACache<K, V, S> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone + Default> NewCache<K, V>
+    for LFUDACache<K, V, S>
+{
+    fn new(capacity: usize) -> Self {
+        LFUDACache::with_hasher(capacity, S::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    #[should_panic(expected = "Capacity must be greater than 0")]
+    fn test_new_cache_zero_capacity() {
+        let _cache: LFUDACache<String, i32> = LFUDACache::new(0);
+    }
+
+    #[test]
+    fn test_basic_operations() {
+        let mut cache = LFUDACache::new(2);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.get(&2), Some(&"two"));
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_evicts_lowest_frequency_times_cost() {
+        let mut cache = LFUDACache::new(2);
+        cache.put(1, "one", 3); // priority 3
+        cache.put(2, "two", 1); // priority 1
+        cache.get(&2); // priority 2
+
+        cache.put(3, "three", 1);
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.age, 2);
+    }
+
+    #[test]
+    fn test_aging_retires_formerly_hot_entries() {
+        let mut cache = LFUDACache::new(2);
+        cache.put(-1, "favourite", 1);
+        for _ in 0..4 {
+            cache.get(&-1);
+        }
+        assert_eq!(cache.get_freq(&-1), Some(5));
+
+        // Plain LFU would keep the favourite forever; here each eviction raises the age
+        // that newcomers start from, until they outrank it
+        for key in 0..10 {
+            cache.put(key, "newcomer", 1);
+        }
+        assert!(!cache.contains_key(&-1));
+        assert!(cache.age >= 5);
+    }
+
+    #[test]
+    fn test_update_keeps_frequency() {
+        let mut cache = LFUDACache::new(2);
+        cache.put(1, "one", 1);
+        cache.get(&1);
+        cache.put(1, "ONE", 1);
+
+        assert_eq!(cache.get_freq(&1), Some(3));
+        assert_eq!(cache.peek(&1), Some(&"ONE"));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_peek_does_not_bump() {
+        let mut cache = LFUDACache::new(2);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.get(&2);
+        cache.peek(&1);
+
+        cache.put(3, "three", 1);
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.get_freq(&2), Some(2));
+    }
+
+    #[test]
+    fn test_remove_and_pop_victim() {
+        let mut cache = LFUDACache::new(3);
+        cache.put(1, "one", 5);
+        cache.put(2, "two", 1);
+        cache.put(3, "three", 3);
+
+        assert_eq!(cache.remove(&3), Some("three"));
+        assert_eq!(cache.remove(&3), None);
+        assert_eq!(cache.pop_victim(), Some((2, "two")));
+        assert_eq!(cache.age, 1);
+        assert_eq!(cache.pop_victim(), Some((1, "one")));
+        assert_eq!(cache.pop_victim(), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_iter_in_priority_order() {
+        let mut cache = LFUDACache::new(3);
+        cache.put(1, "one", 2);
+        cache.put(2, "two", 5);
+        cache.put(3, "three", 1);
+
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&2, &1, &3]);
+        let entries: Vec<_> = cache.into_iter().collect();
+        assert_eq!(entries, vec![(2, "two"), (1, "one"), (3, "three")]);
+    }
+
+    #[test]
+    fn test_retain_and_clear() {
+        let mut cache: LFUDACache<_, _> = (1..=4).map(|i| (i, i * 10, 1)).collect();
+        cache.retain(|&key, _| key % 2 == 0);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains_key(&2));
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.age, 0);
+        assert_eq!(cache.stats().current_weight, 0);
+    }
+
+    #[test]
+    fn test_eviction_listener() {
+        use std::sync::Mutex;
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = LFUDACache::new(1);
+        cache.set_eviction_listener(move |key, value| sink.lock().unwrap().push((key, value)));
+
+        cache.put(1, "one", 1);
+        cache.remove(&1);
+        cache.put(2, "two", 1);
+        cache.put(3, "three", 1);
+        assert_eq!(*evicted.lock().unwrap(), vec![(2, "two")]);
+    }
+
+    #[test]
+    fn test_expire_after_write() {
+        let clock = ManualClock::new();
+        let mut cache = LFUDACache::new(2);
+        cache.set_clock(Arc::new(clock.clone()));
+        cache.set_expire_after_write(Duration::from_secs(10));
+
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        clock.advance(Duration::from_secs(5));
+        cache.put(2, "TWO", 1);
+        clock.advance(Duration::from_secs(5));
+
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.expire_due(clock.now()), 1);
+        assert_eq!(cache.get(&2), Some(&"TWO"));
+    }
+}
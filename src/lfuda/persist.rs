@@ -0,0 +1,220 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::hash::{BuildHasher, Hash};
+use std::io::{Read, Write};
+
+use super::{LFUDACache, LFUDANode};
+use crate::expiry::SavedTimestamps;
+use crate::snapshot::{self, impl_codec, Decode, Encode, SnapshotError, SnapshotReader};
+use crate::stats::CacheStats;
+use crate::util;
+use crate::Policy;
+
+// What is saved of a cache, by serde and by snapshots alike. Priorities are saved as they
+// stand rather than recomputed, since each one includes the age at the entry's last hit.
+// Entries are saved in heap order, which pushing them back in the same order leaves
+// untouched.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Saved<K, V> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    stats: CacheStats,
+    age: u64,
+    entries: Vec<SavedEntry<K, V>>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct SavedEntry<K, V> {
+    key: K,
+    value: V,
+    weight: u32,
+    freq: u64,
+    priority: u64,
+    timestamps: SavedTimestamps,
+}
+
+impl_codec!(Saved<K, V> {
+    capacity,
+    max_weight,
+    stats,
+    age,
+    entries,
+});
+impl_codec!(SavedEntry<K, V> {
+    key,
+    value,
+    weight,
+    freq,
+    priority,
+    timestamps,
+});
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> LFUDACache<K, V, S> {
+    fn save(&self) -> Saved<&K, &V> {
+        let now = self.clock.now();
+        let entries = self
+            .pq
+            .iter()
+            .map(|(key, &Reverse(priority))| {
+                let lfuda_node = &self.cache[key];
+                SavedEntry {
+                    key,
+                    value: &lfuda_node.value,
+                    weight: lfuda_node.weight,
+                    freq: lfuda_node.freq,
+                    priority,
+                    timestamps: SavedTimestamps::save(&lfuda_node.timestamps, now),
+                }
+            })
+            .collect();
+        Saved {
+            capacity: self.capacity,
+            max_weight: self.max_weight,
+            stats: self.stats,
+            age: self.age,
+            entries,
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone + Default> LFUDACache<K, V, S> {
+    fn restore(saved: Saved<K, V>) -> Result<Self, &'static str> {
+        snapshot::check_len(saved.capacity, saved.max_weight, saved.entries.len())?;
+        let mut cache = LFUDACache::with_hasher(saved.capacity, S::default());
+        cache.max_weight = saved.max_weight;
+        cache.age = saved.age;
+        cache.stats = saved.stats;
+        cache.stats.current_weight = 0;
+        let now = cache.clock.now();
+        for entry in saved.entries {
+            match cache.cache.entry(entry.key.clone()) {
+                Entry::Occupied(_) => return Err("duplicate key"),
+                Entry::Vacant(slot) => slot.insert(LFUDANode {
+                    value: entry.value,
+                    weight: entry.weight,
+                    freq: entry.freq,
+                    timestamps: entry.timestamps.restore(now),
+                }),
+            };
+            cache.stats.current_weight += u64::from(entry.weight);
+            cache.pq.push(entry.key, Reverse(entry.priority));
+        }
+        Ok(cache)
+    }
+}
+
+impl<K: Clone + Hash + Eq + Encode, V: Encode, S: BuildHasher + Clone> LFUDACache<K, V, S> {
+    // Writes the entries, their priorities and the age as a checksummed binary snapshot.
+    pub fn save_to<W: Write>(&self, writer: W) -> Result<(), SnapshotError> {
+        snapshot::write(writer, Policy::LFUDA, &self.save())
+    }
+}
+
+impl<K, V, S> LFUDACache<K, V, S>
+where
+    K: Clone + Hash + Eq + Decode,
+    V: Decode,
+    S: BuildHasher + Clone + Default,
+{
+    pub fn load_from<R: Read>(reader: R) -> Result<Self, SnapshotError> {
+        LFUDACache::load_snapshot(SnapshotReader::open(reader)?)
+    }
+
+    pub(crate) fn load_snapshot<R: Read>(
+        snapshot: SnapshotReader<R>,
+    ) -> Result<Self, SnapshotError> {
+        let saved = snapshot.body(Policy::LFUDA)?;
+        LFUDACache::restore(saved).map_err(SnapshotError::Invalid)
+    }
+}
+
+impl<K: Clone + Hash + Eq, V: Clone, S: BuildHasher + Clone> LFUDACache<K, V, S> {
+    // The `n` entries that would be evicted last, highest priority first, with their
+    // weights.
+    pub fn export_top(&self, n: usize) -> Vec<(K, V, u32)> {
+        util::top_entries(self.iter(), n, |key| self.entry_info(key))
+    }
+
+    // Stores exported entries, hottest first. Each starts as if used once since its
+    // insert, so the next new key is evicted before any of them.
+    pub fn import<I: IntoIterator<Item = (K, V, u32)>>(&mut self, entries: I) {
+        let entries: Vec<_> = entries.into_iter().collect();
+        for (key, value, weight) in entries.into_iter().rev() {
+            self.put(key.clone(), value, weight);
+            self.bump(&key);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V, S> serde::Serialize for LFUDACache<K, V, S>
+where
+    K: Clone + Hash + Eq + serde::Serialize,
+    V: serde::Serialize,
+    S: BuildHasher + Clone,
+{
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        serde::Serialize::serialize(&self.save(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, S> serde::Deserialize<'de> for LFUDACache<K, V, S>
+where
+    K: Clone + Hash + Eq + serde::Deserialize<'de>,
+    V: serde::Deserialize<'de>,
+    S: BuildHasher + Clone + Default,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = serde::Deserialize::deserialize(deserializer)?;
+        LFUDACache::restore(saved).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::RandomState;
+
+    fn used() -> LFUDACache<i32, i32> {
+        let mut cache = LFUDACache::new(2);
+        cache.put(1, 10, 1);
+        cache.get(&1);
+        cache.get(&1);
+        cache.put(2, 20, 1);
+        cache.put(3, 30, 1);
+        cache.get(&3);
+        cache
+    }
+
+    fn assert_same(
+        mut restored: LFUDACache<i32, i32, RandomState>,
+        mut cache: LFUDACache<i32, i32>,
+    ) {
+        assert_eq!(restored.stats(), cache.stats());
+        assert_eq!(restored.age, cache.age);
+        assert_eq!(restored.get_freq(&1), Some(3));
+        assert_eq!(restored.pq.get_priority(&3), cache.pq.get_priority(&3));
+
+        restored.put(4, 40, 1);
+        cache.put(4, 40, 1);
+        assert_eq!(restored.pop_victim(), cache.pop_victim());
+        assert_eq!(restored.pop_victim(), cache.pop_victim());
+    }
+
+    #[test]
+    fn test_snapshot_keeps_age_and_priorities() {
+        let cache = used();
+        let mut bytes = Vec::new();
+        cache.save_to(&mut bytes).unwrap();
+        assert_same(LFUDACache::load_from(bytes.as_slice()).unwrap(), cache);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_keeps_age_and_priorities() {
+        let cache = used();
+        let json = serde_json::to_string(&cache).unwrap();
+        assert_same(serde_json::from_str(&json).unwrap(), cache);
+    }
+}
@@ -1,20 +1,400 @@
-extern crate priority_queue;
+extern crate arc_swap;
+extern crate crossbeam_queue;
+extern crate hashbrown;
+#[cfg(feature = "memmap2")]
+extern crate memmap2;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
+pub mod adaptive;
+pub mod admission;
+pub mod arc;
+#[cfg(feature = "tokio")]
+pub mod async_cache;
+pub mod autocomplete;
+pub mod buffered;
+pub mod builder;
+pub mod clock;
+pub mod compare;
+pub mod concurrent;
+pub mod document;
+pub mod epoch;
+pub mod expiry;
+pub mod fifo;
+pub mod frequency;
+pub mod gdsf;
+pub mod greedy_dual;
+pub mod invalidation;
+pub mod journal;
 pub mod landlord;
 pub mod lfu_w;
+pub mod lfuda;
+pub mod loading;
 pub mod lru;
+pub mod maintenance;
+pub mod mq;
+pub mod mru;
+pub mod negative;
+pub mod observer;
+pub mod oracle;
+pub mod pages;
+pub mod pairs;
+pub mod partitioned;
+pub mod persistence;
+pub mod pinned;
+pub mod postings;
+pub mod prefix;
+pub mod profiling;
+pub mod query;
+pub mod queue;
+pub mod random;
+pub mod read_mostly;
+pub mod recording;
+pub mod refresh;
+pub mod replay;
+pub mod scored;
+pub mod sdc;
+pub mod shadow;
+pub mod sharded;
+pub mod slru;
+pub mod snapshot;
+pub mod spill;
+pub mod stack;
+#[cfg(feature = "memmap2")]
+pub mod static_cache;
+pub mod stats;
+pub mod store;
+pub mod sync_cache;
+pub mod tiered;
+pub mod tinylfu;
+pub mod topk;
+mod trace;
+pub mod util;
+pub mod warmup;
+pub mod weight;
+pub mod workload;
+pub mod wtinylfu;
 
-use std::hash::Hash;
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::io::{Read, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+pub use adaptive::AdaptiveCache;
+pub use admission::{
+    AdmissionCache, AdmissionPolicy, AlwaysAdmit, FrequencyThreshold, SizeProbability,
+};
+use arc::ARCCache;
+#[cfg(feature = "tokio")]
+pub use async_cache::AsyncCache;
+pub use autocomplete::AutocompleteCache;
+pub use buffered::BufferedCache;
+pub use builder::CacheBuilder;
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use compare::{compare_policies, compare_policies_by_weight, Comparison, ComparisonReport};
+pub use concurrent::ConcurrentCache;
+pub use document::DocumentCache;
+pub use epoch::{EpochCache, Versioned};
+pub use expiry::{Expiration, Freshness};
+use fifo::FIFOCache;
+pub use frequency::FrequencySketch;
+pub use greedy_dual::CostFn;
+pub use invalidation::{Invalidate, Invalidation, InvalidationWorker, Invalidator};
+pub use journal::{warm_from_journal, Journal};
 use landlord::Landlord;
 use lfu_w::LFUCache;
+use lfuda::LFUDACache;
+pub use loading::{LoadStats, Loader, LoadingCache};
 use lru::LRUCache;
-pub enum CacheType<K, V> {
-    LRU(LRUCache<K, V>),
-    LFU(LFUCache<K, V>),
-    Landlord(Landlord<K, V>),
+pub use maintenance::{Maintenance, MaintenanceReport, MaintenanceWorker};
+use mru::MRUCache;
+pub use negative::NegativeCache;
+use observer::BoxedObserver;
+pub use observer::CacheObserver;
+pub use pages::PagedResultCache;
+pub use pairs::{Combination, TermPair, TermPairCache};
+pub use partitioned::{PartitionError, PartitionedCache};
+pub use persistence::{Persistence, PersistenceWorker};
+pub use pinned::PinnedCache;
+pub use postings::{PostingListCache, PostingSize};
+pub use prefix::PrefixCache;
+pub use profiling::{ReuseDistanceProfiler, ReuseHistogram, ShardsProfiler};
+pub use query::{Normalizer, QueryResultCache};
+use random::RandomCache;
+pub use recording::{read_recording, recorded_trace, Operation, Outcome, RecordingCache};
+pub use refresh::RefreshAheadCache;
+pub use replay::{
+    read_trace, read_trace_file, replay, replay_with, Access, ReplayReport, TraceError, TraceFormat,
+};
+pub use scored::{EntryFeatures, EvictionScorer, ScoredCache};
+pub use sdc::StaticDynamicCache;
+pub use shadow::{ShadowCache, ShadowedCache};
+pub use sharded::{ShardStats, ShardedCache};
+pub use snapshot::{Decode, Encode, SnapshotError};
+pub use spill::{FileStore, SpillCache, VictimStore};
+pub use stack::{SearchCacheStack, StackConfig, StackStats};
+#[cfg(feature = "memmap2")]
+pub use static_cache::{LayeredCache, StaticCache};
+pub use stats::{CacheStats, EntryInfo};
+pub use store::{Store, StoreCache, WriteMode};
+pub use sync_cache::SyncCache;
+pub use tiered::TieredCache;
+pub use topk::TopKCache;
+pub use warmup::{Warmup, WarmupProgress};
+pub use weight::Weight;
+pub use workload::{Keys, Popularity, Workload};
+
+// Receives the entries a cache drops to make room for new ones.
+pub type EvictionListener<K, V> = Box<dyn FnMut(K, V) + Send>;
+// Derives an entry's weight from its key and value.
+pub type Weigher<K, V, W = u32> = Box<dyn Fn(&K, &V) -> W + Send>;
+// Operations shared by every eviction policy, so generic code can accept any of them.
+// The trait is object safe: `Box<dyn Cache<K, V>>` selects a policy at runtime.
+pub trait Cache<K, V> {
+    fn get(&mut self, key: &K) -> Option<&V>;
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V>;
+
+    fn peek(&self, key: &K) -> Option<&V>;
+
+    fn contains_key(&self, key: &K) -> bool;
+
+    fn put(&mut self, key: K, value: V, weight: u32);
+
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    fn pop_victim(&mut self) -> Option<(K, V)>;
+
+    // The key `pop_victim` would remove, without removing it.
+    fn peek_victim(&self) -> Option<&K>;
+
+    fn clear(&mut self);
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Construction lives outside `Cache` to keep the latter usable as a trait object.
+pub trait NewCache<K, V>: Cache<K, V> + Sized {
+    fn new(capacity: usize) -> Self;
+}
+
+impl<K, V, C: Cache<K, V> + ?Sized> Cache<K, V> for Box<C> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        (**self).get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        (**self).get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        (**self).peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        (**self).contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        (**self).put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        (**self).remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        (**self).pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        (**self).peek_victim()
+    }
+
+    fn clear(&mut self) {
+        (**self).clear()
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn is_empty(&self) -> bool {
+        (**self).is_empty()
+    }
+}
+
+pub enum Iter<'a, K, V> {
+    LRU(lru::Iter<'a, K, V>),
+    LFU(lfu_w::Iter<'a, K, V>),
+    Landlord(landlord::Iter<'a, K, V>),
+    LFUDA(lfuda::Iter<'a, K, V>),
+    ARC(arc::Iter<'a, K, V>),
+    FIFO(fifo::Iter<'a, K, V>),
+    Random(random::Iter<'a, K, V>),
+    MRU(mru::Iter<'a, K, V>),
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Iter::LRU(iter) => iter.next(),
+            Iter::LFU(iter) => iter.next(),
+            Iter::Landlord(iter) => iter.next(),
+            Iter::LFUDA(iter) => iter.next(),
+            Iter::ARC(iter) => iter.next(),
+            Iter::FIFO(iter) => iter.next(),
+            Iter::Random(iter) => iter.next(),
+            Iter::MRU(iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Iter::LRU(iter) => iter.size_hint(),
+            Iter::LFU(iter) => iter.size_hint(),
+            Iter::Landlord(iter) => iter.size_hint(),
+            Iter::LFUDA(iter) => iter.size_hint(),
+            Iter::ARC(iter) => iter.size_hint(),
+            Iter::FIFO(iter) => iter.size_hint(),
+            Iter::Random(iter) => iter.size_hint(),
+            Iter::MRU(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+pub enum IntoIter<K, V> {
+    LRU(lru::IntoIter<K, V>),
+    LFU(lfu_w::IntoIter<K, V>),
+    Landlord(landlord::IntoIter<K, V>),
+    LFUDA(lfuda::IntoIter<K, V>),
+    ARC(arc::IntoIter<K, V>),
+    FIFO(fifo::IntoIter<K, V>),
+    Random(random::IntoIter<K, V>),
+    MRU(mru::IntoIter<K, V>),
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            IntoIter::LRU(iter) => iter.next(),
+            IntoIter::LFU(iter) => iter.next(),
+            IntoIter::Landlord(iter) => iter.next(),
+            IntoIter::LFUDA(iter) => iter.next(),
+            IntoIter::ARC(iter) => iter.next(),
+            IntoIter::FIFO(iter) => iter.next(),
+            IntoIter::Random(iter) => iter.next(),
+            IntoIter::MRU(iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            IntoIter::LRU(iter) => iter.size_hint(),
+            IntoIter::LFU(iter) => iter.size_hint(),
+            IntoIter::Landlord(iter) => iter.size_hint(),
+            IntoIter::LFUDA(iter) => iter.size_hint(),
+            IntoIter::ARC(iter) => iter.size_hint(),
+            IntoIter::FIFO(iter) => iter.size_hint(),
+            IntoIter::Random(iter) => iter.size_hint(),
+            IntoIter::MRU(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Policy {
+    LRU,
+    LFU,
+    Landlord,
+    LFUDA,
+    ARC,
+    FIFO,
+    Random,
+    MRU,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePolicyError(String);
+
+impl fmt::Display for ParsePolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown cache policy: {:?}", self.0)
+    }
+}
+
+impl Error for ParsePolicyError {}
+
+// Accepts the policy names case-insensitively, e.g. from a configuration file.
+impl FromStr for Policy {
+    type Err = ParsePolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lru" => Ok(Policy::LRU),
+            "lfu" => Ok(Policy::LFU),
+            "landlord" => Ok(Policy::Landlord),
+            "lfuda" => Ok(Policy::LFUDA),
+            "arc" => Ok(Policy::ARC),
+            "fifo" => Ok(Policy::FIFO),
+            "random" => Ok(Policy::Random),
+            "mru" => Ok(Policy::MRU),
+            _ => Err(ParsePolicyError(s.to_string())),
+        }
+    }
+}
+
+// Keys must be `Clone`: Landlord, LFUDA, ARC and Random keep copies of their keys, and
+// any of them can be behind a `CacheType`. LRU, LFU, FIFO and MRU store each key once
+// and take keys that are not `Clone` when used directly.
+//
+// Serialized tagged with the policy, so a restored cache comes back as the same one.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "K: Clone + Hash + Eq + serde::Serialize, V: serde::Serialize, \
+                     S: BuildHasher + Clone",
+        deserialize = "K: Clone + Hash + Eq + serde::Deserialize<'de>, \
+                       V: serde::Deserialize<'de>, S: BuildHasher + Clone + Default"
+    ))
+)]
+pub enum CacheType<K, V, S = RandomState> {
+    LRU(LRUCache<K, V, S>),
+    LFU(LFUCache<K, V, S>),
+    Landlord(Landlord<K, V, S>),
+    LFUDA(LFUDACache<K, V, S>),
+    ARC(ARCCache<K, V, S>),
+    FIFO(FIFOCache<K, V, S>),
+    Random(RandomCache<K, V, S>),
+    MRU(MRUCache<K, V, S>),
 }
 
 impl<K: Clone + Hash + Eq, V> CacheType<K, V> {
+    pub fn new(policy: Policy, capacity: usize) -> Self {
+        CacheType::with_hasher(policy, capacity, RandomState::new())
+    }
+
     pub fn new_lru(capacity: usize) -> Self {
         CacheType::LRU(LRUCache::new(capacity))
     }
@@ -26,14 +406,93 @@ impl<K: Clone + Hash + Eq, V> CacheType<K, V> {
     pub fn new_landlord(capacity: usize) -> Self {
         CacheType::Landlord(Landlord::new(capacity))
     }
+
+    pub fn new_lfuda(capacity: usize) -> Self {
+        CacheType::LFUDA(LFUDACache::new(capacity))
+    }
+
+    pub fn new_arc(capacity: usize) -> Self {
+        CacheType::ARC(ARCCache::new(capacity))
+    }
+
+    pub fn new_fifo(capacity: usize) -> Self {
+        CacheType::FIFO(FIFOCache::new(capacity))
+    }
+
+    pub fn new_random(capacity: usize) -> Self {
+        CacheType::Random(RandomCache::new(capacity))
+    }
+
+    pub fn new_mru(capacity: usize) -> Self {
+        CacheType::MRU(MRUCache::new(capacity))
+    }
 }
 
-impl<K: Clone + Hash + Eq, V> CacheType<K, V> {
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> CacheType<K, V, S> {
+    pub fn with_hasher(policy: Policy, capacity: usize, hash_builder: S) -> Self {
+        match policy {
+            Policy::LRU => CacheType::LRU(LRUCache::with_hasher(capacity, hash_builder)),
+            Policy::LFU => CacheType::LFU(LFUCache::with_hasher(capacity, hash_builder)),
+            Policy::Landlord => CacheType::Landlord(Landlord::with_hasher(capacity, hash_builder)),
+            Policy::LFUDA => CacheType::LFUDA(LFUDACache::with_hasher(capacity, hash_builder)),
+            Policy::ARC => CacheType::ARC(ARCCache::with_hasher(capacity, hash_builder)),
+            Policy::FIFO => CacheType::FIFO(FIFOCache::with_hasher(capacity, hash_builder)),
+            Policy::Random => CacheType::Random(RandomCache::with_hasher(capacity, hash_builder)),
+            Policy::MRU => CacheType::MRU(MRUCache::with_hasher(capacity, hash_builder)),
+        }
+    }
+
+    pub fn policy(&self) -> Policy {
+        match self {
+            CacheType::LRU(_) => Policy::LRU,
+            CacheType::LFU(_) => Policy::LFU,
+            CacheType::Landlord(_) => Policy::Landlord,
+            CacheType::LFUDA(_) => Policy::LFUDA,
+            CacheType::ARC(_) => Policy::ARC,
+            CacheType::FIFO(_) => Policy::FIFO,
+            CacheType::Random(_) => Policy::Random,
+            CacheType::MRU(_) => Policy::MRU,
+        }
+    }
+
     pub fn put(&mut self, key: K, value: V, weight: u32) {
         match self {
             CacheType::LRU(cache) => cache.put(key, value, weight),
             CacheType::LFU(cache) => cache.put(key, value, weight),
             CacheType::Landlord(cache) => cache.put(key, value, weight),
+            CacheType::LFUDA(cache) => cache.put(key, value, weight),
+            CacheType::ARC(cache) => cache.put(key, value, weight),
+            CacheType::FIFO(cache) => cache.put(key, value, weight),
+            CacheType::Random(cache) => cache.put(key, value, weight),
+            CacheType::MRU(cache) => cache.put(key, value, weight),
+        }
+    }
+
+    // Stores an entry whose cost, what it takes to recompute, differs from its size. Only
+    // Landlord tells the two apart; the other policies store it with `size` as its weight.
+    pub fn put_sized(&mut self, key: K, value: V, cost: u32, size: u32) {
+        match self {
+            CacheType::LRU(cache) => cache.put(key, value, size),
+            CacheType::LFU(cache) => cache.put(key, value, size),
+            CacheType::Landlord(cache) => cache.put_sized(key, value, cost, size),
+            CacheType::LFUDA(cache) => cache.put(key, value, size),
+            CacheType::ARC(cache) => cache.put(key, value, size),
+            CacheType::FIFO(cache) => cache.put(key, value, size),
+            CacheType::Random(cache) => cache.put(key, value, size),
+            CacheType::MRU(cache) => cache.put(key, value, size),
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        match self {
+            CacheType::LRU(cache) => cache.insert(key, value),
+            CacheType::LFU(cache) => cache.insert(key, value),
+            CacheType::Landlord(cache) => cache.insert(key, value),
+            CacheType::LFUDA(cache) => cache.insert(key, value),
+            CacheType::ARC(cache) => cache.insert(key, value),
+            CacheType::FIFO(cache) => cache.insert(key, value),
+            CacheType::Random(cache) => cache.insert(key, value),
+            CacheType::MRU(cache) => cache.insert(key, value),
         }
     }
 
@@ -42,6 +501,398 @@ impl<K: Clone + Hash + Eq, V> CacheType<K, V> {
             CacheType::LRU(cache) => cache.get(key),
             CacheType::LFU(cache) => cache.get(key),
             CacheType::Landlord(cache) => cache.get(key),
+            CacheType::LFUDA(cache) => cache.get(key),
+            CacheType::ARC(cache) => cache.get(key),
+            CacheType::FIFO(cache) => cache.get(key),
+            CacheType::Random(cache) => cache.get(key),
+            CacheType::MRU(cache) => cache.get(key),
+        }
+    }
+
+    // Like `get`, but an entry that has expired and not been reclaimed yet is returned as
+    // stale instead of being dropped. A stale read counts as a miss, not as a use.
+    pub fn get_stale(&mut self, key: &K) -> Option<Freshness<&V>> {
+        match self {
+            CacheType::LRU(cache) => cache.get_stale(key),
+            CacheType::LFU(cache) => cache.get_stale(key),
+            CacheType::Landlord(cache) => cache.get_stale(key),
+            CacheType::LFUDA(cache) => cache.get_stale(key),
+            CacheType::ARC(cache) => cache.get_stale(key),
+            CacheType::FIFO(cache) => cache.get_stale(key),
+            CacheType::Random(cache) => cache.get_stale(key),
+            CacheType::MRU(cache) => cache.get_stale(key),
+        }
+    }
+
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, weight: u32, f: F) -> &V {
+        match self {
+            CacheType::LRU(cache) => cache.get_or_insert_with(key, weight, f),
+            CacheType::LFU(cache) => cache.get_or_insert_with(key, weight, f),
+            CacheType::Landlord(cache) => cache.get_or_insert_with(key, weight, f),
+            CacheType::LFUDA(cache) => cache.get_or_insert_with(key, weight, f),
+            CacheType::ARC(cache) => cache.get_or_insert_with(key, weight, f),
+            CacheType::FIFO(cache) => cache.get_or_insert_with(key, weight, f),
+            CacheType::Random(cache) => cache.get_or_insert_with(key, weight, f),
+            CacheType::MRU(cache) => cache.get_or_insert_with(key, weight, f),
+        }
+    }
+
+    // Landlord times `f` and takes the milliseconds it took as the entry's cost; the other
+    // policies are not cost-aware and behave like `get_or_insert_with`.
+    pub fn get_or_insert_timed<F: FnOnce() -> V>(&mut self, key: K, size: u32, f: F) -> &V {
+        match self {
+            CacheType::LRU(cache) => cache.get_or_insert_with(key, size, f),
+            CacheType::LFU(cache) => cache.get_or_insert_with(key, size, f),
+            CacheType::Landlord(cache) => cache.get_or_insert_timed(key, size, f),
+            CacheType::LFUDA(cache) => cache.get_or_insert_with(key, size, f),
+            CacheType::ARC(cache) => cache.get_or_insert_with(key, size, f),
+            CacheType::FIFO(cache) => cache.get_or_insert_with(key, size, f),
+            CacheType::Random(cache) => cache.get_or_insert_with(key, size, f),
+            CacheType::MRU(cache) => cache.get_or_insert_with(key, size, f),
+        }
+    }
+
+    pub fn try_get_or_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        weight: u32,
+        f: F,
+    ) -> Result<&V, E> {
+        match self {
+            CacheType::LRU(cache) => cache.try_get_or_insert_with(key, weight, f),
+            CacheType::LFU(cache) => cache.try_get_or_insert_with(key, weight, f),
+            CacheType::Landlord(cache) => cache.try_get_or_insert_with(key, weight, f),
+            CacheType::LFUDA(cache) => cache.try_get_or_insert_with(key, weight, f),
+            CacheType::ARC(cache) => cache.try_get_or_insert_with(key, weight, f),
+            CacheType::FIFO(cache) => cache.try_get_or_insert_with(key, weight, f),
+            CacheType::Random(cache) => cache.try_get_or_insert_with(key, weight, f),
+            CacheType::MRU(cache) => cache.try_get_or_insert_with(key, weight, f),
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        match self {
+            CacheType::LRU(cache) => cache.get_mut(key),
+            CacheType::LFU(cache) => cache.get_mut(key),
+            CacheType::Landlord(cache) => cache.get_mut(key),
+            CacheType::LFUDA(cache) => cache.get_mut(key),
+            CacheType::ARC(cache) => cache.get_mut(key),
+            CacheType::FIFO(cache) => cache.get_mut(key),
+            CacheType::Random(cache) => cache.get_mut(key),
+            CacheType::MRU(cache) => cache.get_mut(key),
+        }
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        match self {
+            CacheType::LRU(cache) => cache.peek(key),
+            CacheType::LFU(cache) => cache.peek(key),
+            CacheType::Landlord(cache) => cache.peek(key),
+            CacheType::LFUDA(cache) => cache.peek(key),
+            CacheType::ARC(cache) => cache.peek(key),
+            CacheType::FIFO(cache) => cache.peek(key),
+            CacheType::Random(cache) => cache.peek(key),
+            CacheType::MRU(cache) => cache.peek(key),
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        match self {
+            CacheType::LRU(cache) => cache.contains_key(key),
+            CacheType::LFU(cache) => cache.contains_key(key),
+            CacheType::Landlord(cache) => cache.contains_key(key),
+            CacheType::LFUDA(cache) => cache.contains_key(key),
+            CacheType::ARC(cache) => cache.contains_key(key),
+            CacheType::FIFO(cache) => cache.contains_key(key),
+            CacheType::Random(cache) => cache.contains_key(key),
+            CacheType::MRU(cache) => cache.contains_key(key),
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        match self {
+            CacheType::LRU(cache) => cache.remove(key),
+            CacheType::LFU(cache) => cache.remove(key),
+            CacheType::Landlord(cache) => cache.remove(key),
+            CacheType::LFUDA(cache) => cache.remove(key),
+            CacheType::ARC(cache) => cache.remove(key),
+            CacheType::FIFO(cache) => cache.remove(key),
+            CacheType::Random(cache) => cache.remove(key),
+            CacheType::MRU(cache) => cache.remove(key),
+        }
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        match self {
+            CacheType::LRU(cache) => cache.pop_victim(),
+            CacheType::LFU(cache) => cache.pop_victim(),
+            CacheType::Landlord(cache) => cache.pop_victim(),
+            CacheType::LFUDA(cache) => cache.pop_victim(),
+            CacheType::ARC(cache) => cache.pop_victim(),
+            CacheType::FIFO(cache) => cache.pop_victim(),
+            CacheType::Random(cache) => cache.pop_victim(),
+            CacheType::MRU(cache) => cache.pop_victim(),
+        }
+    }
+
+    pub fn evict_to(&mut self, len: usize) -> usize {
+        match self {
+            CacheType::LRU(cache) => cache.evict_to(len),
+            CacheType::LFU(cache) => cache.evict_to(len),
+            CacheType::Landlord(cache) => cache.evict_to(len),
+            CacheType::LFUDA(cache) => cache.evict_to(len),
+            CacheType::ARC(cache) => cache.evict_to(len),
+            CacheType::FIFO(cache) => cache.evict_to(len),
+            CacheType::Random(cache) => cache.evict_to(len),
+            CacheType::MRU(cache) => cache.evict_to(len),
+        }
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        match self {
+            CacheType::LRU(cache) => cache.peek_victim(),
+            CacheType::LFU(cache) => cache.peek_victim(),
+            CacheType::Landlord(cache) => cache.peek_victim(),
+            CacheType::LFUDA(cache) => cache.peek_victim(),
+            CacheType::ARC(cache) => cache.peek_victim(),
+            CacheType::FIFO(cache) => cache.peek_victim(),
+            CacheType::Random(cache) => cache.peek_victim(),
+            CacheType::MRU(cache) => cache.peek_victim(),
+        }
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, f: F) {
+        match self {
+            CacheType::LRU(cache) => cache.retain(f),
+            CacheType::LFU(cache) => cache.retain(f),
+            CacheType::Landlord(cache) => cache.retain(f),
+            CacheType::LFUDA(cache) => cache.retain(f),
+            CacheType::ARC(cache) => cache.retain(f),
+            CacheType::FIFO(cache) => cache.retain(f),
+            CacheType::Random(cache) => cache.retain(f),
+            CacheType::MRU(cache) => cache.retain(f),
+        }
+    }
+
+    // Called with each entry evicted to make room; explicit removals are not reported.
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        match self {
+            CacheType::LRU(cache) => cache.set_eviction_listener(listener),
+            CacheType::LFU(cache) => cache.set_eviction_listener(listener),
+            CacheType::Landlord(cache) => cache.set_eviction_listener(listener),
+            CacheType::LFUDA(cache) => cache.set_eviction_listener(listener),
+            CacheType::ARC(cache) => cache.set_eviction_listener(listener),
+            CacheType::FIFO(cache) => cache.set_eviction_listener(listener),
+            CacheType::Random(cache) => cache.set_eviction_listener(listener),
+            CacheType::MRU(cache) => cache.set_eviction_listener(listener),
+        }
+    }
+
+    pub(crate) fn set_boxed_eviction_listener(&mut self, listener: EvictionListener<K, V>) {
+        match self {
+            CacheType::LRU(cache) => cache.set_boxed_eviction_listener(listener),
+            CacheType::LFU(cache) => cache.set_boxed_eviction_listener(listener),
+            CacheType::Landlord(cache) => cache.set_boxed_eviction_listener(listener),
+            CacheType::LFUDA(cache) => cache.set_boxed_eviction_listener(listener),
+            CacheType::ARC(cache) => cache.set_boxed_eviction_listener(listener),
+            CacheType::FIFO(cache) => cache.set_boxed_eviction_listener(listener),
+            CacheType::Random(cache) => cache.set_boxed_eviction_listener(listener),
+            CacheType::MRU(cache) => cache.set_boxed_eviction_listener(listener),
+        }
+    }
+
+    pub fn set_weigher<F: Fn(&K, &V) -> u32 + Send + 'static>(&mut self, weigher: F) {
+        self.set_boxed_weigher(Box::new(weigher));
+    }
+
+    pub(crate) fn set_boxed_weigher(&mut self, weigher: Weigher<K, V>) {
+        match self {
+            CacheType::LRU(cache) => cache.set_boxed_weigher(weigher),
+            CacheType::LFU(cache) => cache.set_boxed_weigher(weigher),
+            CacheType::Landlord(cache) => cache.set_boxed_weigher(weigher),
+            CacheType::LFUDA(cache) => cache.set_boxed_weigher(weigher),
+            CacheType::ARC(cache) => cache.set_boxed_weigher(weigher),
+            CacheType::FIFO(cache) => cache.set_boxed_weigher(weigher),
+            CacheType::Random(cache) => cache.set_boxed_weigher(weigher),
+            CacheType::MRU(cache) => cache.set_boxed_weigher(weigher),
+        }
+    }
+
+    pub fn set_observer<O: CacheObserver<K> + Send + 'static>(&mut self, observer: O) {
+        self.set_boxed_observer(Box::new(observer));
+    }
+
+    pub(crate) fn set_boxed_observer(&mut self, observer: BoxedObserver<K>) {
+        match self {
+            CacheType::LRU(cache) => cache.set_boxed_observer(observer),
+            CacheType::LFU(cache) => cache.set_boxed_observer(observer),
+            CacheType::Landlord(cache) => cache.set_boxed_observer(observer),
+            CacheType::LFUDA(cache) => cache.set_boxed_observer(observer),
+            CacheType::ARC(cache) => cache.set_boxed_observer(observer),
+            CacheType::FIFO(cache) => cache.set_boxed_observer(observer),
+            CacheType::Random(cache) => cache.set_boxed_observer(observer),
+            CacheType::MRU(cache) => cache.set_boxed_observer(observer),
+        }
+    }
+
+    pub fn set_expire_after_write(&mut self, time_to_live: Duration) {
+        match self {
+            CacheType::LRU(cache) => cache.set_expire_after_write(time_to_live),
+            CacheType::LFU(cache) => cache.set_expire_after_write(time_to_live),
+            CacheType::Landlord(cache) => cache.set_expire_after_write(time_to_live),
+            CacheType::LFUDA(cache) => cache.set_expire_after_write(time_to_live),
+            CacheType::ARC(cache) => cache.set_expire_after_write(time_to_live),
+            CacheType::FIFO(cache) => cache.set_expire_after_write(time_to_live),
+            CacheType::Random(cache) => cache.set_expire_after_write(time_to_live),
+            CacheType::MRU(cache) => cache.set_expire_after_write(time_to_live),
+        }
+    }
+
+    pub fn set_expire_after_access(&mut self, time_to_idle: Duration) {
+        match self {
+            CacheType::LRU(cache) => cache.set_expire_after_access(time_to_idle),
+            CacheType::LFU(cache) => cache.set_expire_after_access(time_to_idle),
+            CacheType::Landlord(cache) => cache.set_expire_after_access(time_to_idle),
+            CacheType::LFUDA(cache) => cache.set_expire_after_access(time_to_idle),
+            CacheType::ARC(cache) => cache.set_expire_after_access(time_to_idle),
+            CacheType::FIFO(cache) => cache.set_expire_after_access(time_to_idle),
+            CacheType::Random(cache) => cache.set_expire_after_access(time_to_idle),
+            CacheType::MRU(cache) => cache.set_expire_after_access(time_to_idle),
+        }
+    }
+
+    // Bounds the cache by the summed weight of its entries instead of by entry count.
+    // Victims are dropped until a new entry fits; one heavier than the whole budget is
+    // still cached, alone. Entries already over the budget are evicted right away.
+    pub fn set_max_weight(&mut self, max_weight: u64) {
+        match self {
+            CacheType::LRU(cache) => cache.set_max_weight(max_weight),
+            CacheType::LFU(cache) => cache.set_max_weight(max_weight),
+            CacheType::Landlord(cache) => cache.set_max_weight(max_weight),
+            CacheType::LFUDA(cache) => cache.set_max_weight(max_weight),
+            CacheType::ARC(cache) => cache.set_max_weight(max_weight),
+            CacheType::FIFO(cache) => cache.set_max_weight(max_weight),
+            CacheType::Random(cache) => cache.set_max_weight(max_weight),
+            CacheType::MRU(cache) => cache.set_max_weight(max_weight),
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        match self {
+            CacheType::LRU(cache) => cache.set_clock(clock),
+            CacheType::LFU(cache) => cache.set_clock(clock),
+            CacheType::Landlord(cache) => cache.set_clock(clock),
+            CacheType::LFUDA(cache) => cache.set_clock(clock),
+            CacheType::ARC(cache) => cache.set_clock(clock),
+            CacheType::FIFO(cache) => cache.set_clock(clock),
+            CacheType::Random(cache) => cache.set_clock(clock),
+            CacheType::MRU(cache) => cache.set_clock(clock),
+        }
+    }
+
+    // Removes every entry that has expired by `now` and returns how many were dropped.
+    // Only due entries are visited, so this is cheap enough to call on every tick.
+    pub fn expire_due(&mut self, now: Instant) -> usize {
+        match self {
+            CacheType::LRU(cache) => cache.expire_due(now),
+            CacheType::LFU(cache) => cache.expire_due(now),
+            CacheType::Landlord(cache) => cache.expire_due(now),
+            CacheType::LFUDA(cache) => cache.expire_due(now),
+            CacheType::ARC(cache) => cache.expire_due(now),
+            CacheType::FIFO(cache) => cache.expire_due(now),
+            CacheType::Random(cache) => cache.expire_due(now),
+            CacheType::MRU(cache) => cache.expire_due(now),
+        }
+    }
+
+    pub(crate) fn set_expiration(&mut self, expiration: Expiration) {
+        match self {
+            CacheType::LRU(cache) => cache.set_expiration(expiration),
+            CacheType::LFU(cache) => cache.set_expiration(expiration),
+            CacheType::Landlord(cache) => cache.set_expiration(expiration),
+            CacheType::LFUDA(cache) => cache.set_expiration(expiration),
+            CacheType::ARC(cache) => cache.set_expiration(expiration),
+            CacheType::FIFO(cache) => cache.set_expiration(expiration),
+            CacheType::Random(cache) => cache.set_expiration(expiration),
+            CacheType::MRU(cache) => cache.set_expiration(expiration),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match self {
+            CacheType::LRU(cache) => cache.clear(),
+            CacheType::LFU(cache) => cache.clear(),
+            CacheType::Landlord(cache) => cache.clear(),
+            CacheType::LFUDA(cache) => cache.clear(),
+            CacheType::ARC(cache) => cache.clear(),
+            CacheType::FIFO(cache) => cache.clear(),
+            CacheType::Random(cache) => cache.clear(),
+            CacheType::MRU(cache) => cache.clear(),
+        }
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        match self {
+            CacheType::LRU(cache) => cache.shrink_to_fit(),
+            CacheType::LFU(cache) => cache.shrink_to_fit(),
+            CacheType::Landlord(cache) => cache.shrink_to_fit(),
+            CacheType::LFUDA(cache) => cache.shrink_to_fit(),
+            CacheType::ARC(cache) => cache.shrink_to_fit(),
+            CacheType::FIFO(cache) => cache.shrink_to_fit(),
+            CacheType::Random(cache) => cache.shrink_to_fit(),
+            CacheType::MRU(cache) => cache.shrink_to_fit(),
+        }
+    }
+
+    pub fn preallocate(&mut self) {
+        match self {
+            CacheType::LRU(cache) => cache.preallocate(),
+            CacheType::LFU(cache) => cache.preallocate(),
+            CacheType::Landlord(cache) => cache.preallocate(),
+            CacheType::LFUDA(cache) => cache.preallocate(),
+            CacheType::ARC(cache) => cache.preallocate(),
+            CacheType::FIFO(cache) => cache.preallocate(),
+            CacheType::Random(cache) => cache.preallocate(),
+            CacheType::MRU(cache) => cache.preallocate(),
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        match self {
+            CacheType::LRU(cache) => Iter::LRU(cache.iter()),
+            CacheType::LFU(cache) => Iter::LFU(cache.iter()),
+            CacheType::Landlord(cache) => Iter::Landlord(cache.iter()),
+            CacheType::LFUDA(cache) => Iter::LFUDA(cache.iter()),
+            CacheType::ARC(cache) => Iter::ARC(cache.iter()),
+            CacheType::FIFO(cache) => Iter::FIFO(cache.iter()),
+            CacheType::Random(cache) => Iter::Random(cache.iter()),
+            CacheType::MRU(cache) => Iter::MRU(cache.iter()),
+        }
+    }
+
+    pub fn entry_info(&self, key: &K) -> Option<EntryInfo> {
+        match self {
+            CacheType::LRU(cache) => cache.entry_info(key),
+            CacheType::LFU(cache) => cache.entry_info(key),
+            CacheType::Landlord(cache) => cache.entry_info(key),
+            CacheType::LFUDA(cache) => cache.entry_info(key),
+            CacheType::ARC(cache) => cache.entry_info(key),
+            CacheType::FIFO(cache) => cache.entry_info(key),
+            CacheType::Random(cache) => cache.entry_info(key),
+            CacheType::MRU(cache) => cache.entry_info(key),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        match self {
+            CacheType::LRU(cache) => cache.stats(),
+            CacheType::LFU(cache) => cache.stats(),
+            CacheType::Landlord(cache) => cache.stats(),
+            CacheType::LFUDA(cache) => cache.stats(),
+            CacheType::ARC(cache) => cache.stats(),
+            CacheType::FIFO(cache) => cache.stats(),
+            CacheType::Random(cache) => cache.stats(),
+            CacheType::MRU(cache) => cache.stats(),
         }
     }
 
@@ -50,6 +901,11 @@ impl<K: Clone + Hash + Eq, V> CacheType<K, V> {
             CacheType::LRU(cache) => cache.len(),
             CacheType::LFU(cache) => cache.len(),
             CacheType::Landlord(cache) => cache.len(),
+            CacheType::LFUDA(cache) => cache.len(),
+            CacheType::ARC(cache) => cache.len(),
+            CacheType::FIFO(cache) => cache.len(),
+            CacheType::Random(cache) => cache.len(),
+            CacheType::MRU(cache) => cache.len(),
         }
     }
 
@@ -58,6 +914,167 @@ impl<K: Clone + Hash + Eq, V> CacheType<K, V> {
             CacheType::LRU(cache) => cache.is_empty(),
             CacheType::LFU(cache) => cache.is_empty(),
             CacheType::Landlord(cache) => cache.is_empty(),
+            CacheType::LFUDA(cache) => cache.is_empty(),
+            CacheType::ARC(cache) => cache.is_empty(),
+            CacheType::FIFO(cache) => cache.is_empty(),
+            CacheType::Random(cache) => cache.is_empty(),
+            CacheType::MRU(cache) => cache.is_empty(),
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V: Clone, S: BuildHasher + Clone> CacheType<K, V, S> {
+    // The `n` entries the policy would evict last, hottest first, with their weights.
+    pub fn export_top(&self, n: usize) -> Vec<(K, V, u32)> {
+        match self {
+            CacheType::LRU(cache) => cache.export_top(n),
+            CacheType::LFU(cache) => cache.export_top(n),
+            CacheType::Landlord(cache) => cache.export_top(n),
+            CacheType::LFUDA(cache) => cache.export_top(n),
+            CacheType::ARC(cache) => cache.export_top(n),
+            CacheType::FIFO(cache) => cache.export_top(n),
+            CacheType::Random(cache) => cache.export_top(n),
+            CacheType::MRU(cache) => cache.export_top(n),
+        }
+    }
+
+    // Seeds entries exported by `export_top`, possibly from another policy, so that they
+    // outrank what a plain `put` would give them.
+    pub fn import<I: IntoIterator<Item = (K, V, u32)>>(&mut self, entries: I) {
+        match self {
+            CacheType::LRU(cache) => cache.import(entries),
+            CacheType::LFU(cache) => cache.import(entries),
+            CacheType::Landlord(cache) => cache.import(entries),
+            CacheType::LFUDA(cache) => cache.import(entries),
+            CacheType::ARC(cache) => cache.import(entries),
+            CacheType::FIFO(cache) => cache.import(entries),
+            CacheType::Random(cache) => cache.import(entries),
+            CacheType::MRU(cache) => cache.import(entries),
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq + Encode, V: Encode, S: BuildHasher + Clone> CacheType<K, V, S> {
+    pub fn save_to<W: Write>(&self, writer: W) -> Result<(), SnapshotError> {
+        match self {
+            CacheType::LRU(cache) => cache.save_to(writer),
+            CacheType::LFU(cache) => cache.save_to(writer),
+            CacheType::Landlord(cache) => cache.save_to(writer),
+            CacheType::LFUDA(cache) => cache.save_to(writer),
+            CacheType::ARC(cache) => cache.save_to(writer),
+            CacheType::FIFO(cache) => cache.save_to(writer),
+            CacheType::Random(cache) => cache.save_to(writer),
+            CacheType::MRU(cache) => cache.save_to(writer),
+        }
+    }
+}
+
+impl<K, V, S> CacheType<K, V, S>
+where
+    K: Clone + Hash + Eq + Decode,
+    V: Decode,
+    S: BuildHasher + Clone + Default,
+{
+    // Restores whichever policy the snapshot holds.
+    pub fn load_from<R: Read>(reader: R) -> Result<Self, SnapshotError> {
+        let snapshot = snapshot::SnapshotReader::open(reader)?;
+        Ok(match snapshot.policy() {
+            Policy::LRU => CacheType::LRU(LRUCache::load_snapshot(snapshot)?),
+            Policy::LFU => CacheType::LFU(LFUCache::load_snapshot(snapshot)?),
+            Policy::Landlord => CacheType::Landlord(Landlord::load_snapshot(snapshot)?),
+            Policy::LFUDA => CacheType::LFUDA(LFUDACache::load_snapshot(snapshot)?),
+            Policy::ARC => CacheType::ARC(ARCCache::load_snapshot(snapshot)?),
+            Policy::FIFO => CacheType::FIFO(FIFOCache::load_snapshot(snapshot)?),
+            Policy::Random => CacheType::Random(RandomCache::load_snapshot(snapshot)?),
+            Policy::MRU => CacheType::MRU(MRUCache::load_snapshot(snapshot)?),
+        })
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Cache<K, V> for CacheType<K, V, S> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> IntoIterator for CacheType<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        match self {
+            CacheType::LRU(cache) => IntoIter::LRU(cache.into_iter()),
+            CacheType::LFU(cache) => IntoIter::LFU(cache.into_iter()),
+            CacheType::Landlord(cache) => IntoIter::Landlord(cache.into_iter()),
+            CacheType::LFUDA(cache) => IntoIter::LFUDA(cache.into_iter()),
+            CacheType::ARC(cache) => IntoIter::ARC(cache.into_iter()),
+            CacheType::FIFO(cache) => IntoIter::FIFO(cache.into_iter()),
+            CacheType::Random(cache) => IntoIter::Random(cache.into_iter()),
+            CacheType::MRU(cache) => IntoIter::MRU(cache.into_iter()),
+        }
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher + Clone> IntoIterator for &'a CacheType<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Extend<(K, V, u32)> for CacheType<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V, u32)>>(&mut self, iter: I) {
+        match self {
+            CacheType::LRU(cache) => cache.extend(iter),
+            CacheType::LFU(cache) => cache.extend(iter),
+            CacheType::Landlord(cache) => cache.extend(iter),
+            CacheType::LFUDA(cache) => cache.extend(iter),
+            CacheType::ARC(cache) => cache.extend(iter),
+            CacheType::FIFO(cache) => cache.extend(iter),
+            CacheType::Random(cache) => cache.extend(iter),
+            CacheType::MRU(cache) => cache.extend(iter),
         }
     }
 }
@@ -66,6 +1083,25 @@ impl<K: Clone + Hash + Eq, V> CacheType<K, V> {
 mod integration_tests {
     use super::*;
 
+    const POLICIES: [Policy; 8] = [
+        Policy::LRU,
+        Policy::LFU,
+        Policy::Landlord,
+        Policy::LFUDA,
+        Policy::ARC,
+        Policy::FIFO,
+        Policy::Random,
+        Policy::MRU,
+    ];
+
+    // One empty cache of every policy `CacheType` can hold, in `POLICIES` order.
+    fn all_caches<V>(capacity: usize) -> Vec<CacheType<i32, V>> {
+        POLICIES
+            .iter()
+            .map(|&policy| CacheType::new(policy, capacity))
+            .collect()
+    }
+
     #[test]
     fn test_lru_basic_usage() {
         let mut cache = lru::LRUCache::new(3);
@@ -115,6 +1151,501 @@ mod integration_tests {
         assert_eq!(cache.get(&2), Some(&"high_priority"));
     }
 
+    #[test]
+    fn test_cache_type_remove() {
+        let mut caches: Vec<CacheType<i32, &str>> = all_caches(2);
+
+        for cache in caches.iter_mut() {
+            cache.put(1, "one", 1);
+            cache.put(2, "two", 1);
+
+            assert!(cache.contains_key(&1));
+            assert_eq!(cache.peek(&2), Some(&"two"));
+            assert_eq!(cache.remove(&1), Some("one"));
+            assert!(!cache.contains_key(&1));
+            assert_eq!(cache.remove(&1), None);
+            assert_eq!(cache.get(&1), None);
+            assert_eq!(cache.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_cache_type_get_mut() {
+        let mut caches: Vec<CacheType<i32, Vec<i32>>> = all_caches(2);
+
+        for cache in caches.iter_mut() {
+            cache.put(1, vec![1], 1);
+            if let Some(buffer) = cache.get_mut(&1) {
+                buffer.push(2);
+            }
+            assert_eq!(cache.get(&1), Some(&vec![1, 2]));
+            assert_eq!(cache.get_mut(&2), None);
+        }
+    }
+
+    #[test]
+    fn test_cache_type_pop_victim() {
+        for (&policy, mut cache) in POLICIES.iter().zip(all_caches(2)) {
+            cache.put(1, "one", 1);
+            cache.put(2, "two", 2);
+            cache.get(&2);
+
+            // Key 2 is the more recent, the more frequent and the costlier
+            let victim = *cache.peek_victim().unwrap();
+            match policy {
+                Policy::Random => {}
+                Policy::MRU => assert_eq!(victim, 2),
+                _ => assert_eq!(victim, 1, "{:?}", policy),
+            }
+            let popped: Vec<i32> = std::iter::from_fn(|| cache.pop_victim())
+                .map(|(key, _)| key)
+                .collect();
+            assert_eq!(popped, vec![victim, 3 - victim], "{:?}", policy);
+            assert!(cache.is_empty(), "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn test_cache_type_iter() {
+        for (&policy, mut cache) in POLICIES.iter().zip(all_caches(3)) {
+            cache.put(1, "one", 1);
+            cache.put(2, "two", 2);
+            cache.put(3, "three", 3);
+            cache.get(&1);
+
+            assert_eq!(cache.iter().len(), 3);
+            let mut keys: Vec<i32> = cache.iter().map(|(&key, _)| key).collect();
+            let expected = match policy {
+                Policy::LRU | Policy::MRU => vec![1, 3, 2],
+                // Key 1 was read, but is still worth less than key 3
+                Policy::LFU | Policy::LFUDA => vec![3, 1, 2],
+                Policy::Landlord | Policy::FIFO => vec![3, 2, 1],
+                // Keys seen once come before key 1, seen twice
+                Policy::ARC => vec![3, 2, 1],
+                Policy::Random => {
+                    keys.sort();
+                    vec![1, 2, 3]
+                }
+            };
+            assert_eq!(keys, expected, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn test_cache_type_extend_and_into_iter() {
+        let caches: Vec<CacheType<i32, &str>> = all_caches(3);
+
+        for mut cache in caches {
+            cache.extend(vec![(1, "one", 1), (2, "two", 2), (3, "three", 3)]);
+            assert_eq!((&cache).into_iter().count(), 3);
+
+            let mut entries: Vec<_> = cache.into_iter().collect();
+            entries.sort();
+            assert_eq!(entries, vec![(1, "one"), (2, "two"), (3, "three")]);
+        }
+    }
+
+    #[test]
+    fn test_cache_type_get_or_insert_with() {
+        let mut caches: Vec<CacheType<i32, String>> = all_caches(2);
+
+        for cache in caches.iter_mut() {
+            let mut loads = 0;
+            for _ in 0..2 {
+                let value = cache.get_or_insert_with(7, 1, || {
+                    loads += 1;
+                    "results for 7".to_string()
+                });
+                assert_eq!(value, "results for 7");
+            }
+            assert_eq!(loads, 1);
+        }
+    }
+
+    #[test]
+    fn test_cache_type_try_get_or_insert_with() {
+        let mut caches: Vec<CacheType<i32, String>> = all_caches(2);
+
+        for cache in caches.iter_mut() {
+            let failed = cache.try_get_or_insert_with(7, 1, || Err("timeout"));
+            assert_eq!(failed, Err("timeout"));
+            assert!(cache.is_empty());
+
+            let loaded = cache.try_get_or_insert_with(7, 1, || Ok::<_, &str>("hits".to_string()));
+            assert_eq!(loaded.map(|v| v.as_str()), Ok("hits"));
+        }
+    }
+
+    #[test]
+    fn test_cache_type_retain() {
+        let mut caches: Vec<CacheType<i32, &str>> = all_caches(3);
+
+        for cache in caches.iter_mut() {
+            cache.extend(vec![(1, "one", 1), (2, "two", 1), (3, "three", 1)]);
+            cache.retain(|&key, _| key != 2);
+
+            assert_eq!(cache.len(), 2);
+            assert!(!cache.contains_key(&2));
+            assert_eq!(cache.iter().count(), 2);
+        }
+    }
+
+    #[test]
+    fn test_cache_type_with_custom_hasher() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+
+        type Hasher = BuildHasherDefault<DefaultHasher>;
+        let mut caches: Vec<CacheType<i32, &str, Hasher>> = vec![
+            CacheType::LRU(LRUCache::with_hasher(2, Hasher::default())),
+            CacheType::LFU(LFUCache::with_hasher(2, Hasher::default())),
+            CacheType::Landlord(Landlord::with_hasher(2, Hasher::default())),
+            CacheType::LFUDA(LFUDACache::with_hasher(2, Hasher::default())),
+            CacheType::ARC(ARCCache::with_hasher(2, Hasher::default())),
+            CacheType::FIFO(FIFOCache::with_hasher(2, Hasher::default())),
+            CacheType::Random(RandomCache::with_hasher(2, Hasher::default())),
+            CacheType::MRU(MRUCache::with_hasher(2, Hasher::default())),
+        ];
+
+        for cache in caches.iter_mut() {
+            cache.put(1, "one", 1);
+            assert_eq!(cache.get(&1), Some(&"one"));
+            assert_eq!(cache.remove(&1), Some("one"));
+        }
+    }
+
+    #[test]
+    fn test_cache_type_clear() {
+        let mut caches: Vec<CacheType<i32, &str>> = all_caches(2);
+
+        for cache in caches.iter_mut() {
+            cache.put(1, "one", 1);
+            cache.put(2, "two", 1);
+            cache.clear();
+
+            assert!(cache.is_empty());
+            assert_eq!(cache.get(&1), None);
+
+            cache.put(3, "three", 1);
+            assert_eq!(cache.get(&3), Some(&"three"));
+        }
+    }
+
+    #[test]
+    fn test_cache_type_expire_due() {
+        let mut caches: Vec<CacheType<i32, &str>> = all_caches(2);
+
+        for cache in caches.iter_mut() {
+            let clock = ManualClock::new();
+            cache.set_clock(Arc::new(clock.clone()));
+            assert_eq!(cache.expire_due(clock.now()), 0);
+
+            cache.set_expire_after_write(Duration::from_secs(5));
+            cache.put(1, "one", 1);
+            cache.put(2, "two", 2);
+            cache.remove(&2);
+
+            clock.advance(Duration::from_secs(5));
+            assert_eq!(cache.expire_due(clock.now()), 1);
+            assert!(cache.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_cache_type_max_weight() {
+        for &policy in POLICIES.iter() {
+            // The entry count no longer limits the cache
+            let mut cache = CacheType::new(policy, 1);
+            cache.set_max_weight(100);
+
+            cache.put(1, "one", 40);
+            cache.put(2, "two", 40);
+            assert_eq!(cache.len(), 2, "{:?}", policy);
+
+            cache.put(3, "three", 40);
+            assert_eq!(cache.len(), 2, "{:?}", policy);
+            assert!(cache.contains_key(&3), "{:?}", policy);
+            assert_eq!(cache.stats().current_weight, 80, "{:?}", policy);
+
+            // Heavier than the whole budget: everything else makes way
+            cache.put(4, "four", 500);
+            assert_eq!(cache.len(), 1, "{:?}", policy);
+            assert!(cache.contains_key(&4), "{:?}", policy);
+
+            cache.put(5, "five", 10);
+            assert_eq!(cache.len(), 1, "{:?}", policy);
+            assert!(cache.contains_key(&5), "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn test_cache_type_weigher() {
+        for &policy in POLICIES.iter() {
+            let mut cache = CacheType::new(policy, 4);
+            cache.insert(1, "unweighed");
+            assert_eq!(cache.stats().current_weight, 1, "{:?}", policy);
+
+            cache.set_weigher(|_, value: &&str| value.len() as u32);
+            cache.insert(2, "four");
+            cache.insert(3, "eleven char");
+            assert_eq!(cache.entry_info(&3).unwrap().weight, 11, "{:?}", policy);
+            assert_eq!(cache.stats().current_weight, 16, "{:?}", policy);
+
+            // An explicit weight still wins
+            cache.put(4, "four", 40);
+            assert_eq!(cache.entry_info(&4).unwrap().weight, 40, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn test_lowering_max_weight_evicts() {
+        for &policy in POLICIES.iter() {
+            let mut cache = CacheType::new(policy, 4);
+            for key in 1..=4 {
+                cache.put(key, "value", 10);
+            }
+
+            cache.set_max_weight(25);
+            assert_eq!(cache.len(), 2, "{:?}", policy);
+            assert_eq!(cache.stats().evictions, 2, "{:?}", policy);
+        }
+    }
+
+    fn exercise_cache<C: Cache<i32, &'static str>>(mut cache: C) {
+        assert!(cache.is_empty());
+
+        cache.put(1, "one", 10);
+        cache.put(2, "two", 5);
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.peek(&2), Some(&"two"));
+        assert!(cache.contains_key(&2));
+        assert_eq!(cache.peek_victim(), Some(&2));
+
+        // Key 2 is the victim for every policy: least recent, least frequent and lowest credit
+        cache.put(3, "three", 10);
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.len(), 2);
+
+        *cache.get_mut(&3).unwrap() = "THREE";
+        assert_eq!(cache.remove(&3), Some("THREE"));
+        assert_eq!(cache.pop_victim(), Some((1, "one")));
+
+        cache.put(4, "four", 1);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    fn new_cache<C: NewCache<i32, &'static str>>() -> C {
+        C::new(2)
+    }
+
+    #[test]
+    fn test_cache_trait_is_implemented_by_every_policy() {
+        exercise_cache(new_cache::<LRUCache<i32, &str>>());
+        exercise_cache(new_cache::<LFUCache<i32, &str>>());
+        exercise_cache(new_cache::<Landlord<i32, &str>>());
+        exercise_cache(new_cache::<LFUDACache<i32, &str>>());
+        exercise_cache(new_cache::<ARCCache<i32, &str>>());
+        exercise_cache(new_cache::<gdsf::GDSFCache<i32, &str>>());
+        exercise_cache(new_cache::<greedy_dual::GreedyDual<i32, &str>>());
+        exercise_cache(new_cache::<slru::SLRUCache<i32, &str>>());
+        exercise_cache(new_cache::<mq::MQCache<i32, &str>>());
+        exercise_cache(new_cache::<wtinylfu::WTinyLFUCache<i32, &str>>());
+    }
+
+    #[test]
+    fn test_boxed_dyn_cache() {
+        let policies = ["lru", "lfu", "landlord", "lfuda", "arc"];
+
+        for policy in policies.iter() {
+            let cache: Box<dyn Cache<i32, &str>> = match *policy {
+                "lru" => Box::new(LRUCache::new(2)),
+                "lfu" => Box::new(LFUCache::new(2)),
+                "landlord" => Box::new(Landlord::new(2)),
+                "lfuda" => Box::new(LFUDACache::new(2)),
+                _ => Box::new(ARCCache::new(2)),
+            };
+            exercise_cache(cache);
+        }
+    }
+
+    #[test]
+    fn test_cache_type_implements_cache() {
+        for &policy in [
+            Policy::LRU,
+            Policy::LFU,
+            Policy::Landlord,
+            Policy::LFUDA,
+            Policy::ARC,
+        ]
+        .iter()
+        {
+            let cache: CacheType<i32, &str> = CacheType::new(policy, 2);
+            assert_eq!(cache.policy(), policy);
+            exercise_cache(cache);
+        }
+    }
+
+    // Neither baseline looks at accesses, so `exercise_cache` does not apply.
+    #[test]
+    fn test_baseline_policies_implement_cache() {
+        for &policy in [Policy::FIFO, Policy::Random].iter() {
+            let mut cache: Box<dyn Cache<i32, &str>> = Box::new(CacheType::new(policy, 2));
+            cache.put(1, "one", 1);
+            cache.put(2, "two", 1);
+            cache.get(&1);
+            cache.put(3, "three", 1);
+            assert_eq!(cache.len(), 2, "{:?}", policy);
+            assert!(cache.contains_key(&3), "{:?}", policy);
+
+            let victim = *cache.peek_victim().unwrap();
+            assert_eq!(cache.pop_victim().map(|(key, _)| key), Some(victim));
+            assert_eq!(cache.len(), 1, "{:?}", policy);
+            cache.clear();
+            assert!(cache.is_empty(), "{:?}", policy);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_cache_type_round_trips_through_serde() {
+        for &policy in POLICIES.iter() {
+            let mut cache = CacheType::new(policy, 4);
+            for key in 0..6 {
+                cache.put(key, key.to_string(), 1);
+                cache.get(&(key / 2));
+            }
+
+            let json = serde_json::to_string(&cache).unwrap();
+            let mut restored: CacheType<i32, String> = serde_json::from_str(&json).unwrap();
+            assert_eq!(restored.policy(), policy);
+            assert_eq!(restored.stats(), cache.stats(), "{:?}", policy);
+            while let Some(victim) = cache.pop_victim() {
+                assert_eq!(restored.pop_victim(), Some(victim), "{:?}", policy);
+            }
+            assert!(restored.is_empty(), "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn test_cache_type_round_trips_through_snapshot() {
+        for &policy in POLICIES.iter() {
+            let mut cache = CacheType::new(policy, 4);
+            for key in 0..6 {
+                cache.put(key, key.to_string(), 1);
+                cache.get(&(key / 2));
+            }
+
+            let mut bytes = Vec::new();
+            cache.save_to(&mut bytes).unwrap();
+            let mut restored: CacheType<i32, String> =
+                CacheType::load_from(bytes.as_slice()).unwrap();
+            assert_eq!(restored.policy(), policy);
+            assert_eq!(restored.stats(), cache.stats(), "{:?}", policy);
+            while let Some(victim) = cache.pop_victim() {
+                assert_eq!(restored.pop_victim(), Some(victim), "{:?}", policy);
+            }
+            assert!(restored.is_empty(), "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn test_export_top_is_the_reverse_of_eviction_order() {
+        for &policy in &[
+            Policy::LRU,
+            Policy::LFU,
+            Policy::Landlord,
+            Policy::LFUDA,
+            Policy::FIFO,
+            Policy::MRU,
+        ] {
+            let mut cache = CacheType::new(policy, 4);
+            for key in 0..4u32 {
+                cache.put(key, key * 10, key + 1);
+                for _ in 0..key {
+                    cache.get(&key);
+                }
+            }
+            let top = cache.export_top(3);
+            assert_eq!(top.len(), 3, "{:?}", policy);
+            let mut hottest_last: Vec<u32> = cache.export_top(4).iter().map(|e| e.0).collect();
+            hottest_last.reverse();
+            let victims: Vec<u32> =
+                std::iter::from_fn(|| cache.pop_victim().map(|(key, _)| key)).collect();
+            assert_eq!(hottest_last, victims, "{:?}", policy);
+            assert_eq!(top[0], (victims[3], victims[3] * 10, victims[3] + 1));
+        }
+    }
+
+    #[test]
+    fn test_imported_entries_outlast_new_ones() {
+        for &policy in &[
+            Policy::LRU,
+            Policy::LFU,
+            Policy::LFUDA,
+            Policy::ARC,
+            Policy::FIFO,
+            Policy::MRU,
+        ] {
+            let mut old = CacheType::new(policy, 8);
+            for key in 0..8 {
+                old.put(key, key, 1);
+                old.get(&key);
+            }
+            let top = old.export_top(2);
+            let hottest = top[0].0;
+
+            let mut replacement = CacheType::new(policy, 3);
+            replacement.import(top);
+            assert_eq!(replacement.len(), 2, "{:?}", policy);
+            replacement.put(100, 100, 1);
+            replacement.put(101, 101, 1);
+            assert!(replacement.contains_key(&hottest), "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn test_get_stale_serves_expired_entries() {
+        for &policy in POLICIES.iter() {
+            let clock = ManualClock::new();
+            let mut cache = CacheType::new(policy, 4);
+            cache.set_clock(Arc::new(clock.clone()));
+            cache.set_expire_after_write(Duration::from_secs(10));
+            cache.put(1, "one", 1);
+            assert_eq!(cache.get_stale(&1), Some(Freshness::Fresh(&"one")));
+
+            clock.advance(Duration::from_secs(10));
+            assert_eq!(cache.get_stale(&1), Some(Freshness::Stale(&"one")));
+            assert_eq!(cache.get_stale(&2), None);
+            assert_eq!(cache.stats().hits, 1, "{:?}", policy);
+            assert_eq!(cache.stats().misses, 2, "{:?}", policy);
+
+            // The stale entry is kept until it is refreshed or read the usual way
+            cache.put(1, "ONE", 1);
+            assert_eq!(cache.get_stale(&1), Some(Freshness::Fresh(&"ONE")));
+            clock.advance(Duration::from_secs(10));
+            assert_eq!(cache.get(&1), None);
+            assert_eq!(cache.get_stale(&1), None, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn test_policy_from_config_string() {
+        let policy: Policy = "Landlord".parse().unwrap();
+        assert_eq!(policy, Policy::Landlord);
+        assert_eq!("lru".parse(), Ok(Policy::LRU));
+        assert_eq!("LFU".parse(), Ok(Policy::LFU));
+        assert_eq!("lfuda".parse(), Ok(Policy::LFUDA));
+
+        assert_eq!("ARC".parse(), Ok(Policy::ARC));
+        assert_eq!("fifo".parse(), Ok(Policy::FIFO));
+        assert_eq!("Random".parse(), Ok(Policy::Random));
+        assert_eq!("MRU".parse(), Ok(Policy::MRU));
+
+        let err = "clock".parse::<Policy>().unwrap_err();
+        assert_eq!(err.to_string(), "unknown cache policy: \"clock\"");
+    }
+
     #[test]
     fn test_all_caches_with_strings() {
         // LRU
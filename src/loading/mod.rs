@@ -0,0 +1,239 @@
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::{Clock, SystemClock};
+use crate::{CacheStats, CacheType, Weigher};
+
+// Computes the value for a key the cache does not hold, such as running a query.
+pub trait Loader<K, V> {
+    type Error;
+
+    fn load(&mut self, key: &K) -> Result<V, Self::Error>;
+}
+
+impl<K, V, E, F: FnMut(&K) -> Result<V, E>> Loader<K, V> for F {
+    type Error = E;
+
+    fn load(&mut self, key: &K) -> Result<V, E> {
+        self(key)
+    }
+}
+
+// Counters for the loads a `LoadingCache` ran.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoadStats {
+    pub loads: u64,
+    pub failures: u64,
+    // Time spent in the loader, failed loads included.
+    pub load_time: Duration,
+}
+
+impl LoadStats {
+    pub fn average_load_time(&self) -> Duration {
+        match self.loads + self.failures {
+            0 => Duration::ZERO,
+            calls => self.load_time / u32::try_from(calls).unwrap_or(u32::MAX),
+        }
+    }
+}
+
+// A cache that fills itself: `get` runs the loader on a miss and caches what it returns.
+// Each load is timed, and with `set_cost_unit` its latency becomes the entry's cost, so a
+// cost-aware policy like Landlord keeps the expensive queries and gives up the cheap ones.
+// Policies that are not cost-aware ignore the cost.
+//
+// Loaded entries are weighed with the weigher, or given a weight of 1 without one. Failed
+// loads cache nothing and are retried on the next lookup.
+pub struct LoadingCache<K, V, L, S = RandomState> {
+    cache: CacheType<K, V, S>,
+    loader: L,
+    weigher: Option<Weigher<K, V>>,
+    cost_unit: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    load_stats: LoadStats,
+}
+
+impl<K, V, L, S> LoadingCache<K, V, L, S>
+where
+    K: Clone + Hash + Eq,
+    L: Loader<K, V>,
+    S: BuildHasher + Clone,
+{
+    pub fn new(cache: CacheType<K, V, S>, loader: L) -> Self {
+        LoadingCache {
+            cache,
+            loader,
+            weigher: None,
+            cost_unit: None,
+            clock: Arc::new(SystemClock),
+            load_stats: LoadStats::default(),
+        }
+    }
+
+    pub fn set_weigher<F: Fn(&K, &V) -> u32 + Send + 'static>(&mut self, weigher: F) {
+        self.weigher = Some(Box::new(weigher));
+    }
+
+    // Charges each loaded entry one unit of cost per `unit` its load took, and at least one.
+    pub fn set_cost_unit(&mut self, unit: Duration) {
+        assert!(unit > Duration::ZERO, "Cost unit must be positive");
+        self.cost_unit = Some(unit);
+    }
+
+    // The clock loads are timed with, so tests can control their latency.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    // Loads the value on a miss, which the cache's stats still count as one. Returns
+    // `None` only when the cache turned the loaded value away, e.g. for being heavier
+    // than its capacity.
+    pub fn get(&mut self, key: &K) -> Result<Option<&V>, L::Error> {
+        if self.cache.get(key).is_none() {
+            let started = self.clock.now();
+            let loaded = self.loader.load(key);
+            let latency = self.clock.now().saturating_duration_since(started);
+            self.load_stats.load_time += latency;
+            let value = match loaded {
+                Ok(value) => value,
+                Err(error) => {
+                    self.load_stats.failures += 1;
+                    return Err(error);
+                }
+            };
+            self.load_stats.loads += 1;
+            let weight = self
+                .weigher
+                .as_ref()
+                .map_or(1, |weigher| weigher(key, &value));
+            let cost = self.cost_unit.map_or(weight, |unit| cost_of(latency, unit));
+            self.cache.put_sized(key.clone(), value, cost, weight);
+        }
+        Ok(self.cache.peek(key))
+    }
+
+    // Only looks in the cache, without loading.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.cache.peek(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        self.cache.put(key, value, weight);
+    }
+
+    // The next lookup loads the key again.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.cache.remove(key)
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn load_stats(&self) -> LoadStats {
+        self.load_stats
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn loader(&self) -> &L {
+        &self.loader
+    }
+
+    pub fn cache(&self) -> &CacheType<K, V, S> {
+        &self.cache
+    }
+
+    pub fn cache_mut(&mut self) -> &mut CacheType<K, V, S> {
+        &mut self.cache
+    }
+
+    pub fn into_inner(self) -> (CacheType<K, V, S>, L) {
+        (self.cache, self.loader)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+fn cost_of(latency: Duration, unit: Duration) -> u32 {
+    let units = latency.as_nanos() / unit.as_nanos();
+    u32::try_from(units).unwrap_or(u32::MAX).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::Policy;
+
+    #[test]
+    fn test_loads_on_a_miss_only() {
+        let mut loads = 0;
+        let mut cache = LoadingCache::new(CacheType::new(Policy::LRU, 2), |key: &i32| {
+            loads += 1;
+            Ok::<_, String>(key * 10)
+        });
+        assert_eq!(cache.get(&1), Ok(Some(&10)));
+        assert_eq!(cache.get(&1), Ok(Some(&10)));
+        assert_eq!(cache.load_stats().loads, 1);
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().misses, 1);
+        drop(cache);
+        assert_eq!(loads, 1);
+    }
+
+    #[test]
+    fn test_failed_loads_cache_nothing() {
+        let mut cache = LoadingCache::new(CacheType::new(Policy::LRU, 2), |key: &i32| {
+            if *key < 0 {
+                Err("negative")
+            } else {
+                Ok(*key)
+            }
+        });
+        assert_eq!(cache.get(&-1), Err("negative"));
+        assert!(!cache.contains_key(&-1));
+        assert_eq!(cache.load_stats().failures, 1);
+        assert_eq!(cache.load_stats().loads, 0);
+    }
+
+    #[test]
+    fn test_latency_is_the_cost() {
+        let clock = ManualClock::new();
+        let loader_clock = clock.clone();
+        let mut cache = LoadingCache::new(CacheType::new(Policy::Landlord, 2), move |key: &u64| {
+            loader_clock.advance(Duration::from_millis(*key));
+            Ok::<_, ()>(*key)
+        });
+        cache.set_clock(Arc::new(clock));
+        cache.set_cost_unit(Duration::from_millis(1));
+
+        cache.get(&50).unwrap();
+        cache.get(&2).unwrap();
+        cache.get(&30).unwrap();
+        // The cheapest query to recompute made room
+        assert!(!cache.contains_key(&2));
+        assert!(cache.contains_key(&50));
+        assert_eq!(cache.stats().current_weight, 2);
+        assert_eq!(cache.load_stats().load_time, Duration::from_millis(82));
+        assert_eq!(
+            cache.load_stats().average_load_time(),
+            Duration::from_nanos(82_000_000 / 3)
+        );
+    }
+}
@@ -1,75 +1,445 @@
-use std::collections::HashMap;
-use std::hash::Hash;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::expiry::{Expiration, Freshness, Timestamps, TimingWheel, WHEEL_RESOLUTION};
+use crate::observer::{BoxedObserver, CacheObserver};
+use crate::stats::{CacheStats, EntryInfo};
+use crate::trace;
+use crate::util::{KeyIndex, Keyed, Link, Slab, SlabKey};
+use crate::{Cache, EvictionListener, NewCache, Weigher};
+
+mod entry;
+mod persist;
+
+const POLICY: &str = "lru";
+
+pub use self::entry::{Entry, OccupiedEntry, VacantEntry};
 
 struct Node<K, V> {
     key: K,
     value: V,
-    prev: Option<usize>,
+    weight: u32,
+    timestamps: Timestamps,
+    // Still in the probationary part of the list, not yet hit since it was inserted
+    probation: bool,
+    prev: Link,
+    next: Link,
+}
+
+impl<K, V> Keyed for Node<K, V> {
+    type Key = K;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    nodes: &'a Slab<Node<K, V>>,
     next: Option<usize>,
+    remaining: usize,
 }
 
-pub struct LRUCache<K, V> {
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.nodes.get_at(self.next?)?;
+        self.next = node.next.get();
+        self.remaining -= 1;
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+pub struct IntoIter<K, V> {
+    nodes: Slab<Node<K, V>>,
+    next: Option<usize>,
+    remaining: usize,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.nodes.remove(self.next?);
+        self.next = node.next.get();
+        self.remaining -= 1;
+        Some((node.key, node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+pub struct LRUCache<K, V, S = RandomState> {
     capacity: usize,
-    map: HashMap<K, usize>,
-    nodes: Vec<Node<K, V>>,
-    head: Option<usize>,
-    tail: Option<usize>,
-    free_list: Vec<usize>,
+    max_weight: Option<u64>,
+    map: KeyIndex<S>,
+    nodes: Slab<Node<K, V>>,
+    head: Link,
+    tail: Link,
+    // The share of the list kept probationary under midpoint insertion, if enabled
+    probation_share: Option<f64>,
+    // The most recent probationary node; it and everything after it are probationary
+    midpoint: Link,
+    probation_len: usize,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    weigher: Option<Weigher<K, V>>,
+    observer: Option<BoxedObserver<K>>,
+    stats: CacheStats,
+    expiration: Expiration,
+    expiry_wheel: Option<TimingWheel<SlabKey>>,
+    clock: Arc<dyn Clock>,
 }
 
-impl<K: Clone + Hash + Eq, V> LRUCache<K, V> {
+impl<K: Hash + Eq, V> LRUCache<K, V> {
     pub fn new(capacity: usize) -> Self {
+        LRUCache::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> LRUCache<K, V, S> {
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
         assert!(capacity > 0, "Capacity must be greater than 0");
         LRUCache {
             capacity,
-            map: HashMap::new(),
-            nodes: Vec::with_capacity(capacity),
-            head: None,
-            tail: None,
-            free_list: Vec::new(),
+            max_weight: None,
+            map: KeyIndex::with_hasher(hash_builder),
+            nodes: Slab::with_capacity(capacity),
+            head: Link::NONE,
+            tail: Link::NONE,
+            probation_share: None,
+            midpoint: Link::NONE,
+            probation_len: 0,
+            eviction_listener: None,
+            weigher: None,
+            observer: None,
+            stats: CacheStats::default(),
+            expiration: Expiration::default(),
+            expiry_wheel: None,
+            clock: Arc::new(SystemClock),
         }
     }
 
     pub fn get(&mut self, key: &K) -> Option<&V> {
-        let idx = *self.map.get(key)?;
+        let _span = trace::get_span(POLICY, key);
+        let idx = self.live_index(key)?;
         self.move_to_front(idx);
-        Some(&self.nodes[idx].value)
+        Some(&self.node(idx).value)
+    }
+
+    pub fn get_stale(&mut self, key: &K) -> Option<Freshness<&V>> {
+        let now = self.clock.now();
+        match self.index_of(key) {
+            Some(idx) if self.is_expired(idx, now) => {
+                self.stats.misses += 1;
+                trace::miss(POLICY, key);
+                self.notify(|observer| observer.on_miss(key));
+                Some(Freshness::Stale(&self.node(idx).value))
+            }
+            _ => self.get(key).map(Freshness::Fresh),
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let idx = self.live_index(key)?;
+        self.move_to_front(idx);
+        Some(&mut self.node_mut(idx).value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        let idx = self.index_of(key)?;
+        if self.is_expired(idx, self.clock.now()) {
+            return None;
+        }
+        Some(&self.node(idx).value)
     }
 
-    pub fn put(&mut self, key: K, value: V, _weight: u32) {
-        if let Some(&idx) = self.map.get(&key) {
-            self.nodes[idx].value = value;
+    // Like `peek`, this does not count as a use of the key.
+    pub fn entry_info(&self, key: &K) -> Option<EntryInfo> {
+        let idx = self.index_of(key)?;
+        if self.is_expired(idx, self.clock.now()) {
+            return None;
+        }
+        let node = self.node(idx);
+        Some(EntryInfo::new(
+            &node.timestamps,
+            node.weight,
+            &self.expiration,
+        ))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+
+    // The weight does not affect recency order; it only counts towards `current_weight`
+    // and the weight budget.
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        let _span = trace::put_span(POLICY, &key, weight);
+        if let Some(idx) = self.index_of(&key) {
+            let now = self.clock.now();
+            let node = self.node_mut(idx);
+            let old_weight = node.weight;
+            node.value = value;
+            node.weight = weight;
+            node.timestamps.rewrite(now);
             self.move_to_front(idx);
+            self.stats.current_weight -= u64::from(old_weight);
+            self.stats.current_weight += u64::from(weight);
+            // A heavier value can push the cache over its budget; the key itself is the
+            // most recently used, so it goes last
+            while self.len() > 1 && self.is_over_budget() {
+                self.evict();
+            }
+            self.notify(|observer| observer.on_update(&key));
         } else {
-            // Need to evict if at capacity
-            if self.map.len() >= self.capacity {
-                self.remove_tail();
+            self.insert_new(key, value, weight);
+        }
+    }
+
+    // Stores the value with the weight the weigher gives it, or a weight of 1 without one.
+    pub fn insert(&mut self, key: K, value: V) {
+        let weight = self
+            .weigher
+            .as_ref()
+            .map_or(1, |weigher| weigher(&key, &value));
+        self.put(key, value, weight);
+    }
+
+    // An occupied entry counts as a use of the key, just like `get`.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        match self.live_index(&key) {
+            Some(idx) => {
+                self.move_to_front(idx);
+                Entry::Occupied(OccupiedEntry { cache: self, idx })
             }
+            None => Entry::Vacant(VacantEntry { cache: self, key }),
+        }
+    }
 
-            // Get index for new node
-            let idx = if let Some(free_idx) = self.free_list.pop() {
-                self.nodes[free_idx] = Node {
-                    key: key.clone(),
-                    value,
-                    prev: None,
-                    next: None,
-                };
-                free_idx
-            } else {
-                self.nodes.push(Node {
-                    key: key.clone(),
-                    value,
-                    prev: None,
-                    next: None,
-                });
-                self.nodes.len() - 1
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, weight: u32, f: F) -> &V {
+        self.entry(key).or_insert_with(weight, f)
+    }
+
+    // A failed computation leaves the cache untouched and returns the error.
+    pub fn try_get_or_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        weight: u32,
+        f: F,
+    ) -> Result<&V, E> {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(f()?, weight)),
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let idx = self.index_of(key)?;
+        Some(self.remove_at(idx).value)
+    }
+
+    // The entry `pop_victim` would remove, left in place.
+    pub fn peek_victim(&self) -> Option<&K> {
+        Some(&self.node(self.tail.get()?).key)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        let tail_idx = self.tail.get()?;
+        let node = self.remove_at(tail_idx);
+        Some((node.key, node.value))
+    }
+
+    // Evicts entries as a full cache would, listener and stats included, until at most
+    // `len` are left. Returns how many were evicted.
+    pub fn evict_to(&mut self, len: usize) -> usize {
+        let mut evicted = 0;
+        while self.len() > len {
+            self.evict();
+            evicted += 1;
+        }
+        evicted
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        let doomed: Vec<usize> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| !f(&node.key, &node.value))
+            .map(|(idx, _)| idx)
+            .collect();
+
+        for idx in doomed {
+            self.remove_at(idx);
+        }
+    }
+
+    pub fn set_expire_after_write(&mut self, time_to_live: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_live = Some(time_to_live);
+        self.set_expiration(expiration);
+    }
+
+    pub fn set_expire_after_access(&mut self, time_to_idle: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_idle = Some(time_to_idle);
+        self.set_expiration(expiration);
+    }
+
+    // Existing entries are scheduled against the new limits.
+    pub(crate) fn set_expiration(&mut self, expiration: Expiration) {
+        self.expiration = expiration;
+        self.expiry_wheel = if expiration.is_enabled() {
+            Some(TimingWheel::new(self.clock.now(), WHEEL_RESOLUTION))
+        } else {
+            None
+        };
+        let live: Vec<usize> = self.nodes.iter().map(|(idx, _)| idx).collect();
+        for idx in live {
+            self.schedule_expiry(idx);
+        }
+    }
+
+    // Replaces the time source; entries keep the timestamps they were given by the old one.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+        let expiration = self.expiration;
+        self.set_expiration(expiration);
+    }
+
+    pub fn expire_due(&mut self, now: Instant) -> usize {
+        let due = match self.expiry_wheel.as_mut() {
+            Some(wheel) => wheel.advance(now),
+            None => return 0,
+        };
+
+        let mut expired = 0;
+        for slot in due {
+            let idx = match self.nodes.get(slot) {
+                Some(_) => slot.index(),
+                None => continue,
             };
+            if self.is_expired(idx, now) {
+                self.remove_at(idx);
+                expired += 1;
+            } else {
+                // Touched or rewritten since it was scheduled
+                self.schedule_expiry(idx);
+            }
+        }
+        expired
+    }
 
-            self.map.insert(key, idx);
-            self.add_to_front(idx);
+    // Midpoint insertion: new entries join the list `share` of the way up from the victim
+    // end, instead of at the front, and only move to the front when hit again. Entries
+    // used once, like those of a scan, then only displace each other, while entries hit
+    // since they were inserted keep the other part of the cache. 1 behaves like plain LRU.
+    pub fn set_midpoint_insertion(&mut self, share: f64) {
+        assert!(
+            share > 0.0 && share <= 1.0,
+            "Midpoint share must be above 0 and at most 1"
+        );
+        self.probation_share = Some(share);
+        self.rebalance();
+    }
+
+    pub fn set_max_weight(&mut self, max_weight: u64) {
+        self.max_weight = Some(max_weight);
+        while !self.is_empty() && self.is_over_budget() {
+            self.evict();
+        }
+    }
+
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.set_boxed_eviction_listener(Box::new(listener));
+    }
+
+    pub(crate) fn set_boxed_eviction_listener(&mut self, listener: EvictionListener<K, V>) {
+        self.eviction_listener = Some(listener);
+    }
+
+    // Computes the weight of entries stored with `insert`.
+    pub fn set_weigher<F: Fn(&K, &V) -> u32 + Send + 'static>(&mut self, weigher: F) {
+        self.set_boxed_weigher(Box::new(weigher));
+    }
+
+    pub(crate) fn set_boxed_weigher(&mut self, weigher: Weigher<K, V>) {
+        self.weigher = Some(weigher);
+    }
+
+    pub fn set_observer<O: CacheObserver<K> + Send + 'static>(&mut self, observer: O) {
+        self.set_boxed_observer(Box::new(observer));
+    }
+
+    pub(crate) fn set_boxed_observer(&mut self, observer: BoxedObserver<K>) {
+        self.observer = Some(observer);
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.nodes.clear();
+        self.head = Link::NONE;
+        self.tail = Link::NONE;
+        self.midpoint = Link::NONE;
+        self.probation_len = 0;
+        self.stats.current_weight = 0;
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.clear();
         }
     }
 
+    // Moves entries out of the slots freed by removals, so the node storage and the map
+    // can release the memory they no longer need.
+    pub fn shrink_to_fit(&mut self) {
+        for (from, to) in self.nodes.compaction_moves() {
+            self.relocate(from, to);
+        }
+        self.nodes.shrink_to_fit();
+        self.map.shrink_to_fit(&self.nodes);
+    }
+
+    // Reserves room for a full cache, so once it is warm, evicting to make room for new
+    // entries reuses memory instead of allocating.
+    pub fn preallocate(&mut self) {
+        self.nodes.reserve(self.capacity);
+        self.map.reserve(self.capacity, &self.nodes);
+    }
+
+    // Entries from most recently used to the next victim.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            nodes: &self.nodes,
+            next: self.head.get(),
+            remaining: self.map.len(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
     pub fn len(&self) -> usize {
         self.map.len()
     }
@@ -78,59 +448,370 @@ impl<K: Clone + Hash + Eq, V> LRUCache<K, V> {
         self.map.is_empty()
     }
 
-    fn move_to_front(&mut self, idx: usize) {
-        if self.head == Some(idx) {
-            return;
+    fn insert_new(&mut self, key: K, value: V, weight: u32) -> usize {
+        self.make_room(weight);
+
+        self.stats.insertions += 1;
+        self.stats.current_weight += u64::from(weight);
+        trace::insert(POLICY, &key, weight);
+        self.notify(|observer| observer.on_insert(&key));
+        let node = Node {
+            key,
+            value,
+            weight,
+            timestamps: Timestamps::new(self.clock.now()),
+            probation: false,
+            prev: Link::NONE,
+            next: Link::NONE,
+        };
+        let slot = self.nodes.insert(node);
+        let idx = slot.index();
+        self.map.insert(slot, &self.nodes);
+        if self.probation_share.is_some() {
+            self.add_to_midpoint(idx);
+        } else {
+            self.add_to_front(idx);
         }
+        self.schedule_expiry(idx);
+        idx
+    }
+
+    // Whether an entry of this weight only fits after an eviction.
+    fn is_full(&self, weight: u32) -> bool {
+        match self.max_weight {
+            Some(max_weight) => self.stats.current_weight + u64::from(weight) > max_weight,
+            None => self.len() >= self.capacity,
+        }
+    }
 
+    fn is_over_budget(&self) -> bool {
+        self.max_weight
+            .is_some_and(|max_weight| self.stats.current_weight > max_weight)
+    }
+
+    fn make_room(&mut self, weight: u32) {
+        while !self.is_empty() && self.is_full(weight) {
+            self.evict();
+        }
+    }
+
+    // Looks up a key, dropping it instead if it has expired, and records the access.
+    // This is the single place hits and misses are observed.
+    fn live_index(&mut self, key: &K) -> Option<usize> {
+        let now = self.clock.now();
+        let idx = match self.index_of(key) {
+            Some(idx) if self.is_expired(idx, now) => {
+                self.remove_at(idx);
+                None
+            }
+            found => found,
+        };
+        match idx {
+            Some(idx) => {
+                self.node_mut(idx).timestamps.touch(now);
+                self.stats.hits += 1;
+                self.notify(|observer| observer.on_hit(key));
+            }
+            None => {
+                self.stats.misses += 1;
+                trace::miss(POLICY, key);
+                self.notify(|observer| observer.on_miss(key));
+            }
+        }
+        idx
+    }
+
+    fn is_expired(&self, idx: usize, now: Instant) -> bool {
+        self.expiration.is_expired(&self.node(idx).timestamps, now)
+    }
+
+    fn schedule_expiry(&mut self, idx: usize) {
+        let node = &self.nodes[idx];
+        if let (Some(wheel), Some(deadline)) = (
+            self.expiry_wheel.as_mut(),
+            self.expiration.deadline(&node.timestamps),
+        ) {
+            wheel.schedule(self.nodes.key_at(idx), deadline);
+        }
+    }
+
+    fn remove_at(&mut self, idx: usize) -> Node<K, V> {
+        if self.node(idx).probation {
+            self.leave_probation(idx);
+        }
         self.detach(idx);
-        self.add_to_front(idx);
+        let slot = self.nodes.key_at(idx);
+        let node = self.nodes.remove(idx);
+        self.map.remove(&node.key, idx);
+        self.stats.current_weight -= u64::from(node.weight);
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.cancel(&slot);
+        }
+        self.rebalance();
+        node
+    }
+
+    fn move_to_front(&mut self, idx: usize) {
+        let promoted = self.node(idx).probation;
+        if promoted {
+            self.leave_probation(idx);
+        }
+        if self.head != Link::some(idx) {
+            self.detach(idx);
+            self.add_to_front(idx);
+        }
+        if promoted {
+            self.rebalance();
+        }
+    }
+
+    // Unmarks a probationary node, moving the midpoint past it if it was there.
+    fn leave_probation(&mut self, idx: usize) {
+        if self.midpoint == Link::some(idx) {
+            self.midpoint = self.node(idx).next;
+        }
+        self.node_mut(idx).probation = false;
+        self.probation_len -= 1;
+    }
+
+    // Moves the midpoint until the probationary part holds its share of the entries.
+    fn rebalance(&mut self) {
+        let share = match self.probation_share {
+            Some(share) => share,
+            None => return,
+        };
+        let target = (self.len() as f64 * share) as usize;
+        while self.probation_len > target {
+            let idx = self
+                .midpoint
+                .get()
+                .expect("probationary nodes follow the midpoint");
+            self.leave_probation(idx);
+        }
+        while self.probation_len < target {
+            let newest = match self.midpoint.get() {
+                Some(midpoint) => self.node(midpoint).prev,
+                None => self.tail,
+            };
+            let idx = match newest.get() {
+                Some(idx) => idx,
+                None => break,
+            };
+            self.node_mut(idx).probation = true;
+            self.probation_len += 1;
+            self.midpoint = Link::some(idx);
+        }
+    }
+
+    // Links a new node in as the most recent probationary one.
+    fn add_to_midpoint(&mut self, idx: usize) {
+        let next = self.midpoint;
+        let prev = match next.get() {
+            Some(n) => self.node(n).prev,
+            None => self.tail,
+        };
+        let node = self.node_mut(idx);
+        node.prev = prev;
+        node.next = next;
+        node.probation = true;
+        match prev.get() {
+            Some(p) => self.node_mut(p).next = Link::some(idx),
+            None => self.head = Link::some(idx),
+        }
+        match next.get() {
+            Some(n) => self.node_mut(n).prev = Link::some(idx),
+            None => self.tail = Link::some(idx),
+        }
+        self.midpoint = Link::some(idx);
+        self.probation_len += 1;
+        self.rebalance();
+    }
+
+    fn notify<F: FnOnce(&mut dyn CacheObserver<K>)>(&mut self, f: F) {
+        if let Some(observer) = self.observer.as_mut() {
+            f(&mut **observer);
+        }
+    }
+
+    // Capacity-driven eviction; the listener sees every entry dropped this way.
+    fn evict(&mut self) {
+        if let Some((key, value)) = self.pop_victim() {
+            self.stats.evictions += 1;
+            trace::eviction(POLICY, &key);
+            if let Some(listener) = self.eviction_listener.as_mut() {
+                listener(key, value);
+            }
+        }
+    }
+
+    fn node(&self, idx: usize) -> &Node<K, V> {
+        &self.nodes[idx]
+    }
+
+    fn node_mut(&mut self, idx: usize) -> &mut Node<K, V> {
+        &mut self.nodes[idx]
+    }
+
+    fn index_of(&self, key: &K) -> Option<usize> {
+        self.map.get(key, &self.nodes).map(SlabKey::index)
+    }
+
+    // Moves a node into a free slot, repointing its neighbours and its map entry.
+    fn relocate(&mut self, from: usize, to: usize) {
+        let old = self.nodes.key_at(from);
+        let slot = self.nodes.relocate(from, to);
+        let (prev, next) = (self.nodes[to].prev, self.nodes[to].next);
+        match prev.get() {
+            Some(p) => self.node_mut(p).next = Link::some(to),
+            None => self.head = Link::some(to),
+        }
+        match next.get() {
+            Some(n) => self.node_mut(n).prev = Link::some(to),
+            None => self.tail = Link::some(to),
+        }
+        if self.midpoint == Link::some(from) {
+            self.midpoint = Link::some(to);
+        }
+        self.map.repoint(&self.nodes[to].key, from, slot);
+        if self
+            .expiry_wheel
+            .as_mut()
+            .is_some_and(|wheel| wheel.cancel(&old))
+        {
+            self.schedule_expiry(to);
+        }
     }
 
     fn detach(&mut self, idx: usize) {
-        let node = &self.nodes[idx];
+        let node = self.node(idx);
         let prev = node.prev;
         let next = node.next;
 
-        match prev {
-            Some(p) => self.nodes[p].next = next,
+        match prev.get() {
+            Some(p) => self.node_mut(p).next = next,
             None => self.head = next,
         }
 
-        match next {
-            Some(n) => self.nodes[n].prev = prev,
+        match next.get() {
+            Some(n) => self.node_mut(n).prev = prev,
             None => self.tail = prev,
         }
     }
 
     fn add_to_front(&mut self, idx: usize) {
-        self.nodes[idx].prev = None;
-        self.nodes[idx].next = self.head;
+        let head = self.head;
+        let node = self.node_mut(idx);
+        node.prev = Link::NONE;
+        node.next = head;
 
-        if let Some(old_head) = self.head {
-            self.nodes[old_head].prev = Some(idx);
+        if let Some(old_head) = head.get() {
+            self.node_mut(old_head).prev = Link::some(idx);
         }
 
-        self.head = Some(idx);
+        self.head = Link::some(idx);
 
         if self.tail.is_none() {
-            self.tail = Some(idx);
+            self.tail = Link::some(idx);
         }
     }
+}
+
+impl<K, V, S> IntoIterator for LRUCache<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter {
+            remaining: self.map.len(),
+            nodes: self.nodes,
+            next: self.head.get(),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> IntoIterator for &'a LRUCache<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+// The capacity is sized to hold every collected entry.
+impl<K: Hash + Eq, V, S: BuildHasher + Default> FromIterator<(K, V, u32)> for LRUCache<K, V, S> {
+    fn from_iter<I: IntoIterator<Item = (K, V, u32)>>(iter: I) -> Self {
+        let entries: Vec<_> = iter.into_iter().collect();
+        let mut cache = LRUCache::with_hasher(entries.len().max(1), S::default());
+        cache.extend(entries);
+        cache
+    }
+}
 
-    fn remove_tail(&mut self) {
-        if let Some(tail_idx) = self.tail {
-            let key = self.nodes[tail_idx].key.clone();
-            self.map.remove(&key);
-            self.detach(tail_idx);
-            self.free_list.push(tail_idx);
+impl<K: Hash + Eq, V, S: BuildHasher> Extend<(K, V, u32)> for LRUCache<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V, u32)>>(&mut self, iter: I) {
+        for (key, value, weight) in iter {
+            self.put(key, value, weight);
         }
     }
 }
 
+impl<K: Hash + Eq, V, S: BuildHasher> Cache<K, V> for LRUCache<K, V, S> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Default> NewCache<K, V> for LRUCache<K, V, S> {
+    fn new(capacity: usize) -> Self {
+        LRUCache::with_hasher(capacity, S::default())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::ManualClock;
 
     #[test]
     fn test_basic_operations() {
@@ -169,4 +850,458 @@ mod tests {
         assert_eq!(cache.get(&2), None);
         assert_eq!(cache.get(&3), Some(&"three"));
     }
+
+    #[test]
+    fn test_get_mut() {
+        let mut cache = LRUCache::new(2);
+
+        cache.put(1, vec![1], 0);
+        cache.put(2, vec![2], 0);
+
+        cache.get_mut(&1).unwrap().push(10);
+        assert_eq!(cache.get_mut(&3), None);
+
+        // get_mut counts as a use, so key 2 is evicted
+        cache.put(3, vec![3], 0);
+        assert_eq!(cache.get(&1), Some(&vec![1, 10]));
+        assert_eq!(cache.get(&2), None);
+    }
+
+    #[test]
+    fn test_peek_does_not_update_order() {
+        let mut cache = LRUCache::new(2);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+
+        assert_eq!(cache.peek(&1), Some(&"one"));
+        assert!(cache.contains_key(&1));
+        assert_eq!(cache.peek(&3), None);
+        assert!(!cache.contains_key(&3));
+
+        // Key 1 is still the least recently used
+        cache.put(3, "three", 0);
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.peek(&2), Some(&"two"));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut cache = LRUCache::new(2);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+
+        assert_eq!(cache.remove(&1), Some("one"));
+        assert_eq!(cache.remove(&1), None);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&1), None);
+
+        // The freed slot is reused and no eviction is needed
+        cache.put(3, "three", 0);
+        assert_eq!(cache.get(&2), Some(&"two"));
+        assert_eq!(cache.get(&3), Some(&"three"));
+    }
+
+    #[test]
+    fn test_pop_victim_drains_in_lru_order() {
+        let mut cache = LRUCache::new(3);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.put(3, "three", 0);
+        cache.get(&1);
+
+        assert_eq!(cache.pop_victim(), Some((2, "two")));
+        assert_eq!(cache.pop_victim(), Some((3, "three")));
+        assert_eq!(cache.pop_victim(), Some((1, "one")));
+        assert_eq!(cache.pop_victim(), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_iter_in_recency_order() {
+        let mut cache = LRUCache::new(3);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.put(3, "three", 0);
+        cache.get(&1);
+
+        let entries: Vec<_> = cache.iter().collect();
+        assert_eq!(entries, vec![(&1, &"one"), (&3, &"three"), (&2, &"two")]);
+        assert_eq!(cache.iter().len(), 3);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&1, &3, &2]);
+        assert_eq!(
+            cache.values().collect::<Vec<_>>(),
+            vec![&"one", &"three", &"two"]
+        );
+
+        cache.clear();
+        assert_eq!(cache.iter().next(), None);
+    }
+
+    #[test]
+    fn test_from_iter_and_into_iter() {
+        let mut cache: LRUCache<_, _> = vec![(1, "one", 0), (2, "two", 0), (3, "three", 0)]
+            .into_iter()
+            .collect();
+        assert_eq!(cache.len(), 3);
+
+        cache.get(&1);
+        cache.extend(vec![(4, "four", 0)]);
+        assert!(!cache.contains_key(&2));
+
+        let keys: Vec<i32> = (&cache).into_iter().map(|(&key, _)| key).collect();
+        assert_eq!(keys, vec![4, 1, 3]);
+
+        let entries: Vec<_> = cache.into_iter().collect();
+        assert_eq!(entries, vec![(4, "four"), (1, "one"), (3, "three")]);
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut cache = LRUCache::new(2);
+        let mut loads = 0;
+
+        for _ in 0..3 {
+            let value = cache.get_or_insert_with(1, 0, || {
+                loads += 1;
+                "one"
+            });
+            assert_eq!(value, &"one");
+        }
+
+        assert_eq!(loads, 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_try_get_or_insert_with() {
+        let mut cache = LRUCache::new(2);
+
+        let failed: Result<&&str, &str> =
+            cache.try_get_or_insert_with(1, 0, || Err("backend down"));
+        assert_eq!(failed, Err("backend down"));
+        assert!(!cache.contains_key(&1));
+        assert!(cache.is_empty());
+
+        let loaded: Result<&&str, &str> = cache.try_get_or_insert_with(1, 0, || Ok("one"));
+        assert_eq!(loaded, Ok(&"one"));
+
+        // A cached value is returned without calling the loader
+        let cached: Result<&&str, &str> =
+            cache.try_get_or_insert_with(1, 0, || panic!("loader must not run"));
+        assert_eq!(cached, Ok(&"one"));
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut cache = LRUCache::new(4);
+
+        for i in 1..=4 {
+            cache.put(i, i * 10, 0);
+        }
+        cache.retain(|&key, _| key % 2 == 0);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&4, &2]);
+
+        // The remaining list still evicts in order
+        cache.put(5, 50, 0);
+        cache.put(6, 60, 0);
+        cache.put(7, 70, 0);
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.len(), 4);
+    }
+
+    #[test]
+    fn test_with_custom_hasher() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+
+        let mut cache: LRUCache<i32, &str, BuildHasherDefault<DefaultHasher>> =
+            LRUCache::with_hasher(2, BuildHasherDefault::default());
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.get(&1);
+        cache.put(3, "three", 0);
+
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&3), Some(&"three"));
+    }
+
+    #[test]
+    fn test_eviction_listener() {
+        use std::sync::{Arc, Mutex};
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = LRUCache::new(2);
+        cache.set_eviction_listener(move |key, value| sink.lock().unwrap().push((key, value)));
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.remove(&2);
+        cache.put(3, "three", 0);
+        assert!(evicted.lock().unwrap().is_empty());
+
+        cache.put(4, "four", 0);
+        assert_eq!(*evicted.lock().unwrap(), vec![(1, "one")]);
+    }
+
+    #[test]
+    fn test_heavier_update_evicts_others() {
+        let mut cache = LRUCache::new(4);
+        cache.set_max_weight(100);
+
+        cache.put(1, "one", 30);
+        cache.put(2, "two", 30);
+        cache.put(3, "three", 30);
+        cache.put(3, "THREE", 60);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&3, &2]);
+
+        // The updated key is never evicted to make room for itself
+        cache.put(3, "three", 200);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&3]);
+        assert_eq!(cache.stats().current_weight, 200);
+    }
+
+    fn manual_clock_cache(capacity: usize) -> (LRUCache<i32, &'static str>, ManualClock) {
+        let clock = ManualClock::new();
+        let mut cache = LRUCache::new(capacity);
+        cache.set_clock(Arc::new(clock.clone()));
+        (cache, clock)
+    }
+
+    #[test]
+    fn test_expire_after_write() {
+        let (mut cache, clock) = manual_clock_cache(2);
+        cache.set_expire_after_write(Duration::from_secs(30));
+
+        cache.put(1, "one", 0);
+        clock.advance(Duration::from_secs(29));
+        assert_eq!(cache.get(&1), Some(&"one"));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.peek(&1), None);
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.len(), 1);
+
+        // The expired entry is reclaimed on access
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_expire_after_access() {
+        let (mut cache, clock) = manual_clock_cache(2);
+        cache.set_expire_after_access(Duration::from_secs(60));
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        for _ in 0..3 {
+            clock.advance(Duration::from_secs(25));
+            assert_eq!(cache.get(&1), Some(&"one"));
+        }
+
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some(&"one"));
+    }
+
+    #[test]
+    fn test_expire_due_removes_only_expired_entries() {
+        let (mut cache, clock) = manual_clock_cache(4);
+        cache.set_expire_after_access(Duration::from_secs(10));
+        let start = clock.now();
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.put(3, "three", 0);
+        cache.remove(&3);
+
+        assert_eq!(cache.expire_due(start + Duration::from_secs(5)), 0);
+        assert_eq!(cache.expire_due(start + Duration::from_secs(10)), 2);
+        assert!(cache.is_empty());
+        assert_eq!(cache.expire_due(start + Duration::from_secs(60)), 0);
+    }
+
+    #[test]
+    fn test_expire_due_reschedules_touched_entries() {
+        let (mut cache, clock) = manual_clock_cache(4);
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        // Entries cached before expiration was enabled are scheduled too
+        cache.set_expire_after_write(Duration::from_secs(10));
+
+        clock.advance(Duration::from_secs(5));
+        cache.put(2, "TWO", 0);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.expire_due(clock.now()), 1);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&2]);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.expire_due(clock.now()), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut cache = LRUCache::new(2);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.clear();
+
+        assert!(cache.is_empty());
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), None);
+
+        cache.put(3, "three", 0);
+        cache.put(4, "four", 0);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&3), Some(&"three"));
+        assert_eq!(cache.get(&4), Some(&"four"));
+    }
+
+    #[test]
+    fn test_remove_keeps_order_consistent() {
+        let mut cache = LRUCache::new(3);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.put(3, "three", 0);
+
+        // Remove the head, middle and tail in turn
+        assert_eq!(cache.remove(&3), Some("three"));
+        cache.put(4, "four", 0);
+        assert_eq!(cache.remove(&2), Some("two"));
+        cache.put(5, "five", 0);
+        cache.put(6, "six", 0);
+
+        // Key 1 was the least recently used
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&4), Some(&"four"));
+        assert_eq!(cache.get(&5), Some(&"five"));
+        assert_eq!(cache.get(&6), Some(&"six"));
+    }
+
+    #[test]
+    fn test_shrink_to_fit_compacts_nodes() {
+        let mut cache = LRUCache::new(100);
+        for key in 0..100 {
+            cache.put(key, key * 10, 0);
+        }
+        for key in (0..100).filter(|key| key % 10 != 0) {
+            cache.remove(&key);
+        }
+        cache.get(&40);
+        let before: Vec<i32> = cache.keys().copied().collect();
+
+        cache.shrink_to_fit();
+        assert_eq!(cache.nodes.slots(), cache.len());
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), before);
+        assert_eq!(cache.peek_victim(), Some(&0));
+
+        // The relocated links still work for updates and new entries
+        cache.get(&0);
+        cache.put(100, 1000, 0);
+        assert_eq!(cache.peek_victim(), Some(&10));
+        assert_eq!(cache.get(&90), Some(&900));
+        assert_eq!(cache.len(), 11);
+    }
+
+    #[test]
+    fn test_keys_need_not_be_clone() {
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct Term(String);
+        let term = |s: &str| Term(s.to_string());
+
+        let mut cache = LRUCache::new(2);
+        cache.put(term("rust"), 1, 0);
+        cache.put(term("cache"), 2, 0);
+        assert_eq!(cache.get(&term("rust")), Some(&1));
+        cache.put(term("slab"), 3, 0);
+        assert!(!cache.contains_key(&term("cache")));
+
+        *cache.entry(term("slab")).or_insert(0, 0) += 10;
+        assert_eq!(cache.pop_victim(), Some((term("rust"), 1)));
+        assert_eq!(
+            cache.into_iter().collect::<Vec<_>>(),
+            vec![(term("slab"), 13)]
+        );
+    }
+
+    #[test]
+    fn test_shrink_to_fit_keeps_deadlines() {
+        let (mut cache, clock) = manual_clock_cache(8);
+        cache.set_expire_after_write(Duration::from_secs(10));
+        for key in 0..8 {
+            cache.put(key, "value", 0);
+        }
+        for key in 0..6 {
+            cache.remove(&key);
+        }
+        // Keys 6 and 7 move into the freed slots
+        cache.shrink_to_fit();
+        assert_eq!(cache.nodes.slots(), 2);
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cache.expire_due(clock.now()), 2);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_midpoint_insertion_resists_scans() {
+        let mut cache = LRUCache::new(4);
+        cache.set_midpoint_insertion(0.5);
+        for key in ["a", "b", "c", "d"] {
+            cache.put(key.to_string(), 0, 1);
+        }
+        cache.get(&"a".to_string());
+        cache.get(&"b".to_string());
+        for key in 0..10 {
+            cache.put(format!("scan {}", key), 0, 1);
+        }
+        let keys: Vec<&str> = cache.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["b", "a", "scan 9", "scan 8"]);
+
+        // A second use is what earns the front
+        cache.get(&"scan 8".to_string());
+        assert_eq!(cache.keys().next().map(String::as_str), Some("scan 8"));
+        assert_eq!(cache.peek_victim().map(String::as_str), Some("scan 9"));
+    }
+
+    #[test]
+    fn test_midpoint_survives_removals_and_compaction() {
+        let mut cache = LRUCache::new(8);
+        for key in 0..8 {
+            cache.put(key, key, 1);
+        }
+        cache.set_midpoint_insertion(0.25);
+        assert_eq!(cache.probation_len, 2);
+        for key in 0..6 {
+            cache.remove(&key);
+        }
+        cache.shrink_to_fit();
+        for key in 10..20 {
+            cache.put(key, key, 1);
+        }
+        assert_eq!(cache.len(), 8);
+        assert_eq!(cache.probation_len, 2);
+        // While the cache fills, the probationary part is too small to hold every new
+        // entry; once full, new entries only displace each other
+        let keys: Vec<i32> = cache.keys().copied().collect();
+        assert_eq!(keys, vec![7, 6, 10, 12, 13, 14, 19, 18]);
+        let probation: Vec<i32> = cache
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.probation)
+            .map(|(_, node)| node.key)
+            .collect();
+        assert_eq!(probation.len(), 2);
+        assert!(probation.contains(&19) && probation.contains(&18));
+    }
 }
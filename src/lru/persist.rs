@@ -0,0 +1,222 @@
+use std::hash::{BuildHasher, Hash};
+use std::io::{Read, Write};
+
+use super::{LRUCache, Node};
+use crate::expiry::SavedTimestamps;
+use crate::snapshot::{self, impl_codec, Decode, Encode, SnapshotError, SnapshotReader};
+use crate::stats::CacheStats;
+use crate::util::{self, Link};
+use crate::Policy;
+
+// What is saved of a cache, by serde and by snapshots alike. Entries run from the least
+// to the most recently used, so restoring them in order rebuilds the recency list.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Saved<K, V> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    stats: CacheStats,
+    entries: Vec<SavedEntry<K, V>>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct SavedEntry<K, V> {
+    key: K,
+    value: V,
+    weight: u32,
+    timestamps: SavedTimestamps,
+}
+
+impl_codec!(Saved<K, V> {
+    capacity,
+    max_weight,
+    stats,
+    entries,
+});
+impl_codec!(SavedEntry<K, V> {
+    key,
+    value,
+    weight,
+    timestamps,
+});
+
+impl<K, V, S> LRUCache<K, V, S> {
+    fn save(&self) -> Saved<&K, &V> {
+        let now = self.clock.now();
+        let mut entries = Vec::with_capacity(self.map.len());
+        let mut next = self.tail.get();
+        while let Some(idx) = next {
+            let node = &self.nodes[idx];
+            entries.push(SavedEntry {
+                key: &node.key,
+                value: &node.value,
+                weight: node.weight,
+                timestamps: SavedTimestamps::save(&node.timestamps, now),
+            });
+            next = node.prev.get();
+        }
+        Saved {
+            capacity: self.capacity,
+            max_weight: self.max_weight,
+            stats: self.stats,
+            entries,
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Default> LRUCache<K, V, S> {
+    fn restore(saved: Saved<K, V>) -> Result<Self, &'static str> {
+        snapshot::check_len(saved.capacity, saved.max_weight, saved.entries.len())?;
+        let mut cache = LRUCache::with_hasher(saved.capacity, S::default());
+        cache.max_weight = saved.max_weight;
+        cache.stats = saved.stats;
+        cache.stats.current_weight = 0;
+        let now = cache.clock.now();
+        for entry in saved.entries {
+            if cache.index_of(&entry.key).is_some() {
+                return Err("duplicate key");
+            }
+            cache.stats.current_weight += u64::from(entry.weight);
+            let slot = cache.nodes.insert(Node {
+                key: entry.key,
+                value: entry.value,
+                weight: entry.weight,
+                timestamps: entry.timestamps.restore(now),
+                probation: false,
+                prev: Link::NONE,
+                next: Link::NONE,
+            });
+            cache.map.insert(slot, &cache.nodes);
+            cache.add_to_front(slot.index());
+        }
+        Ok(cache)
+    }
+}
+
+impl<K: Encode, V: Encode, S> LRUCache<K, V, S> {
+    // Writes the entries and their recency order as a checksummed binary snapshot.
+    pub fn save_to<W: Write>(&self, writer: W) -> Result<(), SnapshotError> {
+        snapshot::write(writer, Policy::LRU, &self.save())
+    }
+}
+
+impl<K: Hash + Eq + Decode, V: Decode, S: BuildHasher + Default> LRUCache<K, V, S> {
+    pub fn load_from<R: Read>(reader: R) -> Result<Self, SnapshotError> {
+        LRUCache::load_snapshot(SnapshotReader::open(reader)?)
+    }
+
+    pub(crate) fn load_snapshot<R: Read>(
+        snapshot: SnapshotReader<R>,
+    ) -> Result<Self, SnapshotError> {
+        let saved = snapshot.body(Policy::LRU)?;
+        LRUCache::restore(saved).map_err(SnapshotError::Invalid)
+    }
+}
+
+impl<K: Clone + Hash + Eq, V: Clone, S: BuildHasher> LRUCache<K, V, S> {
+    // The `n` entries that would be evicted last, most recently used first, with their
+    // weights. `import` seeds another cache with them.
+    pub fn export_top(&self, n: usize) -> Vec<(K, V, u32)> {
+        util::top_entries(self.iter(), n, |key| self.entry_info(key))
+    }
+
+    // Stores exported entries, hottest first, ahead of everything already cached.
+    pub fn import<I: IntoIterator<Item = (K, V, u32)>>(&mut self, entries: I) {
+        let entries: Vec<_> = entries.into_iter().collect();
+        for (key, value, weight) in entries.into_iter().rev() {
+            self.put(key, value, weight);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K: serde::Serialize, V: serde::Serialize, S> serde::Serialize for LRUCache<K, V, S> {
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        serde::Serialize::serialize(&self.save(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, S> serde::Deserialize<'de> for LRUCache<K, V, S>
+where
+    K: Hash + Eq + serde::Deserialize<'de>,
+    V: serde::Deserialize<'de>,
+    S: BuildHasher + Default,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = serde::Deserialize::deserialize(deserializer)?;
+        LRUCache::restore(saved).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::RandomState;
+
+    #[test]
+    fn test_snapshot_keeps_recency_order() {
+        let mut cache = LRUCache::new(3);
+        cache.put(1, "one".to_string(), 2);
+        cache.put(2, "two".to_string(), 1);
+        cache.put(3, "three".to_string(), 1);
+        cache.get(&1);
+        cache.get(&9);
+
+        let mut bytes = Vec::new();
+        cache.save_to(&mut bytes).unwrap();
+        let mut restored: LRUCache<i32, String, RandomState> =
+            LRUCache::load_from(bytes.as_slice()).unwrap();
+        assert_eq!(restored.stats(), cache.stats());
+        let order: Vec<i32> = restored.iter().map(|(&key, _)| key).collect();
+        assert_eq!(order, vec![1, 3, 2]);
+
+        restored.put(4, "four".to_string(), 1);
+        assert!(!restored.contains_key(&2));
+        assert_eq!(restored.get(&1), Some(&"one".to_string()));
+    }
+
+    #[test]
+    fn test_snapshot_rejects_another_policy() {
+        let mut cache = crate::fifo::FIFOCache::new(2);
+        cache.put(1u32, 1u32, 1);
+        let mut bytes = Vec::new();
+        cache.save_to(&mut bytes).unwrap();
+
+        let result = LRUCache::<u32, u32, RandomState>::load_from(bytes.as_slice());
+        assert!(matches!(result, Err(SnapshotError::WrongPolicy { .. })));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_keeps_recency_order() {
+        let mut cache = LRUCache::new(3);
+        cache.put(1, "one".to_string(), 2);
+        cache.put(2, "two".to_string(), 1);
+        cache.put(3, "three".to_string(), 1);
+        cache.get(&1);
+
+        let json = serde_json::to_string(&cache).unwrap();
+        let restored: LRUCache<i32, String, RandomState> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.stats(), cache.stats());
+        let order: Vec<i32> = restored.iter().map(|(&key, _)| key).collect();
+        assert_eq!(order, vec![1, 3, 2]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_rejects_duplicate_keys() {
+        let mut cache = LRUCache::new(2);
+        cache.put("a", 1, 1);
+        let json = serde_json::to_string(&cache).unwrap();
+        let entry = json
+            .split("\"entries\":[")
+            .nth(1)
+            .unwrap()
+            .trim_end_matches("]}");
+        let doubled = json.replace(entry, &format!("{},{}", entry, entry));
+
+        let result = serde_json::from_str::<LRUCache<String, i32, RandomState>>(&doubled);
+        let error = result.err().expect("duplicate keys are rejected");
+        assert!(error.to_string().contains("duplicate key"));
+    }
+}
@@ -0,0 +1,222 @@
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::clock::{Clock, SystemClock};
+use crate::sync_cache::lock;
+use crate::{CacheType, SyncCache};
+
+// Entries evicted per hold of the cache lock, so foreground calls interleave with a
+// long trim instead of waiting for all of it.
+const EVICTION_BATCH: usize = 64;
+
+// What one maintenance pass removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MaintenanceReport {
+    pub expired: usize,
+    pub evicted: usize,
+}
+
+// Trims a shared cache off the request path. Once the cache holds more than the high
+// watermark, a pass evicts down to the low watermark; every pass also drops expired
+// entries. Build the cache with a capacity above the high watermark: the capacity stays a
+// hard limit that `put` enforces itself, and below it `put` never has to evict.
+pub struct Maintenance {
+    high_watermark: usize,
+    low_watermark: usize,
+    interval: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl Maintenance {
+    pub fn new(high_watermark: usize, low_watermark: usize) -> Self {
+        assert!(
+            low_watermark <= high_watermark,
+            "Low watermark must not exceed the high watermark"
+        );
+        Maintenance {
+            high_watermark,
+            low_watermark,
+            interval: Duration::from_secs(1),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    // How often the worker runs a pass. Defaults to once a second.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    // The clock expiration is judged by; use the one the cache was built with.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn run<K, V, S>(&self, cache: &SyncCache<K, V, CacheType<K, V, S>>) -> MaintenanceReport
+    where
+        K: Clone + Hash + Eq,
+        S: BuildHasher + Clone,
+    {
+        let mut report = MaintenanceReport {
+            expired: cache.lock().expire_due(self.clock.now()),
+            evicted: 0,
+        };
+        if cache.len() <= self.high_watermark {
+            return report;
+        }
+        loop {
+            let mut cache = cache.lock();
+            let target = cache
+                .len()
+                .saturating_sub(EVICTION_BATCH)
+                .max(self.low_watermark);
+            report.evicted += cache.evict_to(target);
+            if target == self.low_watermark {
+                return report;
+            }
+        }
+    }
+
+    // Runs a pass every interval on a new thread until the worker is stopped or dropped.
+    pub fn spawn<K, V, S>(
+        self,
+        cache: Arc<SyncCache<K, V, CacheType<K, V, S>>>,
+    ) -> MaintenanceWorker
+    where
+        K: Clone + Hash + Eq + Send + 'static,
+        V: Send + 'static,
+        S: BuildHasher + Clone + Send + 'static,
+    {
+        let signal = Arc::new(Signal {
+            stopped: Mutex::new(false),
+            wake: Condvar::new(),
+        });
+        let worker_signal = signal.clone();
+        let handle = thread::spawn(move || {
+            let mut stopped = lock(&worker_signal.stopped);
+            while !*stopped {
+                stopped = worker_signal
+                    .wake
+                    .wait_timeout(stopped, self.interval)
+                    .expect("maintenance lock poisoned")
+                    .0;
+                if !*stopped {
+                    drop(stopped);
+                    self.run(&cache);
+                    stopped = lock(&worker_signal.stopped);
+                }
+            }
+        });
+        MaintenanceWorker {
+            signal,
+            handle: Some(handle),
+        }
+    }
+}
+
+struct Signal {
+    stopped: Mutex<bool>,
+    wake: Condvar,
+}
+
+// The thread started by `Maintenance::spawn`. Dropping it stops the thread too.
+pub struct MaintenanceWorker {
+    signal: Arc<Signal>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MaintenanceWorker {
+    // Waits for a pass in progress to finish.
+    pub fn stop(mut self) {
+        self.shut_down();
+    }
+
+    fn shut_down(&mut self) {
+        *lock(&self.signal.stopped) = true;
+        self.signal.wake.notify_all();
+        if let Some(handle) = self.handle.take() {
+            // A panic in the worker was already reported on its thread
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for MaintenanceWorker {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::{CacheBuilder, Policy};
+    use std::time::Instant;
+
+    #[test]
+    fn test_trims_to_low_watermark_above_high() {
+        let cache = SyncCache::new(CacheType::new(Policy::LRU, 1000));
+        let maintenance = Maintenance::new(100, 50);
+        for key in 0..100 {
+            cache.put(key, key, 1);
+        }
+        assert_eq!(maintenance.run(&cache), MaintenanceReport::default());
+
+        for key in 100..300 {
+            cache.put(key, key, 1);
+        }
+        let report = maintenance.run(&cache);
+        assert_eq!(report.evicted, 250);
+        assert_eq!(cache.len(), 50);
+        // The least recently used entries went first
+        assert!(cache.contains_key(&299));
+        assert!(!cache.contains_key(&249));
+        assert_eq!(cache.lock().stats().evictions, 250);
+    }
+
+    #[test]
+    fn test_sweeps_expired_entries() {
+        let clock = ManualClock::new();
+        let cache = SyncCache::new(
+            CacheBuilder::new(10)
+                .policy(Policy::FIFO)
+                .expire_after_write(Duration::from_secs(5))
+                .clock(Arc::new(clock.clone()))
+                .build(),
+        );
+        let maintenance = Maintenance::new(10, 5).clock(Arc::new(clock.clone()));
+        cache.put("a", 1, 1);
+        cache.put("b", 2, 1);
+        clock.advance(Duration::from_secs(6));
+        cache.put("c", 3, 1);
+
+        assert_eq!(maintenance.run(&cache).expired, 2);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_worker_runs_until_stopped() {
+        let cache = Arc::new(SyncCache::new(CacheType::new(Policy::LFU, 1000)));
+        for key in 0..500 {
+            cache.put(key, key, 1);
+        }
+        let worker = Maintenance::new(200, 100)
+            .interval(Duration::from_millis(1))
+            .spawn(cache.clone());
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while cache.len() > 100 {
+            assert!(
+                Instant::now() < deadline,
+                "the worker never trimmed the cache"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+        worker.stop();
+        assert_eq!(cache.len(), 100);
+        assert_eq!(Arc::strong_count(&cache), 1);
+    }
+}
@@ -0,0 +1,623 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::lru::LRUCache;
+use crate::stats::CacheStats;
+use crate::trace;
+use crate::util::{KeyIndex, Keyed, Link, Slab, SlabKey};
+use crate::{Cache, EvictionListener, NewCache};
+
+const POLICY: &str = "mq";
+
+// The number of queues used by `new`.
+const DEFAULT_QUEUES: usize = 8;
+
+// Evicted keys remembered per cache entry.
+const HISTORY_FACTOR: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct List {
+    head: Link,
+    tail: Link,
+}
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    weight: u32,
+    freq: u64,
+    // The logical time after which the entry drops to the queue below
+    expires: u64,
+    queue: usize,
+    prev: Link,
+    next: Link,
+}
+
+impl<K, V> Keyed for Node<K, V> {
+    type Key = K;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+// Multi-Queue (Zhou et al.), designed for second-level caches whose hits are filtered by a
+// cache in front. Queue k is an LRU list of the entries used between 2^k and 2^(k+1) times;
+// victims come from the lowest non-empty queue. An entry that goes unused for `life_time`
+// accesses moves down a queue, so entries popular long ago cannot stay forever. Evicted
+// keys keep their count in a history buffer and pick it up again if they come back.
+pub struct MQCache<K, V, S = RandomState> {
+    capacity: usize,
+    life_time: u64,
+    time: u64,
+    map: KeyIndex<S>,
+    nodes: Slab<Node<K, V>>,
+    queues: Vec<List>,
+    history: LRUCache<K, u64, S>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    stats: CacheStats,
+}
+
+pub struct Iter<'a, K, V> {
+    nodes: &'a Slab<Node<K, V>>,
+    queues: &'a [List],
+    next: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next.is_none() {
+            let (last, rest) = self.queues.split_last()?;
+            self.next = last.head.get();
+            self.queues = rest;
+        }
+        let node = self.nodes.get_at(self.next?)?;
+        self.next = node.next.get();
+        self.remaining -= 1;
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<K: Clone + Hash + Eq, V> MQCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        MQCache::with_queues(capacity, DEFAULT_QUEUES)
+    }
+
+    pub fn with_queues(capacity: usize, queues: usize) -> Self {
+        MQCache::with_hasher(capacity, queues, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> MQCache<K, V, S> {
+    pub fn with_hasher(capacity: usize, queues: usize, hash_builder: S) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        assert!(queues > 0, "Number of queues must be greater than 0");
+        MQCache {
+            capacity,
+            life_time: capacity as u64,
+            time: 0,
+            map: KeyIndex::with_capacity_and_hasher(capacity, hash_builder.clone()),
+            nodes: Slab::with_capacity(capacity),
+            queues: vec![List::default(); queues],
+            history: LRUCache::with_hasher(capacity * HISTORY_FACTOR, hash_builder),
+            eviction_listener: None,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let _span = trace::get_span(POLICY, key);
+        let idx = self.touch(key)?;
+        Some(&self.node(idx).value)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let idx = self.touch(key)?;
+        Some(&mut self.node_mut(idx).value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.index_of(key).map(|idx| &self.node(idx).value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.index_of(key).is_some()
+    }
+
+    // Writing a cached key counts as a use. A key found in the history resumes its count.
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        let _span = trace::put_span(POLICY, &key, weight);
+        self.time += 1;
+        if let Some(idx) = self.index_of(&key) {
+            let node = self.node_mut(idx);
+            let old_weight = std::mem::replace(&mut node.weight, weight);
+            node.value = value;
+            self.stats.current_weight -= u64::from(old_weight);
+            self.stats.current_weight += u64::from(weight);
+            self.promote(idx);
+            self.adjust();
+            return;
+        }
+
+        self.stats.insertions += 1;
+        trace::insert(POLICY, &key, weight);
+        while self.map.len() >= self.capacity {
+            self.evict();
+        }
+
+        let freq = self.history.remove(&key).map_or(1, |freq| freq + 1);
+        let queue = self.queue_for(freq);
+        let slot = self.nodes.insert(Node {
+            key,
+            value,
+            weight,
+            freq,
+            expires: self.time + self.life_time,
+            queue,
+            prev: Link::NONE,
+            next: Link::NONE,
+        });
+        let idx = slot.index();
+        self.push_front(idx, queue);
+        self.map.insert(slot, &self.nodes);
+        self.stats.current_weight += u64::from(weight);
+        self.adjust();
+    }
+
+    // Explicit removals are not remembered in the history.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let idx = self.index_of(key)?;
+        Some(self.unlink(idx).value)
+    }
+
+    // The entry `pop_victim` would remove, left in place.
+    pub fn peek_victim(&self) -> Option<&K> {
+        let idx = self.victim()?;
+        Some(&self.node(idx).key)
+    }
+
+    // The least recently used entry of the lowest non-empty queue. Its count goes to the
+    // history.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        let idx = self.victim()?;
+        let node = self.unlink(idx);
+        self.history.put(node.key.clone(), node.freq, 1);
+        Some((node.key, node.value))
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        let discarded: Vec<usize> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| !f(&node.key, &node.value))
+            .map(|(idx, _)| idx)
+            .collect();
+        for idx in discarded {
+            self.unlink(idx);
+        }
+    }
+
+    // Entries unused for this many accesses move down a queue. Defaults to the capacity.
+    pub fn set_life_time(&mut self, life_time: u64) {
+        self.life_time = life_time;
+    }
+
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.eviction_listener = Some(Box::new(listener));
+    }
+
+    // Forgets the history as well.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.nodes.clear();
+        for list in self.queues.iter_mut() {
+            *list = List::default();
+        }
+        self.history.clear();
+        self.stats.current_weight = 0;
+    }
+
+    // Moves entries out of the slots freed by removals, so the node storage and the maps
+    // can release the memory they no longer need.
+    pub fn shrink_to_fit(&mut self) {
+        for (from, to) in self.nodes.compaction_moves() {
+            self.relocate(from, to);
+        }
+        self.nodes.shrink_to_fit();
+        self.map.shrink_to_fit(&self.nodes);
+        self.history.shrink_to_fit();
+    }
+
+    // From the highest queue down, each from most recently used.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            nodes: &self.nodes,
+            queues: &self.queues,
+            next: None,
+            remaining: self.len(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn get_freq(&self, key: &K) -> Option<u64> {
+        self.index_of(key).map(|idx| self.node(idx).freq)
+    }
+
+    pub fn queue_of(&self, key: &K) -> Option<usize> {
+        self.index_of(key).map(|idx| self.node(idx).queue)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    // Records the lookup and, on a hit, moves the entry up as its count requires.
+    fn touch(&mut self, key: &K) -> Option<usize> {
+        self.time += 1;
+        let found = self.index_of(key);
+        match found {
+            Some(idx) => {
+                self.stats.hits += 1;
+                self.promote(idx);
+            }
+            None => {
+                self.stats.misses += 1;
+                trace::miss(POLICY, key);
+            }
+        }
+        self.adjust();
+        found
+    }
+
+    fn promote(&mut self, idx: usize) {
+        self.detach(idx);
+        let node = self.node_mut(idx);
+        node.freq += 1;
+        let freq = node.freq;
+        self.node_mut(idx).expires = self.time + self.life_time;
+        let queue = self.queue_for(freq);
+        self.push_front(idx, queue);
+    }
+
+    // Moves the least recently used entry of each queue down one if it has expired.
+    fn adjust(&mut self) {
+        for queue in 1..self.queues.len() {
+            let expired = self.queues[queue]
+                .tail
+                .get()
+                .filter(|&idx| self.node(idx).expires < self.time);
+            if let Some(idx) = expired {
+                self.detach(idx);
+                self.node_mut(idx).expires = self.time + self.life_time;
+                self.push_front(idx, queue - 1);
+            }
+        }
+    }
+
+    fn queue_for(&self, freq: u64) -> usize {
+        let log2 = (u64::BITS - 1 - freq.max(1).leading_zeros()) as usize;
+        log2.min(self.queues.len() - 1)
+    }
+
+    fn victim(&self) -> Option<usize> {
+        self.queues.iter().find_map(|list| list.tail.get())
+    }
+
+    // Capacity-driven eviction; the listener sees every entry dropped this way.
+    fn evict(&mut self) {
+        if let Some((key, value)) = self.pop_victim() {
+            self.stats.evictions += 1;
+            trace::eviction(POLICY, &key);
+            if let Some(listener) = self.eviction_listener.as_mut() {
+                listener(key, value);
+            }
+        }
+    }
+
+    fn node(&self, idx: usize) -> &Node<K, V> {
+        &self.nodes[idx]
+    }
+
+    fn node_mut(&mut self, idx: usize) -> &mut Node<K, V> {
+        &mut self.nodes[idx]
+    }
+
+    fn index_of(&self, key: &K) -> Option<usize> {
+        self.map.get(key, &self.nodes).map(SlabKey::index)
+    }
+
+    // Detaches and unmaps the node, frees its slot and takes its weight off the total.
+    fn unlink(&mut self, idx: usize) -> Node<K, V> {
+        self.detach(idx);
+        let node = self.nodes.remove(idx);
+        self.map.remove(&node.key, idx);
+        self.stats.current_weight -= u64::from(node.weight);
+        node
+    }
+
+    // Moves a node into a free slot, repointing its neighbours and its map entry.
+    fn relocate(&mut self, from: usize, to: usize) {
+        let slot = self.nodes.relocate(from, to);
+        let node = &self.nodes[to];
+        let (prev, next, queue) = (node.prev, node.next, node.queue);
+        match prev.get() {
+            Some(p) => self.node_mut(p).next = Link::some(to),
+            None => self.queues[queue].head = Link::some(to),
+        }
+        match next.get() {
+            Some(n) => self.node_mut(n).prev = Link::some(to),
+            None => self.queues[queue].tail = Link::some(to),
+        }
+        self.map.repoint(&self.nodes[to].key, from, slot);
+    }
+
+    fn detach(&mut self, idx: usize) {
+        let node = self.node(idx);
+        let (prev, next, queue) = (node.prev, node.next, node.queue);
+
+        match prev.get() {
+            Some(p) => self.node_mut(p).next = next,
+            None => self.queues[queue].head = next,
+        }
+
+        match next.get() {
+            Some(n) => self.node_mut(n).prev = prev,
+            None => self.queues[queue].tail = prev,
+        }
+    }
+
+    fn push_front(&mut self, idx: usize, queue: usize) {
+        let head = self.queues[queue].head;
+        let node = self.node_mut(idx);
+        node.prev = Link::NONE;
+        node.next = head;
+        node.queue = queue;
+
+        if let Some(old_head) = head.get() {
+            self.node_mut(old_head).prev = Link::some(idx);
+        }
+
+        let list = &mut self.queues[queue];
+        list.head = Link::some(idx);
+        if list.tail.is_none() {
+            list.tail = Link::some(idx);
+        }
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher + Clone> IntoIterator for &'a MQCache<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Extend<(K, V, u32)> for MQCache<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V, u32)>>(&mut self, iter: I) {
+        for (key, value, weight) in iter {
+            self.put(key, value, weight);
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Cache<K, V> for MQCache<K, V, S> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone + Default> NewCache<K, V>
+    for MQCache<K, V, S>
+{
+    fn new(capacity: usize) -> Self {
+        MQCache::with_hasher(capacity, DEFAULT_QUEUES, S::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "Capacity must be greater than 0")]
+    fn test_new_cache_zero_capacity() {
+        let _cache: MQCache<String, i32> = MQCache::new(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Number of queues must be greater than 0")]
+    fn test_no_queues() {
+        let _cache: MQCache<String, i32> = MQCache::with_queues(2, 0);
+    }
+
+    #[test]
+    fn test_queues_follow_log2_of_count() {
+        let mut cache = MQCache::with_queues(4, 3);
+        cache.put(1, "one", 1);
+        assert_eq!(cache.queue_of(&1), Some(0));
+
+        cache.get(&1);
+        assert_eq!(cache.queue_of(&1), Some(1));
+        cache.get(&1);
+        cache.get(&1);
+        assert_eq!(cache.get_freq(&1), Some(4));
+        assert_eq!(cache.queue_of(&1), Some(2));
+
+        // The top queue takes every higher count
+        for _ in 0..10 {
+            cache.get(&1);
+        }
+        assert_eq!(cache.queue_of(&1), Some(2));
+        assert_eq!(cache.stats().hits, 13);
+    }
+
+    #[test]
+    fn test_victims_come_from_the_lowest_queue() {
+        let mut cache = MQCache::new(3);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.put(3, "three", 1);
+        cache.get(&1);
+
+        assert_eq!(cache.peek_victim(), Some(&2));
+        cache.put(4, "four", 1);
+        assert!(!cache.contains_key(&2));
+        cache.put(5, "five", 1);
+        assert!(!cache.contains_key(&3));
+        assert!(cache.contains_key(&1));
+        assert_eq!(cache.stats().evictions, 2);
+    }
+
+    #[test]
+    fn test_unused_entries_expire_downwards() {
+        let mut cache = MQCache::new(4);
+        cache.set_life_time(2);
+        cache.put(1, "one", 1);
+        cache.get(&1);
+        assert_eq!(cache.queue_of(&1), Some(1));
+
+        for _ in 0..3 {
+            cache.get(&2);
+        }
+        assert_eq!(cache.queue_of(&1), Some(0));
+        assert_eq!(cache.get_freq(&1), Some(2));
+    }
+
+    #[test]
+    fn test_history_restores_counts() {
+        let mut cache = MQCache::new(1);
+        cache.put(1, "one", 1);
+        cache.get(&1);
+        cache.get(&1);
+        cache.put(2, "two", 1);
+        assert!(!cache.contains_key(&1));
+
+        cache.put(1, "one", 1);
+        assert_eq!(cache.get_freq(&1), Some(4));
+        assert_eq!(cache.queue_of(&1), Some(2));
+
+        // Explicit removals leave no history
+        cache.remove(&1);
+        cache.put(1, "one", 1);
+        assert_eq!(cache.get_freq(&1), Some(1));
+    }
+
+    #[test]
+    fn test_update_counts_as_use_and_reweighs() {
+        let mut cache = MQCache::new(4);
+        cache.put(1, "one", 5);
+        cache.put(1, "ONE", 7);
+
+        assert_eq!(cache.peek(&1), Some(&"ONE"));
+        assert_eq!(cache.get_freq(&1), Some(2));
+        assert_eq!(cache.stats().current_weight, 7);
+        assert_eq!(cache.stats().insertions, 1);
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut cache = MQCache::new(2);
+        cache.put(1, vec![1], 1);
+        cache.get_mut(&1).unwrap().push(10);
+
+        assert_eq!(cache.peek(&1), Some(&vec![1, 10]));
+        assert_eq!(cache.get_mut(&2), None);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn test_iter_remove_and_retain() {
+        let mut cache = MQCache::new(4);
+        cache.extend(vec![(1, "one", 1), (2, "two", 1), (3, "three", 1)]);
+        cache.get(&2);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&2, &3, &1]);
+        assert_eq!(cache.iter().len(), 3);
+
+        assert_eq!(cache.remove(&2), Some("two"));
+        cache.retain(|&key, _| key != 3);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(cache.stats().current_weight, 1);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats().current_weight, 0);
+    }
+
+    #[test]
+    fn test_eviction_listener() {
+        use std::sync::{Arc, Mutex};
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = MQCache::new(2);
+        cache.set_eviction_listener(move |key, _| sink.lock().unwrap().push(key));
+
+        cache.put(1, "one", 1);
+        cache.get(&1);
+        cache.put(2, "two", 1);
+        cache.put(3, "three", 1);
+        assert_eq!(*evicted.lock().unwrap(), vec![2]);
+    }
+}
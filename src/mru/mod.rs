@@ -0,0 +1,270 @@
+use std::collections::hash_map::RandomState;
+
+use crate::queue::{self, End, Order, QueueCache};
+use crate::Policy;
+
+pub use crate::queue::{IntoIter, Iter};
+
+// Most recently used: the entry just read or written is the next victim. On cyclic scans over
+// slightly more keys than fit, LRU evicts every key just before it is needed again, while
+// MRU keeps most of the cycle cached.
+pub struct Mru;
+
+impl Order for Mru {
+    const POLICY: Policy = Policy::MRU;
+    const NAME: &'static str = "mru";
+    const VICTIM: End = End::Newest;
+    const RENEWS_ON_USE: bool = true;
+}
+
+pub type MRUCache<K, V, S = RandomState> = QueueCache<K, V, S, Mru>;
+pub type Entry<'a, K, V, S = RandomState> = queue::Entry<'a, K, V, S, Mru>;
+pub type OccupiedEntry<'a, K, V, S = RandomState> = queue::OccupiedEntry<'a, K, V, S, Mru>;
+pub type VacantEntry<'a, K, V, S = RandomState> = queue::VacantEntry<'a, K, V, S, Mru>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::lru::LRUCache;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    #[should_panic(expected = "Capacity must be greater than 0")]
+    fn test_new_cache_zero_capacity() {
+        let _cache: MRUCache<String, i32> = MRUCache::new(0);
+    }
+
+    #[test]
+    fn test_evicts_most_recently_used() {
+        let mut cache = MRUCache::new(2);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.get(&1);
+        cache.put(3, "three", 0);
+
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.peek(&2), Some(&"two"));
+        assert_eq!(cache.peek(&3), Some(&"three"));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_cyclic_scan_beats_lru() {
+        let mut mru = MRUCache::new(4);
+        let mut lru = LRUCache::new(4);
+        for _ in 0..10 {
+            for key in 0..5 {
+                if mru.get(&key).is_none() {
+                    mru.put(key, (), 0);
+                }
+                if lru.get(&key).is_none() {
+                    lru.put(key, (), 0);
+                }
+            }
+        }
+
+        assert_eq!(lru.stats().hits, 0);
+        assert!(mru.stats().hits >= 30, "{:?}", mru.stats());
+    }
+
+    #[test]
+    fn test_get_mut_counts_as_use() {
+        let mut cache = MRUCache::new(2);
+
+        cache.put(1, vec![1], 0);
+        cache.put(2, vec![2], 0);
+        cache.get_mut(&1).unwrap().push(10);
+        assert_eq!(cache.get_mut(&3), None);
+
+        cache.put(3, vec![3], 0);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), Some(&vec![2]));
+    }
+
+    #[test]
+    fn test_peek_does_not_update_order() {
+        let mut cache = MRUCache::new(2);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        assert_eq!(cache.peek(&1), Some(&"one"));
+
+        // Key 2 is still the most recently used
+        cache.put(3, "three", 0);
+        assert!(!cache.contains_key(&2));
+        assert!(cache.contains_key(&1));
+    }
+
+    #[test]
+    fn test_pop_victim_and_iter() {
+        let mut cache = MRUCache::new(3);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.put(3, "three", 0);
+        cache.get(&1);
+
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&1, &3, &2]);
+        assert_eq!(cache.iter().len(), 3);
+        assert_eq!(cache.peek_victim(), Some(&1));
+        assert_eq!(cache.pop_victim(), Some((1, "one")));
+        assert_eq!(cache.pop_victim(), Some((3, "three")));
+        assert_eq!(cache.remove(&2), Some("two"));
+        assert_eq!(cache.pop_victim(), None);
+    }
+
+    #[test]
+    fn test_from_iter_and_retain() {
+        let mut cache: MRUCache<_, _> = (1..=4).map(|key| (key, key * 10, 0)).collect();
+        cache.retain(|&key, _| key % 2 == 0);
+
+        assert_eq!(cache.values().collect::<Vec<_>>(), vec![&40, &20]);
+        let entries: Vec<_> = cache.into_iter().collect();
+        assert_eq!(entries, vec![(4, 40), (2, 20)]);
+    }
+
+    #[test]
+    fn test_eviction_listener() {
+        use std::sync::Mutex;
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = MRUCache::new(2);
+        cache.set_eviction_listener(move |key, value| sink.lock().unwrap().push((key, value)));
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.remove(&2);
+        cache.put(3, "three", 0);
+        assert!(evicted.lock().unwrap().is_empty());
+
+        cache.put(4, "four", 0);
+        assert_eq!(*evicted.lock().unwrap(), vec![(3, "three")]);
+    }
+
+    #[test]
+    fn test_heavier_update_evicts_others() {
+        let mut cache = MRUCache::new(4);
+        cache.set_max_weight(100);
+
+        cache.put(1, "one", 30);
+        cache.put(2, "two", 30);
+        cache.put(3, "three", 30);
+        cache.put(1, "ONE", 60);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&1, &2]);
+
+        // The updated key is never evicted to make room for itself
+        cache.put(1, "one", 200);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(cache.stats().current_weight, 200);
+    }
+
+    #[test]
+    fn test_expire_after_write() {
+        let clock = ManualClock::new();
+        let mut cache = MRUCache::new(2);
+        cache.set_clock(Arc::new(clock.clone()));
+        cache.set_expire_after_write(Duration::from_secs(30));
+
+        cache.put(1, "one", 0);
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_or_insert_on_vacant_and_occupied() {
+        let mut cache = MRUCache::new(2);
+
+        *cache.entry(1).or_insert(0, 0) += 1;
+        *cache.entry(1).or_insert(0, 0) += 1;
+
+        assert_eq!(cache.get(&1), Some(&2));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_occupied_entry_counts_as_use() {
+        let mut cache = MRUCache::new(2);
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        cache.entry(1);
+        cache.put(3, "three", 0);
+
+        assert!(!cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+    }
+
+    #[test]
+    fn test_occupied_insert_and_remove() {
+        let mut cache = MRUCache::new(2);
+        cache.put(1, "one", 0);
+
+        match cache.entry(1) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.insert("ONE"), "one");
+                assert_eq!(entry.remove(), "ONE");
+            }
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_vacant_insert_evicts() {
+        let mut cache = MRUCache::new(1);
+        cache.put(1, "one", 0);
+
+        match cache.entry(2) {
+            Entry::Vacant(entry) => {
+                assert_eq!(entry.key(), &2);
+                entry.insert("two", 0);
+            }
+            Entry::Occupied(_) => panic!("expected a vacant entry"),
+        }
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.peek(&2), Some(&"two"));
+    }
+
+    #[test]
+    fn test_snapshot_keeps_recency_order() {
+        let mut cache = MRUCache::new(3);
+        cache.put(1, "one".to_string(), 2);
+        cache.put(2, "two".to_string(), 1);
+        cache.put(3, "three".to_string(), 1);
+        cache.get(&1);
+        cache.get(&9);
+
+        let mut bytes = Vec::new();
+        cache.save_to(&mut bytes).unwrap();
+        let mut restored: MRUCache<i32, String, RandomState> =
+            MRUCache::load_from(bytes.as_slice()).unwrap();
+        assert_eq!(restored.stats(), cache.stats());
+        let order: Vec<i32> = restored.iter().map(|(&key, _)| key).collect();
+        assert_eq!(order, vec![1, 3, 2]);
+
+        restored.put(4, "four".to_string(), 1);
+        assert!(!restored.contains_key(&1));
+        assert_eq!(restored.get(&2), Some(&"two".to_string()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_keeps_recency_order() {
+        let mut cache = MRUCache::new(3);
+        cache.put(1, "one".to_string(), 2);
+        cache.put(2, "two".to_string(), 1);
+        cache.put(3, "three".to_string(), 1);
+        cache.get(&1);
+
+        let json = serde_json::to_string(&cache).unwrap();
+        let restored: MRUCache<i32, String, RandomState> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.stats(), cache.stats());
+        let order: Vec<i32> = restored.iter().map(|(&key, _)| key).collect();
+        assert_eq!(order, vec![1, 3, 2]);
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::{CacheStats, CacheType, Policy};
+
+// What a lookup in a `NegativeCache` found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup<T> {
+    Found(T),
+    // The key is cached as having no results, so the index need not be asked again.
+    NoResults,
+    Miss,
+}
+
+impl<T> Lookup<T> {
+    pub fn is_miss(&self) -> bool {
+        matches!(self, Lookup::Miss)
+    }
+
+    pub fn found(self) -> Option<T> {
+        match self {
+            Lookup::Found(value) => Some(value),
+            Lookup::NoResults | Lookup::Miss => None,
+        }
+    }
+}
+
+// Caches "no results" outcomes beside real ones, in a cache of their own with its own
+// capacity and usually a shorter time-to-live. Repeated junk queries are answered without
+// hitting the index, yet they can only crowd out each other, never real results.
+//
+// A key is held by one side at a time: storing a result forgets that the key had none,
+// and the other way round. Lookups answered by the negative side reach neither side's
+// misses, so `stats` still describes the real results alone.
+pub struct NegativeCache<K, V, S = RandomState> {
+    results: CacheType<K, V, S>,
+    negatives: CacheType<K, (), S>,
+}
+
+impl<K, V, S> NegativeCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    // Keeps up to `capacity` empty outcomes, least recently used first out, each for `ttl`.
+    pub fn new(results: CacheType<K, V, S>, capacity: usize, ttl: Duration) -> Self
+    where
+        S: Default,
+    {
+        let mut negatives = CacheType::with_hasher(Policy::LRU, capacity, S::default());
+        negatives.set_expire_after_write(ttl);
+        NegativeCache::with_negatives(results, negatives)
+    }
+
+    // Uses a negative side configured by the caller, e.g. with another policy.
+    pub fn with_negatives(results: CacheType<K, V, S>, negatives: CacheType<K, (), S>) -> Self {
+        NegativeCache { results, negatives }
+    }
+
+    // Replaces the clock of both sides.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.results.set_clock(clock.clone());
+        self.negatives.set_clock(clock);
+    }
+
+    pub fn get(&mut self, key: &K) -> Lookup<&V> {
+        if self.negatives.contains_key(key) {
+            self.negatives.get(key);
+            return Lookup::NoResults;
+        }
+        match self.results.get(key) {
+            Some(value) => Lookup::Found(value),
+            None => Lookup::Miss,
+        }
+    }
+
+    pub fn peek(&self, key: &K) -> Lookup<&V> {
+        if self.negatives.contains_key(key) {
+            return Lookup::NoResults;
+        }
+        match self.results.peek(key) {
+            Some(value) => Lookup::Found(value),
+            None => Lookup::Miss,
+        }
+    }
+
+    // Whether either outcome is cached for the key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.negatives.contains_key(key) || self.results.contains_key(key)
+    }
+
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        self.negatives.remove(&key);
+        self.results.put(key, value, weight);
+    }
+
+    // Records that the key has no results.
+    pub fn put_empty(&mut self, key: K) {
+        self.results.remove(&key);
+        self.negatives.put(key, (), 1);
+    }
+
+    // Forgets either outcome, returning the result if one was cached.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.negatives.remove(key);
+        self.results.remove(key)
+    }
+
+    pub fn clear(&mut self) {
+        self.results.clear();
+        self.negatives.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.results.stats()
+    }
+
+    pub fn negative_stats(&self) -> CacheStats {
+        self.negatives.stats()
+    }
+
+    pub fn results(&self) -> &CacheType<K, V, S> {
+        &self.results
+    }
+
+    pub fn results_mut(&mut self) -> &mut CacheType<K, V, S> {
+        &mut self.results
+    }
+
+    pub fn negatives(&self) -> &CacheType<K, (), S> {
+        &self.negatives
+    }
+
+    pub fn into_inner(self) -> (CacheType<K, V, S>, CacheType<K, (), S>) {
+        (self.results, self.negatives)
+    }
+
+    // Cached results and empty outcomes together.
+    pub fn len(&self) -> usize {
+        self.results.len() + self.negatives.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty() && self.negatives.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn caching(clock: &ManualClock) -> NegativeCache<&'static str, usize> {
+        let mut cache =
+            NegativeCache::new(CacheType::new(Policy::LRU, 2), 2, Duration::from_secs(5));
+        cache.set_clock(Arc::new(clock.clone()));
+        cache
+    }
+
+    #[test]
+    fn test_empty_outcomes_are_kept_apart() {
+        let clock = ManualClock::new();
+        let mut cache = caching(&clock);
+        cache.put("rust", 120, 1);
+        cache.put("cache", 80, 1);
+        for junk in &["asdf", "qwer", "zxcv"] {
+            cache.put_empty(*junk);
+        }
+        // Junk only crowds out junk
+        assert_eq!(cache.get(&"rust"), Lookup::Found(&120));
+        assert_eq!(cache.get(&"cache"), Lookup::Found(&80));
+        assert_eq!(cache.get(&"zxcv"), Lookup::NoResults);
+        assert_eq!(cache.get(&"asdf"), Lookup::Miss);
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.stats().misses, 1);
+        assert_eq!(cache.negative_stats().hits, 1);
+    }
+
+    #[test]
+    fn test_empty_outcomes_expire_sooner() {
+        let clock = ManualClock::new();
+        let mut cache = caching(&clock);
+        cache.put("rust", 120, 1);
+        cache.put_empty("asdf");
+        clock.advance(Duration::from_secs(5));
+        assert!(cache.get(&"asdf").is_miss());
+        assert_eq!(cache.get(&"rust").found(), Some(&120));
+    }
+
+    #[test]
+    fn test_one_outcome_per_key() {
+        let clock = ManualClock::new();
+        let mut cache = caching(&clock);
+        cache.put_empty("new");
+        cache.put("new", 1, 1);
+        assert_eq!(cache.peek(&"new"), Lookup::Found(&1));
+        assert!(!cache.negatives().contains_key(&"new"));
+
+        cache.put_empty("new");
+        assert_eq!(cache.peek(&"new"), Lookup::NoResults);
+        assert!(!cache.results().contains_key(&"new"));
+        assert_eq!(cache.remove(&"new"), None);
+        assert!(!cache.contains_key(&"new"));
+    }
+}
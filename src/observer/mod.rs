@@ -0,0 +1,105 @@
+// Lifecycle hooks for layering metrics, logging or tracing onto a cache. Every method
+// defaults to doing nothing, so an observer only implements the events it cares about.
+//
+// Hits and misses are counted for `get`, `get_mut` and `entry`; `peek` and `contains_key`
+// are not observed. An expired entry is reported as a miss.
+pub trait CacheObserver<K> {
+    fn on_hit(&mut self, _key: &K) {}
+
+    fn on_miss(&mut self, _key: &K) {}
+
+    // A key that was not cached before has been stored.
+    fn on_insert(&mut self, _key: &K) {}
+
+    // The value of a cached key has been replaced.
+    fn on_update(&mut self, _key: &K) {}
+}
+
+pub type BoxedObserver<K> = Box<dyn CacheObserver<K> + Send>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::{CacheType, Policy};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    type Events = Arc<Mutex<Vec<(&'static str, i32)>>>;
+
+    struct EventLog(Events);
+
+    impl CacheObserver<i32> for EventLog {
+        fn on_hit(&mut self, key: &i32) {
+            self.0.lock().unwrap().push(("hit", *key));
+        }
+
+        fn on_miss(&mut self, key: &i32) {
+            self.0.lock().unwrap().push(("miss", *key));
+        }
+
+        fn on_insert(&mut self, key: &i32) {
+            self.0.lock().unwrap().push(("insert", *key));
+        }
+
+        fn on_update(&mut self, key: &i32) {
+            self.0.lock().unwrap().push(("update", *key));
+        }
+    }
+
+    fn observed(policy: Policy) -> (CacheType<i32, &'static str>, Events) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut cache = CacheType::new(policy, 4);
+        cache.set_observer(EventLog(events.clone()));
+        (cache, events)
+    }
+
+    #[test]
+    fn test_every_policy_reports_lifecycle_events() {
+        for &policy in &[Policy::LRU, Policy::LFU, Policy::Landlord] {
+            let (mut cache, events) = observed(policy);
+
+            cache.put(1, "one", 1);
+            cache.put(1, "ONE", 1);
+            cache.get(&1);
+            cache.get(&2);
+            cache.peek(&1);
+            cache.get_or_insert_with(2, 1, || "two");
+
+            assert_eq!(
+                *events.lock().unwrap(),
+                vec![
+                    ("insert", 1),
+                    ("update", 1),
+                    ("hit", 1),
+                    ("miss", 2),
+                    ("miss", 2),
+                    ("insert", 2),
+                ],
+                "{:?}",
+                policy
+            );
+        }
+    }
+
+    #[test]
+    fn test_expired_entries_are_misses() {
+        for &policy in &[Policy::LRU, Policy::LFU, Policy::Landlord] {
+            let (mut cache, events) = observed(policy);
+            let clock = ManualClock::new();
+            cache.set_clock(Arc::new(clock.clone()));
+            cache.set_expire_after_write(Duration::from_secs(1));
+
+            cache.put(1, "one", 1);
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(cache.get(&1), None);
+
+            assert_eq!(
+                *events.lock().unwrap(),
+                vec![("insert", 1), ("miss", 1)],
+                "{:?}",
+                policy
+            );
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, Hash};
+
+use crate::stats::CacheStats;
+use crate::trace;
+use crate::util::PriorityHeap;
+use crate::{Cache, EvictionListener};
+
+const POLICY: &str = "oracle";
+
+// The next use of a key that does not occur again in the trace.
+const NEVER: usize = usize::MAX;
+
+// Belady's MIN: given the whole access trace up front, evicts the entry whose next use lies
+// furthest in the future, which gives the fewest misses any policy can reach with this
+// capacity. An offline yardstick for the real policies, not a cache to deploy.
+//
+// Each `get` consumes the next occurrence of its key in the trace, so the cache expects its
+// lookups in trace order; the usual `get`, then `put` on a miss, replays one trace entry.
+pub struct OracleCache<K, V, S = RandomState> {
+    capacity: usize,
+    // Positions in the trace not yet reached, per key
+    future: HashMap<K, VecDeque<usize>, S>,
+    position: usize,
+    entries: HashMap<K, (V, u32), S>,
+    // Max-queue of entries by next use
+    pq: PriorityHeap<K, usize, S>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    stats: CacheStats,
+}
+
+impl<K: Clone + Hash + Eq, V> OracleCache<K, V> {
+    pub fn new<I: IntoIterator<Item = K>>(capacity: usize, trace: I) -> Self {
+        OracleCache::with_hasher(capacity, trace, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> OracleCache<K, V, S> {
+    pub fn with_hasher<I: IntoIterator<Item = K>>(
+        capacity: usize,
+        trace: I,
+        hash_builder: S,
+    ) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        let mut future: HashMap<K, VecDeque<usize>, S> = HashMap::with_hasher(hash_builder.clone());
+        for (position, key) in trace.into_iter().enumerate() {
+            future.entry(key).or_default().push_back(position);
+        }
+        OracleCache {
+            capacity,
+            future,
+            position: 0,
+            entries: HashMap::with_hasher(hash_builder.clone()),
+            pq: PriorityHeap::with_hasher(hash_builder),
+            eviction_listener: None,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let _span = trace::get_span(POLICY, key);
+        if !self.access(key) {
+            return None;
+        }
+        self.peek(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if !self.access(key) {
+            return None;
+        }
+        self.entries.get_mut(key).map(|(value, _)| value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    // Does not consume the trace: a put is taken to follow the lookup that missed.
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        let _span = trace::put_span(POLICY, &key, weight);
+        if let Some((old_value, old_weight)) = self.entries.get_mut(&key) {
+            *old_value = value;
+            self.stats.current_weight -= u64::from(*old_weight);
+            self.stats.current_weight += u64::from(weight);
+            *old_weight = weight;
+            return;
+        }
+
+        self.stats.insertions += 1;
+        trace::insert(POLICY, &key, weight);
+        while self.len() >= self.capacity {
+            self.evict();
+        }
+        let next_use = self.next_use(&key).unwrap_or(NEVER);
+        self.entries.insert(key.clone(), (value, weight));
+        self.pq.push(key, next_use);
+        self.stats.current_weight += u64::from(weight);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (value, weight) = self.entries.remove(key)?;
+        self.pq.remove(key);
+        self.stats.current_weight -= u64::from(weight);
+        Some(value)
+    }
+
+    // The entry `pop_victim` would remove, left in place.
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.pq.peek().map(|(key, _)| key)
+    }
+
+    // The entry reused furthest in the future, or one never reused at all.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        let (key, _) = self.pq.pop()?;
+        let (value, weight) = self.entries.remove(&key)?;
+        self.stats.current_weight -= u64::from(weight);
+        Some((key, value))
+    }
+
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.eviction_listener = Some(Box::new(listener));
+    }
+
+    // Keeps the place in the trace.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.pq.clear();
+        self.stats.current_weight = 0;
+    }
+
+    // The trace position of the key's next lookup, if it has one.
+    pub fn next_use(&self, key: &K) -> Option<usize> {
+        self.future.get(key)?.front().copied()
+    }
+
+    // How many trace entries have been consumed.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Consumes the key's next occurrence in the trace and records the hit or miss. A key
+    // that is not in the rest of the trace leaves the position alone.
+    fn access(&mut self, key: &K) -> bool {
+        if let Some(positions) = self.future.get_mut(key) {
+            if let Some(position) = positions.pop_front() {
+                self.position = self.position.max(position + 1);
+            }
+        }
+
+        let next_use = self.next_use(key).unwrap_or(NEVER);
+        if self.pq.change_priority(key, next_use).is_some() {
+            self.stats.hits += 1;
+            true
+        } else {
+            self.stats.misses += 1;
+            trace::miss(POLICY, key);
+            false
+        }
+    }
+
+    // Capacity-driven eviction; the listener sees every entry dropped this way.
+    fn evict(&mut self) {
+        if let Some((key, value)) = self.pop_victim() {
+            self.stats.evictions += 1;
+            trace::eviction(POLICY, &key);
+            if let Some(listener) = self.eviction_listener.as_mut() {
+                listener(key, value);
+            }
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Cache<K, V> for OracleCache<K, V, S> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+// Replays the trace through an oracle cache of the given capacity, caching every miss, and
+// returns the resulting stats: the best hit ratio any policy can reach.
+pub fn simulate<K: Clone + Hash + Eq>(capacity: usize, trace: &[K]) -> CacheStats {
+    let mut cache = OracleCache::new(capacity, trace.iter().cloned());
+    for key in trace {
+        if cache.get(key).is_none() {
+            cache.put(key.clone(), (), 1);
+        }
+    }
+    cache.stats()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lru::LRUCache;
+
+    #[test]
+    #[should_panic(expected = "Capacity must be greater than 0")]
+    fn test_new_cache_zero_capacity() {
+        let _cache: OracleCache<i32, i32> = OracleCache::new(0, vec![1]);
+    }
+
+    #[test]
+    fn test_evicts_entry_reused_furthest_away() {
+        let trace = vec![1, 2, 3, 1, 2, 3];
+        let mut cache = OracleCache::new(2, trace);
+        cache.get(&1);
+        cache.put(1, "one", 1);
+        cache.get(&2);
+        cache.put(2, "two", 1);
+        assert_eq!(cache.next_use(&1), Some(3));
+        assert_eq!(cache.peek_victim(), Some(&2));
+
+        cache.get(&3);
+        cache.put(3, "three", 1);
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.position(), 4);
+
+        // Key 1 is never used again, so it goes before key 3
+        assert_eq!(cache.peek_victim(), Some(&1));
+    }
+
+    #[test]
+    fn test_reference_miss_count() {
+        // A textbook trace: MIN takes 7 misses with three frames, LRU 9
+        let trace = vec![7, 0, 1, 2, 0, 3, 0, 4, 2, 3, 0, 3, 2];
+        let stats = simulate(3, &trace);
+        assert_eq!(stats.misses, 7);
+        assert_eq!(stats.hits, 6);
+
+        let mut lru = LRUCache::new(3);
+        for &key in &trace {
+            if lru.get(&key).is_none() {
+                lru.put(key, (), 1);
+            }
+        }
+        assert_eq!(lru.stats().misses, 9);
+    }
+
+    #[test]
+    fn test_never_worse_than_lru() {
+        let trace: Vec<u64> = (0..2000u64)
+            .map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15) % 50)
+            .collect();
+        for capacity in [1, 5, 20, 50] {
+            let mut lru = LRUCache::new(capacity);
+            for &key in &trace {
+                if lru.get(&key).is_none() {
+                    lru.put(key, (), 1);
+                }
+            }
+            let optimal = simulate(capacity, &trace);
+            assert!(optimal.misses <= lru.stats().misses, "{}", capacity);
+        }
+    }
+
+    #[test]
+    fn test_keys_outside_the_trace() {
+        let mut cache = OracleCache::new(2, vec![1, 2, 1]);
+        cache.put(1, "one", 1);
+        cache.put(9, "nine", 1);
+        assert_eq!(cache.get(&9), Some(&"nine"));
+        assert_eq!(cache.position(), 0);
+
+        // Key 9 never comes up, so it is the victim
+        cache.put(2, "two", 1);
+        assert!(!cache.contains_key(&9));
+        assert!(cache.contains_key(&1));
+    }
+
+    #[test]
+    fn test_update_remove_and_stats() {
+        let mut cache = OracleCache::new(2, vec![1, 1]);
+        cache.put(1, vec![1], 5);
+        cache.put(1, vec![10], 7);
+        cache.get_mut(&1).unwrap().push(11);
+
+        assert_eq!(cache.peek(&1), Some(&vec![10, 11]));
+        assert_eq!(cache.stats().current_weight, 7);
+        assert_eq!(cache.stats().insertions, 1);
+        assert_eq!(cache.remove(&1), Some(vec![10, 11]));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().misses, 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_eviction_listener() {
+        use std::sync::{Arc, Mutex};
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = OracleCache::new(1, vec![1, 2, 1]);
+        cache.set_eviction_listener(move |key, _| sink.lock().unwrap().push(key));
+
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        assert_eq!(*evicted.lock().unwrap(), vec![1]);
+        assert_eq!(cache.pop_victim(), Some((2, "two")));
+    }
+}
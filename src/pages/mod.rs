@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::ops::Range;
+
+use crate::query::Normalizer;
+use crate::{CacheStats, CacheType, Policy};
+
+// The cached results of one query, as disjoint runs keyed by the rank they start at.
+struct Pages<T> {
+    runs: BTreeMap<usize, Vec<T>>,
+    // How many results the query has, once a page past the last one was seen
+    total: Option<usize>,
+}
+
+impl<T> Pages<T> {
+    fn new() -> Self {
+        Pages {
+            runs: BTreeMap::new(),
+            total: None,
+        }
+    }
+
+    // The results ranked within `range`, if one run covers all of them. Ranks past the
+    // last result are not needed once the total is known.
+    fn slice(&self, range: &Range<usize>) -> Option<&[T]> {
+        let end = self.total.map_or(range.end, |total| range.end.min(total));
+        if range.start >= end {
+            return self.total.map(|_| &[][..]);
+        }
+        let (&start, run) = self.runs.range(..=range.start).next_back()?;
+        if start + run.len() < end {
+            return None;
+        }
+        Some(&run[range.start - start..end - start])
+    }
+
+    // Merges the results with every run they overlap or touch. Where they overlap, the
+    // new results win.
+    fn insert(&mut self, start: usize, results: Vec<T>) {
+        let end = start + results.len();
+        let touching: Vec<usize> = self
+            .runs
+            .range(..=end)
+            .rev()
+            .take_while(|(&run_start, run)| run_start + run.len() >= start)
+            .map(|(&run_start, _)| run_start)
+            .collect();
+        let mut merged_start = start;
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        for run_start in touching.into_iter().rev() {
+            let run = self.runs.remove(&run_start).unwrap_or_default();
+            merged_start = merged_start.min(run_start);
+            for (offset, result) in run.into_iter().enumerate() {
+                let rank = run_start + offset;
+                if rank < start {
+                    before.push(result);
+                } else if rank >= end {
+                    after.push(result);
+                }
+            }
+        }
+        before.extend(results);
+        before.extend(after);
+        if !before.is_empty() {
+            self.runs.insert(merged_start, before);
+        }
+    }
+
+    // Drops whatever was cached past the last result.
+    fn end_at(&mut self, total: usize) {
+        self.total = Some(total);
+        self.runs.retain(|&start, _| start < total);
+        if let Some((&start, run)) = self.runs.iter_mut().next_back() {
+            run.truncate(total - start);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.runs.values().map(Vec::len).sum()
+    }
+}
+
+// Caches result pages of a query as ranges of ranks rather than one entry per page. A
+// request for results 20..30 is served from a cached 0..50, and pages fetched one after
+// another are merged into a single run as they arrive, so paging through a query leaves
+// one entry behind instead of one per page. Queries are keyed after normalization, and an
+// entry weighs as many results as it holds.
+pub struct PagedResultCache<T> {
+    cache: CacheType<String, Pages<T>>,
+    normalizer: Normalizer,
+}
+
+impl<T> PagedResultCache<T> {
+    // Caches the results of up to `capacity` queries.
+    pub fn new(policy: Policy, capacity: usize) -> Self {
+        PagedResultCache::with_normalizer(policy, capacity, Normalizer::new())
+    }
+
+    pub fn with_normalizer(policy: Policy, capacity: usize, normalizer: Normalizer) -> Self {
+        PagedResultCache {
+            cache: CacheType::new(policy, capacity),
+            normalizer,
+        }
+    }
+
+    // Bounds the results cached across every query; queries are evicted to stay within it.
+    pub fn set_max_results(&mut self, max_results: u64) {
+        self.cache.set_max_weight(max_results);
+    }
+
+    // The results ranked within `range`, if they are all cached. A range reaching past
+    // the last result is cut short once the last page has been cached with `put_last`.
+    pub fn get(&mut self, query: &str, range: Range<usize>) -> Option<&[T]> {
+        let key = self.normalizer.normalize(query);
+        self.cache.get(&key)?.slice(&range)
+    }
+
+    pub fn peek(&self, query: &str, range: Range<usize>) -> Option<&[T]> {
+        let key = self.normalizer.normalize(query);
+        self.cache.peek(&key)?.slice(&range)
+    }
+
+    // Caches results ranked from `start` on, merged with the ranges already cached.
+    pub fn put(&mut self, query: &str, start: usize, results: Vec<T>) {
+        self.update(query, |pages| pages.insert(start, results));
+    }
+
+    // Like `put`, for a page that ends the query's results.
+    pub fn put_last(&mut self, query: &str, start: usize, results: Vec<T>) {
+        let total = start + results.len();
+        self.update(query, |pages| {
+            pages.insert(start, results);
+            pages.end_at(total);
+        });
+    }
+
+    // The cached ranges of the query, in order.
+    pub fn cached_ranges(&self, query: &str) -> Vec<Range<usize>> {
+        let key = self.normalizer.normalize(query);
+        self.cache.peek(&key).map_or_else(Vec::new, |pages| {
+            pages
+                .runs
+                .iter()
+                .map(|(&start, run)| start..start + run.len())
+                .collect()
+        })
+    }
+
+    // How many results the query has, if its last page is cached.
+    pub fn total(&self, query: &str) -> Option<usize> {
+        let key = self.normalizer.normalize(query);
+        self.cache.peek(&key).and_then(|pages| pages.total)
+    }
+
+    // Drops every cached page of the query and returns whether there were any.
+    pub fn remove(&mut self, query: &str) -> bool {
+        let key = self.normalizer.normalize(query);
+        self.cache.remove(&key).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    // Queries cached.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    // Stores the query's pages again after the change, so that its weight follows.
+    fn update<F: FnOnce(&mut Pages<T>)>(&mut self, query: &str, change: F) {
+        let key = self.normalizer.normalize(query);
+        let mut pages = self.cache.remove(&key).unwrap_or_else(Pages::new);
+        change(&mut pages);
+        let weight = u32::try_from(pages.len()).unwrap_or(u32::MAX).max(1);
+        self.cache.put(key, pages, weight);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked(range: Range<u32>) -> Vec<u32> {
+        range.collect()
+    }
+
+    #[test]
+    fn test_pages_are_served_from_a_wider_range() {
+        let mut cache = PagedResultCache::new(Policy::LRU, 10);
+        cache.put("Rust Cache", 0, ranked(0..50));
+        assert_eq!(cache.get("rust cache", 20..30), Some(&ranked(20..30)[..]));
+        assert_eq!(cache.get("rust cache", 45..55), None);
+        assert_eq!(cache.get("zig", 0..10), None);
+        assert_eq!(cache.stats().current_weight, 50);
+    }
+
+    #[test]
+    fn test_adjacent_and_overlapping_pages_merge() {
+        let mut cache = PagedResultCache::new(Policy::LRU, 10);
+        cache.put("rust", 0, ranked(0..10));
+        cache.put("rust", 10, ranked(10..20));
+        cache.put("rust", 40, ranked(40..50));
+        assert_eq!(cache.cached_ranges("rust"), vec![0..20, 40..50]);
+
+        cache.put("rust", 15, vec![99; 30]);
+        assert_eq!(cache.cached_ranges("rust"), vec![0..50]);
+        assert_eq!(cache.peek("rust", 13..16), Some(&[13, 14, 99][..]));
+        assert_eq!(cache.peek("rust", 44..46), Some(&[99, 45][..]));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_the_last_page_bounds_later_requests() {
+        let mut cache = PagedResultCache::new(Policy::LRU, 10);
+        cache.put("rare", 0, ranked(0..10));
+        cache.put_last("rare", 10, ranked(10..14));
+        assert_eq!(cache.total("rare"), Some(14));
+        assert_eq!(cache.get("rare", 10..20), Some(&ranked(10..14)[..]));
+        assert_eq!(cache.get("rare", 20..30), Some(&[][..]));
+        assert!(cache.remove("rare"));
+        assert_eq!(cache.get("rare", 0..10), None);
+    }
+}
@@ -0,0 +1,241 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use crate::postings::{PostingListCache, PostingSize};
+use crate::CacheStats;
+
+// Two terms in order, so that "rust cache" and "cache rust" name the same pair.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TermPair {
+    first: String,
+    second: String,
+}
+
+impl TermPair {
+    pub fn new(a: &str, b: &str) -> Self {
+        let (first, second) = if a <= b { (a, b) } else { (b, a) };
+        TermPair {
+            first: first.to_string(),
+            second: second.to_string(),
+        }
+    }
+
+    pub fn first(&self) -> &str {
+        &self.first
+    }
+
+    pub fn second(&self) -> &str {
+        &self.second
+    }
+
+    pub fn contains(&self, term: &str) -> bool {
+        self.first == term || self.second == term
+    }
+}
+
+// Every pair of distinct terms in a query.
+pub fn pairs_of(terms: &[&str]) -> Vec<TermPair> {
+    let mut distinct: Vec<&str> = terms.to_vec();
+    distinct.sort_unstable();
+    distinct.dedup();
+    let mut pairs = Vec::new();
+    for (i, a) in distinct.iter().enumerate() {
+        for b in &distinct[i + 1..] {
+            pairs.push(TermPair::new(a, b));
+        }
+    }
+    pairs
+}
+
+// Counts how many queries of a log each pair of terms appears in together, and returns
+// the `n` pairs that co-occur most, most frequent first. Pairs seen together fewer than
+// `min_count` times are left out: their lists are cheaper to combine on demand than to
+// keep. Terms are split on whitespace.
+pub fn frequent_pairs<I, Q>(log: I, min_count: u64, n: usize) -> Vec<(TermPair, u64)>
+where
+    I: IntoIterator<Item = Q>,
+    Q: AsRef<str>,
+{
+    let mut counts: HashMap<TermPair, u64> = HashMap::new();
+    for query in log {
+        let terms: Vec<&str> = query.as_ref().split_whitespace().collect();
+        for pair in pairs_of(&terms) {
+            *counts.entry(pair).or_insert(0) += 1;
+        }
+    }
+    let mut ranked: Vec<(TermPair, u64)> = counts
+        .into_iter()
+        .filter(|&(_, count)| count >= min_count)
+        .collect();
+    ranked.sort_by_key(|(pair, count)| (Reverse(*count), pair.clone()));
+    ranked.truncate(n);
+    ranked
+}
+
+// How the two lists of a pair were combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Combination {
+    // Documents containing both terms, for conjunctive queries.
+    Intersection,
+    // Documents containing either, for disjunctive ones.
+    Union,
+}
+
+// Caches intersections and unions of the posting lists of term pairs within a byte
+// budget, ranked like a `PostingListCache`. Left alone, it caches every pair it is given;
+// `restrict_to` limits it to chosen pairs, e.g. those `frequent_pairs` found in last
+// week's log, so that rare pairs never push out the ones that keep recurring.
+pub struct TermPairCache<P> {
+    cache: PostingListCache<(TermPair, Combination), P>,
+    admitted: Option<HashSet<TermPair>>,
+}
+
+impl<P: PostingSize> TermPairCache<P> {
+    pub fn new(budget: u64) -> Self {
+        TermPairCache {
+            cache: PostingListCache::new(budget),
+            admitted: None,
+        }
+    }
+
+    // Only these pairs are cached from now on; cached pairs left out are dropped.
+    pub fn restrict_to<I: IntoIterator<Item = TermPair>>(&mut self, pairs: I) {
+        let admitted: HashSet<TermPair> = pairs.into_iter().collect();
+        self.cache.retain(|(pair, _), _| admitted.contains(pair));
+        self.admitted = Some(admitted);
+    }
+
+    // Caches every pair again.
+    pub fn admit_all(&mut self) {
+        self.admitted = None;
+    }
+
+    pub fn is_admitted(&self, pair: &TermPair) -> bool {
+        self.admitted
+            .as_ref()
+            .is_none_or(|admitted| admitted.contains(pair))
+    }
+
+    pub fn get(&mut self, pair: &TermPair, combination: Combination) -> Option<&P> {
+        self.cache.get(&(pair.clone(), combination))
+    }
+
+    pub fn peek(&self, pair: &TermPair, combination: Combination) -> Option<&P> {
+        self.cache.peek(&(pair.clone(), combination))
+    }
+
+    // Caches the combined list if the pair is admitted, and returns whether it was.
+    pub fn put(&mut self, pair: TermPair, combination: Combination, postings: P) -> bool {
+        if !self.is_admitted(&pair) {
+            return false;
+        }
+        self.cache.put((pair, combination), postings);
+        true
+    }
+
+    pub fn get_intersection(&mut self, a: &str, b: &str) -> Option<&P> {
+        self.get(&TermPair::new(a, b), Combination::Intersection)
+    }
+
+    pub fn put_intersection(&mut self, a: &str, b: &str, postings: P) -> bool {
+        self.put(TermPair::new(a, b), Combination::Intersection, postings)
+    }
+
+    pub fn get_union(&mut self, a: &str, b: &str) -> Option<&P> {
+        self.get(&TermPair::new(a, b), Combination::Union)
+    }
+
+    pub fn put_union(&mut self, a: &str, b: &str, postings: P) -> bool {
+        self.put(TermPair::new(a, b), Combination::Union, postings)
+    }
+
+    pub fn remove(&mut self, pair: &TermPair, combination: Combination) -> Option<P> {
+        self.cache.remove(&(pair.clone(), combination))
+    }
+
+    // Drops every list combined from the term's, e.g. after its posting list changed.
+    // Returns how many there were.
+    pub fn invalidate_term(&mut self, term: &str) -> usize {
+        let before = self.cache.len();
+        self.cache.retain(|(pair, _), _| !pair.contains(term));
+        before - self.cache.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.cache.budget()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.cache.bytes()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frequent_pairs_from_a_log() {
+        let log = vec![
+            "rust cache",
+            "cache rust lru",
+            "rust  cache",
+            "lru cache",
+            "zig",
+        ];
+        let pairs = frequent_pairs(log, 2, 10);
+        assert_eq!(
+            pairs,
+            vec![
+                (TermPair::new("rust", "cache"), 3),
+                (TermPair::new("cache", "lru"), 2)
+            ]
+        );
+        assert_eq!(pairs_of(&["a", "b", "a", "c"]).len(), 3);
+        assert_eq!(TermPair::new("rust", "cache").first(), "cache");
+    }
+
+    #[test]
+    fn test_intersections_and_unions_are_kept_apart() {
+        let mut cache = TermPairCache::new(1024);
+        assert!(cache.put_intersection("rust", "cache", vec![4u32]));
+        assert!(cache.put_union("cache", "rust", vec![1u32, 4, 9]));
+        assert_eq!(cache.get_intersection("cache", "rust"), Some(&vec![4]));
+        assert_eq!(cache.get_union("rust", "cache"), Some(&vec![1, 4, 9]));
+        assert_eq!(cache.bytes(), 16);
+
+        assert_eq!(cache.invalidate_term("rust"), 2);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_restricted_to_chosen_pairs() {
+        let mut cache = TermPairCache::new(1024);
+        cache.put_intersection("rare", "pair", vec![1u32]);
+        cache.put_intersection("rust", "cache", vec![4u32]);
+        let chosen = frequent_pairs(vec!["rust cache", "rust cache"], 2, 10);
+        cache.restrict_to(chosen.into_iter().map(|(pair, _)| pair));
+
+        assert_eq!(cache.len(), 1);
+        assert!(!cache.put_intersection("rare", "pair", vec![1u32]));
+        assert!(cache.put_union("rust", "cache", vec![4u32, 5]));
+        cache.admit_all();
+        assert!(cache.put_intersection("rare", "pair", vec![1u32]));
+    }
+}
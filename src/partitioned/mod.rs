@@ -0,0 +1,293 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{CacheStats, CacheType, Policy};
+
+// The entry capacity namespaces are built with. It only sizes their tables up front,
+// since each namespace is bounded by its quota of weight.
+const INITIAL_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionError {
+    UnknownNamespace(String),
+    DuplicateNamespace(String),
+    // The quotas asked for would add up to more than the budget.
+    OverBudget { requested: u64, available: u64 },
+}
+
+impl fmt::Display for PartitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PartitionError::UnknownNamespace(name) => write!(f, "no namespace {:?}", name),
+            PartitionError::DuplicateNamespace(name) => {
+                write!(f, "namespace {:?} already exists", name)
+            }
+            PartitionError::OverBudget {
+                requested,
+                available,
+            } => write!(
+                f,
+                "quota of {} exceeds the {} left in the budget",
+                requested, available
+            ),
+        }
+    }
+}
+
+impl Error for PartitionError {}
+
+struct Namespace<K, V, S> {
+    cache: CacheType<K, V, S>,
+    quota: u64,
+}
+
+// Divides one weight budget among named namespaces, such as search verticals or
+// customers. Each namespace is a cache of its own bounded by its quota, so a namespace
+// that outgrows its quota only evicts its own entries, and keeps stats of its own.
+// Quotas never add up to more than the budget, though part of it may be left unassigned.
+pub struct PartitionedCache<K, V, S = RandomState> {
+    namespaces: HashMap<String, Namespace<K, V, S>>,
+    policy: Policy,
+    budget: u64,
+    assigned: u64,
+    hash_builder: S,
+}
+
+impl<K: Clone + Hash + Eq, V> PartitionedCache<K, V> {
+    pub fn new(policy: Policy, budget: u64) -> Self {
+        PartitionedCache::with_hasher(policy, budget, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> PartitionedCache<K, V, S> {
+    pub fn with_hasher(policy: Policy, budget: u64, hash_builder: S) -> Self {
+        PartitionedCache {
+            namespaces: HashMap::new(),
+            policy,
+            budget,
+            assigned: 0,
+            hash_builder,
+        }
+    }
+
+    // Adds a namespace running the cache-wide policy within `quota`.
+    pub fn add_namespace<N: Into<String>>(
+        &mut self,
+        name: N,
+        quota: u64,
+    ) -> Result<(), PartitionError> {
+        let cache =
+            CacheType::with_hasher(self.policy, INITIAL_CAPACITY, self.hash_builder.clone());
+        self.add_namespace_with(name, cache, quota)
+    }
+
+    // For namespaces configured beyond the policy, e.g. by `CacheBuilder`. The cache's
+    // own weight bound is replaced by the quota.
+    pub fn add_namespace_with<N: Into<String>>(
+        &mut self,
+        name: N,
+        mut cache: CacheType<K, V, S>,
+        quota: u64,
+    ) -> Result<(), PartitionError> {
+        let name = name.into();
+        if self.namespaces.contains_key(&name) {
+            return Err(PartitionError::DuplicateNamespace(name));
+        }
+        self.check_budget(quota, 0)?;
+        cache.set_max_weight(quota);
+        self.assigned += quota;
+        self.namespaces.insert(name, Namespace { cache, quota });
+        Ok(())
+    }
+
+    // Shrinking a quota evicts the namespace's victims until it fits.
+    pub fn set_quota(&mut self, name: &str, quota: u64) -> Result<(), PartitionError> {
+        let current = self.namespace(name)?.quota;
+        self.check_budget(quota, current)?;
+        let namespace = self.namespace_mut(name)?;
+        namespace.cache.set_max_weight(quota);
+        namespace.quota = quota;
+        self.assigned = self.assigned - current + quota;
+        Ok(())
+    }
+
+    // Drops the namespace and hands back its cache, returning its quota to the budget.
+    pub fn remove_namespace(&mut self, name: &str) -> Option<CacheType<K, V, S>> {
+        let namespace = self.namespaces.remove(name)?;
+        self.assigned -= namespace.quota;
+        Some(namespace.cache)
+    }
+
+    // Unknown namespaces hold nothing.
+    pub fn get(&mut self, name: &str, key: &K) -> Option<&V> {
+        self.namespaces.get_mut(name)?.cache.get(key)
+    }
+
+    pub fn peek(&self, name: &str, key: &K) -> Option<&V> {
+        self.namespaces.get(name)?.cache.peek(key)
+    }
+
+    pub fn contains_key(&self, name: &str, key: &K) -> bool {
+        self.peek(name, key).is_some()
+    }
+
+    pub fn put(&mut self, name: &str, key: K, value: V, weight: u32) -> Result<(), PartitionError> {
+        self.namespace_mut(name)?.cache.put(key, value, weight);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str, key: &K) -> Option<V> {
+        self.namespaces.get_mut(name)?.cache.remove(key)
+    }
+
+    // Empties every namespace, keeping their quotas.
+    pub fn clear(&mut self) {
+        for namespace in self.namespaces.values_mut() {
+            namespace.cache.clear();
+        }
+    }
+
+    pub fn stats(&self, name: &str) -> Option<CacheStats> {
+        self.namespaces
+            .get(name)
+            .map(|namespace| namespace.cache.stats())
+    }
+
+    // Every namespace's stats added up.
+    pub fn total_stats(&self) -> CacheStats {
+        let mut total = CacheStats::default();
+        for namespace in self.namespaces.values() {
+            total += namespace.cache.stats();
+        }
+        total
+    }
+
+    pub fn quota(&self, name: &str) -> Option<u64> {
+        self.namespaces.get(name).map(|namespace| namespace.quota)
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    // The part of the budget no namespace has been given.
+    pub fn unassigned(&self) -> u64 {
+        self.budget - self.assigned
+    }
+
+    pub fn namespaces(&self) -> impl Iterator<Item = &str> {
+        self.namespaces.keys().map(String::as_str)
+    }
+
+    pub fn cache(&self, name: &str) -> Option<&CacheType<K, V, S>> {
+        self.namespaces.get(name).map(|namespace| &namespace.cache)
+    }
+
+    // Entries in every namespace together.
+    pub fn len(&self) -> usize {
+        self.namespaces
+            .values()
+            .map(|namespace| namespace.cache.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Whether `quota` fits once `released` has been given back to the budget.
+    fn check_budget(&self, quota: u64, released: u64) -> Result<(), PartitionError> {
+        let available = self.budget - (self.assigned - released);
+        if quota > available {
+            return Err(PartitionError::OverBudget {
+                requested: quota,
+                available,
+            });
+        }
+        Ok(())
+    }
+
+    fn namespace(&self, name: &str) -> Result<&Namespace<K, V, S>, PartitionError> {
+        self.namespaces
+            .get(name)
+            .ok_or_else(|| PartitionError::UnknownNamespace(name.to_string()))
+    }
+
+    fn namespace_mut(&mut self, name: &str) -> Result<&mut Namespace<K, V, S>, PartitionError> {
+        self.namespaces
+            .get_mut(name)
+            .ok_or_else(|| PartitionError::UnknownNamespace(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partitioned() -> PartitionedCache<u32, &'static str> {
+        let mut cache = PartitionedCache::new(Policy::LRU, 100);
+        cache.add_namespace("web", 60).unwrap();
+        cache.add_namespace("images", 30).unwrap();
+        cache
+    }
+
+    #[test]
+    fn test_eviction_stays_within_a_namespace() {
+        let mut cache = partitioned();
+        cache.put("web", 1, "page", 50).unwrap();
+        for key in 0..10 {
+            cache.put("images", key, "thumbnail", 10).unwrap();
+        }
+        assert_eq!(cache.cache("images").unwrap().len(), 3);
+        assert_eq!(cache.stats("images").unwrap().evictions, 7);
+        assert_eq!(cache.get("web", &1), Some(&"page"));
+        assert_eq!(cache.stats("web").unwrap().evictions, 0);
+        assert_eq!(cache.total_stats().current_weight, 80);
+
+        // The same key in two namespaces is two entries
+        assert_eq!(cache.get("web", &9), None);
+        assert_eq!(cache.get("images", &9), Some(&"thumbnail"));
+        assert_eq!(cache.len(), 4);
+    }
+
+    #[test]
+    fn test_quotas_fit_the_budget() {
+        let mut cache = partitioned();
+        assert_eq!(
+            cache.add_namespace("video", 20),
+            Err(PartitionError::OverBudget {
+                requested: 20,
+                available: 10
+            })
+        );
+        assert_eq!(
+            cache.add_namespace("web", 1),
+            Err(PartitionError::DuplicateNamespace("web".to_string()))
+        );
+        assert_eq!(
+            cache.put("video", 1, "clip", 1),
+            Err(PartitionError::UnknownNamespace("video".to_string()))
+        );
+
+        cache.set_quota("web", 70).unwrap();
+        assert_eq!(cache.unassigned(), 0);
+        assert!(cache.remove_namespace("images").is_some());
+        cache.add_namespace("video", 30).unwrap();
+        assert_eq!(cache.quota("video"), Some(30));
+    }
+
+    #[test]
+    fn test_shrinking_a_quota_evicts() {
+        let mut cache = partitioned();
+        for key in 0..6 {
+            cache.put("web", key, "page", 10).unwrap();
+        }
+        cache.set_quota("web", 20).unwrap();
+        assert_eq!(cache.cache("web").unwrap().len(), 2);
+        assert!(cache.contains_key("web", &5));
+        assert_eq!(cache.unassigned(), 50);
+    }
+}
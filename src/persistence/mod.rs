@@ -0,0 +1,329 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::snapshot::{Decode, Encode, SnapshotError};
+use crate::sync_cache::lock;
+use crate::{trace, CacheType, SyncCache};
+
+// How often the worker checks the mutation count, when snapshots are also triggered by it.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Snapshots a shared cache to disk in the background, so a restarted process can warm up
+// from the last one. `path` holds the newest snapshot and `path.1`, `path.2`, ... the
+// older generations. Each snapshot is written beside them first and renamed into place,
+// so a crash mid-write never leaves a torn file behind.
+pub struct Persistence {
+    path: PathBuf,
+    interval: Duration,
+    mutations: Option<u64>,
+    generations: usize,
+}
+
+impl Persistence {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Persistence {
+            path: path.into(),
+            interval: Duration::from_secs(60),
+            mutations: None,
+            generations: 3,
+        }
+    }
+
+    // How often the worker saves a snapshot while the cache changes. Defaults to once a
+    // minute. Nothing is written while the cache is unchanged.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    // Also saves as soon as this many mutations have happened since the last snapshot.
+    // Only mutations made through the `SyncCache` methods are counted.
+    pub fn every_mutations(mut self, mutations: u64) -> Self {
+        assert!(
+            mutations > 0,
+            "Mutations between snapshots must be positive"
+        );
+        self.mutations = Some(mutations);
+        self
+    }
+
+    // How many snapshots are kept, the newest included. Defaults to 3.
+    pub fn generations(mut self, generations: usize) -> Self {
+        assert!(generations > 0, "At least one generation must be kept");
+        self.generations = generations;
+        self
+    }
+
+    // Writes a snapshot now and shifts the older generations back, dropping the oldest.
+    pub fn save<K, V, S>(
+        &self,
+        cache: &SyncCache<K, V, CacheType<K, V, S>>,
+    ) -> Result<(), SnapshotError>
+    where
+        K: Clone + Hash + Eq + Encode,
+        V: Encode,
+        S: BuildHasher + Clone,
+    {
+        // Encoded in memory first, so the lock is not held while the disk is written
+        let mut bytes = Vec::new();
+        cache.lock().save_to(&mut bytes)?;
+
+        let staging = self.suffixed("tmp");
+        let mut file = File::create(&staging)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        drop(file);
+        for generation in (1..self.generations).rev() {
+            rename_if_exists(
+                &self.generation(generation - 1),
+                &self.generation(generation),
+            )?;
+        }
+        fs::rename(&staging, &self.path)?;
+        Ok(())
+    }
+
+    // Loads the newest generation that is intact, falling back to older ones when a
+    // snapshot is missing or corrupt. Fails with the newest one's error if none loads.
+    pub fn restore<K, V, S>(&self) -> Result<CacheType<K, V, S>, SnapshotError>
+    where
+        K: Clone + Hash + Eq + Decode,
+        V: Decode,
+        S: BuildHasher + Clone + Default,
+    {
+        let mut newest_error = None;
+        for generation in 0..self.generations {
+            let loaded = File::open(self.generation(generation))
+                .map_err(SnapshotError::from)
+                .and_then(CacheType::load_from);
+            match loaded {
+                Ok(cache) => return Ok(cache),
+                Err(error) => {
+                    newest_error.get_or_insert(error);
+                }
+            }
+        }
+        Err(newest_error.expect("at least one generation is kept"))
+    }
+
+    // Saves on a new thread whenever a trigger fires, until the worker is stopped or
+    // dropped. Stopping it saves a last snapshot if the cache changed since the previous
+    // one. Failed snapshots are reported through tracing and retried at the next trigger.
+    pub fn spawn<K, V, S>(
+        self,
+        cache: Arc<SyncCache<K, V, CacheType<K, V, S>>>,
+    ) -> PersistenceWorker
+    where
+        K: Clone + Hash + Eq + Encode + Send + 'static,
+        V: Encode + Send + 'static,
+        S: BuildHasher + Clone + Send + 'static,
+    {
+        let signal = Arc::new(Signal {
+            stopped: Mutex::new(false),
+            wake: Condvar::new(),
+            wakeups: AtomicU64::new(0),
+        });
+        let worker_signal = signal.clone();
+        // What the cache already holds when the worker starts is not saved until it changes
+        let mut saved_at = Instant::now();
+        let mut saved_mutations = cache.mutations();
+        let handle = thread::spawn(move || {
+            let mut result = Ok(());
+            let mut stopped = lock(&worker_signal.stopped);
+            loop {
+                worker_signal.wakeups.fetch_add(1, Ordering::Relaxed);
+                let stopping = *stopped;
+                let pending = cache.mutations() - saved_mutations;
+                let due = stopping
+                    || saved_at.elapsed() >= self.interval
+                    || self.mutations.is_some_and(|mutations| pending >= mutations);
+                if due && pending > 0 {
+                    drop(stopped);
+                    saved_at = Instant::now();
+                    saved_mutations += pending;
+                    result = self.save(&cache);
+                    if let Err(error) = &result {
+                        trace::snapshot_failed(&self.path, error);
+                    }
+                    stopped = lock(&worker_signal.stopped);
+                } else if due {
+                    // Nothing changed this interval; wait a whole one again
+                    saved_at = Instant::now();
+                }
+                if stopping {
+                    return result;
+                }
+                let wait = match self.mutations {
+                    Some(_) => POLL_INTERVAL,
+                    None => self.interval.saturating_sub(saved_at.elapsed()),
+                };
+                stopped = worker_signal
+                    .wake
+                    .wait_timeout(stopped, wait.max(Duration::from_millis(1)))
+                    .expect("persistence lock poisoned")
+                    .0;
+            }
+        });
+        PersistenceWorker {
+            signal,
+            handle: Some(handle),
+        }
+    }
+
+    fn generation(&self, generation: usize) -> PathBuf {
+        match generation {
+            0 => self.path.clone(),
+            _ => self.suffixed(&generation.to_string()),
+        }
+    }
+
+    fn suffixed(&self, suffix: &str) -> PathBuf {
+        let mut name = OsString::from(self.path.as_os_str());
+        name.push(".");
+        name.push(suffix);
+        PathBuf::from(name)
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+struct Signal {
+    stopped: Mutex<bool>,
+    wake: Condvar,
+    // How often the worker has checked for a snapshot to save
+    wakeups: AtomicU64,
+}
+
+// The thread started by `Persistence::spawn`. Dropping it stops the thread too.
+pub struct PersistenceWorker {
+    signal: Arc<Signal>,
+    handle: Option<JoinHandle<Result<(), SnapshotError>>>,
+}
+
+impl PersistenceWorker {
+    // Waits for the last snapshot and returns how the most recent one went.
+    pub fn stop(mut self) -> Result<(), SnapshotError> {
+        self.shut_down()
+    }
+
+    fn shut_down(&mut self) -> Result<(), SnapshotError> {
+        *lock(&self.signal.stopped) = true;
+        self.signal.wake.notify_all();
+        match self.handle.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            // A panic in the worker was already reported on its thread
+            Some(Err(_)) | None => Ok(()),
+        }
+    }
+}
+
+impl Drop for PersistenceWorker {
+    fn drop(&mut self) {
+        let _ = self.shut_down();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Policy;
+    use std::collections::hash_map::RandomState;
+    use std::process;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("persistence-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn restored_len(persistence: &Persistence) -> usize {
+        persistence
+            .restore::<i32, i32, RandomState>()
+            .unwrap()
+            .len()
+    }
+
+    #[test]
+    fn test_keeps_the_last_generations() {
+        let dir = scratch("generations");
+        let persistence = Persistence::new(dir.join("cache")).generations(3);
+        let cache = SyncCache::new(CacheType::new(Policy::LRU, 10));
+        for key in 0..4 {
+            cache.put(key, key, 1);
+            persistence.save(&cache).unwrap();
+        }
+        assert!(dir.join("cache.2").exists());
+        assert!(!dir.join("cache.3").exists());
+        assert!(!dir.join("cache.tmp").exists());
+        assert_eq!(restored_len(&persistence), 4);
+
+        // The newest snapshot is torn, so the one before it is restored
+        let newest = fs::read(dir.join("cache")).unwrap();
+        fs::write(dir.join("cache"), &newest[..newest.len() / 2]).unwrap();
+        assert_eq!(restored_len(&persistence), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_fails_without_a_snapshot() {
+        let dir = scratch("missing");
+        let persistence = Persistence::new(dir.join("cache"));
+        let result = persistence.restore::<i32, i32, RandomState>();
+        assert!(matches!(result, Err(SnapshotError::Io(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_worker_saves_after_mutations_and_on_stop() {
+        let dir = scratch("worker");
+        let path = dir.join("cache");
+        let cache = Arc::new(SyncCache::new(CacheType::new(Policy::LFU, 100)));
+        let worker = Persistence::new(&path)
+            .interval(Duration::from_secs(3600))
+            .every_mutations(10)
+            .spawn(cache.clone());
+        for key in 0..10 {
+            cache.put(key, key, 1);
+        }
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !path.exists() {
+            assert!(Instant::now() < deadline, "the worker never saved");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        cache.put(10, 10, 1);
+        worker.stop().unwrap();
+        assert_eq!(restored_len(&Persistence::new(&path)), 11);
+        assert_eq!(Arc::strong_count(&cache), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_idle_worker_sleeps_whole_intervals() {
+        let dir = scratch("idle");
+        let cache = Arc::new(SyncCache::new(CacheType::<i32, i32>::new(Policy::LRU, 10)));
+        let worker = Persistence::new(dir.join("cache"))
+            .interval(Duration::from_millis(20))
+            .spawn(cache);
+        thread::sleep(Duration::from_millis(200));
+        let wakeups = worker.signal.wakeups.load(Ordering::Relaxed);
+        worker.stop().unwrap();
+        // About one per interval, not one per millisecond once the first is over
+        assert!(wakeups <= 20, "woke {} times", wakeups);
+        assert!(!dir.join("cache").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{Cache, CacheStats, CacheType};
+
+// Keeps critical entries, like the home-page query or a spelling model, out of the policy's
+// reach. A pinned entry is moved beside the policy, so it is never chosen as a victim and
+// does not expire. Its weight comes out of the budget the unpinned entries share, which
+// `dynamic_weight` shows: pinning shrinks what the policy may hold, and unpinning hands the
+// weight back with the entry. The policy's entry capacity only counts unpinned entries.
+pub struct PinnedCache<K, V, S = RandomState> {
+    cache: CacheType<K, V, S>,
+    pinned: HashMap<K, (V, u32)>,
+    max_weight: u64,
+    pinned_weight: u64,
+    pinned_hits: u64,
+}
+
+impl<K, V, S> PinnedCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    // The cache's weight bound is replaced by `max_weight`.
+    pub fn new(mut cache: CacheType<K, V, S>, max_weight: u64) -> Self {
+        cache.set_max_weight(max_weight);
+        PinnedCache {
+            cache,
+            pinned: HashMap::new(),
+            max_weight,
+            pinned_weight: 0,
+            pinned_hits: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        if let Some((value, _)) = self.pinned.get(key) {
+            self.pinned_hits += 1;
+            return Some(value);
+        }
+        self.cache.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if let Some((value, _)) = self.pinned.get_mut(key) {
+            self.pinned_hits += 1;
+            return Some(value);
+        }
+        self.cache.get_mut(key)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        match self.pinned.get(key) {
+            Some((value, _)) => Some(value),
+            None => self.cache.peek(key),
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.pinned.contains_key(key) || self.cache.contains_key(key)
+    }
+
+    // Writing a pinned key replaces its value and keeps it pinned, unless the new weight
+    // no longer fits the budget: then the entry is unpinned.
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        if self.pinned.contains_key(&key) {
+            if self.pinned_weight_with(&key, weight) <= self.max_weight {
+                self.put_pinned(key, value, weight);
+                return;
+            }
+            self.remove(&key);
+        }
+        self.cache.put(key, value, weight);
+    }
+
+    // Stores the entry pinned straight away, e.g. a model blob loaded at startup. Returns
+    // false without storing it when its weight does not fit the budget.
+    pub fn put_pinned(&mut self, key: K, value: V, weight: u32) -> bool {
+        let pinned_weight = self.pinned_weight_with(&key, weight);
+        if pinned_weight > self.max_weight {
+            return false;
+        }
+        self.cache.remove(&key);
+        self.pinned.insert(key, (value, weight));
+        self.set_pinned_weight(pinned_weight);
+        true
+    }
+
+    // Pins a cached entry. Returns false when the key is not cached, or when pinning it
+    // would leave less than nothing of the budget.
+    pub fn pin(&mut self, key: &K) -> bool {
+        if self.pinned.contains_key(key) {
+            return true;
+        }
+        let weight = match self.cache.entry_info(key) {
+            Some(info) => info.weight,
+            None => return false,
+        };
+        if self.pinned_weight + u64::from(weight) > self.max_weight {
+            return false;
+        }
+        match self.cache.remove(key) {
+            Some(value) => {
+                self.pinned.insert(key.clone(), (value, weight));
+                self.set_pinned_weight(self.pinned_weight + u64::from(weight));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Hands the entry back to the policy, which may choose it as a victim from then on.
+    // Returns false when the key was not pinned.
+    pub fn unpin(&mut self, key: &K) -> bool {
+        match self.pinned.remove_entry(key) {
+            Some((key, (value, weight))) => {
+                self.set_pinned_weight(self.pinned_weight - u64::from(weight));
+                self.cache.put(key, value, weight);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_pinned(&self, key: &K) -> bool {
+        self.pinned.contains_key(key)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        match self.pinned.remove(key) {
+            Some((value, weight)) => {
+                self.set_pinned_weight(self.pinned_weight - u64::from(weight));
+                Some(value)
+            }
+            None => self.cache.remove(key),
+        }
+    }
+
+    // Victims only ever come from the unpinned entries.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.cache.pop_victim()
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.cache.peek_victim()
+    }
+
+    // Drops the pinned entries too.
+    pub fn clear(&mut self) {
+        self.cache.clear();
+        self.pinned.clear();
+        self.set_pinned_weight(0);
+    }
+
+    // Lookups served by pinned entries count as hits, and their weight towards
+    // `current_weight`.
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = self.cache.stats();
+        stats.hits += self.pinned_hits;
+        stats.current_weight += self.pinned_weight;
+        stats
+    }
+
+    pub fn max_weight(&self) -> u64 {
+        self.max_weight
+    }
+
+    pub fn pinned_weight(&self) -> u64 {
+        self.pinned_weight
+    }
+
+    // The part of the budget the policy manages, i.e. what is left once pinned entries
+    // are paid for.
+    pub fn dynamic_weight(&self) -> u64 {
+        self.max_weight - self.pinned_weight
+    }
+
+    pub fn pinned_len(&self) -> usize {
+        self.pinned.len()
+    }
+
+    pub fn inner(&self) -> &CacheType<K, V, S> {
+        &self.cache
+    }
+
+    // Pinned entries and the rest together.
+    pub fn len(&self) -> usize {
+        self.cache.len() + self.pinned.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The pinned weight once `key` is pinned with `weight`.
+    fn pinned_weight_with(&self, key: &K, weight: u32) -> u64 {
+        let released = self.pinned.get(key).map_or(0, |(_, weight)| *weight);
+        self.pinned_weight - u64::from(released) + u64::from(weight)
+    }
+
+    // Shrinking the policy's share evicts its victims until the rest fits.
+    fn set_pinned_weight(&mut self, pinned_weight: u64) {
+        self.pinned_weight = pinned_weight;
+        self.cache.set_max_weight(self.max_weight - pinned_weight);
+    }
+}
+
+impl<K, V, S> Cache<K, V> for PinnedCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Policy;
+
+    fn pinning() -> PinnedCache<&'static str, u32> {
+        PinnedCache::new(CacheType::new(Policy::LRU, 100), 10)
+    }
+
+    #[test]
+    fn test_pinned_entries_are_never_victims() {
+        let mut cache = pinning();
+        cache.put("home", 1, 4);
+        assert!(cache.pin(&"home"));
+        assert_eq!(cache.dynamic_weight(), 6);
+        for key in &["a", "b", "c", "d", "e"] {
+            cache.put(key, 2, 2);
+        }
+        assert_eq!(cache.get(&"home"), Some(&1));
+        assert_eq!(cache.inner().len(), 3);
+        assert_eq!(cache.peek_victim(), Some(&"c"));
+        assert_eq!(cache.stats().current_weight, 10);
+        assert_eq!(cache.stats().hits, 1);
+
+        // Unpinned, it is a victim like any other
+        assert!(cache.unpin(&"home"));
+        assert_eq!(cache.pinned_weight(), 0);
+        assert_eq!(cache.inner().len(), 4);
+        cache.put("big", 3, 9);
+        assert!(!cache.contains_key(&"home"));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_pins_fit_the_budget() {
+        let mut cache = pinning();
+        assert!(!cache.pin(&"missing"));
+        assert!(cache.put_pinned("model", 1, 8));
+        assert!(!cache.put_pinned("speller", 2, 3));
+        cache.put("speller", 2, 3);
+        assert!(!cache.pin(&"speller"));
+        assert!(!cache.is_pinned(&"speller"));
+        assert_eq!(cache.peek(&"speller"), Some(&2));
+
+        // Rewriting a pinned entry keeps it pinned under its new weight
+        cache.put("model", 3, 5);
+        assert!(cache.is_pinned(&"model"));
+        assert_eq!(cache.dynamic_weight(), 5);
+        assert_eq!(cache.remove(&"model"), Some(3));
+        assert_eq!(cache.dynamic_weight(), 10);
+
+        // Past the budget, the write stays but the pin goes
+        cache.put_pinned("model", 4, 5);
+        cache.put("model", 5, 11);
+        assert!(!cache.is_pinned(&"model"));
+        assert_eq!(cache.pinned_weight(), 0);
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+use crate::gdsf::GDSFCache;
+use crate::CacheStats;
+
+// The entry capacity the cache is built with. Only the byte budget bounds it.
+const INITIAL_CAPACITY: usize = 64;
+
+// The weight of a list of document ids, in bytes. Lists past 4 GiB count as 4 GiB.
+pub fn u32_postings_size(postings: &[u32]) -> u32 {
+    u32::try_from(postings.len())
+        .unwrap_or(u32::MAX)
+        .saturating_mul(4)
+}
+
+// The weight of an encoded list, e.g. variable-byte or PForDelta compressed, in bytes.
+pub fn byte_postings_size(postings: &[u8]) -> u32 {
+    u32::try_from(postings.len()).unwrap_or(u32::MAX)
+}
+
+// Posting lists that know their size in bytes.
+pub trait PostingSize {
+    fn byte_size(&self) -> u32;
+}
+
+impl PostingSize for Vec<u32> {
+    fn byte_size(&self) -> u32 {
+        u32_postings_size(self)
+    }
+}
+
+impl PostingSize for Vec<u8> {
+    fn byte_size(&self) -> u32 {
+        byte_postings_size(self)
+    }
+}
+
+impl PostingSize for Arc<[u32]> {
+    fn byte_size(&self) -> u32 {
+        u32_postings_size(self)
+    }
+}
+
+impl PostingSize for Arc<[u8]> {
+    fn byte_size(&self) -> u32 {
+        byte_postings_size(self)
+    }
+}
+
+// Caches posting lists by term within a memory budget. Every list is weighed by its size
+// in bytes and ranked by GreedyDual-Size-Frequency. By default every list costs the same
+// to fetch, so for equal sizes the frequent lists stay, and for equal frequencies the
+// small ones do: one long list would otherwise push out many short ones that answer more
+// queries between them.
+pub struct PostingListCache<K, V, S = RandomState> {
+    cache: GDSFCache<K, V, S>,
+    budget: u64,
+}
+
+impl<K: Clone + Hash + Eq, V: PostingSize> PostingListCache<K, V> {
+    pub fn new(budget: u64) -> Self {
+        PostingListCache::with_hasher(budget, RandomState::new())
+    }
+}
+
+impl<K, V, S> PostingListCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    V: PostingSize,
+    S: BuildHasher + Clone,
+{
+    pub fn with_hasher(budget: u64, hash_builder: S) -> Self {
+        let mut cache = GDSFCache::with_hasher(INITIAL_CAPACITY, hash_builder);
+        cache.set_max_weight(budget);
+        PostingListCache { cache, budget }
+    }
+
+    pub fn get(&mut self, term: &K) -> Option<&V> {
+        self.cache.get(term)
+    }
+
+    pub fn peek(&self, term: &K) -> Option<&V> {
+        self.cache.peek(term)
+    }
+
+    pub fn contains_key(&self, term: &K) -> bool {
+        self.cache.contains_key(term)
+    }
+
+    pub fn put(&mut self, term: K, postings: V) {
+        self.put_with_cost(term, postings, 1.0);
+    }
+
+    // For lists that differ in what a miss costs, e.g. the time to read them from disk.
+    pub fn put_with_cost(&mut self, term: K, postings: V, cost: f64) {
+        let size = postings.byte_size();
+        self.cache.put_with_cost(term, postings, size, cost);
+    }
+
+    pub fn remove(&mut self, term: &K) -> Option<V> {
+        self.cache.remove(term)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.cache.pop_victim()
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.cache.peek_victim()
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, f: F) {
+        self.cache.retain(f);
+    }
+
+    // Lists already over a smaller budget are evicted right away.
+    pub fn set_budget(&mut self, budget: u64) {
+        self.budget = budget;
+        self.cache.set_max_weight(budget);
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    // The bytes taken by the cached lists.
+    pub fn bytes(&self) -> u64 {
+        self.cache.stats().current_weight
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn inner(&self) -> &GDSFCache<K, V, S> {
+        &self.cache
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sizes_of_postings() {
+        assert_eq!(vec![1u32, 5, 9].byte_size(), 12);
+        assert_eq!(vec![0x81u8, 0x05].byte_size(), 2);
+        let shared: Arc<[u32]> = Arc::from(vec![3, 4]);
+        assert_eq!(shared.byte_size(), 8);
+        assert_eq!(byte_postings_size(&[]), 0);
+    }
+
+    #[test]
+    fn test_long_lists_give_way_to_short_ones() {
+        let mut cache = PostingListCache::new(64);
+        cache.put("the", vec![0u32; 12]);
+        cache.put("rust", vec![1, 2]);
+        cache.put("cache", vec![3, 4]);
+        assert_eq!(cache.bytes(), 64);
+        assert_eq!(cache.get(&"rust"), Some(&vec![1, 2]));
+
+        cache.put("zig", vec![5, 6, 7]);
+        assert!(!cache.contains_key(&"the"));
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.bytes(), 28);
+        assert_eq!(cache.budget(), 64);
+    }
+
+    #[test]
+    fn test_shrinking_the_budget_evicts() {
+        let mut cache = PostingListCache::new(100);
+        cache.put(1, vec![7u8; 40]);
+        cache.put(2, vec![7u8; 40]);
+        cache.get(&2);
+        cache.set_budget(50);
+        assert_eq!(cache.peek(&2).map(Vec::len), Some(40));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+}
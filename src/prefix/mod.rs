@@ -0,0 +1,222 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeSet;
+use std::hash::{BuildHasher, Hash};
+use std::ops::Bound;
+use std::sync::{Arc, Mutex};
+
+use crate::sync_cache::lock;
+use crate::{Cache, CacheStats, CacheType};
+
+// A cache of string-like keys that can drop every key starting with a prefix at once,
+// e.g. all pages of a query cached as "q:rust page:1", "q:rust page:2", ... An ordered
+// index of the keys beside the cache finds them without a scan of the whole cache. Keys
+// must order like the strings they borrow as, which `String` does.
+//
+// The cache's eviction listener keeps the index in step, so one set beforehand is
+// replaced. Expired entries linger in the index until they are removed or the index is
+// pruned, which happens once it has grown to twice the cache's size.
+pub struct PrefixCache<K, V, S = RandomState> {
+    cache: CacheType<K, V, S>,
+    index: BTreeSet<K>,
+    // What the policy evicted during the current call, unindexed before it returns
+    evicted: Arc<Mutex<Vec<K>>>,
+}
+
+impl<K, V, S> PrefixCache<K, V, S>
+where
+    K: Clone + Hash + Ord + Borrow<str> + Send + 'static,
+    V: Send + 'static,
+    S: BuildHasher + Clone,
+{
+    // Entries already in the cache are indexed too.
+    pub fn new(mut cache: CacheType<K, V, S>) -> Self {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        cache.set_eviction_listener(move |key, _| lock(&sink).push(key));
+        let index = cache.iter().map(|(key, _)| key.clone()).collect();
+        PrefixCache {
+            cache,
+            index,
+            evicted,
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.cache.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.cache.get_mut(key)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.cache.peek(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        self.index.insert(key.clone());
+        self.cache.put(key, value, weight);
+        self.unindex_evicted();
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.index.remove::<K>(key);
+        self.cache.remove(key)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        let (key, value) = self.cache.pop_victim()?;
+        self.index.remove::<K>(&key);
+        Some((key, value))
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.cache.peek_victim()
+    }
+
+    // Removes every entry whose key starts with `prefix` and returns how many there were.
+    pub fn invalidate_prefix(&mut self, prefix: &str) -> usize {
+        let matching: Vec<K> = self.keys_with_prefix(prefix).cloned().collect();
+        let mut removed = 0;
+        for key in matching {
+            self.index.remove::<K>(&key);
+            if self.cache.remove(&key).is_some() {
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    // The indexed keys starting with `prefix`, in order. Expired keys not yet pruned from
+    // the index are included.
+    pub fn keys_with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a K> + 'a {
+        self.index
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(move |key| (*key).borrow().starts_with(prefix))
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+        self.index.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn inner(&self) -> &CacheType<K, V, S> {
+        &self.cache
+    }
+
+    pub fn into_inner(self) -> CacheType<K, V, S> {
+        self.cache
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    fn unindex_evicted(&mut self) {
+        for key in lock(&self.evicted).drain(..) {
+            self.index.remove::<K>(&key);
+        }
+        if self.index.len() > 2 * self.cache.len() + 64 {
+            let cache = &self.cache;
+            self.index.retain(|key| cache.contains_key(key));
+        }
+    }
+}
+
+impl<K, V, S> Cache<K, V> for PrefixCache<K, V, S>
+where
+    K: Clone + Hash + Ord + Borrow<str> + Send + 'static,
+    V: Send + 'static,
+    S: BuildHasher + Clone,
+{
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Policy;
+
+    fn pages(cache: &mut PrefixCache<String, u32>, query: &str, count: u32) {
+        for page in 1..=count {
+            cache.put(format!("q:{} page:{}", query, page), page, 1);
+        }
+    }
+
+    #[test]
+    fn test_invalidates_every_page_of_a_query() {
+        let mut cache = PrefixCache::new(CacheType::new(Policy::LRU, 10));
+        pages(&mut cache, "rust", 3);
+        pages(&mut cache, "rusty", 2);
+        pages(&mut cache, "go", 2);
+
+        assert_eq!(cache.invalidate_prefix("q:rust page:"), 3);
+        assert_eq!(cache.len(), 4);
+        assert!(cache.contains_key(&"q:rusty page:1".to_string()));
+        assert_eq!(cache.keys_with_prefix("q:rust").count(), 2);
+        assert_eq!(cache.invalidate_prefix("q:none"), 0);
+    }
+
+    #[test]
+    fn test_index_follows_evictions() {
+        let mut cache = PrefixCache::new(CacheType::new(Policy::FIFO, 2));
+        pages(&mut cache, "rust", 3);
+        let indexed: Vec<&String> = cache.keys_with_prefix("q:").collect();
+        assert_eq!(indexed, vec!["q:rust page:2", "q:rust page:3"]);
+
+        cache.remove(&"q:rust page:2".to_string());
+        assert_eq!(cache.invalidate_prefix(""), 1);
+        assert!(cache.is_empty());
+    }
+}
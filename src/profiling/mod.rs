@@ -0,0 +1,205 @@
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex};
+
+use crate::observer::CacheObserver;
+
+mod reuse;
+mod stack;
+
+pub use self::reuse::{ReuseDistanceProfiler, ReuseHistogram};
+use self::stack::StackDistances;
+
+// Keys are sampled when their hash, reduced modulo this, falls below the threshold.
+const SAMPLE_MODULUS: u64 = 1 << 24;
+
+// Picks a fixed fraction of the key space by hash. Sampling keys rather than accesses
+// means every access to a sampled key is seen, so distances among the sample stay exact.
+struct KeySampler<S> {
+    hash_builder: S,
+    threshold: u64,
+}
+
+impl<S: BuildHasher> KeySampler<S> {
+    fn new(sample_rate: f64, hash_builder: S) -> Self {
+        assert!(
+            sample_rate > 0.0 && sample_rate <= 1.0,
+            "Sample rate must be in (0, 1]"
+        );
+        KeySampler {
+            hash_builder,
+            threshold: ((sample_rate * SAMPLE_MODULUS as f64) as u64).max(1),
+        }
+    }
+
+    fn is_sampled<K: Hash>(&self, key: &K) -> bool {
+        self.threshold == SAMPLE_MODULUS
+            || self.hash_builder.hash_one(key) % SAMPLE_MODULUS < self.threshold
+    }
+
+    fn rate(&self) -> f64 {
+        self.threshold as f64 / SAMPLE_MODULUS as f64
+    }
+}
+
+// Estimates an LRU hit-ratio curve from an access stream, following SHARDS: only keys whose
+// hash falls in a fixed fraction of the hash space are tracked, and their stack distances
+// are scaled up by the inverse of that fraction.
+//
+// Feed it with `record`, or share it with a cache as an observer so that every `get`,
+// `get_mut` and `entry` lookup is profiled.
+pub struct ShardsProfiler<K, S = RandomState> {
+    sampler: KeySampler<S>,
+    distances: StackDistances<K>,
+    // Sampled accesses by unscaled stack distance.
+    histogram: BTreeMap<u64, u64>,
+    sampled_accesses: u64,
+    cold_misses: u64,
+}
+
+impl<K: Hash + Eq + Clone> ShardsProfiler<K> {
+    pub fn new(sample_rate: f64) -> Self {
+        ShardsProfiler::with_hasher(sample_rate, RandomState::new())
+    }
+}
+
+impl<K: Hash + Eq + Clone, S: BuildHasher> ShardsProfiler<K, S> {
+    pub fn with_hasher(sample_rate: f64, hash_builder: S) -> Self {
+        ShardsProfiler {
+            sampler: KeySampler::new(sample_rate, hash_builder),
+            distances: StackDistances::new(),
+            histogram: BTreeMap::new(),
+            sampled_accesses: 0,
+            cold_misses: 0,
+        }
+    }
+
+    pub fn record(&mut self, key: &K) {
+        if !self.sampler.is_sampled(key) {
+            return;
+        }
+
+        self.sampled_accesses += 1;
+        match self.distances.access(key) {
+            Some(distance) => *self.histogram.entry(distance).or_insert(0) += 1,
+            None => self.cold_misses += 1,
+        }
+    }
+
+    // The fraction of keys that are tracked.
+    pub fn sample_rate(&self) -> f64 {
+        self.sampler.rate()
+    }
+
+    pub fn sampled_accesses(&self) -> u64 {
+        self.sampled_accesses
+    }
+
+    // Sampled accesses to keys seen for the first time; no cache size turns these into hits.
+    pub fn cold_misses(&self) -> u64 {
+        self.cold_misses
+    }
+
+    // An estimate of how many distinct keys the stream has touched.
+    pub fn estimated_distinct_keys(&self) -> u64 {
+        (self.distances.distinct_keys() as f64 / self.sample_rate()).round() as u64
+    }
+
+    // The estimated hit ratio of an LRU cache holding `cache_size` entries. An access hits
+    // when fewer than `cache_size` other keys were touched since the key's last access.
+    // Zero before any sampled access.
+    pub fn hit_ratio(&self, cache_size: u64) -> f64 {
+        if self.sampled_accesses == 0 {
+            return 0.0;
+        }
+        let limit = cache_size as f64 * self.sample_rate();
+        let hits: u64 = self
+            .histogram
+            .iter()
+            .take_while(|&(&distance, _)| (distance as f64) < limit)
+            .map(|(_, &count)| count)
+            .sum();
+        hits as f64 / self.sampled_accesses as f64
+    }
+
+    // The estimated hit ratio at each of `cache_sizes`.
+    pub fn curve(&self, cache_sizes: &[u64]) -> Vec<(u64, f64)> {
+        cache_sizes
+            .iter()
+            .map(|&size| (size, self.hit_ratio(size)))
+            .collect()
+    }
+}
+
+// Shares a profiler with a cache: install a clone with `set_observer` and keep the other
+// handle to read the curve.
+impl<K, S> CacheObserver<K> for Arc<Mutex<ShardsProfiler<K, S>>>
+where
+    K: Hash + Eq + Clone,
+    S: BuildHasher,
+{
+    fn on_hit(&mut self, key: &K) {
+        self.lock().unwrap().record(key);
+    }
+
+    fn on_miss(&mut self, key: &K) {
+        self.lock().unwrap().record(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CacheType, Policy};
+
+    #[test]
+    fn test_full_sampling_gives_the_exact_curve() {
+        let mut profiler = ShardsProfiler::new(1.0);
+        for _ in 0..4 {
+            for key in 0..10 {
+                profiler.record(&key);
+            }
+        }
+
+        assert_eq!(profiler.sampled_accesses(), 40);
+        assert_eq!(profiler.cold_misses(), 10);
+        assert_eq!(profiler.estimated_distinct_keys(), 10);
+        assert_eq!(profiler.hit_ratio(9), 0.0);
+        assert_eq!(profiler.hit_ratio(10), 0.75);
+        assert_eq!(profiler.curve(&[5, 20]), vec![(5, 0.0), (20, 0.75)]);
+    }
+
+    #[test]
+    fn test_sampled_estimate_is_close() {
+        let mut profiler = ShardsProfiler::new(0.1);
+        for _ in 0..10 {
+            for key in 0..2_000 {
+                profiler.record(&key);
+            }
+        }
+
+        // Roughly a tenth of the accesses are seen, and a cyclic scan only fits whole
+        assert!(profiler.sampled_accesses() > 1_000 && profiler.sampled_accesses() < 3_000);
+        assert_eq!(profiler.hit_ratio(1_000), 0.0);
+        assert!((profiler.hit_ratio(4_000) - 0.9).abs() < 1e-9);
+        let distinct = profiler.estimated_distinct_keys();
+        assert!(distinct > 1_500 && distinct < 2_500, "{}", distinct);
+    }
+
+    #[test]
+    fn test_profiles_cache_lookups_as_an_observer() {
+        let profiler = Arc::new(Mutex::new(ShardsProfiler::new(1.0)));
+        let mut cache = CacheType::new(Policy::LRU, 1);
+        cache.set_observer(profiler.clone());
+
+        cache.get(&1);
+        cache.put(1, "one", 1);
+        cache.get(&1);
+        cache.peek(&1);
+
+        let profiler = profiler.lock().unwrap();
+        assert_eq!(profiler.sampled_accesses(), 2);
+        assert_eq!(profiler.hit_ratio(1), 0.5);
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use super::{KeySampler, StackDistances};
+use crate::observer::CacheObserver;
+
+// Reuse distances grouped into power-of-two buckets: bucket 0 holds distance 0 and bucket
+// `i` holds distances in `2^(i-1)..2^i`. Mostly short distances suit LRU; a workload whose
+// reuses spread far beyond the cache size is one where LFU's frequency counts pay off.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReuseHistogram {
+    counts: Vec<u64>,
+    cold_accesses: u64,
+}
+
+impl ReuseHistogram {
+    // Non-empty buckets, as the distances they cover and how many accesses fell in them.
+    pub fn buckets(&self) -> impl Iterator<Item = (Range<u64>, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(bucket, &count)| (bucket_range(bucket), count))
+    }
+
+    // Accesses to a key seen before.
+    pub fn reuses(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // First accesses to a key, which have no reuse distance.
+    pub fn cold_accesses(&self) -> u64 {
+        self.cold_accesses
+    }
+
+    pub fn accesses(&self) -> u64 {
+        self.reuses() + self.cold_accesses
+    }
+
+    // The fraction of all accesses whose whole bucket lies below `distance`, i.e. a lower
+    // bound on the hit ratio of an LRU cache with that many entries.
+    pub fn fraction_below(&self, distance: u64) -> f64 {
+        if self.accesses() == 0 {
+            return 0.0;
+        }
+        let below: u64 = self
+            .buckets()
+            .take_while(|(range, _)| range.end <= distance)
+            .map(|(_, count)| count)
+            .sum();
+        below as f64 / self.accesses() as f64
+    }
+
+    fn add(&mut self, distance: u64) {
+        let bucket = bucket_of(distance);
+        if self.counts.len() <= bucket {
+            self.counts.resize(bucket + 1, 0);
+        }
+        self.counts[bucket] += 1;
+    }
+}
+
+fn bucket_of(distance: u64) -> usize {
+    (64 - distance.leading_zeros()) as usize
+}
+
+fn bucket_range(bucket: usize) -> Range<u64> {
+    match bucket {
+        0 => 0..1,
+        bucket => {
+            let start = 1u64 << (bucket - 1);
+            start..start.saturating_mul(2)
+        }
+    }
+}
+
+// Records the LRU stack distance of every access, either inline on a live stream or from a
+// recorded trace. With a sample rate below one only a fraction of the keys is tracked and
+// distances are scaled up to estimate the full stream, as `ShardsProfiler` does.
+pub struct ReuseDistanceProfiler<K, S = RandomState> {
+    sampler: KeySampler<S>,
+    distances: StackDistances<K>,
+    histogram: ReuseHistogram,
+}
+
+impl<K: Hash + Eq + Clone> ReuseDistanceProfiler<K> {
+    // Tracks every access exactly.
+    pub fn new() -> Self {
+        ReuseDistanceProfiler::sampled(1.0)
+    }
+
+    pub fn sampled(sample_rate: f64) -> Self {
+        ReuseDistanceProfiler::with_hasher(sample_rate, RandomState::new())
+    }
+
+    pub fn from_trace<I: IntoIterator<Item = K>>(trace: I) -> Self {
+        let mut profiler = ReuseDistanceProfiler::new();
+        for key in trace {
+            profiler.record(&key);
+        }
+        profiler
+    }
+}
+
+impl<K: Hash + Eq + Clone> Default for ReuseDistanceProfiler<K> {
+    fn default() -> Self {
+        ReuseDistanceProfiler::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone, S: BuildHasher> ReuseDistanceProfiler<K, S> {
+    pub fn with_hasher(sample_rate: f64, hash_builder: S) -> Self {
+        ReuseDistanceProfiler {
+            sampler: KeySampler::new(sample_rate, hash_builder),
+            distances: StackDistances::new(),
+            histogram: ReuseHistogram::default(),
+        }
+    }
+
+    // Returns the reuse distance of this access, or `None` for the first access to a key
+    // and for keys outside the sample.
+    pub fn record(&mut self, key: &K) -> Option<u64> {
+        if !self.sampler.is_sampled(key) {
+            return None;
+        }
+        match self.distances.access(key) {
+            Some(distance) => {
+                let distance = (distance as f64 / self.sampler.rate()).round() as u64;
+                self.histogram.add(distance);
+                Some(distance)
+            }
+            None => {
+                self.histogram.cold_accesses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn histogram(&self) -> &ReuseHistogram {
+        &self.histogram
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sampler.rate()
+    }
+}
+
+// Shares a profiler with a cache, like the `ShardsProfiler` observer.
+impl<K, S> CacheObserver<K> for Arc<Mutex<ReuseDistanceProfiler<K, S>>>
+where
+    K: Hash + Eq + Clone,
+    S: BuildHasher,
+{
+    fn on_hit(&mut self, key: &K) {
+        self.lock().unwrap().record(key);
+    }
+
+    fn on_miss(&mut self, key: &K) {
+        self.lock().unwrap().record(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_ranges() {
+        assert_eq!(bucket_range(bucket_of(0)), 0..1);
+        assert_eq!(bucket_range(bucket_of(1)), 1..2);
+        assert_eq!(bucket_range(bucket_of(3)), 2..4);
+        assert_eq!(bucket_range(bucket_of(100)), 64..128);
+        assert_eq!(bucket_range(bucket_of(u64::MAX)).end, u64::MAX);
+    }
+
+    #[test]
+    fn test_histogram_from_a_trace() {
+        let profiler = ReuseDistanceProfiler::from_trace(vec!["a", "a", "b", "c", "a", "b"]);
+        let histogram = profiler.histogram();
+
+        // a: 0 then 2, b: 2
+        assert_eq!(
+            histogram.buckets().collect::<Vec<_>>(),
+            vec![(0..1, 1), (2..4, 2)]
+        );
+        assert_eq!(histogram.cold_accesses(), 3);
+        assert_eq!(histogram.accesses(), 6);
+        assert_eq!(histogram.fraction_below(1), 1.0 / 6.0);
+        assert_eq!(histogram.fraction_below(4), 0.5);
+    }
+
+    #[test]
+    fn test_record_returns_each_distance() {
+        let mut profiler = ReuseDistanceProfiler::new();
+        assert_eq!(profiler.record(&1), None);
+        assert_eq!(profiler.record(&2), None);
+        assert_eq!(profiler.record(&1), Some(1));
+        assert_eq!(profiler.record(&1), Some(0));
+    }
+
+    #[test]
+    fn test_sampled_distances_are_scaled() {
+        let mut profiler = ReuseDistanceProfiler::sampled(0.25);
+        for _ in 0..3 {
+            for key in 0..4_000 {
+                profiler.record(&key);
+            }
+        }
+
+        // A cyclic scan reuses every key at a distance of about 4,000
+        let histogram = profiler.histogram();
+        assert!(histogram.reuses() > 0);
+        assert_eq!(histogram.reuses(), 2 * histogram.cold_accesses());
+        let far: u64 = histogram
+            .buckets()
+            .filter(|(range, _)| range.start >= 2_048 && range.end <= 8_192)
+            .map(|(_, count)| count)
+            .sum();
+        assert_eq!(far, histogram.reuses());
+    }
+}
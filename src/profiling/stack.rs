@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// Exact LRU stack distances: the number of distinct other keys touched since a key's
+// previous access. A Fenwick tree over access times marks the latest access of every key,
+// so each distance is a prefix sum and costs O(log n) rather than a walk of the stack.
+pub(crate) struct StackDistances<K> {
+    last_access: HashMap<K, usize>,
+    tree: Vec<u64>,
+    now: usize,
+}
+
+impl<K: Hash + Eq + Clone> StackDistances<K> {
+    pub(crate) fn new() -> Self {
+        StackDistances {
+            last_access: HashMap::new(),
+            tree: vec![0; 65],
+            now: 0,
+        }
+    }
+
+    // Records an access and returns its distance, or `None` the first time a key is seen.
+    pub(crate) fn access(&mut self, key: &K) -> Option<u64> {
+        if self.now + 1 == self.tree.len() {
+            self.compact();
+        }
+        let now = self.now;
+        self.now += 1;
+
+        let distance = match self.last_access.get_mut(key) {
+            Some(previous) => {
+                let previous = std::mem::replace(previous, now);
+                // Every mark sits before `now`, so the marks after `previous` are the
+                // distinct keys accessed since
+                let distance = self.last_access.len() as u64 - self.prefix(previous);
+                self.update(previous, false);
+                Some(distance)
+            }
+            None => {
+                self.last_access.insert(key.clone(), now);
+                None
+            }
+        };
+        self.update(now, true);
+        distance
+    }
+
+    pub(crate) fn distinct_keys(&self) -> usize {
+        self.last_access.len()
+    }
+
+    // Renumbers the live marks from zero once the tree runs out of times, so memory
+    // follows the number of distinct keys rather than the length of the stream.
+    fn compact(&mut self) {
+        let mut times: Vec<usize> = self.last_access.values().cloned().collect();
+        times.sort_unstable();
+        for time in self.last_access.values_mut() {
+            *time = times.binary_search(time).expect("time is a live mark");
+        }
+
+        self.tree = vec![0; (2 * times.len()).max(64) + 1];
+        self.now = times.len();
+        for time in 0..self.now {
+            self.update(time, true);
+        }
+    }
+
+    // The number of marks at times up to and including `time`.
+    fn prefix(&self, time: usize) -> u64 {
+        let mut index = time + 1;
+        let mut sum = 0;
+        while index > 0 {
+            sum += self.tree[index];
+            index &= index - 1;
+        }
+        sum
+    }
+
+    fn update(&mut self, time: usize, mark: bool) {
+        let mut index = time + 1;
+        while index < self.tree.len() {
+            if mark {
+                self.tree[index] += 1;
+            } else {
+                self.tree[index] -= 1;
+            }
+            index += index & index.wrapping_neg();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Walks an explicit LRU stack, for comparison.
+    fn naive_distances(keys: &[u32]) -> Vec<Option<u64>> {
+        let mut stack: Vec<u32> = Vec::new();
+        keys.iter()
+            .map(|key| {
+                let distance = stack
+                    .iter()
+                    .rposition(|k| k == key)
+                    .map(|position| (stack.len() - 1 - position) as u64);
+                stack.retain(|k| k != key);
+                stack.push(*key);
+                distance
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_an_explicit_stack_across_compactions() {
+        // Long enough to compact several times
+        let keys: Vec<u32> = (0..2_000u32).map(|i| (i * i + i / 7) % 37).collect();
+        let mut distances = StackDistances::new();
+        let computed: Vec<_> = keys.iter().map(|key| distances.access(key)).collect();
+
+        assert_eq!(computed, naive_distances(&keys));
+        assert_eq!(distances.distinct_keys(), 37);
+    }
+
+    #[test]
+    fn test_repeated_key_has_distance_zero() {
+        let mut distances = StackDistances::new();
+        assert_eq!(distances.access(&"a"), None);
+        assert_eq!(distances.access(&"a"), Some(0));
+        assert_eq!(distances.access(&"b"), None);
+        assert_eq!(distances.access(&"a"), Some(1));
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+use crate::{Cache, CacheStats, CacheType};
+
+// Turns raw query strings into cache keys. Runs of whitespace always collapse to one
+// space and the ends are trimmed; lowercasing is on by default, and term sorting off,
+// since for most engines "rust cache" and "cache rust" rank differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Normalizer {
+    lowercase: bool,
+    sort_terms: bool,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Normalizer::new()
+    }
+}
+
+impl Normalizer {
+    pub fn new() -> Self {
+        Normalizer {
+            lowercase: true,
+            sort_terms: false,
+        }
+    }
+
+    pub fn lowercase(mut self, lowercase: bool) -> Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    // For engines that ignore term order, so every order of the same terms shares a key.
+    pub fn sort_terms(mut self, sort_terms: bool) -> Self {
+        self.sort_terms = sort_terms;
+        self
+    }
+
+    pub fn normalize(&self, query: &str) -> String {
+        let query = if self.lowercase {
+            query.to_lowercase()
+        } else {
+            query.to_string()
+        };
+        let mut terms: Vec<&str> = query.split_whitespace().collect();
+        if self.sort_terms {
+            terms.sort_unstable();
+        }
+        terms.join(" ")
+    }
+}
+
+// Caches search results under normalized queries, so that "Rust  Cache" and "rust cache"
+// share one entry. Keys handed back, e.g. by `pop_victim`, are the normalized forms.
+pub struct QueryResultCache<V, S = RandomState> {
+    cache: CacheType<String, V, S>,
+    normalizer: Normalizer,
+}
+
+impl<V, S: BuildHasher + Clone> QueryResultCache<V, S> {
+    pub fn new(cache: CacheType<String, V, S>) -> Self {
+        QueryResultCache::with_normalizer(cache, Normalizer::new())
+    }
+
+    // Entries already in the cache are expected to be normalized the same way.
+    pub fn with_normalizer(cache: CacheType<String, V, S>, normalizer: Normalizer) -> Self {
+        QueryResultCache { cache, normalizer }
+    }
+
+    pub fn normalizer(&self) -> Normalizer {
+        self.normalizer
+    }
+
+    // The key a query is cached under.
+    pub fn normalize(&self, query: &str) -> String {
+        self.normalizer.normalize(query)
+    }
+
+    pub fn get(&mut self, query: &str) -> Option<&V> {
+        let key = self.normalize(query);
+        self.cache.get(&key)
+    }
+
+    pub fn get_mut(&mut self, query: &str) -> Option<&mut V> {
+        let key = self.normalize(query);
+        self.cache.get_mut(&key)
+    }
+
+    pub fn peek(&self, query: &str) -> Option<&V> {
+        self.cache.peek(&self.normalize(query))
+    }
+
+    pub fn contains_key(&self, query: &str) -> bool {
+        self.cache.contains_key(&self.normalize(query))
+    }
+
+    pub fn put(&mut self, query: &str, value: V, weight: u32) {
+        let key = self.normalize(query);
+        self.cache.put(key, value, weight);
+    }
+
+    pub fn remove(&mut self, query: &str) -> Option<V> {
+        let key = self.normalize(query);
+        self.cache.remove(&key)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(String, V)> {
+        self.cache.pop_victim()
+    }
+
+    pub fn peek_victim(&self) -> Option<&String> {
+        self.cache.peek_victim()
+    }
+
+    // Keeps the entries `f` returns true for, given their normalized queries.
+    pub fn retain<F: FnMut(&str, &V) -> bool>(&mut self, mut f: F) {
+        self.cache.retain(|query, value| f(query, value));
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn inner(&self) -> &CacheType<String, V, S> {
+        &self.cache
+    }
+
+    pub fn into_inner(self) -> CacheType<String, V, S> {
+        self.cache
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+// Keys given through the trait are normalized too.
+impl<V, S: BuildHasher + Clone> Cache<String, V> for QueryResultCache<V, S> {
+    fn get(&mut self, key: &String) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &String) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &String) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &String) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: String, value: V, weight: u32) {
+        self.put(&key, value, weight)
+    }
+
+    fn remove(&mut self, key: &String) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(String, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&String> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Policy;
+
+    #[test]
+    fn test_spellings_of_a_query_share_an_entry() {
+        let mut cache = QueryResultCache::new(CacheType::new(Policy::LRU, 4));
+        cache.put("  Rust   Cache\t", vec![1, 2], 1);
+        assert_eq!(cache.get("rust cache"), Some(&vec![1, 2]));
+        assert_eq!(cache.get("RUST\ncache"), Some(&vec![1, 2]));
+        assert_eq!(cache.get("cache rust"), None);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.peek_victim().map(String::as_str), Some("rust cache"));
+        assert_eq!(cache.remove(" rust CACHE"), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn test_normalizer_options() {
+        let sorting = Normalizer::new().sort_terms(true);
+        assert_eq!(sorting.normalize("Cache rust  LRU"), "cache lru rust");
+        let keeping_case = Normalizer::new().lowercase(false);
+        assert_eq!(keeping_case.normalize(" Rust  Cache "), "Rust Cache");
+        assert_eq!(Normalizer::default().normalize("   "), "");
+
+        let mut cache = QueryResultCache::with_normalizer(CacheType::new(Policy::FIFO, 4), sorting);
+        cache.put("rust cache", 7, 1);
+        assert!(cache.contains_key("Cache Rust"));
+    }
+}
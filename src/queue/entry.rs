@@ -0,0 +1,111 @@
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+use super::{Order, QueueCache};
+
+pub enum Entry<'a, K, V, S, O> {
+    Occupied(OccupiedEntry<'a, K, V, S, O>),
+    Vacant(VacantEntry<'a, K, V, S, O>),
+}
+
+pub struct OccupiedEntry<'a, K, V, S, O> {
+    pub(super) cache: &'a mut QueueCache<K, V, S, O>,
+    pub(super) idx: usize,
+}
+
+pub struct VacantEntry<'a, K, V, S, O> {
+    pub(super) cache: &'a mut QueueCache<K, V, S, O>,
+    pub(super) key: K,
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher, O: Order> Entry<'a, K, V, S, O> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, value: V, weight: u32) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(value, weight),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, weight: u32, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(f(), weight),
+        }
+    }
+
+    // Computes both the value and its weight from the key on a miss.
+    pub fn or_insert_with_key<F: FnOnce(&K) -> (V, u32)>(self, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let (value, weight) = f(entry.key());
+                entry.insert(value, weight)
+            }
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher, O: Order> OccupiedEntry<'a, K, V, S, O> {
+    pub fn key(&self) -> &K {
+        &self.cache.node(self.idx).key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.cache.node(self.idx).value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.cache.node_mut(self.idx).value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.cache.node_mut(self.idx).value
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        let now = self.cache.clock.now();
+        let cache = &mut *self.cache;
+        let node = &mut cache.nodes[self.idx];
+        node.timestamps.rewrite(now);
+        if let Some(observer) = cache.observer.as_mut() {
+            observer.on_update(&node.key);
+        }
+        mem::replace(&mut node.value, value)
+    }
+
+    pub fn remove(self) -> V {
+        self.cache.remove_at(self.idx).value
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher, O: Order> VacantEntry<'a, K, V, S, O> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V, weight: u32) -> &'a mut V {
+        let idx = self.cache.insert_new(self.key, value, weight);
+        &mut self.cache.node_mut(idx).value
+    }
+}
@@ -0,0 +1,763 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::expiry::{Expiration, Freshness, Timestamps, TimingWheel, WHEEL_RESOLUTION};
+use crate::observer::{BoxedObserver, CacheObserver};
+use crate::stats::{CacheStats, EntryInfo};
+use crate::trace;
+use crate::util::{KeyIndex, Keyed, Link, Slab, SlabKey};
+use crate::{Cache, EvictionListener, NewCache, Policy, Weigher};
+
+mod entry;
+mod persist;
+
+pub use self::entry::{Entry, OccupiedEntry, VacantEntry};
+
+// The end of the queue an order takes its victims from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum End {
+    // The entry inserted, or with a renewing order used, most recently
+    Newest,
+    Oldest,
+}
+
+// What sets one queue policy apart from another; everything else is shared by `QueueCache`.
+pub trait Order {
+    const POLICY: Policy;
+    // The policy name traces are tagged with
+    const NAME: &'static str;
+    const VICTIM: End;
+    // Whether reading or updating a key moves it to the newest end
+    const RENEWS_ON_USE: bool;
+}
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    weight: u32,
+    timestamps: Timestamps,
+    prev: Link,
+    next: Link,
+}
+
+impl<K, V> Keyed for Node<K, V> {
+    type Key = K;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    nodes: &'a Slab<Node<K, V>>,
+    next: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.nodes.get_at(self.next?)?;
+        self.next = node.next.get();
+        self.remaining -= 1;
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+pub struct IntoIter<K, V> {
+    nodes: Slab<Node<K, V>>,
+    next: Option<usize>,
+    remaining: usize,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.nodes.remove(self.next?);
+        self.next = node.next.get();
+        self.remaining -= 1;
+        Some((node.key, node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+// A doubly linked queue of entries, newest at the head, evicted from the end `O` picks.
+// `FIFOCache` and `MRUCache` are its two orders.
+pub struct QueueCache<K, V, S, O> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    map: KeyIndex<S>,
+    nodes: Slab<Node<K, V>>,
+    head: Link,
+    tail: Link,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    weigher: Option<Weigher<K, V>>,
+    observer: Option<BoxedObserver<K>>,
+    stats: CacheStats,
+    expiration: Expiration,
+    expiry_wheel: Option<TimingWheel<SlabKey>>,
+    clock: Arc<dyn Clock>,
+    order: PhantomData<O>,
+}
+
+impl<K: Hash + Eq, V, O: Order> QueueCache<K, V, RandomState, O> {
+    pub fn new(capacity: usize) -> Self {
+        QueueCache::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher, O: Order> QueueCache<K, V, S, O> {
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        QueueCache {
+            capacity,
+            max_weight: None,
+            map: KeyIndex::with_hasher(hash_builder),
+            nodes: Slab::with_capacity(capacity),
+            head: Link::NONE,
+            tail: Link::NONE,
+            eviction_listener: None,
+            weigher: None,
+            observer: None,
+            stats: CacheStats::default(),
+            expiration: Expiration::default(),
+            expiry_wheel: None,
+            clock: Arc::new(SystemClock),
+            order: PhantomData,
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let _span = trace::get_span(O::NAME, key);
+        let idx = self.live_index(key)?;
+        self.renew(idx);
+        Some(&self.node(idx).value)
+    }
+
+    pub fn get_stale(&mut self, key: &K) -> Option<Freshness<&V>> {
+        let now = self.clock.now();
+        match self.index_of(key) {
+            Some(idx) if self.is_expired(idx, now) => {
+                self.stats.misses += 1;
+                trace::miss(O::NAME, key);
+                self.notify(|observer| observer.on_miss(key));
+                Some(Freshness::Stale(&self.node(idx).value))
+            }
+            _ => self.get(key).map(Freshness::Fresh),
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let idx = self.live_index(key)?;
+        self.renew(idx);
+        Some(&mut self.node_mut(idx).value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        let idx = self.index_of(key)?;
+        if self.is_expired(idx, self.clock.now()) {
+            return None;
+        }
+        Some(&self.node(idx).value)
+    }
+
+    // Like `peek`, this does not count as a use of the key.
+    pub fn entry_info(&self, key: &K) -> Option<EntryInfo> {
+        let idx = self.index_of(key)?;
+        if self.is_expired(idx, self.clock.now()) {
+            return None;
+        }
+        let node = self.node(idx);
+        Some(EntryInfo::new(
+            &node.timestamps,
+            node.weight,
+            &self.expiration,
+        ))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+
+    // An update keeps the key's place in the queue unless the order renews used keys. The
+    // weight does not affect the order; it only counts towards `current_weight` and the
+    // weight budget. A new key goes in after the eviction it causes, so it is not its own
+    // victim.
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        let _span = trace::put_span(O::NAME, &key, weight);
+        if let Some(idx) = self.index_of(&key) {
+            let now = self.clock.now();
+            let node = self.node_mut(idx);
+            let old_weight = node.weight;
+            node.value = value;
+            node.weight = weight;
+            node.timestamps.rewrite(now);
+            self.stats.current_weight -= u64::from(old_weight);
+            self.stats.current_weight += u64::from(weight);
+            self.renew(idx);
+            // A heavier value can push the cache over its budget; the key itself is never
+            // evicted to make room for its own update
+            while self.len() > 1 && self.is_over_budget() {
+                let victim = match self.victim_index() {
+                    Some(victim) if victim == idx => self.inward(idx),
+                    victim => victim,
+                };
+                if let Some(victim) = victim {
+                    self.evict_at(victim);
+                }
+            }
+            self.notify(|observer| observer.on_update(&key));
+        } else {
+            self.insert_new(key, value, weight);
+        }
+    }
+
+    // Stores the value with the weight the weigher gives it, or a weight of 1 without one.
+    pub fn insert(&mut self, key: K, value: V) {
+        let weight = self
+            .weigher
+            .as_ref()
+            .map_or(1, |weigher| weigher(&key, &value));
+        self.put(key, value, weight);
+    }
+
+    // An occupied entry counts as a hit and a use, just like `get`.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S, O> {
+        match self.live_index(&key) {
+            Some(idx) => {
+                self.renew(idx);
+                Entry::Occupied(OccupiedEntry { cache: self, idx })
+            }
+            None => Entry::Vacant(VacantEntry { cache: self, key }),
+        }
+    }
+
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, weight: u32, f: F) -> &V {
+        self.entry(key).or_insert_with(weight, f)
+    }
+
+    // A failed computation leaves the cache untouched and returns the error.
+    pub fn try_get_or_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        weight: u32,
+        f: F,
+    ) -> Result<&V, E> {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(f()?, weight)),
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let idx = self.index_of(key)?;
+        Some(self.remove_at(idx).value)
+    }
+
+    // The entry `pop_victim` would remove, left in place.
+    pub fn peek_victim(&self) -> Option<&K> {
+        Some(&self.node(self.victim_index()?).key)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        let victim = self.victim_index()?;
+        let node = self.remove_at(victim);
+        Some((node.key, node.value))
+    }
+
+    // Evicts entries as a full cache would, listener and stats included, until at most
+    // `len` are left. Returns how many were evicted.
+    pub fn evict_to(&mut self, len: usize) -> usize {
+        let mut evicted = 0;
+        while self.len() > len {
+            self.evict();
+            evicted += 1;
+        }
+        evicted
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        let doomed: Vec<usize> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| !f(&node.key, &node.value))
+            .map(|(idx, _)| idx)
+            .collect();
+
+        for idx in doomed {
+            self.remove_at(idx);
+        }
+    }
+
+    pub fn set_expire_after_write(&mut self, time_to_live: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_live = Some(time_to_live);
+        self.set_expiration(expiration);
+    }
+
+    pub fn set_expire_after_access(&mut self, time_to_idle: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_idle = Some(time_to_idle);
+        self.set_expiration(expiration);
+    }
+
+    // Existing entries are scheduled against the new limits.
+    pub(crate) fn set_expiration(&mut self, expiration: Expiration) {
+        self.expiration = expiration;
+        self.expiry_wheel = if expiration.is_enabled() {
+            Some(TimingWheel::new(self.clock.now(), WHEEL_RESOLUTION))
+        } else {
+            None
+        };
+        let live: Vec<usize> = self.nodes.iter().map(|(idx, _)| idx).collect();
+        for idx in live {
+            self.schedule_expiry(idx);
+        }
+    }
+
+    // Replaces the time source; entries keep the timestamps they were given by the old one.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+        let expiration = self.expiration;
+        self.set_expiration(expiration);
+    }
+
+    pub fn expire_due(&mut self, now: Instant) -> usize {
+        let due = match self.expiry_wheel.as_mut() {
+            Some(wheel) => wheel.advance(now),
+            None => return 0,
+        };
+
+        let mut expired = 0;
+        for slot in due {
+            let idx = match self.nodes.get(slot) {
+                Some(_) => slot.index(),
+                None => continue,
+            };
+            if self.is_expired(idx, now) {
+                self.remove_at(idx);
+                expired += 1;
+            } else {
+                // Touched or rewritten since it was scheduled
+                self.schedule_expiry(idx);
+            }
+        }
+        expired
+    }
+
+    pub fn set_max_weight(&mut self, max_weight: u64) {
+        self.max_weight = Some(max_weight);
+        while !self.is_empty() && self.is_over_budget() {
+            self.evict();
+        }
+    }
+
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.set_boxed_eviction_listener(Box::new(listener));
+    }
+
+    pub(crate) fn set_boxed_eviction_listener(&mut self, listener: EvictionListener<K, V>) {
+        self.eviction_listener = Some(listener);
+    }
+
+    // Computes the weight of entries stored with `insert`.
+    pub fn set_weigher<F: Fn(&K, &V) -> u32 + Send + 'static>(&mut self, weigher: F) {
+        self.set_boxed_weigher(Box::new(weigher));
+    }
+
+    pub(crate) fn set_boxed_weigher(&mut self, weigher: Weigher<K, V>) {
+        self.weigher = Some(weigher);
+    }
+
+    pub fn set_observer<T: CacheObserver<K> + Send + 'static>(&mut self, observer: T) {
+        self.set_boxed_observer(Box::new(observer));
+    }
+
+    pub(crate) fn set_boxed_observer(&mut self, observer: BoxedObserver<K>) {
+        self.observer = Some(observer);
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.nodes.clear();
+        self.head = Link::NONE;
+        self.tail = Link::NONE;
+        self.stats.current_weight = 0;
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.clear();
+        }
+    }
+
+    // Moves entries out of the slots freed by removals, so the node storage and the map
+    // can release the memory they no longer need.
+    pub fn shrink_to_fit(&mut self) {
+        for (from, to) in self.nodes.compaction_moves() {
+            self.relocate(from, to);
+        }
+        self.nodes.shrink_to_fit();
+        self.map.shrink_to_fit(&self.nodes);
+    }
+
+    // Reserves room for a full cache, so once it is warm, evicting to make room for new
+    // entries reuses memory instead of allocating.
+    pub fn preallocate(&mut self) {
+        self.nodes.reserve(self.capacity);
+        self.map.reserve(self.capacity, &self.nodes);
+    }
+
+    // Entries from the newest end of the queue to the oldest.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            nodes: &self.nodes,
+            next: self.head.get(),
+            remaining: self.map.len(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    fn insert_new(&mut self, key: K, value: V, weight: u32) -> usize {
+        self.make_room(weight);
+
+        self.stats.insertions += 1;
+        self.stats.current_weight += u64::from(weight);
+        trace::insert(O::NAME, &key, weight);
+        self.notify(|observer| observer.on_insert(&key));
+        let node = Node {
+            key,
+            value,
+            weight,
+            timestamps: Timestamps::new(self.clock.now()),
+            prev: Link::NONE,
+            next: Link::NONE,
+        };
+        let slot = self.nodes.insert(node);
+        let idx = slot.index();
+        self.map.insert(slot, &self.nodes);
+        self.add_to_front(idx);
+        self.schedule_expiry(idx);
+        idx
+    }
+
+    // Whether an entry of this weight only fits after an eviction.
+    fn is_full(&self, weight: u32) -> bool {
+        match self.max_weight {
+            Some(max_weight) => self.stats.current_weight + u64::from(weight) > max_weight,
+            None => self.len() >= self.capacity,
+        }
+    }
+
+    fn is_over_budget(&self) -> bool {
+        self.max_weight
+            .is_some_and(|max_weight| self.stats.current_weight > max_weight)
+    }
+
+    fn make_room(&mut self, weight: u32) {
+        while !self.is_empty() && self.is_full(weight) {
+            self.evict();
+        }
+    }
+
+    // Looks up a key, dropping it instead if it has expired, and records the access.
+    // This is the single place hits and misses are observed.
+    fn live_index(&mut self, key: &K) -> Option<usize> {
+        let now = self.clock.now();
+        let idx = match self.index_of(key) {
+            Some(idx) if self.is_expired(idx, now) => {
+                self.remove_at(idx);
+                None
+            }
+            found => found,
+        };
+        match idx {
+            Some(idx) => {
+                self.node_mut(idx).timestamps.touch(now);
+                self.stats.hits += 1;
+                self.notify(|observer| observer.on_hit(key));
+            }
+            None => {
+                self.stats.misses += 1;
+                trace::miss(O::NAME, key);
+                self.notify(|observer| observer.on_miss(key));
+            }
+        }
+        idx
+    }
+
+    fn is_expired(&self, idx: usize, now: Instant) -> bool {
+        self.expiration.is_expired(&self.node(idx).timestamps, now)
+    }
+
+    fn schedule_expiry(&mut self, idx: usize) {
+        let node = &self.nodes[idx];
+        if let (Some(wheel), Some(deadline)) = (
+            self.expiry_wheel.as_mut(),
+            self.expiration.deadline(&node.timestamps),
+        ) {
+            wheel.schedule(self.nodes.key_at(idx), deadline);
+        }
+    }
+
+    fn remove_at(&mut self, idx: usize) -> Node<K, V> {
+        self.detach(idx);
+        let slot = self.nodes.key_at(idx);
+        let node = self.nodes.remove(idx);
+        self.map.remove(&node.key, idx);
+        self.stats.current_weight -= u64::from(node.weight);
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.cancel(&slot);
+        }
+        node
+    }
+
+    fn notify<F: FnOnce(&mut dyn CacheObserver<K>)>(&mut self, f: F) {
+        if let Some(observer) = self.observer.as_mut() {
+            f(&mut **observer);
+        }
+    }
+
+    fn evict(&mut self) {
+        if let Some(victim) = self.victim_index() {
+            self.evict_at(victim);
+        }
+    }
+
+    fn victim_index(&self) -> Option<usize> {
+        match O::VICTIM {
+            End::Newest => self.head.get(),
+            End::Oldest => self.tail.get(),
+        }
+    }
+
+    // The neighbour of `idx` one step further from the victim end.
+    fn inward(&self, idx: usize) -> Option<usize> {
+        let node = self.node(idx);
+        match O::VICTIM {
+            End::Newest => node.next.get(),
+            End::Oldest => node.prev.get(),
+        }
+    }
+
+    fn renew(&mut self, idx: usize) {
+        if O::RENEWS_ON_USE && self.head != Link::some(idx) {
+            self.detach(idx);
+            self.add_to_front(idx);
+        }
+    }
+
+    // Capacity-driven eviction; the listener sees every entry dropped this way.
+    fn evict_at(&mut self, idx: usize) {
+        let node = self.remove_at(idx);
+        self.stats.evictions += 1;
+        trace::eviction(O::NAME, &node.key);
+        if let Some(listener) = self.eviction_listener.as_mut() {
+            listener(node.key, node.value);
+        }
+    }
+
+    fn node(&self, idx: usize) -> &Node<K, V> {
+        &self.nodes[idx]
+    }
+
+    fn node_mut(&mut self, idx: usize) -> &mut Node<K, V> {
+        &mut self.nodes[idx]
+    }
+
+    fn index_of(&self, key: &K) -> Option<usize> {
+        self.map.get(key, &self.nodes).map(SlabKey::index)
+    }
+
+    // Moves a node into a free slot, repointing its neighbours and its map entry.
+    fn relocate(&mut self, from: usize, to: usize) {
+        let old = self.nodes.key_at(from);
+        let slot = self.nodes.relocate(from, to);
+        let (prev, next) = (self.nodes[to].prev, self.nodes[to].next);
+        match prev.get() {
+            Some(p) => self.node_mut(p).next = Link::some(to),
+            None => self.head = Link::some(to),
+        }
+        match next.get() {
+            Some(n) => self.node_mut(n).prev = Link::some(to),
+            None => self.tail = Link::some(to),
+        }
+        self.map.repoint(&self.nodes[to].key, from, slot);
+        if self
+            .expiry_wheel
+            .as_mut()
+            .is_some_and(|wheel| wheel.cancel(&old))
+        {
+            self.schedule_expiry(to);
+        }
+    }
+
+    fn detach(&mut self, idx: usize) {
+        let node = self.node(idx);
+        let prev = node.prev;
+        let next = node.next;
+
+        match prev.get() {
+            Some(p) => self.node_mut(p).next = next,
+            None => self.head = next,
+        }
+
+        match next.get() {
+            Some(n) => self.node_mut(n).prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    fn add_to_front(&mut self, idx: usize) {
+        let head = self.head;
+        let node = self.node_mut(idx);
+        node.prev = Link::NONE;
+        node.next = head;
+
+        if let Some(old_head) = head.get() {
+            self.node_mut(old_head).prev = Link::some(idx);
+        }
+
+        self.head = Link::some(idx);
+
+        if self.tail.is_none() {
+            self.tail = Link::some(idx);
+        }
+    }
+}
+
+impl<K, V, S, O> IntoIterator for QueueCache<K, V, S, O> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter {
+            remaining: self.map.len(),
+            nodes: self.nodes,
+            next: self.head.get(),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher, O: Order> IntoIterator for &'a QueueCache<K, V, S, O> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+// The capacity is sized to hold every collected entry.
+impl<K, V, S, O> FromIterator<(K, V, u32)> for QueueCache<K, V, S, O>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+    O: Order,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V, u32)>>(iter: I) -> Self {
+        let entries: Vec<_> = iter.into_iter().collect();
+        let mut cache = QueueCache::with_hasher(entries.len().max(1), S::default());
+        cache.extend(entries);
+        cache
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher, O: Order> Extend<(K, V, u32)> for QueueCache<K, V, S, O> {
+    fn extend<I: IntoIterator<Item = (K, V, u32)>>(&mut self, iter: I) {
+        for (key, value, weight) in iter {
+            self.put(key, value, weight);
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher, O: Order> Cache<K, V> for QueueCache<K, V, S, O> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K, V, S, O> NewCache<K, V> for QueueCache<K, V, S, O>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+    O: Order,
+{
+    fn new(capacity: usize) -> Self {
+        QueueCache::with_hasher(capacity, S::default())
+    }
+}
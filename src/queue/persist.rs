@@ -0,0 +1,162 @@
+use std::hash::{BuildHasher, Hash};
+use std::io::{Read, Write};
+
+use super::{End, Node, Order, QueueCache};
+use crate::expiry::SavedTimestamps;
+use crate::snapshot::{self, impl_codec, Decode, Encode, SnapshotError, SnapshotReader};
+use crate::stats::CacheStats;
+use crate::util::{self, Link};
+
+// What is saved of a cache, by serde and by snapshots alike. Entries run from the oldest
+// to the newest, so restoring them in order rebuilds the queue.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Saved<K, V> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    stats: CacheStats,
+    entries: Vec<SavedEntry<K, V>>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct SavedEntry<K, V> {
+    key: K,
+    value: V,
+    weight: u32,
+    timestamps: SavedTimestamps,
+}
+
+impl_codec!(Saved<K, V> {
+    capacity,
+    max_weight,
+    stats,
+    entries,
+});
+impl_codec!(SavedEntry<K, V> {
+    key,
+    value,
+    weight,
+    timestamps,
+});
+
+impl<K, V, S, O> QueueCache<K, V, S, O> {
+    fn save(&self) -> Saved<&K, &V> {
+        let now = self.clock.now();
+        let mut entries = Vec::with_capacity(self.map.len());
+        let mut next = self.tail.get();
+        while let Some(idx) = next {
+            let node = &self.nodes[idx];
+            entries.push(SavedEntry {
+                key: &node.key,
+                value: &node.value,
+                weight: node.weight,
+                timestamps: SavedTimestamps::save(&node.timestamps, now),
+            });
+            next = node.prev.get();
+        }
+        Saved {
+            capacity: self.capacity,
+            max_weight: self.max_weight,
+            stats: self.stats,
+            entries,
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Default, O: Order> QueueCache<K, V, S, O> {
+    fn restore(saved: Saved<K, V>) -> Result<Self, &'static str> {
+        snapshot::check_len(saved.capacity, saved.max_weight, saved.entries.len())?;
+        let mut cache = QueueCache::with_hasher(saved.capacity, S::default());
+        cache.max_weight = saved.max_weight;
+        cache.stats = saved.stats;
+        cache.stats.current_weight = 0;
+        let now = cache.clock.now();
+        for entry in saved.entries {
+            if cache.index_of(&entry.key).is_some() {
+                return Err("duplicate key");
+            }
+            cache.stats.current_weight += u64::from(entry.weight);
+            let slot = cache.nodes.insert(Node {
+                key: entry.key,
+                value: entry.value,
+                weight: entry.weight,
+                timestamps: entry.timestamps.restore(now),
+                prev: Link::NONE,
+                next: Link::NONE,
+            });
+            cache.map.insert(slot, &cache.nodes);
+            cache.add_to_front(slot.index());
+        }
+        Ok(cache)
+    }
+}
+
+impl<K: Encode, V: Encode, S, O: Order> QueueCache<K, V, S, O> {
+    // Writes the entries and their queue order as a checksummed binary snapshot.
+    pub fn save_to<W: Write>(&self, writer: W) -> Result<(), SnapshotError> {
+        snapshot::write(writer, O::POLICY, &self.save())
+    }
+}
+
+impl<K, V, S, O> QueueCache<K, V, S, O>
+where
+    K: Hash + Eq + Decode,
+    V: Decode,
+    S: BuildHasher + Default,
+    O: Order,
+{
+    pub fn load_from<R: Read>(reader: R) -> Result<Self, SnapshotError> {
+        QueueCache::load_snapshot(SnapshotReader::open(reader)?)
+    }
+
+    pub(crate) fn load_snapshot<R: Read>(
+        snapshot: SnapshotReader<R>,
+    ) -> Result<Self, SnapshotError> {
+        let saved = snapshot.body(O::POLICY)?;
+        QueueCache::restore(saved).map_err(SnapshotError::Invalid)
+    }
+}
+
+impl<K: Clone + Hash + Eq, V: Clone, S: BuildHasher, O: Order> QueueCache<K, V, S, O> {
+    // The `n` entries that would be evicted last, the last of them first, with their
+    // weights.
+    pub fn export_top(&self, n: usize) -> Vec<(K, V, u32)> {
+        let mut entries: Vec<_> = self.iter().collect();
+        if O::VICTIM == End::Newest {
+            entries.reverse();
+        }
+        util::top_entries(entries.into_iter(), n, |key| self.entry_info(key))
+    }
+
+    // Stores exported entries, hottest first, so the hottest is put furthest from the
+    // victim end.
+    pub fn import<I: IntoIterator<Item = (K, V, u32)>>(&mut self, entries: I) {
+        let mut entries: Vec<_> = entries.into_iter().collect();
+        if O::VICTIM == End::Oldest {
+            entries.reverse();
+        }
+        for (key, value, weight) in entries {
+            self.put(key, value, weight);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K: serde::Serialize, V: serde::Serialize, S, O> serde::Serialize for QueueCache<K, V, S, O> {
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        serde::Serialize::serialize(&self.save(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, S, O> serde::Deserialize<'de> for QueueCache<K, V, S, O>
+where
+    K: Hash + Eq + serde::Deserialize<'de>,
+    V: serde::Deserialize<'de>,
+    S: BuildHasher + Default,
+    O: Order,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = serde::Deserialize::deserialize(deserializer)?;
+        QueueCache::restore(saved).map_err(serde::de::Error::custom)
+    }
+}
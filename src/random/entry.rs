@@ -0,0 +1,161 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+use super::RandomCache;
+
+pub enum Entry<'a, K, V, S = RandomState> {
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+pub struct OccupiedEntry<'a, K, V, S = RandomState> {
+    pub(super) cache: &'a mut RandomCache<K, V, S>,
+    pub(super) idx: usize,
+}
+
+pub struct VacantEntry<'a, K, V, S = RandomState> {
+    pub(super) cache: &'a mut RandomCache<K, V, S>,
+    pub(super) key: K,
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher> Entry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, value: V, weight: u32) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(value, weight),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, weight: u32, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(f(), weight),
+        }
+    }
+
+    // Computes both the value and its weight from the key on a miss.
+    pub fn or_insert_with_key<F: FnOnce(&K) -> (V, u32)>(self, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let (value, weight) = f(entry.key());
+                entry.insert(value, weight)
+            }
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher> OccupiedEntry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.cache.node(self.idx).key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.cache.node(self.idx).value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.cache.node_mut(self.idx).value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.cache.node_mut(self.idx).value
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        let now = self.cache.clock.now();
+        let cache = &mut *self.cache;
+        let node = &mut cache.nodes[self.idx];
+        node.timestamps.rewrite(now);
+        if let Some(observer) = cache.observer.as_mut() {
+            observer.on_update(&node.key);
+        }
+        mem::replace(&mut node.value, value)
+    }
+
+    pub fn remove(self) -> V {
+        self.cache.remove_at(self.idx).value
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher> VacantEntry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V, weight: u32) -> &'a mut V {
+        let idx = self.cache.insert_new(self.key, value, weight);
+        &mut self.cache.node_mut(idx).value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::RandomCache;
+    use super::Entry;
+
+    #[test]
+    fn test_or_insert_and_modify() {
+        let mut cache = RandomCache::new(2);
+
+        *cache.entry(1).or_insert(0, 0) += 1;
+        cache.entry(1).and_modify(|v| *v += 10).or_insert(0, 0);
+
+        assert_eq!(cache.get(&1), Some(&11));
+        assert_eq!(cache.stats().hits, 2);
+    }
+
+    #[test]
+    fn test_occupied_insert_and_remove() {
+        let mut cache = RandomCache::new(2);
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+
+        match cache.entry(1) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.insert("ONE"), "one");
+                assert_eq!(entry.remove(), "ONE");
+            }
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        assert_eq!(cache.peek(&2), Some(&"two"));
+    }
+
+    #[test]
+    fn test_vacant_insert_evicts() {
+        let mut cache = RandomCache::new(1);
+        cache.put(1, "one", 0);
+
+        match cache.entry(2) {
+            Entry::Vacant(entry) => {
+                assert_eq!(entry.key(), &2);
+                assert_eq!(*entry.insert("two", 0), "two");
+            }
+            Entry::Occupied(_) => panic!("expected a vacant entry"),
+        }
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,837 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::expiry::{Expiration, Freshness, Timestamps, TimingWheel, WHEEL_RESOLUTION};
+use crate::observer::{BoxedObserver, CacheObserver};
+use crate::stats::{CacheStats, EntryInfo};
+use crate::trace;
+use crate::{Cache, EvictionListener, NewCache, Weigher};
+
+mod entry;
+mod persist;
+
+const POLICY: &str = "random";
+
+pub use self::entry::{Entry, OccupiedEntry, VacantEntry};
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    weight: u32,
+    timestamps: Timestamps,
+}
+
+pub struct Iter<'a, K, V> {
+    nodes: std::slice::Iter<'a, Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nodes.next().map(|node| (&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.nodes.size_hint()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+pub struct IntoIter<K, V> {
+    nodes: std::vec::IntoIter<Node<K, V>>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nodes.next().map(|node| (node.key, node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.nodes.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+// xorshift64*: cheap, and plenty for picking victims. Not for anything security related.
+#[derive(Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        // The all-zero state would only ever produce zeros
+        Rng(if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        })
+    }
+
+    fn next_state(&self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        x
+    }
+
+    // The next number, without consuming it.
+    pub(crate) fn peek(&self) -> u64 {
+        self.next_state().wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub(crate) fn advance(&mut self) {
+        self.0 = self.next_state();
+    }
+
+    // A uniform draw in [0, 1), from the top 53 bits of the next number.
+    pub(crate) fn next_unit(&mut self) -> f64 {
+        let draw = (self.peek() >> 11) as f64 / (1u64 << 53) as f64;
+        self.advance();
+        draw
+    }
+}
+
+// Evicts an entry chosen uniformly at random, ignoring every access. Like FIFO, a baseline for
+// comparing the other policies against; `set_seed` makes runs reproducible.
+pub struct RandomCache<K, V, S = RandomState> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    map: HashMap<K, usize, S>,
+    // Densely packed, so a victim is one random index away
+    nodes: Vec<Node<K, V>>,
+    rng: Rng,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    weigher: Option<Weigher<K, V>>,
+    observer: Option<BoxedObserver<K>>,
+    stats: CacheStats,
+    expiration: Expiration,
+    expiry_wheel: Option<TimingWheel<K>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K: Clone + Hash + Eq, V> RandomCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        RandomCache::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher> RandomCache<K, V, S> {
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        RandomCache {
+            capacity,
+            max_weight: None,
+            map: HashMap::with_hasher(hash_builder),
+            nodes: Vec::with_capacity(capacity),
+            rng: Rng::new(RandomState::new().hash_one(capacity)),
+            eviction_listener: None,
+            weigher: None,
+            observer: None,
+            stats: CacheStats::default(),
+            expiration: Expiration::default(),
+            expiry_wheel: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let _span = trace::get_span(POLICY, key);
+        let idx = self.live_index(key)?;
+        Some(&self.node(idx).value)
+    }
+
+    pub fn get_stale(&mut self, key: &K) -> Option<Freshness<&V>> {
+        let now = self.clock.now();
+        match self.map.get(key).cloned() {
+            Some(idx) if self.is_expired(idx, now) => {
+                self.stats.misses += 1;
+                trace::miss(POLICY, key);
+                self.notify(|observer| observer.on_miss(key));
+                Some(Freshness::Stale(&self.node(idx).value))
+            }
+            _ => self.get(key).map(Freshness::Fresh),
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let idx = self.live_index(key)?;
+        Some(&mut self.node_mut(idx).value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        let idx = *self.map.get(key)?;
+        if self.is_expired(idx, self.clock.now()) {
+            return None;
+        }
+        Some(&self.node(idx).value)
+    }
+
+    // Like `peek`, this does not count as a use of the key.
+    pub fn entry_info(&self, key: &K) -> Option<EntryInfo> {
+        let idx = *self.map.get(key)?;
+        if self.is_expired(idx, self.clock.now()) {
+            return None;
+        }
+        let node = self.node(idx);
+        Some(EntryInfo::new(
+            &node.timestamps,
+            node.weight,
+            &self.expiration,
+        ))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+
+    // The weight does not affect which entry is evicted; it only counts towards
+    // `current_weight` and the weight budget.
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        let _span = trace::put_span(POLICY, &key, weight);
+        if let Some(&idx) = self.map.get(&key) {
+            let now = self.clock.now();
+            let node = self.node_mut(idx);
+            let old_weight = node.weight;
+            node.value = value;
+            node.weight = weight;
+            node.timestamps.rewrite(now);
+            self.stats.current_weight -= u64::from(old_weight);
+            self.stats.current_weight += u64::from(weight);
+            // A heavier value can push the cache over its budget; the key itself is never
+            // evicted to make room for its own update
+            while self.len() > 1 && self.is_over_budget() {
+                // Evictions move entries, so the key's slot is looked up again
+                let idx = self.map[&key];
+                let victim = self.victim_index().expect("the cache is not empty");
+                self.rng.advance();
+                if victim == idx {
+                    // Take its neighbour instead
+                    self.evict_at((victim + 1) % self.nodes.len());
+                } else {
+                    self.evict_at(victim);
+                }
+            }
+            self.notify(|observer| observer.on_update(&key));
+        } else {
+            self.insert_new(key, value, weight);
+        }
+    }
+
+    // Stores the value with the weight the weigher gives it, or a weight of 1 without one.
+    pub fn insert(&mut self, key: K, value: V) {
+        let weight = self
+            .weigher
+            .as_ref()
+            .map_or(1, |weigher| weigher(&key, &value));
+        self.put(key, value, weight);
+    }
+
+    // An occupied entry counts as a hit, just like `get`.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        match self.live_index(&key) {
+            Some(idx) => Entry::Occupied(OccupiedEntry { cache: self, idx }),
+            None => Entry::Vacant(VacantEntry { cache: self, key }),
+        }
+    }
+
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, weight: u32, f: F) -> &V {
+        self.entry(key).or_insert_with(weight, f)
+    }
+
+    // A failed computation leaves the cache untouched and returns the error.
+    pub fn try_get_or_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        weight: u32,
+        f: F,
+    ) -> Result<&V, E> {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(f()?, weight)),
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let idx = *self.map.get(key)?;
+        Some(self.remove_at(idx).value)
+    }
+
+    // The entry `pop_victim` would remove, left in place.
+    pub fn peek_victim(&self) -> Option<&K> {
+        Some(&self.node(self.victim_index()?).key)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        let idx = self.victim_index()?;
+        self.rng.advance();
+        let node = self.remove_at(idx);
+        Some((node.key, node.value))
+    }
+
+    // Evicts entries as a full cache would, listener and stats included, until at most
+    // `len` are left. Returns how many were evicted.
+    pub fn evict_to(&mut self, len: usize) -> usize {
+        let mut evicted = 0;
+        while self.len() > len {
+            self.evict();
+            evicted += 1;
+        }
+        evicted
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        // Removal moves entries around, so the doomed are found by key
+        let doomed: Vec<K> = self
+            .nodes
+            .iter()
+            .filter(|node| !f(&node.key, &node.value))
+            .map(|node| node.key.clone())
+            .collect();
+
+        for key in doomed {
+            self.remove(&key);
+        }
+    }
+
+    // Makes the sequence of victims reproducible, e.g. across simulation runs.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn set_expire_after_write(&mut self, time_to_live: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_live = Some(time_to_live);
+        self.set_expiration(expiration);
+    }
+
+    pub fn set_expire_after_access(&mut self, time_to_idle: Duration) {
+        let mut expiration = self.expiration;
+        expiration.time_to_idle = Some(time_to_idle);
+        self.set_expiration(expiration);
+    }
+
+    // Existing entries are scheduled against the new limits.
+    pub(crate) fn set_expiration(&mut self, expiration: Expiration) {
+        self.expiration = expiration;
+        self.expiry_wheel = if expiration.is_enabled() {
+            Some(TimingWheel::new(self.clock.now(), WHEEL_RESOLUTION))
+        } else {
+            None
+        };
+        let live: Vec<usize> = self.map.values().cloned().collect();
+        for idx in live {
+            self.schedule_expiry(idx);
+        }
+    }
+
+    // Replaces the time source; entries keep the timestamps they were given by the old one.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+        let expiration = self.expiration;
+        self.set_expiration(expiration);
+    }
+
+    pub fn expire_due(&mut self, now: Instant) -> usize {
+        let due = match self.expiry_wheel.as_mut() {
+            Some(wheel) => wheel.advance(now),
+            None => return 0,
+        };
+
+        let mut expired = 0;
+        for key in due {
+            let idx = match self.map.get(&key) {
+                Some(&idx) => idx,
+                None => continue,
+            };
+            if self.is_expired(idx, now) {
+                self.remove_at(idx);
+                expired += 1;
+            } else {
+                // Touched or rewritten since it was scheduled
+                self.schedule_expiry(idx);
+            }
+        }
+        expired
+    }
+
+    pub fn set_max_weight(&mut self, max_weight: u64) {
+        self.max_weight = Some(max_weight);
+        while !self.is_empty() && self.is_over_budget() {
+            self.evict();
+        }
+    }
+
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.set_boxed_eviction_listener(Box::new(listener));
+    }
+
+    pub(crate) fn set_boxed_eviction_listener(&mut self, listener: EvictionListener<K, V>) {
+        self.eviction_listener = Some(listener);
+    }
+
+    // Computes the weight of entries stored with `insert`.
+    pub fn set_weigher<F: Fn(&K, &V) -> u32 + Send + 'static>(&mut self, weigher: F) {
+        self.set_boxed_weigher(Box::new(weigher));
+    }
+
+    pub(crate) fn set_boxed_weigher(&mut self, weigher: Weigher<K, V>) {
+        self.weigher = Some(weigher);
+    }
+
+    pub fn set_observer<O: CacheObserver<K> + Send + 'static>(&mut self, observer: O) {
+        self.set_boxed_observer(Box::new(observer));
+    }
+
+    pub(crate) fn set_boxed_observer(&mut self, observer: BoxedObserver<K>) {
+        self.observer = Some(observer);
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.nodes.clear();
+        self.stats.current_weight = 0;
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.clear();
+        }
+    }
+
+    // Entries are kept dense, so this only releases spare capacity.
+    pub fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+        self.map.shrink_to_fit();
+    }
+
+    // Reserves room for a full cache. The map gets twice that, so the churn of evictions
+    // rehashes it in place instead of growing it.
+    pub fn preallocate(&mut self) {
+        self.nodes
+            .reserve(self.capacity.saturating_sub(self.nodes.len()));
+        self.map
+            .reserve((2 * self.capacity).saturating_sub(self.map.len()));
+    }
+
+    // In no particular order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            nodes: self.nodes.iter(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    fn insert_new(&mut self, key: K, value: V, weight: u32) -> usize {
+        self.make_room(weight);
+
+        self.nodes.push(Node {
+            key: key.clone(),
+            value,
+            weight,
+            timestamps: Timestamps::new(self.clock.now()),
+        });
+        let idx = self.nodes.len() - 1;
+
+        self.stats.insertions += 1;
+        self.stats.current_weight += u64::from(weight);
+        trace::insert(POLICY, &key, weight);
+        self.notify(|observer| observer.on_insert(&key));
+        self.map.insert(key, idx);
+        self.schedule_expiry(idx);
+        idx
+    }
+
+    // Whether an entry of this weight only fits after an eviction.
+    fn is_full(&self, weight: u32) -> bool {
+        match self.max_weight {
+            Some(max_weight) => self.stats.current_weight + u64::from(weight) > max_weight,
+            None => self.len() >= self.capacity,
+        }
+    }
+
+    fn is_over_budget(&self) -> bool {
+        self.max_weight
+            .is_some_and(|max_weight| self.stats.current_weight > max_weight)
+    }
+
+    fn make_room(&mut self, weight: u32) {
+        while !self.is_empty() && self.is_full(weight) {
+            self.evict();
+        }
+    }
+
+    // Looks up a key, dropping it instead if it has expired, and records the access.
+    // This is the single place hits and misses are observed.
+    fn live_index(&mut self, key: &K) -> Option<usize> {
+        let now = self.clock.now();
+        let idx = match self.map.get(key).cloned() {
+            Some(idx) if self.is_expired(idx, now) => {
+                self.remove_at(idx);
+                None
+            }
+            found => found,
+        };
+        match idx {
+            Some(idx) => {
+                self.node_mut(idx).timestamps.touch(now);
+                self.stats.hits += 1;
+                self.notify(|observer| observer.on_hit(key));
+            }
+            None => {
+                self.stats.misses += 1;
+                trace::miss(POLICY, key);
+                self.notify(|observer| observer.on_miss(key));
+            }
+        }
+        idx
+    }
+
+    fn is_expired(&self, idx: usize, now: Instant) -> bool {
+        self.expiration.is_expired(&self.node(idx).timestamps, now)
+    }
+
+    fn schedule_expiry(&mut self, idx: usize) {
+        let node = &self.nodes[idx];
+        if let (Some(wheel), Some(deadline)) = (
+            self.expiry_wheel.as_mut(),
+            self.expiration.deadline(&node.timestamps),
+        ) {
+            wheel.schedule(node.key.clone(), deadline);
+        }
+    }
+
+    // Fills the gap with the last entry.
+    fn remove_at(&mut self, idx: usize) -> Node<K, V> {
+        let node = self.nodes.swap_remove(idx);
+        if let Some(moved) = self.nodes.get(idx) {
+            *self.map.get_mut(&moved.key).expect("every node is mapped") = idx;
+        }
+        self.map.remove(&node.key);
+        self.stats.current_weight -= u64::from(node.weight);
+        if let Some(wheel) = self.expiry_wheel.as_mut() {
+            wheel.cancel(&node.key);
+        }
+        node
+    }
+
+    fn notify<F: FnOnce(&mut dyn CacheObserver<K>)>(&mut self, f: F) {
+        if let Some(observer) = self.observer.as_mut() {
+            f(&mut **observer);
+        }
+    }
+
+    fn victim_index(&self) -> Option<usize> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        Some((self.rng.peek() % self.nodes.len() as u64) as usize)
+    }
+
+    fn evict(&mut self) {
+        if let Some(idx) = self.victim_index() {
+            self.rng.advance();
+            self.evict_at(idx);
+        }
+    }
+
+    // Capacity-driven eviction; the listener sees every entry dropped this way.
+    fn evict_at(&mut self, idx: usize) {
+        let node = self.remove_at(idx);
+        self.stats.evictions += 1;
+        trace::eviction(POLICY, &node.key);
+        if let Some(listener) = self.eviction_listener.as_mut() {
+            listener(node.key, node.value);
+        }
+    }
+
+    fn node(&self, idx: usize) -> &Node<K, V> {
+        &self.nodes[idx]
+    }
+
+    fn node_mut(&mut self, idx: usize) -> &mut Node<K, V> {
+        &mut self.nodes[idx]
+    }
+}
+
+impl<K, V, S> IntoIterator for RandomCache<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter {
+            nodes: self.nodes.into_iter(),
+        }
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher> IntoIterator for &'a RandomCache<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+// The capacity is sized to hold every collected entry.
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Default> FromIterator<(K, V, u32)>
+    for RandomCache<K, V, S>
+{
+    fn from_iter<I: IntoIterator<Item = (K, V, u32)>>(iter: I) -> Self {
+        let entries: Vec<_> = iter.into_iter().collect();
+        let mut cache = RandomCache::with_hasher(entries.len().max(1), S::default());
+        cache.extend(entries);
+        cache
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher> Extend<(K, V, u32)> for RandomCache<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V, u32)>>(&mut self, iter: I) {
+        for (key, value, weight) in iter {
+            self.put(key, value, weight);
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher> Cache<K, V> for RandomCache<K, V, S> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Default> NewCache<K, V> for RandomCache<K, V, S> {
+    fn new(capacity: usize) -> Self {
+        RandomCache::with_hasher(capacity, S::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::collections::HashSet;
+
+    #[test]
+    #[should_panic(expected = "Capacity must be greater than 0")]
+    fn test_new_cache_zero_capacity() {
+        let _cache: RandomCache<String, i32> = RandomCache::new(0);
+    }
+
+    #[test]
+    fn test_capacity_is_kept() {
+        let mut cache = RandomCache::new(3);
+        for key in 0..100 {
+            cache.put(key, key * 10, 0);
+            assert!(cache.len() <= 3);
+        }
+
+        assert_eq!(cache.len(), 3);
+        assert!(cache.contains_key(&99));
+        assert_eq!(cache.stats().evictions, 97);
+        for (key, value) in cache.iter() {
+            assert_eq!(*value, key * 10);
+        }
+    }
+
+    #[test]
+    fn test_peek_victim_matches_pop_victim() {
+        let mut cache = RandomCache::new(8);
+        for key in 0..8 {
+            cache.put(key, key, 0);
+        }
+
+        let mut popped = HashSet::new();
+        while let Some(&victim) = cache.peek_victim() {
+            assert_eq!(cache.pop_victim(), Some((victim, victim)));
+            popped.insert(victim);
+        }
+        assert_eq!(popped.len(), 8);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_seed_makes_victims_reproducible() {
+        let victims = |seed| {
+            let mut cache = RandomCache::new(4);
+            cache.set_seed(seed);
+            let mut evicted = Vec::new();
+            for key in 0..20 {
+                if cache.len() == 4 {
+                    evicted.push(*cache.peek_victim().unwrap());
+                }
+                cache.put(key, (), 0);
+            }
+            evicted
+        };
+
+        assert_eq!(victims(7), victims(7));
+        assert_ne!(victims(7), victims(8));
+    }
+
+    #[test]
+    fn test_victims_are_spread_out() {
+        let mut cache = RandomCache::new(4);
+        cache.set_seed(42);
+        let mut counts = [0; 4];
+        for _ in 0..400 {
+            for key in 0..4 {
+                cache.put(key, (), 0);
+            }
+            let (key, _) = cache.pop_victim().unwrap();
+            counts[key] += 1;
+        }
+        assert!(counts.iter().all(|&count| count > 50), "{:?}", counts);
+    }
+
+    #[test]
+    fn test_update_remove_and_get_mut() {
+        let mut cache = RandomCache::new(3);
+        cache.put(1, vec![1], 1);
+        cache.put(2, vec![2], 2);
+        cache.put(3, vec![3], 3);
+        cache.put(2, vec![20], 5);
+        cache.get_mut(&3).unwrap().push(30);
+
+        assert_eq!(cache.peek(&2), Some(&vec![20]));
+        assert_eq!(cache.stats().current_weight, 9);
+        assert_eq!(cache.remove(&1), Some(vec![1]));
+        assert_eq!(cache.remove(&1), None);
+
+        // The last entry moved into the freed slot
+        assert_eq!(cache.get(&3), Some(&vec![3, 30]));
+        assert_eq!(cache.get(&2), Some(&vec![20]));
+        assert_eq!(cache.stats().current_weight, 8);
+    }
+
+    #[test]
+    fn test_retain_clear_and_from_iter() {
+        let mut cache: RandomCache<_, _> = (0..6).map(|key| (key, key, 0)).collect();
+        cache.retain(|&key, _| key % 2 == 0);
+
+        let mut keys: Vec<_> = cache.keys().copied().collect();
+        keys.sort_unstable();
+        assert_eq!(keys, vec![0, 2, 4]);
+        assert_eq!(cache.values().count(), 3);
+
+        let mut entries: Vec<_> = cache.into_iter().collect();
+        entries.sort_unstable();
+        assert_eq!(entries, vec![(0, 0), (2, 2), (4, 4)]);
+    }
+
+    #[test]
+    fn test_heavier_update_evicts_others() {
+        let mut cache = RandomCache::new(4);
+        cache.set_max_weight(100);
+
+        cache.put(1, "one", 30);
+        cache.put(2, "two", 30);
+        cache.put(3, "three", 30);
+        cache.put(1, "ONE", 200);
+
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(cache.stats().current_weight, 200);
+        assert_eq!(cache.stats().evictions, 2);
+    }
+
+    #[test]
+    fn test_eviction_listener() {
+        use std::sync::Mutex;
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = RandomCache::new(1);
+        cache.set_eviction_listener(move |key, value| sink.lock().unwrap().push((key, value)));
+
+        cache.put(1, "one", 0);
+        cache.remove(&1);
+        cache.put(2, "two", 0);
+        assert!(evicted.lock().unwrap().is_empty());
+
+        cache.put(3, "three", 0);
+        assert_eq!(*evicted.lock().unwrap(), vec![(2, "two")]);
+    }
+
+    #[test]
+    fn test_expire_after_access() {
+        let clock = ManualClock::new();
+        let mut cache = RandomCache::new(2);
+        cache.set_clock(Arc::new(clock.clone()));
+        cache.set_expire_after_access(Duration::from_secs(60));
+
+        cache.put(1, "one", 0);
+        cache.put(2, "two", 0);
+        clock.advance(Duration::from_secs(40));
+        assert_eq!(cache.get(&1), Some(&"one"));
+
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.expire_due(clock.now()), 0);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&1]);
+    }
+}
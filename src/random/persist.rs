@@ -0,0 +1,187 @@
+use std::collections::hash_map::Entry;
+use std::hash::{BuildHasher, Hash};
+use std::io::{Read, Write};
+
+use super::{Node, RandomCache, Rng};
+use crate::expiry::SavedTimestamps;
+use crate::snapshot::{self, impl_codec, Decode, Encode, SnapshotError, SnapshotReader};
+use crate::stats::CacheStats;
+use crate::util;
+use crate::Policy;
+
+// What is saved of a cache, by serde and by snapshots alike. The generator state is saved
+// with the entries, so a restored cache goes on to pick the same victims the original
+// would have.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Saved<K, V> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    stats: CacheStats,
+    rng: u64,
+    entries: Vec<SavedEntry<K, V>>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct SavedEntry<K, V> {
+    key: K,
+    value: V,
+    weight: u32,
+    timestamps: SavedTimestamps,
+}
+
+impl_codec!(Saved<K, V> {
+    capacity,
+    max_weight,
+    stats,
+    rng,
+    entries,
+});
+impl_codec!(SavedEntry<K, V> {
+    key,
+    value,
+    weight,
+    timestamps,
+});
+
+impl<K, V, S> RandomCache<K, V, S> {
+    fn save(&self) -> Saved<&K, &V> {
+        let now = self.clock.now();
+        let entries = self
+            .nodes
+            .iter()
+            .map(|node| SavedEntry {
+                key: &node.key,
+                value: &node.value,
+                weight: node.weight,
+                timestamps: SavedTimestamps::save(&node.timestamps, now),
+            })
+            .collect();
+        Saved {
+            capacity: self.capacity,
+            max_weight: self.max_weight,
+            stats: self.stats,
+            rng: self.rng.0,
+            entries,
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Default> RandomCache<K, V, S> {
+    fn restore(saved: Saved<K, V>) -> Result<Self, &'static str> {
+        snapshot::check_len(saved.capacity, saved.max_weight, saved.entries.len())?;
+        let mut cache = RandomCache::with_hasher(saved.capacity, S::default());
+        cache.max_weight = saved.max_weight;
+        cache.rng = Rng::new(saved.rng);
+        cache.stats = saved.stats;
+        cache.stats.current_weight = 0;
+        let now = cache.clock.now();
+        for entry in saved.entries {
+            match cache.map.entry(entry.key.clone()) {
+                Entry::Occupied(_) => return Err("duplicate key"),
+                Entry::Vacant(slot) => slot.insert(cache.nodes.len()),
+            };
+            cache.stats.current_weight += u64::from(entry.weight);
+            cache.nodes.push(Node {
+                key: entry.key,
+                value: entry.value,
+                weight: entry.weight,
+                timestamps: entry.timestamps.restore(now),
+            });
+        }
+        Ok(cache)
+    }
+}
+
+impl<K: Encode, V: Encode, S> RandomCache<K, V, S> {
+    // Writes the entries and the generator state as a checksummed binary snapshot.
+    pub fn save_to<W: Write>(&self, writer: W) -> Result<(), SnapshotError> {
+        snapshot::write(writer, Policy::Random, &self.save())
+    }
+}
+
+impl<K: Clone + Hash + Eq + Decode, V: Decode, S: BuildHasher + Default> RandomCache<K, V, S> {
+    pub fn load_from<R: Read>(reader: R) -> Result<Self, SnapshotError> {
+        RandomCache::load_snapshot(SnapshotReader::open(reader)?)
+    }
+
+    pub(crate) fn load_snapshot<R: Read>(
+        snapshot: SnapshotReader<R>,
+    ) -> Result<Self, SnapshotError> {
+        let saved = snapshot.body(Policy::Random)?;
+        RandomCache::restore(saved).map_err(SnapshotError::Invalid)
+    }
+}
+
+impl<K: Clone + Hash + Eq, V: Clone, S: BuildHasher> RandomCache<K, V, S> {
+    // Any `n` entries with their weights, since no entry is more likely to stay than another.
+    pub fn export_top(&self, n: usize) -> Vec<(K, V, u32)> {
+        util::top_entries(self.iter(), n, |key| self.entry_info(key))
+    }
+
+    pub fn import<I: IntoIterator<Item = (K, V, u32)>>(&mut self, entries: I) {
+        for (key, value, weight) in entries {
+            self.put(key, value, weight);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K: serde::Serialize, V: serde::Serialize, S> serde::Serialize for RandomCache<K, V, S> {
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        serde::Serialize::serialize(&self.save(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, S> serde::Deserialize<'de> for RandomCache<K, V, S>
+where
+    K: Clone + Hash + Eq + serde::Deserialize<'de>,
+    V: serde::Deserialize<'de>,
+    S: BuildHasher + Default,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = serde::Deserialize::deserialize(deserializer)?;
+        RandomCache::restore(saved).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::RandomState;
+
+    fn seeded() -> RandomCache<i32, i32> {
+        let mut cache = RandomCache::new(4);
+        cache.set_seed(7);
+        for key in 0..4 {
+            cache.put(key, key * 10, 1);
+        }
+        cache
+    }
+
+    #[test]
+    fn test_snapshot_picks_the_same_victims() {
+        let mut cache = seeded();
+        let mut bytes = Vec::new();
+        cache.save_to(&mut bytes).unwrap();
+        let mut restored: RandomCache<i32, i32, RandomState> =
+            RandomCache::load_from(bytes.as_slice()).unwrap();
+        assert_eq!(restored.stats(), cache.stats());
+        assert_eq!(restored.peek(&2), Some(&20));
+        for _ in 0..4 {
+            assert_eq!(restored.pop_victim(), cache.pop_victim());
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_picks_the_same_victims() {
+        let mut cache = seeded();
+        let json = serde_json::to_string(&cache).unwrap();
+        let mut restored: RandomCache<i32, i32, RandomState> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.stats(), cache.stats());
+        for _ in 0..4 {
+            assert_eq!(restored.pop_victim(), cache.pop_victim());
+        }
+    }
+}
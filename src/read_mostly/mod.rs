@@ -0,0 +1,330 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use arc_swap::ArcSwap;
+use crossbeam_queue::ArrayQueue;
+
+use crate::buffered::HIT_BUFFER_SIZE;
+use crate::sync_cache::lock;
+use crate::{CacheStats, CacheType, Policy};
+
+enum Op<K, V> {
+    Insert(K, V),
+    Remove(K),
+    Clear,
+}
+
+impl<K: Hash + Eq, V> Op<K, V> {
+    fn apply<S: BuildHasher>(self, map: &mut HashMap<K, V, S>) {
+        match self {
+            Op::Insert(key, value) => {
+                map.insert(key, value);
+            }
+            Op::Remove(key) => {
+                map.remove(&key);
+            }
+            Op::Clear => map.clear(),
+        }
+    }
+}
+
+impl<K: Clone, V: Clone> Op<K, V> {
+    fn duplicate(&self) -> Self {
+        match *self {
+            Op::Insert(ref key, ref value) => Op::Insert(key.clone(), value.clone()),
+            Op::Remove(ref key) => Op::Remove(key.clone()),
+            Op::Clear => Op::Clear,
+        }
+    }
+}
+
+struct Shared<K, V, S> {
+    published: ArcSwap<HashMap<K, V, S>>,
+    hits: ArrayQueue<K>,
+    hit_count: AtomicU64,
+    miss_count: AtomicU64,
+}
+
+// Reads the map the writer last published, without locks or waiting on the writer.
+// Clone it for each reading thread.
+pub struct ReadHandle<K, V, S = RandomState> {
+    shared: Arc<Shared<K, V, S>>,
+}
+
+impl<K, V, S> Clone for ReadHandle<K, V, S> {
+    fn clone(&self) -> Self {
+        ReadHandle {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher> ReadHandle<K, V, S> {
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.with_value(key, V::clone)
+    }
+
+    // Runs `f` on the value in the snapshot. The writer's next publish waits for the
+    // snapshot to be released, so `f` should be quick.
+    pub fn with_value<R, F: FnOnce(&V) -> R>(&self, key: &K, f: F) -> Option<R> {
+        let result = self.shared.published.load().get(key).map(f);
+        match result {
+            // Dropped when the writer has not kept up, as in `BufferedCache`
+            Some(_) => {
+                self.shared.hit_count.fetch_add(1, Ordering::Relaxed);
+                let _ = self.shared.hits.push(key.clone());
+            }
+            None => {
+                self.shared.miss_count.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+
+    // Does not count as a use of the key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.shared.published.load().contains_key(key)
+    }
+
+    // Entries in the published snapshot.
+    pub fn len(&self) -> usize {
+        self.shared.published.load().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// The only handle that changes the cache. Writes go to the eviction policy at once but
+// only reach readers at the next `publish`. The writer keeps two copies of the map:
+// readers use one while writes are applied to the other, and publishing swaps them and
+// then replays the same writes on the copy readers just left (Ramalhete and Correia's
+// left-right technique). Memory is that of two maps; nothing is copied wholesale.
+pub struct WriteHandle<K, V, S = RandomState> {
+    shared: Arc<Shared<K, V, S>>,
+    standby: Arc<HashMap<K, V, S>>,
+    // Writes not yet applied to the standby copy
+    pending: Vec<Op<K, V>>,
+    policy: CacheType<K, (), S>,
+    evicted: Arc<Mutex<Vec<K>>>,
+}
+
+impl<K: Clone + Hash + Eq + Send + 'static, V: Clone> WriteHandle<K, V> {
+    pub fn new(policy: Policy, capacity: usize) -> Self {
+        WriteHandle::with_hasher(policy, capacity, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq + Send + 'static, V: Clone, S: BuildHasher + Clone> WriteHandle<K, V, S> {
+    pub fn with_hasher(policy: Policy, capacity: usize, hash_builder: S) -> Self {
+        let mut policy = CacheType::with_hasher(policy, capacity, hash_builder.clone());
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        policy.set_eviction_listener(move |key, ()| lock(&sink).push(key));
+        let empty = || Arc::new(HashMap::with_hasher(hash_builder.clone()));
+        WriteHandle {
+            shared: Arc::new(Shared {
+                published: ArcSwap::new(empty()),
+                hits: ArrayQueue::new(HIT_BUFFER_SIZE),
+                hit_count: AtomicU64::new(0),
+                miss_count: AtomicU64::new(0),
+            }),
+            standby: empty(),
+            pending: Vec::new(),
+            policy,
+            evicted,
+        }
+    }
+
+    pub fn reader(&self) -> ReadHandle<K, V, S> {
+        ReadHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        self.apply_hits();
+        self.policy.put(key.clone(), (), weight);
+        self.pending.push(Op::Insert(key, value));
+        let evicted = mem::take(&mut *lock(&self.evicted));
+        self.pending.extend(evicted.into_iter().map(Op::Remove));
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        if self.policy.remove(key).is_some() {
+            self.pending.push(Op::Remove(key.clone()));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.policy.clear();
+        self.pending.clear();
+        self.pending.push(Op::Clear);
+    }
+
+    // Makes the writes so far visible to readers. Waits for readers still using the
+    // previous snapshot, which is only ever as long as one of their lookups.
+    pub fn publish(&mut self) {
+        self.apply_hits();
+        let pending = mem::take(&mut self.pending);
+        let standby = Arc::get_mut(&mut self.standby).expect("readers never see the standby");
+        for op in &pending {
+            op.duplicate().apply(standby);
+        }
+        let mut retired = self.shared.published.swap(self.standby.clone());
+        // Readers that loaded the retired copy before the swap may still be using it
+        while Arc::get_mut(&mut retired).is_none() {
+            thread::yield_now();
+        }
+        let map = Arc::get_mut(&mut retired).expect("no reader holds the retired copy");
+        for op in pending {
+            op.apply(map);
+        }
+        self.standby = retired;
+    }
+
+    // Writes made since the last `publish`.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    // Counts the hits and misses readers saw, and the policy's view of everything else.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.shared.hit_count.load(Ordering::Relaxed),
+            misses: self.shared.miss_count.load(Ordering::Relaxed),
+            ..self.policy.stats()
+        }
+    }
+
+    // Entries after the pending writes, which readers may not see yet.
+    pub fn len(&self) -> usize {
+        self.policy.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Replays the buffered reader hits into the policy, at most one buffer's worth.
+    fn apply_hits(&mut self) {
+        for _ in 0..self.shared.hits.capacity() {
+            match self.shared.hits.pop() {
+                Some(key) => {
+                    self.policy.get(&key);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readers_see_writes_once_published() {
+        let mut writer = WriteHandle::new(Policy::LRU, 4);
+        let reader = writer.reader();
+        writer.put("rust", 1, 1);
+        assert_eq!(writer.pending(), 1);
+        assert_eq!(reader.get(&"rust"), None);
+
+        writer.publish();
+        assert_eq!(writer.pending(), 0);
+        assert_eq!(reader.get(&"rust"), Some(1));
+        assert_eq!(reader.with_value(&"rust", |value| value + 1), Some(2));
+
+        // Both copies stay in step across several publishes
+        writer.put("go", 2, 1);
+        writer.publish();
+        writer.remove(&"rust");
+        writer.publish();
+        writer.put("zig", 3, 1);
+        writer.publish();
+        assert!(!reader.contains_key(&"rust"));
+        assert_eq!(reader.get(&"go"), Some(2));
+        assert_eq!(reader.get(&"zig"), Some(3));
+        assert_eq!(reader.len(), 2);
+        assert_eq!(writer.len(), 2);
+    }
+
+    #[test]
+    fn test_reader_hits_guide_eviction() {
+        let mut writer = WriteHandle::new(Policy::LRU, 2);
+        let reader = writer.reader();
+        writer.put(1, "one", 1);
+        writer.put(2, "two", 1);
+        writer.publish();
+        assert_eq!(reader.get(&1), Some("one"));
+
+        // 1 was read since 2, so 2 is evicted and disappears from the next snapshot
+        writer.put(3, "three", 1);
+        writer.publish();
+        assert!(reader.contains_key(&1));
+        assert!(!reader.contains_key(&2));
+        assert_eq!(reader.len(), 2);
+
+        let stats = writer.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 0));
+        assert_eq!(stats.evictions, 1);
+    }
+
+    #[test]
+    fn test_clear_drops_unpublished_writes() {
+        let mut writer = WriteHandle::new(Policy::FIFO, 4);
+        let reader = writer.reader();
+        writer.put(1, 1, 1);
+        writer.publish();
+        writer.put(2, 2, 1);
+        writer.clear();
+        assert_eq!(writer.pending(), 1);
+        writer.publish();
+        assert!(reader.is_empty());
+        assert!(writer.is_empty());
+
+        writer.put(3, 3, 1);
+        writer.publish();
+        writer.publish();
+        assert_eq!(reader.get(&3), Some(3));
+    }
+
+    #[test]
+    fn test_readers_on_other_threads() {
+        let mut writer = WriteHandle::new(Policy::LFU, 64);
+        let reader = writer.reader();
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                let reader = reader.clone();
+                scope.spawn(move || {
+                    for key in 0..20_000u64 {
+                        let key = key % 128;
+                        if let Some(value) = reader.get(&key) {
+                            assert_eq!(value, key * 2);
+                        }
+                    }
+                });
+            }
+            for key in 0..5_000u64 {
+                writer.put(key % 128, key % 128 * 2, 1);
+                if key % 16 == 0 {
+                    writer.publish();
+                }
+            }
+        });
+        writer.publish();
+        assert_eq!(reader.len(), 64);
+        let stats = writer.stats();
+        assert_eq!(stats.hits + stats.misses, 80_000);
+    }
+}
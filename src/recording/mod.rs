@@ -0,0 +1,414 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::clock::{Clock, SystemClock};
+use crate::replay::Access;
+use crate::snapshot::SnapshotError;
+use crate::Cache;
+
+const MAGIC: [u8; 4] = *b"SECR";
+const VERSION: u16 = 1;
+// An operation byte, the key hash, the weight and the timestamp.
+const RECORD_LEN: usize = 1 + 8 + 4 + 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Hit,
+    Miss,
+    Put,
+}
+
+impl Outcome {
+    fn byte(self) -> u8 {
+        match self {
+            Outcome::Hit => 0,
+            Outcome::Miss => 1,
+            Outcome::Put => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Outcome> {
+        match byte {
+            0 => Some(Outcome::Hit),
+            1 => Some(Outcome::Miss),
+            2 => Some(Outcome::Put),
+            _ => None,
+        }
+    }
+}
+
+// One recorded operation. Lookups have a weight of 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Operation {
+    // Microseconds since the recording started.
+    pub timestamp: u64,
+    pub key_hash: u64,
+    pub outcome: Outcome,
+    pub weight: u32,
+}
+
+// Records every lookup and put of a cache to a file, so production traffic can be replayed
+// offline against other policies and capacities. Keys are written as hashes, which keeps
+// them out of the file and makes every record the same 21 bytes; `peek` and
+// `contains_key` are not recorded, as they are not traffic.
+//
+// Writes are buffered. The first write that fails stops the recording without affecting
+// the cache, and is returned by `flush`.
+pub struct RecordingCache<K, V, C> {
+    cache: C,
+    file: BufWriter<File>,
+    error: Option<io::Error>,
+    clock: Arc<dyn Clock>,
+    started: Instant,
+    marker: PhantomData<fn(K, V)>,
+}
+
+impl<K: Hash, V, C: Cache<K, V>> RecordingCache<K, V, C> {
+    // Starts a recording at `path`, replacing any file there.
+    pub fn create<P: AsRef<Path>>(cache: C, path: P) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        Ok(RecordingCache {
+            cache,
+            file,
+            error: None,
+            started: clock.now(),
+            clock,
+            marker: PhantomData,
+        })
+    }
+
+    // The clock timestamps are taken from. Timestamps count from when it is set.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.started = clock.now();
+        self.clock = clock;
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let timestamp = self.timestamp();
+        let value = self.cache.get(key);
+        let outcome = if value.is_some() {
+            Outcome::Hit
+        } else {
+            Outcome::Miss
+        };
+        write_operation(&mut self.file, &mut self.error, key, outcome, 0, timestamp);
+        value
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let timestamp = self.timestamp();
+        let value = self.cache.get_mut(key);
+        let outcome = if value.is_some() {
+            Outcome::Hit
+        } else {
+            Outcome::Miss
+        };
+        write_operation(&mut self.file, &mut self.error, key, outcome, 0, timestamp);
+        value
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.cache.peek(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        let timestamp = self.timestamp();
+        write_operation(
+            &mut self.file,
+            &mut self.error,
+            &key,
+            Outcome::Put,
+            weight,
+            timestamp,
+        );
+        self.cache.put(key, value, weight);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.cache.remove(key)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.cache.pop_victim()
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.cache.peek_victim()
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear()
+    }
+
+    // Writes out buffered records, or returns the error that stopped the recording.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.file.flush()
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.cache
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.cache
+    }
+
+    // Ends the recording, writing out what is buffered.
+    pub fn into_inner(mut self) -> io::Result<C> {
+        self.flush()?;
+        Ok(self.cache)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    fn timestamp(&self) -> u64 {
+        let elapsed = self.clock.now().saturating_duration_since(self.started);
+        elapsed.as_micros() as u64
+    }
+}
+
+fn write_operation<K: Hash>(
+    file: &mut BufWriter<File>,
+    error: &mut Option<io::Error>,
+    key: &K,
+    outcome: Outcome,
+    weight: u32,
+    timestamp: u64,
+) {
+    if error.is_some() {
+        return;
+    }
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    let mut record = [0; RECORD_LEN];
+    record[0] = outcome.byte();
+    record[1..9].copy_from_slice(&hasher.finish().to_le_bytes());
+    record[9..13].copy_from_slice(&weight.to_le_bytes());
+    record[13..].copy_from_slice(&timestamp.to_le_bytes());
+    if let Err(failed) = file.write_all(&record) {
+        *error = Some(failed);
+    }
+}
+
+impl<K: Hash, V, C: Cache<K, V>> Cache<K, V> for RecordingCache<K, V, C> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+// Reads a recording back, oldest operation first. A record cut short at the end, as a
+// crash leaves it, is dropped.
+pub fn read_recording<P: AsRef<Path>>(path: P) -> Result<Vec<Operation>, SnapshotError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut header = [0; 6];
+    reader
+        .read_exact(&mut header)
+        .map_err(|error| match error.kind() {
+            io::ErrorKind::UnexpectedEof => SnapshotError::NotASnapshot,
+            _ => SnapshotError::Io(error),
+        })?;
+    if header[..4] != MAGIC {
+        return Err(SnapshotError::NotASnapshot);
+    }
+    match u16::from_le_bytes([header[4], header[5]]) {
+        VERSION => {}
+        version => return Err(SnapshotError::UnsupportedVersion(version)),
+    }
+
+    let mut operations = Vec::new();
+    let mut record = [0; RECORD_LEN];
+    loop {
+        match reader.read_exact(&mut record) {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(error) => return Err(error.into()),
+        }
+        let outcome =
+            Outcome::from_byte(record[0]).ok_or(SnapshotError::Invalid("unknown operation"))?;
+        let mut key_hash = [0; 8];
+        key_hash.copy_from_slice(&record[1..9]);
+        let mut weight = [0; 4];
+        weight.copy_from_slice(&record[9..13]);
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&record[13..]);
+        operations.push(Operation {
+            timestamp: u64::from_le_bytes(timestamp),
+            key_hash: u64::from_le_bytes(key_hash),
+            outcome,
+            weight: u32::from_le_bytes(weight),
+        });
+    }
+    Ok(operations)
+}
+
+// Turns recorded lookups into a trace for `replay`, keyed by the hash in hex. Each lookup
+// is sized by the weight its key was last put with, or for a key not yet put, by the
+// weight of its first put, which is usually what the miss fetched. Puts themselves are
+// not lookups and are left out.
+pub fn recorded_trace(operations: &[Operation]) -> Vec<Access> {
+    let mut weights: HashMap<u64, u32> = HashMap::new();
+    for operation in operations {
+        if operation.outcome == Outcome::Put {
+            weights
+                .entry(operation.key_hash)
+                .or_insert(operation.weight);
+        }
+    }
+    let mut trace = Vec::new();
+    for operation in operations {
+        if operation.outcome == Outcome::Put {
+            weights.insert(operation.key_hash, operation.weight);
+            continue;
+        }
+        trace.push(Access {
+            timestamp: operation.timestamp,
+            key: format!("{:016x}", operation.key_hash),
+            size: weights.get(&operation.key_hash).copied(),
+            cost: None,
+        });
+    }
+    trace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::lru::LRUCache;
+    use crate::replay::replay;
+    use std::fs::{self, OpenOptions};
+    use std::path::PathBuf;
+    use std::process;
+    use std::time::Duration;
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("recording-{}-{}", process::id(), name))
+    }
+
+    #[test]
+    fn test_records_lookups_and_puts() {
+        let path = scratch("ops");
+        let clock = ManualClock::new();
+        let mut cache = RecordingCache::create(LRUCache::new(4), &path).unwrap();
+        cache.set_clock(Arc::new(clock.clone()));
+        assert_eq!(cache.get(&"rust"), None);
+        cache.put("rust", 1, 300);
+        clock.advance(Duration::from_millis(2));
+        assert_eq!(cache.get(&"rust"), Some(&1));
+        assert!(cache.contains_key(&"rust"));
+        let cache = cache.into_inner().unwrap();
+        assert_eq!(cache.len(), 1);
+
+        let operations = read_recording(&path).unwrap();
+        let outcomes: Vec<Outcome> = operations.iter().map(|op| op.outcome).collect();
+        assert_eq!(outcomes, vec![Outcome::Miss, Outcome::Put, Outcome::Hit]);
+        assert_eq!(operations[1].weight, 300);
+        assert_eq!(operations[2].timestamp, 2000);
+        assert!(operations
+            .iter()
+            .all(|op| op.key_hash == operations[0].key_hash));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_recordings_replay_through_other_caches() {
+        let path = scratch("replay");
+        let mut cache = RecordingCache::create(LRUCache::new(10), &path).unwrap();
+        for key in [1, 2, 3, 1, 2, 3, 1] {
+            if cache.get(&key).is_none() {
+                cache.put(key, key, key * 100);
+            }
+        }
+        cache.flush().unwrap();
+        drop(cache);
+        // A torn record at the end is dropped
+        let len = fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0; 5]).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), len + 5);
+
+        let trace = recorded_trace(&read_recording(&path).unwrap());
+        assert_eq!(trace.len(), 7);
+        assert_eq!(trace[0].size, Some(100));
+        let report = replay(&mut LRUCache::new(2), &trace);
+        assert_eq!(report.hits, 0);
+        let report = replay(&mut LRUCache::new(3), &trace);
+        assert_eq!(report.hits, 4);
+        assert_eq!(report.bytes_hit, 100 + 200 + 300 + 100);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        let path = scratch("other");
+        fs::write(&path, b"SEC").unwrap();
+        assert!(matches!(
+            read_recording(&path),
+            Err(SnapshotError::NotASnapshot)
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::{Clock, SystemClock};
+use crate::{Cache, CacheStats, CacheType};
+
+// Flags entries for refresh before they expire, so a hot query is recomputed while its
+// old result is still served instead of missing once. A hit on an entry due to expire
+// within the window flags its key; the application takes the flagged keys, recomputes
+// them and puts the new values, which clears the flags.
+//
+// Only hits flag keys, so entries nobody reads are left to expire. A flagged key stays
+// flagged until it is taken, written or removed, however often it is hit meanwhile.
+pub struct RefreshAheadCache<K, V, S = RandomState> {
+    cache: CacheType<K, V, S>,
+    window: Duration,
+    pending: HashSet<K>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K, V, S> RefreshAheadCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    // The cache should expire entries, or nothing is ever flagged.
+    pub fn new(cache: CacheType<K, V, S>, window: Duration) -> Self {
+        RefreshAheadCache {
+            cache,
+            window,
+            pending: HashSet::new(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    // Also replaces the cache's clock, so both agree on when entries expire.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.cache.set_clock(clock.clone());
+        self.clock = clock;
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        if self.cache.get(key).is_some() && !self.pending.contains(key) {
+            let now = self.clock.now();
+            let due = self
+                .cache
+                .entry_info(key)
+                .and_then(|info| info.expires_at)
+                .is_some_and(|deadline| deadline.saturating_duration_since(now) <= self.window);
+            if due {
+                self.pending.insert(key.clone());
+            }
+        }
+        self.cache.peek(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get(key)?;
+        self.cache.get_mut(key)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.cache.peek(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        self.pending.remove(&key);
+        self.cache.put(key, value, weight);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.pending.remove(key);
+        self.cache.remove(key)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        let (key, value) = self.cache.pop_victim()?;
+        self.pending.remove(&key);
+        Some((key, value))
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.cache.peek_victim()
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+        self.pending.clear();
+    }
+
+    pub fn is_refresh_pending(&self, key: &K) -> bool {
+        self.pending.contains(key)
+    }
+
+    pub fn pending_refreshes(&self) -> impl Iterator<Item = &K> {
+        self.pending.iter()
+    }
+
+    // Hands over the flagged keys to recompute. Keys evicted or expired since they were
+    // flagged are dropped, since there is nothing left to keep warm.
+    pub fn take_refreshes(&mut self) -> Vec<K> {
+        let cache = &self.cache;
+        self.pending
+            .drain()
+            .filter(|key| cache.contains_key(key))
+            .collect()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn inner(&self) -> &CacheType<K, V, S> {
+        &self.cache
+    }
+
+    pub fn into_inner(self) -> CacheType<K, V, S> {
+        self.cache
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+impl<K, V, S> Cache<K, V> for RefreshAheadCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::{Policy, SyncCache};
+
+    fn refreshing(clock: &ManualClock) -> RefreshAheadCache<i32, &'static str> {
+        let mut inner = CacheType::new(Policy::LRU, 4);
+        inner.set_expire_after_write(Duration::from_secs(60));
+        let mut cache = RefreshAheadCache::new(inner, Duration::from_secs(10));
+        cache.set_clock(Arc::new(clock.clone()));
+        cache
+    }
+
+    #[test]
+    fn test_flags_hits_near_expiry() {
+        let clock = ManualClock::new();
+        let mut cache = refreshing(&clock);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+
+        clock.advance(Duration::from_secs(45));
+        cache.get(&1);
+        assert!(!cache.is_refresh_pending(&1));
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert!(cache.is_refresh_pending(&1));
+        // Not read, so left to expire
+        assert!(!cache.is_refresh_pending(&2));
+
+        assert_eq!(cache.take_refreshes(), vec![1]);
+        cache.put(1, "ONE", 1);
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(cache.get(&1), Some(&"ONE"));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.pending_refreshes().count(), 0);
+    }
+
+    #[test]
+    fn test_writes_clear_the_flag() {
+        let clock = ManualClock::new();
+        let mut cache = refreshing(&clock);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        clock.advance(Duration::from_secs(55));
+        cache.get(&1);
+        cache.get(&2);
+
+        cache.put(1, "ONE", 1);
+        cache.remove(&2);
+        assert!(cache.take_refreshes().is_empty());
+    }
+
+    #[test]
+    fn test_shared_behind_a_lock() {
+        let clock = ManualClock::new();
+        let cache = SyncCache::new(refreshing(&clock));
+        cache.put(1, "one", 1);
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(cache.get(&1), Some("one"));
+        assert_eq!(cache.lock().take_refreshes(), vec![1]);
+    }
+}
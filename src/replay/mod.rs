@@ -0,0 +1,266 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::Cache;
+
+mod parse;
+
+// One request of an access log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Access {
+    // In whatever unit the log uses; replay only relies on the order of the lines.
+    pub timestamp: u64,
+    pub key: String,
+    // The result's size in bytes, stored as its weight. Requests without one weigh 1.
+    pub size: Option<u32>,
+    // What a miss costs, e.g. the query's latency. Requests without one cost 1.
+    pub cost: Option<f64>,
+}
+
+impl Access {
+    pub fn weight(&self) -> u32 {
+        self.size.unwrap_or(1)
+    }
+
+    pub fn miss_cost(&self) -> f64 {
+        self.cost.unwrap_or(1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    // `timestamp,key[,size[,cost]]` per line, with an optional header line.
+    Csv,
+    // A JSON object per line with `timestamp`, `key` and optionally `size` and `cost`.
+    JsonLines,
+}
+
+impl TraceFormat {
+    // Guessed from the file extension: `.csv`, or `.jsonl` and `.ndjson`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<TraceFormat> {
+        match path.as_ref().extension()?.to_str()? {
+            "csv" => Some(TraceFormat::Csv),
+            "jsonl" | "ndjson" => Some(TraceFormat::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    // The 1-based line that could not be parsed, and why.
+    Parse { line: usize, message: String },
+    // The format could not be guessed from the path.
+    UnknownFormat,
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceError::Io(error) => write!(f, "trace I/O failed: {}", error),
+            TraceError::Parse { line, message } => {
+                write!(f, "invalid trace line {}: {}", line, message)
+            }
+            TraceError::UnknownFormat => write!(f, "unknown trace format"),
+        }
+    }
+}
+
+impl Error for TraceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TraceError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TraceError {
+    fn from(error: io::Error) -> Self {
+        TraceError::Io(error)
+    }
+}
+
+// Parses an access log. Blank lines and lines starting with `#` are skipped.
+pub fn read_trace<R: BufRead>(reader: R, format: TraceFormat) -> Result<Vec<Access>, TraceError> {
+    let mut trace = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let parsed = match format {
+            TraceFormat::Csv => parse::csv_access(trimmed),
+            TraceFormat::JsonLines => parse::json_access(trimmed).map(Some),
+        };
+        match parsed {
+            Ok(Some(access)) => trace.push(access),
+            Ok(None) => {}
+            Err(message) => {
+                return Err(TraceError::Parse {
+                    line: index + 1,
+                    message,
+                })
+            }
+        }
+    }
+    Ok(trace)
+}
+
+// Opens and parses an access log, in the format its extension names.
+pub fn read_trace_file<P: AsRef<Path>>(path: P) -> Result<Vec<Access>, TraceError> {
+    let format = TraceFormat::from_path(&path).ok_or(TraceError::UnknownFormat)?;
+    read_trace(BufReader::new(File::open(path)?), format)
+}
+
+// What a replay measured. Evictions are entries that left the cache while new ones went
+// in; rejections are new entries the cache turned away, e.g. by admission.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayReport {
+    pub requests: u64,
+    pub hits: u64,
+    pub bytes_requested: u64,
+    pub bytes_hit: u64,
+    pub cost_requested: f64,
+    pub cost_hit: f64,
+    pub insertions: u64,
+    pub rejections: u64,
+    pub evictions: u64,
+}
+
+impl ReplayReport {
+    pub fn misses(&self) -> u64 {
+        self.requests - self.hits
+    }
+
+    pub fn hit_ratio(&self) -> f64 {
+        ratio(self.hits as f64, self.requests as f64)
+    }
+
+    // The share of requested bytes served from the cache.
+    pub fn byte_hit_ratio(&self) -> f64 {
+        ratio(self.bytes_hit as f64, self.bytes_requested as f64)
+    }
+
+    // The share of miss cost the cache saved.
+    pub fn cost_hit_ratio(&self) -> f64 {
+        ratio(self.cost_hit, self.cost_requested)
+    }
+}
+
+fn ratio(part: f64, whole: f64) -> f64 {
+    if whole == 0.0 {
+        0.0
+    } else {
+        part / whole
+    }
+}
+
+// Replays the log against any cache: every request is a lookup, and every miss stores
+// the key with its size as the weight.
+pub fn replay<C: Cache<String, ()> + ?Sized>(cache: &mut C, trace: &[Access]) -> ReplayReport {
+    replay_with(cache, trace, |cache, access| {
+        cache.put(access.key.clone(), (), access.weight())
+    })
+}
+
+// Like `replay`, but misses are stored by `insert`, e.g. to give a cost-aware policy the
+// request's cost through `CacheType::put_sized`.
+pub fn replay_with<C, F>(cache: &mut C, trace: &[Access], mut insert: F) -> ReplayReport
+where
+    C: Cache<String, ()> + ?Sized,
+    F: FnMut(&mut C, &Access),
+{
+    let mut report = ReplayReport::default();
+    for access in trace {
+        report.requests += 1;
+        report.bytes_requested += u64::from(access.weight());
+        report.cost_requested += access.miss_cost();
+        if cache.get(&access.key).is_some() {
+            report.hits += 1;
+            report.bytes_hit += u64::from(access.weight());
+            report.cost_hit += access.miss_cost();
+            continue;
+        }
+
+        let before = cache.len();
+        insert(cache, access);
+        let stored = cache.contains_key(&access.key);
+        if stored {
+            report.insertions += 1;
+        } else {
+            report.rejections += 1;
+        }
+        let expected = before + usize::from(stored);
+        report.evictions += expected.saturating_sub(cache.len()) as u64;
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lru::LRUCache;
+    use crate::{CacheType, Policy};
+
+    const LOG: &str = "timestamp,key,size,cost
+1,rust,100,5
+2,zig,300,1
+# a comment
+3,rust,100,5
+4,go,100,2
+5,zig,300,1
+";
+
+    #[test]
+    fn test_replay_reports_ratios() {
+        let trace = read_trace(LOG.as_bytes(), TraceFormat::Csv).unwrap();
+        assert_eq!(trace.len(), 5);
+        let mut cache = LRUCache::new(2);
+        let report = replay(&mut cache, &trace);
+
+        assert_eq!(report.hits, 1);
+        assert_eq!(report.misses(), 4);
+        assert_eq!(report.hit_ratio(), 0.2);
+        assert_eq!(report.byte_hit_ratio(), 100.0 / 900.0);
+        assert_eq!(report.cost_hit_ratio(), 5.0 / 14.0);
+        assert_eq!(report.insertions, 4);
+        assert_eq!(report.evictions, 2);
+    }
+
+    #[test]
+    fn test_replay_with_costs_and_json_lines() {
+        let log = r#"{"timestamp": 1, "key": "slow", "cost": 50}
+{"timestamp": 2, "key": "a"}
+{"timestamp": 3, "key": "b"}
+{"timestamp": 4, "key": "slow", "cost": 50}
+"#;
+        let trace = read_trace(log.as_bytes(), TraceFormat::JsonLines).unwrap();
+        let mut cache: CacheType<String, ()> = CacheType::new(Policy::Landlord, 2);
+        let report = replay_with(&mut cache, &trace, |cache, access| {
+            let cost = access.miss_cost() as u32;
+            cache.put_sized(access.key.clone(), (), cost, access.weight());
+        });
+        assert_eq!(report.hits, 1);
+        assert_eq!(report.cost_hit_ratio(), 50.0 / 102.0);
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_line() {
+        let error = read_trace("1,rust\n2\n".as_bytes(), TraceFormat::Csv).unwrap_err();
+        assert!(matches!(error, TraceError::Parse { line: 2, .. }));
+        assert_eq!(
+            TraceFormat::from_path("queries.jsonl"),
+            Some(TraceFormat::JsonLines)
+        );
+        assert!(matches!(
+            read_trace_file("queries.txt"),
+            Err(TraceError::UnknownFormat)
+        ));
+    }
+}
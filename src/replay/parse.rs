@@ -0,0 +1,302 @@
+use std::convert::TryFrom;
+
+use super::Access;
+
+// Fields of one CSV line; fields in double quotes may hold commas, and `""` inside them
+// is a literal quote.
+fn csv_fields(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (false, '"') if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            (false, ',') => fields.push(std::mem::take(&mut field)),
+            (_, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".to_string());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+// `timestamp,key[,size[,cost]]`, with empty size and cost fields left out. Returns `None`
+// for a header line.
+pub(super) fn csv_access(line: &str) -> Result<Option<Access>, String> {
+    let fields = csv_fields(line)?;
+    let field = |i: usize| fields.get(i).map(|field| field.trim()).unwrap_or("");
+    if field(0).eq_ignore_ascii_case("timestamp") {
+        return Ok(None);
+    }
+    if fields.len() < 2 || fields.len() > 4 {
+        return Err(format!("expected 2 to 4 fields, found {}", fields.len()));
+    }
+    let timestamp = field(0)
+        .parse()
+        .map_err(|_| format!("invalid timestamp {:?}", field(0)))?;
+    let size = match field(2) {
+        "" => None,
+        size => Some(parse_size(size)?),
+    };
+    let cost = match field(3) {
+        "" => None,
+        cost => Some(parse_cost(cost)?),
+    };
+    Ok(Some(Access {
+        timestamp,
+        key: field(1).to_string(),
+        size,
+        cost,
+    }))
+}
+
+fn parse_size(size: &str) -> Result<u32, String> {
+    size.parse().map_err(|_| format!("invalid size {:?}", size))
+}
+
+fn parse_cost(cost: &str) -> Result<f64, String> {
+    cost.parse().map_err(|_| format!("invalid cost {:?}", cost))
+}
+
+enum Json {
+    String(String),
+    Number(String),
+    Null,
+    // A boolean, array or object, none of which a known field takes
+    Other,
+}
+
+// A JSON object such as `{"timestamp": 3, "key": "rust", "size": 512}`. Fields other than
+// the four known ones are skipped, whatever their value.
+pub(super) fn json_access(line: &str) -> Result<Access, String> {
+    let mut parser = JsonParser {
+        chars: line.trim().chars().collect(),
+        at: 0,
+    };
+    let mut timestamp = None;
+    let mut key = None;
+    let mut size = None;
+    let mut cost = None;
+    parser.expect('{')?;
+    if !parser.eat('}') {
+        loop {
+            let name = parser.string()?;
+            parser.expect(':')?;
+            let value = parser.value()?;
+            match (name.as_str(), value) {
+                (_, Json::Null) => {}
+                ("timestamp", Json::Number(number)) => {
+                    timestamp = Some(
+                        number
+                            .parse()
+                            .map_err(|_| format!("invalid timestamp {}", number))?,
+                    )
+                }
+                ("key", Json::String(string)) => key = Some(string),
+                ("key", Json::Number(number)) => key = Some(number),
+                ("size", Json::Number(number)) => size = Some(parse_size(&number)?),
+                ("cost", Json::Number(number)) => cost = Some(parse_cost(&number)?),
+                ("timestamp", _) | ("size", _) | ("cost", _) => {
+                    return Err(format!("{} must be a number", name))
+                }
+                _ => {}
+            }
+            if parser.eat('}') {
+                break;
+            }
+            parser.expect(',')?;
+        }
+    }
+    if parser.at < parser.chars.len() {
+        return Err("trailing characters after the object".to_string());
+    }
+    Ok(Access {
+        timestamp: timestamp.ok_or("missing timestamp")?,
+        key: key.ok_or("missing key")?,
+        size,
+        cost,
+    })
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl JsonParser {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.at).is_some_and(|c| c.is_whitespace()) {
+            self.at += 1;
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.get(self.at) == Some(&expected) {
+            self.at += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(format!("expected {:?} at column {}", expected, self.at + 1))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.chars.get(self.at) {
+            Some('"') => self.string().map(Json::String),
+            Some('n') => self.literal("null").map(|_| Json::Null),
+            Some('t') => self.literal("true").map(|_| Json::Other),
+            Some('f') => self.literal("false").map(|_| Json::Other),
+            Some('[') => {
+                self.at += 1;
+                if !self.eat(']') {
+                    loop {
+                        self.value()?;
+                        if self.eat(']') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Json::Other)
+            }
+            Some('{') => {
+                self.at += 1;
+                if !self.eat('}') {
+                    loop {
+                        self.string()?;
+                        self.expect(':')?;
+                        self.value()?;
+                        if self.eat('}') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Json::Other)
+            }
+            Some(c) if *c == '-' || c.is_ascii_digit() => {
+                let start = self.at;
+                while self
+                    .chars
+                    .get(self.at)
+                    .is_some_and(|c| matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+                {
+                    self.at += 1;
+                }
+                Ok(Json::Number(self.chars[start..self.at].iter().collect()))
+            }
+            _ => Err(format!("unsupported value at column {}", self.at + 1)),
+        }
+    }
+
+    fn literal(&mut self, literal: &str) -> Result<(), String> {
+        let end = self.at + literal.len();
+        if !self
+            .chars
+            .get(self.at..end)
+            .is_some_and(|chars| chars.iter().copied().eq(literal.chars()))
+        {
+            return Err(format!("invalid value at column {}", self.at + 1));
+        }
+        self.at = end;
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            let c = *self.chars.get(self.at).ok_or("unterminated string")?;
+            self.at += 1;
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escaped = *self.chars.get(self.at).ok_or("unterminated string")?;
+                    self.at += 1;
+                    string.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => self.unicode_escape()?,
+                        other => other,
+                    });
+                }
+                c => string.push(c),
+            }
+        }
+    }
+
+    // The four hex digits after `\u`. Surrogate pairs are not combined.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let digits: String = self
+            .chars
+            .get(self.at..self.at + 4)
+            .ok_or("truncated unicode escape")?
+            .iter()
+            .collect();
+        self.at += 4;
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(|code| char::try_from(code).ok())
+            .ok_or_else(|| format!("invalid unicode escape {:?}", digits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_lines() {
+        let access = csv_access("17, \"rust, cache\",512,2.5").unwrap().unwrap();
+        assert_eq!(access.timestamp, 17);
+        assert_eq!(access.key, "rust, cache");
+        assert_eq!(access.size, Some(512));
+        assert_eq!(access.cost, Some(2.5));
+
+        let bare = csv_access("3, zig ").unwrap().unwrap();
+        assert_eq!(bare.key, "zig");
+        assert_eq!((bare.size, bare.cost), (None, None));
+        assert!(csv_access("timestamp,key,size").unwrap().is_none());
+        assert!(csv_access("soon,zig").is_err());
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let line =
+            r#"{"timestamp": 4, "key": "say \"hi\"", "size": 10, "user": "x", "cost": null}"#;
+        let access = json_access(line).unwrap();
+        assert_eq!(access.key, "say \"hi\"");
+        assert_eq!(access.size, Some(10));
+        assert_eq!(access.cost, None);
+
+        let extra = r#"{"timestamp": 5, "cached": true, "key": "rust", "user": {"id": [1, {"x": false}], "tags": []}}"#;
+        let access = json_access(extra).unwrap();
+        assert_eq!((access.timestamp, access.key.as_str()), (5, "rust"));
+
+        assert!(json_access(r#"{"key": "rust"}"#).is_err());
+        assert!(json_access(r#"{"timestamp": 4, "key": "rust", "size": true}"#).is_err());
+        assert!(json_access(r#"{"timestamp": "4", "key": "rust"}"#).is_err());
+    }
+}
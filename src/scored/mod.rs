@@ -0,0 +1,440 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+use crate::random::Rng;
+use crate::stats::CacheStats;
+use crate::trace;
+use crate::{Cache, EvictionListener};
+
+const POLICY: &str = "scored";
+
+// What the cache tracks about each entry for a scorer to decide on. Time is counted in
+// requests, every lookup and put being one, so scores do not depend on the wall clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EntryFeatures {
+    // Hits plus the insert.
+    pub frequency: u64,
+    // Requests since the entry was last used.
+    pub recency: u64,
+    // Requests since the entry was inserted.
+    pub age: u64,
+    pub size: u32,
+    // What a miss on it costs, as given to `put_with_cost`; 1 for plain puts.
+    pub cost: f64,
+}
+
+// Scores an entry for eviction from its features, e.g. with a model trained offline on
+// recorded traffic; the entry scored lowest is evicted. Closures taking the key and the
+// features implement it.
+pub trait EvictionScorer<K> {
+    fn score(&self, key: &K, features: &EntryFeatures) -> f64;
+}
+
+impl<K, F: Fn(&K, &EntryFeatures) -> f64> EvictionScorer<K> for F {
+    fn score(&self, key: &K, features: &EntryFeatures) -> f64 {
+        self(key, features)
+    }
+}
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    weight: u32,
+    cost: f64,
+    frequency: u64,
+    inserted: u64,
+    used: u64,
+}
+
+// Leaves the eviction decision to a user-supplied scorer, while the cache keeps the
+// features and the entries. By default every entry is scored on each eviction; with
+// `set_sample_size`, only that many picked at random are, the usual trade for learned
+// policies whose models are too slow to run over the whole cache.
+pub struct ScoredCache<K, V, E, S = RandomState> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    map: HashMap<K, usize, S>,
+    // Densely packed, so a sample is a few random indices
+    nodes: Vec<Node<K, V>>,
+    scorer: E,
+    sample_size: Option<usize>,
+    rng: Rng,
+    tick: u64,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    stats: CacheStats,
+}
+
+impl<K: Clone + Hash + Eq, V, E: EvictionScorer<K>> ScoredCache<K, V, E> {
+    pub fn new(capacity: usize, scorer: E) -> Self {
+        ScoredCache::with_hasher(capacity, scorer, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, E: EvictionScorer<K>, S: BuildHasher> ScoredCache<K, V, E, S> {
+    pub fn with_hasher(capacity: usize, scorer: E, hash_builder: S) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        ScoredCache {
+            capacity,
+            max_weight: None,
+            map: HashMap::with_hasher(hash_builder),
+            nodes: Vec::with_capacity(capacity),
+            scorer,
+            sample_size: None,
+            rng: Rng::new(RandomState::new().hash_one(capacity)),
+            tick: 0,
+            eviction_listener: None,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn set_max_weight(&mut self, max_weight: u64) {
+        self.max_weight = Some(max_weight);
+        self.evict_to_fit(None);
+    }
+
+    // Scores only `sample_size` entries, drawn at random, on each eviction.
+    pub fn set_sample_size(&mut self, sample_size: usize) {
+        assert!(sample_size > 0, "Sample size must be greater than 0");
+        self.sample_size = Some(sample_size);
+    }
+
+    // Makes the samples reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn scorer(&self) -> &E {
+        &self.scorer
+    }
+
+    // For models updated online, e.g. retrained from the features of evicted entries.
+    pub fn scorer_mut(&mut self) -> &mut E {
+        &mut self.scorer
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let _span = trace::get_span(POLICY, key);
+        let idx = self.touch(key)?;
+        Some(&self.nodes[idx].value)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let idx = self.touch(key)?;
+        Some(&mut self.nodes[idx].value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.map.get(key).map(|&idx| &self.nodes[idx].value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    // The entry's features as the scorer would see them now. Not a use of the key.
+    pub fn features(&self, key: &K) -> Option<EntryFeatures> {
+        self.map.get(key).map(|&idx| self.features_at(idx))
+    }
+
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put_with_cost(key, value, weight, 1.0);
+    }
+
+    // An update keeps the entry's frequency and age and counts as a use.
+    pub fn put_with_cost(&mut self, key: K, value: V, weight: u32, cost: f64) {
+        let _span = trace::put_span(POLICY, &key, weight);
+        self.tick += 1;
+        match self.map.get(&key) {
+            Some(&idx) => {
+                let node = &mut self.nodes[idx];
+                self.stats.current_weight -= u64::from(node.weight);
+                node.value = value;
+                node.weight = weight;
+                node.cost = cost;
+                node.frequency += 1;
+                node.used = self.tick;
+            }
+            None => {
+                self.stats.insertions += 1;
+                trace::insert(POLICY, &key, weight);
+                self.map.insert(key.clone(), self.nodes.len());
+                self.nodes.push(Node {
+                    key: key.clone(),
+                    value,
+                    weight,
+                    cost,
+                    frequency: 1,
+                    inserted: self.tick,
+                    used: self.tick,
+                });
+            }
+        }
+        self.stats.current_weight += u64::from(weight);
+        // The entry itself is never evicted to make room for itself
+        self.evict_to_fit(Some(&key));
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let idx = *self.map.get(key)?;
+        Some(self.remove_at(idx).value)
+    }
+
+    // The lowest scored of the entries an eviction would score now.
+    pub fn peek_victim(&self) -> Option<&K> {
+        let idx = self.victim_index(&mut self.rng.clone(), None)?;
+        Some(&self.nodes[idx].key)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        let mut rng = self.rng.clone();
+        let idx = self.victim_index(&mut rng, None)?;
+        self.rng = rng;
+        let node = self.remove_at(idx);
+        Some((node.key, node.value))
+    }
+
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.eviction_listener = Some(Box::new(listener));
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.nodes.clear();
+        self.stats.current_weight = 0;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Records the lookup and, on a hit, the use.
+    fn touch(&mut self, key: &K) -> Option<usize> {
+        self.tick += 1;
+        match self.map.get(key) {
+            Some(&idx) => {
+                self.stats.hits += 1;
+                let node = &mut self.nodes[idx];
+                node.frequency += 1;
+                node.used = self.tick;
+                Some(idx)
+            }
+            None => {
+                self.stats.misses += 1;
+                trace::miss(POLICY, key);
+                None
+            }
+        }
+    }
+
+    fn features_at(&self, idx: usize) -> EntryFeatures {
+        let node = &self.nodes[idx];
+        EntryFeatures {
+            frequency: node.frequency,
+            recency: self.tick - node.used,
+            age: self.tick - node.inserted,
+            size: node.weight,
+            cost: node.cost,
+        }
+    }
+
+    // The lowest scored entry of every entry or of a sample, other than `keep`. A score
+    // that is not a number is never the lowest.
+    fn victim_index(&self, rng: &mut Rng, keep: Option<usize>) -> Option<usize> {
+        let candidates = self.nodes.len() - usize::from(keep.is_some());
+        if candidates == 0 {
+            return None;
+        }
+        let score = |idx: usize| {
+            let score = self
+                .scorer
+                .score(&self.nodes[idx].key, &self.features_at(idx));
+            if score.is_nan() {
+                f64::INFINITY
+            } else {
+                score
+            }
+        };
+        let lowest = |best: Option<(usize, f64)>, idx: usize| {
+            let score = score(idx);
+            match best {
+                Some((_, best_score)) if best_score <= score => best,
+                _ => Some((idx, score)),
+            }
+        };
+        let best = match self.sample_size.filter(|&size| size < candidates) {
+            None => (0..self.nodes.len())
+                .filter(|&idx| Some(idx) != keep)
+                .fold(None, lowest),
+            Some(size) => (0..size)
+                .map(|_| {
+                    let mut idx = (rng.peek() % candidates as u64) as usize;
+                    rng.advance();
+                    // Skip over the kept entry
+                    if keep.is_some_and(|keep| idx >= keep) {
+                        idx += 1;
+                    }
+                    idx
+                })
+                .fold(None, lowest),
+        };
+        best.map(|(idx, _)| idx)
+    }
+
+    fn over_budget(&self) -> bool {
+        self.nodes.len() > self.capacity
+            || self
+                .max_weight
+                .is_some_and(|max_weight| self.stats.current_weight > max_weight)
+    }
+
+    fn evict_to_fit(&mut self, keep: Option<&K>) {
+        while self.over_budget() {
+            // Evictions move entries, so the kept key's slot is looked up again
+            let keep = keep.and_then(|key| self.map.get(key).copied());
+            let mut rng = self.rng.clone();
+            let victim = match self.victim_index(&mut rng, keep) {
+                Some(victim) => victim,
+                None => break,
+            };
+            self.rng = rng;
+            let node = self.remove_at(victim);
+            self.stats.evictions += 1;
+            trace::eviction(POLICY, &node.key);
+            if let Some(listener) = self.eviction_listener.as_mut() {
+                listener(node.key, node.value);
+            }
+        }
+    }
+
+    fn remove_at(&mut self, idx: usize) -> Node<K, V> {
+        let node = self.nodes.swap_remove(idx);
+        if let Some(moved) = self.nodes.get(idx) {
+            *self.map.get_mut(&moved.key).expect("every node is mapped") = idx;
+        }
+        self.map.remove(&node.key);
+        self.stats.current_weight -= u64::from(node.weight);
+        node
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, E: EvictionScorer<K>, S: BuildHasher> Cache<K, V>
+    for ScoredCache<K, V, E, S>
+{
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Scoring by how recently an entry was used reproduces LRU.
+    fn recency(_: &&str, features: &EntryFeatures) -> f64 {
+        -(features.recency as f64)
+    }
+
+    #[test]
+    fn test_evicts_the_lowest_score() {
+        let mut cache = ScoredCache::new(2, recency);
+        cache.put("a", 1, 1);
+        cache.put("b", 2, 1);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        cache.put("c", 3, 1);
+        assert!(!cache.contains_key(&"b"));
+        assert_eq!(cache.stats().evictions, 1);
+
+        let features = cache.features(&"a").unwrap();
+        assert_eq!(features.frequency, 2);
+        assert_eq!((features.recency, features.age), (1, 3));
+        assert_eq!(cache.peek_victim(), Some(&"a"));
+    }
+
+    #[test]
+    fn test_scorers_see_size_and_cost() {
+        // Keep what is expensive to recompute per byte it takes, like GreedyDual-Size
+        let scorer = |_: &u32, features: &EntryFeatures| features.cost / f64::from(features.size);
+        let mut cache = ScoredCache::new(3, scorer);
+        cache.put_with_cost(1, "slow", 10, 500.0);
+        cache.put_with_cost(2, "big", 1000, 50.0);
+        cache.put_with_cost(3, "cheap", 1, 1.0);
+        cache.put_with_cost(4, "new", 1, 20.0);
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.len(), 3);
+
+        cache.set_max_weight(11);
+        assert!(cache.contains_key(&1) && cache.contains_key(&4));
+        assert_eq!(cache.stats().current_weight, 11);
+    }
+
+    #[test]
+    fn test_sampled_evictions_are_reproducible() {
+        let frequency = |_: &u32, features: &EntryFeatures| features.frequency as f64;
+        let run = || {
+            let mut cache = ScoredCache::new(100, frequency);
+            cache.set_sample_size(8);
+            cache.set_seed(7);
+            for key in 0..1000u32 {
+                cache.put(key % 150, key, 1);
+                cache.get(&(key % 10));
+            }
+            let mut keys: Vec<u32> = (0..150).filter(|key| cache.contains_key(key)).collect();
+            keys.sort_unstable();
+            keys
+        };
+        let kept = run();
+        assert_eq!(kept, run());
+        assert_eq!(kept.len(), 100);
+        // The hot keys are in almost every sample's best
+        assert!((0..10).all(|key| kept.contains(&key)));
+    }
+}
@@ -0,0 +1,269 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+use crate::{Cache, CacheStats, CacheType, Policy};
+
+// Counts how often each query appears in a historical log and returns the `n` most
+// frequent, most frequent first, ready to have their results computed for `load_static`.
+pub fn top_queries<K, I>(log: I, n: usize) -> Vec<(K, u64)>
+where
+    K: Hash + Eq,
+    I: IntoIterator<Item = K>,
+{
+    let mut counts: HashMap<K, u64> = HashMap::new();
+    for query in log {
+        *counts.entry(query).or_insert(0) += 1;
+    }
+    let mut ranked: Vec<(K, u64)> = counts.into_iter().collect();
+    ranked.sort_by_key(|&(_, count)| Reverse(count));
+    ranked.truncate(n);
+    ranked
+}
+
+// The Static-Dynamic Cache of Fagni et al.: a read-only static section holding the results
+// of the queries that were most frequent historically, filled offline, beside a dynamic
+// section run by any policy for whatever is popular right now. Queries that are always
+// popular can never be evicted by a burst of fresh ones, while the dynamic section still
+// follows changes in the traffic.
+//
+// The static section only changes through `load_static`: writes to a static key are
+// ignored, though `remove` still drops it.
+pub struct StaticDynamicCache<K, V, S = RandomState> {
+    static_entries: HashMap<K, V>,
+    static_capacity: usize,
+    static_hits: u64,
+    dynamic: CacheType<K, V, S>,
+}
+
+impl<K: Clone + Hash + Eq, V> StaticDynamicCache<K, V> {
+    // Gives `static_share` of `capacity` entries to the static section and the rest to a
+    // dynamic section run by `policy`.
+    pub fn new(policy: Policy, capacity: usize, static_share: f64) -> Self {
+        assert!(
+            (0.0..1.0).contains(&static_share),
+            "Static share must be at least 0 and below 1"
+        );
+        let static_capacity = (capacity as f64 * static_share) as usize;
+        StaticDynamicCache::with_dynamic(
+            static_capacity,
+            CacheType::new(policy, capacity - static_capacity),
+        )
+    }
+}
+
+impl<K, V, S> StaticDynamicCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    // For a dynamic section configured beyond its policy, e.g. by `CacheBuilder`.
+    pub fn with_dynamic(static_capacity: usize, dynamic: CacheType<K, V, S>) -> Self {
+        StaticDynamicCache {
+            static_entries: HashMap::new(),
+            static_capacity,
+            static_hits: 0,
+            dynamic,
+        }
+    }
+
+    // Replaces the static section with the most frequent of `entries`, given as key,
+    // value and historical frequency, and returns how many it kept. Keys now static are
+    // dropped from the dynamic section.
+    pub fn load_static<I: IntoIterator<Item = (K, V, u64)>>(&mut self, entries: I) -> usize {
+        let mut ranked: Vec<(K, V, u64)> = entries.into_iter().collect();
+        ranked.sort_by_key(|&(_, _, frequency)| Reverse(frequency));
+        ranked.truncate(self.static_capacity);
+        self.static_entries.clear();
+        for (key, value, _) in ranked {
+            self.dynamic.remove(&key);
+            self.static_entries.insert(key, value);
+        }
+        self.static_entries.len()
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        if let Some(value) = self.static_entries.get(key) {
+            self.static_hits += 1;
+            return Some(value);
+        }
+        self.dynamic.get(key)
+    }
+
+    // Static values can be changed in place, even though `put` leaves them alone.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if let Some(value) = self.static_entries.get_mut(key) {
+            self.static_hits += 1;
+            return Some(value);
+        }
+        self.dynamic.get_mut(key)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.static_entries
+            .get(key)
+            .or_else(|| self.dynamic.peek(key))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.static_entries.contains_key(key) || self.dynamic.contains_key(key)
+    }
+
+    pub fn is_static(&self, key: &K) -> bool {
+        self.static_entries.contains_key(key)
+    }
+
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        if !self.static_entries.contains_key(&key) {
+            self.dynamic.put(key, value, weight);
+        }
+    }
+
+    // A static entry removed stays out until the next `load_static`.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        match self.static_entries.remove(key) {
+            Some(value) => Some(value),
+            None => self.dynamic.remove(key),
+        }
+    }
+
+    // Victims only come from the dynamic section.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.dynamic.pop_victim()
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.dynamic.peek_victim()
+    }
+
+    // Empties the dynamic section; the static one keeps its entries.
+    pub fn clear(&mut self) {
+        self.dynamic.clear();
+    }
+
+    // The dynamic section's stats, with static hits added to its hits.
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = self.dynamic.stats();
+        stats.hits += self.static_hits;
+        stats
+    }
+
+    pub fn static_hits(&self) -> u64 {
+        self.static_hits
+    }
+
+    pub fn static_capacity(&self) -> usize {
+        self.static_capacity
+    }
+
+    pub fn static_len(&self) -> usize {
+        self.static_entries.len()
+    }
+
+    pub fn dynamic(&self) -> &CacheType<K, V, S> {
+        &self.dynamic
+    }
+
+    pub fn dynamic_mut(&mut self) -> &mut CacheType<K, V, S> {
+        &mut self.dynamic
+    }
+
+    // Both sections together.
+    pub fn len(&self) -> usize {
+        self.static_entries.len() + self.dynamic.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V, S> Cache<K, V> for StaticDynamicCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_queries_ranks_the_log() {
+        let log = vec!["rust", "go", "rust", "zig", "rust", "go"];
+        assert_eq!(top_queries(log, 2), vec![("rust", 3), ("go", 2)]);
+    }
+
+    #[test]
+    fn test_static_entries_outlive_bursts() {
+        let mut cache = StaticDynamicCache::new(Policy::LRU, 4, 0.5);
+        cache.put("weather", 0, 1);
+        let loaded = cache.load_static(vec![("news", 1, 90), ("weather", 2, 70), ("zig", 3, 5)]);
+        assert_eq!(loaded, 2);
+        assert_eq!(cache.dynamic().len(), 0);
+        for key in &["a", "b", "c", "d"] {
+            cache.put(*key, 9, 1);
+        }
+        assert_eq!(cache.get(&"news"), Some(&1));
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.static_hits(), 1);
+    }
+
+    #[test]
+    fn test_static_entries_are_read_only() {
+        let mut cache = StaticDynamicCache::new(Policy::FIFO, 4, 0.25);
+        assert_eq!(cache.static_capacity(), 1);
+        cache.load_static(vec![("news", 1, 90)]);
+        cache.put("news", 5, 1);
+        assert_eq!(cache.peek(&"news"), Some(&1));
+        assert_eq!(cache.peek_victim(), None);
+
+        cache.clear();
+        assert!(cache.is_static(&"news"));
+        assert_eq!(cache.remove(&"news"), Some(1));
+        assert!(cache.is_empty());
+    }
+}
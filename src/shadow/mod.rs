@@ -0,0 +1,382 @@
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use crate::replay::ReplayReport;
+use crate::{Cache, CacheType, Policy};
+
+// Simulates a cache on live traffic without holding any values: it keeps the keys, their
+// weights and the policy's own bookkeeping, which is what decides hits, so a candidate
+// policy or a larger capacity costs a fraction of the memory of really running it. Each
+// lookup that misses is taken to be filled, as the real cache would be after loading it.
+pub struct ShadowCache<K> {
+    cache: CacheType<K, ()>,
+    policy: Policy,
+    report: ReplayReport,
+}
+
+impl<K: Clone + Hash + Eq> ShadowCache<K> {
+    // Simulates `policy` holding up to `capacity` entries.
+    pub fn new(policy: Policy, capacity: usize) -> Self {
+        ShadowCache {
+            cache: CacheType::new(policy, capacity),
+            policy,
+            report: ReplayReport::default(),
+        }
+    }
+
+    // Also bounds the simulated cache by total weight, like `CacheType::set_max_weight`.
+    pub fn set_max_weight(&mut self, max_weight: u64) {
+        self.cache.set_max_weight(max_weight);
+    }
+
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    // A lookup of a key weighing `weight`, filled on a miss. Returns whether it would
+    // have hit.
+    pub fn access(&mut self, key: &K, weight: u32) -> bool {
+        if self.lookup(key, weight) {
+            return true;
+        }
+        self.fill(key.clone(), weight);
+        false
+    }
+
+    // Counts a lookup without filling a miss, for callers that fill from `put`.
+    pub fn lookup(&mut self, key: &K, weight: u32) -> bool {
+        let hit = self.cache.get(key).is_some();
+        self.report.requests += 1;
+        self.report.bytes_requested += u64::from(weight);
+        self.report.cost_requested += 1.0;
+        if hit {
+            self.report.hits += 1;
+            self.report.bytes_hit += u64::from(weight);
+            self.report.cost_hit += 1.0;
+        }
+        hit
+    }
+
+    // Stores a key, or updates its weight, without counting a lookup.
+    pub fn fill(&mut self, key: K, weight: u32) {
+        let cached = self.cache.contains_key(&key);
+        let before = self.cache.len();
+        self.cache.put(key.clone(), (), weight);
+        if cached {
+            return;
+        }
+        let stored = self.cache.contains_key(&key);
+        if stored {
+            self.report.insertions += 1;
+        } else {
+            self.report.rejections += 1;
+        }
+        let expected = before + usize::from(stored);
+        self.report.evictions += expected.saturating_sub(self.cache.len()) as u64;
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    // The key the simulated policy would evict next.
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.cache.peek_victim()
+    }
+
+    // Called with each key the simulated policy evicts.
+    pub(crate) fn set_eviction_listener<F: FnMut(K) + Send + 'static>(&mut self, mut listener: F) {
+        self.cache
+            .set_eviction_listener(move |key, ()| listener(key));
+    }
+
+    // Drops a key the real cache invalidated.
+    pub fn remove(&mut self, key: &K) -> bool {
+        self.cache.remove(key).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    // What the simulated cache would have done so far. Requests without a known weight
+    // count as 1 byte, and every miss as a cost of 1.
+    pub fn report(&self) -> ReplayReport {
+        self.report
+    }
+
+    pub fn hit_ratio(&self) -> f64 {
+        self.report.hit_ratio()
+    }
+
+    // Starts counting afresh, keeping what is simulated as cached.
+    pub fn reset_report(&mut self) {
+        self.report = ReplayReport::default();
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+type ValueWeigher<K, V> = Box<dyn Fn(&K, &V) -> u32 + Send>;
+
+// Runs shadow caches beside a production cache: every lookup and put the cache serves is
+// shown to each shadow, and `report` gives the cache's own numbers to compare them with.
+// A shadow that misses where the cache hit is filled with the cached value's weight, from
+// the weigher, which counts every entry as 1 unless one is set.
+pub struct ShadowedCache<K, V, C> {
+    cache: C,
+    shadows: Vec<ShadowCache<K>>,
+    weigher: ValueWeigher<K, V>,
+    report: ReplayReport,
+    marker: PhantomData<fn(K, V)>,
+}
+
+impl<K: Clone + Hash + Eq, V, C: Cache<K, V>> ShadowedCache<K, V, C> {
+    pub fn new(cache: C) -> Self {
+        ShadowedCache {
+            cache,
+            shadows: Vec::new(),
+            weigher: Box::new(|_, _| 1),
+            report: ReplayReport::default(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn set_weigher<F: Fn(&K, &V) -> u32 + Send + 'static>(&mut self, weigher: F) {
+        self.weigher = Box::new(weigher);
+    }
+
+    // Starts simulating another cache from here on, empty. Returns its index in `shadows`.
+    pub fn add_shadow(&mut self, shadow: ShadowCache<K>) -> usize {
+        self.shadows.push(shadow);
+        self.shadows.len() - 1
+    }
+
+    pub fn shadows(&self) -> &[ShadowCache<K>] {
+        &self.shadows
+    }
+
+    pub fn shadows_mut(&mut self) -> &mut [ShadowCache<K>] {
+        &mut self.shadows
+    }
+
+    // The production cache's lookups so far, counted like a shadow's.
+    pub fn report(&self) -> ReplayReport {
+        self.report
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let value = self.cache.get(key);
+        let weigher = &self.weigher;
+        let weight = value.map_or(1, |value| weigher(key, value));
+        record_lookup(
+            &mut self.report,
+            &mut self.shadows,
+            key,
+            weight,
+            value.is_some(),
+        );
+        value
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let value = self.cache.get_mut(key);
+        let weigher = &self.weigher;
+        let weight = value.as_deref().map_or(1, |value| weigher(key, value));
+        record_lookup(
+            &mut self.report,
+            &mut self.shadows,
+            key,
+            weight,
+            value.is_some(),
+        );
+        value
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.cache.peek(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        for shadow in &mut self.shadows {
+            shadow.fill(key.clone(), weight);
+        }
+        self.cache.put(key, value, weight);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        for shadow in &mut self.shadows {
+            shadow.remove(key);
+        }
+        self.cache.remove(key)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.cache.pop_victim()
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.cache.peek_victim()
+    }
+
+    pub fn clear(&mut self) {
+        for shadow in &mut self.shadows {
+            shadow.clear();
+        }
+        self.cache.clear()
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.cache
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.cache
+    }
+
+    pub fn into_inner(self) -> C {
+        self.cache
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+// A shadow that misses is filled now only if the cache hit: after a miss, the cache's
+// own put fills it with the loaded value's weight.
+fn record_lookup<K: Clone + Hash + Eq>(
+    report: &mut ReplayReport,
+    shadows: &mut [ShadowCache<K>],
+    key: &K,
+    weight: u32,
+    hit: bool,
+) {
+    report.requests += 1;
+    report.bytes_requested += u64::from(weight);
+    report.cost_requested += 1.0;
+    if hit {
+        report.hits += 1;
+        report.bytes_hit += u64::from(weight);
+        report.cost_hit += 1.0;
+    }
+    for shadow in shadows {
+        if !shadow.lookup(key, weight) && hit {
+            shadow.fill(key.clone(), weight);
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, C: Cache<K, V>> Cache<K, V> for ShadowedCache<K, V, C> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lru::LRUCache;
+
+    #[test]
+    fn test_shadow_simulates_without_values() {
+        let mut shadow = ShadowCache::new(Policy::LRU, 2);
+        let hits: Vec<bool> = ["a", "b", "a", "c", "b", "a"]
+            .iter()
+            .map(|key| shadow.access(key, 10))
+            .collect();
+        assert_eq!(hits, vec![false, false, true, false, false, false]);
+        let report = shadow.report();
+        assert_eq!(report.hits, 1);
+        assert_eq!(report.bytes_requested, 60);
+        assert_eq!(report.evictions, 3);
+        assert_eq!(shadow.len(), 2);
+    }
+
+    #[test]
+    fn test_shadows_see_production_traffic() {
+        let mut cache = ShadowedCache::new(LRUCache::new(2));
+        let bigger = cache.add_shadow(ShadowCache::new(Policy::LRU, 3));
+        let smaller = cache.add_shadow(ShadowCache::new(Policy::LRU, 1));
+        for key in [1, 2, 3, 1, 2, 3, 3, 2] {
+            if cache.get(&key).is_none() {
+                cache.put(key, key * 10, 1);
+            }
+        }
+        // The loop of three defeats the real cache but fits the bigger shadow
+        assert_eq!(cache.report().hits, 2);
+        assert_eq!(cache.shadows()[bigger].report().hits, 5);
+        assert_eq!(cache.shadows()[smaller].report().hits, 1);
+        assert_eq!(cache.shadows()[bigger].report().requests, 8);
+    }
+
+    #[test]
+    fn test_shadows_fill_from_production_hits() {
+        let mut cache = ShadowedCache::new(LRUCache::new(4));
+        cache.set_weigher(|_, value: &String| value.len() as u32);
+        cache.put("rust", "a long result".to_string(), 13);
+        let late = cache.add_shadow(ShadowCache::new(Policy::FIFO, 4));
+        assert!(cache.get(&"rust").is_some());
+        assert!(cache.get(&"rust").is_some());
+        let report = cache.shadows()[late].report();
+        assert_eq!((report.requests, report.hits), (2, 1));
+        assert_eq!(report.bytes_hit, 13);
+
+        cache.remove(&"rust");
+        assert!(!cache.shadows()[late].contains_key(&"rust"));
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::sync_cache::lock;
+use crate::util::shard_of;
+use crate::{CacheStats, CacheType, Policy};
+
+// Splits one cache into independently locked shards, so threads working on different
+// keys rarely wait for each other. Each key always lands in the same shard, and every
+// shard runs its own copy of the policy over its share of the capacity, so eviction
+// order is only kept within a shard.
+pub struct ShardedCache<K, V, S = RandomState> {
+    shards: Box<[Mutex<CacheType<K, V, S>>]>,
+    hash_builder: S,
+}
+
+impl<K: Clone + Hash + Eq, V> ShardedCache<K, V> {
+    pub fn new(policy: Policy, capacity: usize, shards: usize) -> Self {
+        ShardedCache::with_hasher(policy, capacity, shards, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> ShardedCache<K, V, S> {
+    // The capacity is split evenly, rounding up, so the shards together hold at least
+    // `capacity` entries.
+    pub fn with_hasher(policy: Policy, capacity: usize, shards: usize, hash_builder: S) -> Self {
+        assert!(shards > 0, "Shard count must be greater than 0");
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        let per_shard = capacity.div_ceil(shards);
+        let shards = (0..shards)
+            .map(|_| CacheType::with_hasher(policy, per_shard, hash_builder.clone()))
+            .collect();
+        ShardedCache::from_shards(shards, hash_builder)
+    }
+
+    // For shards configured beyond a policy and capacity, e.g. by `CacheBuilder`.
+    pub fn from_shards(shards: Vec<CacheType<K, V, S>>, hash_builder: S) -> Self {
+        assert!(!shards.is_empty(), "Shard count must be greater than 0");
+        ShardedCache {
+            shards: shards.into_iter().map(Mutex::new).collect(),
+            hash_builder,
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn put(&self, key: K, value: V, weight: u32) {
+        self.lock_shard(&key).put(key, value, weight);
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        self.lock_shard(&key).insert(key, value);
+    }
+
+    // Values are cloned out, since the shard is unlocked again before this returns.
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.lock_shard(key).get(key).cloned()
+    }
+
+    // Runs `f` on the cached value while its shard is locked, which avoids the clone.
+    pub fn with_value<R, F: FnOnce(&V) -> R>(&self, key: &K, f: F) -> Option<R> {
+        self.lock_shard(key).get(key).map(f)
+    }
+
+    // `f` runs with the shard locked, so it is called at most once per missing key.
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&self, key: K, weight: u32, f: F) -> V
+    where
+        V: Clone,
+    {
+        self.lock_shard(&key)
+            .get_or_insert_with(key, weight, f)
+            .clone()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.lock_shard(key).contains_key(key)
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.lock_shard(key).remove(key)
+    }
+
+    // Shards are locked one at a time, so concurrent writes may land in shards that
+    // were already cleared.
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            lock(shard).clear();
+        }
+    }
+
+    // Summed over the shards, which are read one at a time rather than all at once.
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = CacheStats::default();
+        for shard in self.shards.iter() {
+            stats += lock(shard).stats();
+        }
+        stats
+    }
+
+    // One snapshot per shard, in shard order. Each is taken under that shard's lock.
+    pub fn shard_stats(&self) -> Vec<ShardStats> {
+        self.shards
+            .iter()
+            .map(|shard| {
+                let shard = lock(shard);
+                ShardStats {
+                    stats: shard.stats(),
+                    len: shard.len(),
+                }
+            })
+            .collect()
+    }
+
+    // Shards serving more than `factor` times the mean number of lookups, busiest first.
+    // Keys are spread by hash, so a shard stays hot because of a few popular keys, and
+    // only more shards than such keys will help.
+    pub fn hot_shards(&self, factor: f64) -> Vec<usize> {
+        let shards = self.shard_stats();
+        let mean = mean_lookups(&shards);
+        let mut hot: Vec<usize> = (0..shards.len())
+            .filter(|&idx| shards[idx].stats.lookups() as f64 > factor * mean)
+            .collect();
+        hot.sort_by_key(|&idx| std::cmp::Reverse(shards[idx].stats.lookups()));
+        hot
+    }
+
+    // The busiest shard's lookups over the mean: 1.0 when the load is even, up to the
+    // shard count when one shard serves everything. 1.0 before any lookups.
+    pub fn lookup_skew(&self) -> f64 {
+        let shards = self.shard_stats();
+        let mean = mean_lookups(&shards);
+        if mean == 0.0 {
+            return 1.0;
+        }
+        let busiest = shards.iter().map(|shard| shard.stats.lookups()).max();
+        busiest.unwrap_or(0) as f64 / mean
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| lock(shard).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| lock(shard).is_empty())
+    }
+
+    fn lock_shard(&self, key: &K) -> MutexGuard<'_, CacheType<K, V, S>> {
+        lock(&self.shards[self.shard_index(key)])
+    }
+
+    fn shard_index(&self, key: &K) -> usize {
+        shard_of(self.hash_builder.hash_one(key), self.shards.len())
+    }
+}
+
+// What one shard of a `ShardedCache` holds and has served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardStats {
+    pub stats: CacheStats,
+    pub len: usize,
+}
+
+fn mean_lookups(shards: &[ShardStats]) -> f64 {
+    let total: u64 = shards.iter().map(|shard| shard.stats.lookups()).sum();
+    total as f64 / shards.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CacheBuilder;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_keys_stay_in_their_shard() {
+        let cache = ShardedCache::new(Policy::LRU, 256, 4);
+        assert_eq!(cache.shard_count(), 4);
+        for key in 0..32 {
+            cache.put(key, key * 10, 1);
+        }
+        assert_eq!(cache.len(), 32);
+        for key in 0..32 {
+            assert_eq!(cache.get(&key), Some(key * 10));
+        }
+        assert_eq!(cache.with_value(&3, |value| value + 1), Some(31));
+        assert_eq!(cache.remove(&3), Some(30));
+        assert!(!cache.contains_key(&3));
+        assert_eq!(cache.get(&3), None);
+    }
+
+    #[test]
+    fn test_capacity_is_split_between_shards() {
+        let cache = ShardedCache::new(Policy::FIFO, 10, 4);
+        for key in 0..1000 {
+            cache.insert(key, ());
+        }
+        // Each of the 4 shards holds 3 entries
+        assert_eq!(cache.len(), 12);
+        assert_eq!(cache.stats().evictions, 1000 - 12);
+    }
+
+    #[test]
+    fn test_stats_and_clear_cover_every_shard() {
+        let cache = ShardedCache::new(Policy::LFU, 800, 8);
+        for key in 0..50 {
+            cache.put(key, key, 2);
+        }
+        for key in 0..100 {
+            cache.get(&key);
+        }
+        let stats = cache.stats();
+        assert_eq!(stats.insertions, 50);
+        assert_eq!(stats.hits, 50);
+        assert_eq!(stats.misses, 50);
+        assert_eq!(stats.current_weight, 100);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats().current_weight, 0);
+    }
+
+    #[test]
+    fn test_per_shard_stats_add_up() {
+        let cache = ShardedCache::new(Policy::LRU, 400, 4);
+        for key in 0..100 {
+            cache.put(key, key, 1);
+            cache.get(&key);
+        }
+        let shards = cache.shard_stats();
+        assert_eq!(shards.len(), 4);
+        let mut total = CacheStats::default();
+        for shard in &shards {
+            assert_eq!(shard.len as u64, shard.stats.current_weight);
+            total += shard.stats;
+        }
+        assert_eq!(total, cache.stats());
+        assert_eq!(shards.iter().map(|shard| shard.len).sum::<usize>(), 100);
+    }
+
+    #[test]
+    fn test_detects_hot_shard() {
+        let cache = ShardedCache::new(Policy::LRU, 400, 4);
+        assert_eq!(cache.lookup_skew(), 1.0);
+        assert!(cache.hot_shards(1.5).is_empty());
+
+        for key in 0..100 {
+            cache.put(key, key, 1);
+            cache.get(&key);
+        }
+        for _ in 0..1000 {
+            cache.get(&7);
+        }
+        let hot = cache.shard_index(&7);
+        assert_eq!(cache.hot_shards(1.5), vec![hot]);
+        assert!(cache.lookup_skew() > 3.0);
+    }
+
+    #[test]
+    fn test_from_built_shards() {
+        let shards = (0..2)
+            .map(|_| CacheBuilder::new(4).policy(Policy::ARC).build())
+            .collect();
+        let cache = ShardedCache::from_shards(shards, RandomState::new());
+        assert_eq!(cache.get_or_insert_with("a", 1, || 1), 1);
+        assert_eq!(cache.get_or_insert_with("a", 1, || 2), 1);
+        assert_eq!(cache.stats().insertions, 1);
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let cache = Arc::new(ShardedCache::new(Policy::Landlord, 8192, 8));
+        let workers: Vec<_> = (0..4u64)
+            .map(|worker| {
+                let cache = cache.clone();
+                thread::spawn(move || {
+                    for i in 0..200 {
+                        let key = worker * 1000 + i;
+                        cache.put(key, key, 1);
+                        assert_eq!(cache.get(&key), Some(key));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(cache.len(), 800);
+        assert_eq!(cache.stats().hits, 800);
+    }
+}
@@ -0,0 +1,460 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::lru::LRUCache;
+use crate::stats::CacheStats;
+use crate::trace;
+use crate::{Cache, EvictionListener, NewCache};
+
+const POLICY: &str = "slru";
+
+// The share of the capacity reserved for the protected segment by `new`.
+const DEFAULT_PROTECTED_RATIO: f64 = 0.8;
+
+// Segmented LRU: new keys enter a probationary segment and only move to the protected one
+// when they are used again. Victims come from the probationary segment, so a burst of
+// one-off keys cannot flush entries that have proven themselves. Protected entries that
+// fall off the end of their segment get another round in probation.
+//
+// Both segments are LRU lists holding each value with its weight.
+pub struct SLRUCache<K, V, S = RandomState> {
+    capacity: usize,
+    protected_capacity: usize,
+    probationary: LRUCache<K, (V, u32), S>,
+    protected: LRUCache<K, (V, u32), S>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    stats: CacheStats,
+}
+
+pub struct Iter<'a, K, V> {
+    protected: crate::lru::Iter<'a, K, (V, u32)>,
+    probationary: crate::lru::Iter<'a, K, (V, u32)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.protected
+            .next()
+            .or_else(|| self.probationary.next())
+            .map(|(key, (value, _))| (key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.protected.len() + self.probationary.len();
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<K: Clone + Hash + Eq, V> SLRUCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        let protected_capacity = (capacity as f64 * DEFAULT_PROTECTED_RATIO) as usize;
+        SLRUCache::with_protected_capacity(capacity, protected_capacity)
+    }
+
+    pub fn with_protected_capacity(capacity: usize, protected_capacity: usize) -> Self {
+        SLRUCache::with_hasher(capacity, protected_capacity, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> SLRUCache<K, V, S> {
+    // `protected_capacity` entries of the `capacity` are kept for keys used more than once.
+    pub fn with_hasher(capacity: usize, protected_capacity: usize, hash_builder: S) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        assert!(
+            protected_capacity <= capacity,
+            "Protected capacity must not exceed the capacity"
+        );
+        SLRUCache {
+            capacity,
+            protected_capacity,
+            // Sized so that neither segment ever evicts on its own
+            probationary: LRUCache::with_hasher(capacity, hash_builder.clone()),
+            protected: LRUCache::with_hasher(protected_capacity.max(1), hash_builder),
+            eviction_listener: None,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let _span = trace::get_span(POLICY, key);
+        if !self.touch(key) {
+            return None;
+        }
+        self.peek(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if !self.touch(key) {
+            return None;
+        }
+        let segment = if self.protected.contains_key(key) {
+            &mut self.protected
+        } else {
+            &mut self.probationary
+        };
+        segment.get_mut(key).map(|(value, _)| value)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.protected
+            .peek(key)
+            .or_else(|| self.probationary.peek(key))
+            .map(|(value, _)| value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.protected.contains_key(key) || self.probationary.contains_key(key)
+    }
+
+    // Writing a cached key counts as a use, so it ends up protected.
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        let _span = trace::put_span(POLICY, &key, weight);
+        if self.contains_key(&key) {
+            self.remove(&key);
+            self.protect(key, value, weight);
+            return;
+        }
+
+        self.stats.insertions += 1;
+        trace::insert(POLICY, &key, weight);
+        while !self.is_empty() && self.len() >= self.capacity {
+            self.evict();
+        }
+        self.probationary.put(key, (value, weight), weight);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.protected
+            .remove(key)
+            .or_else(|| self.probationary.remove(key))
+            .map(|(value, _)| value)
+    }
+
+    // The entry `pop_victim` would remove, left in place.
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.probationary
+            .peek_victim()
+            .or_else(|| self.protected.peek_victim())
+    }
+
+    // Probationary entries go first, least recently used first.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.probationary
+            .pop_victim()
+            .or_else(|| self.protected.pop_victim())
+            .map(|(key, (value, _))| (key, value))
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        self.protected.retain(|key, (value, _)| f(key, value));
+        self.probationary.retain(|key, (value, _)| f(key, value));
+    }
+
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.eviction_listener = Some(Box::new(listener));
+    }
+
+    pub fn clear(&mut self) {
+        self.protected.clear();
+        self.probationary.clear();
+    }
+
+    // Protected entries first, then probationary ones, each from most recently used.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            protected: self.protected.iter(),
+            probationary: self.probationary.iter(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn is_protected(&self, key: &K) -> bool {
+        self.protected.contains_key(key)
+    }
+
+    pub fn protected_capacity(&self) -> usize {
+        self.protected_capacity
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            current_weight: self.protected.stats().current_weight
+                + self.probationary.stats().current_weight,
+            ..self.stats
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.protected.len() + self.probationary.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Records the lookup and moves a hit to the front of the protected segment.
+    fn touch(&mut self, key: &K) -> bool {
+        if self.protected.get(key).is_some() {
+            self.stats.hits += 1;
+            return true;
+        }
+        match self.probationary.remove(key) {
+            Some((value, weight)) => {
+                self.stats.hits += 1;
+                self.protect(key.clone(), value, weight);
+                true
+            }
+            None => {
+                self.stats.misses += 1;
+                trace::miss(POLICY, key);
+                false
+            }
+        }
+    }
+
+    // A full protected segment hands its least recently used entry back to probation.
+    fn protect(&mut self, key: K, value: V, weight: u32) {
+        if self.protected_capacity == 0 {
+            self.probationary.put(key, (value, weight), weight);
+            return;
+        }
+        if self.protected.len() >= self.protected_capacity {
+            if let Some((demoted, slot)) = self.protected.pop_victim() {
+                let demoted_weight = slot.1;
+                self.probationary.put(demoted, slot, demoted_weight);
+            }
+        }
+        self.protected.put(key, (value, weight), weight);
+    }
+
+    // Capacity-driven eviction; the listener sees every entry dropped this way.
+    fn evict(&mut self) {
+        if let Some((key, value)) = self.pop_victim() {
+            self.stats.evictions += 1;
+            trace::eviction(POLICY, &key);
+            if let Some(listener) = self.eviction_listener.as_mut() {
+                listener(key, value);
+            }
+        }
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher + Clone> IntoIterator for &'a SLRUCache<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Extend<(K, V, u32)> for SLRUCache<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V, u32)>>(&mut self, iter: I) {
+        for (key, value, weight) in iter {
+            self.put(key, value, weight);
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Cache<K, V> for SLRUCache<K, V, S> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone + Default> NewCache<K, V>
+    for SLRUCache<K, V, S>
+{
+    fn new(capacity: usize) -> Self {
+        let protected_capacity = (capacity as f64 * DEFAULT_PROTECTED_RATIO) as usize;
+        SLRUCache::with_hasher(capacity, protected_capacity, S::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "Capacity must be greater than 0")]
+    fn test_new_cache_zero_capacity() {
+        let _cache: SLRUCache<String, i32> = SLRUCache::new(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Protected capacity must not exceed the capacity")]
+    fn test_oversized_protected_segment() {
+        let _cache: SLRUCache<String, i32> = SLRUCache::with_protected_capacity(2, 3);
+    }
+
+    #[test]
+    fn test_default_split() {
+        let cache: SLRUCache<i32, i32> = SLRUCache::new(10);
+        assert_eq!(cache.protected_capacity(), 8);
+    }
+
+    #[test]
+    fn test_second_use_protects() {
+        let mut cache = SLRUCache::new(4);
+        cache.put(1, "one", 1);
+        assert!(!cache.is_protected(&1));
+
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert!(cache.is_protected(&1));
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn test_one_off_keys_do_not_flush_protected_entries() {
+        let mut cache = SLRUCache::with_protected_capacity(4, 2);
+        for key in 0..2 {
+            cache.put(key, "hot", 1);
+            cache.get(&key);
+        }
+
+        for key in 100..200 {
+            cache.put(key, "once", 1);
+        }
+        assert!(cache.contains_key(&0));
+        assert!(cache.contains_key(&1));
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.stats().evictions, 98);
+    }
+
+    #[test]
+    fn test_full_protected_segment_demotes() {
+        let mut cache = SLRUCache::with_protected_capacity(3, 1);
+        cache.put(0, "zero", 1);
+        cache.put(1, "one", 1);
+        cache.get(&1);
+        cache.put(2, "two", 1);
+        cache.get(&2);
+
+        // Key 1 is back on probation, as its most recently used entry
+        assert!(cache.is_protected(&2));
+        assert!(!cache.is_protected(&1));
+        cache.put(3, "three", 1);
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&0));
+    }
+
+    #[test]
+    fn test_without_protected_segment() {
+        let mut cache = SLRUCache::with_protected_capacity(2, 0);
+        cache.put(1, vec![1], 1);
+        cache.put(2, vec![2], 1);
+        cache.get_mut(&1).unwrap().push(10);
+
+        // Plain LRU
+        cache.put(3, vec![3], 1);
+        assert_eq!(cache.get(&1), Some(&vec![1, 10]));
+        assert!(!cache.contains_key(&2));
+    }
+
+    #[test]
+    fn test_update_protects_and_reweighs() {
+        let mut cache = SLRUCache::new(4);
+        cache.put(1, "one", 5);
+        cache.put(1, "ONE", 7);
+
+        assert!(cache.is_protected(&1));
+        assert_eq!(cache.peek(&1), Some(&"ONE"));
+        assert_eq!(cache.stats().current_weight, 7);
+        assert_eq!(cache.stats().insertions, 1);
+    }
+
+    #[test]
+    fn test_pop_victim_prefers_probation() {
+        let mut cache = SLRUCache::new(4);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.put(3, "three", 1);
+        cache.get(&1);
+
+        assert_eq!(cache.pop_victim(), Some((2, "two")));
+        assert_eq!(cache.pop_victim(), Some((3, "three")));
+        assert_eq!(cache.pop_victim(), Some((1, "one")));
+        assert_eq!(cache.pop_victim(), None);
+    }
+
+    #[test]
+    fn test_iter_remove_and_retain() {
+        let mut cache = SLRUCache::new(4);
+        cache.extend(vec![(1, "one", 1), (2, "two", 1), (3, "three", 1)]);
+        cache.get(&2);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&2, &3, &1]);
+        assert_eq!(cache.iter().len(), 3);
+
+        assert_eq!(cache.remove(&2), Some("two"));
+        cache.retain(|&key, _| key != 3);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&1]);
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_eviction_listener() {
+        use std::sync::{Arc, Mutex};
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = SLRUCache::new(2);
+        cache.set_eviction_listener(move |key, _| sink.lock().unwrap().push(key));
+
+        cache.put(1, "one", 1);
+        cache.get(&1);
+        cache.put(2, "two", 1);
+        cache.put(3, "three", 1);
+        assert_eq!(*evicted.lock().unwrap(), vec![2]);
+    }
+}
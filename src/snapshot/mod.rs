@@ -0,0 +1,537 @@
+// A compact binary format for saving a cache's contents with their policy metadata,
+// independent of serde. A snapshot is a header (magic, format version, policy), the
+// policy's entries and a CRC-32 of everything before it. Integers are little-endian and
+// fixed width, and strings, byte vectors and sequences are prefixed with their length.
+// Listeners, the weigher, the observer, the clock and expiration are configuration rather
+// than contents, so they are not saved, by snapshots or by serde, and a restored cache
+// starts without them.
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::Policy;
+
+pub(crate) mod crc;
+
+use self::crc::Crc32;
+
+const MAGIC: [u8; 4] = *b"SECS";
+const VERSION: u16 = 1;
+
+// Sequences are allocated in steps of at most this many elements, so a corrupt length
+// fails on the missing data instead of asking for an enormous allocation up front.
+const ALLOCATION_STEP: usize = 1 << 16;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    // The data does not start with the snapshot magic bytes.
+    NotASnapshot,
+    UnsupportedVersion(u16),
+    // The snapshot holds a different policy than the cache type loading it.
+    WrongPolicy { expected: Policy, found: Policy },
+    ChecksumMismatch,
+    // The data is well formed but cannot be a cache, such as a key saved twice.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "snapshot I/O failed: {}", error),
+            SnapshotError::NotASnapshot => write!(f, "not a cache snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::WrongPolicy { expected, found } => write!(
+                f,
+                "snapshot holds a {:?} cache, expected {:?}",
+                found, expected
+            ),
+            SnapshotError::ChecksumMismatch => write!(f, "snapshot checksum mismatch"),
+            SnapshotError::Invalid(reason) => write!(f, "invalid snapshot: {}", reason),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+// Implemented by keys and values that can be written to a snapshot.
+pub trait Encode {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+
+    // Writes a run of values, which bytes do in one call rather than one at a time.
+    fn encode_slice<W: Write>(items: &[Self], writer: &mut W) -> io::Result<()>
+    where
+        Self: Sized,
+    {
+        for item in items {
+            item.encode(writer)?;
+        }
+        Ok(())
+    }
+}
+
+// Implemented by keys and values that can be read back from a snapshot.
+pub trait Decode: Sized {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError>;
+
+    fn decode_vec<R: Read>(len: usize, reader: &mut R) -> Result<Vec<Self>, SnapshotError> {
+        let mut items = Vec::with_capacity(len.min(ALLOCATION_STEP));
+        for _ in 0..len {
+            items.push(Self::decode(reader)?);
+        }
+        Ok(items)
+    }
+}
+
+macro_rules! impl_number {
+    ($($number:ty),*) => {
+        $(
+            impl Encode for $number {
+                fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+            }
+
+            impl Decode for $number {
+                fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+                    let mut bytes = [0; std::mem::size_of::<$number>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(<$number>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_number!(u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl Encode for u8 {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&[*self])
+    }
+
+    fn encode_slice<W: Write>(items: &[u8], writer: &mut W) -> io::Result<()> {
+        writer.write_all(items)
+    }
+}
+
+impl Decode for u8 {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn decode_vec<R: Read>(len: usize, reader: &mut R) -> Result<Vec<u8>, SnapshotError> {
+        let mut bytes = Vec::with_capacity(len.min(ALLOCATION_STEP));
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(bytes)
+    }
+}
+
+// Sizes are always written as 64 bits, so snapshots move between platforms.
+impl Encode for usize {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as u64).encode(writer)
+    }
+}
+
+impl Decode for usize {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        let value = u64::decode(reader)?;
+        usize::try_from(value).map_err(|_| SnapshotError::Invalid("size too large"))
+    }
+}
+
+impl Encode for bool {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        u8::from(*self).encode(writer)
+    }
+}
+
+impl Decode for bool {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        match u8::decode(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::Invalid("bad boolean")),
+        }
+    }
+}
+
+impl Encode for str {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.len().encode(writer)?;
+        writer.write_all(self.as_bytes())
+    }
+}
+
+impl Encode for String {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.as_str().encode(writer)
+    }
+}
+
+impl Decode for String {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        let len = usize::decode(reader)?;
+        let bytes = u8::decode_vec(len, reader)?;
+        String::from_utf8(bytes).map_err(|_| SnapshotError::Invalid("string is not UTF-8"))
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.len().encode(writer)?;
+        T::encode_slice(self, writer)
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.as_slice().encode(writer)
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        let len = usize::decode(reader)?;
+        T::decode_vec(len, reader)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Some(value) => {
+                true.encode(writer)?;
+                value.encode(writer)
+            }
+            None => false.encode(writer),
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        match bool::decode(reader)? {
+            true => Ok(Some(T::decode(reader)?)),
+            false => Ok(None),
+        }
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.0.encode(writer)?;
+        self.1.encode(writer)
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        Ok((A::decode(reader)?, B::decode(reader)?))
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (**self).encode(writer)
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for Arc<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (**self).encode(writer)
+    }
+}
+
+impl<T: Decode> Decode for Arc<T> {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        Ok(Arc::new(T::decode(reader)?))
+    }
+}
+
+impl Encode for Duration {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.as_secs().encode(writer)?;
+        self.subsec_nanos().encode(writer)
+    }
+}
+
+impl Decode for Duration {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        let secs = u64::decode(reader)?;
+        let nanos = u32::decode(reader)?;
+        if nanos >= 1_000_000_000 {
+            return Err(SnapshotError::Invalid("bad duration"));
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+// Encodes and decodes a struct field by field, in the order given.
+macro_rules! impl_codec {
+    ($name:ident $(<$($param:ident),*>)? { $($field:ident),* $(,)? }) => {
+        impl$(<$($param: $crate::snapshot::Encode),*>)? $crate::snapshot::Encode
+            for $name$(<$($param),*>)?
+        {
+            fn encode<Wr: std::io::Write>(&self, writer: &mut Wr) -> std::io::Result<()> {
+                $($crate::snapshot::Encode::encode(&self.$field, writer)?;)*
+                Ok(())
+            }
+        }
+
+        impl$(<$($param: $crate::snapshot::Decode),*>)? $crate::snapshot::Decode
+            for $name$(<$($param),*>)?
+        {
+            fn decode<Rd: std::io::Read>(
+                reader: &mut Rd,
+            ) -> Result<Self, $crate::snapshot::SnapshotError> {
+                Ok($name {
+                    $($field: $crate::snapshot::Decode::decode(reader)?,)*
+                })
+            }
+        }
+    };
+}
+
+pub(crate) use impl_codec;
+
+// Feeds everything that passes through into a checksum.
+struct Checksummed<T> {
+    inner: T,
+    crc: Crc32,
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(bytes)?;
+        self.crc.update(&bytes[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, bytes: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(bytes)?;
+        self.crc.update(&bytes[..read]);
+        Ok(read)
+    }
+}
+
+fn policy_tag(policy: Policy) -> u8 {
+    match policy {
+        Policy::LRU => 0,
+        Policy::LFU => 1,
+        Policy::Landlord => 2,
+        Policy::LFUDA => 3,
+        Policy::ARC => 4,
+        Policy::FIFO => 5,
+        Policy::Random => 6,
+        Policy::MRU => 7,
+    }
+}
+
+fn policy_from_tag(tag: u8) -> Option<Policy> {
+    match tag {
+        0 => Some(Policy::LRU),
+        1 => Some(Policy::LFU),
+        2 => Some(Policy::Landlord),
+        3 => Some(Policy::LFUDA),
+        4 => Some(Policy::ARC),
+        5 => Some(Policy::FIFO),
+        6 => Some(Policy::Random),
+        7 => Some(Policy::MRU),
+        _ => None,
+    }
+}
+
+// Writes a whole snapshot through a buffer of its own, so an unbuffered file is fine.
+pub(crate) fn write<W: Write, T: Encode>(
+    writer: W,
+    policy: Policy,
+    body: &T,
+) -> Result<(), SnapshotError> {
+    let mut writer = Checksummed {
+        inner: BufWriter::new(writer),
+        crc: Crc32::new(),
+    };
+    writer.write_all(&MAGIC)?;
+    VERSION.encode(&mut writer)?;
+    policy_tag(policy).encode(&mut writer)?;
+    body.encode(&mut writer)?;
+    let checksum = writer.crc.finish();
+    let mut writer = writer.inner;
+    checksum.encode(&mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+// Reads a snapshot in two steps, so a caller that accepts any policy can decode the body
+// once the header says which one it holds.
+pub(crate) struct SnapshotReader<R> {
+    reader: Checksummed<BufReader<R>>,
+    policy: Policy,
+}
+
+impl<R: Read> SnapshotReader<R> {
+    pub(crate) fn open(reader: R) -> Result<Self, SnapshotError> {
+        let mut reader = Checksummed {
+            inner: BufReader::new(reader),
+            crc: Crc32::new(),
+        };
+        let mut magic = [0; 4];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|error| match error.kind() {
+                io::ErrorKind::UnexpectedEof => SnapshotError::NotASnapshot,
+                _ => SnapshotError::Io(error),
+            })?;
+        if magic != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = u16::decode(&mut reader)?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let policy = policy_from_tag(u8::decode(&mut reader)?)
+            .ok_or(SnapshotError::Invalid("unknown policy"))?;
+        Ok(SnapshotReader { reader, policy })
+    }
+
+    pub(crate) fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    // Decodes the body of a snapshot that must hold the given policy, and checks it
+    // against the checksum that follows it.
+    pub(crate) fn body<T: Decode>(mut self, policy: Policy) -> Result<T, SnapshotError> {
+        if self.policy != policy {
+            return Err(SnapshotError::WrongPolicy {
+                expected: policy,
+                found: self.policy,
+            });
+        }
+        let body = T::decode(&mut self.reader)?;
+        let expected = self.reader.crc.finish();
+        if u32::decode(&mut self.reader.inner)? != expected {
+            return Err(SnapshotError::ChecksumMismatch);
+        }
+        Ok(body)
+    }
+}
+
+// The checks every policy makes before restoring saved entries.
+pub(crate) fn check_len(
+    capacity: usize,
+    max_weight: Option<u64>,
+    len: usize,
+) -> Result<(), &'static str> {
+    if capacity == 0 {
+        return Err("capacity must be greater than 0");
+    }
+    if max_weight.is_none() && len > capacity {
+        return Err("more entries than the capacity allows");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read<T: Decode>(bytes: &[u8], policy: Policy) -> Result<T, SnapshotError> {
+        SnapshotReader::open(bytes)?.body(policy)
+    }
+
+    fn round_trip<T: Encode + Decode>(value: &T) -> T {
+        let mut bytes = Vec::new();
+        write(&mut bytes, Policy::LRU, value).unwrap();
+        read(bytes.as_slice(), Policy::LRU).unwrap()
+    }
+
+    #[test]
+    fn test_values_round_trip() {
+        assert_eq!(round_trip(&0xDEAD_BEEFu32), 0xDEAD_BEEF);
+        assert_eq!(round_trip(&-7i64), -7);
+        assert_eq!(round_trip(&1.5f64), 1.5);
+        assert_eq!(round_trip(&"résumé".to_string()), "résumé");
+        assert_eq!(round_trip(&vec![1u8, 2, 3]), vec![1, 2, 3]);
+        assert_eq!(
+            round_trip(&vec![(1u16, Some(true)), (2, None)]),
+            vec![(1, Some(true)), (2, None)]
+        );
+        assert_eq!(round_trip(&Duration::new(5, 250)), Duration::new(5, 250));
+    }
+
+    #[test]
+    fn test_detects_corruption() {
+        let mut bytes = Vec::new();
+        write(&mut bytes, Policy::LRU, &"some cached result".to_string()).unwrap();
+
+        let mut flipped = bytes.clone();
+        let last = flipped.len() - 6;
+        flipped[last] ^= 0x20;
+        let result = read::<String>(flipped.as_slice(), Policy::LRU);
+        assert!(matches!(result, Err(SnapshotError::ChecksumMismatch)));
+
+        let truncated = &bytes[..bytes.len() - 6];
+        let result = read::<String>(truncated, Policy::LRU);
+        assert!(matches!(result, Err(SnapshotError::Io(_))));
+    }
+
+    #[test]
+    fn test_checks_the_header() {
+        let mut bytes = Vec::new();
+        write(&mut bytes, Policy::ARC, &1u8).unwrap();
+        let result = read::<u8>(bytes.as_slice(), Policy::LRU);
+        assert!(matches!(
+            result,
+            Err(SnapshotError::WrongPolicy {
+                expected: Policy::LRU,
+                found: Policy::ARC
+            })
+        ));
+
+        bytes[4] = 9;
+        let result = read::<u8>(bytes.as_slice(), Policy::ARC);
+        assert!(matches!(result, Err(SnapshotError::UnsupportedVersion(9))));
+
+        let result = read::<u8>(&b"{\"LRU\":{}}"[..], Policy::LRU);
+        assert!(matches!(result, Err(SnapshotError::NotASnapshot)));
+    }
+
+    #[test]
+    fn test_corrupt_length_does_not_allocate_it() {
+        let mut bytes = Vec::new();
+        write(&mut bytes, Policy::LRU, &u64::MAX).unwrap();
+        let result = read::<Vec<u32>>(bytes.as_slice(), Policy::LRU);
+        assert!(result.is_err());
+    }
+}
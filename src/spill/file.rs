@@ -0,0 +1,227 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use super::VictimStore;
+use crate::snapshot::{Decode, Encode};
+
+const EXTENSION: &str = "spill";
+
+struct Spilled {
+    id: u64,
+    len: u64,
+}
+
+// Spills each value to a file of its own in a directory, encoded like a snapshot, so the
+// filesystem reclaims the space of values taken back. Only the index is kept in memory.
+// Once the files would hold more than `max_bytes`, the oldest are dropped.
+//
+// The tier does not outlive the store: files left in the directory by an earlier one are
+// deleted when a new one opens it, and its own when it is dropped. A value that cannot be
+// written or read back is dropped, as if it had never been spilled.
+pub struct FileStore<K, V, S = RandomState> {
+    dir: PathBuf,
+    index: HashMap<K, Spilled, S>,
+    // Keys in the order they were spilled. A key spilled again is skipped at its older
+    // position, which no longer matches the id in the index.
+    order: VecDeque<(u64, K)>,
+    next_id: u64,
+    bytes: u64,
+    max_bytes: u64,
+    value: PhantomData<fn(V) -> V>,
+}
+
+impl<K: Clone + Hash + Eq, V> FileStore<K, V> {
+    pub fn open<P: Into<PathBuf>>(dir: P, max_bytes: u64) -> io::Result<Self> {
+        FileStore::with_hasher(dir, max_bytes, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher> FileStore<K, V, S> {
+    pub fn with_hasher<P: Into<PathBuf>>(
+        dir: P,
+        max_bytes: u64,
+        hash_builder: S,
+    ) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == EXTENSION)
+            {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(FileStore {
+            dir,
+            index: HashMap::with_hasher(hash_builder),
+            order: VecDeque::new(),
+            next_id: 0,
+            bytes: 0,
+            max_bytes,
+            value: PhantomData,
+        })
+    }
+
+    // The total size of the spilled values on disk.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.index.contains_key(key)
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{}.{}", id, EXTENSION))
+    }
+
+    fn write(&self, path: &Path, value: &V) -> io::Result<u64>
+    where
+        V: Encode,
+    {
+        let mut writer = BufWriter::new(File::create(path)?);
+        value.encode(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().metadata().map(|metadata| metadata.len())
+    }
+
+    fn forget(&mut self, key: &K) -> Option<Spilled> {
+        let spilled = self.index.remove(key)?;
+        self.bytes -= spilled.len;
+        // Best effort: a file that cannot be deleted is cleaned up by the next store to open the directory
+        let _ = fs::remove_file(self.path(spilled.id));
+        Some(spilled)
+    }
+
+    fn drop_oldest(&mut self) {
+        while self.bytes > self.max_bytes {
+            let (id, key) = match self.order.pop_front() {
+                Some(oldest) => oldest,
+                None => return,
+            };
+            if self.index.get(&key).is_some_and(|spilled| spilled.id == id) {
+                self.forget(&key);
+            }
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V: Encode + Decode, S: BuildHasher> VictimStore<K, V>
+    for FileStore<K, V, S>
+{
+    fn store(&mut self, key: K, value: V) {
+        self.forget(&key);
+        let id = self.next_id;
+        self.next_id += 1;
+        let path = self.path(id);
+        match self.write(&path, &value) {
+            Ok(len) => {
+                self.bytes += len;
+                self.index.insert(key.clone(), Spilled { id, len });
+                self.order.push_back((id, key));
+                self.drop_oldest();
+                if self.order.len() > 2 * self.index.len() + 64 {
+                    let index = &self.index;
+                    self.order.retain(|(id, key)| {
+                        index.get(key).is_some_and(|spilled| spilled.id == *id)
+                    });
+                }
+            }
+            Err(_) => {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    fn take(&mut self, key: &K) -> Option<V> {
+        let id = self.index.get(key)?.id;
+        let value = File::open(self.path(id))
+            .ok()
+            .and_then(|file| V::decode(&mut BufReader::new(file)).ok());
+        self.forget(key);
+        value
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.forget(key);
+    }
+
+    fn clear(&mut self) {
+        let keys: Vec<K> = self.index.keys().cloned().collect();
+        for key in keys {
+            self.forget(&key);
+        }
+        self.order.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+}
+
+impl<K, V, S> Drop for FileStore<K, V, S> {
+    fn drop(&mut self) {
+        for spilled in self.index.values() {
+            let _ = fs::remove_file(self.dir.join(format!("{}.{}", spilled.id, EXTENSION)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("spill-{}-{}", process::id(), name))
+    }
+
+    fn files_in(dir: &Path) -> usize {
+        fs::read_dir(dir).unwrap().count()
+    }
+
+    #[test]
+    fn test_values_round_trip_through_files() {
+        let dir = scratch("round-trip");
+        let mut store: FileStore<u32, String> = FileStore::open(&dir, 1 << 20).unwrap();
+        store.store(1, "one".to_string());
+        store.store(2, "two".to_string());
+        store.store(1, "ONE".to_string());
+        assert_eq!(store.len(), 2);
+        assert_eq!(files_in(&dir), 2);
+
+        assert_eq!(store.take(&1), Some("ONE".to_string()));
+        assert_eq!(store.take(&1), None);
+        assert_eq!(store.bytes(), 8 + 3);
+        drop(store);
+        assert_eq!(files_in(&dir), 0);
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_drops_the_oldest_past_the_limit() {
+        let dir = scratch("limit");
+        let mut store: FileStore<u32, u64> = FileStore::open(&dir, 20).unwrap();
+        for key in 0..4 {
+            store.store(key, u64::from(key));
+        }
+        assert_eq!(store.len(), 2);
+        assert!(!store.contains_key(&1));
+        assert!(store.contains_key(&3));
+        assert_eq!(files_in(&dir), 2);
+
+        // Files left behind by an earlier store are not taken for this one's
+        std::mem::forget(store);
+        let store: FileStore<u32, u64> = FileStore::open(&dir, 20).unwrap();
+        assert!(store.is_empty());
+        assert_eq!(files_in(&dir), 0);
+        fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex};
+
+use crate::sync_cache::lock;
+use crate::{Cache, CacheStats, CacheType};
+
+mod file;
+
+pub use self::file::FileStore;
+
+// A second tier that keeps what the cache evicts. A store may drop entries of its own
+// accord, e.g. to stay within a size limit, since the cache only asks for them back.
+pub trait VictimStore<K, V> {
+    fn store(&mut self, key: K, value: V);
+
+    // Removes and returns an entry, which the cache is about to take back.
+    fn take(&mut self, key: &K) -> Option<V>;
+
+    fn remove(&mut self, key: &K);
+
+    fn clear(&mut self);
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Keeps every victim in memory, which is mainly useful in tests.
+impl<K: Hash + Eq, V, S: BuildHasher> VictimStore<K, V> for HashMap<K, V, S> {
+    fn store(&mut self, key: K, value: V) {
+        self.insert(key, value);
+    }
+
+    fn take(&mut self, key: &K) -> Option<V> {
+        HashMap::remove(self, key)
+    }
+
+    fn remove(&mut self, key: &K) {
+        HashMap::remove(self, key);
+    }
+
+    fn clear(&mut self) {
+        HashMap::clear(self);
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+}
+
+// Any policy backed by a victim store. Entries the policy evicts to make room are moved
+// to the store instead of being dropped, and a lookup that misses in memory takes the key
+// back from the store before it counts as a miss. Entries are only spilled on eviction:
+// expired and removed ones are gone from both tiers.
+//
+// The cache's eviction listener feeds the store, so one set beforehand is replaced. A
+// value taken back is stored with the weight the cache's weigher gives it, or 1.
+pub struct SpillCache<K, V, T, S = RandomState> {
+    cache: CacheType<K, V, S>,
+    // What the policy evicted during the current call, moved to the store before it returns
+    spilled: Arc<Mutex<Vec<(K, V)>>>,
+    store: T,
+    tier_hits: u64,
+}
+
+impl<K, V, T, S> SpillCache<K, V, T, S>
+where
+    K: Clone + Hash + Eq + Send + 'static,
+    V: Send + 'static,
+    T: VictimStore<K, V>,
+    S: BuildHasher + Clone,
+{
+    pub fn new(mut cache: CacheType<K, V, S>, store: T) -> Self {
+        let spilled = Arc::new(Mutex::new(Vec::new()));
+        let sink = spilled.clone();
+        cache.set_eviction_listener(move |key, value| lock(&sink).push((key, value)));
+        SpillCache {
+            cache,
+            spilled,
+            store,
+            tier_hits: 0,
+        }
+    }
+
+    // A value found in the store is counted as a hit, since no miss reaches the caller.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        if !self.cache.contains_key(key) {
+            if let Some(value) = self.store.take(key) {
+                self.tier_hits += 1;
+                self.cache.insert(key.clone(), value);
+                self.spill();
+            }
+        }
+        self.cache.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get(key)?;
+        self.cache.get_mut(key)
+    }
+
+    // Only looks in memory, like `contains_key`.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.cache.peek(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        self.store.remove(&key);
+        self.cache.put(key, value, weight);
+        self.spill();
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        match self.cache.remove(key) {
+            Some(value) => Some(value),
+            None => self.store.take(key),
+        }
+    }
+
+    // Removes the policy's victim from memory without spilling it.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.cache.pop_victim()
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.cache.peek_victim()
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+        self.store.clear();
+    }
+
+    // Lookups answered from the store instead of missing.
+    pub fn tier_hits(&self) -> u64 {
+        self.tier_hits
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn store(&self) -> &T {
+        &self.store
+    }
+
+    // Entries in memory; the store has its own count.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    pub fn into_inner(self) -> (CacheType<K, V, S>, T) {
+        (self.cache, self.store)
+    }
+
+    fn spill(&mut self) {
+        for (key, value) in lock(&self.spilled).drain(..) {
+            self.store.store(key, value);
+        }
+    }
+}
+
+impl<K, V, T, S> Cache<K, V> for SpillCache<K, V, T, S>
+where
+    K: Clone + Hash + Eq + Send + 'static,
+    V: Send + 'static,
+    T: VictimStore<K, V>,
+    S: BuildHasher + Clone,
+{
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Policy, SyncCache};
+
+    fn spilling(capacity: usize) -> SpillCache<i32, String, HashMap<i32, String>> {
+        SpillCache::new(CacheType::new(Policy::LRU, capacity), HashMap::new())
+    }
+
+    #[test]
+    fn test_misses_are_served_from_the_store() {
+        let mut cache = spilling(2);
+        cache.put(1, "one".to_string(), 1);
+        cache.put(2, "two".to_string(), 1);
+        cache.put(3, "three".to_string(), 1);
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.store().len(), 1);
+
+        // Taking 1 back evicts 2, the least recently used, into the store in its place
+        assert_eq!(cache.get(&1), Some(&"one".to_string()));
+        assert_eq!(cache.tier_hits(), 1);
+        assert!(cache.store().contains_key(&2));
+        assert_eq!(cache.store().len(), 1);
+        assert_eq!(cache.stats().misses, 0);
+
+        assert_eq!(cache.get(&9), None);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn test_writes_reach_both_tiers() {
+        let mut cache = spilling(1);
+        cache.put(1, "one".to_string(), 1);
+        cache.put(2, "two".to_string(), 1);
+
+        // A new value replaces the spilled one instead of being shadowed by it
+        cache.put(1, "ONE".to_string(), 1);
+        assert!(!cache.store().contains_key(&1));
+        assert_eq!(cache.remove(&2), Some("two".to_string()));
+        assert!(cache.store().is_empty());
+
+        cache.clear();
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn test_shared_behind_a_lock() {
+        let cache = SyncCache::new(spilling(1));
+        cache.put(1, "one".to_string(), 1);
+        cache.put(2, "two".to_string(), 1);
+        assert_eq!(cache.get(&1), Some("one".to_string()));
+        assert_eq!(cache.lock().tier_hits(), 1);
+    }
+}
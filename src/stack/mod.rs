@@ -0,0 +1,257 @@
+use crate::postings::{PostingListCache, PostingSize};
+use crate::query::{Normalizer, QueryResultCache};
+use crate::{CacheStats, CacheType, Policy};
+
+// Term sets whose misses are counted towards intersection admission. The least recently
+// missed are forgotten first.
+const ADMISSION_CANDIDATES: usize = 1024;
+
+// Sizes and rules for every level of a `SearchCacheStack`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StackConfig {
+    result_capacity: usize,
+    result_policy: Policy,
+    normalizer: Normalizer,
+    intersection_budget: u64,
+    intersection_admission: u32,
+    posting_budget: u64,
+}
+
+impl StackConfig {
+    // Result pages are counted in entries; intersections and posting lists in bytes.
+    pub fn new(result_capacity: usize, intersection_budget: u64, posting_budget: u64) -> Self {
+        StackConfig {
+            result_capacity,
+            result_policy: Policy::LRU,
+            normalizer: Normalizer::new(),
+            intersection_budget,
+            intersection_admission: 2,
+            posting_budget,
+        }
+    }
+
+    pub fn result_policy(mut self, policy: Policy) -> Self {
+        self.result_policy = policy;
+        self
+    }
+
+    pub fn normalizer(mut self, normalizer: Normalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    // How many times a set of terms must miss the intersection cache before its
+    // intersection is cached. Defaults to 2, so that one-off combinations never push out
+    // recurring ones; 0 caches every intersection.
+    pub fn intersection_admission(mut self, misses: u32) -> Self {
+        self.intersection_admission = misses;
+        self
+    }
+}
+
+// The stats of every level, side by side.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StackStats {
+    pub results: CacheStats,
+    pub intersections: CacheStats,
+    pub postings: CacheStats,
+}
+
+impl StackStats {
+    // Every level added up.
+    pub fn total(&self) -> CacheStats {
+        let mut total = self.results;
+        total += self.intersections;
+        total += self.postings;
+        total
+    }
+}
+
+// The three levels of a search engine's cache in one place: result pages by normalized
+// query, intersections of posting lists by term set, and posting lists by term. A query
+// is answered by the highest level that has it: a result hit skips evaluation, an
+// intersection hit skips merging those terms' lists, and a posting list hit skips the
+// index read.
+pub struct SearchCacheStack<R, P> {
+    results: QueryResultCache<R>,
+    intersections: PostingListCache<Vec<String>, P>,
+    postings: PostingListCache<String, P>,
+    // Misses per term set not cached yet, counted towards admission
+    candidates: CacheType<Vec<String>, u32>,
+    intersection_admission: u32,
+}
+
+impl<R, P: PostingSize> SearchCacheStack<R, P> {
+    pub fn new(config: StackConfig) -> Self {
+        SearchCacheStack {
+            results: QueryResultCache::with_normalizer(
+                CacheType::new(config.result_policy, config.result_capacity),
+                config.normalizer,
+            ),
+            intersections: PostingListCache::new(config.intersection_budget),
+            postings: PostingListCache::new(config.posting_budget),
+            candidates: CacheType::new(Policy::LRU, ADMISSION_CANDIDATES),
+            intersection_admission: config.intersection_admission,
+        }
+    }
+
+    pub fn get_results(&mut self, query: &str) -> Option<&R> {
+        self.results.get(query)
+    }
+
+    pub fn put_results(&mut self, query: &str, results: R, weight: u32) {
+        self.results.put(query, results, weight);
+    }
+
+    // Terms are matched as a set, whatever their order or repetitions.
+    pub fn get_intersection(&mut self, terms: &[&str]) -> Option<&P> {
+        let key = term_set(terms);
+        if !self.intersections.contains_key(&key) {
+            let misses = self.candidates.get(&key).copied().unwrap_or(0);
+            self.candidates
+                .put(key.clone(), misses.saturating_add(1), 1);
+        }
+        self.intersections.get(&key)
+    }
+
+    // Caches the intersection if its terms have missed often enough, and returns whether
+    // it was cached.
+    pub fn put_intersection(&mut self, terms: &[&str], postings: P) -> bool {
+        let key = term_set(terms);
+        let misses = self.candidates.peek(&key).copied().unwrap_or(0);
+        if misses < self.intersection_admission && !self.intersections.contains_key(&key) {
+            return false;
+        }
+        self.candidates.remove(&key);
+        self.intersections.put(key, postings);
+        true
+    }
+
+    pub fn get_postings(&mut self, term: &str) -> Option<&P> {
+        self.postings.get(&term.to_string())
+    }
+
+    pub fn put_postings(&mut self, term: &str, postings: P) {
+        self.postings.put(term.to_string(), postings);
+    }
+
+    // Drops everything an update to the term's posting list makes stale: the list, every
+    // intersection including the term, and every result page whose query contains it.
+    // Returns how many entries were dropped across the levels. The term is compared with
+    // queries after normalization.
+    pub fn invalidate_term(&mut self, term: &str) -> usize {
+        let before = self.len();
+        self.postings.remove(&term.to_string());
+        self.intersections
+            .retain(|terms, _| !terms.iter().any(|cached| cached == term));
+        let normalized = self.results.normalize(term);
+        self.results
+            .retain(|query, _| !query.split(' ').any(|cached| cached == normalized));
+        before - self.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.results.clear();
+        self.intersections.clear();
+        self.postings.clear();
+        self.candidates.clear();
+    }
+
+    pub fn stats(&self) -> StackStats {
+        StackStats {
+            results: self.results.stats(),
+            intersections: self.intersections.stats(),
+            postings: self.postings.stats(),
+        }
+    }
+
+    pub fn results(&self) -> &QueryResultCache<R> {
+        &self.results
+    }
+
+    pub fn intersections(&self) -> &PostingListCache<Vec<String>, P> {
+        &self.intersections
+    }
+
+    pub fn postings(&self) -> &PostingListCache<String, P> {
+        &self.postings
+    }
+
+    // Entries in every level together.
+    pub fn len(&self) -> usize {
+        self.results.len() + self.intersections.len() + self.postings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn term_set(terms: &[&str]) -> Vec<String> {
+    let mut set: Vec<String> = terms.iter().map(|term| term.to_string()).collect();
+    set.sort_unstable();
+    set.dedup();
+    set
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack() -> SearchCacheStack<Vec<u32>, Vec<u32>> {
+        SearchCacheStack::new(StackConfig::new(10, 1024, 1024))
+    }
+
+    #[test]
+    fn test_intersections_are_admitted_after_repeated_misses() {
+        let mut stack = stack();
+        assert_eq!(stack.get_intersection(&["rust", "cache"]), None);
+        assert!(!stack.put_intersection(&["rust", "cache"], vec![4, 9]));
+        assert_eq!(stack.get_intersection(&["cache", "rust", "rust"]), None);
+        assert!(stack.put_intersection(&["rust", "cache"], vec![4, 9]));
+        assert_eq!(
+            stack.get_intersection(&["cache", "rust"]),
+            Some(&vec![4, 9])
+        );
+
+        let mut eager = SearchCacheStack::<(), Vec<u32>>::new(
+            StackConfig::new(10, 1024, 1024).intersection_admission(0),
+        );
+        assert!(eager.put_intersection(&["zig"], vec![1]));
+    }
+
+    #[test]
+    fn test_levels_keep_their_own_stats() {
+        let mut stack = stack();
+        stack.put_results("Rust  Cache", vec![4, 9], 1);
+        stack.put_postings("rust", vec![1, 4, 9]);
+        assert_eq!(stack.get_results("rust cache"), Some(&vec![4, 9]));
+        assert_eq!(stack.get_postings("rust"), Some(&vec![1, 4, 9]));
+        assert_eq!(stack.get_postings("cache"), None);
+
+        let stats = stack.stats();
+        assert_eq!(stats.results.hits, 1);
+        assert_eq!(stats.postings.hits, 1);
+        assert_eq!(stats.postings.misses, 1);
+        assert_eq!(stats.postings.current_weight, 12);
+        assert_eq!(stats.total().lookups(), 3);
+    }
+
+    #[test]
+    fn test_invalidating_a_term_reaches_every_level() {
+        let mut stack = stack();
+        stack.put_results("rust cache", vec![4], 1);
+        stack.put_results("rusty cache", vec![5], 1);
+        stack.put_postings("rust", vec![4]);
+        stack.put_postings("cache", vec![4, 5]);
+        stack.get_intersection(&["rust", "cache"]);
+        stack.get_intersection(&["rust", "cache"]);
+        stack.put_intersection(&["rust", "cache"], vec![4]);
+
+        assert_eq!(stack.invalidate_term("Rust"), 1);
+        assert_eq!(stack.invalidate_term("rust"), 2);
+        assert!(stack.results().contains_key("rusty cache"));
+        assert!(stack.postings().contains_key(&"cache".to_string()));
+        assert_eq!(stack.len(), 2);
+    }
+}
@@ -0,0 +1,395 @@
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::hash::{BuildHasher, Hash};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::snapshot::crc::Crc32;
+use crate::snapshot::SnapshotError;
+use crate::{CacheStats, CacheType};
+
+const MAGIC: [u8; 4] = *b"SECM";
+const VERSION: u16 = 1;
+// Magic, version, padding, slot count and entry count.
+const HEADER_LEN: usize = 24;
+// A key's hash and the offset of its entry, which is never 0 for an occupied slot.
+const SLOT_LEN: usize = 16;
+// Key and value lengths ahead of their bytes.
+const ENTRY_HEADER_LEN: usize = 8;
+
+// An immutable table of byte keys and values, built offline into a file and looked up
+// through a memory map of it, so lookups allocate nothing and the OS pages the table in
+// and out as it likes. The file is an open-addressing hash table at most half full, then
+// the entries, then a CRC-32 of the whole file, which `open` checks once.
+//
+// Keys are hashed with FNV-1a, which the file was built with, not with a `BuildHasher`.
+pub struct StaticCache {
+    map: Mmap,
+    slots: usize,
+    len: usize,
+}
+
+impl StaticCache {
+    // Writes a table holding `entries`, e.g. the results of the most frequent queries in
+    // a log. Fails on a key given twice.
+    pub fn build<W, I, Kb, Vb>(writer: W, entries: I) -> Result<(), SnapshotError>
+    where
+        W: Write,
+        I: IntoIterator<Item = (Kb, Vb)>,
+        Kb: AsRef<[u8]>,
+        Vb: AsRef<[u8]>,
+    {
+        let entries: Vec<(Kb, Vb)> = entries.into_iter().collect();
+        let slots = (2 * entries.len()).next_power_of_two().max(2);
+        let mut table = vec![(0u64, 0u64); slots];
+        let mut offset = (HEADER_LEN + slots * SLOT_LEN) as u64;
+        for (position, (key, value)) in entries.iter().enumerate() {
+            let key = key.as_ref();
+            let hash = fnv1a(key);
+            let mut slot = hash as usize & (slots - 1);
+            while table[slot].1 != 0 {
+                let (other, _) = &entries[table[slot].0 as usize];
+                if other.as_ref() == key {
+                    return Err(SnapshotError::Invalid("duplicate key"));
+                }
+                slot = (slot + 1) & (slots - 1);
+            }
+            // The entry's position for now, replaced by its offset below
+            table[slot] = (position as u64, offset);
+            offset += (ENTRY_HEADER_LEN + key.len() + value.as_ref().len()) as u64;
+        }
+
+        let mut writer = Checksummed {
+            inner: BufWriter::new(writer),
+            crc: Crc32::new(),
+        };
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&[0; 2])?;
+        writer.write_all(&(slots as u64).to_le_bytes())?;
+        writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+        for &(position, offset) in &table {
+            let hash = match offset {
+                0 => 0,
+                _ => fnv1a(entries[position as usize].0.as_ref()),
+            };
+            writer.write_all(&hash.to_le_bytes())?;
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        for (key, value) in &entries {
+            let (key, value) = (key.as_ref(), value.as_ref());
+            let too_long = || SnapshotError::Invalid("key or value longer than 4 GiB");
+            writer.write_all(
+                &u32::try_from(key.len())
+                    .map_err(|_| too_long())?
+                    .to_le_bytes(),
+            )?;
+            writer.write_all(
+                &u32::try_from(value.len())
+                    .map_err(|_| too_long())?
+                    .to_le_bytes(),
+            )?;
+            writer.write_all(key)?;
+            writer.write_all(value)?;
+        }
+        let checksum = writer.crc.finish();
+        let mut writer = writer.inner;
+        writer.write_all(&checksum.to_le_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    // Maps a table written by `build`. The file must not change while it is mapped:
+    // replace it by renaming a new one over it, never by writing to it in place.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        let file = File::open(path)?;
+        // Safety: the table is only read, and the caller keeps the file from being
+        // modified while it is mapped, as above
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < HEADER_LEN + 4 || map[..4] != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = u16::from_le_bytes([map[4], map[5]]);
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let (body, trailer) = map.split_at(map.len() - 4);
+        let mut crc = Crc32::new();
+        crc.update(body);
+        if crc.finish().to_le_bytes() != trailer {
+            return Err(SnapshotError::ChecksumMismatch);
+        }
+        let slots = read_u64(&map, 8) as usize;
+        let len = read_u64(&map, 16) as usize;
+        let fits = slots
+            .checked_mul(SLOT_LEN)
+            .and_then(|table| table.checked_add(HEADER_LEN))
+            .is_some_and(|end| end <= body.len());
+        if !slots.is_power_of_two() || !fits || len >= slots {
+            return Err(SnapshotError::Invalid("malformed table"));
+        }
+        // Lookups stop at an empty slot, so the table must have as many as the header says
+        let occupied = (0..slots)
+            .filter(|slot| read_u64(&map, HEADER_LEN + slot * SLOT_LEN + 8) != 0)
+            .count();
+        if occupied != len {
+            return Err(SnapshotError::Invalid(
+                "entry count disagrees with the table",
+            ));
+        }
+        Ok(StaticCache { map, slots, len })
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        let hash = fnv1a(key);
+        let mut slot = hash as usize & (self.slots - 1);
+        // Bounded even by a table `open` did not check, with no empty slot to stop at
+        for _ in 0..self.slots {
+            let at = HEADER_LEN + slot * SLOT_LEN;
+            let offset = read_u64(&self.map, at + 8) as usize;
+            if offset == 0 {
+                return None;
+            }
+            if read_u64(&self.map, at) == hash {
+                let (found, value) = self.entry(offset)?;
+                if found == key {
+                    return Some(value);
+                }
+            }
+            slot = (slot + 1) & (self.slots - 1);
+        }
+        None
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // The key and value stored at `offset`, or `None` if they would run past the end.
+    fn entry(&self, offset: usize) -> Option<(&[u8], &[u8])> {
+        let lengths = self.map.get(offset..offset + ENTRY_HEADER_LEN)?;
+        let key_len = u32::from_le_bytes([lengths[0], lengths[1], lengths[2], lengths[3]]);
+        let value_len = u32::from_le_bytes([lengths[4], lengths[5], lengths[6], lengths[7]]);
+        let key_start = offset + ENTRY_HEADER_LEN;
+        let value_start = key_start + key_len as usize;
+        let key = self.map.get(key_start..value_start)?;
+        let value = self
+            .map
+            .get(value_start..value_start + value_len as usize)?;
+        Some((key, value))
+    }
+}
+
+// A dynamic cache in front of a static table. Lookups try the cache first and fall back
+// to the table, whose entries the cache's own ones shadow. What is written goes to the
+// cache only, so the table's entries can be overridden but not removed.
+pub struct LayeredCache<K, V, S = RandomState> {
+    cache: CacheType<K, V, S>,
+    base: StaticCache,
+    base_hits: u64,
+}
+
+impl<K, V, S> LayeredCache<K, V, S>
+where
+    K: Clone + Hash + Eq + AsRef<[u8]>,
+    V: AsRef<[u8]>,
+    S: BuildHasher + Clone,
+{
+    pub fn new(cache: CacheType<K, V, S>, base: StaticCache) -> Self {
+        LayeredCache {
+            cache,
+            base,
+            base_hits: 0,
+        }
+    }
+
+    // A miss in the cache is still counted there when the table has the key.
+    pub fn get(&mut self, key: &K) -> Option<&[u8]> {
+        if self.cache.get(key).is_some() {
+            return self.cache.peek(key).map(AsRef::as_ref);
+        }
+        let value = self.base.get(key.as_ref());
+        if value.is_some() {
+            self.base_hits += 1;
+        }
+        value
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key) || self.base.contains_key(key.as_ref())
+    }
+
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        self.cache.put(key, value, weight);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.cache.remove(key)
+    }
+
+    // Lookups answered by the table.
+    pub fn base_hits(&self) -> u64 {
+        self.base_hits
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn cache(&mut self) -> &mut CacheType<K, V, S> {
+        &mut self.cache
+    }
+
+    pub fn base(&self) -> &StaticCache {
+        &self.base
+    }
+}
+
+struct Checksummed<W> {
+    inner: W,
+    crc: Crc32,
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(bytes)?;
+        self.crc.update(&bytes[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    let mut word = [0; 8];
+    word.copy_from_slice(&bytes[at..at + 8]);
+    u64::from_le_bytes(word)
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Policy;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn built<I: IntoIterator<Item = (&'static str, &'static str)>>(
+        name: &str,
+        entries: I,
+    ) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("static-{}-{}", process::id(), name));
+        StaticCache::build(File::create(&path).unwrap(), entries).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_serves_what_was_built() {
+        let queries: Vec<(String, String)> = (0..1000)
+            .map(|n| (format!("query {}", n), format!("results {}", n)))
+            .collect();
+        let path = std::env::temp_dir().join(format!("static-{}-built", process::id()));
+        StaticCache::build(File::create(&path).unwrap(), queries).unwrap();
+
+        let table = StaticCache::open(&path).unwrap();
+        assert_eq!(table.len(), 1000);
+        assert_eq!(table.get(b"query 7"), Some(&b"results 7"[..]));
+        assert_eq!(table.get(b"query 999"), Some(&b"results 999"[..]));
+        assert_eq!(table.get(b"query 1000"), None);
+        assert_eq!(table.get(b""), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_duplicates_and_corruption() {
+        let duplicated = StaticCache::build(Vec::new(), vec![("a", "1"), ("a", "2")]);
+        assert!(matches!(duplicated, Err(SnapshotError::Invalid(_))));
+
+        let path = built("corrupt", vec![("a", "1"), ("b", "2")]);
+        let mut bytes = fs::read(&path).unwrap();
+        let last_value = bytes.len() - 5;
+        bytes[last_value] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            StaticCache::open(&path),
+            Err(SnapshotError::ChecksumMismatch)
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_full_tables_neither_open_nor_hang() {
+        let path = built("full", vec![("a", "1"), ("b", "2")]);
+        let mut bytes = fs::read(&path).unwrap();
+        // Point the two empty slots of four somewhere, and checksum the result
+        for slot in 0..4 {
+            let at = HEADER_LEN + slot * SLOT_LEN + 8;
+            if read_u64(&bytes, at) == 0 {
+                bytes[at..at + 8].copy_from_slice(&1u64.to_le_bytes());
+            }
+        }
+        let body = bytes.len() - 4;
+        let mut crc = Crc32::new();
+        crc.update(&bytes[..body]);
+        bytes[body..].copy_from_slice(&crc.finish().to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            StaticCache::open(&path),
+            Err(SnapshotError::Invalid(_))
+        ));
+
+        let table = StaticCache {
+            map: unsafe { Mmap::map(&File::open(&path).unwrap()).unwrap() },
+            slots: 4,
+            len: 2,
+        };
+        assert_eq!(table.get(b"absent"), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cache_shadows_the_table() {
+        let path = built(
+            "layered",
+            vec![("hot", "from the table"), ("cold", "stale")],
+        );
+        let mut layered = LayeredCache::new(
+            CacheType::new(Policy::LRU, 4),
+            StaticCache::open(&path).unwrap(),
+        );
+        layered.put("cold".to_string(), "fresh".to_string(), 1);
+
+        assert_eq!(
+            layered.get(&"hot".to_string()),
+            Some(&b"from the table"[..])
+        );
+        assert_eq!(layered.get(&"cold".to_string()), Some(&b"fresh"[..]));
+        assert_eq!(layered.get(&"none".to_string()), None);
+        assert_eq!(layered.base_hits(), 1);
+        assert_eq!(layered.stats().hits, 1);
+
+        // Removing the override uncovers the table's entry again
+        layered.remove(&"cold".to_string());
+        assert_eq!(layered.get(&"cold".to_string()), Some(&b"stale"[..]));
+        fs::remove_file(&path).unwrap();
+    }
+}
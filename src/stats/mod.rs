@@ -0,0 +1,157 @@
+use std::ops::AddAssign;
+use std::time::Instant;
+
+use crate::expiry::{Expiration, Timestamps};
+
+// Running counters kept by every policy. Lookups are `get`, `get_mut` and `entry`;
+// evictions only count entries dropped to make room, not removals or expirations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub insertions: u64,
+    pub evictions: u64,
+    // The summed weight of the entries currently cached.
+    pub current_weight: u64,
+}
+
+crate::snapshot::impl_codec!(CacheStats {
+    hits,
+    misses,
+    insertions,
+    evictions,
+    current_weight,
+});
+
+impl CacheStats {
+    pub fn lookups(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    // Zero when nothing has been looked up yet.
+    pub fn hit_ratio(&self) -> f64 {
+        match self.lookups() {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+// Sums the counters of caches that split one workload between them.
+impl AddAssign for CacheStats {
+    fn add_assign(&mut self, other: CacheStats) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.insertions += other.insertions;
+        self.evictions += other.evictions;
+        self.current_weight += other.current_weight;
+    }
+}
+
+// A snapshot of what a policy knows about one cached entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryInfo<W = u32> {
+    pub inserted_at: Instant,
+    // Updated by hits and by writes.
+    pub last_access: Instant,
+    // Hits since insertion; writes are not counted.
+    pub access_count: u64,
+    pub weight: W,
+    // When the entry expires unless it is used or written first, if the cache expires entries.
+    pub expires_at: Option<Instant>,
+}
+
+impl<W> EntryInfo<W> {
+    pub(crate) fn new(timestamps: &Timestamps, weight: W, expiration: &Expiration) -> Self {
+        EntryInfo {
+            inserted_at: timestamps.inserted,
+            last_access: timestamps.accessed,
+            access_count: timestamps.access_count,
+            weight,
+            expires_at: expiration.deadline(timestamps),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CacheType, Policy};
+
+    #[test]
+    fn test_hit_ratio() {
+        let mut stats = CacheStats::default();
+        assert_eq!(stats.hit_ratio(), 0.0);
+
+        stats.hits = 3;
+        stats.misses = 1;
+        assert_eq!(stats.lookups(), 4);
+        assert_eq!(stats.hit_ratio(), 0.75);
+    }
+
+    #[test]
+    fn test_entry_info_for_every_policy() {
+        use crate::clock::{Clock, ManualClock};
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        for &policy in &[Policy::LRU, Policy::LFU, Policy::Landlord] {
+            let clock = ManualClock::new();
+            let start = clock.now();
+            let mut cache = CacheType::new(policy, 2);
+            cache.set_clock(Arc::new(clock.clone()));
+
+            cache.put(1, "one", 7);
+            clock.advance(Duration::from_secs(1));
+            cache.get(&1);
+            cache.get(&1);
+            clock.advance(Duration::from_secs(1));
+            cache.peek(&1);
+
+            let info = cache.entry_info(&1).unwrap();
+            assert_eq!(info.inserted_at, start, "{:?}", policy);
+            assert_eq!(
+                info.last_access,
+                start + Duration::from_secs(1),
+                "{:?}",
+                policy
+            );
+            assert_eq!(info.access_count, 2, "{:?}", policy);
+            assert_eq!(info.weight, 7, "{:?}", policy);
+            assert_eq!(info.expires_at, None, "{:?}", policy);
+            assert_eq!(cache.entry_info(&2), None, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn test_every_policy_keeps_stats() {
+        for &policy in &[Policy::LRU, Policy::LFU, Policy::Landlord] {
+            let mut cache = CacheType::new(policy, 2);
+            cache.put(1, "one", 10);
+            cache.put(2, "two", 20);
+            cache.get(&1);
+            cache.get(&1);
+            cache.get(&3);
+            cache.put(3, "three", 30);
+
+            let stats = cache.stats();
+            assert_eq!(stats.hits, 2, "{:?}", policy);
+            assert_eq!(stats.misses, 1, "{:?}", policy);
+            assert_eq!(stats.insertions, 3, "{:?}", policy);
+            assert_eq!(stats.evictions, 1, "{:?}", policy);
+
+            let live: u64 = [1, 2, 3]
+                .iter()
+                .filter(|key| cache.contains_key(key))
+                .map(|&key| u64::from(key as u32) * 10)
+                .sum();
+            assert_eq!(stats.current_weight, live, "{:?}", policy);
+
+            cache.remove(&3);
+            cache.clear();
+            assert_eq!(cache.stats().current_weight, 0, "{:?}", policy);
+            assert_eq!(cache.stats().insertions, 3, "{:?}", policy);
+        }
+    }
+}
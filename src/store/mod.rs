@@ -0,0 +1,321 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex};
+
+use crate::sync_cache::lock;
+use crate::{CacheStats, CacheType};
+
+// The system of record a cache fronts, such as a document store.
+pub trait Store<K, V> {
+    type Error;
+
+    fn load(&mut self, key: &K) -> Result<Option<V>, Self::Error>;
+
+    fn store(&mut self, key: &K, value: &V) -> Result<(), Self::Error>;
+
+    fn delete(&mut self, key: &K) -> Result<(), Self::Error>;
+}
+
+// Keeps the records in memory, which is mainly useful in tests.
+impl<K: Clone + Hash + Eq, V: Clone, S: BuildHasher> Store<K, V> for HashMap<K, V, S> {
+    type Error = Infallible;
+
+    fn load(&mut self, key: &K) -> Result<Option<V>, Infallible> {
+        Ok(self.get(key).cloned())
+    }
+
+    fn store(&mut self, key: &K, value: &V) -> Result<(), Infallible> {
+        self.insert(key.clone(), value.clone());
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &K) -> Result<(), Infallible> {
+        self.remove(key);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    // Every put reaches the store before the cache, so the store is never behind.
+    WriteThrough,
+    // Puts only mark the entry dirty, and it is written once evicted or flushed.
+    WriteBack,
+}
+
+// A cache in front of a store. Misses load from the store, and the write mode decides
+// when puts reach it. Removals always delete from the store at once.
+//
+// In write-back mode, a dirty victim whose write fails is kept aside and retried before
+// the next call returns, and lookups still find it, so no write is lost to an error. The
+// call that returns such an error has still taken effect in the cache. Expired entries
+// and those dropped by `clear` are not written, so flush before either. The cache's
+// eviction listener feeds the write-backs, so one set beforehand is replaced.
+pub struct StoreCache<K, V, T, S = RandomState> {
+    cache: CacheType<K, V, S>,
+    store: T,
+    mode: WriteMode,
+    dirty: HashSet<K>,
+    // What the policy evicted during the current call, written back before it returns
+    evicted: Arc<Mutex<Vec<(K, V)>>>,
+    // Dirty victims not written yet, oldest first
+    unwritten: VecDeque<(K, V)>,
+}
+
+impl<K, V, T, S> StoreCache<K, V, T, S>
+where
+    K: Clone + Hash + Eq + Send + 'static,
+    V: Send + 'static,
+    T: Store<K, V>,
+    S: BuildHasher + Clone,
+{
+    pub fn new(mut cache: CacheType<K, V, S>, store: T, mode: WriteMode) -> Self {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        cache.set_eviction_listener(move |key, value| lock(&sink).push((key, value)));
+        StoreCache {
+            cache,
+            store,
+            mode,
+            dirty: HashSet::new(),
+            evicted,
+            unwritten: VecDeque::new(),
+        }
+    }
+
+    // Loads a missing key from the store and caches it. The lookup still counts as a
+    // miss in the cache's stats.
+    pub fn get(&mut self, key: &K) -> Result<Option<&V>, T::Error> {
+        if self.cache.get(key).is_none() {
+            let value = match self.take_unwritten(key) {
+                Some(value) => value,
+                None => match self.store.load(key)? {
+                    Some(value) => value,
+                    None => return Ok(None),
+                },
+            };
+            self.cache.insert(key.clone(), value);
+            self.write_back()?;
+        }
+        Ok(self.cache.peek(key))
+    }
+
+    // Only looks in the cache, without loading.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.cache.peek(key).or_else(|| {
+            self.unwritten
+                .iter()
+                .find(|(unwritten, _)| unwritten == key)
+                .map(|(_, value)| value)
+        })
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+
+    // In write-through mode the entry is only cached once the store took it.
+    pub fn put(&mut self, key: K, value: V, weight: u32) -> Result<(), T::Error> {
+        match self.mode {
+            WriteMode::WriteThrough => {
+                self.store.store(&key, &value)?;
+                self.dirty.remove(&key);
+            }
+            WriteMode::WriteBack => {
+                self.dirty.insert(key.clone());
+            }
+        }
+        self.take_unwritten(&key);
+        self.cache.put(key, value, weight);
+        self.write_back()
+    }
+
+    // Returns the cached value, if any, once the store deleted the key.
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>, T::Error> {
+        self.store.delete(key)?;
+        self.dirty.remove(key);
+        let unwritten = self.take_unwritten(key);
+        Ok(self.cache.remove(key).or(unwritten))
+    }
+
+    // Writes every dirty entry to the store. Stops at the first failure, leaving the rest
+    // dirty.
+    pub fn flush(&mut self) -> Result<(), T::Error> {
+        self.write_back()?;
+        let dirty: Vec<K> = self.dirty.iter().cloned().collect();
+        for key in dirty {
+            if let Some(value) = self.cache.peek(&key) {
+                self.store.store(&key, value)?;
+            }
+            self.dirty.remove(&key);
+        }
+        Ok(())
+    }
+
+    pub fn is_dirty(&self, key: &K) -> bool {
+        self.dirty.contains(key)
+    }
+
+    pub fn dirty_len(&self) -> usize {
+        self.dirty.len()
+    }
+
+    pub fn mode(&self) -> WriteMode {
+        self.mode
+    }
+
+    // Drops every entry without writing the dirty ones.
+    pub fn clear(&mut self) {
+        self.cache.clear();
+        self.dirty.clear();
+        self.unwritten.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn store(&self) -> &T {
+        &self.store
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len() + self.unwritten.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Flush first: dirty entries are not written.
+    pub fn into_inner(self) -> (CacheType<K, V, S>, T) {
+        (self.cache, self.store)
+    }
+
+    fn take_unwritten(&mut self, key: &K) -> Option<V> {
+        let position = self
+            .unwritten
+            .iter()
+            .position(|(unwritten, _)| unwritten == key)?;
+        self.unwritten.remove(position).map(|(_, value)| value)
+    }
+
+    fn write_back(&mut self) -> Result<(), T::Error> {
+        for (key, value) in lock(&self.evicted).drain(..) {
+            if self.dirty.contains(&key) {
+                self.unwritten.push_back((key, value));
+            }
+        }
+        while let Some((key, value)) = self.unwritten.front() {
+            self.store.store(key, value)?;
+            self.dirty.remove(key);
+            self.unwritten.pop_front();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Policy;
+
+    fn fronting(mode: WriteMode) -> StoreCache<i32, String, HashMap<i32, String>> {
+        StoreCache::new(CacheType::new(Policy::LRU, 2), HashMap::new(), mode)
+    }
+
+    // Fails every write while `down` is set.
+    struct Flaky {
+        records: HashMap<i32, String>,
+        down: bool,
+    }
+
+    impl Store<i32, String> for Flaky {
+        type Error = &'static str;
+
+        fn load(&mut self, key: &i32) -> Result<Option<String>, &'static str> {
+            Ok(self.records.get(key).cloned())
+        }
+
+        fn store(&mut self, key: &i32, value: &String) -> Result<(), &'static str> {
+            if self.down {
+                return Err("store down");
+            }
+            self.records.insert(*key, value.clone());
+            Ok(())
+        }
+
+        fn delete(&mut self, key: &i32) -> Result<(), &'static str> {
+            self.records.remove(key);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_misses_load_from_the_store() {
+        let mut records = HashMap::new();
+        records.insert(1, "one".to_string());
+        let mut cache = StoreCache::new(
+            CacheType::new(Policy::LRU, 2),
+            records,
+            WriteMode::WriteThrough,
+        );
+
+        assert_eq!(cache.get(&1), Ok(Some(&"one".to_string())));
+        assert_eq!(cache.get(&2), Ok(None));
+        assert!(cache.contains_key(&1));
+        assert_eq!(cache.stats().misses, 2);
+    }
+
+    #[test]
+    fn test_write_through_reaches_the_store_at_once() {
+        let mut cache = fronting(WriteMode::WriteThrough);
+        cache.put(1, "one".to_string(), 1).unwrap();
+        assert_eq!(cache.store().get(&1), Some(&"one".to_string()));
+        assert_eq!(cache.dirty_len(), 0);
+
+        assert_eq!(cache.remove(&1), Ok(Some("one".to_string())));
+        assert!(cache.store().is_empty());
+    }
+
+    #[test]
+    fn test_write_back_writes_victims_and_flushes() {
+        let mut cache = fronting(WriteMode::WriteBack);
+        cache.put(1, "one".to_string(), 1).unwrap();
+        cache.put(2, "two".to_string(), 1).unwrap();
+        assert!(cache.store().is_empty());
+        assert!(cache.is_dirty(&1));
+
+        // Evicting 1 writes it back
+        cache.put(3, "three".to_string(), 1).unwrap();
+        assert_eq!(cache.store().get(&1), Some(&"one".to_string()));
+        assert!(!cache.is_dirty(&1));
+        assert_eq!(cache.store().len(), 1);
+
+        cache.flush().unwrap();
+        assert_eq!(cache.store().len(), 3);
+        assert_eq!(cache.dirty_len(), 0);
+    }
+
+    #[test]
+    fn test_failed_write_backs_are_retried() {
+        let store = Flaky {
+            records: HashMap::new(),
+            down: true,
+        };
+        let mut cache =
+            StoreCache::new(CacheType::new(Policy::LRU, 1), store, WriteMode::WriteBack);
+        cache.put(1, "one".to_string(), 1).unwrap();
+        assert_eq!(cache.put(2, "two".to_string(), 1), Err("store down"));
+
+        // The victim is still found, and written once the store is back
+        assert_eq!(cache.peek(&1), Some(&"one".to_string()));
+        assert_eq!(cache.len(), 2);
+        cache.store.down = false;
+        cache.flush().unwrap();
+        assert_eq!(cache.store().records.len(), 2);
+        assert_eq!(cache.len(), 1);
+    }
+}
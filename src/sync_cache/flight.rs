@@ -0,0 +1,48 @@
+use std::sync::{Condvar, Mutex};
+
+use super::lock;
+
+enum State<V> {
+    Loading,
+    Loaded(V),
+    // The loader failed or panicked
+    Abandoned,
+}
+
+// One in-flight load that other threads can wait on.
+pub(crate) struct Flight<V> {
+    state: Mutex<State<V>>,
+    done: Condvar,
+}
+
+impl<V: Clone> Flight<V> {
+    pub(crate) fn new() -> Self {
+        Flight {
+            state: Mutex::new(State::Loading),
+            done: Condvar::new(),
+        }
+    }
+
+    // Blocks until the load finishes. `None` means it produced no value, and the caller
+    // should load the key itself.
+    pub(crate) fn wait(&self) -> Option<V> {
+        let mut state = lock(&self.state);
+        loop {
+            match *state {
+                State::Loading => {
+                    state = self.done.wait(state).expect("cache lock poisoned");
+                }
+                State::Loaded(ref value) => return Some(value.clone()),
+                State::Abandoned => return None,
+            }
+        }
+    }
+
+    pub(crate) fn finish(&self, value: Option<V>) {
+        *lock(&self.state) = match value {
+            Some(value) => State::Loaded(value),
+            None => State::Abandoned,
+        };
+        self.done.notify_all();
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{Cache, NewCache};
+
+mod flight;
+
+use self::flight::Flight;
+
+// Puts any policy behind a lock, so it can be shared between threads. A `Mutex` rather
+// than an `RwLock`, because even lookups update the policy's bookkeeping.
+pub struct SyncCache<K, V, C> {
+    cache: Mutex<C>,
+    // Loads started by `get_with` and `try_get_with` that have not finished yet
+    loading: Mutex<HashMap<K, Arc<Flight<V>>>>,
+    mutations: AtomicU64,
+}
+
+impl<K, V, C: NewCache<K, V>> SyncCache<K, V, C> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        SyncCache::new(C::new(capacity))
+    }
+}
+
+impl<K, V, C: Cache<K, V>> SyncCache<K, V, C> {
+    pub fn new(cache: C) -> Self {
+        SyncCache {
+            cache: Mutex::new(cache),
+            loading: Mutex::new(HashMap::new()),
+            mutations: AtomicU64::new(0),
+        }
+    }
+
+    // Values are cloned out, since the lock is released again before this returns.
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.lock().get(key).cloned()
+    }
+
+    // Runs `f` on the cached value while the lock is held, which avoids the clone.
+    pub fn with_value<R, F: FnOnce(&V) -> R>(&self, key: &K, f: F) -> Option<R> {
+        self.lock().get(key).map(f)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.lock().peek(key).cloned()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.lock().contains_key(key)
+    }
+
+    pub fn put(&self, key: K, value: V, weight: u32) {
+        self.lock().put(key, value, weight);
+        self.mutated();
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let removed = self.lock().remove(key);
+        if removed.is_some() {
+            self.mutated();
+        }
+        removed
+    }
+
+    pub fn pop_victim(&self) -> Option<(K, V)> {
+        let victim = self.lock().pop_victim();
+        if victim.is_some() {
+            self.mutated();
+        }
+        victim
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+        self.mutated();
+    }
+
+    // Puts, removals, evictions by `pop_victim` and clears made through this handle so far.
+    // Changes made through the guard returned by `lock` are not counted.
+    pub fn mutations(&self) -> u64 {
+        self.mutations.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn mutated(&self) {
+        self.mutations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    // Holds the lock for several operations, or to borrow values without cloning them.
+    // Other threads wait until the guard is dropped.
+    pub fn lock(&self) -> MutexGuard<'_, C> {
+        lock(&self.cache)
+    }
+
+    pub fn into_inner(self) -> C {
+        self.cache.into_inner().expect("cache lock poisoned")
+    }
+}
+
+impl<K: Clone + Hash + Eq, V: Clone, C: Cache<K, V>> SyncCache<K, V, C> {
+    // Returns the cached value, or calls `loader` and caches what it returns. Threads
+    // that miss on a key another thread is already loading block until that load
+    // finishes and share its value, so a burst of misses costs one load. The loader runs
+    // without the cache locked. If it panics, the waiting threads each retry.
+    pub fn get_with<F: FnOnce() -> V>(&self, key: K, weight: u32, loader: F) -> V {
+        match self.try_get_with(key, weight, || Ok::<V, Infallible>(loader())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    // Like `get_with`, but errors are returned to the thread whose loader failed and
+    // nothing is cached. The threads that were waiting on it retry with their own loaders.
+    pub fn try_get_with<E, F: FnOnce() -> Result<V, E>>(
+        &self,
+        key: K,
+        weight: u32,
+        loader: F,
+    ) -> Result<V, E> {
+        // Only this first look counts as a hit or a miss
+        if let Some(value) = self.get(&key) {
+            return Ok(value);
+        }
+        let flight = loop {
+            let mut loading = lock(&self.loading);
+            // A load that just finished stores its value and leaves the map under this lock
+            if let Some(value) = self.peek(&key) {
+                return Ok(value);
+            }
+            match loading.get(&key) {
+                Some(flight) => {
+                    let flight = flight.clone();
+                    drop(loading);
+                    if let Some(value) = flight.wait() {
+                        return Ok(value);
+                    }
+                }
+                None => {
+                    let flight = Arc::new(Flight::new());
+                    loading.insert(key.clone(), flight.clone());
+                    break flight;
+                }
+            }
+        };
+
+        let mut landing = Landing {
+            loading: &self.loading,
+            key: Some(key),
+            flight: &flight,
+        };
+        let value = loader()?;
+        let key = landing.key.take().expect("the load has not landed yet");
+        let mut loading = lock(&self.loading);
+        loading.remove(&key);
+        lock(&self.cache).put(key, value.clone(), weight);
+        self.mutated();
+        flight.finish(Some(value.clone()));
+        Ok(value)
+    }
+}
+
+// Deregisters a load whose loader returned an error or panicked, and wakes its waiters.
+struct Landing<'a, K: Hash + Eq, V: Clone> {
+    loading: &'a Mutex<HashMap<K, Arc<Flight<V>>>>,
+    // Taken once the value is in the cache
+    key: Option<K>,
+    flight: &'a Flight<V>,
+}
+
+impl<'a, K: Hash + Eq, V: Clone> Drop for Landing<'a, K, V> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            // Not `lock`: this may run while unwinding, where a second panic aborts
+            if let Ok(mut loading) = self.loading.lock() {
+                loading.remove(&key);
+            }
+            self.flight.finish(None);
+        }
+    }
+}
+
+impl<K, V, C: Cache<K, V>> From<C> for SyncCache<K, V, C> {
+    fn from(cache: C) -> Self {
+        SyncCache::new(cache)
+    }
+}
+
+// A panic in a listener or weigher poisons the lock, which then keeps failing instead of
+// serving entries from a policy left half updated.
+pub(crate) fn lock<T>(cache: &Mutex<T>) -> MutexGuard<'_, T> {
+    cache.lock().expect("cache lock poisoned")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lru::LRUCache;
+    use crate::{CacheType, Policy};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_get_clones_values_out() {
+        let cache = SyncCache::new(LRUCache::new(2));
+        cache.put(1, "one".to_string(), 1);
+        cache.put(2, "two".to_string(), 1);
+        assert_eq!(cache.get(&1), Some("one".to_string()));
+
+        // 1 was just used, so 2 is evicted
+        cache.put(3, "three".to_string(), 1);
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.with_value(&3, |value| value.len()), Some(5));
+        assert_eq!(cache.peek(&9), None);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_guard_borrows_without_cloning() {
+        let cache: SyncCache<_, _, CacheType<i32, Vec<u8>>> = CacheType::new(Policy::LFU, 4).into();
+        cache.put(1, vec![0; 1024], 1);
+        {
+            let mut guard = cache.lock();
+            assert_eq!(guard.get(&1).map(Vec::len), Some(1024));
+            guard.remove(&1);
+        }
+        assert!(cache.is_empty());
+        assert_eq!(cache.into_inner().stats().hits, 1);
+    }
+
+    #[test]
+    fn test_counts_mutations() {
+        let cache = SyncCache::new(LRUCache::new(2));
+        cache.put(1, 1, 1);
+        cache.put(1, 2, 1);
+        cache.get(&1);
+        assert_eq!(cache.remove(&9), None);
+        assert_eq!(cache.mutations(), 2);
+        cache.remove(&1);
+        cache.get_with(2, 1, || 2);
+        cache.clear();
+        assert_eq!(cache.mutations(), 5);
+    }
+
+    #[test]
+    fn test_concurrent_misses_share_one_load() {
+        let cache: SyncCache<&str, String, LRUCache<_, _>> = SyncCache::with_capacity(4);
+        let loads = AtomicUsize::new(0);
+        let start = Barrier::new(8);
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        start.wait();
+                        cache.get_with("rust", 1, || {
+                            loads.fetch_add(1, Ordering::SeqCst);
+                            thread::sleep(Duration::from_millis(50));
+                            "results for rust".to_string()
+                        })
+                    })
+                })
+                .collect();
+            for worker in workers {
+                assert_eq!(worker.join().unwrap(), "results for rust");
+            }
+        });
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert!(lock(&cache.loading).is_empty());
+    }
+
+    #[test]
+    fn test_failed_loads_are_not_cached() {
+        let cache: SyncCache<u32, u32, LRUCache<_, _>> = SyncCache::with_capacity(4);
+        assert_eq!(
+            cache.try_get_with(1, 1, || Err("backend unavailable")),
+            Err("backend unavailable")
+        );
+        assert!(!cache.contains_key(&1));
+
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+            cache.get_with(1, 1, || panic!("loader panicked"));
+        }));
+        assert!(panicked.is_err());
+        assert!(lock(&cache.loading).is_empty());
+
+        assert_eq!(cache.try_get_with(1, 1, || Ok::<_, &str>(3)), Ok(3));
+        assert_eq!(cache.get_with(1, 1, || 4), 3);
+
+        // Each cold load is one miss, however often the cache is looked at
+        let stats = cache.lock().stats();
+        assert_eq!((stats.misses, stats.hits), (3, 1));
+        cache.get_with(2, 1, || 6);
+        assert_eq!(cache.lock().stats().misses, 4);
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let cache: Arc<SyncCache<u64, u64, LRUCache<u64, u64>>> =
+            Arc::new(SyncCache::with_capacity(1000));
+        let workers: Vec<_> = (0..4u64)
+            .map(|worker| {
+                let cache = cache.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        let key = worker * 100 + i;
+                        cache.put(key, key, 1);
+                        assert_eq!(cache.get(&key), Some(key));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(cache.len(), 400);
+        assert_eq!(cache.lock().stats().hits, 400);
+    }
+}
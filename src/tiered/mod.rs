@@ -0,0 +1,262 @@
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use crate::{Cache, Weigher};
+
+// Two caches stacked into one hierarchy, such as a small result cache over a larger
+// posting-list cache. Lookups try L1 and then L2, and an L2 hit is promoted into L1. Once
+// L1 is full, the victim it gives up for a new key is demoted into L2 rather than
+// dropped, so an entry leaves the hierarchy only when L2 evicts it. A key is held by one
+// tier at a time.
+//
+// Fullness of L1 is judged by entry count, so an L1 that also evicts by weight drops
+// those victims without demoting them. Demoted and promoted entries are weighed with the
+// weigher, or given a weight of 1 without one.
+pub struct TieredCache<K, V, L1, L2> {
+    l1: L1,
+    l1_capacity: usize,
+    l2: L2,
+    weigher: Option<Weigher<K, V>>,
+    promotions: u64,
+    demotions: u64,
+    marker: PhantomData<fn(K, V)>,
+}
+
+impl<K, V, L1, L2> TieredCache<K, V, L1, L2>
+where
+    K: Clone + Hash + Eq,
+    L1: Cache<K, V>,
+    L2: Cache<K, V>,
+{
+    // `l1_capacity` should be the entry capacity L1 was built with.
+    pub fn new(l1: L1, l1_capacity: usize, l2: L2) -> Self {
+        assert!(l1_capacity > 0, "Capacity must be greater than 0");
+        TieredCache {
+            l1,
+            l1_capacity,
+            l2,
+            weigher: None,
+            promotions: 0,
+            demotions: 0,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn set_weigher<F: Fn(&K, &V) -> u32 + Send + 'static>(&mut self, weigher: F) {
+        self.weigher = Some(Box::new(weigher));
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.promote(key);
+        self.l1.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.promote(key);
+        self.l1.get_mut(key)
+    }
+
+    // Looks in both tiers without moving anything between them.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.l1.peek(key).or_else(|| self.l2.peek(key))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.l1.contains_key(key) || self.l2.contains_key(key)
+    }
+
+    // New values always go to L1, replacing any copy in L2.
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        self.l2.remove(&key);
+        self.insert(key, value, weight);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.l1.remove(key).or_else(|| self.l2.remove(key))
+    }
+
+    // The hierarchy's victim is L2's, or L1's once L2 is empty.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.l2.pop_victim().or_else(|| self.l1.pop_victim())
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.l2.peek_victim().or_else(|| self.l1.peek_victim())
+    }
+
+    pub fn clear(&mut self) {
+        self.l1.clear();
+        self.l2.clear();
+    }
+
+    // L2 hits moved up into L1.
+    pub fn promotions(&self) -> u64 {
+        self.promotions
+    }
+
+    // L1 victims moved down into L2.
+    pub fn demotions(&self) -> u64 {
+        self.demotions
+    }
+
+    pub fn l1(&self) -> &L1 {
+        &self.l1
+    }
+
+    pub fn l2(&self) -> &L2 {
+        &self.l2
+    }
+
+    pub fn into_inner(self) -> (L1, L2) {
+        (self.l1, self.l2)
+    }
+
+    pub fn len(&self) -> usize {
+        self.l1.len() + self.l2.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.l1.is_empty() && self.l2.is_empty()
+    }
+
+    fn promote(&mut self, key: &K) {
+        if self.l1.contains_key(key) {
+            return;
+        }
+        if let Some(value) = self.l2.remove(key) {
+            self.promotions += 1;
+            let weight = self.weigh(key, &value);
+            self.insert(key.clone(), value, weight);
+        }
+    }
+
+    fn insert(&mut self, key: K, value: V, weight: u32) {
+        if !self.l1.contains_key(&key) && self.l1.len() >= self.l1_capacity {
+            if let Some((victim, value)) = self.l1.pop_victim() {
+                self.demotions += 1;
+                let weight = self.weigh(&victim, &value);
+                self.l2.put(victim, value, weight);
+            }
+        }
+        self.l1.put(key, value, weight);
+    }
+
+    fn weigh(&self, key: &K, value: &V) -> u32 {
+        self.weigher
+            .as_ref()
+            .map_or(1, |weigher| weigher(key, value))
+    }
+}
+
+impl<K, V, L1, L2> Cache<K, V> for TieredCache<K, V, L1, L2>
+where
+    K: Clone + Hash + Eq,
+    L1: Cache<K, V>,
+    L2: Cache<K, V>,
+{
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lru::LRUCache;
+    use crate::{CacheType, Policy};
+
+    type Lru = LRUCache<i32, String>;
+
+    fn tiered(l1: usize, l2: usize) -> TieredCache<i32, String, Lru, Lru> {
+        TieredCache::new(LRUCache::new(l1), l1, LRUCache::new(l2))
+    }
+
+    #[test]
+    fn test_victims_move_down_and_hits_move_up() {
+        let mut cache = tiered(2, 4);
+        cache.put(1, "one".to_string(), 1);
+        cache.put(2, "two".to_string(), 1);
+        cache.put(3, "three".to_string(), 1);
+        assert_eq!(cache.demotions(), 1);
+        assert!(cache.l2().contains_key(&1));
+        assert_eq!(cache.len(), 3);
+
+        // Promoting 1 demotes 2, the least recently used in L1, in its place
+        assert_eq!(cache.get(&1), Some(&"one".to_string()));
+        assert_eq!(cache.promotions(), 1);
+        assert!(cache.l1().contains_key(&1));
+        assert!(!cache.l1().contains_key(&2));
+        assert!(cache.l2().contains_key(&2));
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn test_entries_leave_through_l2() {
+        let mut cache = tiered(1, 2);
+        for key in 0..4 {
+            cache.put(key, key.to_string(), 1);
+        }
+        assert!(!cache.contains_key(&0));
+        assert_eq!(cache.peek_victim(), Some(&1));
+        assert_eq!(cache.peek(&1), Some(&"1".to_string()));
+        assert!(cache.l2().contains_key(&1));
+
+        // A new value for a demoted key replaces it instead of being shadowed by it
+        cache.put(1, "ONE".to_string(), 1);
+        assert_eq!(cache.remove(&1), Some("ONE".to_string()));
+        assert!(!cache.contains_key(&1));
+    }
+
+    #[test]
+    fn test_weighs_demoted_entries() {
+        let mut cache = TieredCache::new(
+            CacheType::new(Policy::LRU, 1),
+            1,
+            CacheType::<i32, String>::new(Policy::LRU, 10),
+        );
+        cache.set_weigher(|_, value: &String| value.len() as u32);
+        cache.put(1, "four".to_string(), 1);
+        cache.put(2, "two".to_string(), 1);
+        assert_eq!(cache.l2().stats().current_weight, 4);
+
+        let boxed: &mut dyn Cache<i32, String> = &mut cache;
+        assert_eq!(boxed.get(&1), Some(&"four".to_string()));
+        assert_eq!(cache.l2().stats().current_weight, 3);
+    }
+}
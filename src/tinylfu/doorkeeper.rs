@@ -0,0 +1,59 @@
+use crate::frequency::{probe, DEPTH};
+
+// A Bloom filter remembering which keys have been seen once since the last reset, so that
+// one-off keys never reach the sketch.
+pub(crate) struct Doorkeeper {
+    bits: Vec<u64>,
+    mask: usize,
+}
+
+impl Doorkeeper {
+    pub(crate) fn new(bits: usize) -> Self {
+        let bits = bits.max(64).next_power_of_two();
+        Doorkeeper {
+            bits: vec![0; bits / 64],
+            mask: bits - 1,
+        }
+    }
+
+    // Returns whether the key was already present.
+    pub(crate) fn insert(&mut self, hash: u64) -> bool {
+        let mut present = true;
+        for i in 0..DEPTH {
+            let bit = probe(hash.rotate_left(16), i, self.mask);
+            let word = &mut self.bits[bit / 64];
+            present &= *word & (1 << (bit % 64)) != 0;
+            *word |= 1 << (bit % 64);
+        }
+        present
+    }
+
+    pub(crate) fn contains(&self, hash: u64) -> bool {
+        (0..DEPTH).all(|i| {
+            let bit = probe(hash.rotate_left(16), i, self.mask);
+            self.bits[bit / 64] & (1 << (bit % 64)) != 0
+        })
+    }
+
+    pub(crate) fn clear(&mut self) {
+        for word in self.bits.iter_mut() {
+            *word = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_doorkeeper() {
+        let mut doorkeeper = Doorkeeper::new(1024);
+        assert!(!doorkeeper.insert(42));
+        assert!(doorkeeper.insert(42));
+        assert!(doorkeeper.contains(42));
+
+        doorkeeper.clear();
+        assert!(!doorkeeper.contains(42));
+    }
+}
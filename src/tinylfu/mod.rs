@@ -0,0 +1,338 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+use crate::frequency::FrequencySketch;
+use crate::{Cache, NewCache};
+
+mod doorkeeper;
+
+use self::doorkeeper::Doorkeeper;
+
+// Doorkeeper bits per access in a sample period. Far fewer distinct keys than accesses
+// are expected, which keeps false positives rare.
+const DOORKEEPER_BITS_PER_SAMPLE: usize = 4;
+
+// Approximate access frequencies for admission decisions (Einziger et al.). A key's first
+// access in each sample period only sets its doorkeeper bits, so the one-off queries that
+// dominate a query log cost no sketch counters. Every counter is halved once a sample
+// period's worth of accesses has been recorded, so the estimates follow shifts in
+// popularity.
+pub struct TinyLFU<S = RandomState> {
+    sketch: FrequencySketch<S>,
+    doorkeeper: Doorkeeper,
+}
+
+impl TinyLFU {
+    pub fn new(capacity: usize) -> Self {
+        TinyLFU::with_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<S: BuildHasher> TinyLFU<S> {
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Self {
+        let sketch = FrequencySketch::with_hasher(capacity, hash_builder);
+        let doorkeeper = Doorkeeper::new(sketch.sample_size() * DOORKEEPER_BITS_PER_SAMPLE);
+        TinyLFU { sketch, doorkeeper }
+    }
+
+    pub fn record<K: Hash>(&mut self, key: &K) {
+        let hash = self.sketch.hash(key);
+        if self.doorkeeper.insert(hash) {
+            self.sketch.increment(hash);
+        }
+        if self.sketch.tick() {
+            self.doorkeeper.clear();
+        }
+    }
+
+    pub fn estimate<K: Hash>(&self, key: &K) -> u32 {
+        let hash = self.sketch.hash(key);
+        u32::from(self.sketch.estimate_hash(hash)) + u32::from(self.doorkeeper.contains(hash))
+    }
+
+    // Ties go to the victim: replacing it would churn the cache for no expected gain.
+    pub fn admit<K: Hash>(&self, candidate: &K, victim: &K) -> bool {
+        self.estimate(candidate) > self.estimate(victim)
+    }
+
+    pub fn sample_size(&self) -> usize {
+        self.sketch.sample_size()
+    }
+}
+
+// Puts TinyLFU admission in front of any policy. Every lookup and every new key is
+// recorded; once the cache is full, a new key only gets in if it is estimated to be used
+// more often than the policy's next victim. Fullness is judged by entry count, so weight
+// budgets of the wrapped cache are not taken into account.
+pub struct TinyLFUCache<K, V, C, S = RandomState> {
+    cache: C,
+    capacity: usize,
+    filter: TinyLFU<S>,
+    rejections: u64,
+    marker: PhantomData<fn(K, V)>,
+}
+
+impl<K: Hash, V, C: NewCache<K, V>> TinyLFUCache<K, V, C> {
+    pub fn new(capacity: usize) -> Self {
+        TinyLFUCache::wrap(C::new(capacity), capacity)
+    }
+}
+
+impl<K: Hash, V, C: Cache<K, V>> TinyLFUCache<K, V, C> {
+    // `capacity` should be the entry capacity the cache was built with.
+    pub fn wrap(cache: C, capacity: usize) -> Self {
+        TinyLFUCache::with_hasher(cache, capacity, RandomState::new())
+    }
+}
+
+impl<K: Hash, V, C: Cache<K, V>, S: BuildHasher> TinyLFUCache<K, V, C, S> {
+    pub fn with_hasher(cache: C, capacity: usize, hash_builder: S) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        TinyLFUCache {
+            cache,
+            capacity,
+            filter: TinyLFU::with_hasher(capacity, hash_builder),
+            rejections: 0,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.filter.record(key);
+        self.cache.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.filter.record(key);
+        self.cache.get_mut(key)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.cache.peek(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    // Returns whether the entry was stored. Updates to cached keys always are.
+    pub fn put(&mut self, key: K, value: V, weight: u32) -> bool {
+        if self.cache.contains_key(&key) {
+            self.cache.put(key, value, weight);
+            return true;
+        }
+
+        self.filter.record(&key);
+        if self.cache.len() >= self.capacity {
+            if let Some(victim) = self.cache.peek_victim() {
+                if !self.filter.admit(&key, victim) {
+                    self.rejections += 1;
+                    return false;
+                }
+            }
+        }
+        self.cache.put(key, value, weight);
+        true
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.cache.remove(key)
+    }
+
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.cache.pop_victim()
+    }
+
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.cache.peek_victim()
+    }
+
+    // The frequency estimates survive, so the cache refills with popular keys first.
+    pub fn clear(&mut self) {
+        self.cache.clear()
+    }
+
+    // New keys turned away since the cache was built.
+    pub fn rejections(&self) -> u64 {
+        self.rejections
+    }
+
+    pub fn filter(&self) -> &TinyLFU<S> {
+        &self.filter
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.cache
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.cache
+    }
+
+    pub fn into_inner(self) -> C {
+        self.cache
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+impl<K: Hash, V, C: Cache<K, V>, S: BuildHasher> Cache<K, V> for TinyLFUCache<K, V, C, S> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K: Hash, V, C: NewCache<K, V>, S: BuildHasher + Default> NewCache<K, V>
+    for TinyLFUCache<K, V, C, S>
+{
+    fn new(capacity: usize) -> Self {
+        TinyLFUCache::with_hasher(C::new(capacity), capacity, S::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lfu_w::LFUCache;
+    use crate::lru::LRUCache;
+    use crate::{CacheType, Policy};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+
+    // Keeps the sketch's collisions the same from run to run.
+    type FixedState = BuildHasherDefault<DefaultHasher>;
+
+    #[test]
+    fn test_estimates_skip_one_off_keys() {
+        let mut filter = TinyLFU::with_hasher(100, FixedState::default());
+        filter.record(&"once");
+        for _ in 0..5 {
+            filter.record(&"often");
+        }
+
+        // The doorkeeper alone answers for the first access
+        assert_eq!(filter.estimate(&"once"), 1);
+        assert_eq!(filter.estimate(&"often"), 5);
+        assert_eq!(filter.estimate(&"never"), 0);
+        assert!(filter.admit(&"often", &"once"));
+        assert!(!filter.admit(&"once", &"once"));
+    }
+
+    #[test]
+    fn test_estimates_age() {
+        let mut filter = TinyLFU::with_hasher(10, FixedState::default());
+        for _ in 0..8 {
+            filter.record(&"old favourite");
+        }
+        for key in 0..filter.sample_size() {
+            filter.record(&key);
+        }
+        assert!(filter.estimate(&"old favourite") <= 4);
+    }
+
+    #[test]
+    fn test_one_off_keys_are_rejected() {
+        let mut cache = TinyLFUCache::with_hasher(LRUCache::new(2), 2, FixedState::default());
+        for key in 0..2 {
+            cache.put(key, "hot", 1);
+            for _ in 0..3 {
+                cache.get(&key);
+            }
+        }
+
+        // The hot keys keep being read, so aging never leaves them behind a one-off key
+        for key in 100..150 {
+            cache.get(&0);
+            cache.get(&1);
+            assert!(!cache.put(key, "once", 1));
+        }
+        assert!(cache.contains_key(&0));
+        assert!(cache.contains_key(&1));
+        assert_eq!(cache.rejections(), 50);
+    }
+
+    #[test]
+    fn test_popular_newcomer_is_admitted() {
+        let mut cache = TinyLFUCache::wrap(LRUCache::new(2), 2);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+
+        for _ in 0..4 {
+            cache.get(&3);
+        }
+        assert!(cache.put(3, "three", 1));
+        assert!(cache.contains_key(&3));
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_updates_bypass_admission() {
+        let mut cache = TinyLFUCache::wrap(LFUCache::new(1), 1);
+        cache.put(1, "one", 1);
+        assert!(cache.put(1, "ONE", 1));
+        assert_eq!(cache.peek(&1), Some(&"ONE"));
+    }
+
+    #[test]
+    fn test_wraps_any_policy() {
+        for &policy in &[Policy::LRU, Policy::LFU, Policy::Landlord, Policy::ARC] {
+            let mut cache = TinyLFUCache::wrap(CacheType::new(policy, 2), 2);
+            cache.put(1, "one", 1);
+            cache.put(2, "two", 1);
+            cache.get(&1);
+            cache.get(&2);
+
+            assert!(!cache.put(3, "three", 1), "{:?}", policy);
+            assert_eq!(cache.len(), 2, "{:?}", policy);
+            assert_eq!(cache.into_inner().policy(), policy);
+        }
+    }
+}
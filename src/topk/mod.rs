@@ -0,0 +1,226 @@
+use std::convert::TryFrom;
+
+use crate::query::Normalizer;
+use crate::{CacheStats, CacheType, Policy};
+
+// The best results of one query, best first.
+struct Ranked<T> {
+    results: Vec<T>,
+    // Whether the backend ran out of results before the depth it was asked for
+    exhausted: bool,
+}
+
+impl<T> Ranked<T> {
+    fn top(&self, k: usize) -> Option<&[T]> {
+        if k <= self.results.len() {
+            Some(&self.results[..k])
+        } else if self.exhausted {
+            Some(&self.results)
+        } else {
+            None
+        }
+    }
+}
+
+// Caches more of a query's ranking than was asked for: a request for the top `k` fetches
+// the top `k * overfetch`, and any later request for at most that many is served from
+// the same entry. Most queries are followed by a request for the next page, if anything,
+// which over-fetching turns into a hit. Requests deeper than what was cached are counted
+// as too shallow, so the application can fetch deeper. Queries are keyed after
+// normalization, and an entry weighs as many results as it holds.
+pub struct TopKCache<T> {
+    cache: CacheType<String, Ranked<T>>,
+    normalizer: Normalizer,
+    overfetch: f64,
+    max_results: Option<u64>,
+    // The last fetch with too many results to cache, served from here instead
+    uncached: Vec<T>,
+    too_shallow: u64,
+}
+
+impl<T> TopKCache<T> {
+    // Caches the results of up to `capacity` queries, fetching `overfetch` times as many
+    // as were requested.
+    pub fn new(policy: Policy, capacity: usize, overfetch: f64) -> Self {
+        TopKCache::with_normalizer(policy, capacity, overfetch, Normalizer::new())
+    }
+
+    pub fn with_normalizer(
+        policy: Policy,
+        capacity: usize,
+        overfetch: f64,
+        normalizer: Normalizer,
+    ) -> Self {
+        assert!(overfetch >= 1.0, "Over-fetch factor must be at least 1");
+        TopKCache {
+            cache: CacheType::new(policy, capacity),
+            normalizer,
+            overfetch,
+            max_results: None,
+            uncached: Vec::new(),
+            too_shallow: 0,
+        }
+    }
+
+    // How many results to fetch for a request for the top `k`.
+    pub fn fetch_depth(&self, k: usize) -> usize {
+        (k as f64 * self.overfetch).ceil() as usize
+    }
+
+    // Bounds the results cached across every query; queries are evicted to stay within it.
+    pub fn set_max_results(&mut self, max_results: u64) {
+        self.max_results = Some(max_results);
+        self.cache.set_max_weight(max_results);
+    }
+
+    // The top `k` results, or all of them if the query has fewer. A cached query that
+    // holds fewer than `k` is counted as too shallow and answered with `None`.
+    pub fn get(&mut self, query: &str, k: usize) -> Option<&[T]> {
+        let key = self.normalizer.normalize(query);
+        let ranked = self.cache.get(&key)?;
+        let top = ranked.top(k);
+        if top.is_none() {
+            self.too_shallow += 1;
+        }
+        top
+    }
+
+    pub fn peek(&self, query: &str, k: usize) -> Option<&[T]> {
+        let key = self.normalizer.normalize(query);
+        self.cache.peek(&key)?.top(k)
+    }
+
+    // Like `get`, but a miss or a request too deep for the cached entry calls `fetch` with
+    // the query and `fetch_depth(k)`, and caches what it returns. Nothing is cached if
+    // `fetch` fails. Results too many for `set_max_results` are returned without being
+    // cached, rather than evicting every other query for them.
+    pub fn get_or_fetch<E, F>(&mut self, query: &str, k: usize, fetch: F) -> Result<&[T], E>
+    where
+        F: FnOnce(&str, usize) -> Result<Vec<T>, E>,
+    {
+        if self.get(query, k).is_none() {
+            let depth = self.fetch_depth(k);
+            let mut results = fetch(query, depth)?;
+            results.truncate(depth);
+            let weight = u64::from(entry_weight(&results));
+            if self
+                .max_results
+                .is_some_and(|max_results| weight > max_results)
+            {
+                results.truncate(k);
+                self.uncached = results;
+                return Ok(&self.uncached);
+            }
+            self.put(query, depth, results);
+        }
+        Ok(self
+            .peek(query, k)
+            .expect("fetched results within the budget are cached"))
+    }
+
+    // Caches the results fetched for `depth`. Fewer than `depth` are taken to be all the
+    // query has, so every deeper request is served too.
+    pub fn put(&mut self, query: &str, depth: usize, mut results: Vec<T>) {
+        let exhausted = results.len() < depth;
+        results.truncate(depth);
+        let weight = entry_weight(&results);
+        let key = self.normalizer.normalize(query);
+        self.cache.put(key, Ranked { results, exhausted }, weight);
+    }
+
+    // How many results are cached for the query, or `None` if it is not cached.
+    pub fn depth(&self, query: &str) -> Option<usize> {
+        let key = self.normalizer.normalize(query);
+        self.cache.peek(&key).map(|ranked| ranked.results.len())
+    }
+
+    // Requests for more results than their query had cached.
+    pub fn too_shallow(&self) -> u64 {
+        self.too_shallow
+    }
+
+    pub fn remove(&mut self, query: &str) -> Option<Vec<T>> {
+        let key = self.normalizer.normalize(query);
+        self.cache.remove(&key).map(|ranked| ranked.results)
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+        self.uncached = Vec::new();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+fn entry_weight<T>(results: &[T]) -> u32 {
+    u32::try_from(results.len()).unwrap_or(u32::MAX).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    #[test]
+    fn test_shallower_requests_share_the_entry() {
+        let mut cache = TopKCache::new(Policy::LRU, 10, 3.0);
+        assert_eq!(cache.fetch_depth(10), 30);
+        cache.put("Rust", 30, (0..100).collect::<Vec<u32>>());
+        assert_eq!(cache.depth("rust"), Some(30));
+        assert_eq!(cache.get("rust", 10).map(<[u32]>::len), Some(10));
+        assert_eq!(cache.get("rust", 30).map(<[u32]>::len), Some(30));
+
+        assert_eq!(cache.get("rust", 40), None);
+        assert_eq!(cache.too_shallow(), 1);
+        assert_eq!(cache.stats().hits, 3);
+    }
+
+    #[test]
+    fn test_deeper_requests_refetch() {
+        let mut cache = TopKCache::new(Policy::LRU, 10, 2.0);
+        let mut depths = Vec::new();
+        for &k in &[10, 20, 25] {
+            let top = cache
+                .get_or_fetch("rust", k, |_, depth| {
+                    depths.push(depth);
+                    Ok::<_, Infallible>((0..depth as u32).collect())
+                })
+                .unwrap();
+            assert_eq!(top.len(), k);
+        }
+        assert_eq!(depths, vec![20, 50]);
+    }
+
+    #[test]
+    fn test_results_too_many_to_cache_are_still_returned() {
+        let mut cache = TopKCache::new(Policy::LFU, 10, 2.0);
+        cache.set_max_results(5);
+        cache.put("go", 4, vec![1, 2, 3, 4]);
+        let top = cache
+            .get_or_fetch("rust", 4, |_, depth| {
+                Ok::<_, Infallible>((0..depth as u32).collect())
+            })
+            .unwrap();
+        assert_eq!(top, &[0, 1, 2, 3][..]);
+        assert_eq!(cache.depth("rust"), None);
+        assert_eq!(cache.depth("go"), Some(4));
+    }
+
+    #[test]
+    fn test_short_rankings_serve_any_depth() {
+        let mut cache = TopKCache::new(Policy::LRU, 10, 1.5);
+        cache.put("rare", 15, vec!["only", "two"]);
+        assert_eq!(cache.get("rare", 100), Some(&["only", "two"][..]));
+        assert_eq!(cache.too_shallow(), 0);
+    }
+}
@@ -0,0 +1,204 @@
+// Structured diagnostics behind the `tracing` feature. Without the feature every hook is an
+// empty inline function, so the policies can call them unconditionally.
+//
+// Keys are reported by hash rather than by value: it keeps them out of trace backends and
+// does not require `K: Debug`.
+#[cfg(feature = "tracing")]
+mod hooks {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use std::path::Path;
+    use tracing::span::EnteredSpan;
+
+    use crate::snapshot::SnapshotError;
+    use crate::weight::Weight;
+
+    // Inserts at least this heavy are reported; weights are usually byte sizes.
+    pub(crate) const LARGE_INSERT_WEIGHT: u64 = 1 << 20;
+
+    pub(crate) fn key_hash<K: Hash>(key: &K) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub(crate) struct SpanGuard(#[allow(dead_code)] EnteredSpan);
+
+    pub(crate) fn get_span<K: Hash>(policy: &'static str, key: &K) -> SpanGuard {
+        SpanGuard(tracing::trace_span!("cache_get", policy, key_hash = key_hash(key)).entered())
+    }
+
+    pub(crate) fn put_span<K: Hash, W: Weight>(
+        policy: &'static str,
+        key: &K,
+        weight: W,
+    ) -> SpanGuard {
+        SpanGuard(
+            tracing::trace_span!(
+                "cache_put",
+                policy,
+                key_hash = key_hash(key),
+                weight = weight.to_u64()
+            )
+            .entered(),
+        )
+    }
+
+    pub(crate) fn miss<K: Hash>(policy: &'static str, key: &K) {
+        tracing::debug!(policy, key_hash = key_hash(key), "cache miss");
+    }
+
+    pub(crate) fn eviction<K: Hash>(policy: &'static str, key: &K) {
+        tracing::debug!(policy, key_hash = key_hash(key), "cache eviction");
+    }
+
+    pub(crate) fn insert<K: Hash, W: Weight>(policy: &'static str, key: &K, weight: W) {
+        let weight = weight.to_u64();
+        if weight >= LARGE_INSERT_WEIGHT {
+            tracing::info!(
+                policy,
+                key_hash = key_hash(key),
+                weight,
+                "large cache insert"
+            );
+        }
+    }
+
+    pub(crate) fn snapshot_failed(path: &Path, error: &SnapshotError) {
+        tracing::warn!(path = %path.display(), %error, "cache snapshot failed");
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod hooks {
+    use std::hash::Hash;
+    use std::path::Path;
+
+    use crate::snapshot::SnapshotError;
+    use crate::weight::Weight;
+
+    pub(crate) struct SpanGuard;
+
+    #[inline]
+    pub(crate) fn get_span<K: Hash>(_policy: &'static str, _key: &K) -> SpanGuard {
+        SpanGuard
+    }
+
+    #[inline]
+    pub(crate) fn put_span<K: Hash, W: Weight>(
+        _policy: &'static str,
+        _key: &K,
+        _weight: W,
+    ) -> SpanGuard {
+        SpanGuard
+    }
+
+    #[inline]
+    pub(crate) fn miss<K: Hash>(_policy: &'static str, _key: &K) {}
+
+    #[inline]
+    pub(crate) fn eviction<K: Hash>(_policy: &'static str, _key: &K) {}
+
+    #[inline]
+    pub(crate) fn insert<K: Hash, W: Weight>(_policy: &'static str, _key: &K, _weight: W) {}
+
+    #[inline]
+    pub(crate) fn snapshot_failed(_path: &Path, _error: &SnapshotError) {}
+}
+
+pub(crate) use self::hooks::*;
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_hash_is_stable_within_a_process() {
+        assert_eq!(key_hash(&"query"), key_hash(&"query"));
+        assert_ne!(key_hash(&"query"), key_hash(&"other query"));
+    }
+
+    #[test]
+    fn test_operations_emit_spans_and_events() {
+        use crate::{CacheType, Policy};
+        use std::sync::{Arc, Mutex};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        #[derive(Default)]
+        struct Collector {
+            names: Arc<Mutex<Vec<String>>>,
+            next_id: Mutex<u64>,
+        }
+
+        impl Subscriber for Collector {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                self.names
+                    .lock()
+                    .unwrap()
+                    .push(span.metadata().name().to_string());
+                let mut next_id = self.next_id.lock().unwrap();
+                *next_id += 1;
+                Id::from_u64(*next_id)
+            }
+
+            fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                struct Message<'a>(&'a mut String);
+
+                impl<'a> tracing::field::Visit for Message<'a> {
+                    fn record_debug(
+                        &mut self,
+                        field: &tracing::field::Field,
+                        value: &dyn std::fmt::Debug,
+                    ) {
+                        if field.name() == "message" {
+                            *self.0 = format!("{:?}", value);
+                        }
+                    }
+                }
+
+                let mut message = String::new();
+                event.record(&mut Message(&mut message));
+                self.names.lock().unwrap().push(message);
+            }
+
+            fn enter(&self, _span: &Id) {}
+
+            fn exit(&self, _span: &Id) {}
+        }
+
+        for &policy in &[Policy::LRU, Policy::LFU, Policy::Landlord] {
+            let collector = Collector::default();
+            let names = collector.names.clone();
+
+            tracing::subscriber::with_default(collector, || {
+                let mut cache = CacheType::new(policy, 1);
+                cache.put(1, "one", LARGE_INSERT_WEIGHT as u32);
+                cache.get(&2);
+                cache.put(2, "two", 1);
+            });
+
+            assert_eq!(
+                *names.lock().unwrap(),
+                vec![
+                    "cache_put",
+                    "large cache insert",
+                    "cache_get",
+                    "cache miss",
+                    "cache_put",
+                    "cache eviction",
+                ],
+                "{:?}",
+                policy
+            );
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+// A binary max-heap of keys that can find any key, so its priority can be changed or the
+// key removed in O(log n). Wrap priorities in `Reverse` for a min-heap.
+pub struct PriorityHeap<K, P, S = RandomState> {
+    heap: Vec<(K, P)>,
+    positions: HashMap<K, usize, S>,
+}
+
+impl<K: Clone + Hash + Eq, P: Ord> PriorityHeap<K, P> {
+    pub fn new() -> Self {
+        PriorityHeap::with_hasher(RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, P: Ord> Default for PriorityHeap<K, P> {
+    fn default() -> Self {
+        PriorityHeap::new()
+    }
+}
+
+impl<K: Clone + Hash + Eq, P: Ord, S: BuildHasher> PriorityHeap<K, P, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        PriorityHeap {
+            heap: Vec::new(),
+            positions: HashMap::with_hasher(hash_builder),
+        }
+    }
+
+    // Adds the key, or changes its priority if it is already present and returns the old one.
+    pub fn push(&mut self, key: K, priority: P) -> Option<P> {
+        if self.positions.contains_key(&key) {
+            return self.change_priority(&key, priority);
+        }
+        let pos = self.heap.len();
+        self.positions.insert(key.clone(), pos);
+        self.heap.push((key, priority));
+        self.sift_up(pos);
+        None
+    }
+
+    // The key with the highest priority.
+    pub fn peek(&self) -> Option<(&K, &P)> {
+        self.heap.first().map(|(key, priority)| (key, priority))
+    }
+
+    pub fn pop(&mut self) -> Option<(K, P)> {
+        if self.heap.is_empty() {
+            return None;
+        }
+        Some(self.remove_at(0))
+    }
+
+    // Returns the old priority, or `None` if the key is not present.
+    pub fn change_priority(&mut self, key: &K, priority: P) -> Option<P> {
+        let pos = *self.positions.get(key)?;
+        let old = std::mem::replace(&mut self.heap[pos].1, priority);
+        if self.heap[pos].1 > old {
+            self.sift_up(pos);
+        } else {
+            self.sift_down(pos);
+        }
+        Some(old)
+    }
+
+    pub fn get_priority(&self, key: &K) -> Option<&P> {
+        let &pos = self.positions.get(key)?;
+        Some(&self.heap[pos].1)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<(K, P)> {
+        let &pos = self.positions.get(key)?;
+        Some(self.remove_at(pos))
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.positions.clear();
+    }
+
+    // Makes room for `total` keys. The position map gets twice that, so churn through
+    // removals and pushes rehashes it in place instead of growing it.
+    pub fn reserve(&mut self, total: usize) {
+        self.heap.reserve(total.saturating_sub(self.heap.len()));
+        self.positions
+            .reserve((2 * total).saturating_sub(self.positions.len()));
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.heap.shrink_to_fit();
+        self.positions.shrink_to_fit();
+    }
+
+    // In heap order, which is not sorted beyond the first entry.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &P)> {
+        self.heap.iter().map(|(key, priority)| (key, priority))
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    fn remove_at(&mut self, pos: usize) -> (K, P) {
+        let last = self.heap.len() - 1;
+        self.swap(pos, last);
+        let (key, priority) = self.heap.pop().expect("position refers to an entry");
+        self.positions.remove(&key);
+        if pos < self.heap.len() {
+            // The entry moved into the gap may belong either above or below it
+            self.sift_up(pos);
+            self.sift_down(pos);
+        }
+        (key, priority)
+    }
+
+    fn sift_up(&mut self, mut pos: usize) {
+        while pos > 0 {
+            let parent = (pos - 1) / 2;
+            if self.heap[pos].1 <= self.heap[parent].1 {
+                break;
+            }
+            self.swap(pos, parent);
+            pos = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut pos: usize) {
+        loop {
+            let left = 2 * pos + 1;
+            let right = left + 1;
+            let mut largest = pos;
+            if left < self.heap.len() && self.heap[left].1 > self.heap[largest].1 {
+                largest = left;
+            }
+            if right < self.heap.len() && self.heap[right].1 > self.heap[largest].1 {
+                largest = right;
+            }
+            if largest == pos {
+                break;
+            }
+            self.swap(pos, largest);
+            pos = largest;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+        self.heap.swap(a, b);
+        for pos in [a, b] {
+            let slot = self
+                .positions
+                .get_mut(&self.heap[pos].0)
+                .expect("heap entries are indexed");
+            *slot = pos;
+        }
+    }
+}
+
+impl<K, P, S> IntoIterator for PriorityHeap<K, P, S> {
+    type Item = (K, P);
+    type IntoIter = std::vec::IntoIter<(K, P)>;
+
+    // In heap order, like `iter`.
+    fn into_iter(self) -> Self::IntoIter {
+        self.heap.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Reverse;
+
+    #[test]
+    fn test_pops_in_priority_order() {
+        let mut heap = PriorityHeap::new();
+        for (key, priority) in [("c", 3), ("a", 1), ("e", 5), ("b", 2), ("d", 4)] {
+            heap.push(key, priority);
+        }
+        assert_eq!(heap.peek(), Some((&"e", &5)));
+
+        let order: Vec<&str> = std::iter::from_fn(|| heap.pop().map(|(key, _)| key)).collect();
+        assert_eq!(order, vec!["e", "d", "c", "b", "a"]);
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn test_change_priority_both_ways() {
+        let mut heap = PriorityHeap::new();
+        heap.push(1, Reverse(10u64));
+        heap.push(2, Reverse(20));
+        heap.push(3, Reverse(30));
+
+        assert_eq!(heap.change_priority(&3, Reverse(5)), Some(Reverse(30)));
+        assert_eq!(heap.peek(), Some((&3, &Reverse(5))));
+        heap.change_priority(&3, Reverse(40));
+        assert_eq!(heap.peek(), Some((&1, &Reverse(10))));
+        assert_eq!(heap.get_priority(&3), Some(&Reverse(40)));
+        assert_eq!(heap.change_priority(&9, Reverse(1)), None);
+    }
+
+    #[test]
+    fn test_push_existing_key_changes_priority() {
+        let mut heap = PriorityHeap::new();
+        assert_eq!(heap.push("a", 1), None);
+        assert_eq!(heap.push("a", 7), Some(1));
+        assert_eq!(heap.len(), 1);
+        assert_eq!(heap.peek(), Some((&"a", &7)));
+    }
+
+    #[test]
+    fn test_remove_keeps_heap_valid() {
+        let mut heap = PriorityHeap::new();
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut expected = Vec::new();
+        for key in 0..200u64 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            heap.push(key, state % 1000);
+            expected.push((state % 1000, key));
+        }
+        for key in (0..200).step_by(3) {
+            assert!(heap.remove(&key).is_some());
+            assert!(!heap.contains_key(&key));
+        }
+        assert_eq!(heap.remove(&0), None);
+
+        expected.retain(|&(_, key)| key % 3 != 0);
+        expected.sort_unstable();
+        let mut popped = Vec::new();
+        while let Some((_, priority)) = heap.pop() {
+            popped.push(priority);
+        }
+        let mut priorities: Vec<u64> = expected.iter().map(|&(priority, _)| priority).collect();
+        priorities.reverse();
+        assert_eq!(popped, priorities);
+    }
+
+    #[test]
+    fn test_clear_and_iter() {
+        let mut heap = PriorityHeap::new();
+        heap.push("a", 1);
+        heap.push("b", 2);
+        let mut keys: Vec<&str> = heap.iter().map(|(&key, _)| key).collect();
+        keys.sort_unstable();
+        assert_eq!(keys, vec!["a", "b"]);
+
+        heap.clear();
+        assert!(heap.is_empty());
+        assert_eq!(heap.peek(), None);
+        assert_eq!(heap.into_iter().count(), 0);
+    }
+}
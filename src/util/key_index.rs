@@ -0,0 +1,169 @@
+use std::hash::{BuildHasher, Hash};
+
+use hashbrown::HashTable;
+
+use super::{Slab, SlabKey};
+
+// Implemented by slab nodes, so a `KeyIndex` can read the key a slot holds.
+pub(crate) trait Keyed {
+    type Key;
+
+    fn key(&self) -> &Self::Key;
+}
+
+// Finds the slot holding a key without keeping a second copy of it. The table stores only
+// slab keys and compares candidates against the key in the node, so cache keys need
+// `Hash + Eq` but not `Clone`.
+pub(crate) struct KeyIndex<S> {
+    table: HashTable<SlabKey>,
+    hash_builder: S,
+}
+
+impl<S> KeyIndex<S> {
+    pub(crate) fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.table.clear();
+    }
+}
+
+impl<S: BuildHasher> KeyIndex<S> {
+    pub(crate) fn with_hasher(hash_builder: S) -> Self {
+        KeyIndex {
+            table: HashTable::new(),
+            hash_builder,
+        }
+    }
+
+    pub(crate) fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        KeyIndex {
+            table: HashTable::with_capacity(capacity),
+            hash_builder,
+        }
+    }
+
+    pub(crate) fn get<T: Keyed>(&self, key: &T::Key, nodes: &Slab<T>) -> Option<SlabKey>
+    where
+        T::Key: Hash + Eq,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        self.table
+            .find(hash, |&slot| nodes[nodes.index_of(slot)].key() == key)
+            .copied()
+    }
+
+    // Indexes a node already stored in the slab, whose key must not be indexed yet.
+    pub(crate) fn insert<T: Keyed>(&mut self, slot: SlabKey, nodes: &Slab<T>)
+    where
+        T::Key: Hash,
+    {
+        let hash_builder = &self.hash_builder;
+        let hash = hash_builder.hash_one(nodes[slot.index()].key());
+        self.table.insert_unique(hash, slot, |&other| {
+            hash_builder.hash_one(nodes[other.index()].key())
+        });
+    }
+
+    // Takes the key of a node that may already have left the slab, so the table is
+    // searched by slot rather than by comparing keys.
+    pub(crate) fn remove<K: Hash>(&mut self, key: &K, slot: usize) -> SlabKey {
+        let hash = self.hash_builder.hash_one(key);
+        let entry = self
+            .table
+            .find_entry(hash, |other| other.index() == slot)
+            .unwrap_or_else(|_| panic!("live nodes are indexed"));
+        entry.remove().0
+    }
+
+    // Points the key at the slot its node was relocated to.
+    pub(crate) fn repoint<K: Hash>(&mut self, key: &K, from: usize, to: SlabKey) {
+        let hash = self.hash_builder.hash_one(key);
+        let slot = self
+            .table
+            .find_mut(hash, |other| other.index() == from)
+            .expect("live nodes are indexed");
+        *slot = to;
+    }
+
+    // Makes room for `total` keys, then as many again: removals leave tombstones, and a
+    // table at most half full clears them by rehashing in place instead of growing.
+    pub(crate) fn reserve<T: Keyed>(&mut self, total: usize, nodes: &Slab<T>)
+    where
+        T::Key: Hash,
+    {
+        let hash_builder = &self.hash_builder;
+        self.table
+            .reserve((2 * total).saturating_sub(self.table.len()), |&slot| {
+                hash_builder.hash_one(nodes[slot.index()].key())
+            });
+    }
+
+    pub(crate) fn shrink_to_fit<T: Keyed>(&mut self, nodes: &Slab<T>)
+    where
+        T::Key: Hash,
+    {
+        let hash_builder = &self.hash_builder;
+        self.table
+            .shrink_to_fit(|&slot| hash_builder.hash_one(nodes[slot.index()].key()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::RandomState;
+
+    struct Node(String);
+
+    impl Keyed for Node {
+        type Key = String;
+
+        fn key(&self) -> &String {
+            &self.0
+        }
+    }
+
+    #[test]
+    fn test_finds_keys_through_the_slab() {
+        let mut nodes = Slab::with_capacity(4);
+        let mut index = KeyIndex::with_hasher(RandomState::new());
+        for name in ["a", "b", "c"] {
+            let slot = nodes.insert(Node(name.to_string()));
+            index.insert(slot, &nodes);
+        }
+        assert_eq!(index.len(), 3);
+
+        let b = index.get(&"b".to_string(), &nodes).unwrap();
+        assert_eq!(nodes[b.index()].0, "b");
+        assert_eq!(index.get(&"z".to_string(), &nodes), None);
+
+        let node = nodes.remove(b.index());
+        assert_eq!(index.remove(&node.0, b.index()), b);
+        assert_eq!(index.get(&"b".to_string(), &nodes), None);
+        assert_eq!(index.len(), 2);
+    }
+
+    #[test]
+    fn test_repoint_follows_relocation() {
+        let mut nodes = Slab::with_capacity(2);
+        let mut index = KeyIndex::with_hasher(RandomState::new());
+        let a = nodes.insert(Node("a".to_string()));
+        let b = nodes.insert(Node("b".to_string()));
+        index.insert(a, &nodes);
+        index.insert(b, &nodes);
+        let node = nodes.remove(a.index());
+        index.remove(&node.0, a.index());
+
+        let moved = nodes.relocate(b.index(), a.index());
+        index.repoint(&"b".to_string(), b.index(), moved);
+        nodes.shrink_to_fit();
+        index.shrink_to_fit(&nodes);
+        assert_eq!(index.get(&"b".to_string(), &nodes), Some(moved));
+    }
+}
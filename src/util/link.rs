@@ -0,0 +1,59 @@
+// A slab index packed into a `u32`, with `u32::MAX` standing in for no index. Half the
+// size of an `Option<usize>`, which adds up across the two links of every node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Link(u32);
+
+impl Link {
+    pub(crate) const NONE: Link = Link(u32::MAX);
+
+    pub(crate) fn some(idx: usize) -> Link {
+        assert!(idx < u32::MAX as usize, "Slab index does not fit in a link");
+        Link(idx as u32)
+    }
+
+    pub(crate) fn get(self) -> Option<usize> {
+        if self == Link::NONE {
+            None
+        } else {
+            Some(self.0 as usize)
+        }
+    }
+
+    pub(crate) fn is_none(self) -> bool {
+        self == Link::NONE
+    }
+}
+
+impl Default for Link {
+    fn default() -> Link {
+        Link::NONE
+    }
+}
+
+impl From<Option<usize>> for Link {
+    fn from(idx: Option<usize>) -> Link {
+        idx.map_or(Link::NONE, Link::some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::size_of;
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(Link::some(0).get(), Some(0));
+        assert_eq!(Link::some(7).get(), Some(7));
+        assert_eq!(Link::NONE.get(), None);
+        assert_eq!(Link::from(None), Link::NONE);
+        assert!(!Link::from(Some(3)).is_none());
+        assert_eq!(size_of::<Link>(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "Slab index does not fit in a link")]
+    fn test_sentinel_is_not_an_index() {
+        Link::some(u32::MAX as usize);
+    }
+}
@@ -0,0 +1,36 @@
+// Data structures shared by the policies.
+#[cfg(test)]
+pub(crate) mod counting_alloc;
+mod heap;
+mod key_index;
+mod link;
+mod slab;
+
+pub use self::heap::PriorityHeap;
+pub(crate) use self::key_index::{KeyIndex, Keyed};
+pub(crate) use self::link::Link;
+pub(crate) use self::slab::{Slab, SlabKey};
+
+use crate::stats::EntryInfo;
+
+// Picks which of `shards` tables gets a hash. The tables hash keys with the same hasher
+// and place them by the low bits of the hash, so the shard is chosen from the high bits
+// to keep each table's keys spread across it.
+pub(crate) fn shard_of(hash: u64, shards: usize) -> usize {
+    ((hash >> 32) as usize) % shards
+}
+
+// Clones out the first `n` entries `info` finds live, with their weights. Policies pass
+// their entries hottest first, so this is what `export_top` hands to another cache.
+pub(crate) fn top_entries<'a, K, V, W, I, F>(entries: I, n: usize, info: F) -> Vec<(K, V, W)>
+where
+    K: Clone + 'a,
+    V: Clone + 'a,
+    I: Iterator<Item = (&'a K, &'a V)>,
+    F: Fn(&K) -> Option<EntryInfo<W>>,
+{
+    entries
+        .filter_map(|(key, value)| Some((key.clone(), value.clone(), info(key)?.weight)))
+        .take(n)
+        .collect()
+}
@@ -0,0 +1,267 @@
+use std::ops::{Index, IndexMut};
+
+// Names one entry of a `Slab`. The generation is stamped when the entry is stored, so a
+// key that outlives its entry no longer matches once the slot is reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct SlabKey {
+    index: u32,
+    generation: u32,
+}
+
+impl SlabKey {
+    pub(crate) fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+// Node storage shared by the linked policies. Entries keep their index until they are
+// removed, and freed slots are reused before the storage grows. Links between nodes are
+// plain indices; the keys handed out by `insert` also carry a generation, so the maps
+// from cache keys to nodes cannot silently reach an entry stored in the slot later.
+pub(crate) struct Slab<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    len: usize,
+    next_generation: u32,
+}
+
+impl<T> Slab<T> {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Slab {
+            slots: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            len: 0,
+            next_generation: 0,
+        }
+    }
+
+    pub(crate) fn insert(&mut self, value: T) -> SlabKey {
+        let generation = self.next_generation;
+        self.next_generation = self.next_generation.wrapping_add(1);
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index] = Slot {
+                    generation,
+                    value: Some(value),
+                };
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation,
+                    value: Some(value),
+                });
+                self.slots.len() - 1
+            }
+        };
+        assert!(
+            index < u32::MAX as usize,
+            "Slab index does not fit in a link"
+        );
+        self.len += 1;
+        SlabKey {
+            index: index as u32,
+            generation,
+        }
+    }
+
+    // Panics on a key whose entry has been removed, which always means a map was not
+    // updated along with the slab.
+    pub(crate) fn index_of(&self, key: SlabKey) -> usize {
+        assert!(self.get(key).is_some(), "stale slab key");
+        key.index()
+    }
+
+    pub(crate) fn key_at(&self, index: usize) -> SlabKey {
+        let slot = &self.slots[index];
+        assert!(slot.value.is_some(), "index refers to a live node");
+        SlabKey {
+            index: index as u32,
+            generation: slot.generation,
+        }
+    }
+
+    pub(crate) fn get(&self, key: SlabKey) -> Option<&T> {
+        let slot = self.slots.get(key.index())?;
+        if slot.generation != key.generation {
+            return None;
+        }
+        slot.value.as_ref()
+    }
+
+    pub(crate) fn get_at(&self, index: usize) -> Option<&T> {
+        self.slots.get(index)?.value.as_ref()
+    }
+
+    pub(crate) fn remove(&mut self, index: usize) -> T {
+        let value = self.slots[index]
+            .value
+            .take()
+            .expect("index refers to a live node");
+        self.free.push(index);
+        self.len -= 1;
+        value
+    }
+
+    // Occupied slots in index order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| slot.value.as_ref().map(|value| (index, value)))
+    }
+
+    // Slots in use or waiting for reuse, which is what the storage actually holds.
+    #[cfg(test)]
+    pub(crate) fn slots(&self) -> usize {
+        self.slots.len()
+    }
+
+    // Makes room for `total` entries, including the free list that tracks them.
+    pub(crate) fn reserve(&mut self, total: usize) {
+        self.slots.reserve(total.saturating_sub(self.slots.len()));
+        self.free.reserve(total.saturating_sub(self.free.len()));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.slots.clear();
+        self.free.clear();
+        self.len = 0;
+    }
+
+    // Pairs every entry stored past the live count with a free slot below it. The owner
+    // moves each one with `relocate`, fixing its links as it goes, then calls
+    // `shrink_to_fit` to drop the emptied tail.
+    pub(crate) fn compaction_moves(&mut self) -> Vec<(usize, usize)> {
+        let live = self.len;
+        let slots = &self.slots;
+        let holes = self.free.drain(..).filter(|&index| index < live);
+        (live..slots.len())
+            .filter(|&index| slots[index].value.is_some())
+            .zip(holes)
+            .collect()
+    }
+
+    // Moves an entry into a free slot and returns its new key.
+    pub(crate) fn relocate(&mut self, from: usize, to: usize) -> SlabKey {
+        let value = self.slots[from]
+            .value
+            .take()
+            .expect("index refers to a live node");
+        assert!(self.slots[to].value.is_none(), "relocation target is free");
+        let generation = self.next_generation;
+        self.next_generation = self.next_generation.wrapping_add(1);
+        self.slots[to] = Slot {
+            generation,
+            value: Some(value),
+        };
+        SlabKey {
+            index: to as u32,
+            generation,
+        }
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        while self.slots.last().is_some_and(|slot| slot.value.is_none()) {
+            self.slots.pop();
+        }
+        let slots = &self.slots;
+        self.free = (0..slots.len())
+            .filter(|&index| slots[index].value.is_none())
+            .collect();
+        self.slots.shrink_to_fit();
+        self.free.shrink_to_fit();
+    }
+}
+
+impl<T> Index<usize> for Slab<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.slots[index]
+            .value
+            .as_ref()
+            .expect("index refers to a live node")
+    }
+}
+
+impl<T> IndexMut<usize> for Slab<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.slots[index]
+            .value
+            .as_mut()
+            .expect("index refers to a live node")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reuses_freed_slots() {
+        let mut slab = Slab::with_capacity(4);
+        let a = slab.insert("a");
+        let b = slab.insert("b");
+        assert_eq!(slab.remove(a.index()), "a");
+
+        let c = slab.insert("c");
+        assert_eq!(c.index(), a.index());
+        assert_eq!(slab.slots(), 2);
+        assert_eq!(slab[b.index()], "b");
+        assert_eq!(slab.iter().map(|(_, &v)| v).collect::<Vec<_>>(), ["c", "b"]);
+    }
+
+    #[test]
+    fn test_stale_key_does_not_reach_new_entry() {
+        let mut slab = Slab::with_capacity(1);
+        let old = slab.insert(1);
+        slab.remove(old.index());
+        let new = slab.insert(2);
+
+        assert_eq!(old.index(), new.index());
+        assert_eq!(slab.get(old), None);
+        assert_eq!(slab.get(new), Some(&2));
+        assert_eq!(slab.key_at(new.index()), new);
+    }
+
+    #[test]
+    #[should_panic(expected = "stale slab key")]
+    fn test_index_of_stale_key_panics() {
+        let mut slab = Slab::with_capacity(1);
+        let old = slab.insert(1);
+        slab.remove(old.index());
+        slab.insert(2);
+        slab.index_of(old);
+    }
+
+    #[test]
+    fn test_compaction_packs_entries() {
+        let mut slab = Slab::with_capacity(8);
+        let keys: Vec<SlabKey> = (0..8).map(|v| slab.insert(v)).collect();
+        for key in &keys[..5] {
+            slab.remove(key.index());
+        }
+
+        let moves = slab.compaction_moves();
+        assert_eq!(moves.len(), 3);
+        for (from, to) in moves {
+            let key = slab.relocate(from, to);
+            assert!(key.index() < 3);
+            assert_eq!(slab.get(keys[from]), None);
+        }
+        slab.shrink_to_fit();
+        assert_eq!(slab.slots(), 3);
+        let mut values: Vec<i32> = slab.iter().map(|(_, &v)| v).collect();
+        values.sort_unstable();
+        assert_eq!(values, [5, 6, 7]);
+
+        // The free list was rebuilt, so new entries extend the packed storage
+        assert_eq!(slab.insert(8).index(), 3);
+    }
+}
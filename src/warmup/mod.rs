@@ -0,0 +1,225 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::loading::Loader;
+use crate::{Cache, SyncCache};
+
+// How far a warm-up has got.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WarmupProgress {
+    // Entries taken from the list so far.
+    pub seen: u64,
+    pub loaded: u64,
+    // Already cached, so not loaded again.
+    pub skipped: u64,
+    pub failed: u64,
+    pub elapsed: Duration,
+}
+
+impl WarmupProgress {
+    // Loads run, failed ones included; these are what the rate limits.
+    pub fn loads(&self) -> u64 {
+        self.loaded + self.failed
+    }
+}
+
+type ProgressFn = Box<dyn FnMut(&WarmupProgress) + Send>;
+
+// Fills a cache from a ranked list of keys that were hot before, e.g. yesterday's top
+// queries with their weights, hottest first. Loads are spread out to at most `rate` per
+// second, so a warm-up running beside live traffic does not take the backend from it.
+// Keys already cached are skipped without counting towards the rate, and failed loads
+// are counted and passed over.
+pub struct Warmup {
+    rate: Option<f64>,
+    report_every: u64,
+    progress: Option<ProgressFn>,
+}
+
+impl Warmup {
+    // Loads as fast as the loader allows.
+    pub fn new() -> Self {
+        Warmup {
+            rate: None,
+            report_every: 0,
+            progress: None,
+        }
+    }
+
+    pub fn rate(mut self, loads_per_second: f64) -> Self {
+        assert!(loads_per_second > 0.0, "Rate must be positive");
+        self.rate = Some(loads_per_second);
+        self
+    }
+
+    // Calls `report` after every `every` entries taken from the list, and once more at the
+    // end.
+    pub fn on_progress<F: FnMut(&WarmupProgress) + Send + 'static>(
+        mut self,
+        every: u64,
+        report: F,
+    ) -> Self {
+        assert!(every > 0, "Progress interval must be greater than 0");
+        self.report_every = every;
+        self.progress = Some(Box::new(report));
+        self
+    }
+
+    // Warms a cache owned by the caller, which nothing else can use until it returns.
+    pub fn run<K, V, C, I, L>(&mut self, cache: &mut C, entries: I, loader: L) -> WarmupProgress
+    where
+        C: Cache<K, V> + ?Sized,
+        I: IntoIterator<Item = (K, u32)>,
+        L: Loader<K, V>,
+    {
+        self.warm(&mut Owned(cache), entries, loader)
+    }
+
+    // Warms a shared cache, locking it only to check and store each entry, so live
+    // traffic is served between them. Loads run without the lock.
+    pub fn run_shared<K, V, C, I, L>(
+        &mut self,
+        cache: &SyncCache<K, V, C>,
+        entries: I,
+        loader: L,
+    ) -> WarmupProgress
+    where
+        C: Cache<K, V>,
+        I: IntoIterator<Item = (K, u32)>,
+        L: Loader<K, V>,
+    {
+        self.warm(&mut Shared(cache), entries, loader)
+    }
+
+    fn warm<K, V, T, I, L>(&mut self, target: &mut T, entries: I, mut loader: L) -> WarmupProgress
+    where
+        T: Target<K, V>,
+        I: IntoIterator<Item = (K, u32)>,
+        L: Loader<K, V>,
+    {
+        let started = Instant::now();
+        let mut progress = WarmupProgress::default();
+        for (key, weight) in entries {
+            progress.seen += 1;
+            if target.holds(&key) {
+                progress.skipped += 1;
+            } else {
+                self.throttle(started, progress.loads());
+                match loader.load(&key) {
+                    Ok(value) => {
+                        target.store(key, value, weight);
+                        progress.loaded += 1;
+                    }
+                    Err(_) => progress.failed += 1,
+                }
+            }
+            if self.report_every > 0 && progress.seen % self.report_every == 0 {
+                self.report(started, &mut progress);
+            }
+        }
+        self.report(started, &mut progress);
+        progress
+    }
+
+    // Waits until the next load is due: the `n`th may start `n / rate` seconds in.
+    fn throttle(&self, started: Instant, loads: u64) {
+        if let Some(rate) = self.rate {
+            let due = started + Duration::from_secs_f64(loads as f64 / rate);
+            let now = Instant::now();
+            if due > now {
+                thread::sleep(due - now);
+            }
+        }
+    }
+
+    fn report(&mut self, started: Instant, progress: &mut WarmupProgress) {
+        progress.elapsed = started.elapsed();
+        if let Some(report) = self.progress.as_mut() {
+            report(progress);
+        }
+    }
+}
+
+// Where a warm-up stores what it loads.
+trait Target<K, V> {
+    fn holds(&self, key: &K) -> bool;
+    fn store(&mut self, key: K, value: V, weight: u32);
+}
+
+struct Owned<'a, C: ?Sized>(&'a mut C);
+
+impl<'a, K, V, C: Cache<K, V> + ?Sized> Target<K, V> for Owned<'a, C> {
+    fn holds(&self, key: &K) -> bool {
+        self.0.contains_key(key)
+    }
+
+    fn store(&mut self, key: K, value: V, weight: u32) {
+        self.0.put(key, value, weight);
+    }
+}
+
+struct Shared<'a, K, V, C>(&'a SyncCache<K, V, C>);
+
+impl<'a, K, V, C: Cache<K, V>> Target<K, V> for Shared<'a, K, V, C> {
+    fn holds(&self, key: &K) -> bool {
+        self.0.contains_key(key)
+    }
+
+    fn store(&mut self, key: K, value: V, weight: u32) {
+        self.0.put(key, value, weight);
+    }
+}
+
+impl Default for Warmup {
+    fn default() -> Self {
+        Warmup::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lru::LRUCache;
+    use std::sync::{Arc, Mutex};
+
+    fn run_query(query: &&str) -> Result<usize, String> {
+        if query.is_empty() {
+            return Err("empty query".to_string());
+        }
+        Ok(query.len())
+    }
+
+    #[test]
+    fn test_warms_hot_queries_and_reports_progress() {
+        let mut cache = LRUCache::new(10);
+        cache.put("cached", 0, 1);
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        let mut warmup = Warmup::new().on_progress(2, move |progress| {
+            sink.lock().unwrap().push(progress.seen);
+        });
+        let hot = vec![("rust", 3), ("cached", 1), ("", 1), ("zig", 1), ("go", 1)];
+        let progress = warmup.run(&mut cache, hot, run_query);
+
+        assert_eq!(progress.loaded, 3);
+        assert_eq!(progress.skipped, 1);
+        assert_eq!(progress.failed, 1);
+        assert_eq!(cache.peek(&"rust"), Some(&4));
+        assert_eq!(cache.peek(&"cached"), Some(&0));
+        assert_eq!(*reports.lock().unwrap(), vec![2, 4, 5]);
+    }
+
+    #[test]
+    fn test_loads_are_spread_out_to_the_rate() {
+        let cache = SyncCache::new(LRUCache::new(10));
+        let hot: Vec<(u32, u32)> = (0..5).map(|query| (query, 1)).collect();
+        let started = Instant::now();
+        let progress = Warmup::new()
+            .rate(200.0)
+            .run_shared(&cache, hot, |query: &u32| Ok::<_, String>(query * 2));
+
+        assert_eq!(progress.loads(), 5);
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_eq!(cache.peek(&4), Some(8));
+    }
+}
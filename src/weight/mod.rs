@@ -0,0 +1,145 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::ops::{Add, Div, Sub};
+
+// The cost type of a cache entry. Integers suit byte sizes; `f64` suits costs such as query
+// latencies in milliseconds, whose fractions an integer would round away.
+pub trait Weight:
+    Copy
+    + Default
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Div<Output = Self>
+    + Debug
+    + Send
+    + 'static
+{
+    // The weight of entries stored with `insert` when no weigher is configured.
+    const UNIT: Self;
+
+    // A total order, so that weights can rank priorities even when they are floats.
+    fn cmp_weight(&self, other: &Self) -> Ordering;
+
+    // The weight as counted by `CacheStats::current_weight` and by weight budgets.
+    // Fractions are rounded and negative weights count as zero.
+    fn to_u64(self) -> u64;
+
+    // The weight as a float, for ratios an integer division would round down.
+    fn to_f64(self) -> f64;
+
+    // The weight multiplied by a factor, rounded for integer weights.
+    fn scale(self, factor: f64) -> Self;
+}
+
+impl Weight for u32 {
+    const UNIT: Self = 1;
+
+    fn cmp_weight(&self, other: &Self) -> Ordering {
+        self.cmp(other)
+    }
+
+    fn to_u64(self) -> u64 {
+        u64::from(self)
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        (f64::from(self) * factor).round() as u32
+    }
+}
+
+impl Weight for u64 {
+    const UNIT: Self = 1;
+
+    fn cmp_weight(&self, other: &Self) -> Ordering {
+        self.cmp(other)
+    }
+
+    fn to_u64(self) -> u64 {
+        self
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        (self as f64 * factor).round() as u64
+    }
+}
+
+impl Weight for f64 {
+    const UNIT: Self = 1.0;
+
+    fn cmp_weight(&self, other: &Self) -> Ordering {
+        self.total_cmp(other)
+    }
+
+    fn to_u64(self) -> u64 {
+        self.max(0.0).round() as u64
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        self * factor
+    }
+}
+
+// A weight in its total order, for use as a priority-queue priority.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Ordered<W>(pub(crate) W);
+
+impl<W: Weight> PartialEq for Ordered<W> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<W: Weight> Eq for Ordered<W> {}
+
+impl<W: Weight> PartialOrd for Ordered<W> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<W: Weight> Ord for Ordered<W> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp_weight(&other.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_float_weights_are_totally_ordered() {
+        let mut weights = vec![Ordered(2.5), Ordered(0.25), Ordered(1.0)];
+        weights.sort();
+        let sorted: Vec<f64> = weights.into_iter().map(|weight| weight.0).collect();
+        assert_eq!(sorted, vec![0.25, 1.0, 2.5]);
+    }
+
+    #[test]
+    fn test_to_u64() {
+        assert_eq!(7u32.to_u64(), 7);
+        assert_eq!(u64::MAX.to_u64(), u64::MAX);
+        assert_eq!(2.6f64.to_u64(), 3);
+        assert_eq!((-1.0f64).to_u64(), 0);
+    }
+
+    #[test]
+    fn test_scale() {
+        assert_eq!(10u32.scale(0.25), 3);
+        assert_eq!(10u64.scale(0.0), 0);
+        assert_eq!(3.0f64.scale(0.5), 1.5);
+    }
+}
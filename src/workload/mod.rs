@@ -0,0 +1,233 @@
+use crate::random::Rng;
+use crate::replay::Access;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Popularity {
+    // Every key is as likely as any other.
+    Uniform,
+    // The key of rank `r` is drawn in proportion to `1 / r^alpha`; query logs are usually
+    // close to an alpha of 1.
+    Zipf { alpha: f64 },
+}
+
+// Describes a reproducible stream of keys for stress-testing and benchmarking policies,
+// drawn from `0..keys` by popularity rank. On top of the base distribution, the popular
+// keys can drift to other keys every so often, and scans of keys that are never asked for
+// again can be mixed in, the pattern that flushes a plain LRU. The same seed always gives
+// the same stream.
+#[derive(Debug, Clone)]
+pub struct Workload {
+    keys: u64,
+    popularity: Popularity,
+    // The chance of each rank or lower, for drawing Zipf ranks by binary search
+    cumulative: Vec<f64>,
+    seed: u64,
+    // Every `.0` requests, ranks move `.1` keys along
+    shift: Option<(u64, u64)>,
+    // After every `.0` requests, a scan of `.1` keys
+    scan: Option<(u64, u64)>,
+}
+
+impl Workload {
+    pub fn uniform(keys: u64) -> Self {
+        Workload::new(keys, Popularity::Uniform)
+    }
+
+    pub fn zipf(keys: u64, alpha: f64) -> Self {
+        assert!(alpha >= 0.0, "Zipf exponent must not be negative");
+        Workload::new(keys, Popularity::Zipf { alpha })
+    }
+
+    pub fn new(keys: u64, popularity: Popularity) -> Self {
+        assert!(keys > 0, "Key space must not be empty");
+        let cumulative = match popularity {
+            Popularity::Uniform => Vec::new(),
+            Popularity::Zipf { alpha } => {
+                let mut total = 0.0;
+                let mut cumulative: Vec<f64> = (1..=keys)
+                    .map(|rank| {
+                        total += 1.0 / (rank as f64).powf(alpha);
+                        total
+                    })
+                    .collect();
+                for chance in &mut cumulative {
+                    *chance /= total;
+                }
+                cumulative
+            }
+        };
+        Workload {
+            keys,
+            popularity,
+            cumulative,
+            seed: 1,
+            shift: None,
+            scan: None,
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // Every `requests` requests, each rank moves to the key `by` further along, so the
+    // working set changes while the distribution stays the same.
+    pub fn shift_every(mut self, requests: u64, by: u64) -> Self {
+        assert!(requests > 0, "Shift interval must be greater than 0");
+        self.shift = Some((requests, by));
+        self
+    }
+
+    // After every `requests` requests drawn from the distribution, `length` keys outside
+    // the key space are requested once each, in order.
+    pub fn scan_every(mut self, requests: u64, length: u64) -> Self {
+        assert!(requests > 0, "Scan interval must be greater than 0");
+        self.scan = Some((requests, length));
+        self
+    }
+
+    pub fn popularity(&self) -> Popularity {
+        self.popularity
+    }
+
+    // An endless stream of keys.
+    pub fn keys(&self) -> Keys<'_> {
+        Keys {
+            workload: self,
+            rng: Rng::new(self.seed),
+            drawn: 0,
+            scanning: 0,
+            scanned: 0,
+        }
+    }
+
+    pub fn generate(&self, requests: usize) -> Vec<u64> {
+        self.keys().take(requests).collect()
+    }
+
+    // The stream as a trace for `replay` and `compare_policies`, one request per
+    // timestamp, without sizes or costs.
+    pub fn trace(&self, requests: usize) -> Vec<Access> {
+        self.keys()
+            .take(requests)
+            .enumerate()
+            .map(|(timestamp, key)| Access {
+                timestamp: timestamp as u64,
+                key: key.to_string(),
+                size: None,
+                cost: None,
+            })
+            .collect()
+    }
+
+    fn rank(&self, rng: &mut Rng) -> u64 {
+        let draw = rng.next_unit();
+        match self.popularity {
+            Popularity::Uniform => ((draw * self.keys as f64) as u64).min(self.keys - 1),
+            Popularity::Zipf { .. } => {
+                let rank = self.cumulative.partition_point(|&chance| chance <= draw);
+                (rank as u64).min(self.keys - 1)
+            }
+        }
+    }
+}
+
+pub struct Keys<'a> {
+    workload: &'a Workload,
+    rng: Rng,
+    // Requests drawn from the distribution so far
+    drawn: u64,
+    // Keys left in the scan under way
+    scanning: u64,
+    // Scan keys handed out so far, each one past the last
+    scanned: u64,
+}
+
+impl<'a> Iterator for Keys<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let workload = self.workload;
+        if self.scanning > 0 {
+            self.scanning -= 1;
+            self.scanned += 1;
+            return Some(workload.keys + self.scanned - 1);
+        }
+
+        let rank = workload.rank(&mut self.rng);
+        let offset = workload
+            .shift
+            .map_or(0, |(every, by)| (self.drawn / every).wrapping_mul(by));
+        self.drawn += 1;
+        if let Some((every, length)) = workload.scan {
+            if self.drawn.is_multiple_of(every) {
+                self.scanning = length;
+            }
+        }
+        Some(rank.wrapping_add(offset) % workload.keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lru::LRUCache;
+    use crate::replay::replay;
+    use std::collections::HashMap;
+
+    fn counts(keys: &[u64]) -> HashMap<u64, usize> {
+        let mut counts = HashMap::new();
+        for &key in keys {
+            *counts.entry(key).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_zipf_favours_low_ranks_reproducibly() {
+        let workload = Workload::zipf(1000, 1.0).seed(7);
+        let keys = workload.generate(20_000);
+        assert_eq!(keys, workload.generate(20_000));
+        assert_ne!(keys, workload.clone().seed(8).generate(20_000));
+
+        let zipf = counts(&keys);
+        // Rank 1 takes 1 / H(1000), about 13%, of the requests
+        let top = zipf[&0] as f64 / keys.len() as f64;
+        assert!((0.11..0.15).contains(&top), "top share {}", top);
+        assert!(zipf[&0] > zipf[&1] && zipf[&1] > zipf[&9]);
+
+        let uniform = counts(&Workload::uniform(10).generate(10_000));
+        assert_eq!(uniform.len(), 10);
+        assert!(uniform.values().all(|&count| (800..1200).contains(&count)));
+    }
+
+    #[test]
+    fn test_shifts_move_the_hot_keys() {
+        let workload = Workload::zipf(100, 1.2).shift_every(1000, 50);
+        let keys = workload.generate(2000);
+        let before = counts(&keys[..1000]);
+        let after = counts(&keys[1000..]);
+        let hottest = |counts: &HashMap<u64, usize>| {
+            counts
+                .iter()
+                .max_by_key(|&(_, count)| count)
+                .map(|(&key, _)| key)
+        };
+        assert_eq!(hottest(&before), Some(0));
+        assert_eq!(hottest(&after), Some(50));
+    }
+
+    #[test]
+    fn test_scans_mix_in_one_off_keys() {
+        let steady = Workload::zipf(100, 1.0);
+        let scanned = steady.clone().scan_every(200, 100);
+        let keys = scanned.generate(300);
+        assert!(keys[..200].iter().all(|&key| key < 100));
+        assert_eq!(keys[200..300], (100..200).collect::<Vec<u64>>()[..]);
+
+        let clean = replay(&mut LRUCache::new(50), &steady.trace(5000));
+        let polluted = replay(&mut LRUCache::new(50), &scanned.trace(5000));
+        assert!(polluted.hit_ratio() < clean.hit_ratio() - 0.1);
+    }
+}
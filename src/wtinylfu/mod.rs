@@ -0,0 +1,511 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::lru::LRUCache;
+use crate::slru::SLRUCache;
+use crate::stats::CacheStats;
+use crate::tinylfu::TinyLFU;
+use crate::trace;
+use crate::{Cache, EvictionListener, NewCache};
+
+const POLICY: &str = "w-tinylfu";
+
+// The share of the capacity given to the admission window by `new`.
+const DEFAULT_WINDOW_RATIO: f64 = 0.01;
+
+// The share of the main region reserved for its protected segment.
+const MAIN_PROTECTED_RATIO: f64 = 0.8;
+
+// Window TinyLFU (Einziger et al.). New keys land in a small LRU window, where a burst of
+// recent keys can be absorbed. The window's least recently used entry then competes for a
+// place in the segmented LRU main region: TinyLFU admits it only if it is estimated to be
+// used more often than the main region's victim, and otherwise it is the one evicted.
+//
+// The window holds each value with its weight, so moving it on keeps the weight.
+pub struct WTinyLFUCache<K, V, S = RandomState> {
+    capacity: usize,
+    window_capacity: usize,
+    window: LRUCache<K, (V, u32), S>,
+    main: SLRUCache<K, V, S>,
+    filter: TinyLFU<S>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    stats: CacheStats,
+}
+
+pub struct Iter<'a, K, V> {
+    window: crate::lru::Iter<'a, K, (V, u32)>,
+    main: crate::slru::Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.window
+            .next()
+            .map(|(key, (value, _))| (key, value))
+            .or_else(|| self.main.next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.window.len() + self.main.len();
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+fn default_window(capacity: usize) -> usize {
+    ((capacity as f64 * DEFAULT_WINDOW_RATIO) as usize).max(1)
+}
+
+impl<K: Clone + Hash + Eq, V> WTinyLFUCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        WTinyLFUCache::with_window_capacity(capacity, default_window(capacity))
+    }
+
+    pub fn with_window_capacity(capacity: usize, window_capacity: usize) -> Self {
+        WTinyLFUCache::with_hasher(capacity, window_capacity, RandomState::new())
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> WTinyLFUCache<K, V, S> {
+    // `window_capacity` entries of the `capacity` go to the window, the rest to the main region.
+    pub fn with_hasher(capacity: usize, window_capacity: usize, hash_builder: S) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        assert!(
+            window_capacity > 0 && window_capacity <= capacity,
+            "Window capacity must be between 1 and the capacity"
+        );
+        let main_capacity = capacity - window_capacity;
+        WTinyLFUCache {
+            capacity,
+            window_capacity,
+            window: LRUCache::with_hasher(window_capacity, hash_builder.clone()),
+            // An empty main region is never written to, but the segments need a capacity
+            main: SLRUCache::with_hasher(
+                main_capacity.max(1),
+                (main_capacity as f64 * MAIN_PROTECTED_RATIO) as usize,
+                hash_builder.clone(),
+            ),
+            filter: TinyLFU::with_hasher(capacity, hash_builder),
+            eviction_listener: None,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let _span = trace::get_span(POLICY, key);
+        if !self.touch(key) {
+            return None;
+        }
+        self.peek(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if !self.touch(key) {
+            return None;
+        }
+        if self.window.contains_key(key) {
+            return self.window.get_mut(key).map(|(value, _)| value);
+        }
+        self.main.get_mut(key)
+    }
+
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.window
+            .peek(key)
+            .map(|(value, _)| value)
+            .or_else(|| self.main.peek(key))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.window.contains_key(key) || self.main.contains_key(key)
+    }
+
+    // Updates stay in the region the key is in; new keys always enter the window.
+    pub fn put(&mut self, key: K, value: V, weight: u32) {
+        let _span = trace::put_span(POLICY, &key, weight);
+        if self.window.contains_key(&key) {
+            self.window.put(key, (value, weight), weight);
+            return;
+        }
+        if self.main.contains_key(&key) {
+            self.main.put(key, value, weight);
+            return;
+        }
+
+        self.filter.record(&key);
+        self.stats.insertions += 1;
+        trace::insert(POLICY, &key, weight);
+        if self.window.len() >= self.window_capacity {
+            if let Some((candidate, (value, weight))) = self.window.pop_victim() {
+                self.admit(candidate, value, weight);
+            }
+        }
+        self.window.put(key, (value, weight), weight);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.window
+            .remove(key)
+            .map(|(value, _)| value)
+            .or_else(|| self.main.remove(key))
+    }
+
+    // The entry `pop_victim` would remove, left in place.
+    pub fn peek_victim(&self) -> Option<&K> {
+        self.window
+            .peek_victim()
+            .or_else(|| self.main.peek_victim())
+    }
+
+    // Window entries go first: they have not earned a place in the main region yet.
+    pub fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.window
+            .pop_victim()
+            .map(|(key, (value, _))| (key, value))
+            .or_else(|| self.main.pop_victim())
+    }
+
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        self.window.retain(|key, (value, _)| f(key, value));
+        self.main.retain(f);
+    }
+
+    // Newcomers refused by the filter are reported as evicted too.
+    pub fn set_eviction_listener<F: FnMut(K, V) + Send + 'static>(&mut self, listener: F) {
+        self.eviction_listener = Some(Box::new(listener));
+    }
+
+    // The frequency estimates survive, so the cache refills with popular keys first.
+    pub fn clear(&mut self) {
+        self.window.clear();
+        self.main.clear();
+    }
+
+    // Window entries first, then the main region's, each from most recently used.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            window: self.window.iter(),
+            main: self.main.iter(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn in_window(&self, key: &K) -> bool {
+        self.window.contains_key(key)
+    }
+
+    pub fn window_capacity(&self) -> usize {
+        self.window_capacity
+    }
+
+    pub fn filter(&self) -> &TinyLFU<S> {
+        &self.filter
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            current_weight: self.window.stats().current_weight + self.main.stats().current_weight,
+            ..self.stats
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.window.len() + self.main.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Records the lookup with the filter and the hit or miss in the stats.
+    fn touch(&mut self, key: &K) -> bool {
+        self.filter.record(key);
+        let hit = self.window.get(key).is_some() || self.main.get(key).is_some();
+        if hit {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            trace::miss(POLICY, key);
+        }
+        hit
+    }
+
+    // Moves an entry leaving the window into the main region, if the filter lets it in.
+    fn admit(&mut self, candidate: K, value: V, weight: u32) {
+        let main_capacity = self.capacity - self.window_capacity;
+        if self.main.len() >= main_capacity {
+            let admitted = match self.main.peek_victim() {
+                Some(victim) => self.filter.admit(&candidate, victim),
+                None => false,
+            };
+            if !admitted {
+                self.notify(candidate, value);
+                return;
+            }
+            if let Some((victim, victim_value)) = self.main.pop_victim() {
+                self.notify(victim, victim_value);
+            }
+        }
+        self.main.put(candidate, value, weight);
+    }
+
+    fn notify(&mut self, key: K, value: V) {
+        self.stats.evictions += 1;
+        trace::eviction(POLICY, &key);
+        if let Some(listener) = self.eviction_listener.as_mut() {
+            listener(key, value);
+        }
+    }
+}
+
+impl<'a, K: Clone + Hash + Eq, V, S: BuildHasher + Clone> IntoIterator
+    for &'a WTinyLFUCache<K, V, S>
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Extend<(K, V, u32)>
+    for WTinyLFUCache<K, V, S>
+{
+    fn extend<I: IntoIterator<Item = (K, V, u32)>>(&mut self, iter: I) {
+        for (key, value, weight) in iter {
+            self.put(key, value, weight);
+        }
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone> Cache<K, V> for WTinyLFUCache<K, V, S> {
+    fn get(&mut self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn put(&mut self, key: K, value: V, weight: u32) {
+        self.put(key, value, weight)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove(key)
+    }
+
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        self.pop_victim()
+    }
+
+    fn peek_victim(&self) -> Option<&K> {
+        self.peek_victim()
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K: Clone + Hash + Eq, V, S: BuildHasher + Clone + Default> NewCache<K, V>
+    for WTinyLFUCache<K, V, S>
+{
+    fn new(capacity: usize) -> Self {
+        WTinyLFUCache::with_hasher(capacity, default_window(capacity), S::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+
+    // Keeps the sketch's collisions the same from run to run.
+    type FixedState = BuildHasherDefault<DefaultHasher>;
+
+    #[test]
+    #[should_panic(expected = "Capacity must be greater than 0")]
+    fn test_new_cache_zero_capacity() {
+        let _cache: WTinyLFUCache<String, i32> = WTinyLFUCache::new(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Window capacity must be between 1 and the capacity")]
+    fn test_oversized_window() {
+        let _cache: WTinyLFUCache<String, i32> = WTinyLFUCache::with_window_capacity(2, 3);
+    }
+
+    #[test]
+    fn test_default_window() {
+        let cache: WTinyLFUCache<i32, i32> = WTinyLFUCache::new(1000);
+        assert_eq!(cache.window_capacity(), 10);
+        let cache: WTinyLFUCache<i32, i32> = WTinyLFUCache::new(10);
+        assert_eq!(cache.window_capacity(), 1);
+    }
+
+    #[test]
+    fn test_new_keys_enter_the_window() {
+        let mut cache = WTinyLFUCache::with_window_capacity(4, 1);
+        cache.put(1, "one", 1);
+        assert!(cache.in_window(&1));
+
+        // Main has room, so the window's entry moves on without a contest
+        cache.put(2, "two", 1);
+        assert!(cache.in_window(&2));
+        assert!(!cache.in_window(&1));
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats().evictions, 0);
+    }
+
+    #[test]
+    fn test_scan_does_not_flush_hot_keys() {
+        let mut cache = WTinyLFUCache::with_hasher(4, 1, FixedState::default());
+        for key in 0..3 {
+            cache.put(key, "hot", 1);
+        }
+        cache.put(99, "filler", 1);
+
+        for key in 100..150 {
+            for hot in 0..3 {
+                cache.get(&hot);
+            }
+            cache.put(key, "once", 1);
+        }
+        for hot in 0..3 {
+            assert!(cache.contains_key(&hot), "{}", hot);
+        }
+        assert!(cache.in_window(&149));
+        assert_eq!(cache.len(), 4);
+    }
+
+    #[test]
+    fn test_popular_newcomer_replaces_main_victim() {
+        let mut cache = WTinyLFUCache::with_hasher(3, 1, FixedState::default());
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.put(3, "three", 1);
+
+        // 1 and 2 are in main; 3 has been asked for often while it waited in the window
+        for _ in 0..4 {
+            cache.get(&3);
+        }
+        cache.put(4, "four", 1);
+        assert!(cache.contains_key(&3));
+        assert!(!cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_window_only() {
+        let mut cache = WTinyLFUCache::with_window_capacity(2, 2);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+        cache.get(&1);
+
+        // Plain LRU
+        cache.put(3, "three", 1);
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+    }
+
+    #[test]
+    fn test_update_keeps_region_and_reweighs() {
+        let mut cache = WTinyLFUCache::with_window_capacity(4, 1);
+        cache.put(1, "one", 5);
+        cache.put(2, "two", 1);
+        cache.put(1, "ONE", 7);
+        cache.put(2, "TWO", 2);
+
+        assert!(!cache.in_window(&1));
+        assert!(cache.in_window(&2));
+        assert_eq!(cache.peek(&1), Some(&"ONE"));
+        assert_eq!(cache.stats().current_weight, 9);
+        assert_eq!(cache.stats().insertions, 2);
+    }
+
+    #[test]
+    fn test_get_mut_and_stats() {
+        let mut cache = WTinyLFUCache::with_window_capacity(4, 1);
+        cache.put(1, vec![1], 1);
+        cache.put(2, vec![2], 1);
+        cache.get_mut(&1).unwrap().push(10);
+        cache.get_mut(&2).unwrap().push(20);
+
+        assert_eq!(cache.peek(&1), Some(&vec![1, 10]));
+        assert_eq!(cache.peek(&2), Some(&vec![2, 20]));
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.stats().hits, 2);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn test_pop_victim_prefers_window() {
+        let mut cache = WTinyLFUCache::with_window_capacity(4, 1);
+        cache.put(1, "one", 1);
+        cache.put(2, "two", 1);
+
+        assert_eq!(cache.peek_victim(), Some(&2));
+        assert_eq!(cache.pop_victim(), Some((2, "two")));
+        assert_eq!(cache.pop_victim(), Some((1, "one")));
+        assert_eq!(cache.pop_victim(), None);
+    }
+
+    #[test]
+    fn test_iter_remove_and_retain() {
+        let mut cache = WTinyLFUCache::with_window_capacity(4, 1);
+        cache.extend(vec![(1, "one", 1), (2, "two", 1), (3, "three", 1)]);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&3, &2, &1]);
+        assert_eq!(cache.iter().len(), 3);
+
+        assert_eq!(cache.remove(&2), Some("two"));
+        cache.retain(|&key, _| key != 3);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&1]);
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_eviction_listener_sees_rejected_newcomers() {
+        use std::sync::{Arc, Mutex};
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = WTinyLFUCache::with_hasher(2, 1, FixedState::default());
+        cache.set_eviction_listener(move |key, _| sink.lock().unwrap().push(key));
+
+        cache.put(1, "one", 1);
+        cache.get(&1);
+        cache.put(2, "two", 1);
+        cache.put(3, "three", 1);
+        assert_eq!(*evicted.lock().unwrap(), vec![2]);
+        assert!(cache.contains_key(&1));
+    }
+}